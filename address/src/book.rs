@@ -0,0 +1,378 @@
+//! Labeled address book of a wallet.
+//!
+//! Every address is validated when it is added: the checksum of standard and multisig
+//! addresses and the network of all addresses against the network of the book.
+//! Entries are kept sorted by label and can be exported to and imported from JSON.
+
+use crate::{AddressType, Multisig, Network, Standard, SCRIPT_ADDRESS_LENGTH};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Errors of the address parsers and of the address book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address can not be decoded or its checksum does not match.
+    InvalidAddress(&'static str),
+    /// The address bytes are not of the length of their type.
+    InvalidLength {
+        /// Length of the address type, in bytes.
+        expected: usize,
+        /// Length of the given bytes.
+        got: usize,
+    },
+    /// The address is valid on another network than the book.
+    NetworkMismatch {
+        /// Network of the book.
+        expected: Network,
+        /// Network of the address.
+        found: Network,
+    },
+    /// The label is already used by another entry.
+    DuplicateLabel(String),
+    /// The address is already in the book under the given label.
+    DuplicateAddress(String),
+    /// The JSON export can not be parsed.
+    InvalidJson(String),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::InvalidAddress(err) => write!(f, "Invalid address: {}", err),
+            AddressError::InvalidLength { expected, got } => write!(
+                f,
+                "Invalid address length: expected {} bytes, got {}",
+                expected, got
+            ),
+            AddressError::NetworkMismatch { expected, found } => write!(
+                f,
+                "Address is valid on {:?}, the address book is on {:?}",
+                found, expected
+            ),
+            AddressError::DuplicateLabel(label) => write!(f, "Label {} is already used", label),
+            AddressError::DuplicateAddress(label) => {
+                write!(f, "Address is already labeled as {}", label)
+            }
+            AddressError::InvalidJson(err) => write!(f, "Invalid address book: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+// the parsers returning a `&'static str` error keep their messages
+impl From<AddressError> for &'static str {
+    fn from(err: AddressError) -> &'static str {
+        match err {
+            AddressError::InvalidAddress(err) => err,
+            AddressError::InvalidLength { .. } => "Error::InvalidAddressLength",
+            AddressError::NetworkMismatch { .. } => "Error::NetworkMismatch",
+            AddressError::DuplicateLabel(_) => "Error::DuplicateLabel",
+            AddressError::DuplicateAddress(_) => "Error::DuplicateAddress",
+            AddressError::InvalidJson(_) => "Error::InvalidJson",
+        }
+    }
+}
+
+/// A labeled address of the book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBookEntry {
+    /// Label of the address, unique in the book.
+    pub label: String,
+    /// Hex encoded address.
+    pub address: String,
+    /// The address type.
+    pub addr_type: AddressType,
+    /// Watch-only entries are tracked but not spent by the wallet.
+    pub watch_only: bool,
+}
+
+// Export format of the address book
+#[derive(Serialize, Deserialize)]
+struct AddressBookExport {
+    network: Network,
+    entries: Vec<AddressBookEntry>,
+}
+
+/// Labeled addresses of a single network, sorted by label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressBook {
+    network: Network,
+    entries: BTreeMap<String, AddressBookEntry>,
+}
+
+impl AddressBook {
+    /// Create an empty address book for the given network.
+    pub fn new(network: Network) -> AddressBook {
+        AddressBook {
+            network,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The network of the addresses in the book.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Add an address of the wallet under `label`.
+    /// The address is accepted as hex or Base58.
+    pub fn add(&mut self, label: &str, address: &str) -> Result<(), AddressError> {
+        self.insert(label, address, false)
+    }
+
+    /// Add a watch-only address under `label`.
+    pub fn add_watch_only(&mut self, label: &str, address: &str) -> Result<(), AddressError> {
+        self.insert(label, address, true)
+    }
+
+    fn insert(&mut self, label: &str, address: &str, watch_only: bool) -> Result<(), AddressError> {
+        if self.entries.contains_key(label) {
+            return Err(AddressError::DuplicateLabel(label.to_string()));
+        }
+        let (network, addr_type, address) = parse_address(address)?;
+        if network != self.network {
+            return Err(AddressError::NetworkMismatch {
+                expected: self.network,
+                found: network,
+            });
+        }
+        if let Some(entry) = self.find_by_address(&address) {
+            return Err(AddressError::DuplicateAddress(entry.label.clone()));
+        }
+        self.entries.insert(
+            label.to_string(),
+            AddressBookEntry {
+                label: label.to_string(),
+                address,
+                addr_type,
+                watch_only,
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove the entry labeled `label`.
+    pub fn remove(&mut self, label: &str) -> Option<AddressBookEntry> {
+        self.entries.remove(label)
+    }
+
+    /// Get the entry labeled `label`.
+    pub fn get(&self, label: &str) -> Option<&AddressBookEntry> {
+        self.entries.get(label)
+    }
+
+    /// Find the entry of a hex or Base58 encoded address.
+    pub fn find_by_address(&self, address: &str) -> Option<&AddressBookEntry> {
+        let address = match parse_address(address) {
+            Ok((_, _, address)) => address,
+            Err(_) => return None,
+        };
+        self.entries.values().find(|entry| entry.address == address)
+    }
+
+    /// Iterate the entries sorted by label.
+    pub fn iter(&self) -> impl Iterator<Item = &AddressBookEntry> {
+        self.entries.values()
+    }
+
+    /// Entries of the given address type, sorted by label.
+    pub fn addresses_of_type(&self, addr_type: AddressType) -> Vec<&AddressBookEntry> {
+        self.iter()
+            .filter(|entry| entry.addr_type == addr_type)
+            .collect()
+    }
+
+    /// Number of entries in the book.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the book has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Export the book as JSON.
+    pub fn to_json(&self) -> String {
+        let export = AddressBookExport {
+            network: self.network,
+            entries: self.entries.values().cloned().collect(),
+        };
+        serde_json::to_string(&export).expect("Failed to serialize to JSON")
+    }
+
+    /// Import a book exported with [`AddressBook::to_json`].
+    /// Every address is validated again and duplicate labels or addresses are rejected.
+    pub fn from_json(json: &str) -> Result<AddressBook, AddressError> {
+        let export: AddressBookExport =
+            serde_json::from_str(json).map_err(|e| AddressError::InvalidJson(e.to_string()))?;
+        let mut book = AddressBook::new(export.network);
+        for entry in export.entries.iter() {
+            book.insert(&entry.label, &entry.address, entry.watch_only)?;
+            // the type is recovered from the address itself
+            if book.entries[&entry.label].addr_type != entry.addr_type {
+                return Err(AddressError::InvalidAddress("Error::Address type mismatch"));
+            }
+        }
+        Ok(book)
+    }
+}
+
+// Decodes a hex or Base58 address and validates it.
+// Returns the network, the type and the hex encoding of the address.
+fn parse_address(address: &str) -> Result<(Network, AddressType, String), AddressError> {
+    let bytes = match hex::decode(address) {
+        Ok(bytes) => bytes,
+        Err(_) => bs58::decode(address)
+            .into_vec()
+            .map_err(|_| AddressError::InvalidAddress("Error::Invalid hex or Base58 address"))?,
+    };
+    let network = match bytes.first() {
+        Some(byte) => Network::from_u8(*byte).map_err(AddressError::InvalidAddress)?,
+        None => {
+            return Err(AddressError::InvalidLength {
+                expected: 1,
+                got: 0,
+            })
+        }
+    };
+    let addr_type = AddressType::from_slice(&bytes, network)?;
+    match addr_type {
+        AddressType::Standard => {
+            Standard::from_bytes(&bytes)?;
+        }
+        AddressType::Multisig => {
+            Multisig::from_bytes(&bytes)?;
+        }
+        // script addresses carry the hash of the root and no checksum
+        AddressType::Script => {
+            if bytes.len() != SCRIPT_ADDRESS_LENGTH {
+                return Err(AddressError::InvalidLength {
+                    expected: SCRIPT_ADDRESS_LENGTH,
+                    got: bytes.len(),
+                });
+            }
+        }
+    }
+    Ok((network, addr_type, hex::encode(&bytes)))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Address;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+
+    fn standard_address(network: Network) -> String {
+        let (pk, _) = Account::generate_random_account_with_value(Scalar::from(0u64))
+            .0
+            .get_account();
+        Address::standard_address(network, pk).as_hex()
+    }
+
+    #[test]
+    fn address_book_add_test() {
+        let mut book = AddressBook::new(Network::Testnet);
+        let alice = standard_address(Network::Testnet);
+        let bob = standard_address(Network::Testnet);
+        let script = Address::script_address(Network::Testnet, [7u8; 32]).as_hex();
+        book.add("bob", &bob).unwrap();
+        book.add("alice", &alice).unwrap();
+        book.add_watch_only("contract", &script).unwrap();
+
+        assert_eq!(book.get("alice").unwrap().address, alice);
+        assert_eq!(book.find_by_address(&bob).unwrap().label, "bob");
+        let labels: Vec<&str> = book.iter().map(|entry| entry.label.as_str()).collect();
+        assert_eq!(labels, vec!["alice", "bob", "contract"]);
+
+        assert_eq!(book.addresses_of_type(AddressType::Standard).len(), 2);
+        let scripts = book.addresses_of_type(AddressType::Script);
+        assert_eq!(scripts.len(), 1);
+        assert!(scripts[0].watch_only);
+
+        // Base58 addresses are stored as hex
+        let carol =
+            Address::from_hex(&standard_address(Network::Testnet), AddressType::Standard).unwrap();
+        book.add("carol", &carol.as_base58()).unwrap();
+        assert_eq!(book.get("carol").unwrap().address, carol.as_hex());
+    }
+
+    #[test]
+    fn address_book_rejects_invalid_test() {
+        let mut book = AddressBook::new(Network::Mainnet);
+        let alice = standard_address(Network::Mainnet);
+        book.add("alice", &alice).unwrap();
+
+        let testnet = standard_address(Network::Testnet);
+        assert_eq!(
+            book.add("bob", &testnet),
+            Err(AddressError::NetworkMismatch {
+                expected: Network::Mainnet,
+                found: Network::Testnet,
+            })
+        );
+        assert_eq!(
+            book.add("alice", &standard_address(Network::Mainnet)),
+            Err(AddressError::DuplicateLabel("alice".to_string()))
+        );
+        assert_eq!(
+            book.add("alice again", &alice),
+            Err(AddressError::DuplicateAddress("alice".to_string()))
+        );
+
+        // a flipped byte breaks the checksum
+        let mut bytes = hex::decode(&alice).unwrap();
+        bytes[10] ^= 1;
+        assert!(matches!(
+            book.add("broken", &hex::encode(bytes)),
+            Err(AddressError::InvalidAddress(_))
+        ));
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn address_book_json_round_trip_test() {
+        let mut book = AddressBook::new(Network::Testnet);
+        for i in 0..1000u32 {
+            if i % 10 == 0 {
+                let mut root = [0u8; 32];
+                root[..4].copy_from_slice(&i.to_be_bytes());
+                let script = Address::script_address(Network::Testnet, root).as_hex();
+                book.add_watch_only(&format!("script-{:04}", i), &script)
+                    .unwrap();
+            } else {
+                book.add(
+                    &format!("wallet-{:04}", i),
+                    &standard_address(Network::Testnet),
+                )
+                .unwrap();
+            }
+        }
+        assert_eq!(book.len(), 1000);
+
+        let imported = AddressBook::from_json(&book.to_json()).unwrap();
+        assert_eq!(imported, book);
+        assert_eq!(imported.addresses_of_type(AddressType::Script).len(), 100);
+
+        // duplicate labels are rejected on import
+        let mut export: serde_json::Value = serde_json::from_str(&book.to_json()).unwrap();
+        let first = export["entries"][0].clone();
+        export["entries"][1]["label"] = first["label"].clone();
+        assert_eq!(
+            AddressBook::from_json(&export.to_string()),
+            Err(AddressError::DuplicateLabel(
+                first["label"].as_str().unwrap().to_string()
+            ))
+        );
+        assert!(matches!(
+            AddressBook::from_json("{}"),
+            Err(AddressError::InvalidJson(_))
+        ));
+    }
+}
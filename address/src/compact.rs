@@ -0,0 +1,244 @@
+//! Fixed-size binary form of addresses, for outputs and contracts that store
+//! addresses on chain.
+//!
+//! The fixed form is a type tag followed by the address bytes, zero padded to
+//! [`FIXED_ADDRESS_LENGTH`]. Serializing a hex address with this module as
+//! `#[serde(with = "address::compact")]` keeps the hex string for human readable
+//! formats and writes the tag and the address bytes, without padding, for binary ones.
+
+use crate::{
+    check_length, Address, AddressError, AddressType, Multisig, Network, Standard,
+    MULTISIG_ADDRESS_LENGTH, SCRIPT_ADDRESS_LENGTH, STANDARD_ADDRESS_LENGTH,
+};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Length of the fixed form: the tag and the longest address, a standard one.
+pub const FIXED_ADDRESS_LENGTH: usize = STANDARD_ADDRESS_LENGTH + 1;
+
+// tag of the fixed form and length of the address bytes
+fn tag(addr_type: AddressType) -> (u8, usize) {
+    match addr_type {
+        AddressType::Standard => (0, STANDARD_ADDRESS_LENGTH),
+        AddressType::Script => (1, SCRIPT_ADDRESS_LENGTH),
+        AddressType::Multisig => (2, MULTISIG_ADDRESS_LENGTH),
+    }
+}
+
+fn from_tag(byte: u8) -> Result<(AddressType, usize), AddressError> {
+    let addr_type = match byte {
+        0 => AddressType::Standard,
+        1 => AddressType::Script,
+        2 => AddressType::Multisig,
+        _ => return Err(AddressError::InvalidAddress("Error::Invalid address tag")),
+    };
+    Ok((addr_type, tag(addr_type).1))
+}
+
+// the tag and the address bytes of a hex address
+fn tagged_bytes(hex: &str) -> Result<Vec<u8>, AddressError> {
+    let bytes = hex::decode(hex).map_err(|_| AddressError::InvalidAddress("Error::InvalidHex"))?;
+    let byte = *bytes.first().ok_or(AddressError::InvalidLength {
+        expected: 1,
+        got: 0,
+    })?;
+    let network = Network::from_u8(byte).map_err(AddressError::InvalidAddress)?;
+    let (tag, len) = tag(AddressType::from_slice(&bytes, network)?);
+    check_length(&bytes, len)?;
+    let mut tagged = Vec::with_capacity(len + 1);
+    tagged.push(tag);
+    tagged.extend_from_slice(&bytes);
+    Ok(tagged)
+}
+
+// the hex address of its tag and address bytes, padding not included
+fn hex_from_tagged(tagged: &[u8]) -> Result<String, AddressError> {
+    let byte = *tagged.first().ok_or(AddressError::InvalidLength {
+        expected: 1,
+        got: 0,
+    })?;
+    let (addr_type, len) = from_tag(byte)?;
+    let bytes = &tagged[1..];
+    check_length(bytes, len)?;
+    let network = Network::from_u8(bytes[0]).map_err(AddressError::InvalidAddress)?;
+    if AddressType::from_slice(bytes, network)? != addr_type {
+        return Err(AddressError::InvalidAddress("Error::Address tag mismatch"));
+    }
+    Ok(hex::encode(bytes))
+}
+
+/// Fixed form of a hex address of any type.
+pub fn fixed_bytes_from_hex(hex: &str) -> Result<[u8; FIXED_ADDRESS_LENGTH], AddressError> {
+    let tagged = tagged_bytes(hex)?;
+    let mut fixed = [0u8; FIXED_ADDRESS_LENGTH];
+    fixed[..tagged.len()].copy_from_slice(&tagged);
+    Ok(fixed)
+}
+
+/// Hex address of a fixed form, fails unless the padding is zero.
+pub fn hex_from_fixed_bytes(fixed: &[u8; FIXED_ADDRESS_LENGTH]) -> Result<String, AddressError> {
+    let (_, len) = from_tag(fixed[0])?;
+    if fixed[len + 1..].iter().any(|byte| *byte != 0) {
+        return Err(AddressError::InvalidAddress(
+            "Error::Invalid address padding",
+        ));
+    }
+    hex_from_tagged(&fixed[..len + 1])
+}
+
+impl Address {
+    /// Serialize the address as its tag and bytes, zero padded to [`FIXED_ADDRESS_LENGTH`].
+    /// Byte Format : [tag, address bytes, padding]
+    pub fn to_fixed_bytes(&self) -> [u8; FIXED_ADDRESS_LENGTH] {
+        let (tag, len) = match *self {
+            Address::Standard(c) => tag(c.addr_type),
+            Address::Script(s) => tag(s.addr_type),
+            Address::Multisig(m) => tag(m.addr_type),
+        };
+        let mut fixed = [0u8; FIXED_ADDRESS_LENGTH];
+        fixed[0] = tag;
+        fixed[1..len + 1].copy_from_slice(&self.as_bytes());
+        fixed
+    }
+
+    /// Parse an address from its fixed form.
+    /// A script address only carries the hash of its root and can not be re-created,
+    /// use [`hex_from_fixed_bytes`] for its hex string.
+    pub fn from_fixed_bytes(fixed: &[u8; FIXED_ADDRESS_LENGTH]) -> Result<Address, AddressError> {
+        // checks the tag and the padding
+        hex_from_fixed_bytes(fixed)?;
+        let (addr_type, len) = from_tag(fixed[0])?;
+        let bytes = &fixed[1..len + 1];
+        match addr_type {
+            AddressType::Standard => Ok(Address::Standard(Standard::from_bytes(bytes)?)),
+            AddressType::Script => Err(AddressError::InvalidAddress(
+                "Error::ScriptAddress can not be re-created from bytes",
+            )),
+            AddressType::Multisig => Ok(Address::Multisig(Multisig::from_bytes(bytes)?)),
+        }
+    }
+}
+
+/// Serializes a hex address as its tag and bytes if the format is binary.
+pub fn serialize<S: Serializer>(hex: &String, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        return serializer.serialize_str(hex);
+    }
+    let tagged = tagged_bytes(hex).map_err(serde::ser::Error::custom)?;
+    serializer.serialize_bytes(&tagged)
+}
+
+/// Deserializes a hex address written by [`serialize`].
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    if deserializer.is_human_readable() {
+        return String::deserialize(deserializer);
+    }
+    let tagged: Vec<u8> = serde_bytes_vec(deserializer)?;
+    hex_from_tagged(&tagged).map_err(D::Error::custom)
+}
+
+// bytes written with `serialize_bytes`
+fn serde_bytes_vec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    struct BytesVisitor;
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "tagged address bytes")
+        }
+
+        fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+    deserializer.deserialize_bytes(BytesVisitor)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Owner {
+        #[serde(with = "crate::compact")]
+        owner: String,
+    }
+
+    fn addresses() -> Vec<Address> {
+        let (pk, _) = quisquislib::accounts::Account::generate_random_account_with_value(
+            curve25519_dalek::scalar::Scalar::from(0u64),
+        )
+        .0
+        .get_account();
+        vec![
+            Address::standard_address(Network::Mainnet, pk),
+            Address::script_address(Network::Testnet, [7u8; 32]),
+            Address::multisig_address(Network::Mainnet, [9u8; 32]),
+        ]
+    }
+
+    #[test]
+    fn fixed_bytes_test() {
+        for (address, len) in addresses().into_iter().zip([69, 21, 37]) {
+            let fixed = address.to_fixed_bytes();
+            assert_eq!(fixed.len(), 70);
+            assert!(fixed[len + 1..].iter().all(|byte| *byte == 0));
+            assert_eq!(fixed_bytes_from_hex(&address.as_hex()), Ok(fixed));
+            assert_eq!(hex_from_fixed_bytes(&fixed), Ok(address.as_hex()));
+            match address {
+                Address::Script(_) => assert!(Address::from_fixed_bytes(&fixed).is_err()),
+                _ => assert_eq!(Address::from_fixed_bytes(&fixed), Ok(address)),
+            }
+
+            // the tag has to match the type and the padding has to be zero
+            let mut retagged = fixed;
+            retagged[0] = (retagged[0] + 1) % 3;
+            assert!(hex_from_fixed_bytes(&retagged).is_err());
+            if len + 1 < FIXED_ADDRESS_LENGTH {
+                let mut padded = fixed;
+                padded[FIXED_ADDRESS_LENGTH - 1] = 1;
+                assert!(hex_from_fixed_bytes(&padded).is_err());
+            }
+        }
+        assert!(fixed_bytes_from_hex("").is_err());
+        assert!(fixed_bytes_from_hex("not hex").is_err());
+    }
+
+    #[test]
+    fn compact_serde_test() {
+        for address in addresses() {
+            let owner = Owner {
+                owner: address.as_hex(),
+            };
+            // the hex string stays in json
+            let json = serde_json::to_string(&owner).unwrap();
+            assert_eq!(json, format!("{{\"owner\":\"{}\"}}", owner.owner));
+            assert_eq!(serde_json::from_str::<Owner>(&json).unwrap(), owner);
+
+            // bincode writes the length, the tag and the address bytes
+            let bytes = bincode::serialize(&owner).unwrap();
+            assert_eq!(bytes.len(), 8 + 1 + owner.owner.len() / 2);
+            assert_eq!(bincode::deserialize::<Owner>(&bytes).unwrap(), owner);
+        }
+        let invalid = Owner {
+            owner: "predicate root".to_string(),
+        };
+        assert!(bincode::serialize(&invalid).is_err());
+    }
+}
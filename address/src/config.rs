@@ -0,0 +1,300 @@
+//! Network magic byte configuration.
+//!
+//! The magic bytes prefixed to addresses are taken from the chain config.
+//! A [`NetworkConfig`] can be installed once at startup with [`set_network_config`].
+//! If no config is installed the default sample values are used.
+
+use crate::{AddressType, Network};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+static NETWORK_CONFIG: OnceCell<NetworkConfig> = OnceCell::new();
+
+/// Magic bytes of the address types for each network.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Magic byte of standard addresses on Mainnet.
+    pub mainnet_standard: u8,
+    /// Magic byte of script addresses on Mainnet.
+    pub mainnet_script: u8,
+    /// Magic byte of standard addresses on Testnet.
+    pub testnet_standard: u8,
+    /// Magic byte of script addresses on Testnet.
+    pub testnet_script: u8,
+    /// Magic byte of multisig addresses on Mainnet.
+    #[serde(default = "default_mainnet_multisig")]
+    pub mainnet_multisig: u8,
+    /// Magic byte of multisig addresses on Testnet.
+    #[serde(default = "default_testnet_multisig")]
+    pub testnet_multisig: u8,
+}
+
+fn default_mainnet_multisig() -> u8 {
+    36
+}
+
+fn default_testnet_multisig() -> u8 {
+    88
+}
+
+impl Default for NetworkConfig {
+    fn default() -> NetworkConfig {
+        NetworkConfig {
+            mainnet_standard: 12,
+            mainnet_script: 24,
+            testnet_standard: 44,
+            testnet_script: 66,
+            mainnet_multisig: default_mainnet_multisig(),
+            testnet_multisig: default_testnet_multisig(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Create a new config with the default multisig bytes.
+    /// Fails if the same byte is assigned twice.
+    pub fn new(
+        mainnet_standard: u8,
+        mainnet_script: u8,
+        testnet_standard: u8,
+        testnet_script: u8,
+    ) -> Result<NetworkConfig, &'static str> {
+        let config = NetworkConfig {
+            mainnet_standard,
+            mainnet_script,
+            testnet_standard,
+            testnet_script,
+            mainnet_multisig: default_mainnet_multisig(),
+            testnet_multisig: default_testnet_multisig(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Set the multisig bytes. Fails if the same byte is assigned twice.
+    pub fn with_multisig(
+        self,
+        mainnet_multisig: u8,
+        testnet_multisig: u8,
+    ) -> Result<NetworkConfig, &'static str> {
+        let config = NetworkConfig {
+            mainnet_multisig,
+            testnet_multisig,
+            ..self
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Load the config from the environment.
+    /// Reads `MAINNET_STANDARD_BYTE`, `MAINNET_SCRIPT_BYTE`, `TESTNET_STANDARD_BYTE`,
+    /// `TESTNET_SCRIPT_BYTE`, `MAINNET_MULTISIG_BYTE` and `TESTNET_MULTISIG_BYTE`.
+    /// Missing variables fall back to the default values.
+    pub fn from_env() -> Result<NetworkConfig, &'static str> {
+        NetworkConfig::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Load the config with the keys of [`NetworkConfig::from_env`] from `lookup`.
+    pub fn from_lookup<F: Fn(&str) -> Option<String>>(
+        lookup: F,
+    ) -> Result<NetworkConfig, &'static str> {
+        let read = |key: &str, default: u8| -> Result<u8, &'static str> {
+            match lookup(key) {
+                Some(value) => value
+                    .trim()
+                    .parse::<u8>()
+                    .map_err(|_| "Error::InvalidNetworkConfigByte"),
+                None => Ok(default),
+            }
+        };
+        let default = NetworkConfig::default();
+        NetworkConfig::new(
+            read("MAINNET_STANDARD_BYTE", default.mainnet_standard)?,
+            read("MAINNET_SCRIPT_BYTE", default.mainnet_script)?,
+            read("TESTNET_STANDARD_BYTE", default.testnet_standard)?,
+            read("TESTNET_SCRIPT_BYTE", default.testnet_script)?,
+        )?
+        .with_multisig(
+            read("MAINNET_MULTISIG_BYTE", default.mainnet_multisig)?,
+            read("TESTNET_MULTISIG_BYTE", default.testnet_multisig)?,
+        )
+    }
+
+    /// Reject configs where two address types share a magic byte.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let bytes = [
+            self.mainnet_standard,
+            self.mainnet_script,
+            self.testnet_standard,
+            self.testnet_script,
+            self.mainnet_multisig,
+            self.testnet_multisig,
+        ];
+        for i in 0..bytes.len() {
+            for j in i + 1..bytes.len() {
+                if bytes[i] == bytes[j] {
+                    return Err("Error::OverlappingNetworkConfigBytes");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the magic byte of an address type on the given network.
+    pub fn magic_byte(&self, net: Network, addr_type: &AddressType) -> u8 {
+        match (net, addr_type) {
+            (Network::Mainnet, AddressType::Standard) => self.mainnet_standard,
+            (Network::Mainnet, AddressType::Script) => self.mainnet_script,
+            (Network::Testnet, AddressType::Standard) => self.testnet_standard,
+            (Network::Testnet, AddressType::Script) => self.testnet_script,
+            (Network::Mainnet, AddressType::Multisig) => self.mainnet_multisig,
+            (Network::Testnet, AddressType::Multisig) => self.testnet_multisig,
+        }
+    }
+
+    /// Recover the network given a magic byte.
+    pub fn network(&self, byte: u8) -> Result<Network, &'static str> {
+        if byte == self.mainnet_standard
+            || byte == self.mainnet_script
+            || byte == self.mainnet_multisig
+        {
+            Ok(Network::Mainnet)
+        } else if byte == self.testnet_standard
+            || byte == self.testnet_script
+            || byte == self.testnet_multisig
+        {
+            Ok(Network::Testnet)
+        } else {
+            Err("Error::InvalidNteworkByte")
+        }
+    }
+
+    /// Recover the address type given a magic byte and the network.
+    pub fn address_type(&self, byte: u8, net: Network) -> Result<AddressType, &'static str> {
+        if byte == self.magic_byte(net, &AddressType::Standard) {
+            Ok(AddressType::Standard)
+        } else if byte == self.magic_byte(net, &AddressType::Script) {
+            Ok(AddressType::Script)
+        } else if byte == self.magic_byte(net, &AddressType::Multisig) {
+            Ok(AddressType::Multisig)
+        } else {
+            Err("Error::InvalidAddressTypeMagicByte")
+        }
+    }
+}
+
+/// Install the network config. Can only be called once, before any address is parsed.
+pub fn set_network_config(config: NetworkConfig) -> Result<(), &'static str> {
+    config.validate()?;
+    NETWORK_CONFIG
+        .set(config)
+        .map_err(|_| "Error::NetworkConfigAlreadySet")
+}
+
+/// Get the installed network config or the default one.
+pub fn network_config() -> NetworkConfig {
+    match NETWORK_CONFIG.get() {
+        Some(config) => *config,
+        None => NetworkConfig::default(),
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn default_config_test() {
+        let config = NetworkConfig::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.magic_byte(Network::Mainnet, &AddressType::Standard),
+            12
+        );
+        assert_eq!(
+            config.magic_byte(Network::Testnet, &AddressType::Script),
+            66
+        );
+        assert_eq!(config.network(24), Ok(Network::Mainnet));
+        assert_eq!(config.network(44), Ok(Network::Testnet));
+    }
+    #[test]
+    fn overlapping_config_test() {
+        assert!(NetworkConfig::new(1, 2, 3, 1).is_err());
+        assert!(NetworkConfig::new(5, 5, 6, 7).is_err());
+        let config = NetworkConfig {
+            mainnet_standard: 9,
+            mainnet_script: 10,
+            testnet_standard: 10,
+            testnet_script: 11,
+            mainnet_multisig: 12,
+            testnet_multisig: 13,
+        };
+        assert!(set_network_config(config).is_err());
+        // multisig bytes overlapping a standard byte
+        let default = NetworkConfig::default();
+        assert!(default.with_multisig(12, 88).is_err());
+        assert!(default.with_multisig(36, 36).is_err());
+    }
+    #[test]
+    fn multisig_config_test() {
+        let config = NetworkConfig::default().with_multisig(50, 51).unwrap();
+        assert_eq!(
+            config.magic_byte(Network::Mainnet, &AddressType::Multisig),
+            50
+        );
+        assert_eq!(config.network(51), Ok(Network::Testnet));
+        assert_eq!(
+            config.address_type(51, Network::Testnet),
+            Ok(AddressType::Multisig)
+        );
+        assert!(config.address_type(50, Network::Testnet).is_err());
+
+        // configs written before multisig addresses keep the default bytes
+        let json = r#"{"mainnet_standard":12,"mainnet_script":24,"testnet_standard":44,"testnet_script":66}"#;
+        let parsed: NetworkConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed, NetworkConfig::default());
+    }
+    #[test]
+    fn swapped_config_test() {
+        let default = NetworkConfig::default();
+        let swapped = NetworkConfig::new(44, 66, 12, 24).unwrap();
+        // same byte maps to a different network under the swapped config
+        assert_eq!(default.network(12), Ok(Network::Mainnet));
+        assert_eq!(swapped.network(12), Ok(Network::Testnet));
+        assert_eq!(
+            swapped.address_type(66, Network::Mainnet),
+            Ok(AddressType::Script)
+        );
+        assert!(swapped.address_type(66, Network::Testnet).is_err());
+
+        let custom = NetworkConfig::new(100, 101, 102, 103).unwrap();
+        assert!(custom.network(12).is_err());
+        assert_eq!(custom.network(103), Ok(Network::Testnet));
+        assert_eq!(
+            custom.address_type(102, Network::Testnet),
+            Ok(AddressType::Standard)
+        );
+    }
+    #[test]
+    fn lookup_config_test() {
+        let lookup = |key: &str| match key {
+            "MAINNET_STANDARD_BYTE" => Some(String::from(" 100 ")),
+            "TESTNET_MULTISIG_BYTE" => Some(String::from("101")),
+            _ => None,
+        };
+        let config = NetworkConfig::from_lookup(lookup).unwrap();
+        assert_eq!(config.mainnet_standard, 100);
+        assert_eq!(config.testnet_multisig, 101);
+        assert_eq!(
+            config.mainnet_script,
+            NetworkConfig::default().mainnet_script
+        );
+        assert_eq!(
+            NetworkConfig::from_lookup(|_| Some(String::from("x"))),
+            Err("Error::InvalidNetworkConfigByte")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Derivation of standard addresses from a wallet seed.
+//!
+//! A wallet holds one base key pair. The address of index `i` is the standard address
+//! of the base public key updated with a scalar derived from the seed and `i`, so the
+//! addresses of a wallet are unlinkable while the wallet can recompute all of them
+//! from the seed.
+//!
+//! The update scalar is `Keccak512(label || seed length || seed || index)` reduced
+//! modulo the group order, with the seed length as a little endian `u64` and the index
+//! as a little endian `u32`. A QuisQuis key update scales both components of the
+//! public key by the same scalar, so the updated key is still signed for by the base
+//! secret key.
+
+use crate::{Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::keys::PublicKey;
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use sha3::{Digest, Keccak512};
+use std::ops::Range;
+
+/// Domain separation label of the update scalar.
+pub const ADDRESS_DERIVATION_LABEL: &[u8] = b"ZkOS.address.derive";
+
+/// Update scalar of the address of `index`.
+pub fn derivation_scalar(seed: &[u8], index: u32) -> Scalar {
+    let mut hasher = Keccak512::new();
+    hasher.update(ADDRESS_DERIVATION_LABEL);
+    hasher.update((seed.len() as u64).to_le_bytes());
+    hasher.update(seed);
+    hasher.update(index.to_le_bytes());
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+/// Standard address of `index` and the scalar `base_pk` was updated with.
+pub fn derive_standard_address(
+    network: Network,
+    base_pk: &RistrettoPublicKey,
+    seed: &[u8],
+    index: u32,
+) -> (Address, Scalar) {
+    let scalar = derivation_scalar(seed, index);
+    let pk = RistrettoPublicKey::update_public_key(base_pk, scalar);
+    (Address::standard_address(network, pk), scalar)
+}
+
+/// Addresses of all the indexes of `range`, e.g. to scan up to the gap limit.
+pub fn derive_batch(
+    network: Network,
+    base_pk: &RistrettoPublicKey,
+    seed: &[u8],
+    range: Range<u32>,
+) -> Vec<(u32, Address, Scalar)> {
+    range
+        .map(|index| {
+            let (address, scalar) = derive_standard_address(network, base_pk, seed, index);
+            (index, address, scalar)
+        })
+        .collect()
+}
+
+/// Secret key signing for the address derived from `base_pk` with `update_scalar`.
+///
+/// The key update keeps the secret key, so this is `base_sk` once it is checked
+/// against the derived public key. Fails if `base_sk` is not the key of `base_pk`.
+pub fn derive_secret_key(
+    base_sk: &RistrettoSecretKey,
+    base_pk: &RistrettoPublicKey,
+    update_scalar: Scalar,
+) -> Result<RistrettoSecretKey, &'static str> {
+    let pk = RistrettoPublicKey::update_public_key(base_pk, update_scalar);
+    pk.verify_keypair(base_sk)
+        .map_err(|_| "Error::Secret key does not sign for the derived address")?;
+    Ok(base_sk.clone())
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quisquislib::accounts::Account;
+
+    const SEED: &[u8] = b"zkos derivation test seed";
+
+    fn base_keys() -> (RistrettoPublicKey, RistrettoSecretKey) {
+        let (acc, sk) = Account::generate_random_account_with_value(Scalar::from(0u64));
+        (acc.get_account().0, sk)
+    }
+
+    #[test]
+    fn derivation_scalar_vector_test() {
+        let vectors = [
+            (
+                0u32,
+                "9d10237bb8a5774832a02ab51e1f64a5517497b28e69df8603c599c2cfc4fb03",
+            ),
+            (
+                1,
+                "808c6964aa49605e6965dabc7304e241fb9260738aec6199cb37177b5cc0f708",
+            ),
+            (
+                7,
+                "a2b0bacfbdb18eeae7de3b9fd53802f62743ca2730ecc644844248bebce6810a",
+            ),
+        ];
+        for (index, expected) in vectors {
+            assert_eq!(
+                hex::encode(derivation_scalar(SEED, index).as_bytes()),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn derive_standard_address_test() {
+        let (base_pk, _) = base_keys();
+        let (address, scalar) = derive_standard_address(Network::Testnet, &base_pk, SEED, 7);
+        let expected = RistrettoPublicKey::update_public_key(&base_pk, scalar);
+        assert_eq!(
+            address,
+            Address::standard_address(Network::Testnet, expected)
+        );
+        // deterministic, and distinct per index and seed
+        assert_eq!(
+            derive_standard_address(Network::Testnet, &base_pk, SEED, 7).0,
+            address
+        );
+        assert_ne!(
+            derive_standard_address(Network::Testnet, &base_pk, SEED, 8).0,
+            address
+        );
+        assert_ne!(
+            derive_standard_address(Network::Testnet, &base_pk, b"other seed", 7).0,
+            address
+        );
+
+        let batch = derive_batch(Network::Testnet, &base_pk, SEED, 5..10);
+        assert_eq!(batch.len(), 5);
+        assert_eq!(batch[2].0, 7);
+        assert_eq!(batch[2].1, address);
+        assert_eq!(batch[2].2, scalar);
+    }
+
+    #[test]
+    fn derive_secret_key_sign_test() {
+        let (base_pk, base_sk) = base_keys();
+        let (address, scalar) = derive_standard_address(Network::Mainnet, &base_pk, SEED, 3);
+        let sk = derive_secret_key(&base_sk, &base_pk, scalar).unwrap();
+
+        let pk: RistrettoPublicKey = address.into();
+        let message = b"derived address".to_vec();
+        let signature = pk.sign_msg(&message, &sk, b"DeriveSign");
+        assert!(pk.verify_msg(&message, &signature, b"DeriveSign").is_ok());
+
+        // the key of another wallet is rejected
+        let (_, other_sk) = base_keys();
+        assert!(derive_secret_key(&other_sk, &base_pk, scalar).is_err());
+    }
+}
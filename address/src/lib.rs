@@ -4,6 +4,15 @@
 //! ZkOS Transaction Address implementation.
 pub extern crate quisquislib;
 
+mod book;
+pub mod compact;
+mod config;
+pub mod keys;
+pub use book::{AddressBook, AddressBookEntry, AddressError};
+pub use compact::FIXED_ADDRESS_LENGTH;
+pub use config::{network_config, set_network_config, NetworkConfig};
+pub use keys::{derive_batch, derive_secret_key, derive_standard_address};
+
 use bs58;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use quisquislib::{keys::PublicKey, ristretto::RistrettoPublicKey};
@@ -12,11 +21,29 @@ use serde::{Deserialize, Serialize};
 use sha3::Keccak256;
 use std::fmt;
 
+/// Length of a standard address: magic byte, public key and checksum.
+pub const STANDARD_ADDRESS_LENGTH: usize = 69;
+/// Length of a script address: magic byte and RIPEMD-160 of the script root.
+pub const SCRIPT_ADDRESS_LENGTH: usize = 21;
+/// Length of a multisig address: magic byte, key set tree root and checksum.
+pub const MULTISIG_ADDRESS_LENGTH: usize = 37;
+
+// Fails with `InvalidLength` unless `bytes` is `expected` bytes long.
+fn check_length(bytes: &[u8], expected: usize) -> Result<(), AddressError> {
+    if bytes.len() != expected {
+        return Err(AddressError::InvalidLength {
+            expected,
+            got: bytes.len(),
+        });
+    }
+    Ok(())
+}
+
 /// The list of the existing Twilight networks.
 /// Network type: Mainnet, Testnet.
 /// Network implements [`Default`] and returns [`Network::Mainnet`].
 ///
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Network {
     /// Mainnet is the "production" network and blockchain.
     Mainnet,
@@ -26,29 +53,22 @@ pub enum Network {
 }
 impl Network {
     /// Get the associated magic byte given an address type.
-    /// The byte values should be taken from the blockchain config file. The same values should be used here. Sample values are used here
+    /// The byte values are taken from the installed [`NetworkConfig`].
     pub fn as_u8(self, addr_type: &AddressType) -> u8 {
-        use AddressType::*;
-        match self {
-            Network::Mainnet => match addr_type {
-                Standard => 12,
-                Script => 24,
-            },
-            Network::Testnet => match addr_type {
-                Standard => 44,
-                Script => 66,
-            },
-        }
+        network_config().magic_byte(self, addr_type)
     }
 
     /// Recover the network type given an address magic byte.
-    /// The byte values should be taken from the blockchain config file. The same values should be used here. Sample values are used here
+    /// The byte values are taken from the installed [`NetworkConfig`].
     pub fn from_u8(byte: u8) -> Result<Network, &'static str> {
-        use Network::*;
-        match byte {
-            12 | 24 => Ok(Mainnet),
-            44 | 66 => Ok(Testnet),
-            _ => Err("Error::InvalidNteworkByte"),
+        network_config().network(byte)
+    }
+
+    /// Lower case name of the network, as used in configs and metric labels.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
         }
     }
 }
@@ -58,7 +78,26 @@ impl Default for Network {
         Network::Mainnet
     }
 }
-/// Address type: standard, contract.
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Network {
+    type Err = &'static str;
+
+    /// Parses the name of a network, in any case.
+    fn from_str(name: &str) -> Result<Network, Self::Err> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            _ => Err("Error::UnknownNetwork"),
+        }
+    }
+}
+/// Address type: standard, contract, multisig.
 ///
 /// AddressType implements [`Default`] and returns [`AddressType::Coin`].
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
@@ -67,26 +106,30 @@ pub enum AddressType {
     Standard,
     /// Script addresses.
     Script,
+    /// Coin address owned by m of n keys.
+    Multisig,
 }
 
 impl AddressType {
     /// Recover the address type given an address bytes and the network.
-    pub fn from_slice(bytes: &[u8], net: Network) -> Result<AddressType, &'static str> {
-        let byte = bytes[0];
-        use AddressType::*;
-        use Network::*;
-        match net {
-            Mainnet => match byte {
-                12 => Ok(Standard),
-                24 => Ok(Script),
-                _ => Err("Error::InvalidAddressTypeMagicByte"),
-            },
-            Testnet => match byte {
-                44 => Ok(Standard),
-                66 => Ok(Script),
-                _ => Err("Error::InvalidAddressTypeMagicByte"),
-            },
+    pub fn from_slice(bytes: &[u8], net: Network) -> Result<AddressType, AddressError> {
+        let byte = *bytes.first().ok_or(AddressError::InvalidLength {
+            expected: 1,
+            got: 0,
+        })?;
+        network_config()
+            .address_type(byte, net)
+            .map_err(AddressError::InvalidAddress)
+    }
+
+    /// Recover the address type of a hex encoded address.
+    pub fn from_hex(hex: &str) -> Result<AddressType, &'static str> {
+        let bytes = hex::decode(hex).map_err(|_| "Error::InvalidHex")?;
+        if bytes.is_empty() {
+            return Err("Error::InvalidAddressLength");
         }
+        let network = Network::from_u8(bytes[0])?;
+        Ok(AddressType::from_slice(&bytes, network)?)
     }
 }
 
@@ -101,6 +144,7 @@ impl fmt::Display for AddressType {
         match *self {
             AddressType::Standard => write!(f, "Coin address"),
             AddressType::Script => write!(f, "Script address"),
+            AddressType::Multisig => write!(f, "Multisig address"),
         }
     }
 }
@@ -114,6 +158,8 @@ pub enum Address {
     Standard(Standard),
     /// Script addresses.
     Script(Script),
+    /// Multisig coin addresses.
+    Multisig(Multisig),
 }
 
 impl Address {
@@ -138,11 +184,23 @@ impl Address {
             root,
         })
     }
+
+    /// Create a multisig address which is valid on the given network.
+    /// `root` is the merkle root of the key sets allowed to spend, see
+    /// `zkvm::zkos_types::multisig`.
+    pub fn multisig_address(network: Network, root: [u8; 32]) -> Address {
+        Self::Multisig(Multisig {
+            network,
+            addr_type: AddressType::Multisig,
+            root,
+        })
+    }
     /// Serialize the address bytes as a BTC-Base58 string.
     pub fn as_base58(&self) -> String {
         match *self {
             Address::Standard(c) => c.as_base58(),
             Address::Script(s) => s.as_base58(),
+            Address::Multisig(m) => m.as_base58(),
         }
     }
 
@@ -151,6 +209,7 @@ impl Address {
         match *self {
             Address::Standard(c) => c.as_hex(),
             Address::Script(s) => s.as_hex(),
+            Address::Multisig(m) => m.as_hex(),
         }
     }
 
@@ -159,6 +218,7 @@ impl Address {
         match *self {
             Address::Standard(c) => c.as_bytes(),
             Address::Script(s) => s.as_bytes().to_vec(),
+            Address::Multisig(m) => m.as_bytes(),
         }
     }
     pub fn as_script_address(&self) -> Script {
@@ -179,6 +239,7 @@ impl Address {
         match add_type {
             AddressType::Standard => Ok(Address::Standard(Standard::from_bytes(&bytes)?)),
             AddressType::Script => Err("Error::ScriptAddress can not be re-created from hex"),
+            AddressType::Multisig => Ok(Address::Multisig(Multisig::from_bytes(&bytes)?)),
         }
     }
 
@@ -191,6 +252,7 @@ impl Address {
         match add_type {
             AddressType::Standard => Ok(Address::Standard(Standard::from_bytes(&bytes)?)),
             AddressType::Script => Err("Error::ScriptAddress can not be re-created from Base58"),
+            AddressType::Multisig => Ok(Address::Multisig(Multisig::from_bytes(&bytes)?)),
         }
     }
     pub fn get_standard_address(&self) -> Result<Standard, &'static str> {
@@ -205,6 +267,12 @@ impl Address {
             _ => Err("Error::Not a script address"),
         }
     }
+    pub fn get_multisig_address(&self) -> Result<Multisig, &'static str> {
+        match *self {
+            Address::Multisig(m) => Ok(m),
+            _ => Err("Error::Not a multisig address"),
+        }
+    }
 }
 impl Default for Address {
     fn default() -> Address {
@@ -217,6 +285,7 @@ impl fmt::Display for Address {
         match *self {
             Address::Standard(ref c) => write!(f, "{}", c.as_base58()),
             Address::Script(ref s) => write!(f, "{}", s.as_base58()),
+            Address::Multisig(ref m) => write!(f, "{}", m.as_base58()),
         }
     }
 }
@@ -264,18 +333,20 @@ impl Standard {
 
     /// Parse an address from a vector of bytes, fail if the magic byte is incorrect, if public
     /// keys are not valid points, and if checksums missmatch.
-    pub fn from_bytes(bytes: &[u8]) -> Result<Standard, &'static str> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Standard, AddressError> {
         use sha3::Digest;
-        let network = Network::from_u8(bytes[0])?;
+        check_length(bytes, STANDARD_ADDRESS_LENGTH)?;
+        let network = Network::from_u8(bytes[0]).map_err(AddressError::InvalidAddress)?;
         let addr_type = AddressType::from_slice(&bytes, network)?;
-        let public_key = RistrettoPublicKey::from_bytes(&bytes[1..65])?;
+        let public_key =
+            RistrettoPublicKey::from_bytes(&bytes[1..65]).map_err(AddressError::InvalidAddress)?;
 
         let (checksum_bytes, checksum) = (&bytes[0..65], &bytes[65..69]);
         let mut hasher = Keccak256::new();
         hasher.update(checksum_bytes);
         let checksum_verify = hasher.finalize();
         if &checksum_verify[0..4] != checksum {
-            return Err("Invalid Checksum");
+            return Err(AddressError::InvalidAddress("Invalid Checksum"));
         }
 
         Ok(Standard {
@@ -309,6 +380,11 @@ impl Standard {
     }
 
     /// Convert Hex address string to Address
+    ///
+    /// # Panics
+    ///
+    /// Panics if `s` is not a valid address, use [`Standard::from_hex_with_error`]
+    /// for untrusted input.
     pub fn from_hex(s: &str) -> Self {
         Self::from_bytes(&hex::decode(s).unwrap().as_slice()).unwrap()
     }
@@ -319,6 +395,11 @@ impl Standard {
     }
 
     /// Convert Base58 address string to Address
+    ///
+    /// # Panics
+    ///
+    /// Panics if `s` is not a valid address, use [`Address::from_base58`] for
+    /// untrusted input.
     pub fn from_base58(s: &str) -> Self {
         let decoded = bs58::decode(s).into_vec().unwrap();
         Self::from_bytes(&decoded).unwrap()
@@ -397,6 +478,73 @@ impl Default for Script {
         }
     }
 }
+/// A multisig coin address valid for a specific network.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Copy)]
+pub struct Multisig {
+    /// The network on which the address is valid and should be used.
+    pub network: Network,
+    /// The address type.
+    pub addr_type: AddressType,
+    /// The root hash of the tree of key sets allowed to spend.
+    pub root: [u8; 32],
+}
+
+impl Multisig {
+    /// Parse an address from a vector of bytes, fail if the magic byte is not a multisig byte
+    /// or if checksums missmatch.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Multisig, AddressError> {
+        use sha3::Digest;
+        check_length(bytes, MULTISIG_ADDRESS_LENGTH)?;
+        let network = Network::from_u8(bytes[0]).map_err(AddressError::InvalidAddress)?;
+        let addr_type = AddressType::from_slice(&bytes, network)?;
+        if addr_type != AddressType::Multisig {
+            return Err(AddressError::InvalidAddress(
+                "Error::Not a multisig address",
+            ));
+        }
+        let (checksum_bytes, checksum) = (&bytes[0..33], &bytes[33..37]);
+        let mut hasher = Keccak256::new();
+        hasher.update(checksum_bytes);
+        let checksum_verify = hasher.finalize();
+        if &checksum_verify[0..4] != checksum {
+            return Err(AddressError::InvalidAddress("Invalid Checksum"));
+        }
+        Ok(Multisig {
+            network,
+            addr_type,
+            root: bytes[1..33].try_into().unwrap(),
+        })
+    }
+
+    /// Serialize the address as a vector of bytes.
+    /// Byte Format : [magic byte, key set tree root hash, checksum]
+    pub fn as_bytes(&self) -> Vec<u8> {
+        use sha3::Digest;
+        let mut bytes = vec![self.network.as_u8(&self.addr_type)];
+        bytes.extend_from_slice(&self.root);
+        let mut hasher = Keccak256::new();
+        hasher.update(&bytes);
+        let checksum = hasher.finalize();
+        bytes.extend_from_slice(&checksum[0..4]);
+        bytes
+    }
+
+    /// Serialize the address bytes as a hexadecimal string.
+    pub fn as_hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+
+    /// Serialize the address bytes as a BTC-Base58 string.
+    pub fn as_base58(&self) -> String {
+        bs58::encode(self.as_bytes()).into_string()
+    }
+
+    /// get root hash from multisig address
+    pub fn get_root_hash(&self) -> [u8; 32] {
+        self.root
+    }
+}
+
 //MOVED Inside qq-rust-lib
 // Deserialize a public key from a slice. The input slice is 64 bytes
 // Utility Function
@@ -419,7 +567,120 @@ impl Default for Script {
 #[cfg(test)]
 mod test {
     use super::*;
+    use proptest::prelude::*;
     use sha3::{Digest, Keccak256};
+
+    const ADDRESS_TYPES: [AddressType; 3] = [
+        AddressType::Standard,
+        AddressType::Script,
+        AddressType::Multisig,
+    ];
+
+    fn random_standard_address() -> Address {
+        let (pk, _) = quisquislib::accounts::Account::generate_random_account_with_value(
+            curve25519_dalek::scalar::Scalar::from(0u64),
+        )
+        .0
+        .get_account();
+        Address::standard_address(Network::Testnet, pk)
+    }
+
+    // every parser of untrusted input, the results do not matter
+    fn parse_bytes(bytes: &[u8]) {
+        let _ = AddressType::from_slice(bytes, Network::Mainnet);
+        let _ = AddressType::from_slice(bytes, Network::Testnet);
+        let _ = Standard::from_bytes(bytes);
+        let _ = Multisig::from_bytes(bytes);
+        parse_str(&hex::encode(bytes));
+        parse_str(&bs58::encode(bytes).into_string());
+    }
+
+    fn parse_str(s: &str) {
+        let _ = AddressType::from_hex(s);
+        let _ = Standard::from_hex_with_error(s);
+        for add_type in ADDRESS_TYPES {
+            let _ = Address::from_hex(s, add_type);
+            let _ = Address::from_base58(s, add_type);
+        }
+        let _ = AddressBook::new(Network::Testnet).add("fuzz", s);
+    }
+
+    proptest! {
+        #[test]
+        fn parse_arbitrary_bytes_test(bytes in proptest::collection::vec(any::<u8>(), 0..80)) {
+            parse_bytes(&bytes);
+        }
+
+        #[test]
+        fn parse_arbitrary_string_test(s in "\\PC{0,160}") {
+            parse_str(&s);
+        }
+
+        #[test]
+        fn parse_hex_string_test(s in "[0-9a-fA-F]{0,160}") {
+            parse_str(&s);
+        }
+
+        #[test]
+        fn address_length_test(len in 0usize..140, byte in any::<u8>()) {
+            let bytes = vec![byte; len];
+            if len != STANDARD_ADDRESS_LENGTH {
+                prop_assert_eq!(
+                    Standard::from_bytes(&bytes),
+                    Err(AddressError::InvalidLength { expected: STANDARD_ADDRESS_LENGTH, got: len })
+                );
+            }
+            if len != MULTISIG_ADDRESS_LENGTH {
+                prop_assert_eq!(
+                    Multisig::from_bytes(&bytes),
+                    Err(AddressError::InvalidLength { expected: MULTISIG_ADDRESS_LENGTH, got: len })
+                );
+            }
+        }
+    }
+
+    // inputs which panicked the parsers
+    #[test]
+    fn short_address_regression_test() {
+        assert_eq!(
+            AddressType::from_slice(&[], Network::Mainnet),
+            Err(AddressError::InvalidLength {
+                expected: 1,
+                got: 0
+            })
+        );
+        assert_eq!(
+            AddressType::from_hex(""),
+            Err("Error::InvalidAddressLength")
+        );
+        assert_eq!(
+            Address::from_hex("", AddressType::Standard),
+            Err("Error::InvalidAddressLength")
+        );
+        assert_eq!(
+            Address::from_hex("0c", AddressType::Standard),
+            Err("Error::InvalidAddressLength")
+        );
+        assert_eq!(
+            Address::from_base58("", AddressType::Multisig),
+            Err("Error::InvalidAddressLength")
+        );
+        assert!(Standard::from_hex_with_error("0c").is_err());
+
+        // every truncation of a valid address
+        let bytes = random_standard_address().as_bytes();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                Standard::from_bytes(&bytes[..len]),
+                Err(AddressError::InvalidLength {
+                    expected: STANDARD_ADDRESS_LENGTH,
+                    got: len
+                })
+            );
+            parse_bytes(&bytes[..len]);
+        }
+        assert!(Standard::from_bytes(&bytes).is_ok());
+    }
     #[test]
     fn hex_encoding_decoding_test() {}
 
@@ -437,4 +698,70 @@ mod test {
         println!("length: {:?}", by.len());
         println!("bytes: {:?}", by);
     }
+
+    #[test]
+    fn multisig_address_encoding_test() {
+        let mut hasher = Keccak256::new();
+        hasher.update("key set tree");
+        let root: [u8; 32] = hasher.finalize().into();
+
+        let add = Address::multisig_address(Network::Testnet, root);
+        let hex = add.as_hex();
+        assert_eq!(add.as_bytes().len(), 37);
+        assert_eq!(AddressType::from_hex(&hex), Ok(AddressType::Multisig));
+        let parsed = Address::from_hex(&hex, AddressType::Multisig).unwrap();
+        assert_eq!(parsed, add);
+        assert_eq!(parsed.get_multisig_address().unwrap().root, root);
+        // a multisig address is not a standard address
+        assert!(Address::from_hex(&hex, AddressType::Standard).is_err());
+
+        let mut bytes = add.as_bytes();
+        bytes[5] ^= 1;
+        assert_eq!(
+            Multisig::from_bytes(&bytes),
+            Err(AddressError::InvalidAddress("Invalid Checksum"))
+        );
+    }
+
+    #[test]
+    fn network_config_parsing_test() {
+        // the installed config is process wide, the custom bytes are only checked locally
+        let custom = NetworkConfig::new(100, 101, 102, 103).unwrap();
+
+        let (pk, _) = quisquislib::accounts::Account::generate_random_account_with_value(
+            curve25519_dalek::scalar::Scalar::from(0u64),
+        )
+        .0
+        .get_account();
+        let add = Address::standard_address(Network::Testnet, pk);
+        let mut bytes = add.as_bytes();
+        assert_eq!(
+            bytes[0],
+            network_config().magic_byte(Network::Testnet, &AddressType::Standard)
+        );
+        let parsed = Standard::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.network, Network::Testnet);
+
+        bytes[0] = custom.magic_byte(Network::Testnet, &AddressType::Standard);
+        assert_eq!(bytes[0], 102);
+        assert_eq!(custom.network(bytes[0]), Ok(Network::Testnet));
+        assert!(custom.network(44).is_err());
+        assert_eq!(custom.network(101), Ok(Network::Mainnet));
+        assert_eq!(
+            custom.address_type(103, Network::Testnet),
+            Ok(AddressType::Script)
+        );
+        // the custom bytes are unknown to the default config
+        assert_eq!(network_config(), NetworkConfig::default());
+        assert!(Standard::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn network_name_test() {
+        for network in [Network::Mainnet, Network::Testnet] {
+            assert_eq!(network.to_string().parse::<Network>(), Ok(network));
+        }
+        assert_eq!(" TestNet ".parse::<Network>(), Ok(Network::Testnet));
+        assert!("devnet".parse::<Network>().is_err());
+    }
 }
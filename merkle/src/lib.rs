@@ -483,9 +483,20 @@ impl DoubleEndedIterator for Directions {
 //     }
 //}
 
+/// Version of the compact encoding of a `CallProof`.
+pub const CALL_PROOF_VERSION: u8 = 1;
+
+// depth byte of the compact encoding, the network is kept in its highest bit
+const CALL_PROOF_TESTNET_BIT: u8 = 0x80;
+// a position has a bit per neighbor
+const CALL_PROOF_MAX_DEPTH: usize = 64;
+
 /// Call proof represents a proof that a certain program is committed via the merkle tree into the Script Address.
 /// Used by validator primarily to verify. The program is not the part of the proof.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+///
+/// The binary encoding is the compact one of `to_bytes`, the human readable one
+/// (e.g. JSON) keeps the network and path fields.
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallProof {
     // Network Type. Mainnet, Testnet, etc.
     pub network: Network,
@@ -532,6 +543,134 @@ impl CallProof {
         address_hex == script_address
     }
 }
+/// Compact encoding of a call proof:
+/// ```ascii
+/// version (1) | depth (1) | position bits (depth / 8, rounded up) | neighbors (32 each)
+/// ```
+/// The highest bit of the depth byte is set for Testnet. The position bits are
+/// little endian, the bits above the depth are not encoded.
+impl Encodable for CallProof {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        let depth = self.path.neighbors.len();
+        if depth > CALL_PROOF_MAX_DEPTH {
+            return Err(WriteError::InsufficientCapacity);
+        }
+        let network = match self.network {
+            Network::Mainnet => 0,
+            Network::Testnet => CALL_PROOF_TESTNET_BIT,
+        };
+        w.write_u8(b"version", CALL_PROOF_VERSION)?;
+        w.write_u8(b"depth", depth as u8 | network)?;
+        let position = self.path.position.to_le_bytes();
+        w.write(b"position", &position[..(depth + 7) / 8])?;
+        for hash in self.path.neighbors.iter() {
+            w.write(b"hash", &hash.0)?;
+        }
+        Ok(())
+    }
+}
+
+impl ExactSizeEncodable for CallProof {
+    fn encoded_size(&self) -> usize {
+        CallProof::encoded_size(self)
+    }
+}
+
+impl Decodable for CallProof {
+    fn decode(reader: &mut impl Reader) -> Result<Self, ReadError> {
+        if reader.read_u8()? != CALL_PROOF_VERSION {
+            return Err(ReadError::InvalidFormat);
+        }
+        let depth_byte = reader.read_u8()?;
+        let network = if depth_byte & CALL_PROOF_TESTNET_BIT == 0 {
+            Network::Mainnet
+        } else {
+            Network::Testnet
+        };
+        let depth = (depth_byte & !CALL_PROOF_TESTNET_BIT) as usize;
+        if depth > CALL_PROOF_MAX_DEPTH {
+            return Err(ReadError::InvalidFormat);
+        }
+        let mut position = [0u8; 8];
+        reader.read(&mut position[..(depth + 7) / 8])?;
+        let position = u64::from_le_bytes(position);
+        // a bit above the depth would not change the proof, it is not canonical
+        if depth < CALL_PROOF_MAX_DEPTH && position >> depth != 0 {
+            return Err(ReadError::InvalidFormat);
+        }
+        let neighbors = reader.read_vec(depth, |r| r.read_u8x32().map(Hash))?;
+        Ok(CallProof::new(
+            network,
+            Path {
+                position,
+                neighbors,
+            },
+        ))
+    }
+}
+
+impl CallProof {
+    /// Compact encoding of the proof, see the `Encodable` impl.
+    /// Panics if the path has more than 64 neighbors, no tree has such a path.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    /// Size of the compact encoding, for the fee and size estimates of a tx.
+    pub fn encoded_size(&self) -> usize {
+        let depth = self.path.neighbors.len();
+        2 + (depth + 7) / 8 + 32 * depth
+    }
+
+    /// Decodes a proof from its compact encoding, all of `bytes` has to be read.
+    /// Fails with `InvalidFormat` on an unknown version.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, ReadError> {
+        bytes.read_all(CallProof::decode)
+    }
+}
+
+// field form of a call proof, used by the human readable encodings
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CallProof")]
+struct CallProofFields {
+    network: Network,
+    path: Path,
+}
+
+impl Serialize for CallProof {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if serializer.is_human_readable() {
+            CallProofFields {
+                network: self.network,
+                path: self.path.clone(),
+            }
+            .serialize(serializer)
+        } else {
+            let mut bytes = Vec::with_capacity(self.encoded_size());
+            self.encode(&mut bytes).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CallProof {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let fields = CallProofFields::deserialize(deserializer)?;
+            Ok(CallProof::new(fields.network, fields.path))
+        } else {
+            let bytes = <Vec<u8>>::deserialize(deserializer)?;
+            CallProof::from_bytes(&bytes).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
 /// Default implementation for CallProof
 /// EMPTY CallProof
 impl Default for CallProof {
@@ -615,6 +754,63 @@ mod tests {
             assert_proof_err!(num, idx, wrong_idx);
         }
     }
+    #[test]
+    fn call_proof_encoding_test() {
+        let hasher = Hasher::new(b"test");
+        let items = test_items(8);
+        let proof = CallProof::create_call_proof(&items, 5, &hasher, Network::Testnet).unwrap();
+        assert_eq!(proof.path.neighbors.len(), 3);
+        // version, depth, one byte of position bits and three neighbors
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes.len(), 1 + 1 + 1 + 3 * 32);
+        assert_eq!(proof.encoded_size(), bytes.len());
+        assert_eq!(&bytes[..3], &[CALL_PROOF_VERSION, 0x83, 0b101]);
+        assert_eq!(CallProof::from_bytes(&bytes).unwrap(), proof);
+
+        for (num, idx) in [(1, 0), (2, 1), (13, 7), (25, 9)].iter() {
+            let items = test_items(*num);
+            let proof =
+                CallProof::create_call_proof(&items, *idx, &hasher, Network::Mainnet).unwrap();
+            let decoded = CallProof::from_bytes(&proof.to_bytes()).unwrap();
+            assert_eq!(decoded, proof);
+            assert!(decoded.path.verify_root(
+                &MerkleTree::root(b"test", items.clone()),
+                &items[*idx],
+                &hasher
+            ));
+        }
+        assert_eq!(CallProof::default().to_bytes(), vec![CALL_PROOF_VERSION, 0]);
+    }
+
+    #[test]
+    fn call_proof_decoding_test() {
+        let hasher = Hasher::new(b"test");
+        let proof =
+            CallProof::create_call_proof(&test_items(8), 5, &hasher, Network::Mainnet).unwrap();
+        let bytes = proof.to_bytes();
+
+        let mut unknown = bytes.clone();
+        unknown[0] = CALL_PROOF_VERSION + 1;
+        assert!(matches!(
+            CallProof::from_bytes(&unknown),
+            Err(ReadError::InvalidFormat)
+        ));
+        // a position bit above the depth
+        let mut high_bit = bytes.clone();
+        high_bit[2] |= 0b1000;
+        assert!(matches!(
+            CallProof::from_bytes(&high_bit),
+            Err(ReadError::InvalidFormat)
+        ));
+        assert!(CallProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(
+            CallProof::from_bytes(&trailing),
+            Err(ReadError::TrailingBytes)
+        ));
+    }
+
     #[test]
     fn tree_build_test() {
         let num = 13;
@@ -17,6 +17,11 @@ impl BitRange {
     pub fn max() -> Self {
         BitRange(64)
     }
+
+    /// Returns the smallest range [0, 2^n) containing `upper_bound`.
+    pub fn for_bound(upper_bound: u64) -> Self {
+        BitRange((64 - upper_bound.leading_zeros()) as usize)
+    }
 }
 
 impl Into<usize> for BitRange {
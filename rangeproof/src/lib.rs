@@ -11,7 +11,7 @@ mod signed_integer;
 mod value;
 
 pub use crate::bit_range::BitRange;
-pub use crate::range_proof::range_proof;
+pub use crate::range_proof::{range_proof, range_proof_upto};
 pub use crate::signed_integer::SignedInteger;
 pub use crate::value::{AllocatedValue, CommittedValue, Value};
 
@@ -42,6 +42,25 @@ pub fn range_proof<CS: ConstraintSystem>(
     Ok(())
 }
 
+/// Enforces that the quantity of v is in the range [0, upper_bound].
+///
+/// Proves that both v and (upper_bound - v) are in [0, 2^n) for the smallest n
+/// with upper_bound < 2^n. For v > upper_bound the difference wraps around to a
+/// field element far above 2^n, so the second range proof cannot hold.
+pub fn range_proof_upto<CS: ConstraintSystem>(
+    cs: &mut CS,
+    v: LinearCombination,
+    v_assignment: Option<SignedInteger>,
+    upper_bound: u64,
+) -> Result<(), R1CSError> {
+    let n = BitRange::for_bound(upper_bound);
+    let gap = LinearCombination::from(Scalar::from(upper_bound)) - v.clone();
+    let gap_assignment = v_assignment.and_then(|q| SignedInteger::from(upper_bound) + (-q));
+
+    range_proof(cs, v, v_assignment, n)?;
+    range_proof(cs, gap, gap_assignment, n)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,6 +86,78 @@ mod tests {
         }
     }
 
+    #[test]
+    fn bit_range_for_bound() {
+        let widths: [(u64, usize); 7] = [
+            (0, 0),
+            (1, 1),
+            (2, 2),
+            (255, 8),
+            (256, 9),
+            (10_000_000, 24),
+            (u64::max_value(), 64),
+        ];
+        for (bound, n) in widths.iter() {
+            let width: usize = BitRange::for_bound(*bound).into();
+            assert_eq!(width, *n);
+        }
+    }
+
+    #[test]
+    fn range_proof_upto_gadget() {
+        for bound in [0u64, 1, 7, 10_000_000, u64::max_value() - 1].iter() {
+            assert!(range_proof_upto_helper(0, *bound).is_ok());
+            assert!(range_proof_upto_helper(*bound / 2, *bound).is_ok());
+            assert!(range_proof_upto_helper(*bound, *bound).is_ok());
+            assert!(range_proof_upto_helper(*bound + 1, *bound).is_err());
+        }
+    }
+
+    #[test]
+    fn range_proof_upto_forged_bits() {
+        // bound + 1 committed by a prover that fills in the bits of bound
+        let bound = 10_000_000u64;
+        assert!(range_proof_upto_forged_helper(bound + 1, bound, bound).is_err());
+        assert!(range_proof_upto_forged_helper(bound, bound, bound).is_ok());
+    }
+
+    fn range_proof_upto_helper(v_val: u64, bound: u64) -> Result<(), R1CSError> {
+        range_proof_upto_forged_helper(v_val, v_val, bound)
+    }
+
+    // commits to `v_val` but assigns the bits of `v_bits`
+    fn range_proof_upto_forged_helper(
+        v_val: u64,
+        v_bits: u64,
+        bound: u64,
+    ) -> Result<(), R1CSError> {
+        let pc_gens = PedersenGens::default();
+        let bp_gens = BulletproofGens::new(256, 1);
+
+        let (proof, commitment) = {
+            let mut prover_transcript = Transcript::new(b"RangeProofUptoTest");
+            let mut rng = rand::thread_rng();
+
+            let mut prover = Prover::new(&pc_gens, &mut prover_transcript);
+
+            let (com, var) = prover.commit(v_val.into(), Scalar::random(&mut rng));
+            range_proof_upto(&mut prover, var.into(), Some(v_bits.into()), bound)?;
+
+            let proof = prover.prove(&bp_gens)?;
+
+            (proof, com)
+        };
+
+        let mut verifier_transcript = Transcript::new(b"RangeProofUptoTest");
+        let mut verifier = Verifier::new(&mut verifier_transcript);
+
+        let var = verifier.commit(commitment);
+
+        range_proof_upto(&mut verifier, var.into(), None, bound)?;
+
+        Ok(verifier.verify(&proof, &pc_gens, &bp_gens)?)
+    }
+
     fn range_proof_helper(v_val: SignedInteger, n: usize) -> Result<(), R1CSError> {
         // Common
         let pc_gens = PedersenGens::default();
@@ -0,0 +1,84 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+use address::{Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::keys::{PublicKey, SecretKey};
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use transaction::programs::{
+    create_program_call_proof, create_swap_deposit_memo, create_swap_states, get_swap_program,
+    program_tree_script_address, swap_output_amount, SWAP_PROGRAM_INDEX,
+};
+use transaction::vm_run::ProverOpts;
+use transaction::ScriptTransaction;
+use zkvm::zkos_types::{Input, InputData, Output, OutputCoin, Utxo};
+
+// inputs and outputs of a swap against the relayer pool, both owned by the returned key
+fn swap_io() -> (RistrettoSecretKey, Vec<Input>, Vec<Output>) {
+    let mut rng = rand::thread_rng();
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    let owner = Address::standard_address(Network::default(), pk).as_hex();
+    let script_address = program_tree_script_address(Network::default());
+    let (reserve_a, reserve_b, in_a) = (1_000_000u64, 500_000u64, 2_500u64);
+    let out_b = swap_output_amount(reserve_a, reserve_b, in_a).unwrap();
+
+    let blinding = Scalar::random(&mut rng);
+    let encrypt = ElGamalCommitment::generate_commitment(&pk, blinding, Scalar::from(in_a));
+    let coin = Input::coin(InputData::coin(
+        Utxo::default(),
+        OutputCoin::new(encrypt, owner.clone()),
+        0,
+    ));
+    let memo =
+        create_swap_deposit_memo(script_address.clone(), owner.clone(), in_a, blinding, out_b);
+    let (in_state, out_state) = create_swap_states(
+        Utxo::default(),
+        script_address,
+        owner,
+        1,
+        (reserve_a, reserve_b),
+        in_a,
+        out_b,
+    )
+    .unwrap();
+    (sk, vec![coin, in_state], vec![memo, out_state])
+}
+
+fn bench_swap_tx(c: &mut Criterion, name: &str, opts: ProverOpts) {
+    let (sk, inputs, outputs) = swap_io();
+    let call_proof = create_program_call_proof(SWAP_PROGRAM_INDEX, Network::default()).unwrap();
+    c.bench_function(name, move |b| {
+        b.iter(|| {
+            ScriptTransaction::create_script_transaction_bundle_with_opts(
+                &[sk.clone(), sk.clone()],
+                vec![(get_swap_program(), call_proof.clone())],
+                &inputs,
+                &outputs,
+                None,
+                false,
+                1,
+                opts,
+            )
+            .unwrap()
+        })
+    });
+}
+
+fn swap_tx_sequential(c: &mut Criterion) {
+    bench_swap_tx(c, "swap script tx: sequential", ProverOpts::default());
+}
+
+// run with `cargo bench --features parallel`, otherwise the same as sequential
+fn swap_tx_parallel(c: &mut Criterion) {
+    bench_swap_tx(c, "swap script tx: parallel", ProverOpts::parallel());
+}
+
+criterion_group! {
+    name = prover;
+    config = Criterion::default().sample_size(10);
+    targets = swap_tx_sequential, swap_tx_parallel
+}
+criterion_main!(prover);
@@ -0,0 +1,115 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+use address::{Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::keys::{PublicKey, SecretKey};
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use transaction::ScriptTransaction;
+use zkvm::merkle::{CallProof, Hasher, MerkleTree};
+use zkvm::proof_context::{ProofContext, DEFAULT_GENS_CAPACITY};
+use zkvm::zkos_types::{Input, InputData, Output, OutputCoin, OutputData, OutputMemo, Utxo};
+use zkvm::{Commitment, Program};
+
+const INPUT_COUNT: usize = 20;
+
+// program dropping the memo commitment of each coin input
+fn drop_program() -> Program {
+    Program::build(|p| {
+        for _ in 0..INPUT_COUNT {
+            p.drop();
+        }
+    })
+}
+
+// coin inputs moved into memos of the same value, all owned by the returned key
+fn coin_to_memo_io(script_address: &str) -> (RistrettoSecretKey, Vec<Input>, Vec<Output>) {
+    let mut rng = rand::thread_rng();
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    let owner = Address::standard_address(Network::default(), pk).as_hex();
+    let mut inputs = Vec::with_capacity(INPUT_COUNT);
+    let mut outputs = Vec::with_capacity(INPUT_COUNT);
+    for i in 0..INPUT_COUNT {
+        let value = 100 + i as u64;
+        let blinding = Scalar::random(&mut rng);
+        let encrypt = ElGamalCommitment::generate_commitment(&pk, blinding, Scalar::from(value));
+        inputs.push(Input::coin(InputData::coin(
+            Utxo::random(),
+            OutputCoin::new(encrypt, owner.clone()),
+            0,
+        )));
+        outputs.push(Output::memo(OutputData::memo(OutputMemo::new(
+            script_address.to_string(),
+            owner.clone(),
+            Commitment::blinded_with_factor(value, blinding),
+            None,
+            0,
+        ))));
+    }
+    (sk, inputs, outputs)
+}
+
+fn script_tx_20_inputs() -> ScriptTransaction {
+    let programs = vec![drop_program()];
+    let hasher = Hasher::new(b"ZkOS.MerkelTree");
+    let root = MerkleTree::root(b"ZkOS.MerkelTree", programs.iter());
+    let script_address = Address::script_address(Network::default(), root.0).as_hex();
+    let call_proof =
+        CallProof::create_call_proof(&programs, 0, &hasher, Network::default()).unwrap();
+    let (sk, inputs, outputs) = coin_to_memo_io(&script_address);
+    ScriptTransaction::create_script_transaction(
+        &vec![sk; INPUT_COUNT],
+        programs[0].clone(),
+        call_proof,
+        &inputs,
+        &outputs,
+        None,
+        false,
+        1,
+    )
+    .unwrap()
+}
+
+fn script_tx_verify(c: &mut Criterion) {
+    let tx = script_tx_20_inputs();
+    assert!(tx.verify().is_ok());
+    c.bench_function("script tx verify: 20 inputs", move |b| {
+        b.iter(|| tx.verify().unwrap())
+    });
+}
+
+// generators shared by the calls against generators built for each call, as before
+fn script_tx_proof_context(c: &mut Criterion) {
+    let tx = script_tx_20_inputs();
+    let shared = tx.clone();
+    c.bench_function("script tx r1cs verify: shared generators", move |b| {
+        b.iter(|| {
+            shared
+                .verify_r1cs_proof_in(ProofContext::global(), false)
+                .unwrap()
+        })
+    });
+    c.bench_function("script tx r1cs verify: generators per call", move |b| {
+        b.iter(|| {
+            let context = ProofContext::new(DEFAULT_GENS_CAPACITY);
+            tx.verify_r1cs_proof_in(&context, false).unwrap()
+        })
+    });
+}
+
+// the witnesses reuse the commitment points computed for the verifier view
+fn script_tx_create(c: &mut Criterion) {
+    c.bench_function("script tx create: 20 inputs", |b| {
+        b.iter(script_tx_20_inputs)
+    });
+}
+
+criterion_group! {
+    name = verifier;
+    config = Criterion::default().sample_size(10);
+    targets = script_tx_verify, script_tx_create, script_tx_proof_context
+}
+criterion_main!(verifier);
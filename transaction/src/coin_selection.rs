@@ -0,0 +1,387 @@
+#![allow(non_snake_case)]
+//! Coin selection for dark transfers.
+//!
+//! Picks the coin UTXOs of a wallet that fund a transfer of a target amount plus fee.
+//! Values are the decrypted balances of the coins, so selection runs on the wallet side
+//! or on a server holding the view key.
+
+use crate::reference_tx::Receiver;
+use crate::TxError;
+use quisquislib::accounts::Account;
+use zkvm::zkos_types::{Input, InputData, OutputCoin, Utxo};
+
+/// Maximum number of branches explored by the branch-and-bound search.
+pub const BNB_MAX_TRIES: usize = 100_000;
+
+/// A coin UTXO together with its decrypted value.
+#[derive(Debug, Clone)]
+pub struct SpendableCoin {
+    pub utxo: Utxo,
+    pub coin: OutputCoin,
+    pub value: u64,
+}
+
+impl SpendableCoin {
+    pub fn new(utxo: Utxo, coin: OutputCoin, value: u64) -> Self {
+        SpendableCoin { utxo, coin, value }
+    }
+}
+
+/// Strategy used to pick the inputs of a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Spends the largest coins first until the target is covered.
+    LargestFirst,
+    /// Searches for a set of coins matching the target exactly, leaving no change.
+    /// Falls back to largest-first if no exact match is found.
+    BranchAndBound,
+}
+
+/// Inputs chosen to fund a transfer.
+#[derive(Debug, Clone)]
+pub struct CoinSelection {
+    pub inputs: Vec<SpendableCoin>,
+    /// Amount paid to the receivers.
+    pub target: u64,
+    pub fee: u64,
+    /// Value left in the inputs after paying the target and the fee.
+    pub change: u64,
+}
+
+/// Vectors needed by `TransferTransaction::create_private_transfer_transaction`
+/// for a dark transfer funded by a coin selection.
+#[derive(Debug, Clone)]
+pub struct SelectedTransfer {
+    pub value_vector: Vec<i64>,
+    pub account_vector: Vec<Account>,
+    pub sender_updated_balance: Vec<u64>,
+    pub reciever_value_balance: Vec<u64>,
+    pub inputs: Vec<Input>,
+    pub senders_count: usize,
+    pub receivers_count: usize,
+}
+
+/// Selects coins covering `target + fee` using at most `max_inputs` inputs.
+/// Returns `InsufficientFunds` if all coins together do not cover the amount and
+/// `InputLimitExceeded` if they do, but not within `max_inputs` coins.
+pub fn select_coins(
+    coins: &[SpendableCoin],
+    target: u64,
+    fee: u64,
+    max_inputs: usize,
+    strategy: SelectionStrategy,
+) -> Result<CoinSelection, TxError> {
+    let required = target.checked_add(fee).ok_or(TxError::InvalidTx)?;
+    let available = coins
+        .iter()
+        .fold(0u64, |sum, coin| sum.saturating_add(coin.value));
+    if available < required {
+        return Err(TxError::InsufficientFunds {
+            required,
+            available,
+        });
+    }
+
+    // largest coins first, zero value coins can never help
+    let mut sorted: Vec<&SpendableCoin> = coins.iter().filter(|coin| coin.value > 0).collect();
+    sorted.sort_by(|a, b| b.value.cmp(&a.value));
+
+    let chosen = match strategy {
+        SelectionStrategy::LargestFirst => largest_first(&sorted, required, max_inputs)?,
+        SelectionStrategy::BranchAndBound => {
+            match branch_and_bound(&sorted, required, max_inputs) {
+                Some(chosen) => chosen,
+                None => largest_first(&sorted, required, max_inputs)?,
+            }
+        }
+    };
+    let total: u64 = chosen.iter().map(|coin| coin.value).sum();
+    Ok(CoinSelection {
+        inputs: chosen.into_iter().cloned().collect(),
+        target,
+        fee,
+        change: total - required,
+    })
+}
+
+fn largest_first<'a>(
+    sorted: &[&'a SpendableCoin],
+    required: u64,
+    max_inputs: usize,
+) -> Result<Vec<&'a SpendableCoin>, TxError> {
+    let mut chosen = Vec::new();
+    let mut total: u64 = 0;
+    for coin in sorted.iter() {
+        if total >= required {
+            break;
+        }
+        chosen.push(*coin);
+        total += coin.value;
+    }
+    if chosen.len() > max_inputs {
+        return Err(TxError::InputLimitExceeded { max_inputs });
+    }
+    Ok(chosen)
+}
+
+/// Depth first search over include/exclude decisions on the coins sorted by value.
+fn branch_and_bound<'a>(
+    sorted: &[&'a SpendableCoin],
+    required: u64,
+    max_inputs: usize,
+) -> Option<Vec<&'a SpendableCoin>> {
+    // remaining[i] is the value of all coins from index i onwards
+    let mut remaining = vec![0u64; sorted.len() + 1];
+    for i in (0..sorted.len()).rev() {
+        remaining[i] = remaining[i + 1].saturating_add(sorted[i].value);
+    }
+
+    let mut selected: Vec<usize> = Vec::new();
+    let mut tries = 0;
+    if search(
+        sorted,
+        &remaining,
+        required,
+        max_inputs,
+        0,
+        0,
+        &mut selected,
+        &mut tries,
+    ) {
+        Some(selected.iter().map(|i| sorted[*i]).collect())
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn search(
+    sorted: &[&SpendableCoin],
+    remaining: &[u64],
+    required: u64,
+    max_inputs: usize,
+    index: usize,
+    total: u64,
+    selected: &mut Vec<usize>,
+    tries: &mut usize,
+) -> bool {
+    if total == required {
+        return true;
+    }
+    *tries += 1;
+    if *tries > BNB_MAX_TRIES
+        || index == sorted.len()
+        || selected.len() == max_inputs
+        || total.saturating_add(remaining[index]) < required
+    {
+        return false;
+    }
+    let value = sorted[index].value;
+    if total + value <= required {
+        selected.push(index);
+        if search(
+            sorted,
+            remaining,
+            required,
+            max_inputs,
+            index + 1,
+            total + value,
+            selected,
+            tries,
+        ) {
+            return true;
+        }
+        selected.pop();
+    }
+    search(
+        sorted,
+        remaining,
+        required,
+        max_inputs,
+        index + 1,
+        total,
+        selected,
+        tries,
+    )
+}
+
+impl CoinSelection {
+    /// Builds the dark transfer vectors paying `receivers` from the selected inputs.
+    /// Each input is a sender and is debited in selection order until the receivers and
+    /// the fee are paid. The receiver amounts must add up to the selection target.
+    pub fn to_transfer(&self, receivers: &[Receiver]) -> Result<SelectedTransfer, TxError> {
+        let mut sent: u64 = 0;
+        for rec in receivers.iter() {
+            if rec.amount < 0 {
+                return Err(TxError::InvalidTx);
+            }
+            sent = sent.saturating_add(rec.amount as u64);
+        }
+        if sent != self.target {
+            return Err(TxError::InvalidTx);
+        }
+        let senders_count = self.inputs.len();
+        let receivers_count = receivers.len();
+        if senders_count >= 9 || receivers_count >= 9 || senders_count + receivers_count > 9 {
+            return Err(TxError::AccountCountExceeded);
+        }
+
+        let mut value_vector: Vec<i64> = Vec::new();
+        let mut account_vector: Vec<Account> = Vec::new();
+        let mut sender_updated_balance: Vec<u64> = Vec::new();
+        let mut inputs: Vec<Input> = Vec::new();
+        let mut to_send = sent + self.fee;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let owner =
+                address::Address::from_hex(&input.coin.owner, address::AddressType::Standard)
+                    .map_err(|_| TxError::InvalidOwnerAddress { index })?;
+            let pk = owner
+                .get_standard_address()
+                .map_err(|_| TxError::InvalidOwnerAddress { index })?
+                .public_key;
+            let debit = to_send.min(input.value);
+            to_send -= debit;
+
+            value_vector.push(-(debit as i64));
+            account_vector.push(Account::set_account(pk, input.coin.encrypt.clone()));
+            sender_updated_balance.push(input.value - debit);
+            inputs.push(Input::coin(InputData::coin(
+                input.utxo,
+                input.coin.clone(),
+                index as u8,
+            )));
+        }
+        for rec in receivers.iter() {
+            value_vector.push(rec.amount);
+            account_vector.push(rec.acc);
+        }
+        Ok(SelectedTransfer {
+            value_vector,
+            account_vector,
+            sender_updated_balance,
+            reciever_value_balance: receivers.iter().map(|rec| rec.amount as u64).collect(),
+            inputs,
+            senders_count,
+            receivers_count,
+        })
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use address::{Address, Network};
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::elgamal::ElGamalCommitment;
+    use quisquislib::keys::{PublicKey, SecretKey};
+    use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+    use zkvm::Hash;
+
+    fn coins(values: &[u64]) -> Vec<SpendableCoin> {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let owner = Address::standard_address(Network::default(), pk).as_hex();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let utxo = Utxo::from_hash(Hash([i as u8; 32]), 0);
+                let encrypt = ElGamalCommitment::generate_commitment(
+                    &pk,
+                    Scalar::random(&mut rng),
+                    Scalar::from(*value),
+                );
+                SpendableCoin::new(utxo, OutputCoin::new(encrypt, owner.clone()), *value)
+            })
+            .collect()
+    }
+
+    fn values(selection: &CoinSelection) -> Vec<u64> {
+        selection.inputs.iter().map(|coin| coin.value).collect()
+    }
+
+    #[test]
+    fn largest_first_with_dust_test() {
+        let wallet = coins(&[1, 1, 2, 50, 1, 30]);
+        let selection = select_coins(&wallet, 60, 1, 4, SelectionStrategy::LargestFirst).unwrap();
+        assert_eq!(values(&selection), vec![50, 30]);
+        assert_eq!(selection.change, 19);
+    }
+
+    #[test]
+    fn branch_and_bound_exact_match_test() {
+        let wallet = coins(&[1, 1, 2, 50, 1, 30, 9]);
+        let selection = select_coins(&wallet, 58, 1, 4, SelectionStrategy::BranchAndBound).unwrap();
+        let mut chosen = values(&selection);
+        chosen.sort();
+        assert_eq!(chosen, vec![9, 50]);
+        assert_eq!(selection.change, 0);
+    }
+
+    #[test]
+    fn branch_and_bound_fallback_test() {
+        let wallet = coins(&[10, 20, 40]);
+        let selection = select_coins(&wallet, 44, 1, 4, SelectionStrategy::BranchAndBound).unwrap();
+        assert_eq!(values(&selection), vec![40, 20]);
+        assert_eq!(selection.change, 15);
+    }
+
+    #[test]
+    fn insufficient_funds_test() {
+        let wallet = coins(&[1, 2, 3]);
+        for strategy in [
+            SelectionStrategy::LargestFirst,
+            SelectionStrategy::BranchAndBound,
+        ] {
+            let err = select_coins(&wallet, 6, 1, 4, strategy).unwrap_err();
+            assert_eq!(
+                err,
+                TxError::InsufficientFunds {
+                    required: 7,
+                    available: 6
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn input_limit_test() {
+        let wallet = coins(&[1; 10]);
+        let err = select_coins(&wallet, 5, 0, 4, SelectionStrategy::LargestFirst).unwrap_err();
+        assert_eq!(err, TxError::InputLimitExceeded { max_inputs: 4 });
+        let err = select_coins(&wallet, 5, 0, 4, SelectionStrategy::BranchAndBound).unwrap_err();
+        assert_eq!(err, TxError::InputLimitExceeded { max_inputs: 4 });
+    }
+
+    #[test]
+    fn selection_to_transfer_test() {
+        let wallet = coins(&[50, 30, 1]);
+        let selection = select_coins(&wallet, 60, 0, 4, SelectionStrategy::LargestFirst).unwrap();
+        let (receiver, _) = Account::generate_account(RistrettoPublicKey::generate_base_pk());
+        let transfer = selection
+            .to_transfer(&[Receiver::set_receiver(60, receiver)])
+            .unwrap();
+        assert_eq!(transfer.value_vector, vec![-50, -10, 60]);
+        assert_eq!(transfer.sender_updated_balance, vec![0, 20]);
+        assert_eq!(transfer.reciever_value_balance, vec![60]);
+        assert_eq!(transfer.inputs.len(), 2);
+        assert_eq!((transfer.senders_count, transfer.receivers_count), (2, 1));
+
+        let err = selection
+            .to_transfer(&[Receiver::set_receiver(59, receiver)])
+            .unwrap_err();
+        assert_eq!(err, TxError::InvalidTx);
+
+        // the fee is debited after the receivers are paid
+        let selection = select_coins(&wallet, 60, 5, 4, SelectionStrategy::LargestFirst).unwrap();
+        let transfer = selection
+            .to_transfer(&[Receiver::set_receiver(60, receiver)])
+            .unwrap();
+        assert_eq!(transfer.value_vector, vec![-50, -15, 60]);
+        assert_eq!(transfer.sender_updated_balance, vec![0, 15]);
+    }
+}
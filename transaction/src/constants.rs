@@ -26,10 +26,14 @@ pub const GAS_PER_BYTE: u64 = 10;
 pub const CONTRACT_MAX_SIZE: u64 = 16 * 1024 * 1024;
 
 /// Maximum number of inputs.
-pub const MAX_INPUTS: u8 = 255;
+pub const MAX_INPUTS: u16 = u16::MAX;
 
-/// Maximum number of outputs.
-pub const MAX_OUTPUTS: u8 = 255;
+/// Maximum number of outputs. The output indexes stay below `Utxo::PENDING_FLAG`, the
+/// bit of the index marking a reference to an output of the same tx.
+pub const MAX_OUTPUTS: u16 = zkvm::zkos_types::Utxo::PENDING_FLAG - 1;
+
+/// Maximum number of programs executed by a script transaction.
+pub const MAX_PROGRAMS: u8 = 16;
 
 /// Maximum number of witnesses.
 pub const MAX_WITNESSES: u8 = 255;
@@ -37,6 +41,16 @@ pub const MAX_WITNESSES: u8 = 255;
 /// Maximum gas per transaction.
 pub const MAX_GAS_PER_TX: u64 = 1000000;
 
+/// Bounds of the data of memo outputs and the state variables of state outputs.
+/// The default limits are `MAX_OUTPUT_DATA_ELEMENTS` elements of at most
+/// `MAX_OUTPUT_DATA_ELEMENT_SIZE` bytes, and `MAX_OUTPUT_DATA_SIZE` bytes per output.
+/// `Output::validate` applies them in txCommit and block processing.
+pub use zkvm::zkos_types::OutputDataLimits;
+
+/// Default minimum value of a receiver output in a transfer.
+/// Outputs below this value are rejected as dust.
+pub const MIN_OUTPUT_VALUE: u64 = 1;
+
 // TODO set max script length const
 /// Maximum length of script, in instructions.
 pub const MAX_SCRIPT_LENGTH: u64 = 1024 * 1024;
@@ -0,0 +1,404 @@
+#![allow(non_snake_case)]
+//! Decoding of serialized transactions for inspection.
+//!
+//! Transactions are decoded field by field so that malformed bytes report the
+//! field and byte offset where decoding failed. Nothing is verified here.
+
+use crate::{
+    Message, ScriptTransaction, Transaction, TransactionData, TransactionType, TransactionVersion,
+    TransferTransaction,
+};
+use core::fmt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use zkvm::zkos_types::{Input, Output, OutputData, Witness};
+use zkvm::{IOType, Program, String as ZkvmString};
+
+/// Error returned when a serialized transaction can not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodeError {
+    /// Field that failed to decode, e.g. `script.inputs[1]`.
+    pub field: String,
+    /// Byte offset where the field starts.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "failed to decode `{}` at byte offset {}: {}",
+            self.field, self.offset, self.message
+        )
+    }
+}
+
+// reads bincode values one field at a time, keeping track of the offset
+struct FieldReader<'a> {
+    len: usize,
+    cursor: &'a [u8],
+}
+
+impl<'a> FieldReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        FieldReader {
+            len: bytes.len(),
+            cursor: bytes,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.len - self.cursor.len()
+    }
+
+    fn error(&self, field: &str, offset: usize, message: String) -> DecodeError {
+        DecodeError {
+            field: field.to_string(),
+            offset,
+            message,
+        }
+    }
+
+    fn read<T: DeserializeOwned>(&mut self, field: &str) -> Result<T, DecodeError> {
+        let offset = self.offset();
+        bincode::deserialize_from(&mut self.cursor)
+            .map_err(|err| self.error(field, offset, err.to_string()))
+    }
+
+    fn read_vec<T: DeserializeOwned>(&mut self, field: &str) -> Result<Vec<T>, DecodeError> {
+        let len: u64 = self.read(field)?;
+        // the length is untrusted, do not preallocate
+        let mut items = Vec::new();
+        for i in 0..len {
+            items.push(self.read(&format!("{}[{}]", field, i))?);
+        }
+        Ok(items)
+    }
+}
+
+/// Decodes a bincode serialized transaction.
+/// Unlike `bincode::deserialize`, the error names the field and offset that failed.
+pub fn decode_transaction(bytes: &[u8]) -> Result<Transaction, DecodeError> {
+    let mut r = FieldReader::new(bytes);
+    let version: u16 = r.read("version")?;
+    TransactionVersion::from_u16(version).map_err(|err| r.error("version", 0, err.to_string()))?;
+    let tx_type: TransactionType = r.read("tx_type")?;
+    let offset = r.offset();
+    let variant: u32 = r.read("tx")?;
+    let tx = match variant {
+        0 => TransactionData::TransactionTransfer(TransferTransaction {
+            version: r.read("transfer.version")?,
+            maturity: r.read("transfer.maturity")?,
+            fee: r.read("transfer.fee")?,
+            input_count: r.read("transfer.input_count")?,
+            output_count: r.read("transfer.output_count")?,
+            witness_count: r.read("transfer.witness_count")?,
+            inputs: r.read_vec("transfer.inputs")?,
+            outputs: r.read_vec("transfer.outputs")?,
+            proof: r.read("transfer.proof")?,
+            shuffle_proof: r.read("transfer.shuffle_proof")?,
+            witness: r.read("transfer.witness")?,
+        }),
+        1 => TransactionData::TransactionScript(ScriptTransaction {
+            version: r.read("script.version")?,
+            fee: r.read("script.fee")?,
+            maturity: r.read("script.maturity")?,
+            input_count: r.read("script.input_count")?,
+            output_count: r.read("script.output_count")?,
+            witness_count: r.read("script.witness_count")?,
+            inputs: r.read_vec("script.inputs")?,
+            outputs: r.read_vec("script.outputs")?,
+            program: r.read("script.program")?,
+            call_proof: r.read("script.call_proof")?,
+            proof: r.read("script.proof")?,
+            witness: r.read_vec("script.witness")?,
+            tx_data: r.read("script.tx_data")?,
+            program_bundle: r.read_vec("script.program_bundle")?,
+            data_signature: r.read("script.data_signature")?,
+        }),
+        2 => TransactionData::Message(Message {
+            msg_type: r.read("message.msg_type")?,
+            version: r.read("message.version")?,
+            fee: r.read("message.fee")?,
+            input: r.read("message.input")?,
+            msg_data: r.read("message.msg_data")?,
+            proof: r.read("message.proof")?,
+            signature: r.read("message.signature")?,
+        }),
+        _ => {
+            return Err(r.error(
+                "tx",
+                offset,
+                format!("unknown transaction data variant {}", variant),
+            ))
+        }
+    };
+    // the counts of the header have to match the decoded vectors
+    match tx {
+        TransactionData::TransactionTransfer(ref transfer) => transfer.verify_header(),
+        TransactionData::TransactionScript(ref script) => script.verify_header(),
+        TransactionData::Message(_) => Ok(()),
+    }
+    .map_err(|err| r.error("tx.header", offset, err.to_string()))?;
+    Ok(Transaction::new(tx_type, tx))
+}
+
+/// Input of a decoded transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputView {
+    pub in_type: IOType,
+    pub utxo: String,
+    pub owner: Option<String>,
+    pub witness_index: u8,
+}
+
+/// Output of a decoded transaction. Commitments are hex encoded compressed points.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputView {
+    pub out_type: IOType,
+    pub owner: Option<String>,
+    pub script_address: Option<String>,
+    /// Name of the registered contract of the script address.
+    #[serde(default)]
+    pub script_contract: Option<String>,
+    pub commitments: Vec<String>,
+}
+
+/// Structured view of a transaction for inspection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub tx_type: TransactionType,
+    pub fee: u64,
+    pub inputs: Vec<InputView>,
+    pub outputs: Vec<OutputView>,
+    pub witnesses: Vec<String>,
+    /// Disassembled program of a script transaction.
+    /// Only the first program is shown if the transaction carries a program bundle.
+    pub program: Option<Vec<String>>,
+    /// Number of programs executed by a script transaction.
+    pub program_count: Option<usize>,
+    /// Set if the program of a script transaction can not be parsed.
+    pub program_error: Option<String>,
+    /// Merkle path length of the call proof of a script transaction.
+    pub call_proof_depth: Option<usize>,
+}
+
+impl From<&Input> for InputView {
+    fn from(input: &Input) -> Self {
+        InputView {
+            in_type: input.in_type,
+            utxo: input.get_utxo().to_string(),
+            owner: input.as_owner_address().cloned(),
+            witness_index: input.get_witness_index(),
+        }
+    }
+}
+
+impl From<&Output> for OutputView {
+    fn from(output: &Output) -> Self {
+        let data_commitments = |data: Option<&Vec<ZkvmString>>| -> Vec<String> {
+            data.map(|data| {
+                data.iter()
+                    .filter_map(|item| match item {
+                        ZkvmString::Commitment(commitment) => {
+                            Some(hex::encode(commitment.to_point().as_bytes()))
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+        };
+        let commitments = match output.output {
+            OutputData::Coin(ref coin) => vec![hex::encode(coin.encrypt.to_bytes())],
+            OutputData::Memo(ref memo) => {
+                let mut commitments = vec![hex::encode(memo.commitment.to_point().as_bytes())];
+                commitments.extend(data_commitments(memo.data.as_ref()));
+                commitments
+            }
+            OutputData::State(ref state) => {
+                let mut commitments = vec![hex::encode(state.commitment.to_point().as_bytes())];
+                commitments.extend(data_commitments(state.state_variables.as_ref()));
+                commitments
+            }
+        };
+        OutputView {
+            out_type: output.out_type,
+            owner: output.output.get_owner_address().cloned(),
+            script_address: output.output.get_script_address().cloned(),
+            script_contract: None,
+            commitments,
+        }
+    }
+}
+
+fn witness_kind(witness: &Witness) -> String {
+    match witness {
+        Witness::Signature(_) => "Signature",
+        Witness::Proof(_) => "Proof",
+        Witness::ValueWitness(_) => "ValueWitness",
+        Witness::State(_) => "State",
+        Witness::Multisig(_) => "Multisig",
+        Witness::ZeroBalanceProof(_) => "ZeroBalanceProof",
+    }
+    .to_string()
+}
+
+impl From<&Transaction> for TransactionView {
+    fn from(tx: &Transaction) -> Self {
+        let (witnesses, program, program_count, program_error, call_proof_depth) = match tx.tx {
+            TransactionData::TransactionTransfer(ref transfer) => {
+                let witnesses = match transfer.witness {
+                    Some(ref witness) => witness.iter().map(witness_kind).collect(),
+                    None => Vec::new(),
+                };
+                (witnesses, None, None, None, None)
+            }
+            TransactionData::TransactionScript(ref script) => {
+                let (program, program_error) = match Program::parse(&script.program) {
+                    Ok(program) => (
+                        Some(program.iter().map(|instr| format!("{:?}", instr)).collect()),
+                        None,
+                    ),
+                    Err(err) => (None, Some(err.to_string())),
+                };
+                (
+                    script.witness.iter().map(witness_kind).collect(),
+                    program,
+                    Some(1 + script.program_bundle.len()),
+                    program_error,
+                    Some(script.call_proof.path.neighbors.len()),
+                )
+            }
+            TransactionData::Message(ref message) => (
+                vec![witness_kind(&message.signature)],
+                None,
+                None,
+                None,
+                None,
+            ),
+        };
+        TransactionView {
+            tx_type: tx.tx_type,
+            fee: tx.get_tx_fee(),
+            inputs: tx.get_tx_inputs().iter().map(InputView::from).collect(),
+            outputs: tx.get_tx_outputs().iter().map(OutputView::from).collect(),
+            witnesses,
+            program,
+            program_count,
+            program_error,
+            call_proof_depth,
+        }
+    }
+}
+
+impl TransactionView {
+    /// Labels the script addresses of the outputs with the names `contract_name` knows.
+    pub fn label_script_addresses(&mut self, contract_name: impl Fn(&str) -> Option<String>) {
+        for output in self.outputs.iter_mut() {
+            output.script_contract = output.script_address.as_deref().and_then(&contract_name);
+        }
+    }
+}
+
+/// Decodes a serialized transaction into its structured view, without verifying it.
+pub fn decode_transaction_view(bytes: &[u8]) -> Result<TransactionView, DecodeError> {
+    Ok(TransactionView::from(&decode_transaction(bytes)?))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reference_tx::{
+        convert_output_to_input, create_dark_reference_transaction, create_genesis_block,
+    };
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+
+    #[test]
+    fn decode_dark_transfer_test() {
+        let tx = create_dark_reference_transaction();
+        let bytes = bincode::serialize(&tx).unwrap();
+        let view = decode_transaction_view(&bytes).unwrap();
+
+        assert_eq!(view.tx_type, TransactionType::Transfer);
+        assert_eq!(view.fee, tx.get_tx_fee());
+        assert_eq!(view.inputs.len(), tx.get_tx_inputs().len());
+        assert_eq!(view.outputs.len(), tx.get_tx_outputs().len());
+        assert!(view.outputs.iter().all(|o| o.commitments.len() == 1));
+        assert!(view.program.is_none());
+
+        // the field by field decoding matches bincode
+        let decoded = decode_transaction(&bytes).unwrap();
+        assert_eq!(bincode::serialize(&decoded).unwrap(), bytes);
+    }
+
+    #[test]
+    fn decode_script_transaction_test() {
+        let (acc, _) = Account::generate_random_account_with_value(Scalar::from(20u64));
+        let coin = create_genesis_block(30, 1, acc)
+            .into_iter()
+            .find(|record| record.value.out_type == IOType::Coin)
+            .unwrap();
+        let outputs = vec![coin.value.clone()];
+        let inputs = vec![convert_output_to_input(coin).unwrap()];
+        let script_tx = ScriptTransaction::create_utxo_dummy_script_transaction(&inputs, &outputs);
+        let tx = Transaction::transaction_script(TransactionData::TransactionScript(script_tx));
+        let bytes = bincode::serialize(&tx).unwrap();
+        let view = decode_transaction_view(&bytes).unwrap();
+
+        assert_eq!(view.tx_type, TransactionType::Script);
+        assert_eq!(view.inputs.len(), 1);
+        assert_eq!(view.inputs[0].in_type, IOType::Coin);
+        assert_eq!(
+            view.outputs[0].owner,
+            outputs[0].output.get_owner_address().cloned()
+        );
+        assert!(view.program.is_some());
+        assert_eq!(view.program_count, Some(1));
+        assert!(view.program_error.is_none());
+        assert!(view.call_proof_depth.is_some());
+    }
+
+    #[test]
+    fn label_script_addresses_test() {
+        let tx = create_dark_reference_transaction();
+        let mut view = TransactionView::from(&tx);
+        view.outputs[0].script_address = Some("known".to_string());
+        view.outputs[1].script_address = Some("unknown".to_string());
+        view.label_script_addresses(|address| match address {
+            "known" => Some("relayer".to_string()),
+            _ => None,
+        });
+        assert_eq!(view.outputs[0].script_contract, Some("relayer".to_string()));
+        assert!(view.outputs[1..].iter().all(|o| o.script_contract.is_none()));
+    }
+
+    #[test]
+    fn decode_truncated_transaction_test() {
+        let tx = create_dark_reference_transaction();
+        let bytes = bincode::serialize(&tx).unwrap();
+        // version, tx_type, tx variant and the transfer header are 2 + 4 + 4 + 3 * 8 + 3 bytes
+        let err = decode_transaction(&bytes[..42]).unwrap_err();
+        assert_eq!(err.field, "transfer.inputs");
+        assert_eq!(err.offset, 37);
+
+        let err = decode_transaction(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(err.offset < bytes.len());
+        assert!(bincode::deserialize::<Transaction>(&bytes[..bytes.len() - 1]).is_err());
+
+        let err = decode_transaction(&[]).unwrap_err();
+        assert_eq!((err.field.as_str(), err.offset), ("version", 0));
+
+        // unknown versions are not decoded as the current one
+        let mut unknown = bytes.clone();
+        unknown[0] = 2;
+        let err = decode_transaction(&unknown).unwrap_err();
+        assert_eq!((err.field.as_str(), err.offset), ("version", 0));
+        assert_eq!(err.message, "Unsupported transaction version 2");
+    }
+}
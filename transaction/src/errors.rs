@@ -1,12 +1,109 @@
 use thiserror::Error;
+use zkvm::errors::VerificationFailure;
 /// Represents an error in Transaction creation, proving and verification.
 #[derive(Error, Clone, Debug, Eq, PartialEq)]
 pub enum TxError {
     /// This error occurs when a Tx is not valid
     #[error("Transaction is invalid")]
     InvalidTx,
-
     /// This error occurs when a Tx proof verification fails
     #[error("Tx proof failed")]
     InvalidProof,
-}
\ No newline at end of file
+    /// This error occurs when a receiver output is below the minimum allowed value
+    #[error("Receiver output {index} of value {amount} is below the minimum of {min}")]
+    DustOutput { index: usize, amount: u64, min: u64 },
+    /// This error occurs when a sender spends more than it debits or holds
+    #[error("Sender {index} over-spends: sending {sent}, available {available}")]
+    SenderOverspend {
+        index: usize,
+        sent: u64,
+        available: u64,
+    },
+    /// This error occurs when an input owner does not match the account used in the proof
+    #[error("Owner of input {index} does not match the proof account")]
+    OwnerKeyMismatch { index: usize },
+    /// This error occurs when an input owner is not a valid standard address
+    #[error("Owner address of input {index} is invalid")]
+    InvalidOwnerAddress { index: usize },
+    /// This error occurs when the senders and receivers do not fit in a single transfer
+    #[error("senders and receivers count exceeds the anonymity set size")]
+    AccountCountExceeded,
+    /// This error occurs when the anonymity set size is not supported by the shuffle proof
+    #[error("Anonymity set size {0} is not supported")]
+    UnsupportedAnonymitySetSize(usize),
+    /// This error occurs when the available coins do not cover the transfer amount and fee
+    #[error("Insufficient funds: required {required}, available {available}")]
+    InsufficientFunds { required: u64, available: u64 },
+    /// This error occurs when the coin selection needs more inputs than allowed
+    #[error("Coin selection needs more than {max_inputs} inputs")]
+    InputLimitExceeded { max_inputs: usize },
+    /// This error occurs when a count declared in the tx header does not match the tx content
+    #[error("Header {field} is {declared} but the transaction carries {actual}")]
+    HeaderMismatch {
+        field: &'static str,
+        declared: usize,
+        actual: usize,
+    },
+    /// This error occurs when the declared fee is not the fee balanced by the transfer proof
+    #[error("Declared fee {fee} does not match the balance of the transfer proof")]
+    FeeMismatch { fee: u64 },
+    /// This error occurs when two inputs of the tx spend the same utxo
+    #[error("Inputs {index_a} and {index_b} spend the same utxo")]
+    DuplicateInput { index_a: usize, index_b: usize },
+    /// This error occurs when an input refers to an output index the tx does not have
+    #[error("Input {index} refers to output {output_index} the transaction does not have")]
+    DanglingPendingInput { index: usize, output_index: u16 },
+    /// This error occurs when the tx has more outputs than a pending input can refer to
+    #[error("Transaction has {count} outputs, above the limit of {limit}")]
+    TooManyOutputs { count: usize, limit: u16 },
+    /// This error occurs when an input does not carry the output of the tx it refers to
+    #[error("Input {index} does not match output {output_index} it refers to")]
+    PendingInputMismatch { index: usize, output_index: u16 },
+    /// This error occurs when the encoded tx carries a version this release does not know
+    #[error("Unsupported transaction version {0}")]
+    UnsupportedVersion(u16),
+    /// This error occurs when the encoded tx can not be decoded
+    #[error("Invalid transaction encoding: {0}")]
+    InvalidEncoding(String),
+    /// This error occurs when the witness an input refers to is missing or not valid for the input
+    #[error("Witness of input {index} does not match the input")]
+    WitnessMismatch { index: usize },
+    /// This error occurs when a witness of the tx is not referenced by any input
+    #[error("Witness {index} is not referenced by any input")]
+    OrphanWitness { index: usize },
+    /// This error occurs when the data of a memo or the state variables of a state exceed a limit
+    #[error("Output {which} is {size}, above the limit of {limit}")]
+    DataTooLarge {
+        which: &'static str,
+        size: usize,
+        limit: usize,
+    },
+    /// This error occurs when the script R1CS proof verification fails
+    #[error("Script proof verification failed: {0}")]
+    ScriptVerificationFailed(VerificationFailure),
+    /// This error occurs when a verification check without diagnostics rejects the tx
+    #[error("{0}")]
+    Rejected(&'static str),
+}
+
+impl From<&'static str> for TxError {
+    fn from(reason: &'static str) -> TxError {
+        TxError::Rejected(reason)
+    }
+}
+
+/// Checks a count declared in a tx header against the length it describes.
+pub(crate) fn check_header_count(
+    field: &'static str,
+    declared: u16,
+    actual: usize,
+) -> Result<(), TxError> {
+    if declared as usize != actual {
+        return Err(TxError::HeaderMismatch {
+            field,
+            declared: declared as usize,
+            actual,
+        });
+    }
+    Ok(())
+}
@@ -7,15 +7,25 @@ pub extern crate quisquislib;
 #[macro_use]
 
 mod constants;
+pub mod coin_selection;
+pub mod decode;
 mod errors;
 mod message;
 mod proof;
+pub mod oracle;
+pub mod order_lifecycle;
+pub mod programs;
+mod quisquis;
 pub mod reference_tx;
+mod script_builder;
 mod script_tx;
 mod serialization;
 mod transaction;
 mod transfer_tx;
+pub mod verify_relayer;
 pub mod vm_run;
+pub mod weight;
+mod witness;
 //mod encode;
 #[cfg(test)]
 mod tests;
@@ -24,9 +34,11 @@ mod tests;
 pub use self::errors::TxError;
 pub use self::message::Message;
 pub use self::proof::{DarkTxProof, ShuffleTxProof};
+pub use self::quisquis::{QuisQuisConfig, DEFAULT_ANONYMITY_SET_SIZE};
 pub use self::reference_tx::{Receiver, Sender};
+pub use self::script_builder::{ScriptTxBuilder, StateDelta};
 pub use self::script_tx::ScriptTransaction;
-pub use self::transaction::{Transaction, TransactionData, TransactionType};
+pub use self::transaction::{Transaction, TransactionData, TransactionType, TransactionVersion};
 pub use self::transfer_tx::TransferTransaction;
 
 //pub use self::encode::{ReaderExt, WriterExt};
@@ -0,0 +1,290 @@
+//! Oracle signatures over the tx_data of price sensitive programs.
+//!
+//! Settlement programs read the settle price from `tx_data`, which the prover
+//! chooses freely. For programs configured as price sensitive the verifier
+//! requires a signature of the oracle over the script address, a block height
+//! window and the tx_data bytes, and rejects the tx outside of the window.
+
+use address::{Address, AddressType};
+use quisquislib::{keys::PublicKey, ristretto::RistrettoPublicKey, ristretto::RistrettoSecretKey};
+use serde::{Deserialize, Serialize};
+use zkschnorr::Signature;
+use zkvm::Program;
+
+/// Transcript label of the oracle signature.
+pub const ORACLE_SIGN_LABEL: &[u8] = b"OracleTxDataSign";
+
+/// Signature of the oracle over the tx_data of a script transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataSignature {
+    /// First block height the signed data is valid at
+    pub valid_from: u64,
+    /// Last block height the signed data is valid at
+    pub valid_until: u64,
+    pub signature: Signature,
+}
+
+impl DataSignature {
+    /// Returns true if `height` lies within the signed window.
+    pub fn is_valid_at(&self, height: u64) -> bool {
+        self.valid_from <= height && height <= self.valid_until
+    }
+
+    /// Verifies the signature over `tx_data` for `script_address` with the oracle key.
+    pub fn verify(
+        &self,
+        key: &RistrettoPublicKey,
+        script_address: &str,
+        tx_data: &zkvm::String,
+    ) -> Result<(), &'static str> {
+        let message = oracle_message(script_address, self.valid_from, self.valid_until, tx_data);
+        key.verify_msg(&message, &self.signature, ORACLE_SIGN_LABEL)
+            .map_err(|_| "OracleError::InvalidSignature")
+    }
+}
+
+/// Bytes signed by the oracle.
+/// `tx_data` is encoded as it appears in the transaction, i.e. in its verifier view.
+pub fn oracle_message(
+    script_address: &str,
+    valid_from: u64,
+    valid_until: u64,
+    tx_data: &zkvm::String,
+) -> Vec<u8> {
+    bincode::serialize(&(script_address, valid_from, valid_until, tx_data)).unwrap()
+}
+
+/// Signs `tx_data` for `script_address` on the oracle side.
+/// The signature is valid from block `valid_from` up to and including `valid_until`.
+pub fn sign_tx_data(
+    sk: &RistrettoSecretKey,
+    pk: &RistrettoPublicKey,
+    script_address: &str,
+    valid_from: u64,
+    valid_until: u64,
+    tx_data: &zkvm::String,
+) -> DataSignature {
+    let message = oracle_message(script_address, valid_from, valid_until, tx_data);
+    DataSignature {
+        valid_from,
+        valid_until,
+        signature: pk.sign_msg(&message, sk, ORACLE_SIGN_LABEL),
+    }
+}
+
+/// Oracle key and the programs whose tx_data has to be signed by it.
+#[derive(Debug, Clone)]
+pub struct OracleConfig {
+    pub key: RistrettoPublicKey,
+    /// Bytecode of the price sensitive programs
+    pub price_programs: Vec<Vec<u8>>,
+}
+
+impl OracleConfig {
+    pub fn new(key: RistrettoPublicKey, price_programs: &[Program]) -> Self {
+        OracleConfig {
+            key,
+            price_programs: price_programs
+                .iter()
+                .map(|program| program.to_bytes())
+                .collect(),
+        }
+    }
+
+    /// Reads the oracle address from `ORACLE_ADDRESS` and the hex bytecodes of the
+    /// price sensitive programs, comma separated, from `ORACLE_PRICE_PROGRAMS`.
+    /// Returns None if no oracle address is set.
+    pub fn from_env() -> Result<Option<Self>, &'static str> {
+        OracleConfig::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads the keys of [`OracleConfig::from_env`] from `lookup`, e.g. a layered config.
+    pub fn from_lookup<F: Fn(&str) -> Option<String>>(
+        lookup: F,
+    ) -> Result<Option<Self>, &'static str> {
+        let address = match lookup("ORACLE_ADDRESS") {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+        let key: RistrettoPublicKey = Address::from_hex(&address, AddressType::Standard)?.into();
+        let mut price_programs = Vec::new();
+        if let Some(programs) = lookup("ORACLE_PRICE_PROGRAMS") {
+            for program in programs.split(',').filter(|p| !p.trim().is_empty()) {
+                let bytecode =
+                    hex::decode(program.trim()).map_err(|_| "OracleError::Invalid hex program")?;
+                price_programs.push(bytecode);
+            }
+        }
+        Ok(Some(OracleConfig {
+            key,
+            price_programs,
+        }))
+    }
+
+    /// Returns true if the program needs signed tx_data.
+    pub fn is_price_sensitive(&self, bytecode: &[u8]) -> bool {
+        self.price_programs
+            .iter()
+            .any(|program| program.as_slice() == bytecode)
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::programs::{create_swap_deposit_memo, get_swap_program};
+    use crate::ScriptTransaction;
+    use bulletproofs::r1cs::R1CSProof;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::elgamal::ElGamalCommitment;
+    use quisquislib::keys::SecretKey;
+    use zkvm::merkle::CallProof;
+    use zkvm::zkos_types::{Input, InputData, OutputCoin, Utxo};
+
+    const SCRIPT_ADDRESS: &str = "script";
+
+    fn oracle() -> (RistrettoSecretKey, RistrettoPublicKey, OracleConfig) {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let config = OracleConfig::new(pk, &[get_swap_program()]);
+        (sk, pk, config)
+    }
+
+    // settlement tx at the settle price, the proofs are not checked here
+    fn settle_tx(program: Vec<u8>, price: u64) -> ScriptTransaction {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let encrypt =
+            ElGamalCommitment::generate_commitment(&pk, Scalar::zero(), Scalar::from(10u64));
+        let coin = Input::coin(InputData::coin(
+            Utxo::default(),
+            OutputCoin::new(encrypt, String::new()),
+            0,
+        ));
+        let memo = create_swap_deposit_memo(
+            SCRIPT_ADDRESS.to_string(),
+            String::new(),
+            10,
+            Scalar::zero(),
+            5,
+        );
+        ScriptTransaction::set_script_transaction(
+            0,
+            1,
+            0,
+            vec![coin],
+            vec![memo],
+            program,
+            CallProof::default(),
+            R1CSProof::from_bytes(&[0u8; 32]).unwrap(),
+            Vec::new(),
+            Some(zkvm::String::from(Scalar::from(price))),
+        )
+    }
+
+    #[test]
+    fn signed_tx_data_test() {
+        let (sk, pk, config) = oracle();
+        let tx = settle_tx(get_swap_program().to_bytes(), 42_000);
+        let signature = sign_tx_data(
+            &sk,
+            &pk,
+            SCRIPT_ADDRESS,
+            100,
+            110,
+            &tx.tx_data.clone().unwrap(),
+        );
+        let tx = tx.with_data_signature(signature);
+        assert_eq!(tx.verify_data_signature(&config, 100), Ok(()));
+        assert_eq!(tx.verify_data_signature(&config, 110), Ok(()));
+
+        // a price the oracle did not sign
+        let mut tampered = tx.clone();
+        tampered.tx_data = Some(zkvm::String::from(Scalar::from(1u64)));
+        assert_eq!(
+            tampered.verify_data_signature(&config, 105),
+            Err("OracleError::InvalidSignature")
+        );
+    }
+
+    #[test]
+    fn signed_tx_data_encoding_test() {
+        use crate::{Transaction, TransactionVersion, TxError};
+        let (sk, pk, _) = oracle();
+        let tx = settle_tx(get_swap_program().to_bytes(), 42_000);
+        let signature = sign_tx_data(
+            &sk,
+            &pk,
+            SCRIPT_ADDRESS,
+            100,
+            110,
+            &tx.tx_data.clone().unwrap(),
+        );
+        let signed = Transaction::from(tx.clone().with_data_signature(signature)).to_bytes();
+        let decoded = Transaction::from_bytes(&signed).unwrap();
+        assert_eq!(decoded.to_bytes(), signed);
+
+        // the signature closes the script tx layout, an unsigned tx ends with its None tag
+        let unsigned = Transaction::from(tx).to_bytes();
+        assert_eq!(unsigned.last(), Some(&0u8));
+        // txs encoded before the signature was added are rejected by their version
+        let mut old_layout = unsigned[..unsigned.len() - 1].to_vec();
+        old_layout[..2].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(
+            Transaction::from_bytes(&old_layout).unwrap_err(),
+            TxError::UnsupportedVersion(1)
+        );
+        // and do not decode under the current one
+        old_layout[..2].copy_from_slice(&TransactionVersion::CURRENT.to_u16().to_le_bytes());
+        assert!(matches!(
+            Transaction::from_bytes(&old_layout),
+            Err(TxError::InvalidEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn unsigned_tx_data_test() {
+        let (_, _, config) = oracle();
+        let tx = settle_tx(get_swap_program().to_bytes(), 42_000);
+        assert_eq!(
+            tx.verify_data_signature(&config, 100),
+            Err("OracleError::tx_data is not signed by the oracle")
+        );
+        // programs which are not price sensitive take tx_data as is
+        let tx = settle_tx(vec![b'0'; 32], 42_000);
+        assert_eq!(tx.verify_data_signature(&config, 100), Ok(()));
+    }
+
+    #[test]
+    fn stale_tx_data_test() {
+        let (sk, pk, config) = oracle();
+        let tx = settle_tx(get_swap_program().to_bytes(), 42_000);
+        let signature = sign_tx_data(
+            &sk,
+            &pk,
+            SCRIPT_ADDRESS,
+            100,
+            110,
+            &tx.tx_data.clone().unwrap(),
+        );
+        let tx = tx.with_data_signature(signature);
+        for height in [99u64, 111].iter() {
+            assert_eq!(
+                tx.verify_data_signature(&config, *height),
+                Err("OracleError::Signed tx_data is outside of its block height window")
+            );
+        }
+
+        // a signature of another oracle
+        let (_, _, other) = oracle();
+        assert_eq!(
+            tx.verify_data_signature(&other, 105),
+            Err("OracleError::InvalidSignature")
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! Lifecycle of a relayer trade order, followed from its memo output.
+//!
+//! The order tx creates a memo output holding the initial margin (IM) as its
+//! commitment and the order terms as its data. The order is closed exactly once by
+//! the script tx spending that memo. The tx is a settlement if it returns a coin
+//! to the order owner and a liquidation otherwise. The available margin (AM) is the
+//! coin value of the memo input and the payment is the first script data item of
+//! the state input.
+//!
+//! The coin output at the index of the memo input is the payout of the order and has
+//! to go to the owner of the memo, see [`verify_settlement_payout`]. A liquidation
+//! pays a zero value coin to the owner, the value is not checked here.
+
+use crate::Transaction;
+use curve25519_dalek::scalar::Scalar;
+use std::collections::HashMap;
+use zkvm::zkos_types::{IOType, Input, Output, OutputMemo, Utxo};
+use zkvm::Commitment;
+
+/// State of a trade order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderState {
+    Created,
+    Settled,
+    Liquidated,
+}
+
+/// Trade order terms as stored in the memo output of the order tx.
+/// Memo data : [position_size, leverage, entry_price, order_side]
+#[derive(Debug, Clone)]
+pub struct TraderOrderMemo {
+    pub initial_margin: Commitment,
+    pub position_size: Scalar,
+    pub leverage: Commitment,
+    pub entry_price: Scalar,
+    pub order_side: Scalar,
+}
+
+impl TraderOrderMemo {
+    pub fn from_memo(memo: &OutputMemo) -> Result<Self, &'static str> {
+        let data = match memo.get_data() {
+            Some(data) if data.len() == 4 => data,
+            _ => return Err("OrderError::Invalid order memo data"),
+        };
+        let scalar = |index: usize| -> Result<Scalar, &'static str> {
+            data[index]
+                .as_scalar()
+                .map(|witness| witness.to_scalar())
+                .map_err(|_| "OrderError::Invalid order memo data")
+        };
+        Ok(TraderOrderMemo {
+            initial_margin: memo.commitment.clone(),
+            position_size: scalar(0)?,
+            leverage: data[1]
+                .as_commitment()
+                .map_err(|_| "OrderError::Invalid order memo data")?
+                .clone(),
+            entry_price: scalar(2)?,
+            order_side: scalar(3)?,
+        })
+    }
+}
+
+/// Values of a closed order.
+#[derive(Debug, Clone)]
+pub struct OrderOutcome {
+    /// Memo output created at order time
+    pub utxo: Utxo,
+    pub owner: String,
+    /// Settled or Liquidated
+    pub state: OrderState,
+    pub initial_margin: Commitment,
+    pub available_margin: Commitment,
+    /// Payment of the state input, if any
+    pub payment: Option<Commitment>,
+}
+
+/// Order created by a memo output and the transition closing it.
+#[derive(Debug, Clone)]
+pub struct OrderLifecycle {
+    utxo: Utxo,
+    owner: String,
+    memo: TraderOrderMemo,
+    state: OrderState,
+    outcome: Option<OrderOutcome>,
+}
+
+impl OrderLifecycle {
+    /// Tracks the order of the memo `output` created at `utxo`.
+    pub fn new(utxo: Utxo, output: &Output) -> Result<Self, &'static str> {
+        let memo = output
+            .as_out_memo()
+            .ok_or("OrderError::Order output is not a memo")?;
+        Ok(OrderLifecycle {
+            utxo,
+            owner: memo.owner.clone(),
+            memo: TraderOrderMemo::from_memo(memo)?,
+            state: OrderState::Created,
+            outcome: None,
+        })
+    }
+
+    pub fn utxo(&self) -> &Utxo {
+        &self.utxo
+    }
+
+    pub fn state(&self) -> OrderState {
+        self.state
+    }
+
+    pub fn memo(&self) -> &TraderOrderMemo {
+        &self.memo
+    }
+
+    /// Outcome of the order once it is closed.
+    pub fn outcome(&self) -> Option<&OrderOutcome> {
+        self.outcome.as_ref()
+    }
+
+    /// Closes the order with `tx`, which has to spend the order memo.
+    /// Fails if the order is already closed or pays out to another owner.
+    pub fn apply(&mut self, tx: &Transaction) -> Result<&OrderOutcome, &'static str> {
+        let inputs = tx.get_tx_inputs();
+        let (index, memo_input) = inputs
+            .iter()
+            .enumerate()
+            .find(|(_, input)| input.in_type == IOType::Memo && input.as_utxo() == Some(&self.utxo))
+            .ok_or("OrderError::Transaction does not spend the order")?;
+        if self.state != OrderState::Created {
+            return Err("OrderError::Order is already closed");
+        }
+        let available_margin = memo_input
+            .as_input_data()
+            .get_coin_value_from_memo()
+            .clone()
+            .ok_or("OrderError::Missing available margin")?;
+
+        // the coin output at the index of the memo input is the payout of the order
+        let returned = match tx.get_tx_outputs().get(index) {
+            Some(output) if output.out_type == IOType::Coin => {
+                verify_settlement_payout(memo_input, output)?;
+                true
+            }
+            _ => false,
+        };
+        let state = if returned {
+            OrderState::Settled
+        } else {
+            OrderState::Liquidated
+        };
+        self.state = state;
+        Ok(self.outcome.insert(OrderOutcome {
+            utxo: self.utxo,
+            owner: self.owner.clone(),
+            state,
+            initial_margin: self.memo.initial_margin.clone(),
+            available_margin,
+            payment: payment(&inputs),
+        }))
+    }
+}
+
+/// Checks that the coin output `coin_out` paying out the memo input `memo_in` goes to
+/// the owner recorded in the memo.
+pub fn verify_settlement_payout(memo_in: &Input, coin_out: &Output) -> Result<(), &'static str> {
+    if memo_in.in_type != IOType::Memo {
+        return Err("OrderError::Settlement input is not a memo");
+    }
+    if coin_out.out_type != IOType::Coin {
+        return Err("OrderError::Settlement output is not a coin");
+    }
+    match (
+        memo_in.as_owner_address(),
+        coin_out.as_output_data().get_owner_address(),
+    ) {
+        (Some(owner), Some(payee)) if owner == payee => Ok(()),
+        _ => Err("OrderError::Settlement payout is not to the order owner"),
+    }
+}
+
+// first script data item of the state input, if it is a commitment
+fn payment(inputs: &[Input]) -> Option<Commitment> {
+    inputs
+        .iter()
+        .filter_map(|input| input.as_input_data().as_state_script_data())
+        .find_map(|data| data.first()?.as_commitment().ok().cloned())
+}
+
+/// Orders of one trading script, for auditing that each order is closed once.
+#[derive(Debug, Clone)]
+pub struct OrderTracker {
+    script_address: String,
+    orders: HashMap<String, OrderLifecycle>,
+}
+
+impl OrderTracker {
+    /// Tracker of the orders whose memos are locked by `script_address`.
+    pub fn new(script_address: String) -> Self {
+        OrderTracker {
+            script_address,
+            orders: HashMap::new(),
+        }
+    }
+
+    /// Tracks the order memo `output` created at `utxo`.
+    pub fn track(&mut self, utxo: Utxo, output: &Output) -> Result<(), &'static str> {
+        let order = OrderLifecycle::new(utxo, output)?;
+        self.orders.insert(utxo.to_string(), order);
+        Ok(())
+    }
+
+    pub fn get(&self, utxo: &Utxo) -> Option<&OrderLifecycle> {
+        self.orders.get(&utxo.to_string())
+    }
+
+    /// Verifies the order transitions of the txs of a block and applies them.
+    /// Memo inputs of the trading script have to spend a tracked open order, so a
+    /// second settlement of an order fails in any later block.
+    /// Nothing is applied if a tx fails.
+    pub fn verify_block(&mut self, txs: &[Transaction]) -> Result<Vec<OrderOutcome>, &'static str> {
+        let mut orders = self.orders.clone();
+        let mut outcomes = Vec::new();
+        for tx in txs {
+            for input in tx.get_tx_inputs() {
+                let memo = match input.as_out_memo() {
+                    Some(memo) if memo.script_address == self.script_address => memo,
+                    _ => continue,
+                };
+                let utxo = input.as_utxo().ok_or("OrderError::Unknown order")?;
+                let order = orders
+                    .get_mut(&utxo.to_string())
+                    .ok_or("OrderError::Unknown order")?;
+                if memo.owner != order.owner {
+                    return Err("OrderError::Order owner mismatch");
+                }
+                outcomes.push(order.apply(tx)?.clone());
+            }
+        }
+        self.orders = orders;
+        Ok(outcomes)
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ScriptTransaction, TransactionData};
+    use address::{Address, Network};
+    use quisquislib::accounts::Account;
+    use zkvm::zkos_types::{InputData, OutputCoin, OutputData, OutputState};
+    use zkvm::Hash;
+
+    const SCRIPT_ADDRESS: &str = "trading script";
+
+    fn owner() -> (Account, String) {
+        let (acc, _) = Account::generate_random_account_with_value(Scalar::from(10u64));
+        let (pk, _) = acc.get_account();
+        (
+            acc,
+            Address::standard_address(Network::Mainnet, pk).as_hex(),
+        )
+    }
+
+    fn order_memo(owner: &str) -> OutputMemo {
+        let data = vec![
+            zkvm::String::from(Scalar::from(25000u64)),
+            zkvm::String::from(Commitment::blinded(5u64)),
+            zkvm::String::from(Scalar::from(50u64)),
+            zkvm::String::from(Scalar::from(1u64)),
+        ];
+        OutputMemo::new(
+            SCRIPT_ADDRESS.to_string(),
+            owner.to_string(),
+            Commitment::blinded(100u64),
+            Some(data),
+            0,
+        )
+    }
+
+    // settlement tx of the order at `utxo`, the proofs are not checked here
+    fn settle_tx(utxo: Utxo, account: &Account, owner: &str, returned: bool) -> Transaction {
+        settle_tx_to(utxo, account, owner, returned.then_some(owner))
+    }
+
+    // settlement tx paying the coin of `account` to `payee`, at the index of the memo input
+    fn settle_tx_to(
+        utxo: Utxo,
+        account: &Account,
+        owner: &str,
+        payee: Option<&str>,
+    ) -> Transaction {
+        let memo = Input::memo(InputData::memo(
+            utxo,
+            order_memo(owner),
+            0,
+            Some(Commitment::blinded(90u64)),
+        ));
+        let out_state = OutputState {
+            nonce: 1,
+            script_address: SCRIPT_ADDRESS.to_string(),
+            owner: owner.to_string(),
+            commitment: Commitment::blinded(1000u64),
+            state_variables: None,
+            timebounds: 0,
+        };
+        let state = Input::state(InputData::state(
+            Utxo::from_hash(Hash([2u8; 32]), 0),
+            out_state.clone(),
+            Some(vec![zkvm::String::from(Commitment::blinded(10u64))]),
+            1,
+        ));
+        let mut outputs = Vec::new();
+        if let Some(payee) = payee {
+            let coin = OutputCoin::new(account.get_account().1, payee.to_string());
+            outputs.push(Output::coin(OutputData::Coin(coin)));
+        }
+        outputs.push(Output::state(OutputData::State(OutputState {
+            nonce: 2,
+            ..out_state
+        })));
+        let tx = ScriptTransaction::create_utxo_dummy_script_transaction(&[memo, state], &outputs);
+        Transaction::transaction_script(TransactionData::TransactionScript(tx))
+    }
+
+    #[test]
+    fn order_settle_test() {
+        let (account, owner) = owner();
+        let utxo = Utxo::from_hash(Hash([1u8; 32]), 0);
+        let output = Output::memo(OutputData::Memo(order_memo(&owner)));
+        let mut order = OrderLifecycle::new(utxo, &output).unwrap();
+        assert_eq!(order.state(), OrderState::Created);
+        assert_eq!(order.memo().entry_price, Scalar::from(50u64));
+
+        let outcome = order
+            .apply(&settle_tx(utxo, &account, &owner, true))
+            .unwrap()
+            .clone();
+        assert_eq!(outcome.state, OrderState::Settled);
+        assert!(outcome.payment.is_some());
+        assert_eq!(order.state(), OrderState::Settled);
+
+        // the margin stays with the pool
+        let mut order = OrderLifecycle::new(utxo, &output).unwrap();
+        let outcome = order.apply(&settle_tx(utxo, &account, &owner, false));
+        assert_eq!(outcome.unwrap().state, OrderState::Liquidated);
+
+        // a tx of another order
+        let other = Utxo::from_hash(Hash([3u8; 32]), 0);
+        let mut order = OrderLifecycle::new(utxo, &output).unwrap();
+        assert_eq!(
+            order
+                .apply(&settle_tx(other, &account, &owner, true))
+                .unwrap_err(),
+            "OrderError::Transaction does not spend the order"
+        );
+    }
+
+    #[test]
+    fn order_double_settle_test() {
+        let (account, owner) = owner();
+        let utxo = Utxo::from_hash(Hash([1u8; 32]), 0);
+        let mut tracker = OrderTracker::new(SCRIPT_ADDRESS.to_string());
+        tracker
+            .track(utxo, &Output::memo(OutputData::Memo(order_memo(&owner))))
+            .unwrap();
+
+        let settle = settle_tx(utxo, &account, &owner, true);
+        let outcomes = tracker.verify_block(&[settle.clone()]).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(tracker.get(&utxo).unwrap().state(), OrderState::Settled);
+
+        // a second settlement in a later block
+        let liquidate = settle_tx(utxo, &account, &owner, false);
+        assert_eq!(
+            tracker.verify_block(&[liquidate]).unwrap_err(),
+            "OrderError::Order is already closed"
+        );
+        assert_eq!(tracker.get(&utxo).unwrap().state(), OrderState::Settled);
+
+        // and twice in the same block, nothing is applied
+        let mut tracker = OrderTracker::new(SCRIPT_ADDRESS.to_string());
+        tracker
+            .track(utxo, &Output::memo(OutputData::Memo(order_memo(&owner))))
+            .unwrap();
+        assert!(tracker.verify_block(&[settle.clone(), settle]).is_err());
+        assert_eq!(tracker.get(&utxo).unwrap().state(), OrderState::Created);
+    }
+
+    #[test]
+    fn order_unknown_settle_test() {
+        let (account, owner) = owner();
+        let mut tracker = OrderTracker::new(SCRIPT_ADDRESS.to_string());
+        let unknown = Utxo::from_hash(Hash([4u8; 32]), 0);
+        assert_eq!(
+            tracker
+                .verify_block(&[settle_tx(unknown, &account, &owner, true)])
+                .unwrap_err(),
+            "OrderError::Unknown order"
+        );
+
+        // memos of other scripts are not orders of the tracker
+        let mut other = OrderTracker::new("other script".to_string());
+        assert!(other
+            .verify_block(&[settle_tx(unknown, &account, &owner, true)])
+            .unwrap()
+            .is_empty());
+
+        // an order memo without the order terms
+        let mut memo = order_memo(&owner);
+        memo.set_data(vec![zkvm::String::from(Scalar::from(1u64))]);
+        assert!(tracker
+            .track(unknown, &Output::memo(OutputData::Memo(memo)))
+            .is_err());
+    }
+
+    #[test]
+    fn settlement_payout_test() {
+        let (account, owner) = owner();
+        let (_, attacker) = owner();
+        let utxo = Utxo::from_hash(Hash([1u8; 32]), 0);
+        let memo_in = Input::memo(InputData::memo(utxo, order_memo(&owner), 0, None));
+        let payout = |payee: &str, account: &Account| {
+            Output::coin(OutputData::Coin(OutputCoin::new(
+                account.get_account().1,
+                payee.to_string(),
+            )))
+        };
+        assert!(verify_settlement_payout(&memo_in, &payout(&owner, &account)).is_ok());
+        assert_eq!(
+            verify_settlement_payout(&memo_in, &payout(&attacker, &account)).unwrap_err(),
+            "OrderError::Settlement payout is not to the order owner"
+        );
+        // a liquidation pays a zero value coin to the owner
+        let (empty, _) = Account::generate_random_account_with_value(Scalar::zero());
+        assert!(verify_settlement_payout(&memo_in, &payout(&owner, &empty)).is_ok());
+        let output = Output::memo(OutputData::Memo(order_memo(&owner)));
+        assert_eq!(
+            verify_settlement_payout(&memo_in, &output).unwrap_err(),
+            "OrderError::Settlement output is not a coin"
+        );
+
+        // a redirected payout does not close the order
+        let mut order = OrderLifecycle::new(utxo, &output).unwrap();
+        assert_eq!(
+            order
+                .apply(&settle_tx_to(utxo, &account, &owner, Some(&attacker)))
+                .unwrap_err(),
+            "OrderError::Settlement payout is not to the order owner"
+        );
+        assert_eq!(order.state(), OrderState::Created);
+        let outcome = order.apply(&settle_tx_to(utxo, &empty, &owner, Some(&owner)));
+        assert_eq!(outcome.unwrap().state, OrderState::Settled);
+    }
+}
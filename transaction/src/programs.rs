@@ -0,0 +1,579 @@
+//! Programs of the relayer script address and helpers for the constant-product swap pool.
+//!
+//! The pool is a contract State whose value commitment holds the reserve `R_a`
+//! and whose single state variable holds the reserve `R_b`. A swap spends a coin
+//! of `in_a` into a deposit Memo carrying the committed `out_b`, and moves the
+//! pool State to `(R_a + in_a, R_b - out_b)`. The swap program proves
+//!
+//! `(R_a + in_a) * (R_b - out_b) = R_a * R_b + error`
+//!
+//! with a committed, range proven `error`, i.e. the invariant never decreases.
+//! The payout Coin of `out_b` is released later by spending the deposit Memo.
+//!
+//! The tree also carries the programs opening a trader order and settling a single
+//! order against the pool State.
+
+use address::{Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::ristretto::RistrettoPublicKey;
+use zkvm::merkle::{CallProof, Hasher, MerkleTree};
+use zkvm::zkos_types::{
+    Input, InputData, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo,
+};
+use zkvm::{Commitment, Program, String as ZkvmString};
+
+/// Label of the merkle tree of the relayer programs.
+pub const PROGRAM_TREE_LABEL: &[u8] = b"ZkOS.MerkelTree";
+
+/// Position of the swap program in [`create_program_tree`].
+pub const SWAP_PROGRAM_INDEX: usize = 0;
+
+/// Position of the contract deploy program in [`create_program_tree`].
+pub const CONTRACT_DEPLOY_PROGRAM_INDEX: usize = 1;
+
+/// Position of the contract migration program in [`create_program_tree`].
+pub const CONTRACT_MIGRATION_PROGRAM_INDEX: usize = 2;
+
+/// Position of the trader order program in [`create_program_tree`].
+pub const TRADER_ORDER_PROGRAM_INDEX: usize = 3;
+
+/// Position of the trader settle program in [`create_program_tree`].
+pub const TRADER_SETTLE_PROGRAM_INDEX: usize = 4;
+
+/// Program enforcing the constant-product invariant of the swap pool.
+///
+/// Expects the stack initialized from the inputs `[Coin, State]` and the
+/// outputs `[Memo, State]`, i.e. from bottom to top:
+/// `in_a, out_b, R_a0, R_a1, R_b0, R_b1, error`.
+pub fn get_swap_program() -> Program {
+    Program::build(|p| {
+        p.dup(3) // R_a1
+            .commit()
+            .expr()
+            .dup(5) // R_a0
+            .commit()
+            .expr()
+            .dup(8) // in_a
+            .commit()
+            .expr()
+            .add()
+            .eq() // R_a1 == R_a0 + in_a
+            .dup(2) // R_b1
+            .commit()
+            .expr()
+            .roll(7) // out_b
+            .commit()
+            .expr()
+            .add()
+            .dup(4) // R_b0
+            .commit()
+            .expr()
+            .eq() // R_b1 + out_b == R_b0
+            .and()
+            .dup(2) // R_b1
+            .commit()
+            .expr()
+            .range() // R_b1 >= 0
+            .drop()
+            .roll(1) // error
+            .commit()
+            .expr()
+            .range() // error >= 0
+            .roll(3) // R_b0
+            .commit()
+            .expr()
+            .roll(5) // R_a0
+            .commit()
+            .expr()
+            .mul()
+            .add() // R_a0 * R_b0 + error
+            .roll(2) // R_b1
+            .commit()
+            .expr()
+            .roll(3) // R_a1
+            .commit()
+            .expr()
+            .mul()
+            .eq() // R_a1 * R_b1 == R_a0 * R_b0 + error
+            .and()
+            .verify()
+            .drop(); // in_a
+    })
+}
+
+/// Program run by the deploy transaction of the pool.
+///
+/// Expects the stack initialized for a contract deploy from the output Memo
+/// without data and the output State, i.e. from bottom to top:
+/// `locked, R_a, R_b`. Proves that the reserve `R_a` covers the locked amount.
+pub fn get_contract_deploy_program() -> Program {
+    Program::build(|p| {
+        p.drop() // R_b
+            .commit()
+            .expr()
+            .roll(1) // locked
+            .commit()
+            .expr()
+            .neg()
+            .add()
+            .range() // R_a - locked >= 0
+            .drop();
+    })
+}
+
+/// Program moving the contract State to the script address of an upgraded program tree.
+///
+/// Expects the stack initialized from the input State and the output State under
+/// the new script address, i.e. from bottom to top: `V0, V1, S0, S1`. Proves that
+/// the value and the state variable of the State are carried over unchanged.
+pub fn get_contract_migration_program() -> Program {
+    Program::build(|p| {
+        p.commit() // S1
+            .expr()
+            .roll(1) // S0
+            .commit()
+            .expr()
+            .eq() // S1 == S0
+            .roll(1) // V1
+            .commit()
+            .expr()
+            .roll(2) // V0
+            .commit()
+            .expr()
+            .eq() // V1 == V0
+            .and()
+            .verify();
+    })
+}
+
+/// Program settling `orders` trader orders against the pool State in one run.
+///
+/// Expects the stack initialized from the inputs `[Memo; orders]` followed by
+/// the State, and the outputs `[Coin; orders]` followed by the State. The memo
+/// data of each order holds `IM`, its coin value `CM` and the input State carries
+/// one committed `payment` per order as script data, i.e. from bottom to top:
+/// `(amount, IM, CM) * orders, TVL0, TVL1, TPS0, TPS1, payment * orders`.
+///
+/// Proves `payment == IM - CM` for every order on its own, and
+/// `TVL1 - TVL0 == TPS1 - TPS0 == sum(payment)` with `TVL1 >= 0` for the pool.
+pub fn get_batch_settle_program(orders: usize) -> Program {
+    Program::build(|p| {
+        // the running sum and constraint sit on top once the first order is settled
+        for j in (0..orders).rev() {
+            let acc = if j + 1 == orders { 0 } else { 2 };
+            p.roll(acc + j + 7) // amount
+                .drop()
+                .roll(acc + j + 6) // IM
+                .commit()
+                .expr()
+                .roll(acc + j + 6) // CM
+                .commit()
+                .expr()
+                .neg()
+                .add() // IM - CM
+                .roll(acc + 1) // payment
+                .dup(0)
+                .commit()
+                .expr()
+                .roll(2)
+                .eq() // payment == IM - CM
+                .roll(1)
+                .commit()
+                .expr();
+            if acc == 0 {
+                p.roll(1);
+            } else {
+                p.roll(3) // sum
+                    .add() // sum + payment
+                    .roll(2)
+                    .roll(2)
+                    .and();
+            }
+        }
+        p.dup(4) // TVL1
+            .commit()
+            .expr()
+            .dup(6) // TVL0
+            .commit()
+            .expr()
+            .neg()
+            .add() // TVL1 - TVL0
+            .roll(2) // sum
+            .eq() // sum == TVL1 - TVL0
+            .and()
+            .roll(1) // TPS1
+            .commit()
+            .expr()
+            .roll(2) // TPS0
+            .commit()
+            .expr()
+            .neg()
+            .add() // TPS1 - TPS0
+            .roll(2) // TVL1
+            .commit()
+            .expr()
+            .range() // TVL1 >= 0
+            .roll(3) // TVL0
+            .commit()
+            .expr()
+            .neg()
+            .add() // TVL1 - TVL0
+            .eq() // TPS1 - TPS0 == TVL1 - TVL0
+            .and()
+            .verify();
+    })
+}
+
+/// Program run by the transaction opening a trader order.
+///
+/// Expects the stack initialized from the input Coin and the output Memo of the
+/// order, whose data holds the terms read by
+/// [`crate::order_lifecycle::TraderOrderMemo`], i.e. from bottom to top:
+/// `IM, PositionSize, Leverage, EntryPrice, OrderSide`.
+/// Proves `IM * EntryPrice * Leverage == PositionSize`, the side is not checked.
+pub fn get_trader_order_program() -> Program {
+    Program::build(|p| {
+        p.drop() // OrderSide
+            .roll(3) // IM
+            .commit()
+            .expr()
+            .roll(1) // EntryPrice
+            .scalar()
+            .mul() // EntryPrice * IM
+            .roll(1) // Leverage
+            .commit()
+            .expr()
+            .mul() // Leverage * EntryPrice * IM
+            .roll(1) // PositionSize
+            .scalar()
+            .eq()
+            .verify();
+    })
+}
+
+/// Program settling a single trader order opened by [`get_trader_order_program`].
+///
+/// Expects the stack initialized from the inputs `[Memo, State]` and the outputs
+/// `[Coin, State]`, the input State carrying the committed `payment` as script
+/// data, i.e. from bottom to top:
+/// `IM, PositionSize, Leverage, EntryPrice, OrderSide, CM, TVL0, TVL1, TPS0, TPS1, payment`.
+///
+/// Drops the terms of the order and proves the same relations as
+/// [`get_batch_settle_program`] for one order.
+pub fn get_trader_settle_program() -> Program {
+    Program::build(|p| {
+        p.roll(6) // OrderSide
+            .drop()
+            .roll(6) // EntryPrice
+            .drop()
+            .roll(6) // Leverage
+            .drop()
+            .roll(6) // PositionSize
+            .drop()
+            .roll(6) // IM
+            .commit()
+            .expr()
+            .roll(6) // CM
+            .commit()
+            .expr()
+            .neg()
+            .add() // IM - CM
+            .roll(1) // payment
+            .dup(0)
+            .commit()
+            .expr()
+            .roll(2)
+            .eq() // payment == IM - CM
+            .roll(1)
+            .commit()
+            .expr()
+            .roll(1)
+            .dup(4) // TVL1
+            .commit()
+            .expr()
+            .dup(6) // TVL0
+            .commit()
+            .expr()
+            .neg()
+            .add() // TVL1 - TVL0
+            .roll(2) // payment
+            .eq() // payment == TVL1 - TVL0
+            .and()
+            .roll(1) // TPS1
+            .commit()
+            .expr()
+            .roll(2) // TPS0
+            .commit()
+            .expr()
+            .neg()
+            .add() // TPS1 - TPS0
+            .roll(2) // TVL1
+            .commit()
+            .expr()
+            .range() // TVL1 >= 0
+            .roll(3) // TVL0
+            .commit()
+            .expr()
+            .neg()
+            .add() // TVL1 - TVL0
+            .eq() // TPS1 - TPS0 == TVL1 - TVL0
+            .and()
+            .verify();
+    })
+}
+
+/// Programs deployed under the shared relayer script address.
+/// The index of a program is the position its call proof is created for.
+/// The migration program lets the States of the tree move to the script address
+/// of a later tree, which has to carry a migration program of its own.
+pub fn create_program_tree() -> Vec<Program> {
+    vec![
+        get_swap_program(),
+        get_contract_deploy_program(),
+        get_contract_migration_program(),
+        get_trader_order_program(),
+        get_trader_settle_program(),
+    ]
+}
+
+/// Hex script address of the program tree on `network`.
+pub fn program_tree_script_address(network: Network) -> String {
+    let root = MerkleTree::root(PROGRAM_TREE_LABEL, create_program_tree().iter());
+    Address::script_address(network, root.0).as_hex()
+}
+
+/// Call proof of the program at `index` of the program tree.
+pub fn create_program_call_proof(
+    index: usize,
+    network: Network,
+) -> Result<CallProof, &'static str> {
+    let hasher = Hasher::new(PROGRAM_TREE_LABEL);
+    CallProof::create_call_proof(&create_program_tree(), index, &hasher, network)
+        .ok_or("Error::Program index is out of the program tree")
+}
+
+/// Largest `out_b` paid for `in_a` that keeps the invariant of the pool.
+/// Returns None if the pool is empty or the amounts overflow.
+pub fn swap_output_amount(reserve_a: u64, reserve_b: u64, in_a: u64) -> Option<u64> {
+    if reserve_a == 0 || reserve_b == 0 {
+        return None;
+    }
+    let k = reserve_a as u128 * reserve_b as u128;
+    let new_a = reserve_a.checked_add(in_a)? as u128;
+    // round the new reserve up, so the pool never loses on rounding
+    let new_b = (k + new_a - 1) / new_a;
+    Some(reserve_b - new_b as u64)
+}
+
+/// Error term of a swap, `(R_a + in_a) * (R_b - out_b) - R_a * R_b`.
+/// Returns None if the swap decreases the invariant or the term does not fit in a u64.
+pub fn swap_error_term(reserve_a: u64, reserve_b: u64, in_a: u64, out_b: u64) -> Option<u64> {
+    let new_a = reserve_a.checked_add(in_a)? as u128;
+    let new_b = reserve_b.checked_sub(out_b)? as u128;
+    let error = (new_a * new_b).checked_sub(reserve_a as u128 * reserve_b as u128)?;
+    u64::try_from(error).ok()
+}
+
+/// Deposit Memo of a swap. The memo commitment holds `in_a` with the blinding of
+/// the spent coin and the memo data carries the committed `out_b`.
+pub fn create_swap_deposit_memo(
+    script_address: String,
+    owner: String,
+    in_a: u64,
+    blinding: Scalar,
+    out_b: u64,
+) -> Output {
+    let data = vec![ZkvmString::from(Commitment::blinded(out_b))];
+    let memo = OutputMemo::new(
+        script_address,
+        owner,
+        Commitment::blinded_with_factor(in_a, blinding),
+        Some(data),
+        0u32,
+    );
+    Output::memo(OutputData::memo(memo))
+}
+
+/// Input and output State of the pool for a swap of `in_a` against `out_b`.
+/// The error term is attached to the input State as script data.
+pub fn create_swap_states(
+    utxo: Utxo,
+    script_address: String,
+    owner: String,
+    nonce: u32,
+    reserves: (u64, u64),
+    in_a: u64,
+    out_b: u64,
+) -> Result<(Input, Output), &'static str> {
+    let (reserve_a, reserve_b) = reserves;
+    let error = swap_error_term(reserve_a, reserve_b, in_a, out_b)
+        .ok_or("Error::Swap decreases the invariant of the pool")?;
+    let in_state = OutputState {
+        nonce,
+        script_address: script_address.clone(),
+        owner: owner.clone(),
+        commitment: Commitment::blinded(reserve_a),
+        state_variables: Some(vec![ZkvmString::from(Commitment::blinded(reserve_b))]),
+        timebounds: 0,
+    };
+    let out_state = OutputState {
+        nonce: nonce + 1,
+        script_address,
+        owner,
+        commitment: Commitment::blinded(reserve_a + in_a),
+        state_variables: Some(vec![ZkvmString::from(Commitment::blinded(
+            reserve_b - out_b,
+        ))]),
+        timebounds: 0,
+    };
+    let script_data = vec![ZkvmString::from(Commitment::blinded(error))];
+    let input = Input::state(InputData::state(utxo, in_state, Some(script_data), 0));
+    Ok((input, Output::state(OutputData::state(out_state))))
+}
+
+/// Payout Coin of `out_b` to the swapper.
+pub fn create_swap_payout_coin(
+    pk: &RistrettoPublicKey,
+    network: Network,
+    out_b: u64,
+    blinding: Scalar,
+) -> Output {
+    let encrypt = ElGamalCommitment::generate_commitment(pk, blinding, Scalar::from(out_b));
+    let owner = Address::standard_address(network, *pk).as_hex();
+    Output::coin(OutputData::coin(OutputCoin::new(encrypt, owner)))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm_run::{Prover, Verifier};
+    use quisquislib::keys::{PublicKey, SecretKey};
+    use quisquislib::ristretto::RistrettoSecretKey;
+
+    // coin input of the swapper and its address
+    fn swapper_coin(in_a: u64, blinding: Scalar) -> (Input, String, RistrettoPublicKey) {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let owner = Address::standard_address(Network::default(), pk).as_hex();
+        let encrypt = ElGamalCommitment::generate_commitment(&pk, blinding, Scalar::from(in_a));
+        let coin = OutputCoin::new(encrypt, owner.clone());
+        (
+            Input::coin(InputData::coin(Utxo::default(), coin, 0)),
+            owner,
+            pk,
+        )
+    }
+
+    fn prove_swap(input: &[Input], output: &[Output]) -> bool {
+        match Prover::build_proof(get_swap_program(), input, output, false, None) {
+            Ok((bytecode, proof)) => {
+                Verifier::verify_r1cs_proof(&proof, &bytecode, input, output, false, None).is_ok()
+            }
+            Err(_) => false,
+        }
+    }
+
+    #[test]
+    fn swap_output_amount_test() {
+        let out_b = swap_output_amount(1000, 1000, 100).unwrap();
+        assert_eq!(out_b, 90);
+        assert_eq!(swap_error_term(1000, 1000, 100, out_b), Some(1000));
+        // one more unit breaks the invariant
+        assert_eq!(swap_error_term(1000, 1000, 100, out_b + 1), None);
+        assert_eq!(swap_output_amount(0, 1000, 100), None);
+    }
+
+    #[test]
+    fn swap_program_test() {
+        let mut rng = rand::thread_rng();
+        let script_address = program_tree_script_address(Network::default());
+        let (reserve_a, reserve_b, in_a) = (1_000_000u64, 500_000u64, 2_500u64);
+        let out_b = swap_output_amount(reserve_a, reserve_b, in_a).unwrap();
+
+        let blinding = Scalar::random(&mut rng);
+        let (coin, owner, pk) = swapper_coin(in_a, blinding);
+        let memo =
+            create_swap_deposit_memo(script_address.clone(), owner.clone(), in_a, blinding, out_b);
+        let (in_state, out_state) = create_swap_states(
+            Utxo::default(),
+            script_address.clone(),
+            owner,
+            1,
+            (reserve_a, reserve_b),
+            in_a,
+            out_b,
+        )
+        .unwrap();
+        assert!(prove_swap(&[coin, in_state], &[memo, out_state]));
+
+        // the payout is owned by the swapper
+        let payout =
+            create_swap_payout_coin(&pk, Network::default(), out_b, Scalar::random(&mut rng));
+        assert_eq!(
+            payout.as_out_coin().unwrap().owner,
+            Address::standard_address(Network::default(), pk).as_hex()
+        );
+
+        // the program verifies against the shared script address
+        let call_proof = create_program_call_proof(SWAP_PROGRAM_INDEX, Network::default()).unwrap();
+        let hasher = Hasher::new(PROGRAM_TREE_LABEL);
+        assert!(call_proof.verify_call_proof(script_address, &get_swap_program(), &hasher));
+    }
+
+    #[test]
+    fn swap_program_rejects_invariant_violation_test() {
+        let mut rng = rand::thread_rng();
+        let script_address = program_tree_script_address(Network::default());
+        let (reserve_a, reserve_b, in_a) = (1000u64, 1000u64, 100u64);
+        // the fair payout is 90
+        let out_b = 95u64;
+        assert!(create_swap_states(
+            Utxo::default(),
+            script_address.clone(),
+            String::new(),
+            1,
+            (reserve_a, reserve_b),
+            in_a,
+            out_b,
+        )
+        .is_err());
+
+        // build the states by hand with a zero error term
+        let blinding = Scalar::random(&mut rng);
+        let (coin, owner, _) = swapper_coin(in_a, blinding);
+        let memo =
+            create_swap_deposit_memo(script_address.clone(), owner.clone(), in_a, blinding, out_b);
+        let in_state = OutputState {
+            nonce: 1,
+            script_address: script_address.clone(),
+            owner: owner.clone(),
+            commitment: Commitment::blinded(reserve_a),
+            state_variables: Some(vec![ZkvmString::from(Commitment::blinded(reserve_b))]),
+            timebounds: 0,
+        };
+        let out_state = OutputState {
+            nonce: 2,
+            script_address,
+            owner,
+            commitment: Commitment::blinded(reserve_a + in_a),
+            state_variables: Some(vec![ZkvmString::from(Commitment::blinded(
+                reserve_b - out_b,
+            ))]),
+            timebounds: 0,
+        };
+        let script_data = vec![ZkvmString::from(Commitment::blinded(0u64))];
+        let in_state = Input::state(InputData::state(
+            Utxo::default(),
+            in_state,
+            Some(script_data),
+            0,
+        ));
+        let out_state = Output::state(OutputData::state(out_state));
+        assert!(!prove_swap(&[coin, in_state], &[memo, out_state]));
+    }
+}
@@ -2,10 +2,9 @@
 #![deny(missing_docs)]
 //! Definition of the proof struct.
 
-use bulletproofs::PedersenGens;
+use crate::QuisQuisConfig;
 use bulletproofs::RangeProof;
 use curve25519_dalek::scalar::Scalar;
-use quisquislib::shuffle::shuffle::ROWS;
 use quisquislib::{
     accounts::prover::{Prover, SigmaProof},
     accounts::verifier::Verifier,
@@ -16,6 +15,7 @@ use quisquislib::{
     ristretto::{RistrettoPublicKey, RistrettoSecretKey},
     shuffle::{Shuffle, ShuffleProof, ShuffleStatement},
 };
+use zkvm::proof_context::ProofContext;
 
 use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -82,6 +82,26 @@ pub struct ShuffleTxProof {
     pub(super) output_shuffle_proof: ShuffleProof,
     pub(super) output_shuffle_statement: ShuffleStatement,
 }
+/// Epsilon account committing to the cleartext `fee` with a zero rscalar
+pub fn fee_epsilon_account(fee: u64) -> Account {
+    let base_pk = RistrettoPublicKey::generate_base_pk();
+    let commitment =
+        ElGamalCommitment::generate_commitment(&base_pk, Scalar::zero(), Scalar::from(fee));
+    Account::set_account(base_pk, commitment)
+}
+
+/// Identity check on the epsilon accounts of a transfer paying `fee`.
+/// The rscalars of the epsilon accounts sum to zero, so adding the fee account
+/// gives the identity only if the values sum to -fee
+pub fn verify_fee_balance(epsilon_accounts: &[Account], fee: u64) -> Result<(), &'static str> {
+    let accounts: Vec<Account> = epsilon_accounts
+        .iter()
+        .cloned()
+        .chain(std::iter::once(fee_epsilon_account(fee)))
+        .collect();
+    Verifier::verify_delta_identity_check(&accounts)
+}
+
 impl DarkTxProof {
     /// Serializes the proof into a byte array
     ///
@@ -285,6 +305,13 @@ impl DarkTxProof {
         // }
     }
 
+    ///
+    /// Verify that the epsilon accounts balance to the cleartext `fee`,
+    /// i.e., sum(senders) = sum(receivers) + fee
+    pub fn verify_fee_balance(&self, fee: u64) -> Result<(), &'static str> {
+        verify_fee_balance(&self.epsilon_accounts, fee)
+    }
+
     ///
     /// Verify the DarkTx Proof
     pub fn verify(
@@ -293,10 +320,13 @@ impl DarkTxProof {
         updated_input: &[Account], //Updated_input = input in case of Dark Tx. In case of quisquis tx, it is input' from shuffle
         // Used in case of Dark tx. In case of quisquis tx, it is None. Shuffle takes care of the update
         update_output_accounts: Option<&[Account]>,
+        // cleartext fee paid by the senders
+        fee: u64,
     ) -> Result<(), &'static str> {
         let base_pk = RistrettoPublicKey::generate_base_pk();
         //identity check function to verify the construction of epsilon accounts using correct rscalars
-        Verifier::verify_delta_identity_check(&self.epsilon_accounts)?;
+        //and the balance of the values against the fee
+        self.verify_fee_balance(fee)?;
 
         // Verify the DLEQ proof for same balance value commitment in Epsilon and Delta accounts
         let delta_dleq = self.delta_dleq.clone();
@@ -481,17 +511,19 @@ impl ShuffleTxProof {
         input_shuffle: &Shuffle,
         // for output shuffle and update proof
         output_shuffle: &Shuffle,
+        // anonymity set size of the shuffled accounts
+        config: &QuisQuisConfig,
     ) -> ShuffleTxProof {
         //Step 1. create proof for Input shuffle
 
         //generate Xcomit generator points of length m+1
-        let xpc_gens = VectorPedersenGens::new(ROWS + 1);
+        let xpc_gens = VectorPedersenGens::new(config.shuffle_rows() + 1);
 
         // Prepare the constraint system
-        let pc_gens = PedersenGens::default();
+        let pc_gens = ProofContext::global().pc_gens();
 
         let (input_shuffle_proof, input_shuffle_statement) =
-            ShuffleProof::create_shuffle_proof(prover, input_shuffle, &pc_gens, &xpc_gens);
+            ShuffleProof::create_shuffle_proof(prover, input_shuffle, pc_gens, &xpc_gens);
         #[cfg(feature = "debug_print")]
         {
             println!("Input Shuffle Proof created");
@@ -522,7 +554,7 @@ impl ShuffleTxProof {
         //     );
         // }
         let (output_shuffle_proof, output_shuffle_statement) =
-            ShuffleProof::create_shuffle_proof(prover, output_shuffle, &pc_gens, &xpc_gens);
+            ShuffleProof::create_shuffle_proof(prover, output_shuffle, pc_gens, &xpc_gens);
         #[cfg(feature = "debug_print")]
         {
             println!("Output Shuffle Proof created");
@@ -548,11 +580,21 @@ impl ShuffleTxProof {
         updated_delta_accounts: &[Account],
         //anonymity_index: usize,
     ) -> Result<(), &'static str> {
+        // the shuffled account vectors have the anonymity set size
+        let set_size = updated_delta_accounts.len();
+        let config = QuisQuisConfig::new(set_size)
+            .map_err(|_| "Tx Verification failed. Anonymity set size is not supported.")?;
+        if input_accounts.len() != set_size
+            || output_accounts.len() != set_size
+            || self.input_dash_accounts.len() != set_size
+        {
+            return Err("Tx Verification failed. Shuffle account count is not valid.");
+        }
         //Recreate Pedersen Commitment (PC) Genarater and Xtended PC (XPC) Gens
         //generate Xcomit generator points of length m+1
-        let xpc_gens = VectorPedersenGens::new(ROWS + 1);
+        let xpc_gens = VectorPedersenGens::new(config.shuffle_rows() + 1);
         // Prepare the constraint system
-        let pc_gens = PedersenGens::default();
+        let pc_gens = ProofContext::global().pc_gens();
 
         //verify the input shuffle
         self.input_shuffle_proof.verify(
@@ -560,7 +602,7 @@ impl ShuffleTxProof {
             &self.input_shuffle_statement,
             &input_accounts,
             &self.input_dash_accounts,
-            &pc_gens,
+            pc_gens,
             &xpc_gens,
         )?;
         #[cfg(feature = "debug_print")]
@@ -571,9 +613,11 @@ impl ShuffleTxProof {
         let (z_vector, x) = self.updated_delta_dlog.clone().get_dlog();
         // ind the number of anonymity accounts
         let num_anonymity_accounts = z_vector.len(); // Z vector contains z for each anonymity account at the prover time
-        let anonymity_index = updated_delta_accounts.len() - num_anonymity_accounts;
-        let updated_accounts_slice = &self.input_dash_accounts[anonymity_index..9];
-        let updated_delta_accounts_slice = &updated_delta_accounts[anonymity_index..9];
+        let anonymity_index = set_size
+            .checked_sub(num_anonymity_accounts)
+            .ok_or("Tx Verification failed. Anonymity account count is not valid.")?;
+        let updated_accounts_slice = &self.input_dash_accounts[anonymity_index..set_size];
+        let updated_delta_accounts_slice = &updated_delta_accounts[anonymity_index..set_size];
 
         //verify dlog proof
         #[cfg(feature = "debug_print")]
@@ -611,7 +655,7 @@ impl ShuffleTxProof {
             &self.output_shuffle_statement,
             updated_delta_accounts,
             output_accounts,
-            &pc_gens,
+            pc_gens,
             &xpc_gens,
         )?;
         #[cfg(feature = "debug_print")]
@@ -814,12 +858,27 @@ mod test {
         // );
         // Veification in case of Dark Tx
 
-        let verify = dark_tx_proof.verify(&mut verifier, &updated_accounts, Some(&outputs));
+        let verify = dark_tx_proof.verify(&mut verifier, &updated_accounts, Some(&outputs), 0u64);
         //let verify = dark_tx_proof.verify(&mut verifier, &updated_accounts, None);
         println!("{:?}", verify);
         assert!(verify.is_ok())
     }
     #[test]
+    fn verify_fee_balance_test() {
+        let base_pk = RistrettoPublicKey::generate_base_pk();
+        let accounts: Vec<Account> = (0..2)
+            .map(|_| Account::generate_random_account_with_value(Scalar::from(10u64)).0)
+            .collect();
+        // sender pays 5 to the receiver and 2 as fee
+        let value_vector = vec![-Scalar::from(7u64), Scalar::from(5u64)];
+        let (_, epsilon_accounts, _) =
+            Account::create_delta_and_epsilon_accounts(&accounts, &value_vector, base_pk);
+
+        assert!(verify_fee_balance(&epsilon_accounts, 2).is_ok());
+        assert!(verify_fee_balance(&epsilon_accounts, 0).is_err());
+        assert!(verify_fee_balance(&epsilon_accounts, 3).is_err());
+    }
+    #[test]
     fn create_scalar_test() {
         let iin: i64 = -5;
         let uin: i64 = 5;
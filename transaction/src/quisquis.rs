@@ -0,0 +1,102 @@
+//! Size of the anonymity set of a QuisQuis transfer.
+//!
+//! The senders, receivers and anonymity accounts of a QuisQuis transfer are
+//! shuffled together as a square matrix of accounts, so the set size has to be
+//! the square of a row count the shuffle proof supports.
+
+use crate::TxError;
+use quisquislib::shuffle::shuffle::ROWS;
+use serde::{Deserialize, Serialize};
+
+/// Account count of a QuisQuis transfer unless configured otherwise.
+pub const DEFAULT_ANONYMITY_SET_SIZE: usize = ROWS * ROWS;
+
+/// Largest row count of the shuffle matrix.
+pub const MAX_SHUFFLE_ROWS: usize = 4;
+
+/// Anonymity set config of a QuisQuis transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuisQuisConfig {
+    /// Number of accounts shuffled, senders and receivers included
+    set_size: usize,
+}
+
+impl QuisQuisConfig {
+    /// Returns the config for `set_size` accounts if the shuffle proof supports it.
+    pub fn new(set_size: usize) -> Result<QuisQuisConfig, TxError> {
+        if !QuisQuisConfig::supported_sizes().contains(&set_size) {
+            return Err(TxError::UnsupportedAnonymitySetSize(set_size));
+        }
+        Ok(QuisQuisConfig { set_size })
+    }
+
+    /// Set sizes the shuffle proof supports, in increasing order.
+    pub fn supported_sizes() -> Vec<usize> {
+        (ROWS..=MAX_SHUFFLE_ROWS).map(|rows| rows * rows).collect()
+    }
+
+    pub fn set_size(&self) -> usize {
+        self.set_size
+    }
+
+    /// Row count of the shuffle matrix, the Xtended PC generators are one more.
+    pub fn shuffle_rows(&self) -> usize {
+        (ROWS..=MAX_SHUFFLE_ROWS)
+            .find(|rows| rows * rows == self.set_size)
+            .unwrap_or(ROWS)
+    }
+
+    /// Returns the number of anonymity accounts padding `senders_count` and
+    /// `receivers_count` to the set size.
+    pub fn anonymity_count(
+        &self,
+        senders_count: usize,
+        receivers_count: usize,
+    ) -> Result<usize, TxError> {
+        if senders_count >= self.set_size
+            || receivers_count >= self.set_size
+            || senders_count + receivers_count > self.set_size
+        {
+            return Err(TxError::AccountCountExceeded);
+        }
+        Ok(self.set_size - senders_count - receivers_count)
+    }
+}
+
+impl Default for QuisQuisConfig {
+    fn default() -> Self {
+        QuisQuisConfig {
+            set_size: DEFAULT_ANONYMITY_SET_SIZE,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quisquis_config_test() {
+        assert_eq!(QuisQuisConfig::default().set_size(), 9);
+        assert_eq!(QuisQuisConfig::supported_sizes(), vec![9, 16]);
+
+        let config = QuisQuisConfig::new(16).unwrap();
+        assert_eq!(config.shuffle_rows(), 4);
+        assert_eq!(config.anonymity_count(2, 3), Ok(11));
+        assert_eq!(QuisQuisConfig::new(9).unwrap().shuffle_rows(), 3);
+        assert_eq!(
+            QuisQuisConfig::default().anonymity_count(5, 5),
+            Err(TxError::AccountCountExceeded)
+        );
+
+        for size in [0, 4, 11, 25] {
+            assert_eq!(
+                QuisQuisConfig::new(size),
+                Err(TxError::UnsupportedAnonymitySetSize(size))
+            );
+        }
+    }
+}
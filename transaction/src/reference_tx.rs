@@ -1,24 +1,36 @@
 #![allow(non_snake_case)]
 //#![deny(missing_docs)]
 
-use crate::{Transaction, TransactionData, TransferTransaction};
+use crate::constants::MIN_OUTPUT_VALUE;
+use crate::programs::{
+    create_program_call_proof, get_trader_order_program, get_trader_settle_program,
+    program_tree_script_address, TRADER_ORDER_PROGRAM_INDEX, TRADER_SETTLE_PROGRAM_INDEX,
+};
+use crate::verify_relayer::deploy_relayer_contract;
+use crate::{
+    QuisQuisConfig, ScriptTransaction, ScriptTxBuilder, StateDelta, Transaction, TransactionData,
+    TransferTransaction, TxError,
+};
+use address::{Address, AddressType, Network};
 use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 
 use quisquislib::{
     accounts::Account,
-    keys::PublicKey,
+    elgamal::ElGamalCommitment,
+    keys::{PublicKey, SecretKey},
     ristretto::{RistrettoPublicKey, RistrettoSecretKey},
 };
 use rand::rngs::OsRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use sha3::Sha3_512;
+use sha3::{Digest, Keccak256, Sha3_512};
 use zkvm::merkle::Hash;
 use zkvm::zkos_types::{
     IOType, Input, InputData, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo,
 };
+use zkvm::{Commitment, ScalarWitness, String as ZkvmString};
 
 ///Needed for Creating Reference transaction for Testing RPC
 ///
@@ -27,8 +39,8 @@ use zkvm::zkos_types::{
 
 #[derive(Debug, Clone)]
 pub struct Receiver {
-    amount: i64,
-    acc: Account,
+    pub(crate) amount: i64,
+    pub(crate) acc: Account,
 }
 impl Receiver {
     pub fn set_receiver(amount: i64, acc: Account) -> Receiver {
@@ -41,6 +53,8 @@ pub struct Sender {
     total_amount: i64,
     account: Account,
     receivers: Vec<Receiver>,
+    // balance of the sender account before the transfer, if known
+    balance: Option<u64>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RecordUtxo {
@@ -50,8 +64,34 @@ pub struct RecordUtxo {
 impl Sender {
     pub fn generate_value_and_account_vector(
         tx_vector: Vec<Sender>,
-    ) -> Result<(Vec<i64>, Vec<Account>, usize, usize), &'static str> {
-        if tx_vector.len() < 9 {
+    ) -> Result<(Vec<i64>, Vec<Account>, usize, usize), TxError> {
+        Sender::generate_value_and_account_vector_for(tx_vector, &QuisQuisConfig::default())
+    }
+
+    /// Value and account vectors of the senders and receivers, which have to fit in
+    /// the anonymity set of `config`.
+    pub fn generate_value_and_account_vector_for(
+        tx_vector: Vec<Sender>,
+        config: &QuisQuisConfig,
+    ) -> Result<(Vec<i64>, Vec<Account>, usize, usize), TxError> {
+        if tx_vector.len() < config.set_size() {
+            // check that no sender pays its receivers more than it debits or holds
+            for (index, sender) in tx_vector.iter().enumerate() {
+                let sent: i64 = sender.receivers.iter().map(|r| r.amount).sum();
+                let debit = -sender.total_amount;
+                let available = match sender.balance {
+                    Some(balance) => debit.min(balance as i64),
+                    None => debit,
+                };
+                if sent > available {
+                    return Err(TxError::SenderOverspend {
+                        index,
+                        sent: sent as u64,
+                        available: available.max(0) as u64,
+                    });
+                }
+            }
+
             let mut value_vector: Vec<i64> = tx_vector.iter().map(|s| s.total_amount).collect();
             let mut account_vector: Vec<Account> = tx_vector.iter().map(|s| s.account).collect();
 
@@ -70,23 +110,22 @@ impl Sender {
                 }
             }
 
-            if senders_count < 9 && receivers_count < 9 && senders_count + receivers_count <= 9 {
-                value_vector.append(&mut receiver_amount_vector);
-                account_vector.append(&mut receiver_account_vector);
+            config.anonymity_count(senders_count, receivers_count)?;
+            value_vector.append(&mut receiver_amount_vector);
+            account_vector.append(&mut receiver_account_vector);
 
-                Ok((value_vector, account_vector, senders_count, receivers_count))
-            } else {
-                Err("senders and receivers count should be less than 9")
-            }
+            Ok((value_vector, account_vector, senders_count, receivers_count))
         } else {
-            Err("account count is more than 9")
+            Err(TxError::AccountCountExceeded)
         }
     }
 
     //create anonymous account for anonymity set
+    //pads the senders and receivers to the set size of config
     pub fn create_anonymity_set(
         senders_count: usize,
         receivers_count: usize,
+        config: &QuisQuisConfig,
     ) -> (Vec<Account>, Vec<Scalar>) {
         // let mut value_vector: Vec<i64> = Vec::new();
         let mut account_vector: Vec<Account> = Vec::new();
@@ -97,7 +136,9 @@ impl Sender {
         // lets create anonymity set - these are randomly generated on the fly
         // this anonymity set may need to come from the blockchain state itself in the future
 
-        let diff = 9 - (senders_count + receivers_count);
+        let diff = config
+            .anonymity_count(senders_count, receivers_count)
+            .unwrap_or(0);
         //use random key as base pk for annonymity accounts
         let pk_g = RISTRETTO_BASEPOINT_COMPRESSED;
         let pk_h =
@@ -161,6 +202,7 @@ impl Sender {
                     amount: 5,
                     acc: alice_account,
                 }],
+                balance: Some(10),
             },
             // //Sender {
             //     total_amount: -3,
@@ -179,16 +221,15 @@ impl Sender {
         ];
 
         let (mut value_vector, mut account_vector, sender_count, receiver_count) =
-            Sender::generate_value_and_account_vector(tx_vector)?;
+            Sender::generate_value_and_account_vector(tx_vector)
+                .map_err(|_| "Invalid sender and receiver vector")?;
         let (anonymity_account_vec, annonymity_com_scalar_vector) =
-            Sender::create_anonymity_set(sender_count, receiver_count);
+            Sender::create_anonymity_set(sender_count, receiver_count, &QuisQuisConfig::default());
 
-        let diff: usize = 9 - (sender_count + receiver_count);
-        if diff >= 1 {
-            for i in 0..diff {
-                value_vector.push(0);
-                account_vector.push(anonymity_account_vec[i].clone());
-            }
+        let diff: usize = anonymity_account_vec.len();
+        for account in anonymity_account_vec.iter() {
+            value_vector.push(0);
+            account_vector.push(account.clone());
         }
 
         //Create sender updated account vector for the verification of sk and bl-v
@@ -214,8 +255,61 @@ impl Sender {
             total_amount,
             account,
             receivers,
+            balance: None,
         }
     }
+
+    /// Creates a sender paying `receivers` out of an account holding `balance`.
+    /// The debited amount is the sum of the receiver amounts and the remainder is kept
+    /// as change in the sender account. Receiver outputs below `min_output` are rejected as dust.
+    /// Use `MIN_OUTPUT_VALUE` as the default minimum.
+    pub fn with_change(
+        balance: u64,
+        account: Account,
+        receivers: Vec<Receiver>,
+        min_output: u64,
+    ) -> Result<Sender, TxError> {
+        let mut sent: u64 = 0;
+        for (index, rec) in receivers.iter().enumerate() {
+            if rec.amount < 0 || (rec.amount as u64) < min_output {
+                return Err(TxError::DustOutput {
+                    index,
+                    amount: rec.amount.max(0) as u64,
+                    min: min_output,
+                });
+            }
+            sent = sent.saturating_add(rec.amount as u64);
+        }
+        // change can not be negative
+        if sent > balance {
+            return Err(TxError::SenderOverspend {
+                index: 0,
+                sent,
+                available: balance,
+            });
+        }
+        Ok(Sender {
+            total_amount: -(sent as i64),
+            account,
+            receivers,
+            balance: Some(balance),
+        })
+    }
+
+    /// Creates a sender with change using the default dust limit `MIN_OUTPUT_VALUE`.
+    pub fn with_default_change(
+        balance: u64,
+        account: Account,
+        receivers: Vec<Receiver>,
+    ) -> Result<Sender, TxError> {
+        Sender::with_change(balance, account, receivers, MIN_OUTPUT_VALUE)
+    }
+
+    /// Returns the sender balance after the transfer, if the initial balance is known.
+    pub fn updated_balance(&self) -> Option<u64> {
+        self.balance
+            .map(|balance| (balance as i64 + self.total_amount) as u64)
+    }
 }
 
 pub fn create_qq_reference_transaction() -> Transaction {
@@ -230,22 +324,7 @@ pub fn create_qq_reference_transaction() -> Transaction {
         updated_sender_balance,
     ) = Sender::create_reference_tx_data_for_zkos_test().unwrap();
     //create vector of inputs to be used in tx
-    //random utxo IDS to be used in Inputs
-
-    let hash: Hash = Hash::default();
-    //SHOULD BE TAKEN FROM UTXO SET
-    let utxo = Utxo::from_hash(hash, 0);
-    let utxo_vector: Vec<Utxo> = vec![
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-        utxo.clone(),
-    ];
+    //random utxo IDS to be used in Inputs, the default Utxo marks a fresh account
 
     let updated_balance_reciever: Vec<u64> = vec![5]; //, 2, 1];
     println!("Data : {:?}", sender_count);
@@ -254,7 +333,7 @@ pub fn create_qq_reference_transaction() -> Transaction {
     let mut inputs: Vec<Input> = Vec::new();
     for inp in account_vector.iter() {
         let input =
-            Input::input_from_quisquis_account(inp, utxo_vector[0], 0, address::Network::default());
+            Input::input_from_quisquis_account(inp, Utxo::random(), 0, address::Network::default());
         inputs.push(input.clone());
     }
     // create quisquis transfer transaction
@@ -271,6 +350,7 @@ pub fn create_qq_reference_transaction() -> Transaction {
         diff,
         None,
         0u64,
+        &QuisQuisConfig::default(),
     );
 
     Transaction::transaction_transfer(TransactionData::TransactionTransfer(transfer.unwrap()))
@@ -294,9 +374,7 @@ pub fn create_dark_reference_transaction() -> Transaction {
     let accounts: &[Account] = &account_vector[..(sender_count + receiver_count)];
     let values: &[i64] = &value_vector[..(sender_count + receiver_count)];
 
-    let hash: Hash = Hash::default();
-    //SHOULD BE TAKEN FROM UTXO SET
-    let utxo = Utxo::from_hash(hash, 0);
+    //random utxo IDS to be used in Inputs, the default Utxo marks a fresh account
     //create vec of Inouts
     let mut inputs: Vec<Input> = Vec::new();
     for input in accounts.iter() {
@@ -307,10 +385,11 @@ pub fn create_dark_reference_transaction() -> Transaction {
             owner: address::Address::standard_address(address::Network::Mainnet, pk).as_hex(),
         };
         let inp = Input::coin(InputData::coin(
-            utxo,
+            Utxo::random(),
             // address::Address::coin_address(address::Network::Mainnet, pk).as_hex(),
             //  enc,
-            out_coin, 0,
+            out_coin,
+            0,
         ));
         inputs.push(inp.clone());
     }
@@ -512,6 +591,7 @@ pub fn create_dark_reference_tx_for_utxo_test(
                 acc: out_acc_2,
             },
         ],
+        balance: None,
     }];
 
     let (value_vector, account_vector, sender_count, receiver_count) =
@@ -551,6 +631,248 @@ pub fn create_dark_reference_tx_for_utxo_test(
     Transaction::transaction_transfer(TransactionData::TransactionTransfer(transfer))
 }
 
+/// Pool TVL and pool share of the State deployed for a reference order.
+pub const REFERENCE_POOL_VALUE: u64 = 1_000_000;
+
+/// Reference trader order and the secrets needed to settle it.
+///
+/// The order is opened against a pool State deployed by `pool_deploy`, which has to
+/// be applied before `tx`. The utxos of the order Memo and of the pool State are
+/// derived from [`reference_tx_id`] of their transactions.
+#[derive(Clone)]
+pub struct ScriptReference {
+    /// Transaction opening the order, spending a coin into the order Memo
+    pub tx: Transaction,
+    /// Transaction deploying the pool State
+    pub pool_deploy: Transaction,
+    /// Coins spent by `pool_deploy` and `tx`, to seed a utxo set with
+    pub funding: Vec<RecordUtxo>,
+    pub trader_sk: RistrettoSecretKey,
+    pub relayer_sk: RistrettoSecretKey,
+    /// Blinding of the trader coin and of the initial margin of the order
+    pub scalar: Scalar,
+    pub initial_margin: u64,
+    pub leverage: u64,
+    pub entry_price: u64,
+    /// Prover view of the order Memo, output 0 of `tx`
+    pub memo: Output,
+    pub memo_utxo: Utxo,
+    /// Prover view of the pool State, output 1 of `pool_deploy`
+    pub pool_state: Output,
+    pub pool_utxo: Utxo,
+}
+
+/// Reference settlement of a [`ScriptReference`] order.
+#[derive(Clone)]
+pub struct ScriptSettlement {
+    /// Transaction spending the order Memo and the pool State
+    pub tx: Transaction,
+    /// Blinding of the payout Coin
+    pub blinding: Scalar,
+    /// Payout Coin of the trader, output 0 of `tx`
+    pub payout: Output,
+    /// Prover view of the settled pool State, output 1 of `tx`
+    pub pool_state: Output,
+    pub pool_utxo: Utxo,
+}
+
+/// Id the transaction api assigns to `tx`, the Keccak256 hash of its encoding.
+pub fn reference_tx_id(tx: &Transaction) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(&tx.to_bytes());
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&hasher.finalize());
+    id
+}
+
+// random key and its standard address on `network`
+fn reference_key(network: Network) -> (RistrettoSecretKey, RistrettoPublicKey, String) {
+    let mut rng = rand::thread_rng();
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    let owner = Address::standard_address(network, pk).as_hex();
+    (sk, pk, owner)
+}
+
+// coin of `value` owned by `pk` at a random utxo
+fn reference_coin(pk: &RistrettoPublicKey, owner: &str, value: u64, scalar: Scalar) -> RecordUtxo {
+    let mut id: [u8; 32] = [0; 32];
+    rand::thread_rng().fill(&mut id);
+    let encrypt = ElGamalCommitment::generate_commitment(pk, scalar, Scalar::from(value));
+    RecordUtxo {
+        utx: Utxo::from_hash(Hash(id), 0),
+        value: Output::coin(OutputData::coin(OutputCoin::new(
+            encrypt,
+            owner.to_string(),
+        ))),
+    }
+}
+
+/// Trade order script transaction with the terms of the trader order example.
+pub fn create_script_reference_transaction() -> ScriptReference {
+    create_script_reference_transaction_with(7000, 10, 10000)
+        .expect("the reference order terms are valid")
+}
+
+/// Trade order script transaction locking an initial margin of `value` at
+/// `leverage` and `entry_price`.
+///
+/// A coin of `value` is spent into the order Memo of the program tree, carrying
+/// `[position_size, leverage, entry_price, order_side]` as data, and the trader
+/// order program proves `value * entry_price * leverage == position_size`.
+pub fn create_script_reference_transaction_with(
+    value: u64,
+    leverage: u64,
+    entry_price: u64,
+) -> Result<ScriptReference, &'static str> {
+    if value < MIN_OUTPUT_VALUE || leverage == 0 || entry_price == 0 {
+        return Err("Error::Order terms must not be zero");
+    }
+    let position_size = value
+        .checked_mul(leverage)
+        .and_then(|size| size.checked_mul(entry_price))
+        .ok_or("Error::Position size overflows")?;
+    let network = Network::default();
+    let script_address = program_tree_script_address(network);
+
+    let (relayer_sk, relayer_pk, relayer) = reference_key(network);
+    let pool_scalar = Scalar::random(&mut rand::thread_rng());
+    let pool_coin = reference_coin(&relayer_pk, &relayer, REFERENCE_POOL_VALUE, pool_scalar);
+    let (pool_deploy, _, pool_state) = deploy_relayer_contract(
+        &relayer_sk,
+        pool_coin.utx,
+        &relayer,
+        REFERENCE_POOL_VALUE,
+        pool_scalar,
+        REFERENCE_POOL_VALUE,
+        0,
+    )?;
+
+    let (trader_sk, trader_pk, trader) = reference_key(network);
+    let scalar = Scalar::random(&mut rand::thread_rng());
+    let trader_coin = reference_coin(&trader_pk, &trader, value, scalar);
+    let data = vec![
+        ZkvmString::from(Scalar::from(position_size)),
+        ZkvmString::from(Commitment::blinded(leverage)),
+        ZkvmString::from(Scalar::from(entry_price)),
+        // long
+        ZkvmString::from(Scalar::from(1u64)),
+    ];
+    let memo = Output::memo(OutputData::memo(OutputMemo::new(
+        script_address,
+        trader,
+        Commitment::blinded_with_factor(value, scalar),
+        Some(data),
+        0u32,
+    )));
+    let call_proof = create_program_call_proof(TRADER_ORDER_PROGRAM_INDEX, network)?;
+    let tx = ScriptTransaction::create_script_transaction(
+        &[trader_sk.clone()],
+        get_trader_order_program(),
+        call_proof,
+        &[convert_output_to_input(trader_coin.clone()).ok_or("Error::Invalid coin")?],
+        &[memo.clone()],
+        None,
+        false,
+        0,
+    )
+    .map_err(|_| "Error::Trade order proof can not be created")?;
+    let tx = Transaction::from(tx);
+
+    Ok(ScriptReference {
+        memo_utxo: Utxo::from_hash(Hash(reference_tx_id(&tx)), 0),
+        pool_utxo: Utxo::from_hash(Hash(reference_tx_id(&pool_deploy)), 1),
+        tx,
+        pool_deploy,
+        funding: vec![pool_coin, trader_coin],
+        trader_sk,
+        relayer_sk,
+        scalar,
+        initial_margin: value,
+        leverage,
+        entry_price,
+        memo,
+        pool_state,
+    })
+}
+
+/// Settles the order of `prior` at break-even, paying the initial margin back.
+pub fn create_settlement_reference_transaction(
+    prior: &ScriptReference,
+) -> Result<ScriptSettlement, &'static str> {
+    create_settlement_reference_transaction_with(prior, prior.initial_margin)
+}
+
+/// Settles the order of `prior` by paying `payout` to the trader from its pool.
+///
+/// The order Memo and the pool State are spent into the payout Coin and the pool
+/// State moved by `initial_margin - payout`, and the trader settle program proves
+/// the pool and the payout balance.
+pub fn create_settlement_reference_transaction_with(
+    prior: &ScriptReference,
+    payout: u64,
+) -> Result<ScriptSettlement, &'static str> {
+    let order = prior
+        .memo
+        .as_out_memo()
+        .ok_or("Error::Order is not a Memo")?;
+    let address = Address::from_hex(&order.owner, AddressType::Standard)?;
+    let network = address.get_standard_address()?.network;
+    let pk: RistrettoPublicKey = address.into();
+
+    let blinding = Scalar::random(&mut rand::thread_rng());
+    let encrypt = ElGamalCommitment::generate_commitment(&pk, blinding, Scalar::from(payout));
+    let coin_value = Commitment::blinded_with_factor(payout, blinding);
+    let update = StateDelta {
+        order: Input::memo(InputData::memo(
+            prior.memo_utxo,
+            order.clone(),
+            0,
+            Some(coin_value),
+        )),
+        payout: Output::coin(OutputData::coin(OutputCoin::new(
+            encrypt,
+            order.owner.clone(),
+        ))),
+        payment: Commitment::blinded(
+            ScalarWitness::from(prior.initial_margin) + -ScalarWitness::from(payout),
+        ),
+    };
+    let pool_state = prior
+        .pool_state
+        .as_out_state()
+        .ok_or("Error::Pool is not a State")?
+        .clone();
+    let in_state = Input::state(InputData::state(prior.pool_utxo, pool_state, None, 0));
+    // the builder moves the State by the payment, the order carries its own program
+    let builder = ScriptTxBuilder::batch_state_updates(in_state, vec![update])?;
+
+    let call_proof = create_program_call_proof(TRADER_SETTLE_PROGRAM_INDEX, network)?;
+    let tx = ScriptTransaction::create_script_transaction(
+        &[prior.trader_sk.clone(), prior.relayer_sk.clone()],
+        get_trader_settle_program(),
+        call_proof,
+        builder.inputs(),
+        builder.outputs(),
+        None,
+        false,
+        0,
+    )
+    .map_err(|_| "Error::Settlement proof can not be created")?;
+    let tx = Transaction::from(tx);
+
+    Ok(ScriptSettlement {
+        pool_utxo: Utxo::from_hash(Hash(reference_tx_id(&tx)), 1),
+        tx,
+        blinding,
+        payout: builder.outputs()[0].clone(),
+        pool_state: builder
+            .out_state()
+            .ok_or("Error::Settlement has no State")?
+            .clone(),
+    })
+}
+
 // ------------------------------------------------------------------------
 // Tests
 // ------------------------------------------------------------------------
@@ -580,10 +902,140 @@ mod test {
         println!("{:?}", verify_qq);
     }
     #[test]
+    fn script_reference_transaction_test() {
+        let order = create_script_reference_transaction();
+        assert!(order.pool_deploy.verify().is_ok());
+        assert!(order.tx.verify().is_ok());
+        let memo = order.memo.as_out_memo().unwrap();
+        assert_eq!(
+            memo.owner,
+            order.funding[1].value.as_out_coin().unwrap().owner
+        );
+        assert_eq!(memo.data.as_ref().unwrap().len(), 4);
+
+        let settlement = create_settlement_reference_transaction(&order).unwrap();
+        assert!(settlement.tx.verify().is_ok());
+        // the pool pays the gain of the trader
+        let settlement = create_settlement_reference_transaction_with(&order, 9000).unwrap();
+        assert!(settlement.tx.verify().is_ok());
+        assert_eq!(
+            settlement
+                .pool_state
+                .as_out_state()
+                .unwrap()
+                .commitment
+                .assignment()
+                .unwrap()
+                .to_scalar(),
+            Scalar::from(REFERENCE_POOL_VALUE - 2000)
+        );
+        // a payout beyond the pool does not verify
+        assert!(
+            create_settlement_reference_transaction_with(&order, REFERENCE_POOL_VALUE + 7001)
+                .map_or(true, |settlement| settlement.tx.verify().is_err())
+        );
+    }
+    #[test]
+    fn script_reference_transaction_terms_test() {
+        let order = create_script_reference_transaction_with(100, 5, 20).unwrap();
+        assert!(order.tx.verify().is_ok());
+        assert!(create_script_reference_transaction_with(0, 5, 20).is_err());
+        assert!(create_script_reference_transaction_with(u64::MAX, 2, 1).is_err());
+    }
+    #[test]
     fn create_genesis_block_test() {
         //create base test account
         let (acc, _prv) = Account::generate_random_account_with_value(Scalar::from(20u64));
         let utxo_set = create_genesis_block(1000, 100, acc);
         println!("{:?}", utxo_set);
     }
+    #[test]
+    fn sender_with_change_exact_spend_test() {
+        let (bob, _) = Account::generate_random_account_with_value(10u64.into());
+        let (alice, _) = Account::generate_random_account_with_value(0u64.into());
+        let sender =
+            Sender::with_default_change(10, bob, vec![Receiver::set_receiver(10, alice)]).unwrap();
+        assert_eq!(sender.updated_balance(), Some(0));
+
+        let (value_vector, _, senders_count, receivers_count) =
+            Sender::generate_value_and_account_vector(vec![sender]).unwrap();
+        assert_eq!(value_vector, vec![-10, 10]);
+        assert_eq!((senders_count, receivers_count), (1, 1));
+    }
+    #[test]
+    fn sender_with_change_partial_spend_test() {
+        let (bob, _) = Account::generate_random_account_with_value(10u64.into());
+        let (alice, _) = Account::generate_random_account_with_value(0u64.into());
+        let (fay, _) = Account::generate_random_account_with_value(0u64.into());
+        let sender = Sender::with_default_change(
+            10,
+            bob,
+            vec![
+                Receiver::set_receiver(3, alice),
+                Receiver::set_receiver(2, fay),
+            ],
+        )
+        .unwrap();
+        assert_eq!(sender.updated_balance(), Some(5));
+
+        let (value_vector, _, _, receivers_count) =
+            Sender::generate_value_and_account_vector(vec![sender]).unwrap();
+        assert_eq!(value_vector, vec![-5, 3, 2]);
+        assert_eq!(receivers_count, 2);
+    }
+    #[test]
+    fn sender_with_change_overspend_test() {
+        let (bob, _) = Account::generate_random_account_with_value(10u64.into());
+        let (alice, _) = Account::generate_random_account_with_value(0u64.into());
+        let err = Sender::with_default_change(10, bob, vec![Receiver::set_receiver(11, alice)])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            TxError::SenderOverspend {
+                index: 0,
+                sent: 11,
+                available: 10
+            }
+        );
+
+        // second sender debits less than it pays out
+        let (fay, _) = Account::generate_random_account_with_value(5u64.into());
+        let (jay, _) = Account::generate_random_account_with_value(0u64.into());
+        let ok_sender = Sender::set_sender(-5, bob, vec![Receiver::set_receiver(5, alice)]);
+        let bad_sender = Sender::set_sender(-3, fay, vec![Receiver::set_receiver(4, jay)]);
+        let err =
+            Sender::generate_value_and_account_vector(vec![ok_sender, bad_sender]).unwrap_err();
+        assert_eq!(
+            err,
+            TxError::SenderOverspend {
+                index: 1,
+                sent: 4,
+                available: 3
+            }
+        );
+    }
+    #[test]
+    fn sender_with_change_dust_test() {
+        let (bob, _) = Account::generate_random_account_with_value(10u64.into());
+        let (alice, _) = Account::generate_random_account_with_value(0u64.into());
+        let (fay, _) = Account::generate_random_account_with_value(0u64.into());
+        let err = Sender::with_change(
+            10,
+            bob,
+            vec![
+                Receiver::set_receiver(5, alice),
+                Receiver::set_receiver(1, fay),
+            ],
+            2,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            TxError::DustOutput {
+                index: 1,
+                amount: 1,
+                min: 2
+            }
+        );
+    }
 }
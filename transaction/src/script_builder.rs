@@ -0,0 +1,393 @@
+//! Batched updates of a relayer pool State.
+//!
+//! Settling an order moves the TVL (State value) and the TPS (state variable)
+//! of the pool by the order payment. Instead of one transaction and one nonce
+//! bump per order, [`ScriptTxBuilder::batch_state_updates`] folds the committed
+//! payments of many orders into a single output State and runs
+//! [`get_batch_settle_program`] once over all of them.
+
+use crate::constants::OutputDataLimits;
+use crate::programs::get_batch_settle_program;
+use crate::transaction::check_output_data;
+use crate::vm_run::Prover;
+use address::{Address, AddressType};
+use bulletproofs::r1cs::R1CSProof;
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::ristretto::RistrettoPublicKey;
+use zkvm::zkos_types::{Input, InputData, Output, OutputCoin, OutputData, OutputMemo, Utxo};
+use zkvm::{Commitment, Program, ScalarWitness, String as ZkvmString};
+
+/// Settlement of one trader order against the pool.
+#[derive(Debug, Clone)]
+pub struct StateDelta {
+    /// Memo of the order, spent with the committed payout as coin value
+    pub order: Input,
+    /// Coin paying the payout to the trader
+    pub payout: Output,
+    /// Committed change of the pool, `IM - payout`. Negative if the trader gained
+    pub payment: Commitment,
+}
+
+impl StateDelta {
+    /// Settles the order Memo at `utxo`, whose data holds the open initial
+    /// margin `IM`, by paying `payout` to its owner.
+    /// `blinding` encrypts the payout Coin and blinds its coin value commitment.
+    pub fn settle_order(
+        utxo: Utxo,
+        order: OutputMemo,
+        payout: u64,
+        blinding: Scalar,
+    ) -> Result<StateDelta, &'static str> {
+        let initial_margin = order
+            .data
+            .as_ref()
+            .and_then(|data| data.first())
+            .ok_or("Error::Order memo does not carry an initial margin")?
+            .as_commitment()
+            .map_err(|_| "Error::Initial margin of the order memo is not a commitment")?
+            .assignment()
+            .ok_or("Error::Initial margin of the order memo is not open")?;
+        let address = Address::from_hex(&order.owner, AddressType::Standard)?;
+        let pk: RistrettoPublicKey = address.into();
+
+        let encrypt = ElGamalCommitment::generate_commitment(&pk, blinding, Scalar::from(payout));
+        let coin = OutputCoin::new(encrypt, order.owner.clone());
+        let coin_value = Commitment::blinded_with_factor(payout, blinding);
+        Ok(StateDelta {
+            order: Input::memo(InputData::memo(utxo, order, 0, Some(coin_value))),
+            payout: Output::coin(OutputData::coin(coin)),
+            payment: Commitment::blinded(initial_margin + -ScalarWitness::from(payout)),
+        })
+    }
+}
+
+/// Inputs, outputs and program of a script transaction.
+#[derive(Debug, Clone)]
+pub struct ScriptTxBuilder {
+    program: Program,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    tx_data: Option<ZkvmString>,
+}
+
+impl ScriptTxBuilder {
+    /// Settles all `updates` against the pool State `in_state` in one execution.
+    ///
+    /// The payments are added homomorphically to the value and the first state
+    /// variable of the State, which moves to the next nonce once. The inputs are
+    /// the order Memos followed by `in_state`, carrying the payments as script
+    /// data, and the outputs one payout Coin per order followed by the new State.
+    /// The commitments of `in_state` must be open.
+    pub fn batch_state_updates(
+        in_state: Input,
+        updates: Vec<StateDelta>,
+    ) -> Result<ScriptTxBuilder, &'static str> {
+        if updates.is_empty() {
+            return Err("Error::No state updates to batch");
+        }
+        let utxo = in_state
+            .as_utxo()
+            .ok_or("Error::Input is not a State")?
+            .clone();
+        let state = in_state
+            .as_out_state()
+            .ok_or("Error::Input is not a State")?
+            .clone();
+        let tps = state
+            .state_variables
+            .as_ref()
+            .and_then(|vars| vars.first())
+            .ok_or("Error::State does not carry a state variable")?
+            .as_commitment()
+            .map_err(|_| "Error::State variable of the pool is not a commitment")?
+            .clone();
+
+        let mut out_state = state.clone();
+        out_state.nonce = state.nonce + 1;
+        let mut out_tps = tps;
+        for update in updates.iter() {
+            out_state.commitment = fold_commitment(&out_state.commitment, &update.payment)?;
+            out_tps = fold_commitment(&out_tps, &update.payment)?;
+        }
+        if let Some(vars) = out_state.state_variables.as_mut() {
+            vars[0] = ZkvmString::from(out_tps);
+        }
+
+        let payments = updates
+            .iter()
+            .map(|update| ZkvmString::from(update.payment.clone()))
+            .collect();
+        let mut inputs: Vec<Input> = updates.iter().map(|update| update.order.clone()).collect();
+        inputs.push(Input::state(InputData::state(
+            utxo,
+            state,
+            Some(payments),
+            in_state.get_witness_index(),
+        )));
+        let mut outputs: Vec<Output> = updates.iter().map(|update| update.payout.clone()).collect();
+        outputs.push(Output::state(OutputData::state(out_state)));
+        // nodes reject outputs carrying more data than the limits
+        check_output_data(&outputs, &OutputDataLimits::default())
+            .map_err(|_| "Error::Output data exceeds the size limits")?;
+
+        Ok(ScriptTxBuilder {
+            program: get_batch_settle_program(updates.len()),
+            inputs,
+            outputs,
+            tx_data: None,
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// Output State of the pool, the last output.
+    pub fn out_state(&self) -> Option<&Output> {
+        self.outputs.last()
+    }
+
+    /// Runs the program once over all inputs and outputs.
+    /// Returns the program bytecode and the r1cs proof.
+    pub fn prove(&self) -> Result<(Vec<u8>, R1CSProof), &'static str> {
+        Prover::build_proof(
+            self.program.clone(),
+            &self.inputs,
+            &self.outputs,
+            false,
+            self.tx_data.clone(),
+        )
+        .map_err(|_| "Error::Batched state update proof can not be created")
+    }
+}
+
+/// Adds two open commitments, the sum commits to the sum of the values with
+/// the sum of the blindings.
+fn fold_commitment(a: &Commitment, b: &Commitment) -> Result<Commitment, &'static str> {
+    match (a.witness(), b.witness()) {
+        (Some((a_value, a_blinding)), Some((b_value, b_blinding))) => Ok(
+            Commitment::blinded_with_factor(a_value + b_value, a_blinding + b_blinding),
+        ),
+        _ => Err("Error::Commitments must be open to fold the state updates"),
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm_run::Verifier;
+    use address::Network;
+    use curve25519_dalek::ristretto::CompressedRistretto;
+    use quisquislib::keys::{PublicKey, SecretKey};
+    use quisquislib::ristretto::RistrettoSecretKey;
+    use zkvm::zkos_types::OutputState;
+
+    // (initial margin, payout) of the settled orders, gains and losses of the traders
+    const ORDERS: [(u64, u64); 5] = [(10, 6), (20, 25), (8, 0), (15, 15), (30, 41)];
+
+    fn order(script_address: &str, initial_margin: u64) -> OutputMemo {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        OutputMemo::new(
+            script_address.to_string(),
+            Address::standard_address(Network::default(), pk).as_hex(),
+            Commitment::blinded(initial_margin),
+            Some(vec![ZkvmString::from(Commitment::blinded(initial_margin))]),
+            0u32,
+        )
+    }
+
+    fn settlements(script_address: &str) -> Vec<StateDelta> {
+        ORDERS
+            .iter()
+            .map(|(initial_margin, payout)| {
+                StateDelta::settle_order(
+                    Utxo::default(),
+                    order(script_address, *initial_margin),
+                    *payout,
+                    Scalar::random(&mut rand::thread_rng()),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn pool_state(script_address: &str, tvl: u64, tps: u64) -> Input {
+        let state = OutputState {
+            nonce: 1,
+            script_address: script_address.to_string(),
+            owner: String::new(),
+            commitment: Commitment::blinded(tvl),
+            state_variables: Some(vec![ZkvmString::from(Commitment::blinded(tps))]),
+            timebounds: 0,
+        };
+        Input::state(InputData::state(Utxo::default(), state, None, 0))
+    }
+
+    fn verify(builder: &ScriptTxBuilder) -> bool {
+        match builder.prove() {
+            Ok((bytecode, proof)) => Verifier::verify_r1cs_proof(
+                &proof,
+                &bytecode,
+                builder.inputs(),
+                builder.outputs(),
+                false,
+                None,
+            )
+            .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // points of the value and the state variable of a State output
+    fn state_points(state: &Output) -> (u32, CompressedRistretto, CompressedRistretto) {
+        let state = state.as_out_state().unwrap();
+        let tps = state.state_variables.as_ref().unwrap()[0]
+            .clone()
+            .to_commitment()
+            .unwrap();
+        (state.nonce, state.commitment.to_point(), tps.to_point())
+    }
+
+    #[test]
+    fn batch_state_updates_test() {
+        let script_address = Address::script_address(Network::default(), [7u8; 32]).as_hex();
+        let updates = settlements(&script_address);
+        let builder =
+            ScriptTxBuilder::batch_state_updates(pool_state(&script_address, 1000, 1000), updates)
+                .unwrap();
+        assert_eq!(builder.inputs().len(), 6);
+        assert_eq!(builder.outputs().len(), 6);
+        assert!(builder.outputs()[..5]
+            .iter()
+            .all(|out| out.as_out_coin().is_some()));
+
+        // the pool lost 4 on the orders
+        let out_state = builder.out_state().unwrap().as_out_state().unwrap();
+        assert_eq!(out_state.nonce, 2);
+        assert_eq!(
+            out_state.commitment.assignment().unwrap().to_scalar(),
+            Scalar::from(996u64)
+        );
+        assert!(verify(&builder));
+    }
+
+    #[test]
+    fn batch_matches_sequential_updates_test() {
+        let script_address = Address::script_address(Network::default(), [7u8; 32]).as_hex();
+        let updates = settlements(&script_address);
+        let in_state = pool_state(&script_address, 1000, 1000);
+        let batch =
+            ScriptTxBuilder::batch_state_updates(in_state.clone(), updates.clone()).unwrap();
+        assert!(verify(&batch));
+
+        // one transaction per order, each spending the state of the previous one
+        let mut state = in_state;
+        for update in updates {
+            let single = ScriptTxBuilder::batch_state_updates(state, vec![update]).unwrap();
+            assert!(verify(&single));
+            let out_state = single.out_state().unwrap().as_out_state().unwrap().clone();
+            state = Input::state(InputData::state(Utxo::default(), out_state, None, 0));
+        }
+        let sequential = Output::state(OutputData::state(state.as_out_state().unwrap().clone()));
+
+        let (batch_nonce, batch_tvl, batch_tps) = state_points(batch.out_state().unwrap());
+        let (sequential_nonce, sequential_tvl, sequential_tps) = state_points(&sequential);
+        assert_eq!(batch_tvl, sequential_tvl);
+        assert_eq!(batch_tps, sequential_tps);
+        // a single nonce bump instead of five
+        assert_eq!(batch_nonce, 2);
+        assert_eq!(sequential_nonce, 6);
+    }
+
+    #[test]
+    fn settle_rejects_wrong_kind_data_test() {
+        let script_address = Address::script_address(Network::default(), [7u8; 32]).as_hex();
+        let blinding = Scalar::random(&mut rand::thread_rng());
+
+        // a scalar where the committed initial margin is expected
+        let mut memo = order(&script_address, 10);
+        memo.set_data(vec![ZkvmString::from(10u64)]);
+        let err = StateDelta::settle_order(Utxo::default(), memo, 6, blinding).unwrap_err();
+        assert_eq!(
+            err,
+            "Error::Initial margin of the order memo is not a commitment"
+        );
+
+        // the verifier view of the memo only carries the closed commitment
+        let memo = order(&script_address, 10).verifier_view();
+        let err = StateDelta::settle_order(Utxo::default(), memo, 6, blinding).unwrap_err();
+        assert_eq!(err, "Error::Initial margin of the order memo is not open");
+
+        let mut in_state = pool_state(&script_address, 1000, 1000);
+        if let InputData::State { out_state, .. } = &mut in_state.input {
+            out_state.state_variables = Some(vec![ZkvmString::U64(1000)]);
+        }
+        let err = ScriptTxBuilder::batch_state_updates(in_state, settlements(&script_address))
+            .unwrap_err();
+        assert_eq!(err, "Error::State variable of the pool is not a commitment");
+    }
+
+    #[test]
+    fn batch_rejects_oversize_state_test() {
+        let script_address = Address::script_address(Network::default(), [7u8; 32]).as_hex();
+        let limits = OutputDataLimits::default();
+
+        // the state variables of the pool are carried to the output State
+        let mut in_state = pool_state(&script_address, 1000, 1000);
+        if let InputData::State { out_state, .. } = &mut in_state.input {
+            let vars = out_state.state_variables.as_mut().unwrap();
+            vars.resize(limits.max_elements, ZkvmString::U64(0));
+        }
+        assert!(ScriptTxBuilder::batch_state_updates(
+            in_state.clone(),
+            settlements(&script_address)
+        )
+        .is_ok());
+
+        if let InputData::State { out_state, .. } = &mut in_state.input {
+            out_state
+                .state_variables
+                .as_mut()
+                .unwrap()
+                .push(ZkvmString::U64(0));
+        }
+        let err = ScriptTxBuilder::batch_state_updates(in_state, settlements(&script_address))
+            .unwrap_err();
+        assert_eq!(err, "Error::Output data exceeds the size limits");
+    }
+
+    #[test]
+    fn batch_rejects_misassigned_payment_test() {
+        let script_address = Address::script_address(Network::default(), [7u8; 32]).as_hex();
+        let updates = settlements(&script_address);
+        let mut builder =
+            ScriptTxBuilder::batch_state_updates(pool_state(&script_address, 1000, 1000), updates)
+                .unwrap();
+
+        // swapping two payments keeps the total but breaks both orders
+        let state = builder.inputs.pop().unwrap();
+        let mut payments = state.input.as_state_script_data().unwrap().clone();
+        payments.swap(0, 1);
+        builder.inputs.push(Input::state(InputData::state(
+            Utxo::default(),
+            state.as_out_state().unwrap().clone(),
+            Some(payments),
+            0,
+        )));
+        assert!(!verify(&builder));
+    }
+}
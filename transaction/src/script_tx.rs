@@ -24,10 +24,21 @@ use curve25519_dalek::scalar::Scalar;
 // use std::fmt;
 use zkschnorr::{Signature, VerificationKey};
 use zkvm::merkle::CallProof; //, Hash, MerkleItem, MerkleTree};
+use zkvm::proof_context::ProofContext;
+use zkvm::VMError;
+
+use crate::constants::{CONTRACT_MAX_SIZE, MAX_PROGRAMS};
+use crate::oracle::{DataSignature, OracleConfig};
+use crate::order_lifecycle::verify_settlement_payout;
+use crate::errors::{check_header_count, TxError};
+use crate::vm_run::{join, map_enumerated, ProverOpts};
+use crate::witness::{check_witness_references, input_witness};
 
 ///
 /// Store for TransactionScript
+/// Deserialization fails if the header counts do not match the vectors
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedScriptTransaction")]
 pub struct ScriptTransaction {
     //transaction header
     pub(crate) version: u64,
@@ -35,8 +46,8 @@ pub struct ScriptTransaction {
     pub(crate) maturity: u64,
 
     //lengths of vectors to come
-    pub(crate) input_count: u8,
-    pub(crate) output_count: u8,
+    pub(crate) input_count: u16,
+    pub(crate) output_count: u16,
     pub(crate) witness_count: u8,
 
     //List of inputs and outputs
@@ -55,18 +66,70 @@ pub struct ScriptTransaction {
     pub(crate) witness: Vec<Witness>,
     // Transaction data. e.g., supporting data needed for a script transaction at the top level.
     pub(crate) tx_data: Option<zkvm::String>,
+
+    //Programs executed after `program`, in order, each with its own Call Proof
+    //`program` and `call_proof` hold the first program of the sequence
+    //Part of the layout of every versioned encoding, the single program layout has no version
+    pub(crate) program_bundle: Vec<(Vec<u8>, CallProof)>,
+
+    //Oracle signature over `tx_data`, required by price sensitive programs
+    //Part of the layout since version 2, txs encoded without it are rejected by their version
+    pub(crate) data_signature: Option<DataSignature>,
+}
+
+// Wire format of ScriptTransaction, before the header counts are checked
+#[derive(Deserialize)]
+struct UncheckedScriptTransaction {
+    version: u64,
+    fee: u64,
+    maturity: u64,
+    input_count: u16,
+    output_count: u16,
+    witness_count: u8,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    program: Vec<u8>,
+    call_proof: CallProof,
+    proof: R1CSProof,
+    witness: Vec<Witness>,
+    tx_data: Option<zkvm::String>,
+    program_bundle: Vec<(Vec<u8>, CallProof)>,
+    data_signature: Option<DataSignature>,
+}
+
+impl TryFrom<UncheckedScriptTransaction> for ScriptTransaction {
+    type Error = TxError;
+
+    fn try_from(tx: UncheckedScriptTransaction) -> Result<Self, Self::Error> {
+        let tx = ScriptTransaction {
+            version: tx.version,
+            fee: tx.fee,
+            maturity: tx.maturity,
+            input_count: tx.input_count,
+            output_count: tx.output_count,
+            witness_count: tx.witness_count,
+            inputs: tx.inputs,
+            outputs: tx.outputs,
+            program: tx.program,
+            call_proof: tx.call_proof,
+            proof: tx.proof,
+            witness: tx.witness,
+            tx_data: tx.tx_data,
+            program_bundle: tx.program_bundle,
+            data_signature: tx.data_signature,
+        };
+        tx.verify_header()?;
+        Ok(tx)
+    }
 }
 
 impl ScriptTransaction {
     /// Set a script transaction
-    ///
+    /// The header counts are taken from the input, output and witness vectors
     pub fn set_script_transaction(
         version: u64,
         fee: u64,
         maturity: u64,
-        input_count: u8,
-        output_count: u8,
-        witness_count: u8,
         inputs: Vec<Input>,
         outputs: Vec<Output>,
         program: Vec<u8>,
@@ -79,9 +142,9 @@ impl ScriptTransaction {
             version,
             fee,
             maturity,
-            input_count,
-            output_count,
-            witness_count,
+            input_count: inputs.len() as u16,
+            output_count: outputs.len() as u16,
+            witness_count: witness.len() as u8,
             inputs,
             outputs,
             program,
@@ -89,8 +152,40 @@ impl ScriptTransaction {
             proof,
             witness,
             tx_data,
+            program_bundle: Vec::new(),
+            data_signature: None,
         }
     }
+    /// Set the programs to be executed after the first program of the transaction
+    /// The proof of the transaction has to cover the full sequence of programs
+    pub fn with_program_bundle(mut self, program_bundle: Vec<(Vec<u8>, CallProof)>) -> Self {
+        self.program_bundle = program_bundle;
+        self
+    }
+    /// Set the oracle signature over the tx_data of the transaction
+    pub fn with_data_signature(mut self, data_signature: DataSignature) -> Self {
+        self.data_signature = Some(data_signature);
+        self
+    }
+    pub fn get_data_signature(&self) -> Option<&DataSignature> {
+        self.data_signature.as_ref()
+    }
+    /// Returns the programs of the transaction in execution order, each with its Call Proof
+    pub fn get_programs(&self) -> Vec<(&Vec<u8>, &CallProof)> {
+        let mut programs = Vec::with_capacity(1 + self.program_bundle.len());
+        programs.push((&self.program, &self.call_proof));
+        for (program, call_proof) in self.program_bundle.iter() {
+            programs.push((program, call_proof));
+        }
+        programs
+    }
+    /// Returns the total size of the programs in bytes
+    pub fn get_program_size(&self) -> usize {
+        self.get_programs()
+            .iter()
+            .map(|(program, _)| program.len())
+            .sum()
+    }
     ///DUMMY TX FOR UTXO SET VERIFICATION
     /// Done only for verifying the utxo set in block processing module
     ///
@@ -104,9 +199,6 @@ impl ScriptTransaction {
             0u64,
             0u64,
             0u64,
-            inputs.len() as u8,
-            outputs.len() as u8,
-            0u8,
             inputs.to_vec(),
             outputs.to_vec(),
             program,
@@ -134,40 +226,101 @@ impl ScriptTransaction {
         contract_deploy_flag: bool,
         fee: u64,
     ) -> Result<ScriptTransaction, zkvm::VMError> {
-        // execute the program and create a proof
-        let (program, proof) = crate::vm_run::Prover::build_proof(
-            prog,
-            &inputs,
-            &outputs,
+        ScriptTransaction::create_script_transaction_bundle(
+            sk_list,
+            vec![(prog, call_proof)],
+            inputs,
+            outputs,
+            tx_data,
             contract_deploy_flag,
-            tx_data.clone(),
-        )?;
-
-        // create signatures and witness proofs for all inputs and corresponding outputs
-        let witness: Vec<Witness> = ScriptTransaction::create_witness_for_script_tx(
+            fee,
+        )
+    }
+    /// create a script transaction executing a sequence of programs in order
+    /// the programs share the stack initialized from the inputs and outputs and a single proof is created
+    /// each program carries its own call proof for the same script address
+    pub fn create_script_transaction_bundle(
+        sk_list: &[RistrettoSecretKey],
+        programs: Vec<(Program, CallProof)>,
+        inputs: &[Input],
+        outputs: &[Output],
+        tx_data: Option<zkvm::String>,
+        contract_deploy_flag: bool,
+        fee: u64,
+    ) -> Result<ScriptTransaction, zkvm::VMError> {
+        ScriptTransaction::create_script_transaction_bundle_with_opts(
             sk_list,
+            programs,
             inputs,
             outputs,
+            tx_data,
             contract_deploy_flag,
-        );
+            fee,
+            ProverOpts::default(),
+        )
+    }
+    /// create a script transaction executing a sequence of programs with the given prover options
+    /// with `opts.parallel` the proof and the witnesses are created concurrently
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_script_transaction_bundle_with_opts(
+        sk_list: &[RistrettoSecretKey],
+        programs: Vec<(Program, CallProof)>,
+        inputs: &[Input],
+        outputs: &[Output],
+        tx_data: Option<zkvm::String>,
+        contract_deploy_flag: bool,
+        fee: u64,
+        opts: ProverOpts,
+    ) -> Result<ScriptTransaction, zkvm::VMError> {
+        if programs.len() > MAX_PROGRAMS as usize {
+            return Err(VMError::InvalidFormat);
+        }
+        let (progs, call_proofs): (Vec<Program>, Vec<CallProof>) = programs.into_iter().unzip();
         // converts inputs and outputs to hide the encrypted data using verifier view and update witness index
-        let (inputs, outputs, tx_data) =
-            ScriptTransaction::create_verifier_view(inputs, outputs, tx_data);
+        // the points of the open commitments are computed here once, the witnesses sign views of the same commitments
+        let (verifier_inputs, verifier_outputs, verifier_tx_data) =
+            ScriptTransaction::create_verifier_view(inputs, outputs, tx_data.clone());
+        // execute the programs and create a proof
+        // and create signatures and witness proofs for all inputs and corresponding outputs
+        let (proof_result, witness) = join(
+            opts,
+            move || {
+                crate::vm_run::Prover::build_proof_bundle_with_opts(
+                    progs,
+                    inputs,
+                    outputs,
+                    contract_deploy_flag,
+                    tx_data,
+                    opts,
+                )
+            },
+            || {
+                ScriptTransaction::create_witness_for_script_tx_with_opts(
+                    sk_list,
+                    inputs,
+                    outputs,
+                    contract_deploy_flag,
+                    opts,
+                )
+            },
+        );
+        let (bytecodes, proof) = proof_result?;
+        let mut programs = bytecodes.into_iter().zip(call_proofs.into_iter());
+        // build_proof_bundle fails on an empty sequence
+        let (program, call_proof) = programs.next().ok_or(VMError::InvalidFormat)?;
         Ok(ScriptTransaction::set_script_transaction(
             0u64,
             fee,
             0u64,
-            inputs.len() as u8,
-            outputs.len() as u8,
-            witness.len() as u8,
-            inputs,
-            outputs,
+            verifier_inputs,
+            verifier_outputs,
             program,
             call_proof,
             proof,
             witness,
-            tx_data,
-        ))
+            verifier_tx_data,
+        )
+        .with_program_bundle(programs.collect()))
     }
     // create verifier view for the transaction
     // Should be replace with Encoding function for Tx which should do this automatically
@@ -211,125 +364,259 @@ impl ScriptTransaction {
         outputs: &[Output],
         contract_deploy_flag: bool,
     ) -> Vec<Witness> {
+        ScriptTransaction::create_witness_for_script_tx_with_opts(
+            sk_list,
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            ProverOpts::default(),
+        )
+    }
+
+    ///create the witnesses of the inputs, in parallel with `opts.parallel`
+    pub fn create_witness_for_script_tx_with_opts(
+        sk_list: &[RistrettoSecretKey],
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        opts: ProverOpts,
+    ) -> Vec<Witness> {
+        let input_witness = |(i, inp): (usize, &Input)| {
+            ScriptTransaction::create_input_witness(
+                sk_list,
+                i,
+                inp,
+                outputs,
+                contract_deploy_flag,
+            )
+        };
+        let input_witnesses: Vec<(Witness, Option<Witness>)> =
+            map_enumerated(opts, inputs, input_witness);
+
         let mut witness: Vec<Witness> = Vec::with_capacity(inputs.len());
+        // owner rotation signatures are appended after the input witnesses
+        let mut rotation_witness: Vec<Witness> = Vec::new();
+        for (input_witness, rotation) in input_witnesses {
+            witness.push(input_witness);
+            rotation_witness.extend(rotation);
+        }
+        witness.extend(rotation_witness);
+        witness
+    }
 
-        //iterate over Inputs and build the corresponding witness
-        // Coin <-> Memo always carry ValueWitness
-        // State <-> State
-        //  1. Deploy Contract: State -> StateWitness
-        //  2. Call Contract: Signature -> SignatureWitness
-        for (i, inp) in inputs.iter().enumerate() {
-            match inp.in_type {
-                IOType::Coin => {
-                    // get corresponding OutputMemo
-                    let out_memo: Output = outputs[i].clone();
-                    let acc: Account = inp
-                        .to_quisquis_account()
-                        .expect("Input is not a quisquis account");
-                    // get the public key from account
-                    let (pk, _) = acc.get_account();
-                    // get Pedersen commitment value from Memo
-                    let memo_commitment = out_memo
-                        .output
-                        .get_commitment()
-                        .expect("Memo is not a coin");
-                    // get commitment value and scalar
-                    let (memo_value, memo_scalar) = memo_commitment.witness().unwrap();
-                    let memo_commit = memo_commitment.to_point();
-                    let value = memo_value
-                        .to_integer()
-                        .expect("Can not cconvert to signed int")
-                        .to_u64()
-                        .expect("Value is not a u64");
-                    // create coin input witness
-                    let input_coin = inp.clone();
-                    let sk = sk_list[i].clone();
-                    let coin_witness = zkvm::zkos_types::ValueWitness::create_value_witness(
-                        input_coin,
-                        sk,
-                       // out_memo,
-                        acc,
-                        pk,
-                        memo_commit,
-                        value,
-                        memo_scalar,
-                    );
-                    witness.push(Witness::ValueWitness(coin_witness));
-                }
-                IOType::Memo => {
-                    //let in_memo: &OutputMemo = inp
-                      //  .as_out_memo()
-                        //.expect("OutputMemo can not be extracted from Input Memo");
-                    // get corresponding OutputCoin
-                    let out_coin: Output = outputs[i].clone();
-                    //let acc: Account = out_coin
-                      //  .to_quisquis_account()
-                      //  .expect("Output is not a quisquis account");
-                    // get the public key from account
-                    //let (pk, _) = acc.get_account();
-                    
-                   
-                    //let sk = sk_list[i].clone();
-                    // create memo input signature 
-                    // convert OutputMemo to Output
-                   // let previous_output_memo = Output::from(in_memo.clone());
-                    // sign the input memo
-                   // let previous_output_memo_verifier_view = previous_output_memo.to_verifier_view();
-                    //let message = bincode::serialize(&previous_output_memo_verifier_view).unwrap();
-                    //let signature: Signature = quisquislib::keys::PublicKey::sign_msg(&pk, &message, &sk, ("PublicKeySign").as_bytes());
-                    
-                    let memo_witness = zkvm::zkos_types::Witness::create_witness_for_memo_input(
-                        out_coin,
-                       inp.clone(),
-                    ).expect("Memo Witness can not be created");
-                    witness.push(memo_witness);
-                }
-                IOType::State => {
-                    // get the input
-                    let input = inp.clone();
-                    let sk = sk_list[i].clone();
-                    let output = outputs[i].clone();
-                    let owner = input
-                        .as_owner_address()
-                        .expect("Owner address does not exist");
-                    // extract pk from owner string
-                    let address: Address = Address::from_hex(owner, address::AddressType::Standard)
-                        .expect("Hex address is not decodable");
-                    let pk: RistrettoPublicKey = address.into();
-                    let state_witness = StateWitness::create_state_witness(
-                        &input,
-                        &output,
-                        sk.clone(),
-                        pk.clone(),
-                        contract_deploy_flag,
-                    );
-                    witness.push(Witness::State(state_witness));
-                }
+    // witness of the input at index `i`, and the owner rotation signature of a State
+    // Coin <-> Memo always carry ValueWitness
+    // State <-> State
+    //  1. Deploy Contract: State -> StateWitness
+    //  2. Call Contract: Signature -> SignatureWitness
+    fn create_input_witness(
+        sk_list: &[RistrettoSecretKey],
+        i: usize,
+        inp: &Input,
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+    ) -> (Witness, Option<Witness>) {
+        match inp.in_type {
+            IOType::Coin => {
+                // get corresponding OutputMemo
+                let out_memo: Output = outputs[i].clone();
+                let acc: Account = inp
+                    .to_quisquis_account()
+                    .expect("Input is not a quisquis account");
+                // get the public key from account
+                let (pk, _) = acc.get_account();
+                // get Pedersen commitment value from Memo
+                let memo_commitment = out_memo
+                    .output
+                    .get_commitment()
+                    .expect("Memo is not a coin");
+                // get commitment value and scalar
+                let (memo_value, memo_scalar) = memo_commitment.witness().unwrap();
+                let memo_commit = memo_commitment.to_point();
+                let value = memo_value
+                    .to_integer()
+                    .expect("Can not cconvert to signed int")
+                    .to_u64()
+                    .expect("Value is not a u64");
+                // create coin input witness
+                let input_coin = inp.clone();
+                let sk = sk_list[i].clone();
+                let coin_witness = zkvm::zkos_types::ValueWitness::create_value_witness(
+                    input_coin,
+                    sk,
+                   // out_memo,
+                    acc,
+                    pk,
+                    memo_commit,
+                    value,
+                    memo_scalar,
+                );
+                (Witness::ValueWitness(coin_witness), None)
+            }
+            IOType::Memo => {
+                //let in_memo: &OutputMemo = inp
+                  //  .as_out_memo()
+                    //.expect("OutputMemo can not be extracted from Input Memo");
+                // get corresponding OutputCoin
+                let out_coin: Output = outputs[i].clone();
+                //let acc: Account = out_coin
+                  //  .to_quisquis_account()
+                  //  .expect("Output is not a quisquis account");
+                // get the public key from account
+                //let (pk, _) = acc.get_account();
+                
+               
+                //let sk = sk_list[i].clone();
+                // create memo input signature 
+                // convert OutputMemo to Output
+               // let previous_output_memo = Output::from(in_memo.clone());
+                // sign the input memo
+               // let previous_output_memo_verifier_view = previous_output_memo.to_verifier_view();
+                //let message = bincode::serialize(&previous_output_memo_verifier_view).unwrap();
+                //let signature: Signature = quisquislib::keys::PublicKey::sign_msg(&pk, &message, &sk, ("PublicKeySign").as_bytes());
+                
+                let memo_witness = zkvm::zkos_types::Witness::create_witness_for_memo_input(
+                    out_coin,
+                   inp.clone(),
+                ).expect("Memo Witness can not be created");
+                (memo_witness, None)
+            }
+            IOType::State => {
+                // get the input
+                let input = inp.clone();
+                let sk = sk_list[i].clone();
+                let output = outputs[i].clone();
+                let owner = input
+                    .as_owner_address()
+                    .expect("Owner address does not exist");
+                // extract pk from owner string
+                let address: Address = Address::from_hex(owner, address::AddressType::Standard)
+                    .expect("Hex address is not decodable");
+                let pk: RistrettoPublicKey = address.into();
+                let state_witness = StateWitness::create_state_witness(
+                    &input,
+                    &output,
+                    sk.clone(),
+                    pk.clone(),
+                    contract_deploy_flag,
+                )
+                .expect("State witness can not be created");
+                // the owner of the input state approves the change of owner
+                let rotation = if StateWitness::is_owner_rotation(&input, &output) {
+                    Some(
+                        StateWitness::create_owner_rotation(&input, &output, sk, pk)
+                            .expect("Owner rotation signature can not be created"),
+                    )
+                } else {
+                    None
+                };
+                (Witness::State(state_witness), rotation)
             }
         }
-        witness
+    }
+
+    /// Checks that the input, output and witness counts of the header match the tx
+    pub fn verify_header(&self) -> Result<(), TxError> {
+        check_header_count("input_count", self.input_count, self.inputs.len())?;
+        check_header_count("output_count", self.output_count, self.outputs.len())?;
+        check_header_count("witness_count", self.witness_count.into(), self.witness.len())
     }
 
     /// verify the script tx
-    pub fn verify(&self) -> Result<(), &'static str> {
+    /// a failing r1cs proof returns the verification failure diagnostics
+    pub fn verify(&self) -> Result<(), TxError> {
         //assume that the Utxo Ids have been verified already
 
+        // the header counts are used for fee and size accounting
+        self.verify_header()?;
+
         // Differentiate between contract deploy and contract call
         let contract_initialize = self.is_contract_deploy();
 
+        // every coin value moved by the tx has to be covered by a same value witness
+        self.verify_value_coverage()?;
+        self.verify_settlement_payouts()?;
+
         //verify the witnesses and the proofs of same value and zero balance proof as required
         self.verify_witnesses(contract_initialize)?;
 
+        if self.program_bundle.len() + 1 > MAX_PROGRAMS as usize {
+            return Err("Too many programs in the transaction".into());
+        }
+        if self.get_program_size() as u64 > CONTRACT_MAX_SIZE {
+            return Err("Programs exceed the maximum size".into());
+        }
+
         // verify the call proof for the program to check the authenticity of the program
         // Checking authenticity of the program is not required for contract deploy
 
+        self.verify_contract_migration()?;
         self.verify_call_proof()?;
 
         // verify the r1cs proof
-
-        let verify = crate::vm_run::Verifier::verify_r1cs_proof(
+        self.verify_r1cs_proof(contract_initialize)
+    }
+    /// verify the script tx at block `height`
+    /// with an oracle configured, the tx_data of price sensitive programs has to be signed by the oracle
+    pub fn verify_with_oracle(
+        &self,
+        oracle: Option<&OracleConfig>,
+        height: u64,
+    ) -> Result<(), TxError> {
+        self.verify()?;
+        match oracle {
+            Some(oracle) => Ok(self.verify_data_signature(oracle, height)?),
+            None => Ok(()),
+        }
+    }
+    /// verify the oracle signature over the tx_data if any program of the tx is price sensitive
+    /// the signature has to be made for the script address of the tx and be valid at `height`
+    pub fn verify_data_signature(
+        &self,
+        oracle: &OracleConfig,
+        height: u64,
+    ) -> Result<(), &'static str> {
+        let tx_data = match &self.tx_data {
+            Some(tx_data) => tx_data,
+            None => return Ok(()),
+        };
+        let price_sensitive = self
+            .get_programs()
+            .iter()
+            .any(|(program, _)| oracle.is_price_sensitive(program));
+        if !price_sensitive {
+            return Ok(());
+        }
+        let data_signature = match &self.data_signature {
+            Some(data_signature) => data_signature,
+            None => return Err("OracleError::tx_data is not signed by the oracle"),
+        };
+        if !data_signature.is_valid_at(height) {
+            return Err("OracleError::Signed tx_data is outside of its block height window");
+        }
+        let script_address = self.get_script_address()?;
+        data_signature.verify(&oracle.key, &script_address, tx_data)
+    }
+    /// verify the r1cs proof of the tx program
+    /// returns the verification failure diagnostics on error
+    pub fn verify_r1cs_proof(&self, contract_initialize: bool) -> Result<(), TxError> {
+        self.verify_r1cs_proof_in(ProofContext::global(), contract_initialize)
+    }
+    /// verify the r1cs proof of the tx program with the generators of `context`
+    pub fn verify_r1cs_proof_in(
+        &self,
+        context: &ProofContext,
+        contract_initialize: bool,
+    ) -> Result<(), TxError> {
+        let programs: Vec<Vec<u8>> = self
+            .get_programs()
+            .into_iter()
+            .map(|(program, _)| program.clone())
+            .collect();
+        let verify = crate::vm_run::Verifier::verify_r1cs_proof_bundle_in(
+            context,
             &self.proof,
-            &self.program,
+            &programs,
             &self.inputs,
             &self.outputs,
             contract_initialize,
@@ -337,55 +624,87 @@ impl ScriptTransaction {
         );
         match verify {
             Ok(_x) => Ok(()),
-            Err(_e) => Err("R1CS Proof Verification Failed"),
+            Err(VMError::VerificationFailed(failure)) => {
+                Err(TxError::ScriptVerificationFailed(failure))
+            }
+            Err(_e) => Err(TxError::InvalidProof),
         }
     }
-    // verify call proof for the tx program
-    // assuming a single tx can only have one program and can only interact with single state
+    // verify call proof for each of the tx programs
+    // assuming a single tx can only interact with single state, all programs belong to the same script address
     pub fn verify_call_proof(&self) -> Result<(), &'static str> {
         // verify the call proof for the program to check the authenticity of the program
         let hasher: zkvm::Hasher<Program> = zkvm::Hasher::<Program>::new(b"ZkOS.MerkelTree");
-        let bytecode = self.program.clone();
-        // recreate ProgramItem from Vec[u8]
-        let prog = match Program::parse(&bytecode) {
-            Ok(prog) => prog,
-            Err(_e) => {
-                return Err("Program is not a valid bytecode");
+        let script_address = self.get_script_address()?;
+        for (bytecode, call_proof) in self.get_programs() {
+            // recreate ProgramItem from Vec[u8]
+            let prog = match Program::parse(bytecode) {
+                Ok(prog) => prog,
+                Err(_e) => {
+                    return Err("Program is not a valid bytecode");
+                }
+            };
+            // verify the call proof
+            if !call_proof.verify_call_proof(script_address.clone(), &prog, &hasher) {
+                return Err("Call Proof Verification Failed");
+            }
+        }
+        Ok(())
+    }
+    // get the script address from Inputs and Outputs
+    // the first input will always be a Coin or a Memo
+    pub fn get_script_address(&self) -> Result<String, &'static str> {
+        let inp = match self.inputs.first() {
+            Some(inp) => inp,
+            None => {
+                return Err("Transaction has no Inputs");
             }
         };
-        // get the script address from Inputs and Outputs
-        // the first input will always be a Coin or a Memo
-        let inp = self.inputs[0].clone();
-        let mut script_address = String::new();
-        if inp.in_type == IOType::Coin {
-            // get corresponding OutputMemo
-            let out_memo = match self.outputs[0].as_out_memo() {
-                Some(out_memo) => out_memo,
-                None => {
-                    return Err("First Output is not a Memo");
+        match inp.in_type {
+            IOType::Coin => {
+                // get corresponding OutputMemo
+                match self.outputs.first().and_then(|out| out.as_out_memo()) {
+                    Some(out_memo) => Ok(out_memo.script_address.clone()),
+                    None => Err("First Output is not a Memo"),
                 }
-            };
-            script_address = out_memo.script_address.clone();
+            }
+            IOType::Memo => match inp.as_script_address() {
+                Some(addr) => Ok(addr.to_owned()),
+                None => Err("Script Address does not exist"),
+            },
+            // a migration is called on the script address the State leaves
+            IOType::State if self.is_contract_migration() => match inp.as_script_address() {
+                Some(addr) => Ok(addr.to_owned()),
+                None => Err("Script Address does not exist"),
+            },
+            IOType::State => Err("First Input is not a Coin or a Memo"),
         }
-        if inp.in_type == IOType::Memo {
-            script_address = match inp.as_script_address() {
-                Some(addr) => addr.to_owned(),
-                None => {
-                    return Err("Script Address does not exist");
+    }
+    // check if script is moving a contract State to another script address
+    // a migration spends a single State into a single State under a different script address
+    pub fn is_contract_migration(&self) -> bool {
+        match (self.inputs.as_slice(), self.outputs.as_slice()) {
+            ([inp], [out]) if inp.in_type == IOType::State => {
+                match (inp.as_script_address(), out.as_out_state()) {
+                    (Some(old), Some(new)) => *old != new.script_address,
+                    _ => false,
                 }
             }
+            _ => false,
         }
-        if inp.in_type == IOType::State {
-            return Err("First Input is not a Coin or a Memo");
+    }
+    // a migration has to run the migration program alone
+    // its call proof is verified against the old script address, the output State carries the new one
+    pub fn verify_contract_migration(&self) -> Result<(), &'static str> {
+        if !self.is_contract_migration() {
+            return Ok(());
         }
-        // verify the call proof
-        let verify_call_proof = self
-            .call_proof
-            .verify_call_proof(script_address, &prog, &hasher);
-        match verify_call_proof {
-            true => Ok(()),
-            false => Err("Call Proof Verification Failed"),
+        if !self.program_bundle.is_empty()
+            || self.program != crate::programs::get_contract_migration_program().to_bytes()
+        {
+            return Err("Contract migration has to run the migration program");
         }
+        Ok(())
     }
     // check if script is deploying contract
     // can also use Utxo existance to check this but this is more efficient
@@ -421,28 +740,76 @@ impl ScriptTransaction {
         false
     }
 
+    // check that no coin value enters or leaves the tx without a same value witness
+    // Coin Input -> Memo Output and Memo Input -> Coin Output at the same index are the only coin movements
+    // the witnesses themselves are verified in verify_witnesses
+    pub fn verify_value_coverage(&self) -> Result<(), &'static str> {
+        if self.outputs.len() < self.inputs.len() {
+            return Err("ValueCoverage::Input without a corresponding Output");
+        }
+        for (i, inp) in self.inputs.iter().enumerate() {
+            let witness = match self.witness.get(inp.get_witness_index() as usize) {
+                Some(witness) => witness,
+                None => {
+                    return Err("ValueCoverage::Witness does not exist for Input");
+                }
+            };
+            // Coin Inputs carry a ValueWitness or a Multisig witness with a same value proof,
+            // Memo Inputs a same value SigmaProof
+            let (expected_output, covered) = match inp.in_type {
+                IOType::Coin => (
+                    IOType::Memo,
+                    witness.to_value_witness().is_ok()
+                        || witness
+                            .to_multisig_witness()
+                            .map_or(false, |multisig| multisig.get_value_proof().is_some()),
+                ),
+                IOType::Memo => (IOType::Coin, witness.to_sigma_proof().is_ok()),
+                IOType::State => (IOType::State, true),
+            };
+            if self.outputs[i].out_type != expected_output {
+                return Err("ValueCoverage::Input and Output types do not match");
+            }
+            if !covered {
+                return Err("ValueCoverage::Coin value is not covered by a same value witness");
+            }
+        }
+        // a Coin Output can only take its value from the Memo Input at the same index
+        for (i, out) in self.outputs.iter().enumerate() {
+            if out.out_type == IOType::Coin && i >= self.inputs.len() {
+                return Err("ValueCoverage::Coin Output is not covered by a Memo Input");
+            }
+        }
+        Ok(())
+    }
+
+    // the Coin Output paired with a Memo Input pays the order out, it has to go to the owner of the memo
+    // run after verify_value_coverage, which checks that the pair exists
+    pub fn verify_settlement_payouts(&self) -> Result<(), &'static str> {
+        for (inp, out) in self.inputs.iter().zip(self.outputs.iter()) {
+            if inp.in_type == IOType::Memo {
+                verify_settlement_payout(inp, out)?;
+            }
+        }
+        Ok(())
+    }
+
     // verify the witnesses and the proofs of same value and zero balance proof as required
     pub fn verify_witnesses(&self, contract_deploy_flag: bool) -> Result<(), &'static str> {
         // get the witness vector
         let witness_vector: Vec<Witness> = self.witness.clone();
+        // owner rotation signatures are appended after the witnesses of the inputs
+        let mut rotation_index = self.inputs.len();
+        let mut referenced = Vec::with_capacity(witness_vector.len());
         // loop over inputs and extract their corresponding witnesses
         for (i, inp) in self.inputs.iter().enumerate() {
+            let witness = input_witness(&witness_vector, i, inp)
+                .map_err(|_| "Witness index of the input is not valid")?;
+            referenced.push(inp.get_witness_index() as usize);
             match inp.in_type {
                 IOType::Coin => {
                     // get corresponding OutputMemo
                     let out_memo: Output = self.outputs[i].clone();
-                    // get coin input witness
-                    let coin_witness: zkvm::zkos_types::ValueWitness = witness_vector
-                        [inp.get_witness_index() as usize]
-                        .clone()
-                        .to_value_witness()
-                        .map_err(|_| "Invalid ValueWitness for Input")?;
-
-                    // verify the witness
-                    // get account from input
-                    let acc: Account = inp.to_quisquis_account()?;
-                    // get the public key from account
-                    let (pk, _) = acc.get_account();
                     // get Pedersen commitment value from Memo
                     let memo_value = out_memo.output.get_commitment();
 
@@ -452,6 +819,29 @@ impl ScriptTransaction {
                             return Err("VerificationError::MemoComitment does not exist");
                         }
                     };
+                    // multisig coins are signed by the key set of the witness
+                    if let Witness::Multisig(multisig_witness) = witness {
+                        multisig_witness
+                            .verify_same_value(
+                                i,
+                                &self.inputs,
+                                &self.outputs,
+                                memo_value.to_point(),
+                            )
+                            .map_err(|_| "Multisig Witness Verification Failed")?;
+                        continue;
+                    }
+                    // get coin input witness
+                    let coin_witness: zkvm::zkos_types::ValueWitness =
+                        witness
+                            .to_value_witness()
+                            .map_err(|_| "Invalid ValueWitness for Input")?;
+
+                    // verify the witness
+                    // get account from input
+                    let acc: Account = inp.to_quisquis_account()?;
+                    // get the public key from account
+                    let (pk, _) = acc.get_account();
                     let witness_verify = coin_witness.verify_value_witness(
                         inp.clone(),
                       //  out_memo.clone(),
@@ -471,7 +861,7 @@ impl ScriptTransaction {
                     let out_coin: Output = self.outputs[i].clone();
 
                     // get memo input witness
-                    let memo_witness = witness_vector[inp.get_witness_index() as usize].clone();
+                    let memo_witness = witness.clone();
                     //     .clone()
                     //     .to_value_witness()
                     //     .map_err(|_| "VerificationError::Invalid ValueWitness for Input")?;
@@ -498,8 +888,7 @@ impl ScriptTransaction {
                 }
                 IOType::State => {
                     // get the witness for the input
-                    let state_witness = witness_vector[inp.get_witness_index() as usize]
-                        .clone()
+                    let state_witness = witness
                         .to_state_witness()
                         .map_err(|_| "VerificationEroor::Invalid StateWitness")?;
 
@@ -516,15 +905,36 @@ impl ScriptTransaction {
                     if !state_witness.verify_state_witness(
                         inp.clone(),
                         self.outputs[i].clone(),
-                        pk,
+                        pk.clone(),
                         contract_deploy_flag,
                     )? {
                         return Err("State Witness Verification Failed");
                     };
+                    // a change of owner has to be approved by the previous owner
+                    if StateWitness::is_owner_rotation(inp, &self.outputs[i]) {
+                        let rotation_sign = match witness_vector.get(rotation_index) {
+                            Some(witness) => witness
+                                .to_signature()
+                                .map_err(|_| "Owner rotation witness is not a Signature")?,
+                            None => {
+                                return Err("Owner rotation signature does not exist");
+                            }
+                        };
+                        StateWitness::verify_owner_rotation(
+                            inp,
+                            &self.outputs[i],
+                            &rotation_sign,
+                            pk,
+                        )?;
+                        referenced.push(rotation_index);
+                        rotation_index += 1;
+                    }
                 }
             }
         }
-        Ok(())
+        // every witness has to be referenced by an input or an owner rotation
+        check_witness_references(witness_vector.len(), referenced)
+            .map_err(|_| "Witness is not referenced by any input")
     }
     //created for utxo-in-memory
     pub fn get_input_values(&self) -> Vec<Input> {
@@ -1,5 +1,6 @@
 // Unit tests for transaction module
-use crate::vm_run::{Prover, Verifier};
+use crate::vm_run::{decode_r1cs_proof, Prover, Verifier};
+use crate::ScriptTransaction;
 
 use address::{Address, Network};
 use curve25519_dalek::scalar::Scalar;
@@ -13,9 +14,11 @@ use quisquislib::{
 use readerwriter::Encodable;
 use zkvm::merkle::{CallProof, Hasher, MerkleTree, Path};
 use zkvm::zkos_types::{
-    Input, InputData, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo,
+    Input, InputData, Output, OutputCoin, OutputData, OutputMemo, OutputState, StateWitness,
+    Utxo,
 };
-use zkvm::{Commitment, Program, String};
+use zkvm::errors::{ProgramBuildError, VMError, VerificationFailure, VerificationPhase};
+use zkvm::{Commitment, Opcode, Program, ProgramBuilder, String};
 
 #[test]
 fn call_proof_test() {
@@ -110,12 +113,23 @@ pub fn get_trader_order_program() -> Program {
     });
     return order_prog;
 }
-#[test]
-fn order_message_test_bali() {
-    let correct_program = self::get_trader_order_program();
-
-    //create input and output array
+// trader order program written with the program builder, the stack holds the memo
+// commitment IM followed by its data
+fn trader_order_program_with_builder() -> Result<Program, ProgramBuildError> {
+    let mut b = ProgramBuilder::new();
+    let im = b.input_commitment("IM");
+    let position_size = b.input_scalar("PositionSize");
+    let leverage = b.input_commitment("Leverage");
+    let entry_price = b.input_scalar("EntryPrice");
+    let margin_value = b.mul(im, entry_price);
+    let notional = b.mul(margin_value, leverage);
+    let order = b.eq(notional, position_size);
+    b.verify(order);
+    b.build()
+}
 
+// coin input of a trader and the order memo it is moved into
+fn trader_order_io() -> (Vec<Input>, Vec<Output>) {
     let mut rng = rand::thread_rng();
     let sk_in: RistrettoSecretKey = SecretKey::random(&mut rng);
     let pk_in = RistrettoPublicKey::from_secret_key(&sk_in, &mut rng);
@@ -158,7 +172,13 @@ fn order_message_test_bali() {
     );
 
     let output: Output = Output::memo(OutputData::memo(output_memo));
-    let output: Vec<Output> = vec![output];
+    (input, vec![output])
+}
+
+#[test]
+fn order_message_test_bali() {
+    let correct_program = self::get_trader_order_program();
+    let (input, output) = trader_order_io();
 
     //cretae unsigned Tx with program proof
     let result = Prover::build_proof(correct_program, &input, &output, false, None);
@@ -167,6 +187,23 @@ fn order_message_test_bali() {
     let verify = Verifier::verify_r1cs_proof(&proof, &prog_bytes, &input, &output, false, None);
     println!("{:?}", verify);
 }
+
+#[test]
+fn trader_order_program_builder_test() {
+    let program = trader_order_program_with_builder().unwrap();
+    assert_eq!(program.to_bytes(), get_trader_order_program().to_bytes());
+
+    // both programs are proven and verified on the same order
+    let (input, output) = trader_order_io();
+    for program in [program, get_trader_order_program()] {
+        let (prog_bytes, proof) =
+            Prover::build_proof(program, &input, &output, false, None).unwrap();
+        assert!(
+            Verifier::verify_r1cs_proof(&proof, &prog_bytes, &input, &output, false, None).is_ok()
+        );
+    }
+}
+
 #[test]
 fn order_message_test() {
     let _program = order_message_prog_input_output(16u64, 9u64, 0, 0);
@@ -377,6 +414,54 @@ fn lend_order_initial_dup_test_stack_initialized() -> Program {
     return order_prog;
 }
 
+// lend order program written with the program builder
+fn lend_order_program_with_builder() -> Result<Program, ProgramBuildError> {
+    let mut b = ProgramBuilder::new();
+    let coin = b.input_commitment("Coin");
+    let deposit = b.input_commitment("Deposit");
+    let pool_share = b.input_commitment("PoolShare");
+    let tvl0 = b.input_commitment("TVL0");
+    let tvl1 = b.input_commitment("TVL1");
+    let tps0 = b.input_commitment("TPS0");
+    let tps1 = b.input_commitment("TPS1");
+    let error = b.input_commitment("Error");
+    // Coin - Deposit >= 0
+    let coin = b.expr(coin);
+    let neg_deposit = b.neg(deposit.copy());
+    let balance = b.add(coin, neg_deposit);
+    let balance = b.range(balance);
+    b.drop(balance);
+    // TPS1 == TPS0 + PoolShare
+    let tps1 = b.expr(tps1);
+    let tps0_copy = b.expr(tps0.copy());
+    let new_tps = b.add(tps0_copy, pool_share.copy());
+    let shares = b.eq(tps1, new_tps);
+    // TVL1 == TVL0 + Deposit
+    let tvl1 = b.expr(tvl1);
+    let tvl0_copy = b.expr(tvl0.copy());
+    let new_tvl = b.add(tvl0_copy, deposit.copy());
+    let value = b.eq(tvl1, new_tvl);
+    let pool = b.and(shares, value);
+    // Deposit * TPS0 + Error == TVL0 * PoolShare
+    let error = b.expr(error);
+    let deposit_shares = b.mul(tps0, deposit);
+    let lhs = b.add(error, deposit_shares);
+    let rhs = b.mul(tvl0, pool_share);
+    let ratio = b.eq(lhs, rhs);
+    let order = b.and(pool, ratio);
+    b.verify(order);
+    b.build()
+}
+
+#[test]
+fn lend_order_program_builder_test() {
+    let program = lend_order_program_with_builder().unwrap();
+    assert_eq!(
+        program.to_bytes(),
+        lend_order_initial_dup_test_stack_initialized().to_bytes()
+    );
+}
+
 fn settle_order_lost_test_stack_initialized() -> Program {
     let order_prog = Program::build(|p| {
         p.roll(8)
@@ -651,6 +736,96 @@ fn lend_order_tx_program_stack_initialized_test() {
     println!("{:?}", verify);
 }
 
+// inputs and outputs of a lend deposit of 4 into a pool of TVL 10 and TPS 10
+// moved to the given TVL
+fn lend_deposit_io(tvl_1: u64) -> (Vec<Input>, Vec<Output>) {
+    let mut rng = rand::thread_rng();
+    let sk_in: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk_in = RistrettoPublicKey::from_secret_key(&sk_in, &mut rng);
+    let commit_in = ElGamalCommitment::generate_commitment(
+        &pk_in,
+        Scalar::random(&mut rng),
+        Scalar::from(10u64),
+    );
+    let add: Address = Address::standard_address(Network::default(), pk_in.clone());
+    let out_coin = OutputCoin {
+        encrypt: commit_in,
+        owner: add.as_hex(),
+    };
+    let coin_in: Input = Input::coin(InputData::coin(Utxo::default(), out_coin, 0));
+    let script_address =
+        Address::script_address(Network::Mainnet, *Scalar::random(&mut rng).as_bytes());
+    let data: Vec<String> = vec![
+        String::from(Commitment::blinded(4u64)),
+        String::from(Commitment::blinded(4u64)),
+    ];
+    let memo = Output::memo(OutputData::Memo(OutputMemo {
+        script_address: script_address.as_hex(),
+        owner: add.as_hex(),
+        commitment: Commitment::blinded(10u64),
+        data: Some(data),
+        timebounds: 0,
+    }));
+    let out_state = Output::state(OutputData::State(OutputState {
+        nonce: 2,
+        script_address: script_address.as_hex(),
+        owner: add.as_hex(),
+        commitment: Commitment::blinded(tvl_1),
+        state_variables: Some(vec![String::from(Commitment::blinded(14u64))]),
+        timebounds: 0,
+    }));
+    let in_state = OutputState {
+        nonce: 1,
+        script_address: script_address.as_hex(),
+        owner: add.as_hex(),
+        commitment: Commitment::blinded(10u64),
+        state_variables: Some(vec![String::from(Commitment::blinded(10u64))]),
+        timebounds: 0,
+    };
+    let input_state: Input = Input::state(InputData::state(
+        Utxo::default(),
+        in_state,
+        Some(vec![String::from(Commitment::blinded(0u64))]),
+        1,
+    ));
+    (vec![coin_in, input_state], vec![memo, out_state])
+}
+
+#[test]
+fn lend_deposit_eval_test() {
+    let program = self::lend_order_initial_dup_test_stack_initialized();
+    let bytecode = program.to_bytes();
+
+    let (input, output) = lend_deposit_io(14u64);
+    let result =
+        Verifier::eval_program_bundle(&[bytecode.clone()], &input, &output, false, None).unwrap();
+    assert_eq!(result.satisfiable, Some(true));
+    assert_eq!(result.error, None);
+    assert!(result.stack.is_empty());
+
+    // TVL1 != TVL0 + Deposit fails at the final verify
+    let (input, output) = lend_deposit_io(15u64);
+    let result =
+        Verifier::eval_program_bundle(&[bytecode.clone()], &input, &output, false, None).unwrap();
+    assert_eq!(result.satisfiable, Some(false));
+    assert_eq!(
+        result.failed_instruction,
+        Some(program.clone().to_vec().len() - 1)
+    );
+    assert_eq!(result.failed_opcode, Some(Opcode::Verify as u8));
+    assert_eq!(
+        result.error,
+        Some(VMError::CleartextConstraintFalse.to_string())
+    );
+
+    // without the openings the constraints can not be checked
+    let input: Vec<Input> = input.iter().map(|i| i.verifier_view()).collect();
+    let output: Vec<Output> = output.iter().map(|o| o.to_verifier_view()).collect();
+    let result = Verifier::eval_program_bundle(&[bytecode], &input, &output, false, None).unwrap();
+    assert_eq!(result.satisfiable, None);
+    assert_eq!(result.failed_instruction, None);
+}
+
 #[test]
 fn trade_order_settle_tx_program_stack_initialized_test() {
     let correct_program = self::settle_order_lost_test_stack_initialized();
@@ -926,6 +1101,66 @@ fn test_create_trade_order_program() {
     println!("{:?}", verify);
 }
 #[test]
+fn program_bundle_test() {
+    // the trade order program consumes the stack,
+    // the contract initialize program then runs on the emptied stack
+    let programs = vec![get_trader_order_program(), contract_initialize_program()];
+
+    let mut rng = rand::thread_rng();
+    let sk_in: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk_in = RistrettoPublicKey::from_secret_key(&sk_in, &mut rng);
+    let scalar_in = Scalar::random(&mut rng);
+    let commit_in =
+        ElGamalCommitment::generate_commitment(&pk_in, scalar_in.clone(), Scalar::from(7000u64));
+    let add: Address = Address::standard_address(Network::default(), pk_in.clone());
+    let out_coin = OutputCoin {
+        encrypt: commit_in,
+        owner: add.as_hex(),
+    };
+    let in_data: InputData = InputData::coin(Utxo::default(), out_coin, 0);
+    let input: Vec<Input> = vec![Input::coin(in_data)];
+
+    // both programs belong to the same script address
+    let hasher = Hasher::new(b"ZkOS.MerkelTree");
+    let root = MerkleTree::root(b"ZkOS.MerkelTree", programs.iter());
+    let script_address = Address::script_address(Network::default(), root.0).as_hex();
+    let commitment = Commitment::blinded_with_factor(7000, scalar_in);
+    let leverage_commitment = Commitment::blinded_with_factor(10, scalar_in);
+    let data: Vec<String> = vec![
+        String::from(Scalar::from(700000000u64)),
+        String::Commitment(Box::new(leverage_commitment)),
+        String::from(Scalar::from(10000u64)),
+    ];
+    let output_memo = OutputMemo::new(
+        script_address.clone(),
+        add.as_hex(),
+        commitment,
+        Some(data),
+        0u32,
+    );
+    let output: Vec<Output> = vec![Output::memo(OutputData::memo(output_memo))];
+
+    let result = Prover::build_proof_bundle(programs.clone(), &input, &output, false, None);
+    let (bytecodes, proof) = result.unwrap();
+    assert_eq!(bytecodes.len(), 2);
+    let verify =
+        Verifier::verify_r1cs_proof_bundle(&proof, &bytecodes, &input, &output, false, None);
+    assert!(verify.is_ok());
+
+    // the proof binds the order of the programs
+    let swapped = vec![bytecodes[1].clone(), bytecodes[0].clone()];
+    let verify =
+        Verifier::verify_r1cs_proof_bundle(&proof, &swapped, &input, &output, false, None);
+    assert!(verify.is_err());
+
+    // each program carries its own call proof
+    for (i, prog) in programs.iter().enumerate() {
+        let call_proof =
+            CallProof::create_call_proof(&programs, i, &hasher, Network::default()).unwrap();
+        assert!(call_proof.verify_call_proof(script_address.clone(), prog, &hasher));
+    }
+}
+#[test]
 fn test_private_transaction_single_sender_reciever() {
     let mut rng = rand::thread_rng();
 
@@ -1157,6 +1392,27 @@ fn test_dark_transaction_pow_2() {
     assert!(verify.is_ok());
 }
 
+#[test]
+fn test_dark_transaction_swapped_owner() {
+    let tx = crate::reference_tx::create_dark_reference_transaction();
+    let mut transfer = crate::TransactionData::to_transfer(tx.tx).unwrap();
+    assert!(transfer.verify_input_owners().is_ok());
+
+    // swap the owner strings of the sender and the receiver input
+    let owner_0 = transfer.inputs[0].input.owner().unwrap().clone();
+    let owner_1 = transfer.inputs[1].input.owner().unwrap().clone();
+    for (input, owner) in transfer.inputs.iter_mut().zip([owner_1, owner_0]) {
+        if let InputData::Coin { ref mut out_coin, .. } = input.input {
+            out_coin.owner = owner;
+        }
+    }
+    assert_eq!(
+        transfer.verify_input_owners(),
+        Err(crate::TxError::OwnerKeyMismatch { index: 0 })
+    );
+    assert!(transfer.verify().is_err());
+}
+
 #[test]
 fn test_dark_transaction_odd() {
     let mut rng = rand::thread_rng();
@@ -1287,7 +1543,7 @@ fn test_quisquis_transaction_single_sender_reciever() {
 
     // get anonymity accounts. Creating them on the fly for testing purposes. Should be retrieved from utxo
     let (anonymity_account_vector, anonymity_scalar_vector) =
-        crate::Sender::create_anonymity_set(1, 1);
+        crate::Sender::create_anonymity_set(1, 1, &crate::QuisQuisConfig::default());
 
     // add anonymity accounts to account vectors
     account_vector.extend(anonymity_account_vector);
@@ -1335,6 +1591,7 @@ fn test_quisquis_transaction_single_sender_reciever() {
         diff,
         None,
         0u64,
+        &crate::QuisQuisConfig::default(),
     );
 
     let tx = crate::Transaction::transaction_transfer(crate::TransactionData::TransactionTransfer(
@@ -1460,3 +1717,921 @@ fn test_create_burn_message() {
     // );
     // assert_eq!(new_enc, enc);
 }
+
+fn expect_verification_failure(result: Result<bool, VMError>) -> VerificationFailure {
+    match result {
+        Err(VMError::VerificationFailed(failure)) => failure,
+        other => panic!("expected verification failure, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_verification_failure_truncated_proof() {
+    let (_prog_bytes, proof) =
+        Prover::build_proof(contract_initialize_program(), &[], &[], false, None).unwrap();
+    let bytes = proof.to_bytes();
+    assert!(decode_r1cs_proof(&bytes).is_ok());
+
+    let failure = match decode_r1cs_proof(&bytes[..bytes.len() - 7]) {
+        Err(VMError::VerificationFailed(failure)) => failure,
+        other => panic!("expected verification failure, got {:?}", other),
+    };
+    assert_eq!(failure.phase, VerificationPhase::Decode);
+    assert_eq!(failure.instruction_index, None);
+}
+
+#[test]
+fn test_verification_failure_stack_underflow() {
+    let (_prog_bytes, proof) =
+        Prover::build_proof(contract_initialize_program(), &[], &[], false, None).unwrap();
+    // drop on an empty stack
+    let bad_program = Program::build(|p| {
+        p.push(Commitment::blinded(5u64)).drop().drop();
+    });
+    let prog_bytes = bad_program.encode_to_vec();
+    let failure = expect_verification_failure(Verifier::verify_r1cs_proof(
+        &proof,
+        &prog_bytes,
+        &[],
+        &[],
+        false,
+        None,
+    ));
+    assert_eq!(failure.phase, VerificationPhase::Execution);
+    assert_eq!(failure.instruction_index, Some(2));
+    assert_eq!(failure.opcode, Some(Opcode::Drop.to_u8()));
+    assert_eq!(failure.stack_depth, 0);
+    assert_eq!(*failure.cause, VMError::StackUnderflow);
+}
+
+#[test]
+fn test_verification_failure_unsatisfied_constraint() {
+    let unsatisfied_program = Program::build(|p| {
+        p.push(Commitment::blinded(5u64))
+            .commit()
+            .expr()
+            .push(Commitment::blinded(7u64))
+            .commit()
+            .expr()
+            .eq()
+            .verify();
+    });
+    let (prog_bytes, proof) =
+        Prover::build_proof(unsatisfied_program, &[], &[], false, None).unwrap();
+    let failure = expect_verification_failure(Verifier::verify_r1cs_proof(
+        &proof,
+        &prog_bytes,
+        &[],
+        &[],
+        false,
+        None,
+    ));
+    assert_eq!(failure.phase, VerificationPhase::ConstraintSystem);
+    assert_eq!(failure.gadget_index, None);
+    assert!(matches!(*failure.cause, VMError::R1CSError(_)));
+}
+
+fn range_upto_program(value: u64, upper_bound: u64) -> Program {
+    Program::build(|p| {
+        p.push(Commitment::blinded(value))
+            .commit()
+            .range_upto(upper_bound)
+            .drop();
+    })
+}
+
+#[test]
+fn test_range_upto_program() {
+    let bound = 10_000_000u64;
+    for value in [0, 1, bound - 1, bound].iter() {
+        let (prog_bytes, proof) =
+            Prover::build_proof(range_upto_program(*value, bound), &[], &[], false, None).unwrap();
+        assert!(Verifier::verify_r1cs_proof(&proof, &prog_bytes, &[], &[], false, None).is_ok());
+    }
+    // bound - value is negative, so no bits can be assigned to it
+    assert!(
+        Prover::build_proof(range_upto_program(bound + 1, bound), &[], &[], false, None).is_err()
+    );
+}
+
+// creates a state only script tx moving the state from `old_owner` to `new_owner`
+fn owner_rotation_tx(
+    sk_old: RistrettoSecretKey,
+    old_owner: &Address,
+    new_owner: &Address,
+) -> (ScriptTransaction, Input, Output) {
+    let mut rng = rand::thread_rng();
+    let script_address =
+        Address::script_address(Network::Mainnet, *Scalar::random(&mut rng).as_bytes());
+    let in_state = OutputState {
+        nonce: 1,
+        script_address: script_address.as_hex(),
+        owner: old_owner.as_hex(),
+        commitment: Commitment::blinded(10u64),
+        state_variables: None,
+        timebounds: 0,
+    };
+    let out_state = OutputState {
+        nonce: 2,
+        script_address: script_address.as_hex(),
+        owner: new_owner.as_hex(),
+        commitment: Commitment::blinded(10u64),
+        state_variables: None,
+        timebounds: 0,
+    };
+    let inputs = vec![Input::state(InputData::state(Utxo::default(), in_state, None, 0))];
+    let outputs = vec![Output::state(OutputData::State(out_state))];
+    let witness =
+        ScriptTransaction::create_witness_for_script_tx(&[sk_old], &inputs, &outputs, false);
+    let (verifier_inputs, verifier_outputs, _) =
+        ScriptTransaction::create_verifier_view(&inputs, &outputs, None);
+    let mut tx = ScriptTransaction::create_utxo_dummy_script_transaction(
+        &verifier_inputs,
+        &verifier_outputs,
+    );
+    tx.witness = witness;
+    (tx, inputs[0].clone(), outputs[0].clone())
+}
+
+#[test]
+fn test_state_owner_rotation() {
+    let mut rng = rand::thread_rng();
+    let sk_old: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk_old = RistrettoPublicKey::from_secret_key(&sk_old, &mut rng);
+    let old_owner = Address::standard_address(Network::default(), pk_old);
+    let sk_new: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk_new = RistrettoPublicKey::from_secret_key(&sk_new, &mut rng);
+    let new_owner = Address::standard_address(Network::default(), pk_new);
+
+    // same owner does not need an extra signature
+    let (tx, _, _) = owner_rotation_tx(sk_old.clone(), &old_owner, &old_owner);
+    assert_eq!(tx.witness.len(), 1);
+    assert!(tx.verify_witnesses(false).is_ok());
+    // a witness no input refers to is rejected
+    let mut extra = tx.clone();
+    extra.witness.push(tx.witness[0].clone());
+    assert_eq!(
+        extra.verify_witnesses(false),
+        Err("Witness is not referenced by any input")
+    );
+
+    // rotation carries the signature of the previous owner
+    let (tx, _, _) = owner_rotation_tx(sk_old, &old_owner, &new_owner);
+    assert_eq!(tx.witness.len(), 2);
+    assert!(tx.verify_witnesses(false).is_ok());
+}
+
+#[test]
+fn test_state_owner_rotation_without_signature() {
+    let mut rng = rand::thread_rng();
+    let sk_old: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk_old = RistrettoPublicKey::from_secret_key(&sk_old, &mut rng);
+    let old_owner = Address::standard_address(Network::default(), pk_old);
+    let sk_new: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk_new = RistrettoPublicKey::from_secret_key(&sk_new, &mut rng);
+    let new_owner = Address::standard_address(Network::default(), pk_new.clone());
+
+    let (mut tx, input, output) = owner_rotation_tx(sk_old, &old_owner, &new_owner);
+    // drop the rotation signature
+    tx.witness.truncate(1);
+    assert!(tx.verify_witnesses(false).is_err());
+
+    // rotation signed by the new owner is rejected
+    let forged = StateWitness::create_owner_rotation(&input, &output, sk_new, pk_new).unwrap();
+    tx.witness.push(forged);
+    assert!(tx.verify_witnesses(false).is_err());
+}
+
+// creates a script tx releasing the value of a memo into a coin owned by `owner`
+fn memo_to_coin_tx(sk: RistrettoSecretKey, owner: &Address) -> ScriptTransaction {
+    let mut rng = rand::thread_rng();
+    let script_address =
+        Address::script_address(Network::Mainnet, *Scalar::random(&mut rng).as_bytes());
+    let memo_out = OutputMemo {
+        script_address: script_address.as_hex(),
+        owner: owner.as_hex(),
+        commitment: Commitment::blinded(10u64),
+        data: None,
+        timebounds: 0,
+    };
+    let input_memo = Input::memo(InputData::memo(
+        Utxo::default(),
+        memo_out,
+        0,
+        Some(Commitment::blinded(10u64)),
+    ));
+    let out_coin = OutputCoin {
+        encrypt: ElGamalCommitment::generate_commitment(
+            &owner.as_coin_address().public_key,
+            Scalar::random(&mut rng),
+            Scalar::from(10u64),
+        ),
+        owner: owner.as_hex(),
+    };
+    let inputs = vec![input_memo];
+    let outputs = vec![Output::coin(OutputData::Coin(out_coin))];
+    let witness = ScriptTransaction::create_witness_for_script_tx(&[sk], &inputs, &outputs, false);
+    let (verifier_inputs, verifier_outputs, _) =
+        ScriptTransaction::create_verifier_view(&inputs, &outputs, None);
+    let mut tx = ScriptTransaction::create_utxo_dummy_script_transaction(
+        &verifier_inputs,
+        &verifier_outputs,
+    );
+    tx.witness = witness;
+    tx
+}
+
+#[test]
+fn test_value_coverage() {
+    let mut rng = rand::thread_rng();
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    let owner = Address::standard_address(Network::default(), pk);
+
+    let tx = memo_to_coin_tx(sk, &owner);
+    assert!(tx.verify_value_coverage().is_ok());
+    assert!(tx.verify_witnesses(false).is_ok());
+}
+
+#[test]
+fn test_value_coverage_printed_coin() {
+    let mut rng = rand::thread_rng();
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    let owner = Address::standard_address(Network::default(), pk.clone());
+
+    // extra coin output without a memo input paying for it
+    let mut tx = memo_to_coin_tx(sk.clone(), &owner);
+    let printed = OutputCoin {
+        encrypt: ElGamalCommitment::generate_commitment(
+            &pk,
+            Scalar::random(&mut rng),
+            Scalar::from(1000u64),
+        ),
+        owner: owner.as_hex(),
+    };
+    tx.outputs.push(Output::coin(OutputData::Coin(printed)));
+    assert!(tx.verify_value_coverage().is_err());
+    assert!(tx.verify().is_err());
+
+    // coin output with its same value witness omitted
+    let mut tx = memo_to_coin_tx(sk, &owner);
+    tx.witness.clear();
+    assert!(tx.verify_value_coverage().is_err());
+    assert!(tx.verify().is_err());
+}
+
+// Dark transfer spending a coin owned by a 2-of-3 multisig address
+fn multisig_dark_transfer(
+    signers: &[usize],
+) -> (crate::TransferTransaction, zkvm::zkos_types::multisig::MultisigPolicy) {
+    use zkvm::zkos_types::multisig::{sign_multisig_input, MultisigPolicy, MultisigWitness};
+    let mut rng = rand::thread_rng();
+
+    let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+    let (alice_account, _) = Account::generate_random_account_with_value(0u64.into());
+
+    // bob coin is owned by three keys, any two of them can spend it
+    let (sks, pks): (Vec<RistrettoSecretKey>, Vec<RistrettoPublicKey>) = (0..3)
+        .map(|_| {
+            let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+            let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+            (sk, pk)
+        })
+        .unzip();
+    let (bob_key, bob_encrypt) = bob_account.get_account();
+    let policy = MultisigPolicy::new(bob_key, pks, 2).unwrap();
+    let bob_owner = policy.to_address(Network::default()).as_hex();
+    let bob_input = Input::coin(InputData::coin(
+        Utxo::random(),
+        OutputCoin::new(bob_encrypt, bob_owner),
+        0,
+    ));
+    let alice_input =
+        Input::input_from_quisquis_account(&alice_account, Utxo::random(), 0, Network::default());
+
+    let alice_reciever = crate::Receiver::set_receiver(400, alice_account);
+    let bob_sender = crate::Sender::set_sender(-400, bob_account, vec![alice_reciever]);
+    let (value_vector, account_vector, sender_count, receiver_count) =
+        crate::Sender::generate_value_and_account_vector(vec![bob_sender]).unwrap();
+    let (mut transfer, _) = crate::TransferTransaction::create_private_transfer_transaction(
+        &value_vector,
+        &account_vector,
+        &[600],
+        &[400],
+        &[bob_input, alice_input],
+        &[bob_sk],
+        sender_count,
+        receiver_count,
+        None,
+        0u64,
+    )
+    .unwrap();
+
+    // the owners sign the inputs and outputs of the transfer
+    let (key_set, path) = policy.key_set_path(&[0, 1]).unwrap();
+    let signatures = signers
+        .iter()
+        .map(|i| {
+            sign_multisig_input(
+                0,
+                &transfer.inputs,
+                &transfer.outputs,
+                &sks[*i],
+                &policy.keys[*i],
+            )
+        })
+        .collect();
+    transfer
+        .add_multisig_witness(0, MultisigWitness::new(key_set, path, signatures, None))
+        .unwrap();
+    (transfer, policy)
+}
+
+#[test]
+fn test_dark_transaction_multisig_two_of_three() {
+    let (transfer, _) = multisig_dark_transfer(&[0, 1]);
+    assert!(transfer.verify_input_owners().is_ok());
+    let tx = crate::Transaction::transaction_transfer(crate::TransactionData::TransactionTransfer(
+        transfer,
+    ));
+    assert!(tx.verify().is_ok());
+}
+
+#[test]
+fn test_dark_transaction_multisig_one_of_three() {
+    // only the first key of the key set signs
+    let (transfer, _) = multisig_dark_transfer(&[0]);
+    assert_eq!(
+        transfer.verify_input_owners(),
+        Err(crate::TxError::InvalidOwnerAddress { index: 0 })
+    );
+    assert!(transfer.verify().is_err());
+}
+
+#[test]
+fn test_dark_transaction_multisig_wrong_tree() {
+    let (mut transfer, policy) = multisig_dark_transfer(&[0, 1]);
+    // the input now claims a multisig address with another third key
+    let mut rng = rand::thread_rng();
+    let other_sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let other = zkvm::zkos_types::multisig::MultisigPolicy::new(
+        policy.account_key.clone(),
+        vec![
+            policy.keys[0].clone(),
+            policy.keys[1].clone(),
+            RistrettoPublicKey::from_secret_key(&other_sk, &mut rng),
+        ],
+        2,
+    )
+    .unwrap();
+    if let InputData::Coin { ref mut out_coin, .. } = transfer.inputs[0].input {
+        out_coin.owner = other.to_address(Network::default()).as_hex();
+    }
+    assert!(transfer.verify_input_owners().is_err());
+    assert!(transfer.verify().is_err());
+}
+
+#[test]
+fn test_dark_transaction_multisig_lifted_signatures() {
+    let (mut transfer, _) = multisig_dark_transfer(&[0, 1]);
+    assert!(transfer.verify_witnesses().is_ok());
+    // the signed witness moved to a transfer paying alice output to another account
+    let (other, _) = Account::generate_random_account_with_value(400u64.into());
+    transfer.outputs[1] = Output::from_quisquis_account(other, Network::default());
+    assert_eq!(
+        transfer.verify_witnesses(),
+        Err(crate::TxError::WitnessMismatch { index: 0 })
+    );
+    assert!(transfer.verify().is_err());
+}
+
+#[test]
+fn test_transfer_header_mismatch() {
+    let tx = crate::reference_tx::create_dark_reference_transaction();
+    let mut transfer = crate::TransactionData::to_transfer(tx.tx).unwrap();
+    assert!(transfer.verify_header().is_ok());
+
+    transfer.input_count += 1;
+    assert_eq!(
+        transfer.verify_header(),
+        Err(crate::TxError::HeaderMismatch {
+            field: "input_count",
+            declared: transfer.inputs.len() + 1,
+            actual: transfer.inputs.len(),
+        })
+    );
+    assert_eq!(
+        transfer.verify(),
+        Err("Tx Verification failed. Header counts do not match the transaction.")
+    );
+
+    // mismatched headers are rejected when decoding
+    let bytes = bincode::serialize(&crate::Transaction::from(transfer)).unwrap();
+    assert!(bincode::deserialize::<crate::Transaction>(&bytes).is_err());
+    let err = crate::decode::decode_transaction(&bytes).unwrap_err();
+    assert_eq!(err.field, "tx.header");
+}
+
+#[test]
+fn test_transfer_proof_account_count_mismatch() {
+    let tx = crate::reference_tx::create_dark_reference_transaction();
+    let mut transfer = crate::TransactionData::to_transfer(tx.tx).unwrap();
+    // more receivers than the proof has accounts for
+    transfer.proof.receivers_count = transfer.inputs.len();
+    assert!(matches!(
+        transfer.verify_header(),
+        Err(crate::TxError::HeaderMismatch {
+            field: "senders_and_receivers_count",
+            ..
+        })
+    ));
+    assert!(transfer.verify().is_err());
+}
+
+#[test]
+fn test_script_header_mismatch() {
+    let (acc, _) = Account::generate_random_account_with_value(Scalar::from(20u64));
+    let coin = crate::reference_tx::create_genesis_block(30, 1, acc)
+        .into_iter()
+        .find(|record| record.value.out_type == zkvm::IOType::Coin)
+        .unwrap();
+    let outputs = vec![coin.value.clone()];
+    let inputs = vec![crate::reference_tx::convert_output_to_input(coin).unwrap()];
+    let mut script_tx = ScriptTransaction::create_utxo_dummy_script_transaction(&inputs, &outputs);
+    assert!(script_tx.verify_header().is_ok());
+    let bytes = bincode::serialize(&crate::Transaction::from(script_tx.clone())).unwrap();
+    assert!(bincode::deserialize::<crate::Transaction>(&bytes).is_ok());
+
+    script_tx.witness_count = 2;
+    assert_eq!(
+        script_tx.verify_header(),
+        Err(crate::TxError::HeaderMismatch {
+            field: "witness_count",
+            declared: 2,
+            actual: 0,
+        })
+    );
+    assert_eq!(
+        script_tx.verify(),
+        Err(crate::TxError::HeaderMismatch {
+            field: "witness_count",
+            declared: 2,
+            actual: 0,
+        })
+    );
+
+    let bytes = bincode::serialize(&crate::Transaction::from(script_tx)).unwrap();
+    assert!(bincode::deserialize::<crate::Transaction>(&bytes).is_err());
+    let err = crate::decode::decode_transaction(&bytes).unwrap_err();
+    assert_eq!(err.field, "tx.header");
+}
+
+#[test]
+fn test_script_header_wide_output_count() {
+    let (acc, _) = Account::generate_random_account_with_value(Scalar::from(20u64));
+    let coin = crate::reference_tx::create_genesis_block(30, 1, acc)
+        .into_iter()
+        .find(|record| record.value.out_type == zkvm::IOType::Coin)
+        .unwrap();
+    // more outputs than a single byte count or index can address
+    let outputs = vec![coin.value.clone(); 300];
+    let inputs = vec![crate::reference_tx::convert_output_to_input(coin).unwrap()];
+    let script_tx = ScriptTransaction::create_utxo_dummy_script_transaction(&inputs, &outputs);
+    assert_eq!(script_tx.output_count, 300);
+    assert!(script_tx.verify_header().is_ok());
+
+    let bytes = bincode::serialize(&crate::Transaction::from(script_tx)).unwrap();
+    let decoded = crate::decode::decode_transaction(&bytes).unwrap();
+    assert_eq!(decoded.get_tx_outputs().len(), 300);
+    let last = Utxo::new(zkvm::TxID(zkvm::Hash([1u8; 32])), 299);
+    assert_eq!(Utxo::from_bytes(&last.to_bytes()), Some(last));
+}
+
+// Dark transfer of 500 from bob to alice, paying `fee` out of bob's 1000
+fn fee_dark_transfer(fee: u64) -> Result<crate::TransferTransaction, &'static str> {
+    let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+    let (alice_account, _) = Account::generate_random_account_with_value(0u64.into());
+    let bob_input =
+        Input::input_from_quisquis_account(&bob_account, Utxo::random(), 0, Network::default());
+    let alice_input =
+        Input::input_from_quisquis_account(&alice_account, Utxo::random(), 0, Network::default());
+
+    let alice_reciever = crate::Receiver::set_receiver(500, alice_account);
+    let bob_sender =
+        crate::Sender::set_sender(-(500 + fee as i64), bob_account, vec![alice_reciever]);
+    let (value_vector, account_vector, sender_count, receiver_count) =
+        crate::Sender::generate_value_and_account_vector(vec![bob_sender]).unwrap();
+    let (transfer, _) = crate::TransferTransaction::create_private_transfer_transaction(
+        &value_vector,
+        &account_vector,
+        &[500 - fee],
+        &[500],
+        &[bob_input, alice_input],
+        &[bob_sk],
+        sender_count,
+        receiver_count,
+        None,
+        fee,
+    )?;
+    Ok(transfer)
+}
+
+#[test]
+fn test_dark_transaction_with_fee() {
+    let transfer = fee_dark_transfer(10).unwrap();
+    assert!(transfer.verify_fee().is_ok());
+    let tx = crate::Transaction::transaction_transfer(crate::TransactionData::TransactionTransfer(
+        transfer,
+    ));
+    assert!(tx.verify().is_ok());
+}
+
+#[test]
+fn test_dark_transaction_fee_mismatch() {
+    // the proof pays a fee of 10
+    let mut transfer = fee_dark_transfer(10).unwrap();
+    for declared in [0u64, 9, 20] {
+        transfer.fee = declared;
+        assert_eq!(
+            transfer.verify_fee(),
+            Err(crate::TxError::FeeMismatch { fee: declared })
+        );
+        assert!(transfer.verify().is_err());
+    }
+
+    // the proof pays no fee
+    let mut transfer = fee_dark_transfer(0).unwrap();
+    transfer.fee = 10;
+    assert_eq!(
+        transfer.verify_fee(),
+        Err(crate::TxError::FeeMismatch { fee: 10 })
+    );
+    assert!(transfer.verify().is_err());
+}
+
+#[test]
+fn test_dark_transaction_fee_not_covered() {
+    let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+    let (alice_account, _) = Account::generate_random_account_with_value(0u64.into());
+    let inputs: Vec<Input> = [bob_account, alice_account]
+        .iter()
+        .map(|acc| Input::input_from_quisquis_account(acc, Utxo::random(), 0, Network::default()))
+        .collect();
+    // bob debits only the receiver amount but declares a fee
+    let transfer = crate::TransferTransaction::create_private_transfer_transaction(
+        &[-500, 500],
+        &[bob_account, alice_account],
+        &[500],
+        &[500],
+        &inputs,
+        &[bob_sk],
+        1,
+        1,
+        None,
+        10u64,
+    );
+    assert!(transfer.is_err());
+}
+
+#[test]
+fn test_quisquis_transaction_fee_mismatch() {
+    let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+    let (alice_account, _) = Account::generate_random_account_with_value(0u64.into());
+    // bob pays 500 to alice and a fee of 10
+    let value_vector: Vec<i64> = vec![-510, 500, 0, 0, 0, 0, 0, 0, 0];
+    let mut account_vector: Vec<Account> = vec![bob_account, alice_account];
+    let (anonymity_account_vector, _) =
+        crate::Sender::create_anonymity_set(1, 1, &crate::QuisQuisConfig::default());
+    account_vector.extend(anonymity_account_vector);
+    let utxo = Utxo::random();
+    let inputs: Vec<Input> = account_vector
+        .iter()
+        .map(|acc| Input::input_from_quisquis_account(acc, utxo, 0, Network::default()))
+        .collect();
+
+    let mut transfer = crate::TransferTransaction::create_quisquis_transaction(
+        &inputs,
+        &value_vector,
+        &account_vector,
+        &[490],
+        &[500],
+        &[bob_sk],
+        1,
+        1,
+        7,
+        None,
+        10u64,
+        &crate::QuisQuisConfig::default(),
+    )
+    .unwrap();
+    assert!(transfer.verify().is_ok());
+
+    transfer.fee = 0;
+    assert_eq!(
+        transfer.verify_fee(),
+        Err(crate::TxError::FeeMismatch { fee: 0 })
+    );
+    assert!(transfer.verify().is_err());
+}
+
+// bob pays 500 to alice in a quisquis transfer padded to the set size of config
+fn quisquis_transfer(config: &crate::QuisQuisConfig) -> crate::TransferTransaction {
+    let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+    let (alice_account, _) = Account::generate_random_account_with_value(0u64.into());
+    let alice = crate::Receiver::set_receiver(500, alice_account);
+    let bob = crate::Sender::set_sender(-500, bob_account, vec![alice]);
+    let (mut value_vector, mut account_vector, senders_count, receivers_count) =
+        crate::Sender::generate_value_and_account_vector_for(vec![bob], config).unwrap();
+    let (anonymity_account_vector, _) =
+        crate::Sender::create_anonymity_set(senders_count, receivers_count, config);
+    let diff = anonymity_account_vector.len();
+    value_vector.extend(vec![0; diff]);
+    account_vector.extend(anonymity_account_vector);
+    let utxo = Utxo::random();
+    let inputs: Vec<Input> = account_vector
+        .iter()
+        .map(|acc| Input::input_from_quisquis_account(acc, utxo, 0, Network::default()))
+        .collect();
+
+    crate::TransferTransaction::create_quisquis_transaction(
+        &inputs,
+        &value_vector,
+        &account_vector,
+        &[500],
+        &[500],
+        &[bob_sk],
+        senders_count,
+        receivers_count,
+        diff,
+        None,
+        0u64,
+        config,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_quisquis_transaction_set_sizes() {
+    for size in [9, 16] {
+        let config = crate::QuisQuisConfig::new(size).unwrap();
+        let transfer = quisquis_transfer(&config);
+        assert_eq!(transfer.get_input_values().len(), size);
+        assert_eq!(transfer.get_output_values().len(), size);
+        assert!(transfer.verify().is_ok(), "set size {}", size);
+    }
+}
+
+#[test]
+fn test_quisquis_transaction_unsupported_set_size() {
+    assert_eq!(
+        crate::QuisQuisConfig::new(11),
+        Err(crate::TxError::UnsupportedAnonymitySetSize(11))
+    );
+
+    // a valid transfer of 9 accounts carrying two more inputs and outputs
+    let mut transfer = quisquis_transfer(&crate::QuisQuisConfig::default());
+    let mut input_accounts = Vec::new();
+    let mut output_accounts = Vec::new();
+    for (input, output) in transfer.inputs.iter().zip(transfer.outputs.iter()) {
+        input_accounts.push(input.to_quisquis_account().unwrap());
+        output_accounts.push(output.to_quisquis_account().unwrap());
+    }
+    for _ in 0..2 {
+        let (account, _) = Account::generate_random_account_with_value(0u64.into());
+        transfer.inputs.push(Input::input_from_quisquis_account(
+            &account,
+            Utxo::random(),
+            0,
+            Network::default(),
+        ));
+        transfer
+            .outputs
+            .push(Output::from_quisquis_account(account, Network::default()));
+        input_accounts.push(account);
+        output_accounts.push(account);
+    }
+    assert_eq!(
+        transfer.verify_quisquis_tx(&input_accounts, &output_accounts),
+        Err("Tx Verification failed. Anonymity set size is not supported.")
+    );
+    assert!(transfer.verify().is_err());
+}
+
+// dark transfer of the whole coin to a zero balance account of the owner per value
+fn naive_split_transaction(
+    input: &Input,
+    split_values: &[u64],
+    owner_sk: RistrettoSecretKey,
+    fee: u64,
+) -> crate::TransferTransaction {
+    let sender_account = input.to_quisquis_account().unwrap();
+    let (owner_pk, _) = sender_account.get_account();
+    let paid: u64 = split_values.iter().sum::<u64>() + fee;
+    let mut value_vector: Vec<i64> = vec![-(paid as i64)];
+    let mut account_vector = vec![sender_account];
+    let mut inputs = vec![input.clone()];
+    let mut comm_scalars = Vec::new();
+    for (index, value) in split_values.iter().enumerate() {
+        let mut rng = rand::thread_rng();
+        let pk = RistrettoPublicKey::update_public_key(&owner_pk, Scalar::random(&mut rng));
+        let comm_scalar = Scalar::random(&mut rng);
+        let commitment = ElGamalCommitment::generate_commitment(&pk, comm_scalar, Scalar::zero());
+        let account = Account::set_account(pk, commitment);
+        inputs.push(Input::input_from_quisquis_account(
+            &account,
+            Utxo::default(),
+            index as u8,
+            Network::default(),
+        ));
+        value_vector.push(*value as i64);
+        account_vector.push(account);
+        comm_scalars.push(comm_scalar);
+    }
+    let (transfer, _) = crate::TransferTransaction::create_private_transfer_transaction(
+        &value_vector,
+        &account_vector,
+        &[0],
+        split_values,
+        &inputs,
+        &[owner_sk],
+        1,
+        split_values.len(),
+        Some(&comm_scalars),
+        fee,
+    )
+    .unwrap();
+    transfer
+}
+
+#[test]
+fn split_transaction_test() {
+    let fee = 1u64;
+    for n in [2usize, 8, 16] {
+        let split_values = vec![10u64; n];
+        let (account, sk) =
+            Account::generate_random_account_with_value(Scalar::from(10 * n as u64 + fee));
+        let input =
+            Input::input_from_quisquis_account(&account, Utxo::random(), 0, Network::default());
+
+        let split = crate::TransferTransaction::create_split_transaction(
+            input.clone(),
+            &split_values,
+            sk.clone(),
+            fee,
+        )
+        .unwrap();
+        assert_eq!(split.get_output_values().len(), n);
+        // all output range proofs share one bulletproof
+        assert_eq!(split.proof.range_proof.len(), 1);
+        let split_tx = crate::Transaction::from(split);
+        assert!(split_tx.verify().is_ok());
+
+        // same coins with a change output of the sender and a full proof per receiver
+        let naive =
+            crate::Transaction::from(naive_split_transaction(&input, &split_values, sk, fee));
+        assert!(naive.verify().is_ok());
+        let split_size = bincode::serialize(&split_tx).unwrap().len();
+        let naive_size = bincode::serialize(&naive).unwrap().len();
+        println!("split into {}: {} bytes, naive: {} bytes", n, split_size, naive_size);
+        assert!(split_size < naive_size);
+    }
+}
+
+#[test]
+fn split_transaction_invalid_test() {
+    let (account, sk) = Account::generate_random_account_with_value(Scalar::from(21u64));
+    let input = Input::input_from_quisquis_account(&account, Utxo::random(), 0, Network::default());
+    let split = |values: &[u64], sk: RistrettoSecretKey| {
+        crate::TransferTransaction::create_split_transaction(input.clone(), values, sk, 1)
+    };
+    assert!(split(&[20], sk.clone()).is_err());
+    let (_, other_sk) = Account::generate_random_account_with_value(Scalar::from(0u64));
+    assert!(split(&[10, 10], other_sk).is_err());
+    // the input holds 21, not 10 + 11 + 1
+    let transfer = split(&[10, 11], sk).unwrap();
+    assert!(crate::Transaction::from(transfer).verify().is_err());
+}
+
+// dark transfer of 500 from bob to a fresh alice account holding `alice_balance`
+fn fresh_receiver_transfer(
+    alice_balance: u64,
+    witness_comm_scalar: bool,
+) -> Result<crate::TransferTransaction, &'static str> {
+    let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+    let alice_pk = RistrettoPublicKey::generate_base_pk();
+    let alice_comm_scalar = Scalar::random(&mut rand::thread_rng());
+    let alice_commitment = ElGamalCommitment::generate_commitment(
+        &alice_pk,
+        alice_comm_scalar,
+        Scalar::from(alice_balance),
+    );
+    let alice_account = Account::set_account(alice_pk, alice_commitment);
+
+    let alice_reciever = crate::Receiver::set_receiver(500, alice_account);
+    let bob_sender = crate::Sender::set_sender(-500, bob_account, vec![alice_reciever]);
+    let (value_vector, account_vector, sender_count, receiver_count) =
+        crate::Sender::generate_value_and_account_vector(vec![bob_sender]).unwrap();
+    let inputs = vec![
+        Input::input_from_quisquis_account(&bob_account, Utxo::random(), 0, Network::default()),
+        Input::input_from_quisquis_account(&alice_account, Utxo::default(), 0, Network::default()),
+    ];
+    let comm_scalars = vec![alice_comm_scalar];
+    let (transfer, _) = crate::TransferTransaction::create_private_transfer_transaction(
+        &value_vector,
+        &account_vector,
+        &[500],
+        &[500],
+        &inputs,
+        &[bob_sk],
+        sender_count,
+        receiver_count,
+        witness_comm_scalar.then(|| &comm_scalars[..]),
+        0u64,
+    )?;
+    Ok(transfer)
+}
+
+#[test]
+fn zero_balance_proof_fresh_receiver_test() {
+    let transfer = fresh_receiver_transfer(0, true).unwrap();
+    let witnesses = transfer.witness.clone().unwrap();
+    assert!(witnesses[0].to_zero_balance_proof().is_ok());
+    assert!(crate::Transaction::from(transfer.clone()).verify().is_ok());
+
+    // the fresh receiver input has to reference its proof
+    let mut stripped = transfer;
+    stripped.witness = None;
+    stripped.witness_count = 0;
+    assert_eq!(
+        crate::Transaction::from(stripped).verify(),
+        Err(crate::TxError::Rejected(
+            "Tx Verification failed. Witness does not match its input."
+        ))
+    );
+    // the scalar of a fresh receiver is needed to prove it
+    assert!(fresh_receiver_transfer(0, false).is_err());
+}
+
+#[test]
+fn transfer_witness_references_test() {
+    use crate::witness::check_witness_references;
+
+    let (multisig, _) = multisig_dark_transfer(&[0, 1]);
+    assert!(multisig.verify_witnesses().is_ok());
+    let multisig_witness = multisig.witness.unwrap().remove(0);
+
+    // the fresh receiver at input 1 refers to its zero balance proof
+    let transfer = fresh_receiver_transfer(0, true).unwrap();
+    assert!(transfer.verify_witnesses().is_ok());
+    let zero_proof = transfer.witness.clone().unwrap().remove(0);
+
+    // a shuffled witness vector is reported at the input referring to the wrong witness
+    let mut shuffled = transfer.clone();
+    shuffled.witness = Some(vec![multisig_witness, zero_proof.clone()]);
+    shuffled.witness_count = 2;
+    assert_eq!(
+        shuffled.verify_witnesses(),
+        Err(crate::TxError::WitnessMismatch { index: 1 })
+    );
+    // pointing the receiver to its proof leaves the multisig witness unreferenced
+    shuffled.inputs[1].replace_witness_index(1);
+    assert_eq!(
+        shuffled.verify_witnesses(),
+        Err(crate::TxError::OrphanWitness { index: 0 })
+    );
+
+    // a witness no input refers to is rejected
+    let mut orphan = transfer;
+    orphan.witness = Some(vec![zero_proof.clone(), zero_proof]);
+    orphan.witness_count = 2;
+    assert_eq!(
+        orphan.verify_witnesses(),
+        Err(crate::TxError::OrphanWitness { index: 1 })
+    );
+    assert_eq!(
+        crate::Transaction::from(orphan).verify(),
+        Err(crate::TxError::Rejected(
+            "Tx Verification failed. Witness is not referenced by any input."
+        ))
+    );
+
+    // inputs may share a witness, every witness has to be referenced once at least
+    assert!(check_witness_references(2, vec![1, 0, 1]).is_ok());
+    assert_eq!(
+        check_witness_references(3, vec![2, 0, 2]),
+        Err(crate::TxError::OrphanWitness { index: 1 })
+    );
+}
+
+#[test]
+fn zero_balance_proof_smuggled_account_test() {
+    // an account holding 100 passed off as a fresh receiver
+    let transfer = fresh_receiver_transfer(100, true).unwrap();
+    assert_eq!(
+        crate::Transaction::from(transfer).verify(),
+        Err(crate::TxError::Rejected(
+            "Tx Verification failed. Zero balance proof is not valid."
+        ))
+    );
+}
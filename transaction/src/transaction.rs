@@ -1,9 +1,57 @@
+//! Canonical encoding of a Transaction.
+//!
+//! The encoding starts with a `u16` version, see [`TransactionVersion`].
+//! Changes that older parsers can not read bump the version, and parsers reject
+//! any version they do not know instead of decoding the bytes as the current one.
+//!
+//! Forward-compatible additions do not bump the version. They are appended after
+//! the body as an extension section, a `u64` length followed by that many bytes.
+//! Parsers skip an extension they do not understand, so a tx with an extension
+//! is still accepted by nodes running an older release.
+
 //use merlin::Transcript;
-use zkvm::zkos_types::{Input, Output};
+use zkvm::zkos_types::{IOType, Input, Output, Utxo};
+use zkvm::{TxID, VMError};
 
-use crate::{Message, ScriptTransaction, TransferTransaction};
+use crate::constants::{OutputDataLimits, MAX_OUTPUTS};
+use crate::oracle::OracleConfig;
+use crate::{Message, ScriptTransaction, TransferTransaction, TxError};
 use serde::{Deserialize, Serialize};
 
+/// Versions of the canonical Transaction encoding.
+/// V1, with `u8` utxo output indexes and input/output counts, and V2, with the struct
+/// layout of the call proofs of script txs, are no longer accepted.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TransactionVersion {
+    /// `u16` utxo output indexes and input/output counts, compact `CallProof` encoding
+    V3 = 3,
+}
+
+impl TransactionVersion {
+    /// Version of the transactions created by this release.
+    pub const CURRENT: TransactionVersion = TransactionVersion::V3;
+
+    /// Versions accepted by `from_u16`, in increasing order.
+    pub const SUPPORTED: [TransactionVersion; 1] = [TransactionVersion::V3];
+
+    pub fn from_u16(version: u16) -> Result<TransactionVersion, TxError> {
+        match version {
+            3 => Ok(TransactionVersion::V3),
+            _ => Err(TxError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        self as u16
+    }
+}
+
+impl Default for TransactionVersion {
+    fn default() -> TransactionVersion {
+        TransactionVersion::CURRENT
+    }
+}
+
 /// Transaction type: Transfer. Script, Vault
 /// TransactionType implements [`Default`] and returns [`TransactionType::Transfer`].
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
@@ -66,40 +114,101 @@ impl TransactionData {
 }
 
 /// A complete twilight Transactiont valid for a specific network.
+/// Deserialization fails if the version is unknown
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedTransaction")]
 pub struct Transaction {
+    /// Version of the encoding. Always the first field.
+    pub version: u16,
     /// Defines the Tx type.
     pub tx_type: TransactionType,
     /// The Tx data corresponding to the Tx type.
     pub tx: TransactionData,
 }
 
+// Wire format of Transaction, before the version is checked
+#[derive(Deserialize)]
+struct UncheckedTransaction {
+    version: u16,
+    tx_type: TransactionType,
+    tx: TransactionData,
+}
+
+impl TryFrom<UncheckedTransaction> for Transaction {
+    type Error = TxError;
+
+    fn try_from(tx: UncheckedTransaction) -> Result<Self, Self::Error> {
+        TransactionVersion::from_u16(tx.version)?;
+        Ok(Transaction {
+            version: tx.version,
+            tx_type: tx.tx_type,
+            tx: tx.tx,
+        })
+    }
+}
+
 impl Transaction {
     /// set a new transaction
     pub fn new(tx_type: TransactionType, tx: TransactionData) -> Transaction {
-        Transaction { tx_type, tx }
+        Transaction {
+            version: TransactionVersion::CURRENT.to_u16(),
+            tx_type,
+            tx,
+        }
+    }
+
+    /// Canonical encoding of the tx, without extension.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to serialize the transaction")
+    }
+
+    /// Canonical encoding of the tx, refused with `DataTooLarge` if the data of an output
+    /// exceeds the default [`OutputDataLimits`]. Nodes reject such a tx, so a prover
+    /// finds out before sending it.
+    pub fn try_to_bytes(&self) -> Result<Vec<u8>, TxError> {
+        check_output_data(&self.get_tx_outputs(), &OutputDataLimits::default())?;
+        Ok(self.to_bytes())
+    }
+
+    /// Decodes a tx from its canonical encoding.
+    /// Fails with `UnsupportedVersion` if the version is unknown.
+    /// An extension section after the body is skipped.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Transaction, TxError> {
+        if bytes.len() < 2 {
+            return Err(TxError::InvalidEncoding(
+                "missing transaction version".to_string(),
+            ));
+        }
+        // check the version before the body is decoded
+        TransactionVersion::from_u16(u16::from_le_bytes([bytes[0], bytes[1]]))?;
+        let mut cursor = bytes;
+        let tx: Transaction = bincode::deserialize_from(&mut cursor)
+            .map_err(|err| TxError::InvalidEncoding(err.to_string()))?;
+        if !cursor.is_empty() {
+            let len: u64 = bincode::deserialize_from(&mut cursor)
+                .map_err(|err| TxError::InvalidEncoding(err.to_string()))?;
+            if len != cursor.len() as u64 {
+                return Err(TxError::InvalidEncoding(format!(
+                    "extension of {} bytes declares {} bytes",
+                    cursor.len(),
+                    len
+                )));
+            }
+        }
+        Ok(tx)
     }
 
     /// Create a transfer tx .
     pub fn transaction_transfer(data: TransactionData) -> Transaction {
-        Transaction {
-            tx_type: TransactionType::default(),
-            tx: data,
-        }
+        Transaction::new(TransactionType::default(), data)
     }
     /// Create a Script tx .
     pub fn transaction_script(data: TransactionData) -> Transaction {
-        Transaction {
-            tx_type: TransactionType::Script,
-            tx: data,
-        }
+        Transaction::new(TransactionType::Script, data)
     }
     /// Create a Message tx .
     pub fn transaction_message(data: TransactionData) -> Transaction {
-        Transaction {
-            tx_type: TransactionType::Message,
-            tx: data,
-        }
+        Transaction::new(TransactionType::Message, data)
     }
 
     /// return tx Input values
@@ -138,41 +247,541 @@ impl Transaction {
             TransactionData::Message(message) => message.fee.clone(),
         }
     }
-    pub fn verify(&self) -> Result<(), &'static str> {
+    /// Structural checks of the tx, run by txCommit and block processing before any proof
+    /// is verified. Fails with `DuplicateInput` if two inputs spend the same utxo, and
+    /// with `DanglingPendingInput` or `PendingInputMismatch` if an input refers to an
+    /// output of the tx that it does not carry, and with `TooManyOutputs` above
+    /// `MAX_OUTPUTS`.
+    pub fn validate_structure(&self) -> Result<(), TxError> {
+        let inputs = self.get_tx_inputs();
+        check_duplicate_inputs(&inputs)?;
+        check_pending_inputs(&inputs, &self.get_tx_outputs())
+    }
+    /// Inputs of the tx committed under `txid`, with the [`Utxo::pending`] references
+    /// resolved to its outputs. The references must have passed `validate_structure`.
+    ///
+    /// Signatures and proofs are made before the txid is known, so they cover the
+    /// pending form, which is also the form kept in the tx.
+    pub fn resolve_pending_inputs(&self, txid: TxID) -> Vec<Input> {
+        self.get_tx_inputs()
+            .iter()
+            .map(|input| input.resolve_pending(txid))
+            .collect()
+    }
+    pub fn verify(&self) -> Result<(), TxError> {
         match self.tx.clone() {
             TransactionData::TransactionTransfer(transfer_transaction) => {
-                transfer_transaction.verify()
+                Ok(transfer_transaction.verify()?)
             }
             TransactionData::TransactionScript(script_transaction) => script_transaction.verify(),
-            TransactionData::Message(message) => message.verify(),
+            TransactionData::Message(message) => Ok(message.verify()?),
+        }
+    }
+    /// Verifies the tx at block `height`.
+    /// Script txs running a price sensitive program need tx_data signed by the oracle.
+    pub fn verify_with_oracle(
+        &self,
+        oracle: Option<&OracleConfig>,
+        height: u64,
+    ) -> Result<(), TxError> {
+        match &self.tx {
+            TransactionData::TransactionScript(script_transaction) => {
+                script_transaction.verify_with_oracle(oracle, height)
+            }
+            _ => self.verify(),
+        }
+    }
+}
+
+/// Checks the data of the memo and state outputs against `limits`.
+/// Fails with `DataTooLarge` for the first output exceeding a limit.
+pub fn check_output_data(outputs: &[Output], limits: &OutputDataLimits) -> Result<(), TxError> {
+    for output in outputs.iter() {
+        if let Err(VMError::DataTooLarge { which, size, limit }) = output.validate_data_size(limits)
+        {
+            return Err(TxError::DataTooLarge { which, size, limit });
+        }
+    }
+    Ok(())
+}
+
+/// Rejects two inputs spending the same utxo, whatever their type, so a utxo can not be
+/// spent as both a coin and a memo of a script tx.
+/// The decoys of a quisquis transfer carry the default utxo, which is not in the utxo set,
+/// so it may appear any number of times.
+pub fn check_duplicate_inputs(inputs: &[Input]) -> Result<(), TxError> {
+    let decoy_utxo = Utxo::default();
+    let mut spent: Vec<(usize, &Utxo)> = Vec::with_capacity(inputs.len());
+    for (index_b, input) in inputs.iter().enumerate() {
+        let utxo = match input.as_utxo() {
+            Some(utxo) if *utxo != decoy_utxo => utxo,
+            _ => continue,
+        };
+        if let Some((index_a, _)) = spent.iter().find(|(_, spent_utxo)| *spent_utxo == utxo) {
+            return Err(TxError::DuplicateInput {
+                index_a: *index_a,
+                index_b,
+            });
+        }
+        spent.push((index_b, utxo));
+    }
+    Ok(())
+}
+
+/// Checks the inputs referring to an output of their own tx with [`Utxo::pending`].
+/// The output has to exist, and the input has to carry it, of the same type. A tx
+/// with more than `MAX_OUTPUTS` outputs is rejected, the index of a pending reference
+/// above it would alias another output.
+pub fn check_pending_inputs(inputs: &[Input], outputs: &[Output]) -> Result<(), TxError> {
+    if outputs.len() > MAX_OUTPUTS as usize {
+        return Err(TxError::TooManyOutputs {
+            count: outputs.len(),
+            limit: MAX_OUTPUTS,
+        });
+    }
+    for (index, input) in inputs.iter().enumerate() {
+        let output_index = match input.as_utxo().and_then(|utxo| utxo.pending_index()) {
+            Some(output_index) => output_index,
+            None => continue,
+        };
+        let output = outputs
+            .get(output_index as usize)
+            .ok_or(TxError::DanglingPendingInput {
+                index,
+                output_index,
+            })?;
+        // an output of another type carries nothing the input could match
+        let carried = match input.in_type {
+            IOType::Coin => input.as_out_coin() == output.as_out_coin(),
+            IOType::Memo => input.as_out_memo() == output.as_out_memo(),
+            IOType::State => input.as_out_state() == output.as_out_state(),
+        };
+        if !carried {
+            return Err(TxError::PendingInputMismatch {
+                index,
+                output_index,
+            });
         }
     }
+    Ok(())
 }
 
 impl From<ScriptTransaction> for Transaction {
     fn from(tx_script: ScriptTransaction) -> Transaction {
-        Transaction {
-            tx_type: TransactionType::Script,
-            tx: TransactionData::TransactionScript(tx_script),
-        }
+        Transaction::new(
+            TransactionType::Script,
+            TransactionData::TransactionScript(tx_script),
+        )
     }
 }
 /// from transfer transaction to transaction
 impl From<TransferTransaction> for Transaction {
     fn from(tx_transfer: TransferTransaction) -> Transaction {
-        Transaction {
-            tx_type: TransactionType::Transfer,
-            tx: TransactionData::TransactionTransfer(tx_transfer),
-        }
+        Transaction::new(
+            TransactionType::Transfer,
+            TransactionData::TransactionTransfer(tx_transfer),
+        )
     }
 }
 
 /// from message to transaction
 impl From<Message> for Transaction {
     fn from(message: Message) -> Transaction {
-        Transaction {
-            tx_type: TransactionType::Message,
-            tx: TransactionData::Message(message),
+        Transaction::new(TransactionType::Message, TransactionData::Message(message))
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reference_tx::{create_dark_reference_transaction, create_qq_reference_transaction};
+    use zkvm::constraints::Commitment;
+    use zkvm::zkos_types::{InputData, OutputData, OutputMemo, OutputState};
+    use zkvm::Hash;
+
+    // appends an extension section to an encoded tx
+    fn with_extension(mut bytes: Vec<u8>, extension: &[u8]) -> Vec<u8> {
+        bytes.extend_from_slice(&(extension.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(extension);
+        bytes
+    }
+
+    #[test]
+    fn transaction_version_encoding_test() {
+        let tx = create_dark_reference_transaction();
+        assert_eq!(tx.version, TransactionVersion::V3.to_u16());
+        let bytes = tx.to_bytes();
+        // the version is the first field of the encoding
+        assert_eq!(&bytes[..2], &[3u8, 0u8]);
+
+        let decoded = Transaction::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(
+            Transaction::from_bytes(&[]).unwrap_err(),
+            TxError::InvalidEncoding("missing transaction version".to_string())
+        );
+    }
+
+    #[test]
+    fn transaction_version_extension_test() {
+        let bytes = create_dark_reference_transaction().to_bytes();
+
+        // an extension of a known version is skipped
+        let extended = with_extension(bytes.clone(), b"fee outputs");
+        let decoded = Transaction::from_bytes(&extended).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        // so is an empty one
+        assert!(Transaction::from_bytes(&with_extension(bytes.clone(), &[])).is_ok());
+
+        // a V4 tx with an extension is rejected by the V3 parser, not garbage-decoded
+        let mut v4 = with_extension(bytes.clone(), b"fee outputs");
+        v4[..2].copy_from_slice(&4u16.to_le_bytes());
+        assert_eq!(
+            Transaction::from_bytes(&v4).unwrap_err(),
+            TxError::UnsupportedVersion(4)
+        );
+        assert!(bincode::deserialize::<Transaction>(&v4).is_err());
+
+        // so is a V1 tx, its single byte output indexes do not decode as V3
+        let mut v1 = bytes.clone();
+        v1[..2].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(
+            Transaction::from_bytes(&v1).unwrap_err(),
+            TxError::UnsupportedVersion(1)
+        );
+
+        // the extension length has to cover the rest of the bytes
+        let mut truncated = with_extension(bytes.clone(), b"fee outputs");
+        truncated.pop();
+        assert!(matches!(
+            Transaction::from_bytes(&truncated),
+            Err(TxError::InvalidEncoding(_))
+        ));
+        let mut dangling = bytes;
+        dangling.push(7);
+        assert!(matches!(
+            Transaction::from_bytes(&dangling),
+            Err(TxError::InvalidEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn script_call_proof_encoding_test() {
+        let programs: Vec<zkvm::Program> = (0..8u64)
+            .map(|i| {
+                zkvm::Program::build(|p| {
+                    p.push(zkvm::String::U64(i)).drop();
+                })
+            })
+            .collect();
+        let hasher = zkvm::merkle::Hasher::new(b"ZkOS.MerkelTree");
+        let call_proof = zkvm::merkle::CallProof::create_call_proof(
+            &programs,
+            5,
+            &hasher,
+            address::Network::default(),
+        )
+        .unwrap();
+        let script = ScriptTransaction::set_script_transaction(
+            0u64,
+            0u64,
+            0u64,
+            vec![],
+            vec![],
+            programs[5].to_bytes(),
+            call_proof.clone(),
+            bulletproofs::r1cs::R1CSProof::from_bytes(&[0u8; 32]).unwrap(),
+            vec![],
+            None,
+        );
+        let tx = Transaction::transaction_script(TransactionData::TransactionScript(script));
+
+        // the canonical encoding carries the compact call proof behind its length
+        let bytes = tx.to_bytes();
+        let mut compact = (call_proof.encoded_size() as u64).to_le_bytes().to_vec();
+        compact.extend_from_slice(&call_proof.to_bytes());
+        let start = bytes
+            .windows(compact.len())
+            .position(|window| window == &compact[..])
+            .unwrap();
+        let decoded = Transaction::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        match decoded.tx {
+            TransactionData::TransactionScript(script) => {
+                assert_eq!(script.call_proof, call_proof)
+            }
+            _ => unreachable!(),
         }
+
+        // a V2 tx carries the call proof in its struct layout, it is rejected by its version
+        let mut v2 = bytes[..start].to_vec();
+        v2.extend(bincode::serialize(&(call_proof.network, &call_proof.path)).unwrap());
+        v2.extend_from_slice(&bytes[start + compact.len()..]);
+        v2[..2].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(
+            Transaction::from_bytes(&v2).unwrap_err(),
+            TxError::UnsupportedVersion(2)
+        );
+        assert!(bincode::deserialize::<Transaction>(&v2).is_err());
+
+        // JSON keeps the fields of the proof
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(json.contains(r#""call_proof":{"network""#));
+        let decoded: Transaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn try_to_bytes_data_limits_test() {
+        let limits = OutputDataLimits::default();
+        let owner = create_dark_reference_transaction().get_tx_inputs()[0]
+            .as_owner_address()
+            .unwrap()
+            .clone();
+        let script_tx = |data: Vec<zkvm::String>| {
+            let memo = OutputMemo::new(
+                "script".to_string(),
+                owner.clone(),
+                Commitment::blinded(5u64),
+                Some(data),
+                0,
+            );
+            let script = ScriptTransaction::set_script_transaction(
+                0u64,
+                0u64,
+                0u64,
+                vec![],
+                vec![Output::memo(OutputData::memo(memo))],
+                vec![],
+                zkvm::merkle::CallProof::default(),
+                bulletproofs::r1cs::R1CSProof::from_bytes(&[0u8; 32]).unwrap(),
+                vec![],
+                None,
+            );
+            Transaction::transaction_script(TransactionData::TransactionScript(script))
+        };
+
+        let at_limit = script_tx(vec![zkvm::String::U64(0); limits.max_elements]);
+        assert_eq!(at_limit.try_to_bytes().unwrap(), at_limit.to_bytes());
+        let over_limit = script_tx(vec![zkvm::String::U64(0); limits.max_elements + 1]);
+        assert_eq!(
+            over_limit.try_to_bytes(),
+            Err(TxError::DataTooLarge {
+                which: "data_elements",
+                size: limits.max_elements + 1,
+                limit: limits.max_elements,
+            })
+        );
+        let element = zkvm::String::Opaque(vec![0u8; limits.max_element_size + 1]);
+        let oversize_element = script_tx(vec![element]);
+        assert!(matches!(
+            oversize_element.try_to_bytes(),
+            Err(TxError::DataTooLarge {
+                which: "data_element_size",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn script_program_bundle_encoding_test() {
+        let script = ScriptTransaction::set_script_transaction(
+            0u64,
+            0u64,
+            0u64,
+            vec![],
+            vec![],
+            vec![1u8],
+            zkvm::merkle::CallProof::default(),
+            bulletproofs::r1cs::R1CSProof::from_bytes(&[0u8; 32]).unwrap(),
+            vec![],
+            None,
+        );
+        let single = Transaction::from(script.clone()).to_bytes();
+        let bundle = vec![(vec![2u8], zkvm::merkle::CallProof::default())];
+        let bundled = Transaction::from(script.with_program_bundle(bundle)).to_bytes();
+        let decoded = Transaction::from_bytes(&bundled).unwrap();
+        assert_eq!(decoded.to_bytes(), bundled);
+
+        // an empty bundle and no data signature close the layout of a single program
+        assert!(single.ends_with(&[0u8; 9]));
+        // txs of the layout before the bundle are rejected by their version
+        let mut old_layout = single[..single.len() - 9].to_vec();
+        old_layout[..2].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(
+            Transaction::from_bytes(&old_layout).unwrap_err(),
+            TxError::UnsupportedVersion(1)
+        );
+        // and do not decode under the current one
+        old_layout[..2].copy_from_slice(&TransactionVersion::CURRENT.to_u16().to_le_bytes());
+        assert!(matches!(
+            Transaction::from_bytes(&old_layout),
+            Err(TxError::InvalidEncoding(_))
+        ));
+    }
+
+    // coin input spending `utxo`, with the coin of `input`
+    fn coin_input(input: &Input, utxo: Utxo) -> Input {
+        Input::coin(InputData::coin(
+            utxo,
+            input.as_out_coin().unwrap().clone(),
+            0,
+        ))
+    }
+
+    #[test]
+    fn validate_structure_duplicate_input_test() {
+        let mut tx = create_dark_reference_transaction();
+        assert!(tx.validate_structure().is_ok());
+
+        let utxo = Utxo::from_hash(Hash([7u8; 32]), 1);
+        match &mut tx.tx {
+            TransactionData::TransactionTransfer(transfer) => {
+                assert_eq!(transfer.inputs.len(), 2);
+                transfer.inputs[0] = coin_input(&transfer.inputs[0], utxo);
+                transfer.inputs[1] = coin_input(&transfer.inputs[1], utxo);
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            tx.validate_structure(),
+            Err(TxError::DuplicateInput {
+                index_a: 0,
+                index_b: 1
+            })
+        );
+
+        // another output of the same tx is another utxo
+        let inputs = tx.get_tx_inputs();
+        let other = coin_input(&inputs[1], Utxo::from_hash(Hash([7u8; 32]), 2));
+        assert!(check_duplicate_inputs(&[inputs[0].clone(), other]).is_ok());
+    }
+
+    #[test]
+    fn validate_structure_coin_and_memo_test() {
+        let inputs = create_dark_reference_transaction().get_tx_inputs();
+        let utxo = Utxo::from_hash(Hash([9u8; 32]), 0);
+        let coin = coin_input(&inputs[0], utxo);
+        let memo = Input::memo(InputData::memo(
+            utxo,
+            OutputMemo::new(
+                "script".to_string(),
+                inputs[0].as_owner_address().unwrap().clone(),
+                Commitment::blinded(5u64),
+                None,
+                0,
+            ),
+            0,
+            None,
+        ));
+        assert_eq!(
+            check_duplicate_inputs(&[coin, memo]),
+            Err(TxError::DuplicateInput {
+                index_a: 0,
+                index_b: 1
+            })
+        );
+    }
+
+    #[test]
+    fn validate_structure_decoy_inputs_test() {
+        let tx = create_qq_reference_transaction();
+        let inputs = tx.get_tx_inputs();
+        assert!(inputs.len() > 2);
+        assert!(tx.validate_structure().is_ok());
+
+        // fresh accounts all carry the default utxo
+        let fresh: Vec<Input> = inputs
+            .iter()
+            .map(|input| coin_input(input, Utxo::default()))
+            .collect();
+        assert!(check_duplicate_inputs(&fresh).is_ok());
+    }
+
+    #[test]
+    fn validate_structure_pending_input_test() {
+        let inputs = create_dark_reference_transaction().get_tx_inputs();
+        let coin = coin_input(&inputs[0], Utxo::from_hash(Hash([9u8; 32]), 0));
+        let owner = inputs[0].as_owner_address().unwrap().clone();
+        let memo = OutputMemo::new(
+            "script".to_string(),
+            owner.clone(),
+            Commitment::blinded(5u64),
+            None,
+            0,
+        );
+        let memo_ref = |output_index| {
+            Input::memo(InputData::memo(
+                Utxo::pending(output_index),
+                memo.clone(),
+                1,
+                None,
+            ))
+        };
+        let outputs = vec![Output::memo(OutputData::memo(memo.clone()))];
+        let script_tx = |inputs: Vec<Input>| {
+            Transaction::from(ScriptTransaction::create_utxo_dummy_script_transaction(
+                &inputs, &outputs,
+            ))
+        };
+
+        // the memo input spends the memo output of its own tx
+        let tx = script_tx(vec![coin.clone(), memo_ref(0)]);
+        assert!(tx.validate_structure().is_ok());
+        let txid = TxID(Hash([3u8; 32]));
+        let resolved = tx.resolve_pending_inputs(txid);
+        assert_eq!(resolved[0], coin);
+        assert_eq!(resolved[1].get_utxo(), Utxo::new(txid, 0));
+
+        assert_eq!(
+            script_tx(vec![coin.clone(), memo_ref(1)]).validate_structure(),
+            Err(TxError::DanglingPendingInput {
+                index: 1,
+                output_index: 1
+            })
+        );
+
+        // a State input does not carry the memo output it refers to
+        let state = OutputState {
+            nonce: 0,
+            script_address: "script".to_string(),
+            owner,
+            commitment: Commitment::blinded(0u64),
+            state_variables: None,
+            timebounds: 0,
+        };
+        let state_ref = Input::state(InputData::state(Utxo::pending(0), state, None, 1));
+        assert_eq!(
+            script_tx(vec![coin.clone(), state_ref]).validate_structure(),
+            Err(TxError::PendingInputMismatch {
+                index: 1,
+                output_index: 0
+            })
+        );
+
+        // the index of a pending reference is only unambiguous up to MAX_OUTPUTS outputs
+        let too_many = vec![outputs[0].clone(); MAX_OUTPUTS as usize + 1];
+        assert_eq!(
+            check_pending_inputs(&[coin.clone()], &too_many),
+            Err(TxError::TooManyOutputs {
+                count: MAX_OUTPUTS as usize + 1,
+                limit: MAX_OUTPUTS
+            })
+        );
+
+        // nor does a memo input carrying another memo
+        let mut other = memo.clone();
+        other.timebounds = 9;
+        let other_ref = Input::memo(InputData::memo(Utxo::pending(0), other, 1, None));
+        assert_eq!(
+            check_pending_inputs(&[coin, other_ref], &outputs),
+            Err(TxError::PendingInputMismatch {
+                index: 1,
+                output_index: 0
+            })
+        );
     }
 }
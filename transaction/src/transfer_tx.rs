@@ -1,9 +1,12 @@
 #![allow(non_snake_case)]
 //#![deny(missing_docs)]
 
-use crate::proof::{DarkTxProof, ShuffleTxProof};
+use crate::proof::{verify_fee_balance, DarkTxProof, ShuffleTxProof};
+use crate::errors::check_header_count;
+use crate::witness::{check_witness_references, input_witness};
+use crate::{QuisQuisConfig, TxError};
 use merlin::Transcript;
-use zkvm::zkos_types::{Input, Output, Witness};
+use zkvm::zkos_types::{multisig, Input, MultisigWitness, Output, Witness};
 
 use serde::{Deserialize, Serialize};
 
@@ -12,7 +15,8 @@ use curve25519_dalek::scalar::Scalar;
 use quisquislib::{
     accounts::prover::Prover,
     accounts::Account,
-    accounts::{verifier::Verifier, SigmaProof},
+    accounts::verifier::Verifier,
+    elgamal::ElGamalCommitment,
     keys::PublicKey,
     ristretto::{RistrettoPublicKey, RistrettoSecretKey},
     shuffle::{Permutation, Shuffle},
@@ -20,15 +24,17 @@ use quisquislib::{
 
 ///
 /// Store for TransactionTransfer
+/// Deserialization fails if the header counts do not match the vectors
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedTransferTransaction")]
 pub struct TransferTransaction {
     //transaction header
     pub(crate) version: u64,
     pub(crate) maturity: u64,
     pub(crate) fee: u64,
     //lengths of vectors to come
-    pub(crate) input_count: u8,
-    pub(crate) output_count: u8,
+    pub(crate) input_count: u16,
+    pub(crate) output_count: u16,
     pub(crate) witness_count: u8,
     //List of inputs and outputs
     pub(crate) inputs: Vec<Input>,
@@ -41,14 +47,53 @@ pub struct TransferTransaction {
     pub(crate) witness: Option<Vec<Witness>>,
 }
 
+// Wire format of TransferTransaction, before the header counts are checked
+#[derive(Deserialize)]
+struct UncheckedTransferTransaction {
+    version: u64,
+    maturity: u64,
+    fee: u64,
+    input_count: u16,
+    output_count: u16,
+    witness_count: u8,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    proof: DarkTxProof,
+    shuffle_proof: Option<ShuffleTxProof>,
+    witness: Option<Vec<Witness>>,
+}
+
+impl TryFrom<UncheckedTransferTransaction> for TransferTransaction {
+    type Error = TxError;
+
+    fn try_from(tx: UncheckedTransferTransaction) -> Result<Self, Self::Error> {
+        let tx = TransferTransaction {
+            version: tx.version,
+            maturity: tx.maturity,
+            fee: tx.fee,
+            input_count: tx.input_count,
+            output_count: tx.output_count,
+            witness_count: tx.witness_count,
+            inputs: tx.inputs,
+            outputs: tx.outputs,
+            proof: tx.proof,
+            shuffle_proof: tx.shuffle_proof,
+            witness: tx.witness,
+        };
+        tx.verify_header()?;
+        Ok(tx)
+    }
+}
+
 /// Utility functions for Creating the Zero balance proof as witness for newly minted reciver accounts
+/// Every reciever input with the default Utxo needs the commitment scalar of its account
 fn reciever_zero_balance_proof(
     prover: &mut Prover,
     input_vector: &[Input],
     scalar_vector: &[Scalar],
     senders_count: usize,
     receivers_count: usize,
-) -> Vec<Witness> {
+) -> Result<Vec<Witness>, &'static str> {
     let mut witnesses = Vec::<Witness>::new();
     let mut scalar_index = 0;
     let reciever_inputs = input_vector[senders_count..senders_count + receivers_count].to_vec();
@@ -57,19 +102,22 @@ fn reciever_zero_balance_proof(
         if inp.get_utxo() == zkvm::Utxo::default() {
             // UTXO does not exist. So create a witness proof for the reciever account
             // get the account
-            let rec = inp.to_quisquis_account().unwrap();
+            let rec = inp.to_quisquis_account()?;
+            let scalar = scalar_vector
+                .get(scalar_index)
+                .ok_or("Tx Creation failed. Zero balance proof scalar is missing.")?;
             //create proof
-            let witness_proof =
-                Prover::zero_balance_account_prover(rec, scalar_vector[scalar_index], prover);
+            let witness_proof = Prover::zero_balance_account_prover(rec, *scalar, prover);
             scalar_index += 1;
-            witnesses.push(Witness::Proof(witness_proof));
+            witnesses.push(Witness::ZeroBalanceProof(witness_proof));
         }
     }
-    witnesses
+    Ok(witnesses)
 }
 
 /// Utility function to verify the zero balance proof for newly minted reciever accounts
-///
+/// An input with the default Utxo is not in the UTXO set, its witness has to prove that it carries no value.
+/// This holds for a sender too, it would otherwise spend a balance that was never created.
 fn verify_zero_balance_witness(
     verifier: &mut Verifier,
     inputs: &[Input],
@@ -80,40 +128,71 @@ fn verify_zero_balance_witness(
             // UTXO does not exist. Check the witness proof
             // get the account
             let rec = inp.to_quisquis_account()?;
-
-            // witness is present
-            match witness {
-                Some(witnesses) => {
-                    let index = inp.get_witness_index();
-                    if index as usize >= witnesses.len() {
-                        return Err("Tx Verification failed. Witness index is not valid.");
-                    }
-                    let witness_proof: SigmaProof = match witnesses[index as usize].clone() {
-                        Witness::Proof(proof) => proof,
-                        _ => return Err("Tx Verification failed. Witness is not valid."),
-                    };
-                    let (z_vector, x) = witness_proof.get_dlog();
-                    Verifier::zero_balance_account_verifier(rec, z_vector[0], x, verifier)?;
-                }
-                None => {// do noting
-                // Receiver accounts already exist in the UTXO set. So no witness proof is required
-                // No zero balance proof is provided in this case
-                },
-            }
+            let witness_proof = witness
+                .as_ref()
+                .and_then(|witnesses| witnesses.get(inp.get_witness_index() as usize))
+                .ok_or("Tx Verification failed. Zero balance proof is missing.")?
+                .to_zero_balance_proof()
+                .map_err(|_| "Tx Verification failed. Zero balance proof is missing.")?;
+            let (z_vector, x) = witness_proof.get_dlog();
+            let z = z_vector
+                .first()
+                .ok_or("Tx Verification failed. Zero balance proof is not valid.")?;
+            Verifier::zero_balance_account_verifier(rec, *z, x, verifier)
+                .map_err(|_| "Tx Verification failed. Zero balance proof is not valid.")?;
         }
     }
     Ok(())
 }
 
+/// Utility function to convert the value vector into scalars
+/// The senders pay the receivers and the fee, i.e., the values sum to -fee
+fn fee_balanced_value_vector(value_vector: &[i64], fee: u64) -> Result<Vec<Scalar>, &'static str> {
+    let balance: i128 = value_vector.iter().map(|v| *v as i128).sum();
+    if balance + fee as i128 != 0 {
+        return Err("Tx Creation failed. Sender values do not cover the receivers and the fee.");
+    }
+    let mut value_vector_scalar = Vec::<Scalar>::new();
+    for v in value_vector.iter() {
+        if v >= &0 {
+            value_vector_scalar.push(Scalar::from(*v as u64));
+        } else {
+            value_vector_scalar.push(-Scalar::from((-*v) as u64));
+        }
+    }
+    Ok(value_vector_scalar)
+}
+
+/// Utility function to get the account of the coin input at `index`
+/// Multisig coins take the account key from the multisig witness of the input
+fn input_account(
+    index: usize,
+    inputs: &[Input],
+    outputs: &[Output],
+    witness: &Option<Vec<Witness>>,
+) -> Result<Account, &'static str> {
+    let inp = &inputs[index];
+    match inp.as_owner_address() {
+        Some(owner) if multisig::is_multisig_owner(owner) => {
+            let multisig_witness = witness
+                .as_ref()
+                .and_then(|witnesses| witnesses.get(inp.get_witness_index() as usize))
+                .ok_or("Tx Verification failed. Witness index is not valid.")?
+                .to_multisig_witness()
+                .map_err(|_| "Tx Verification failed. Witness is not valid.")?;
+            multisig_witness.verify(index, inputs, outputs)
+        }
+        _ => inp.to_quisquis_account(),
+    }
+}
+
 impl TransferTransaction {
     // Private constructor
+    // The header counts are taken from the input, output and witness vectors
     fn set_transfer_transaction(
         version: u64,
         maturity: u64,
         fee: u64,
-        input_count: u8,
-        output_count: u8,
-        witness_count: u8,
         inputs: Vec<Input>,
         outputs: Vec<Output>,
         proof: DarkTxProof,
@@ -124,9 +203,9 @@ impl TransferTransaction {
             version,
             maturity,
             fee,
-            input_count,
-            output_count,
-            witness_count,
+            input_count: inputs.len() as u16,
+            output_count: outputs.len() as u16,
+            witness_count: witness.as_ref().map_or(0, |w| w.len()) as u8,
             inputs,
             outputs,
             proof,
@@ -153,14 +232,7 @@ impl TransferTransaction {
     ) -> Result<(TransferTransaction, Option<Vec<Scalar>>), &'static str> {
        
         //convert the valur vector into scalar type to create the proof
-        let mut value_vector_scalar = Vec::<Scalar>::new();
-        for v in value_vector.iter() {
-            if v >= &0 {
-                value_vector_scalar.push(Scalar::from(*v as u64));
-            } else {
-                value_vector_scalar.push(-Scalar::from((-*v) as u64));
-            }
-        }
+        let value_vector_scalar = fee_balanced_value_vector(value_vector, fee)?;
         //create base pk for epsilon accounts
         let base_pk = RistrettoPublicKey::generate_base_pk();
 
@@ -177,7 +249,7 @@ impl TransferTransaction {
             );
         
         //identity check function to verify the construction of epsilon accounts using correct rscalars
-        Verifier::verify_delta_identity_check(&epsilon_accounts)?;
+        verify_fee_balance(&epsilon_accounts, fee)?;
 
         //update delta_accounts to reflect the change in balance
         //updated_delta_accounts = Output account for DarkTx
@@ -230,13 +302,18 @@ impl TransferTransaction {
 
         let version = 1u64;
         let maturity = 0u64;
-        let input_count = input_vector.len() as u8;
-        let output_count = outputs.len() as u8;
 
         // Create Zero account proof for Reciever accounts as witness in Tx
         // required if new account has been created for the reciever.
         // Not required if the account used for reciever is already present in the UTXO Set
         // get the reciever inputs
+        let witnesses = reciever_zero_balance_proof(
+            &mut prover,
+            &input_vector,
+            witness_comm_scalar.unwrap_or_default(),
+            senders_count,
+            receivers_count,
+        )?;
         let witness_proof_encrypt_scalar = match witness_comm_scalar {
             Some(scalar_vector) => {
                 // create Output_account_commitment_scalar for reciever accounts. Returned back to the client. Required for burnMessage/Script Tx(esp. Order/Lend)
@@ -244,32 +321,18 @@ impl TransferTransaction {
                let delta_rscalar_receiver = &delta_rscalar[senders_count..senders_count+receivers_count];
                // output account commitment scalar = input_commitment_scalar + delta_rscalar + comm_update_scalar
                let encrypt_scalar_sum_vector = delta_rscalar_receiver.iter().zip(scalar_vector.iter()).map(|(x,y)| x+y+comm_update_scalar).collect::<Vec<Scalar>>();
-                 // create proof zero balance commitment for reciever accounts
-                let witnesses = reciever_zero_balance_proof(
-                    &mut prover,
-                    &input_vector,
-                    scalar_vector,
-                    senders_count,
-                    receivers_count,
-                );
                 (Some(witnesses), Some(encrypt_scalar_sum_vector))
                 
             }
             None => {(None, None)}
         };
-        let (witness_count, witness, ) = match witness_proof_encrypt_scalar.0 {
-            Some(witnesses) => (witnesses.len() as u8, Some(witnesses)),
-            None => ( 0u8,None),
-        };
+        let witness = witness_proof_encrypt_scalar.0;
         // return TransferTransaction
         Ok((
             TransferTransaction::set_transfer_transaction(
                 version,
                 maturity,
-                fee, // fee is zero for dark tx for NOW
-                input_count,
-                output_count,
-                witness_count,
+                fee,
                 input_vector.to_vec(),
                 outputs,
                 dark_tx_proof,
@@ -280,7 +343,80 @@ impl TransferTransaction {
         ))        
         
     }
-    
+
+    /// Split of one coin input into `split_values.len()` coins of the same owner.
+    ///
+    /// The input stays the only sender and keeps `split_values[0]`, the other values
+    /// are paid to zero balance accounts at addresses derived from the input key with
+    /// the input utxo as seed. The n output accounts share one batched range proof if
+    /// n is a power of two, and there is no shuffle. The input must hold the sum of
+    /// `split_values` plus `fee`, otherwise the tx fails verification.
+    pub fn create_split_transaction(
+        input: Input,
+        split_values: &[u64],
+        owner_sk: RistrettoSecretKey,
+        fee: u64,
+    ) -> Result<TransferTransaction, &'static str> {
+        if split_values.len() < 2 {
+            return Err("Tx Creation failed. A split needs at least two outputs.");
+        }
+        let sender_account = input.to_quisquis_account()?;
+        let (base_pk, _) = sender_account.get_account();
+        base_pk
+            .verify_keypair(&owner_sk)
+            .map_err(|_| "Tx Creation failed. Secret key does not own the input.")?;
+
+        let paid = split_values[1..]
+            .iter()
+            .try_fold(fee, |sum, value| sum.checked_add(*value))
+            .filter(|paid| *paid <= i64::MAX as u64)
+            .ok_or("Tx Creation failed. Split values overflow.")?;
+        let mut value_vector: Vec<i64> = vec![-(paid as i64)];
+        let mut account_vector: Vec<Account> = vec![sender_account];
+        let mut inputs: Vec<Input> = vec![input.clone()];
+        let mut comm_scalars: Vec<Scalar> = Vec::new();
+
+        // fresh receiver accounts of the owner
+        let network = address::Network::default();
+        let seed = input.get_utxo().to_bytes();
+        for (index, value) in split_values[1..].iter().enumerate() {
+            if *value > i64::MAX as u64 {
+                return Err("Tx Creation failed. Split values overflow.");
+            }
+            let (address, _) =
+                address::derive_standard_address(network, &base_pk, &seed, index as u32);
+            let pk = address.as_coin_address().public_key;
+            let comm_scalar = Scalar::random(&mut rand::rngs::OsRng);
+            let commitment =
+                ElGamalCommitment::generate_commitment(&pk, comm_scalar, Scalar::zero());
+            let account = Account::set_account(pk, commitment);
+            // witness index of the zero balance proof of the receiver
+            inputs.push(Input::input_from_quisquis_account(
+                &account,
+                zkvm::Utxo::default(),
+                index as u8,
+                network,
+            ));
+            value_vector.push(*value as i64);
+            account_vector.push(account);
+            comm_scalars.push(comm_scalar);
+        }
+
+        let (transfer, _) = TransferTransaction::create_private_transfer_transaction(
+            &value_vector,
+            &account_vector,
+            &split_values[..1],
+            &split_values[1..],
+            &inputs,
+            &[owner_sk],
+            1,
+            split_values.len() - 1,
+            Some(&comm_scalars),
+            fee,
+        )?;
+        Ok(transfer)
+    }
+
     pub fn verify_private_transfer_tx(
         &self,
         input_accounts: &[Account],
@@ -299,7 +435,7 @@ impl TransferTransaction {
 
         //verify the dark tx proof
         self.proof
-            .verify(&mut verifier, &input_accounts, output_accounts)?;
+            .verify(&mut verifier, &input_accounts, output_accounts, self.fee)?;
 
         //verify the witnesses if they exist
         // check for inputs with utxo::default()
@@ -338,8 +474,8 @@ impl TransferTransaction {
             let sender = input_account_vector[i].clone();
             //let reciever = account_vector[senders_count].clone();
             let sender_input = shuffled_inputs[i].clone();
-            let sender_input_account = sender_input.to_quisquis_account().unwrap();
-            assert_eq!(sender, sender_input_account);
+            let input_account = sender_input.to_quisquis_account().unwrap();
+            assert_eq!(sender, input_account);
         }
 
         //create vec of Outputs -- Recievers in this case
@@ -352,6 +488,8 @@ impl TransferTransaction {
     }
     /// Create a Quisquis tx .
     /// This is a special case of Transfer Tx where the anonymity set is obtained from utxo set itself
+    /// The account and value vectors carry `config.set_size()` entries, the last
+    /// `anonymity_account_diff` of them being the anonymity accounts.
     pub fn create_quisquis_transaction(
         inputs: &[Input], // input vector as received from the client (may include zero utxo for reciever/s)
         value_vector: &[i64],
@@ -368,16 +506,23 @@ impl TransferTransaction {
         // setting the witness index properly in the input is the resposibility of the client
         witness_comm_scalar: Option<&[Scalar]>,
         fee: u64,
+        config: &QuisQuisConfig,
     ) -> Result<TransferTransaction, &'static str> {
-        //convert the valur vector into scalar type to create the proof
-        let mut value_vector_scalar = Vec::<Scalar>::new();
-        for v in value_vector.iter() {
-            if v >= &0 {
-                value_vector_scalar.push(Scalar::from(*v as u64));
-            } else {
-                value_vector_scalar.push(-Scalar::from((-*v) as u64));
-            }
+        let set_size = config.set_size();
+        if account_vector.len() != set_size
+            || value_vector.len() != set_size
+            || inputs.len() != set_size
+        {
+            return Err("Tx Creation failed. Account count does not match the anonymity set size.");
         }
+        let max_anonymity = config
+            .anonymity_count(senders_count, receivers_count)
+            .map_err(|_| "Tx Creation failed. Senders and receivers exceed the anonymity set.")?;
+        if anonymity_account_diff > max_anonymity {
+            return Err("Tx Creation failed. Anonymity account count is not valid.");
+        }
+        //convert the valur vector into scalar type to create the proof
+        let value_vector_scalar = fee_balanced_value_vector(value_vector, fee)?;
 
         //create base pk for epsilon accounts
         let base_pk = RistrettoPublicKey::generate_base_pk();
@@ -404,7 +549,7 @@ impl TransferTransaction {
             );
 
         //Step 3. identity check function to verify the construction of epsilon accounts using correct rscalars
-        Verifier::verify_delta_identity_check(&epsilon_accounts)?;
+        verify_fee_balance(&epsilon_accounts, fee)?;
 
         // Step 4. update delta_accounts to reflect the change in balance
         let updated_delta_accounts =
@@ -435,17 +580,18 @@ impl TransferTransaction {
             // Should always be none for Quisquis Tx. Only required for Dark Tx
             None,
         );
-        // assuming the number of accounts to be 9
-        let anonymity_index = 9 - anonymity_account_diff;
+        // the anonymity accounts close the set
+        let anonymity_index = set_size - anonymity_account_diff;
         // println!("anonymity index: {}", anonymity_index);
         // get a list of anonymity accounts in the input' vector
-        let input_dash_accounts_anonymity_slice = &input_dash_accounts[anonymity_index..9];
+        let input_dash_accounts_anonymity_slice = &input_dash_accounts[anonymity_index..set_size];
         // get a list of anonymity accounts in the updated delta accounts vector
-        let updated_delta_accounts_anonymity_slice = &updated_delta_accounts[anonymity_index..9];
+        let updated_delta_accounts_anonymity_slice =
+            &updated_delta_accounts[anonymity_index..set_size];
         // get of list of scalar witnesses for anonymity accounts in delta and epsilon accounts vector
-        let rscalars_anonymity_slice = &delta_rscalar[anonymity_index..9];
+        let rscalars_anonymity_slice = &delta_rscalar[anonymity_index..set_size];
         //for anonymity zero account proof. Not needed anymore
-        //let input_anonymity_account_slice = &account_vector[anonymity_index..9];
+        //let input_anonymity_account_slice = &account_vector[anonymity_index..set_size];
         //Shuffle accounts
         let output_shuffle = Shuffle::output_shuffle(&updated_delta_accounts)?;
 
@@ -456,6 +602,7 @@ impl TransferTransaction {
             &rscalars_anonymity_slice,
             &input_shuffle,
             &output_shuffle,
+            config,
         );
 
         let output_final = output_shuffle.get_outputs_vector();
@@ -463,64 +610,34 @@ impl TransferTransaction {
         // required if new account has been created for the reciever.
         // Not required if the account used for reciever is already present in the UTXO Set
         // get the reciever inputs
-        match witness_comm_scalar {
-            Some(scalar_vector) => {
-                let witnesses = reciever_zero_balance_proof(
-                    &mut prover,
-                    &inputs,
-                    scalar_vector,
-                    senders_count,
-                    receivers_count,
-                );
-
-                // create vec of shuffled Inputs and Outputs.
-                // This comes after Witnesses are created because the witness index is set in the input for recievers
-                let (shuffled_inputs, outputs) = Self::set_quisquis_input_output_prover(
-                    &output_final,
-                    &input_account_vector,
-                    &inputs,
-                    input_shuffle.get_permutation().to_owned(),
-                    address::Network::default(),
-                );
-                Ok(TransferTransaction::set_transfer_transaction(
-                    0u64,
-                    0u64,
-                    fee, // fee is zero for quisquis tx for NOW
-                    9u8,
-                    9u8,
-                    witnesses.len() as u8,
-                    shuffled_inputs,
-                    outputs,
-                    dark_tx_proof,
-                    Some(shuffle_proof),
-                    Some(witnesses.to_vec()),
-                ))
-            }
-            None => {
-                // create vec of shuffled Inputs and Outputs.
-                // This comes after Witnesses are created because the witness index is set in the input for recievers
-                let (shuffled_inputs, outputs) = Self::set_quisquis_input_output_prover(
-                    &output_final,
-                    &input_account_vector,
-                    &inputs,
-                    input_shuffle.get_permutation().to_owned(),
-                    address::Network::default(),
-                );
-                Ok(TransferTransaction::set_transfer_transaction(
-                    0u64,
-                    0u64,
-                    fee, // fee is zero for quisquis tx for NOW
-                    9u8,
-                    9u8,
-                    0u8,
-                    shuffled_inputs,
-                    outputs,
-                    dark_tx_proof,
-                    Some(shuffle_proof),
-                    None,
-                ))
-            }
-        }
+        // a fresh reciever without a scalar fails the creation
+        let witnesses = reciever_zero_balance_proof(
+            &mut prover,
+            &inputs,
+            witness_comm_scalar.unwrap_or_default(),
+            senders_count,
+            receivers_count,
+        )?;
+
+        // create vec of shuffled Inputs and Outputs.
+        // This comes after Witnesses are created because the witness index is set in the input for recievers
+        let (shuffled_inputs, outputs) = Self::set_quisquis_input_output_prover(
+            &output_final,
+            &input_account_vector,
+            &inputs,
+            input_shuffle.get_permutation().to_owned(),
+            address::Network::default(),
+        );
+        Ok(TransferTransaction::set_transfer_transaction(
+            0u64,
+            0u64,
+            fee,
+            shuffled_inputs,
+            outputs,
+            dark_tx_proof,
+            Some(shuffle_proof),
+            witness_comm_scalar.map(|_| witnesses),
+        ))
     }
 
     pub fn verify_quisquis_tx(
@@ -528,6 +645,9 @@ impl TransferTransaction {
         inputs: &[Account],
         outputs: &[Account],
     ) -> Result<(), &'static str> {
+        // the shuffle proof only covers the supported set sizes
+        QuisQuisConfig::new(self.inputs.len())
+            .map_err(|_| "Tx Verification failed. Anonymity set size is not supported.")?;
         //create QuisQUisTx Prover merlin transcript
         let mut transcript = Transcript::new(b"TxProof");
         let mut verifier = Verifier::new(b"QuisQuisTx", &mut transcript);
@@ -537,11 +657,18 @@ impl TransferTransaction {
         //create updated senders delta account slice
         // let updated_senders_delta_account = &self.proof.delta_accounts[..senders_count];
         //extract shuffle proof
-        let shuffle_proof = self.shuffle_proof.as_ref().unwrap();
+        let shuffle_proof = self
+            .shuffle_proof
+            .as_ref()
+            .ok_or("Tx Verification failed. Shuffle proof is missing.")?;
 
         //verify the Dark Proof first
-        self.proof
-            .verify(&mut verifier, &shuffle_proof.input_dash_accounts, None)?;
+        self.proof.verify(
+            &mut verifier,
+            &shuffle_proof.input_dash_accounts,
+            None,
+            self.fee,
+        )?;
         //let anonymity_index = self.proof.range_proof.len();
         //verify the shuffle proof
         shuffle_proof.verify(
@@ -569,7 +696,164 @@ impl TransferTransaction {
         self.outputs.clone()
     }
 
+    /// Appends the witness of a multisig coin input and points the input to it.
+    /// The owners sign the inputs and outputs of the tx, see [`multisig::multisig_signing_message`].
+    pub fn add_multisig_witness(
+        &mut self,
+        input_index: usize,
+        multisig_witness: MultisigWitness,
+    ) -> Result<(), &'static str> {
+        let mut witnesses = self.witness.clone().unwrap_or_default();
+        let input = self
+            .inputs
+            .get_mut(input_index)
+            .ok_or("Tx Verification failed. Input index is not valid.")?;
+        input.replace_witness_index(witnesses.len() as u8);
+        witnesses.push(Witness::Multisig(multisig_witness));
+        self.witness_count = witnesses.len() as u8;
+        self.witness = Some(witnesses);
+        Ok(())
+    }
+
+    /// Checks that the owner of every input is a valid standard or multisig address and,
+    /// for Dark Tx, that each sender owner key is the key of the proof account at the same index.
+    /// The owner key of a multisig input is the account key of its verified multisig witness.
+    pub fn verify_input_owners(&self) -> Result<(), TxError> {
+        // sender accounts come first in the delta account vector
+        let senders_count = self.proof.updated_sender_epsilon_accounts.len();
+        for (index, input) in self.inputs.iter().enumerate() {
+            let owner = input
+                .input
+                .owner()
+                .ok_or(TxError::InvalidOwnerAddress { index })?;
+            let owner_pk: RistrettoPublicKey = if multisig::is_multisig_owner(owner) {
+                let (account_key, _) =
+                    input_account(index, &self.inputs, &self.outputs, &self.witness)
+                        .map_err(|_| TxError::InvalidOwnerAddress { index })?
+                        .get_account();
+                account_key
+            } else {
+                // decoding the address validates the checksum
+                let address = address::Address::from_hex(owner, address::AddressType::Standard)
+                    .map_err(|_| TxError::InvalidOwnerAddress { index })?;
+                address
+                    .get_standard_address()
+                    .map_err(|_| TxError::InvalidOwnerAddress { index })?
+                    .public_key
+            };
+
+            // inputs are shuffled in QQ Tx. The shuffle proof binds them to the proof accounts
+            if self.shuffle_proof.is_none() && index < senders_count {
+                let (proof_pk, _) = match self.proof.delta_accounts.get(index) {
+                    Some(account) => account.get_account(),
+                    None => return Err(TxError::OwnerKeyMismatch { index }),
+                };
+                if owner_pk != proof_pk {
+                    return Err(TxError::OwnerKeyMismatch { index });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the witness each input refers to, i.e., the multisig witness of a multisig coin
+    /// and the zero balance proof of a fresh receiver, and that every witness is referenced.
+    /// The zero balance proofs themselves are verified with the transfer proof.
+    pub fn verify_witnesses(&self) -> Result<(), TxError> {
+        let witnesses = self.witness.as_deref().unwrap_or_default();
+        let mut referenced = Vec::with_capacity(witnesses.len());
+        for (index, input) in self.inputs.iter().enumerate() {
+            let is_multisig = input
+                .as_owner_address()
+                .map_or(false, |owner| multisig::is_multisig_owner(owner));
+            if !is_multisig && input.get_utxo() != zkvm::Utxo::default() {
+                // standard coins are signed in the transfer proof
+                continue;
+            }
+            let witness = input_witness(witnesses, index, input)?;
+            let is_valid = match witness {
+                Witness::Multisig(multisig_witness) if is_multisig => multisig_witness
+                    .verify(index, &self.inputs, &self.outputs)
+                    .is_ok(),
+                Witness::ZeroBalanceProof(_) => !is_multisig,
+                _ => false,
+            };
+            if !is_valid {
+                return Err(TxError::WitnessMismatch { index });
+            }
+            referenced.push(input.get_witness_index() as usize);
+        }
+        check_witness_references(witnesses.len(), referenced)
+    }
+
+    /// Checks that the input, output and witness counts of the header match the tx and
+    /// that the sender and receiver counts of the proof fit its account vectors.
+    pub fn verify_header(&self) -> Result<(), TxError> {
+        check_header_count("input_count", self.input_count, self.inputs.len())?;
+        check_header_count("output_count", self.output_count, self.outputs.len())?;
+        let witness_len = self.witness.as_ref().map_or(0, |w| w.len());
+        check_header_count("witness_count", self.witness_count.into(), witness_len)?;
+        if self.inputs.len() != self.outputs.len() {
+            return Err(TxError::HeaderMismatch {
+                field: "output_count",
+                declared: self.outputs.len(),
+                actual: self.inputs.len(),
+            });
+        }
+        // one delta and epsilon account per input
+        for (field, accounts) in [
+            ("delta_accounts", &self.proof.delta_accounts),
+            ("epsilon_accounts", &self.proof.epsilon_accounts),
+        ] {
+            if accounts.len() != self.inputs.len() {
+                return Err(TxError::HeaderMismatch {
+                    field,
+                    declared: accounts.len(),
+                    actual: self.inputs.len(),
+                });
+            }
+        }
+        // senders and receivers come first in the account vectors
+        let senders_count = self.proof.updated_sender_epsilon_accounts.len();
+        let accounts_count = senders_count + self.proof.receivers_count;
+        if senders_count == 0 || accounts_count > self.inputs.len() {
+            return Err(TxError::HeaderMismatch {
+                field: "senders_and_receivers_count",
+                declared: accounts_count,
+                actual: self.inputs.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the senders of the proof pay the receivers plus the declared fee.
+    pub fn verify_fee(&self) -> Result<(), TxError> {
+        self.proof
+            .verify_fee_balance(self.fee)
+            .map_err(|_| TxError::FeeMismatch { fee: self.fee })
+    }
+
     pub fn verify(&self) -> Result<(), &'static str> {
+        // the header counts are used for fee and size accounting
+        self.verify_header()
+            .map_err(|_| "Tx Verification failed. Header counts do not match the transaction.")?;
+
+        // the declared fee must be the one paid in the proof
+        self.verify_fee()
+            .map_err(|_| "Tx Verification failed. Declared fee does not match the proof.")?;
+
+        //check the input owners against the proof accounts
+        self.verify_input_owners()
+            .map_err(|_| "Tx Verification failed. Input owner does not match the proof account.")?;
+
+        //check the witness referenced by each input
+        self.verify_witnesses().map_err(|e| match e {
+            TxError::OrphanWitness { .. } => {
+                "Tx Verification failed. Witness is not referenced by any input."
+            }
+            _ => "Tx Verification failed. Witness does not match its input.",
+        })?;
+
         //convert Inputs and Outputs to Just Accounts
         let inputs = self.get_input_values();
         let outputs = self.get_output_values();
@@ -577,8 +861,8 @@ impl TransferTransaction {
         let mut input_accounts = Vec::<Account>::new();
         let mut output_accounts = Vec::<Account>::new();
 
-        for (inp, out) in inputs.iter().zip(outputs.iter()) {
-            let inp_acc = inp.to_quisquis_account()?;
+        for (index, (_, out)) in inputs.iter().zip(outputs.iter()).enumerate() {
+            let inp_acc = input_account(index, &inputs, &outputs, &self.witness)?;
             let out_acc = out.to_quisquis_account()?;
             input_accounts.push(inp_acc);
             output_accounts.push(out_acc);
@@ -0,0 +1,530 @@
+//! Signatures over relayer requests.
+//!
+//! Every request is signed over its canonical encoding, which carries the
+//! request type, the account address and a nonce. Each request type uses its
+//! own transcript label, so a signature made for one type never verifies as
+//! another, and the relayer rejects any nonce not above the last one it saw
+//! for the account.
+//!
+//! The relayer contract itself is brought on chain by the deploy transaction
+//! built in [`deploy_relayer_contract`], and moved to the script address of an
+//! upgraded program tree by the migration built in [`build_contract_migration`].
+
+use crate::programs::{
+    create_program_call_proof, get_contract_deploy_program, get_contract_migration_program,
+    program_tree_script_address, CONTRACT_DEPLOY_PROGRAM_INDEX, CONTRACT_MIGRATION_PROGRAM_INDEX,
+};
+use crate::{ScriptTransaction, Transaction};
+use address::{Address, AddressType};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::{keys::PublicKey, ristretto::RistrettoPublicKey, ristretto::RistrettoSecretKey};
+use serde::{Deserialize, Serialize};
+use zkschnorr::Signature;
+use zkvm::zkos_types::{
+    Input, InputData, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo,
+};
+use zkvm::{Commitment, String as ZkvmString};
+
+/// Fields common to every relayer request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayerRequestBody {
+    /// Hex address of the account signing the request
+    pub account: String,
+    /// Strictly increasing per account. A unix timestamp works as well
+    pub nonce: u64,
+    /// Request specific data, e.g. the order id to cancel
+    pub data: Vec<u8>,
+}
+
+/// Requests a client signs before the relayer acts on them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelayerRequest {
+    QueryTraderOrder(RelayerRequestBody),
+    QueryLendOrder(RelayerRequestBody),
+    CancelTraderOrder(RelayerRequestBody),
+    SettleRequest(RelayerRequestBody),
+}
+
+impl RelayerRequest {
+    pub fn body(&self) -> &RelayerRequestBody {
+        match self {
+            RelayerRequest::QueryTraderOrder(body)
+            | RelayerRequest::QueryLendOrder(body)
+            | RelayerRequest::CancelTraderOrder(body)
+            | RelayerRequest::SettleRequest(body) => body,
+        }
+    }
+
+    /// Transcript label of the request type
+    pub fn label(&self) -> &'static [u8] {
+        match self {
+            RelayerRequest::QueryTraderOrder(_) => b"RelayerQueryTraderOrder",
+            RelayerRequest::QueryLendOrder(_) => b"RelayerQueryLendOrder",
+            RelayerRequest::CancelTraderOrder(_) => b"RelayerCancelTraderOrder",
+            RelayerRequest::SettleRequest(_) => b"RelayerSettleRequest",
+        }
+    }
+
+    /// Canonical bytes signed for the request.
+    /// bincode writes the variant index first, followed by the body fields in order.
+    pub fn to_signing_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+}
+
+fn account_public_key(account: &str) -> Result<RistrettoPublicKey, &'static str> {
+    let address = Address::from_hex(account, AddressType::default())?;
+    Ok(address.into())
+}
+
+/// Signs `req` with the secret key of the account named in its body.
+pub fn sign_relayer_request(
+    sk: &RistrettoSecretKey,
+    req: &RelayerRequest,
+) -> Result<Signature, &'static str> {
+    let pubkey = account_public_key(&req.body().account)?;
+    Ok(pubkey.sign_msg(&req.to_signing_bytes(), sk, req.label()))
+}
+
+/// Verifies a signed relayer request from `address`.
+/// Returns the nonce of the request, which the caller stores as the new
+/// `last_seen_nonce` of the account.
+pub fn verify_relayer_request(
+    address: &str,
+    sig: &Signature,
+    req: &RelayerRequest,
+    last_seen_nonce: u64,
+) -> Result<u64, &'static str> {
+    let body = req.body();
+    if body.account != address {
+        return Err("RelayerError::AccountMismatch");
+    }
+    if body.nonce <= last_seen_nonce {
+        return Err("RelayerError::StaleNonce");
+    }
+    let pubkey = account_public_key(address)?;
+    pubkey
+        .verify_msg(&req.to_signing_bytes(), sig, req.label())
+        .map_err(|_| "RelayerError::InvalidSignature")?;
+    Ok(body.nonce)
+}
+
+/// Verifies a signature over raw message bytes with the "PublicKeySign" label.
+/// The signature is not bound to a request type, an account or a time, so it can be replayed.
+#[deprecated(note = "use verify_relayer_request")]
+pub fn verify_query_order(
+    address: &str,
+    sig: &Signature,
+    message: &[u8],
+) -> Result<(), &'static str> {
+    let pubkey = account_public_key(address)?;
+    pubkey
+        .verify_msg(message, sig, ("PublicKeySign").as_bytes())
+        .map_err(|_| "RelayerError::InvalidSignature")
+}
+
+/// Builds the transaction deploying the relayer contract.
+///
+/// The coin of `amount` at `utxo`, owned by `owner` and encrypted with `scalar`, is
+/// locked into a Memo under the relayer script address. The zero State input is
+/// moved to the initial contract State holding `amount` as its value and
+/// `pool_share` as its state variable, and `sk` signs both inputs.
+/// Returns the transaction, the blinding of the State value commitment and the
+/// prover view of the deployed State, which the relayer spends on the next call.
+pub fn deploy_relayer_contract(
+    sk: &RistrettoSecretKey,
+    utxo: Utxo,
+    owner: &str,
+    amount: u64,
+    scalar: Scalar,
+    pool_share: u64,
+    fee: u64,
+) -> Result<(Transaction, Scalar, Output), &'static str> {
+    let address = Address::from_hex(owner, AddressType::Standard)?;
+    let network = address.get_standard_address()?.network;
+    let pk: RistrettoPublicKey = address.into();
+    let script_address = program_tree_script_address(network);
+
+    // coin input and the memo locking its value, with the same blinding
+    let encrypt = ElGamalCommitment::generate_commitment(&pk, scalar, Scalar::from(amount));
+    let coin = Input::coin(InputData::coin(
+        utxo,
+        OutputCoin::new(encrypt, owner.to_string()),
+        0,
+    ));
+    let memo = OutputMemo::new(
+        script_address.clone(),
+        owner.to_string(),
+        Commitment::blinded_with_factor(amount, scalar),
+        None,
+        0u32,
+    );
+
+    // the State does not exist yet, the witness proves the input commits to zeros
+    let zero_state = OutputState {
+        nonce: 0,
+        script_address: script_address.clone(),
+        owner: owner.to_string(),
+        commitment: Commitment::blinded(0u64),
+        state_variables: Some(vec![ZkvmString::from(Commitment::blinded(0u64))]),
+        timebounds: 0,
+    };
+    let blinding = Scalar::random(&mut rand::thread_rng());
+    let state = OutputState {
+        nonce: 1,
+        script_address,
+        owner: owner.to_string(),
+        commitment: Commitment::blinded_with_factor(amount, blinding),
+        state_variables: Some(vec![ZkvmString::from(Commitment::blinded(pool_share))]),
+        timebounds: 0,
+    };
+    let state = Output::state(OutputData::state(state));
+
+    let inputs = vec![
+        coin,
+        Input::state(InputData::state(Utxo::default(), zero_state, None, 1)),
+    ];
+    let outputs = vec![Output::memo(OutputData::memo(memo)), state.clone()];
+    let call_proof = create_program_call_proof(CONTRACT_DEPLOY_PROGRAM_INDEX, network)?;
+    let tx = ScriptTransaction::create_script_transaction(
+        &[sk.clone(), sk.clone()],
+        get_contract_deploy_program(),
+        call_proof,
+        &inputs,
+        &outputs,
+        None,
+        true,
+        fee,
+    )
+    .map_err(|_| "Error::Contract deploy proof can not be created")?;
+    Ok((Transaction::from(tx), blinding, state))
+}
+
+/// Builds the transaction moving a contract State to `new_script_address`.
+///
+/// The State of `old_state_input`, the prover view of a State under the current
+/// program tree, is spent into a State of the next nonce under the new script address
+/// that carries the same value and state variables. The migration program proves
+/// the values are unchanged and `owner_sk` signs the move as the owner of the State.
+/// Returns the transaction and the prover view of the migrated State.
+pub fn build_contract_migration(
+    old_state_input: &Input,
+    new_script_address: &str,
+    owner_sk: &RistrettoSecretKey,
+    fee: u64,
+) -> Result<(Transaction, Output), &'static str> {
+    let old_state = old_state_input
+        .as_out_state()
+        .ok_or("Error::Only a State can be migrated")?;
+    if AddressType::from_hex(new_script_address)? != AddressType::Script {
+        return Err("Error::The new address is not a script address");
+    }
+    if old_state.script_address == new_script_address {
+        return Err("Error::The State is already under the new script address");
+    }
+    let address = Address::from_hex(&old_state.owner, AddressType::Standard)?;
+    let network = address.get_standard_address()?.network;
+
+    let utxo = *old_state_input
+        .as_utxo()
+        .ok_or("Error::Only a State can be migrated")?;
+    // the single input of the migration, without script data
+    let input = Input::state(InputData::state(utxo, old_state.clone(), None, 0));
+    let state = OutputState {
+        nonce: old_state.nonce + 1,
+        script_address: new_script_address.to_string(),
+        ..old_state.clone()
+    };
+    let state = Output::state(OutputData::state(state));
+    let call_proof = create_program_call_proof(CONTRACT_MIGRATION_PROGRAM_INDEX, network)?;
+    let tx = ScriptTransaction::create_script_transaction(
+        &[owner_sk.clone()],
+        get_contract_migration_program(),
+        call_proof,
+        &[input],
+        &[state.clone()],
+        None,
+        false,
+        fee,
+    )
+    .map_err(|_| "Error::Contract migration proof can not be created")?;
+    Ok((Transaction::from(tx), state))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::programs::{
+        create_program_tree, create_swap_deposit_memo, get_batch_settle_program, get_swap_program,
+        swap_error_term, swap_output_amount, PROGRAM_TREE_LABEL, SWAP_PROGRAM_INDEX,
+    };
+    use address::Network;
+    use quisquislib::keys::SecretKey;
+    use zkvm::merkle::{CallProof, Hasher, MerkleTree};
+
+    fn account() -> (RistrettoSecretKey, String) {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        (
+            sk,
+            Address::standard_address(Network::default(), pk).as_hex(),
+        )
+    }
+
+    fn body(account: &str, nonce: u64) -> RelayerRequestBody {
+        RelayerRequestBody {
+            account: account.to_string(),
+            nonce,
+            data: b"order-1".to_vec(),
+        }
+    }
+
+    // swap of a fresh swapper against `state`, holding `reserves`
+    fn swap_against(
+        state: OutputState,
+        relayer_sk: &RistrettoSecretKey,
+        reserves: (u64, u64),
+        call_proof: CallProof,
+    ) -> ScriptTransaction {
+        let mut rng = rand::thread_rng();
+        let (reserve_a, reserve_b) = reserves;
+        let swapper_sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let swapper_pk = RistrettoPublicKey::from_secret_key(&swapper_sk, &mut rng);
+        let swapper = Address::standard_address(Network::default(), swapper_pk).as_hex();
+        let in_a = 2_500u64;
+        let out_b = swap_output_amount(reserve_a, reserve_b, in_a).unwrap();
+        let error = swap_error_term(reserve_a, reserve_b, in_a, out_b).unwrap();
+        let coin_blinding = Scalar::random(&mut rng);
+        let encrypt =
+            ElGamalCommitment::generate_commitment(&swapper_pk, coin_blinding, Scalar::from(in_a));
+        let coin = Input::coin(InputData::coin(
+            Utxo::default(),
+            OutputCoin::new(encrypt, swapper.clone()),
+            0,
+        ));
+        let memo = create_swap_deposit_memo(
+            state.script_address.clone(),
+            swapper,
+            in_a,
+            coin_blinding,
+            out_b,
+        );
+        let next_state = OutputState {
+            nonce: state.nonce + 1,
+            script_address: state.script_address.clone(),
+            owner: state.owner.clone(),
+            commitment: Commitment::blinded(reserve_a + in_a),
+            state_variables: Some(vec![ZkvmString::from(Commitment::blinded(
+                reserve_b - out_b,
+            ))]),
+            timebounds: 0,
+        };
+        let script_data = vec![ZkvmString::from(Commitment::blinded(error))];
+        let inputs = vec![
+            coin,
+            Input::state(InputData::state(
+                Utxo::default(),
+                state,
+                Some(script_data),
+                1,
+            )),
+        ];
+        let outputs = vec![memo, Output::state(OutputData::state(next_state))];
+        ScriptTransaction::create_script_transaction(
+            &[swapper_sk, relayer_sk.clone()],
+            get_swap_program(),
+            call_proof,
+            &inputs,
+            &outputs,
+            None,
+            false,
+            1,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sign_verify_relayer_request_test() {
+        let (sk, address) = account();
+        let req = RelayerRequest::QueryTraderOrder(body(&address, 1));
+        let sig = sign_relayer_request(&sk, &req).unwrap();
+        assert_eq!(verify_relayer_request(&address, &sig, &req, 0), Ok(1));
+
+        let (_, other) = account();
+        assert_eq!(
+            verify_relayer_request(&other, &sig, &req, 0),
+            Err("RelayerError::AccountMismatch")
+        );
+    }
+
+    #[test]
+    fn replayed_relayer_request_test() {
+        let (sk, address) = account();
+        let req = RelayerRequest::CancelTraderOrder(body(&address, 5));
+        let sig = sign_relayer_request(&sk, &req).unwrap();
+
+        let last_seen = verify_relayer_request(&address, &sig, &req, 4).unwrap();
+        assert_eq!(
+            verify_relayer_request(&address, &sig, &req, last_seen),
+            Err("RelayerError::StaleNonce")
+        );
+
+        // bumping the nonce without signing again fails too
+        let bumped = RelayerRequest::CancelTraderOrder(body(&address, 6));
+        assert_eq!(
+            verify_relayer_request(&address, &sig, &bumped, last_seen),
+            Err("RelayerError::InvalidSignature")
+        );
+    }
+
+    #[test]
+    fn cross_type_relayer_request_test() {
+        let (sk, address) = account();
+        let query = RelayerRequest::QueryTraderOrder(body(&address, 1));
+        let sig = sign_relayer_request(&sk, &query).unwrap();
+
+        let cancel = RelayerRequest::CancelTraderOrder(body(&address, 1));
+        assert_eq!(
+            verify_relayer_request(&address, &sig, &cancel, 0),
+            Err("RelayerError::InvalidSignature")
+        );
+        let settle = RelayerRequest::SettleRequest(body(&address, 1));
+        assert_eq!(
+            verify_relayer_request(&address, &sig, &settle, 0),
+            Err("RelayerError::InvalidSignature")
+        );
+    }
+
+    #[test]
+    fn deploy_relayer_contract_test() {
+        let mut rng = rand::thread_rng();
+        let (relayer_sk, relayer) = account();
+        let (reserve_a, reserve_b) = (1_000_000u64, 500_000u64);
+        let (tx, blinding, deployed) = deploy_relayer_contract(
+            &relayer_sk,
+            Utxo::default(),
+            &relayer,
+            reserve_a,
+            Scalar::random(&mut rng),
+            reserve_b,
+            1,
+        )
+        .unwrap();
+        assert!(tx.clone().tx.to_script().unwrap().is_contract_deploy());
+        assert_eq!(tx.verify(), Ok(()));
+        let deployed = deployed.as_out_state().unwrap().clone();
+        assert_eq!(deployed.nonce, 1);
+        assert_eq!(
+            deployed.commitment.to_point(),
+            Commitment::blinded_with_factor(reserve_a, blinding).to_point()
+        );
+
+        // trade against the deployed state
+        let call_proof = create_program_call_proof(SWAP_PROGRAM_INDEX, Network::default()).unwrap();
+        let trade = swap_against(deployed, &relayer_sk, (reserve_a, reserve_b), call_proof);
+        assert!(!trade.is_contract_deploy());
+        assert_eq!(Transaction::from(trade.clone()).verify(), Ok(()));
+
+        // the proof of the deploy does not prove the swap, the verifier diagnostics are returned
+        let mut forged = trade;
+        forged.proof = tx.tx.to_script().unwrap().proof;
+        assert!(matches!(
+            Transaction::from(forged).verify(),
+            Err(crate::TxError::ScriptVerificationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn contract_migration_test() {
+        let mut rng = rand::thread_rng();
+        let (relayer_sk, relayer) = account();
+        let (reserve_a, reserve_b) = (1_000_000u64, 500_000u64);
+        let (_, _, deployed) = deploy_relayer_contract(
+            &relayer_sk,
+            Utxo::default(),
+            &relayer,
+            reserve_a,
+            Scalar::random(&mut rng),
+            reserve_b,
+            1,
+        )
+        .unwrap();
+        let deployed = deployed.as_out_state().unwrap().clone();
+
+        // the upgraded tree adds a program, which changes the script address
+        let mut programs = create_program_tree();
+        programs.push(get_batch_settle_program(1));
+        let root = MerkleTree::root(PROGRAM_TREE_LABEL, programs.iter());
+        let new_address = Address::script_address(Network::default(), root.0).as_hex();
+        assert_ne!(new_address, deployed.script_address);
+
+        let state_input =
+            Input::state(InputData::state(Utxo::default(), deployed.clone(), None, 0));
+        let (migration, migrated) =
+            build_contract_migration(&state_input, &new_address, &relayer_sk, 1).unwrap();
+        let script = migration.clone().tx.to_script().unwrap();
+        assert!(script.is_contract_migration());
+        assert_eq!(
+            script.get_script_address(),
+            Ok(deployed.script_address.clone())
+        );
+        assert_eq!(migration.verify(), Ok(()));
+        let migrated = migrated.as_out_state().unwrap().clone();
+        assert_eq!(migrated.script_address, new_address);
+        assert_eq!(migrated.nonce, deployed.nonce + 1);
+        assert_eq!(
+            build_contract_migration(&state_input, &deployed.script_address, &relayer_sk, 1)
+                .unwrap_err(),
+            "Error::The State is already under the new script address"
+        );
+
+        // a State can not be moved with value taken out of it
+        let drained = OutputState {
+            nonce: deployed.nonce + 1,
+            script_address: new_address.clone(),
+            commitment: Commitment::blinded(reserve_a - 1),
+            ..deployed.clone()
+        };
+        let drain = ScriptTransaction::create_script_transaction(
+            &[relayer_sk.clone()],
+            get_contract_migration_program(),
+            create_program_call_proof(CONTRACT_MIGRATION_PROGRAM_INDEX, Network::default())
+                .unwrap(),
+            &[state_input.clone()],
+            &[Output::state(OutputData::state(drained.clone()))],
+            None,
+            false,
+            1,
+        );
+        assert!(drain.map_or(true, |tx| Transaction::from(tx).verify().is_err()));
+        // nor by any other program of the old tree
+        let swap = ScriptTransaction::create_script_transaction(
+            &[relayer_sk.clone()],
+            get_swap_program(),
+            create_program_call_proof(SWAP_PROGRAM_INDEX, Network::default()).unwrap(),
+            &[state_input],
+            &[Output::state(OutputData::state(drained))],
+            None,
+            false,
+            1,
+        );
+        assert!(swap.map_or(true, |tx| tx.verify().is_err()));
+
+        // orders run against the new script address with the call proofs of the new tree
+        let hasher = Hasher::new(PROGRAM_TREE_LABEL);
+        let call_proof = CallProof::create_call_proof(
+            &programs,
+            SWAP_PROGRAM_INDEX,
+            &hasher,
+            Network::default(),
+        )
+        .unwrap();
+        let trade = swap_against(migrated, &relayer_sk, (reserve_a, reserve_b), call_proof);
+        assert_eq!(Transaction::from(trade).verify(), Ok(()));
+    }
+}
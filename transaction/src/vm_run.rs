@@ -1,17 +1,76 @@
-use bulletproofs::r1cs::{self, R1CSProof};
-use bulletproofs::{BulletproofGens, PedersenGens};
+use bulletproofs::r1cs::{self, ConstraintSystem, R1CSProof};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use merlin::Transcript;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use zkvm::encoding::{Encodable, Reader};
 
 use zkvm::constraints::Commitment;
-use zkvm::errors::VMError;
+use zkvm::errors::{VMError, VerificationFailure, VerificationPhase};
 use zkvm::ops::Instruction;
 use zkvm::program::{Program, ProgramItem};
-use zkvm::vm::{VMRun, VMScript};
+use zkvm::proof_context::ProofContext;
+use zkvm::vm::{EvalReport, VMRun, VMScript};
 use zkvm::zkos_types::{Input, Output};
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Generator capacity of the verifier, and the least capacity preallocated by the prover.
+pub const DEFAULT_GENS_CAPACITY: usize = zkvm::DEFAULT_GENS_CAPACITY;
+
+/// Options of the proof generation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProverOpts {
+    /// Runs the independent parts of the proof generation on the rayon thread pool.
+    /// Ignored unless the crate is built with the `parallel` feature.
+    pub parallel: bool,
+    /// Number of multipliers to grow the generators of the proof context to.
+    /// Estimated from the programs if not set.
+    pub gens_capacity_hint: Option<usize>,
+}
+
+impl ProverOpts {
+    pub fn parallel() -> Self {
+        ProverOpts {
+            parallel: true,
+            gens_capacity_hint: None,
+        }
+    }
+
+    /// Whether the parallel path is taken in this build.
+    pub fn is_parallel(&self) -> bool {
+        cfg!(feature = "parallel") && self.parallel
+    }
+
+    /// Generator capacity to preallocate for the programs, a power of two
+    /// of at least `DEFAULT_GENS_CAPACITY`.
+    pub fn gens_capacity(&self, programs: &[Program]) -> usize {
+        self.gens_capacity_hint
+            .unwrap_or_else(|| estimate_multipliers(programs))
+            .max(DEFAULT_GENS_CAPACITY)
+            .next_power_of_two()
+    }
+}
+
+/// Upper estimate of the multipliers allocated by the programs, without the
+/// range proofs and constraints of the stack initialization.
+pub fn estimate_multipliers(programs: &[Program]) -> usize {
+    programs
+        .iter()
+        .flat_map(|program| program.to_vec())
+        .map(|instruction| match instruction {
+            // bit decomposition of a u64
+            Instruction::Range => 64,
+            Instruction::Mul | Instruction::Alloc(_) => 1,
+            // secret constraints are combined with one multiplier per clause
+            Instruction::And | Instruction::Or | Instruction::Not => 2,
+            Instruction::Verify => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
 /// This is the entry point API for creating a proof for Script transaction.
 /// Prover passes the list of instructions through the VM,
 /// creates a R1CS proof and returns the full proof
@@ -59,28 +118,114 @@ impl<'g> Prover<'g> {
         contract_deploy_flag: bool,
         tx_data: Option<zkvm::String>,
     ) -> Result<(Vec<u8>, R1CSProof), VMError> {
-        // Prepare the constraint system
-        let bp_gens = BulletproofGens::new(256, 1);
-        let pc_gens = PedersenGens::default();
-        let cs = r1cs::Prover::new(&pc_gens, Transcript::new(b"ZkVM.r1cs"));
+        Prover::build_proof_with_opts(
+            program,
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            tx_data,
+            ProverOpts::default(),
+        )
+    }
 
-        // Serialize the tx program
-        let mut bytecode = Vec::new();
+    /// Builds a proof with a given list of instructions and prover options.
+    pub fn build_proof_with_opts(
+        program: Program,
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+        opts: ProverOpts,
+    ) -> Result<(Vec<u8>, R1CSProof), VMError> {
+        let (mut bytecodes, proof) = Prover::build_proof_bundle_with_opts(
+            vec![program],
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            tx_data,
+            opts,
+        )?;
+        Ok((bytecodes.remove(0), proof))
+    }
 
-        program.encode(&mut bytecode)?;
+    /// Builds a single proof for a sequence of programs.
+    /// The stack is initialized once with the inputs and outputs, and the programs
+    /// run in order on the same stack and constraint system.
+    /// Returns the bytecode of each program in the same order.
+    pub fn build_proof_bundle(
+        programs: Vec<Program>,
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+    ) -> Result<(Vec<Vec<u8>>, R1CSProof), VMError> {
+        Prover::build_proof_bundle_with_opts(
+            programs,
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            tx_data,
+            ProverOpts::default(),
+        )
+    }
+
+    /// Builds a single proof for a sequence of programs with the given prover options.
+    /// The proof does not depend on the options, only the time taken to build it.
+    pub fn build_proof_bundle_with_opts(
+        programs: Vec<Program>,
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+        opts: ProverOpts,
+    ) -> Result<(Vec<Vec<u8>>, R1CSProof), VMError> {
+        Prover::build_proof_bundle_in(
+            ProofContext::global(),
+            programs,
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            tx_data,
+            opts,
+        )
+    }
+
+    /// Builds a single proof for a sequence of programs with the generators of `context`.
+    pub fn build_proof_bundle_in(
+        context: &ProofContext,
+        programs: Vec<Program>,
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+        opts: ProverOpts,
+    ) -> Result<(Vec<Vec<u8>>, R1CSProof), VMError> {
+        if programs.is_empty() {
+            return Err(VMError::InvalidFormat);
+        }
+        // Prepare the constraint system
+        let cs = r1cs::Prover::new(context.pc_gens(), Transcript::new(b"ZkVM.r1cs"));
+
+        // Serialize the tx programs
+        // the encoding does not touch the constraint system and can run out of order
+        let bytecodes = encode_programs(&programs, opts)?;
 
         let mut prover = Prover { cs };
 
+        let mut runs = programs.iter().map(|program| ProverRun {
+            program: program.to_vec().into(),
+        });
         let mut vm: VMScript<'_, r1cs::Prover<'_, Transcript>, Prover<'_>> = VMScript::new(
-            ProverRun {
-                program: program.to_vec().into(),
-            },
+            runs.next().unwrap(),
             &mut prover,
             inputs,
             outputs,
             tx_data,
             // contract_init_flag,
         );
+        for run in runs {
+            vm.queue_run(run);
+        }
 
         // initialize the Stack with inputs and outputs
         match contract_deploy_flag {
@@ -96,18 +241,87 @@ impl<'g> Prover<'g> {
         // let init_result = vm.initialize_stack()?;
         // println!("VM initialized result {:?}", init_result);
 
-        // run the program to create a R1CS circuit
+        // run the programs to create a R1CS circuit
         let run_result = vm.run()?;
         println!("Vm run result {:?}", run_result);
 
+        // the shared generators are grown to the estimate, or to the circuit size if the
+        // estimate was too low
+        let padded_multipliers = prover.cs.metrics().multipliers.next_power_of_two();
+        let bp_gens = context.bp_gens(opts.gens_capacity(&programs).max(padded_multipliers));
+
         // Generate the R1CS proof
         let proof = prover
             .cs
-            .prove(&bp_gens)
+            .prove(bp_gens)
             .map_err(|_| VMError::InvalidR1CSProof)?;
         // Defer signing of the transaction to the UnsignedTx API.
-        Ok((bytecode, proof))
+        Ok((bytecodes, proof))
+    }
+}
+
+// bytecode of each program, in order
+fn encode_programs(programs: &[Program], opts: ProverOpts) -> Result<Vec<Vec<u8>>, VMError> {
+    let encode = |program: &Program| -> Result<Vec<u8>, VMError> {
+        let mut bytecode = Vec::new();
+        program.encode(&mut bytecode)?;
+        Ok(bytecode)
+    };
+    if opts.is_parallel() {
+        #[cfg(feature = "parallel")]
+        return programs.par_iter().map(encode).collect();
     }
+    programs.iter().map(encode).collect()
+}
+
+/// Runs `a` and `b`, concurrently if the parallel path is taken.
+#[cfg(feature = "parallel")]
+pub fn join<A, B, RA, RB>(opts: ProverOpts, a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    if opts.is_parallel() {
+        rayon::join(a, b)
+    } else {
+        (a(), b())
+    }
+}
+
+/// Runs `a` and `b`, concurrently if the parallel path is taken.
+#[cfg(not(feature = "parallel"))]
+pub fn join<A, B, RA, RB>(_opts: ProverOpts, a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA,
+    B: FnOnce() -> RB,
+{
+    (a(), b())
+}
+
+/// Maps the enumerated items in order, in parallel if the parallel path is taken.
+#[cfg(feature = "parallel")]
+pub fn map_enumerated<T, R, F>(opts: ProverOpts, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn((usize, &T)) -> R + Sync + Send,
+{
+    if opts.is_parallel() {
+        items.par_iter().enumerate().map(f).collect()
+    } else {
+        items.iter().enumerate().map(f).collect()
+    }
+}
+
+/// Maps the enumerated items in order, in parallel if the parallel path is taken.
+#[cfg(not(feature = "parallel"))]
+pub fn map_enumerated<T, R, F>(_opts: ProverOpts, items: &[T], f: F) -> Vec<R>
+where
+    F: Fn((usize, &T)) -> R,
+{
+    items.iter().enumerate().map(f).collect()
 }
 
 /// This is the entry point API for verifying a R1CS proof.
@@ -167,44 +381,177 @@ impl Verifier {
         contract_deploy_flag: bool,
         tx_data: Option<zkvm::String>,
     ) -> Result<bool, VMError> {
-        let bp_gens = BulletproofGens::new(256, 1);
-        //print!("BP Gens in verify_proof {:?}", bp_gens);
-        let pc_gens = PedersenGens::default();
+        Verifier::verify_r1cs_proof_bundle(
+            proof,
+            std::slice::from_ref(program),
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            tx_data,
+        )
+    }
+
+    /// Verifies the R1CS proof of a sequence of programs created with `Prover::build_proof_bundle`.
+    pub fn verify_r1cs_proof_bundle(
+        proof: &R1CSProof,
+        programs: &[Vec<u8>],
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+    ) -> Result<bool, VMError> {
+        Verifier::verify_r1cs_proof_bundle_in(
+            ProofContext::global(),
+            proof,
+            programs,
+            inputs,
+            outputs,
+            contract_deploy_flag,
+            tx_data,
+        )
+    }
+
+    /// Verifies the R1CS proof of a sequence of programs with the generators of `context`.
+    pub fn verify_r1cs_proof_bundle_in(
+        context: &ProofContext,
+        proof: &R1CSProof,
+        programs: &[Vec<u8>],
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+    ) -> Result<bool, VMError> {
+        if programs.is_empty() {
+            return Err(VMError::InvalidFormat);
+        }
         let cs = r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs"));
 
         let mut verifier = Verifier { cs };
 
         let mut vm = VMScript::new(
-            VerifierRun::new(program.clone()),
+            VerifierRun::new(programs[0].clone()),
             &mut verifier,
             inputs,
             outputs,
             tx_data,
             // contract_init_flag,
         );
+        for program in programs[1..].iter() {
+            vm.queue_run(VerifierRun::new(program.clone()));
+        }
 
         // initialize the Stack with inputs and outputs
-        match contract_deploy_flag {
-            false => {
-                let _init_result = vm.initialize_stack()?;
-            }
-            true => {
-                let _init_result = vm.initialize_deploy_contract_stack()?;
-            }
+        let init_result = match contract_deploy_flag {
+            false => vm.initialize_stack(),
+            true => vm.initialize_deploy_contract_stack(),
+        };
+        if let Err(err) = init_result {
+            let depth = vm.stack_depth();
+            return Err(VerificationFailure::new(VerificationPhase::StackInit, depth, err).into());
         }
-        //let _init_result = vm.initialize_stack()?;
-        // run the program to create a proof
-        let _run_result = vm.run()?;
-
+        // run the programs to create a proof
+        vm.run_with_diagnostics()?;
 
         // Verify the R1CS proof
+        // bulletproofs does not report which gadget failed, so `gadget_index` stays empty
         verifier
             .cs
-            .verify(&proof, &pc_gens, &bp_gens)
-            .map_err(|_| VMError::InvalidR1CSProof)?;
+            .verify(
+                proof,
+                context.pc_gens(),
+                context.bp_gens(DEFAULT_GENS_CAPACITY),
+            )
+            .map_err(|err| {
+                VerificationFailure::new(
+                    VerificationPhase::ConstraintSystem,
+                    0,
+                    VMError::R1CSError(err),
+                )
+            })?;
 
         Ok(true)
     }
+
+    /// Evaluates a sequence of programs against the inputs and outputs without a proof.
+    /// Commitments are checked in cleartext where their openings are known, constraints over
+    /// closed commitments are counted as unresolved. See `VMScript::eval_only`.
+    pub fn eval_program_bundle(
+        programs: &[Vec<u8>],
+        inputs: &[Input],
+        outputs: &[Output],
+        contract_deploy_flag: bool,
+        tx_data: Option<zkvm::String>,
+    ) -> Result<EvalResult, VMError> {
+        if programs.is_empty() {
+            return Err(VMError::InvalidFormat);
+        }
+        let cs = r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs"));
+        let mut verifier = Verifier { cs };
+
+        let mut vm = VMScript::new(
+            VerifierRun::new(programs[0].clone()),
+            &mut verifier,
+            inputs,
+            outputs,
+            tx_data,
+        );
+        for program in programs[1..].iter() {
+            vm.queue_run(VerifierRun::new(program.clone()));
+        }
+        let init_result = match contract_deploy_flag {
+            false => vm.initialize_stack(),
+            true => vm.initialize_deploy_contract_stack(),
+        };
+        if let Err(err) = init_result {
+            let depth = vm.stack_depth();
+            return Err(VerificationFailure::new(VerificationPhase::StackInit, depth, err).into());
+        }
+        Ok(EvalResult::from(vm.eval_only()))
+    }
+}
+
+/// Outcome of a read-only program evaluation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalResult {
+    /// True if every constraint holds, None if some depend on closed commitments
+    pub satisfiable: Option<bool>,
+    /// Index of the failing instruction, counted across the programs
+    pub failed_instruction: Option<usize>,
+    pub failed_opcode: Option<u8>,
+    pub error: Option<std::string::String>,
+    /// Items left on the stack, bottom first
+    pub stack: Vec<std::string::String>,
+    pub unresolved_constraints: usize,
+}
+
+impl From<EvalReport> for EvalResult {
+    fn from(report: EvalReport) -> Self {
+        EvalResult {
+            satisfiable: report.satisfiable(),
+            failed_instruction: report
+                .failure
+                .as_ref()
+                .and_then(|failure| failure.instruction_index),
+            failed_opcode: report.failure.as_ref().and_then(|failure| failure.opcode),
+            error: report
+                .failure
+                .as_ref()
+                .map(|failure| failure.cause.to_string()),
+            stack: report
+                .stack
+                .iter()
+                .map(|item| format!("{:?}", item))
+                .collect(),
+            unresolved_constraints: report.unresolved_constraints,
+        }
+    }
+}
+
+/// Decodes a R1CS proof, reporting malformed bytes as a decode phase failure.
+pub fn decode_r1cs_proof(bytes: &[u8]) -> Result<R1CSProof, VMError> {
+    R1CSProof::from_bytes(bytes).map_err(|err| {
+        VerificationFailure::new(VerificationPhase::Decode, 0, VMError::R1CSError(err)).into()
+    })
 }
 
 impl VerifierRun {
@@ -212,3 +559,171 @@ impl VerifierRun {
         VerifierRun { program, offset: 0 }
     }
 }
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::programs::{
+        create_program_call_proof, create_swap_deposit_memo, create_swap_states, get_swap_program,
+        program_tree_script_address, swap_output_amount, SWAP_PROGRAM_INDEX,
+    };
+    use crate::ScriptTransaction;
+    use address::{Address, Network};
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::elgamal::ElGamalCommitment;
+    use quisquislib::keys::{PublicKey, SecretKey};
+    use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+    use zkvm::zkos_types::{InputData, OutputCoin, Utxo};
+
+    // inputs and outputs of a swap, both inputs owned by the returned key
+    fn swap_io() -> (RistrettoSecretKey, Vec<Input>, Vec<Output>) {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let owner = Address::standard_address(Network::default(), pk).as_hex();
+        let script_address = program_tree_script_address(Network::default());
+        let (reserve_a, reserve_b, in_a) = (1_000_000u64, 500_000u64, 2_500u64);
+        let out_b = swap_output_amount(reserve_a, reserve_b, in_a).unwrap();
+
+        let blinding = Scalar::random(&mut rng);
+        let encrypt = ElGamalCommitment::generate_commitment(&pk, blinding, Scalar::from(in_a));
+        let coin = Input::coin(InputData::coin(
+            Utxo::default(),
+            OutputCoin::new(encrypt, owner.clone()),
+            0,
+        ));
+        let memo =
+            create_swap_deposit_memo(script_address.clone(), owner.clone(), in_a, blinding, out_b);
+        let (in_state, out_state) = create_swap_states(
+            Utxo::default(),
+            script_address,
+            owner,
+            1,
+            (reserve_a, reserve_b),
+            in_a,
+            out_b,
+        )
+        .unwrap();
+        (sk, vec![coin, in_state], vec![memo, out_state])
+    }
+
+    #[test]
+    fn gens_capacity_test() {
+        let programs = vec![get_swap_program()];
+        // two range proofs and a multiplication fit the default capacity
+        assert!(estimate_multipliers(&programs) < DEFAULT_GENS_CAPACITY);
+        assert_eq!(
+            ProverOpts::default().gens_capacity(&programs),
+            DEFAULT_GENS_CAPACITY
+        );
+        let opts = ProverOpts {
+            parallel: false,
+            gens_capacity_hint: Some(300),
+        };
+        assert_eq!(opts.gens_capacity(&programs), 512);
+    }
+
+    #[test]
+    fn prover_opts_proof_verifies_test() {
+        let (_, inputs, outputs) = swap_io();
+        let options = [
+            ProverOpts::default(),
+            ProverOpts::parallel(),
+            ProverOpts {
+                parallel: true,
+                gens_capacity_hint: Some(1024),
+            },
+            // the generators grow to the circuit size
+            ProverOpts {
+                parallel: false,
+                gens_capacity_hint: Some(1),
+            },
+        ];
+        for opts in options.iter() {
+            let (bytecode, proof) = Prover::build_proof_with_opts(
+                get_swap_program(),
+                &inputs,
+                &outputs,
+                false,
+                None,
+                *opts,
+            )
+            .unwrap();
+            assert!(
+                Verifier::verify_r1cs_proof(&proof, &bytecode, &inputs, &outputs, false, None)
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn prover_opts_script_transaction_test() {
+        let (sk, inputs, outputs) = swap_io();
+        let call_proof = create_program_call_proof(SWAP_PROGRAM_INDEX, Network::default()).unwrap();
+        let mut witness_counts = Vec::new();
+        for opts in [ProverOpts::default(), ProverOpts::parallel()].iter() {
+            let tx = ScriptTransaction::create_script_transaction_bundle_with_opts(
+                &[sk.clone(), sk.clone()],
+                vec![(get_swap_program(), call_proof.clone())],
+                &inputs,
+                &outputs,
+                None,
+                false,
+                1,
+                *opts,
+            )
+            .unwrap();
+            assert!(tx.verify_r1cs_proof(false).is_ok());
+            witness_counts.push(tx.witness.len());
+        }
+        assert_eq!(witness_counts[0], witness_counts[1]);
+    }
+    #[test]
+    fn proof_context_concurrent_verify_test() {
+        let (_, inputs, outputs) = swap_io();
+        let context = std::sync::Arc::new(ProofContext::new(1));
+        let (bytecodes, proof) = Prover::build_proof_bundle_in(
+            &context,
+            vec![get_swap_program()],
+            &inputs,
+            &outputs,
+            false,
+            None,
+            ProverOpts::default(),
+        )
+        .unwrap();
+        let proof = std::sync::Arc::new((bytecodes, proof, inputs, outputs));
+
+        // the verifiers read the generators while another thread grows them
+        let grower = {
+            let context = context.clone();
+            std::thread::spawn(move || {
+                for capacity in [512, 1024, 2048, 4096] {
+                    assert_eq!(context.bp_gens(capacity).gens_capacity, capacity);
+                }
+            })
+        };
+        let verifiers: Vec<_> = (0..8)
+            .map(|_| {
+                let (context, proof) = (context.clone(), proof.clone());
+                std::thread::spawn(move || {
+                    let (bytecodes, proof, inputs, outputs) = &*proof;
+                    for _ in 0..4 {
+                        assert!(Verifier::verify_r1cs_proof_bundle_in(
+                            &context, proof, bytecodes, inputs, outputs, false, None
+                        )
+                        .unwrap());
+                    }
+                })
+            })
+            .collect();
+        grower.join().unwrap();
+        for verifier in verifiers {
+            verifier.join().unwrap();
+        }
+        assert_eq!(context.capacity(), 4096);
+    }
+}
@@ -0,0 +1,107 @@
+//! Weight of a transaction, the verification work it demands from a block.
+//!
+//! The weight of a tx is the gas of its encoded bytes, [`GAS_PER_BYTE`] each, plus
+//! the gas estimated for its proofs: [`GAS_PER_INPUT`] per input and
+//! [`GAS_PER_MULTIPLIER`] per multiplier of its programs, capped at [`MAX_GAS_PER_TX`].
+//! Block processing bounds the summed weight of the txs of a block, and the txCommit
+//! of the node rejects a tx no block could hold.
+
+use crate::constants::{GAS_PER_BYTE, MAX_GAS_PER_TX};
+use crate::vm_run::estimate_multipliers;
+use crate::{Transaction, TransactionData};
+use zkvm::program::Program;
+
+/// Gas of the proofs of an input, a shuffle or same value proof.
+pub const GAS_PER_INPUT: u64 = 10_000;
+/// Gas of an R1CS multiplier allocated by a program.
+pub const GAS_PER_MULTIPLIER: u64 = 100;
+/// Weight a block may hold unless `MAX_BLOCK_WEIGHT` is set.
+pub const DEFAULT_MAX_BLOCK_WEIGHT: u64 = 50_000_000;
+
+/// Weight of `len` bytes, for the block messages that carry no tx.
+pub fn bytes_weight(len: usize) -> u64 {
+    (len as u64).saturating_mul(GAS_PER_BYTE)
+}
+
+/// Largest encoded size of a tx a block of `max_block_weight` could hold, proofs aside.
+pub fn max_tx_size(max_block_weight: u64) -> u64 {
+    max_block_weight / GAS_PER_BYTE
+}
+
+/// Gas estimated for verifying the proofs of `tx`.
+/// A program that does not parse allocates no multipliers, its tx fails verification.
+pub fn estimate_gas(tx: &Transaction) -> u64 {
+    let inputs = tx.get_tx_inputs().len() as u64;
+    let multipliers = match &tx.tx {
+        TransactionData::TransactionScript(script) => {
+            let programs: Vec<Program> = script
+                .get_programs()
+                .into_iter()
+                .filter_map(|(program, _)| Program::parse(program).ok())
+                .collect();
+            estimate_multipliers(&programs) as u64
+        }
+        _ => 0,
+    };
+    inputs
+        .saturating_mul(GAS_PER_INPUT)
+        .saturating_add(multipliers.saturating_mul(GAS_PER_MULTIPLIER))
+        .min(MAX_GAS_PER_TX)
+}
+
+/// Weight of `tx`, its encoded size and its estimated gas.
+pub fn tx_weight(tx: &Transaction) -> u64 {
+    bytes_weight(tx.to_bytes().len()).saturating_add(estimate_gas(tx))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reference_tx::create_dark_reference_transaction;
+    use crate::ScriptTransaction;
+    use bulletproofs::r1cs::R1CSProof;
+    use zkvm::merkle::CallProof;
+
+    fn script_tx(program: Vec<u8>) -> Transaction {
+        let script = ScriptTransaction::set_script_transaction(
+            0u64,
+            0u64,
+            0u64,
+            vec![],
+            vec![],
+            program,
+            CallProof::default(),
+            R1CSProof::from_bytes(&[0u8; 32]).unwrap(),
+            vec![],
+            None,
+        );
+        Transaction::transaction_script(TransactionData::TransactionScript(script))
+    }
+
+    #[test]
+    fn tx_weight_test() {
+        let tx = create_dark_reference_transaction();
+        let inputs = tx.get_tx_inputs().len() as u64;
+        assert_eq!(estimate_gas(&tx), inputs * GAS_PER_INPUT);
+        assert_eq!(
+            tx_weight(&tx),
+            tx.to_bytes().len() as u64 * GAS_PER_BYTE + inputs * GAS_PER_INPUT
+        );
+
+        // two range proofs of 64 multipliers each
+        let program = Program::build(|p| {
+            p.push(zkvm::String::U64(5)).range();
+            p.push(zkvm::String::U64(7)).range();
+        });
+        let tx = script_tx(program.to_bytes());
+        assert_eq!(estimate_gas(&tx), 128 * GAS_PER_MULTIPLIER);
+
+        // a program that does not parse is weighed by its bytes
+        let tx = script_tx(vec![0xffu8; 64]);
+        assert_eq!(estimate_gas(&tx), 0);
+        assert_eq!(tx_weight(&tx), bytes_weight(tx.to_bytes().len()));
+    }
+}
@@ -0,0 +1,37 @@
+//! Resolution of the witnesses of a tx.
+//!
+//! Every input needing a witness refers to it by its witness index. Witnesses are
+//! verified by iterating the inputs, so each input is checked against the witness it
+//! refers to. Several inputs may refer to the same witness, but every witness has to
+//! be referenced by at least one input of the tx.
+
+use crate::TxError;
+use zkvm::zkos_types::{Input, Witness};
+
+/// Witness of the input at `index`, an error if its witness index is out of range.
+pub(crate) fn input_witness<'a>(
+    witnesses: &'a [Witness],
+    index: usize,
+    input: &Input,
+) -> Result<&'a Witness, TxError> {
+    witnesses
+        .get(input.get_witness_index() as usize)
+        .ok_or(TxError::WitnessMismatch { index })
+}
+
+/// Checks that each of the `witness_count` witnesses is one of the `referenced` indices.
+pub(crate) fn check_witness_references(
+    witness_count: usize,
+    referenced: impl IntoIterator<Item = usize>,
+) -> Result<(), TxError> {
+    let mut is_referenced = vec![false; witness_count];
+    for index in referenced {
+        if let Some(flag) = is_referenced.get_mut(index) {
+            *flag = true;
+        }
+    }
+    match is_referenced.iter().position(|flag| !flag) {
+        Some(index) => Err(TxError::OrphanWitness { index }),
+        None => Ok(()),
+    }
+}
@@ -1,19 +1,22 @@
-use rpcclient::method::Method;
-use rpcclient::txrequest::{Resp, RpcBody, RpcRequest};
+use clap::Parser;
 use rpcserver::*;
-use serde_json::to_string;
 use std::thread;
-use std::time::Duration;
-use transaction::Transaction;
-use transactionapi::TransactionStatusId;
-use transactionapi::{rpcclient, rpcserver};
+use transactionapi::cli::{self, Cli, Command, ServeArgs};
+use transactionapi::config::{load_node_config, ApiConfig};
+use transactionapi::rpcserver;
 #[macro_use]
 extern crate lazy_static;
-use transaction::reference_tx::{
-    create_dark_reference_transaction, create_qq_reference_transaction,
+use std::sync::Arc;
+use utxo_in_memory::{
+    check_block_height_on_startup, init_utxo_state, install_global_state, zk_oracle_subscriber,
+    UtxoState,
 };
-use utxo_in_memory::{init_utxo, zk_oracle_subscriber};
 use utxo_in_memory::blockoperations::blockprocessing::read_telemetry_stats_from_file;
+use utxo_in_memory::blockoperations::pruning::memo_pruning_task;
+use utxo_in_memory::db::compaction::storage_compaction_task;
+use utxo_in_memory::config::config_value;
+use utxo_in_memory::network::NetworkStates;
+use utxo_in_memory::pgsql::NetworkStore;
 #[macro_use] extern crate rocket;
 use rocket::data::{Limits, ToByteUnit};
 use rocket::{State, response::content};
@@ -21,32 +24,149 @@ use prometheus::{Encoder, TextEncoder, Counter, Gauge, register_counter, registe
 
 
 fn main() {
-    init_utxo(); // Execute synchronously
-    read_telemetry_stats_from_file();
+    let command = Cli::parse().command();
+
+    // load the address magic bytes from the chain config before parsing any address,
+    // refusing to run on invalid ones rather than parsing with the defaults
+    let network_config = address::NetworkConfig::from_lookup(config_value)
+        .and_then(address::set_network_config);
+    if let Err(e) = network_config {
+        eprintln!("Invalid network config: {}", e);
+        std::process::exit(1);
+    }
+
+    match command {
+        Command::Serve(args) => serve(args),
+        command => {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            match rt.block_on(cli::run(command)) {
+                Ok(output) => println!("{}", output),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn serve(args: ServeArgs) {
+    // refuses to start on an invalid config, naming the key
+    let (store_config, api_config) = match load_node_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if args.print_config {
+        print!("{}{}", store_config.redacted(), api_config.redacted());
+        return;
+    }
+    set_zkos_oracle_url(api_config.zkos_oracle_url.as_str());
+
+    // refuse to start on a corrupt height file, see --force-resync-from
+    let block_height = check_block_height_on_startup();
+    // the rpc and ws servers use the state through the globals it is installed behind
+    let state = Arc::new(UtxoState::for_network(store_config.network));
+    if install_global_state(state.clone()).is_err() {
+        eprintln!("utxo state is already in use");
+        std::process::exit(1);
+    }
+    init_utxo_state(&state); // Execute synchronously
+    if let Some(height) = block_height {
+        state.utxo_storage.lock().unwrap().block_height = height as usize;
+    }
+    if let Err(e) = read_telemetry_stats_from_file(&state) {
+        println!("Telemetry stats not loaded: {}", e);
+    }
+
+    // the status served to the monitors is signed with the key of NODE_KEY_PATH
+    let node_key = match api_config.node_key() {
+        Ok(node_key) => node_key,
+        Err(e) => {
+            eprintln!("Invalid node key: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // the networks of SECONDARY_NETWORKS are tracked each from its own oracle, and
+    // written to the rows of their network in psql
+    let mut networks = NetworkStates::new(state.clone());
+    for (network, url) in store_config.secondary_networks {
+        let store = Arc::new(NetworkStore::new(network));
+        let secondary = Arc::new(UtxoState::secondary(network, store));
+        if let Err(e) = secondary.metrics.register_default() {
+            println!("Metrics of {} not registered: {}", network, e);
+        }
+        init_utxo_state(&secondary);
+        networks = networks.with_state(secondary.clone());
+        thread::spawn(move || {
+            zk_oracle_subscriber(secondary, url);
+        });
+    }
+    // committed txs are relayed to the peers of RELAY_PEERS, one hop
+    let mut node = RpcNode::new(state.clone(), TxRelay::new(api_config.relay_config()))
+        .with_audit(api_config.audit_config())
+        .with_node_key(node_key)
+        .with_networks(networks)
+        .with_chain_params(api_config.chain_params());
+    // served over TLS with RPC_TLS_CERT_PATH, the certificates are read again on SIGHUP
+    if let Some(tls_config) = api_config.tls_config() {
+        match ServerTls::load(tls_config) {
+            Ok(tls) => node = node.with_tls(tls),
+            Err(e) => {
+                eprintln!("Invalid TLS config: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    // audit records older than RPC_AUDIT_RETENTION_DAYS are removed
+    let audit_log = node.audit.clone();
+    thread::spawn(move || {
+        audit_pruning_task(audit_log);
+    });
+
+    let block_subscriber_url = store_config.block_subscriber_url;
+    let zk_subscriber_thread = thread::spawn(move || {
+        zk_oracle_subscriber(state, block_subscriber_url);
+    });
+
+    let rpc_bind_address = api_config.rpc_bind_address;
+    let rpc_server_thread = thread::spawn(move || {
+        rpcserver(rpc_bind_address, node);
+    });
+
+    let ws_bind_address = api_config.ws_bind_address;
+    let ws_server_thread = thread::spawn(move || {
+        ws_subscription_server(ws_bind_address);
+    });
 
-    let zk_subscriber_thread = thread::spawn(|| {
-        zk_oracle_subscriber();
+    // opt-in, memos are only pruned for script addresses with a pruning policy
+    thread::spawn(|| {
+        memo_pruning_task();
     });
 
-    let rpc_server_thread = thread::spawn(|| {
-        rpcserver();
+    // shrinks the utxo partitions after large deletions
+    thread::spawn(|| {
+        storage_compaction_task();
     });
 
 
     // Now start the async part
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(async_main());
+    rt.block_on(async_main(api_config));
 
     zk_subscriber_thread.join().unwrap();
     rpc_server_thread.join().unwrap();
+    ws_server_thread.join().unwrap();
 }
 
-async fn async_main() {
+async fn async_main(api_config: ApiConfig) {
 
-    let telemetry_server_thread = std::thread::spawn(|| {
+    let telemetry_server_thread = std::thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async {
-            run_telemetry_server().await;
+            run_telemetry_server(api_config.telemetry_port).await;
         })
     });
 
@@ -54,12 +174,12 @@ async fn async_main() {
 
 }
 
-async fn run_telemetry_server(){
+async fn run_telemetry_server(port: u16){
     println!("starting telemetry server");
         
     let figment = rocket::Config::figment()
     .merge(("address", "0.0.0.0"))
-    .merge(("port", 2500))
+    .merge(("port", port))
     .merge(("limits", Limits::new().limit("json", 2.mebibytes())));
 
     let rocket = rocket::custom(figment).mount("/", routes![telemetry_metrics]);
@@ -81,43 +201,3 @@ fn telemetry_metrics() -> String {
 }
 
 
-// fn main() {
-        // let handle = std::thread::Builder::new()
-    //     .name(String::from("rpc request"))
-    //     .spawn(move || {
-    //         std::thread::sleep(std::time::Duration::from_millis(5000));
-    //         let tx = create_dark_reference_transaction();
-
-    //         let tx_send: RpcBody<Transaction> = RpcRequest::new(tx, Method::TxCommit);
-    //         let res = tx_send.send("http://127.0.0.1:3030".to_string());
-
-    //         // println!("res:{:#?}", res.unwrap().bytes());
-
-    //         // let resp: Resp = serde_json::from_slice(&res.unwrap().bytes().unwrap()).unwrap();
-    //         // println!("res:{:#?}", resp);
-    //         match res {
-    //             Ok(x) => {
-    //                 println!("res1:{:#?}", x);
-    //             }
-    //             Err(arg) => {
-    //                 println!("errr1:{:#?}", arg);
-    //             }
-    //         }
-    //         let tx_send: RpcBody<TransactionStatusId> = RpcRequest::new(
-    //             TransactionStatusId {
-    //                 txid: "5f516a8a-fc68-4a34-b299-373dcaae6b4c".to_string(),
-    //             },
-    //             Method::TxStatus,
-    //         );
-    //         let res = tx_send.send("http://127.0.0.1:3030".to_string());
-    //         match res {
-    //             Ok(x) => {
-    //                 println!("res:{:#?}", x);
-    //             }
-    //             Err(arg) => {
-    //                 println!("errr:{:#?}", arg);
-    //             }
-    //         }
-    //     })
-    //     .unwrap();
-// }
\ No newline at end of file
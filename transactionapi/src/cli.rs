@@ -0,0 +1,698 @@
+//! Command line interface of the `api_server` binary.
+//!
+//! Without a subcommand, or with `serve`, the binary runs the node as before. The
+//! other subcommands are thin clients of a running node: they build transactions
+//! with the `transaction` crate and talk to the node through [`AsyncRpcClient`].
+//!
+//! `reference` builds reference script txs offline, see [`ReferenceTxs`].
+//!
+//! Secret keys are never taken from the command line. A subcommand signing a
+//! transaction reads the hex encoded key from the file named by `--sk-file`
+//! (or `ZKOS_SK_FILE`), or else from the `ZKOS_SK` environment variable.
+
+use crate::rpcclient::async_client::AsyncRpcClient;
+use address::{Address, AddressType, Network, Standard};
+use clap::{Args, Parser, Subcommand};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::accounts::Account;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::keys::{PublicKey, SecretKey};
+use quisquislib::ristretto::RistrettoSecretKey;
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use transaction::coin_selection::{select_coins, SelectionStrategy, SpendableCoin};
+use transaction::programs::PROGRAM_TREE_LABEL;
+use transaction::reference_tx::{
+    create_script_reference_transaction_with, create_settlement_reference_transaction_with,
+    reference_tx_id, Receiver,
+};
+use transaction::{ScriptTransaction, Transaction, TransactionData, TransferTransaction};
+use utxo_in_memory::blockoperations::balanceindexer::{decrypt_value, BSGS_TABLE};
+use zkvm::merkle::{CallProof, Hasher};
+use zkvm::zkos_types::{Input, Output, Utxo};
+use zkvm::Program;
+
+/// Environment variable holding the hex encoded secret key.
+pub const SECRET_KEY_ENV: &str = "ZKOS_SK";
+/// Environment variable naming the file of the secret key.
+pub const SECRET_KEY_FILE_ENV: &str = "ZKOS_SK_FILE";
+/// Default endpoint of the node.
+pub const DEFAULT_RPC_URL: &str = "http://127.0.0.1:3030";
+/// A dark transfer has at most 9 accounts, one of them is the receiver.
+pub const MAX_DARK_INPUTS: usize = 8;
+
+#[derive(Parser, Debug, PartialEq)]
+#[command(name = "api_server", about = "ZkOS node and client")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Arguments of `serve` when no subcommand is given
+    #[command(flatten)]
+    pub serve: ServeArgs,
+}
+
+impl Cli {
+    /// The subcommand to run, `serve` if none was given.
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Serve(self.serve))
+    }
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum Command {
+    /// Runs the node
+    Serve(ServeArgs),
+    /// Sends a dark transfer from an address of the key
+    SendDark(SendDarkArgs),
+    /// Calls a program of a registered script contract
+    SendScript(SendScriptArgs),
+    /// Prints the block including a tx
+    Status(StatusArgs),
+    /// Prints the coin utxos of an address
+    Utxos(UtxosArgs),
+    /// Decodes a hex encoded tx
+    Decode(DecodeArgs),
+    /// Prints reference script txs opening and settling a trader order
+    Reference(ReferenceArgs),
+}
+
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct ServeArgs {
+    /// Overwrites the persisted block height and resyncs from `height`
+    #[arg(long, value_name = "HEIGHT")]
+    pub force_resync_from: Option<u64>,
+    /// Prints the effective config, secrets redacted, and exits
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct KeyArgs {
+    /// File holding the hex encoded secret key, `ZKOS_SK` is used if not set
+    #[arg(long, env = SECRET_KEY_FILE_ENV, value_name = "PATH")]
+    pub sk_file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct RpcArgs {
+    /// Url of the node
+    #[arg(long, default_value = DEFAULT_RPC_URL)]
+    pub rpc: String,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct SendDarkArgs {
+    /// Sender address, owned by the secret key
+    #[arg(long)]
+    pub from: String,
+    /// Receiver address
+    #[arg(long)]
+    pub to: String,
+    #[arg(long)]
+    pub amount: u64,
+    #[arg(long, default_value_t = 0)]
+    pub fee: u64,
+    /// Prints the hex encoded tx instead of committing it
+    #[arg(long)]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub key: KeyArgs,
+    #[command(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct SendScriptArgs {
+    /// Name of the contract in the script registry
+    #[arg(long)]
+    pub program: String,
+    /// Index of the called program in the contract
+    #[arg(long, default_value_t = 0)]
+    pub index: usize,
+    /// JSON file with the `inputs`, `outputs` and optional `tx_data` of the tx
+    #[arg(long, value_name = "PATH")]
+    pub io_file: PathBuf,
+    #[arg(long, default_value_t = 0)]
+    pub fee: u64,
+    /// Builds the call proof for testnet addresses
+    #[arg(long)]
+    pub testnet: bool,
+    /// Prints the hex encoded tx instead of committing it
+    #[arg(long)]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub key: KeyArgs,
+    #[command(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct StatusArgs {
+    #[arg(long)]
+    pub txid: String,
+    #[command(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct UtxosArgs {
+    #[arg(long)]
+    pub address: String,
+    #[command(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct DecodeArgs {
+    /// File holding the hex encoded tx
+    #[arg(long, value_name = "PATH")]
+    pub hex_file: PathBuf,
+    #[command(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ReferenceArgs {
+    /// Initial margin of the order
+    #[arg(long, default_value_t = 7000)]
+    pub value: u64,
+    #[arg(long, default_value_t = 10)]
+    pub leverage: u64,
+    #[arg(long, default_value_t = 10000)]
+    pub price: u64,
+    /// Payout of the settlement, the initial margin if not set
+    #[arg(long)]
+    pub payout: Option<u64>,
+}
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("Invalid argument, {0}")]
+    InvalidArgument(String),
+
+    #[error("Invalid secret key, {0}")]
+    InvalidKey(String),
+
+    #[error("Tx creation failed, {0}")]
+    TxCreation(String),
+
+    #[error("Rpc error {0}")]
+    Rpc(#[from] reqwest::Error),
+
+    #[error("Io error {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Json serde error {0:?}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// Inputs and outputs of a script tx, read from the `--io-file` of `send-script`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScriptIo {
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+    #[serde(default)]
+    pub tx_data: Option<zkvm::String>,
+}
+
+/// Reference txs printed by `reference`, by hex encoded tx id, in the order they apply.
+/// The coins they spend are made up, so a node only accepts them on a utxo set seeded
+/// with those coins. They exercise the script verification of the node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReferenceTxs {
+    pub pool_deploy: (String, String),
+    pub order: (String, String),
+    pub settlement: (String, String),
+}
+
+/// A secret key and its scalar, used as view key to decrypt coin values.
+pub struct KeyMaterial {
+    pub sk: RistrettoSecretKey,
+    pub scalar: Scalar,
+}
+
+impl KeyMaterial {
+    pub fn from_hex(hex_key: &str) -> Result<Self, CliError> {
+        let bytes = hex::decode(hex_key.trim())
+            .map_err(|_| CliError::InvalidKey("expected a hex string".to_string()))?;
+        if bytes.len() != 32 {
+            return Err(CliError::InvalidKey("expected 32 bytes".to_string()));
+        }
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&bytes);
+        let scalar = Scalar::from_canonical_bytes(scalar)
+            .ok_or_else(|| CliError::InvalidKey("not a canonical scalar".to_string()))?;
+        Ok(KeyMaterial {
+            sk: SecretKey::from_bytes(&bytes),
+            scalar,
+        })
+    }
+}
+
+/// Reads the key from `file`, or from `ZKOS_SK` if no file is given.
+/// A key file readable by other users is refused.
+pub fn read_key(file: Option<&Path>) -> Result<KeyMaterial, CliError> {
+    match file {
+        Some(path) => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(path)?.permissions().mode();
+                if mode & 0o077 != 0 {
+                    return Err(CliError::InvalidKey(format!(
+                        "{} is accessible by other users, mode {:o}",
+                        path.display(),
+                        mode & 0o777
+                    )));
+                }
+            }
+            KeyMaterial::from_hex(&std::fs::read_to_string(path)?)
+        }
+        None => match std::env::var(SECRET_KEY_ENV) {
+            Ok(hex_key) => KeyMaterial::from_hex(&hex_key),
+            Err(_) => Err(CliError::InvalidKey(format!(
+                "set --sk-file, {} or {}",
+                SECRET_KEY_FILE_ENV, SECRET_KEY_ENV
+            ))),
+        },
+    }
+}
+
+fn standard_address(hex_address: &str) -> Result<Standard, CliError> {
+    Address::from_hex(hex_address, AddressType::Standard)
+        .and_then(|address| address.get_standard_address())
+        .map_err(|e| CliError::InvalidArgument(format!("{} {}", e, hex_address)))
+}
+
+/// Runs a client subcommand and returns the text to print. `serve` is run by the binary.
+pub async fn run(command: Command) -> Result<String, CliError> {
+    match command {
+        Command::Serve(_) => Err(CliError::InvalidArgument(
+            "serve is not a client command".to_string(),
+        )),
+        Command::SendDark(args) => send_dark(args).await,
+        Command::SendScript(args) => send_script(args).await,
+        Command::Status(args) => status(args).await,
+        Command::Utxos(args) => utxos(args).await,
+        Command::Decode(args) => decode(args).await,
+        Command::Reference(args) => reference(args),
+    }
+}
+
+async fn send_dark(args: SendDarkArgs) -> Result<String, CliError> {
+    let key = read_key(args.key.sk_file.as_deref())?;
+    let from = standard_address(&args.from)?;
+    let to = standard_address(&args.to)?;
+    from.public_key
+        .verify_keypair(&key.sk)
+        .map_err(|_| CliError::InvalidKey("key does not own the sender address".to_string()))?;
+    if args.amount > i64::MAX as u64 {
+        return Err(CliError::InvalidArgument("amount overflows".to_string()));
+    }
+
+    // spendable coins of the sender, with their decrypted values
+    let client = AsyncRpcClient::new(args.rpc.rpc);
+    let mut coins: Vec<SpendableCoin> = Vec::new();
+    for utxo in client.get_utxos(args.from.clone()).await? {
+        let output = match client.get_output(&utxo).await?.all_utxo {
+            Some(output) => output,
+            None => continue,
+        };
+        let coin = match output.as_out_coin() {
+            Some(coin) => coin.clone(),
+            None => continue,
+        };
+        let value = decrypt_value(&coin.encrypt, &key.scalar, &BSGS_TABLE).ok_or_else(|| {
+            CliError::InvalidKey(format!("can not decrypt the value of {}", utxo))
+        })?;
+        coins.push(SpendableCoin::new(utxo, coin, value));
+    }
+    let selection = select_coins(
+        &coins,
+        args.amount,
+        args.fee,
+        MAX_DARK_INPUTS,
+        SelectionStrategy::LargestFirst,
+    )
+    .map_err(|e| CliError::TxCreation(e.to_string()))?;
+
+    // zero balance account of the receiver, proven with the witness of the last input
+    let comm_scalar = Scalar::random(&mut rand::rngs::OsRng);
+    let commitment =
+        ElGamalCommitment::generate_commitment(&to.public_key, comm_scalar, Scalar::zero());
+    let account = Account::set_account(to.public_key, commitment);
+    let transfer = selection
+        .to_transfer(&[Receiver::set_receiver(args.amount as i64, account)])
+        .map_err(|e| CliError::TxCreation(e.to_string()))?;
+    let mut inputs = transfer.inputs;
+    inputs.push(Input::input_from_quisquis_account(
+        &account,
+        Utxo::default(),
+        0,
+        to.network,
+    ));
+
+    let (transfer_tx, _) = TransferTransaction::create_private_transfer_transaction(
+        &transfer.value_vector,
+        &transfer.account_vector,
+        &transfer.sender_updated_balance,
+        &transfer.reciever_value_balance,
+        &inputs,
+        &vec![key.sk; transfer.senders_count],
+        transfer.senders_count,
+        transfer.receivers_count,
+        Some(&[comm_scalar]),
+        args.fee,
+    )
+    .map_err(|e| CliError::TxCreation(e.to_string()))?;
+    let tx = Transaction::transaction_transfer(TransactionData::TransactionTransfer(transfer_tx));
+    commit(&client, tx, args.dry_run).await
+}
+
+async fn send_script(args: SendScriptArgs) -> Result<String, CliError> {
+    let key = read_key(args.key.sk_file.as_deref())?;
+    let io: ScriptIo = serde_json::from_str(&std::fs::read_to_string(&args.io_file)?)?;
+    let client = AsyncRpcClient::new(args.rpc.rpc);
+    let contract = client
+        .list_script_contracts()
+        .await?
+        .contracts
+        .into_iter()
+        .find(|contract| contract.name == args.program)
+        .ok_or_else(|| {
+            CliError::InvalidArgument(format!("no registered contract {}", args.program))
+        })?;
+    let programs = contract
+        .programs
+        .iter()
+        .map(|program| {
+            hex::decode(program)
+                .ok()
+                .and_then(|bytes| Program::parse(&bytes).ok())
+                .ok_or_else(|| {
+                    CliError::InvalidArgument(format!("invalid program of {}", contract.name))
+                })
+        })
+        .collect::<Result<Vec<Program>, CliError>>()?;
+    let program = programs.get(args.index).cloned().ok_or_else(|| {
+        CliError::InvalidArgument(format!("{} has no program {}", contract.name, args.index))
+    })?;
+    let network = if args.testnet {
+        Network::Testnet
+    } else {
+        Network::Mainnet
+    };
+    let call_proof = CallProof::create_call_proof(
+        &programs,
+        args.index,
+        &Hasher::new(PROGRAM_TREE_LABEL),
+        network,
+    )
+    .ok_or_else(|| CliError::TxCreation("call proof creation failed".to_string()))?;
+
+    let script_tx = ScriptTransaction::create_script_transaction(
+        &[key.sk],
+        program,
+        call_proof,
+        &io.inputs,
+        &io.outputs,
+        io.tx_data,
+        false,
+        args.fee,
+    )
+    .map_err(|e| CliError::TxCreation(e.to_string()))?;
+    let tx = Transaction::transaction_script(TransactionData::TransactionScript(script_tx));
+    commit(&client, tx, args.dry_run).await
+}
+
+async fn commit(
+    client: &AsyncRpcClient,
+    tx: Transaction,
+    dry_run: bool,
+) -> Result<String, CliError> {
+    if dry_run {
+        return Ok(hex::encode(tx.to_bytes()));
+    }
+    client
+        .tx_commit(tx)
+        .await?
+        .map_err(|e| CliError::TxCreation(format!("commit rejected, {}", e)))
+}
+
+async fn status(args: StatusArgs) -> Result<String, CliError> {
+    let client = AsyncRpcClient::new(args.rpc.rpc);
+    let response = client.get_tx_inclusion_proof(args.txid.clone()).await?;
+    Ok(match response.proof {
+        Some(proof) => format!("{} included at height {}", args.txid, proof.block_height),
+        None => format!("{} not included", args.txid),
+    })
+}
+
+async fn utxos(args: UtxosArgs) -> Result<String, CliError> {
+    standard_address(&args.address)?;
+    let client = AsyncRpcClient::new(args.rpc.rpc);
+    let utxos: Vec<String> = client
+        .get_utxos(args.address)
+        .await?
+        .iter()
+        .map(|utxo| utxo.to_string())
+        .collect();
+    Ok(serde_json::to_string_pretty(&utxos)?)
+}
+
+async fn decode(args: DecodeArgs) -> Result<String, CliError> {
+    let data = std::fs::read_to_string(&args.hex_file)?.trim().to_string();
+    let client = AsyncRpcClient::new(args.rpc.rpc);
+    match client.decode_tx(data, "hex").await?.tx {
+        Some(tx) => Ok(serde_json::to_string_pretty(&tx)?),
+        None => Err(CliError::InvalidArgument(format!(
+            "{} is not a valid tx",
+            args.hex_file.display()
+        ))),
+    }
+}
+
+fn reference(args: ReferenceArgs) -> Result<String, CliError> {
+    let order = create_script_reference_transaction_with(args.value, args.leverage, args.price)
+        .map_err(|e| CliError::TxCreation(e.to_string()))?;
+    let settlement =
+        create_settlement_reference_transaction_with(&order, args.payout.unwrap_or(args.value))
+            .map_err(|e| CliError::TxCreation(e.to_string()))?;
+    let encode = |tx: &Transaction| (hex::encode(reference_tx_id(tx)), hex::encode(tx.to_bytes()));
+    Ok(serde_json::to_string_pretty(&ReferenceTxs {
+        pool_deploy: encode(&order.pool_deploy),
+        order: encode(&order.tx),
+        settlement: encode(&settlement.tx),
+    })?)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Command, clap::Error> {
+        Cli::try_parse_from(std::iter::once("api_server").chain(args.split_whitespace()))
+            .map(Cli::command)
+    }
+
+    #[test]
+    fn parse_serve_test() {
+        assert_eq!(parse("").unwrap(), Command::Serve(ServeArgs::default()));
+        let resync = Command::Serve(ServeArgs {
+            force_resync_from: Some(42),
+            ..ServeArgs::default()
+        });
+        assert_eq!(parse("--force-resync-from 42").unwrap(), resync);
+        assert_eq!(parse("serve --force-resync-from 42").unwrap(), resync);
+        assert!(parse("--force-resync-from x").is_err());
+        let print_config = Command::Serve(ServeArgs {
+            print_config: true,
+            ..ServeArgs::default()
+        });
+        assert_eq!(parse("--print-config").unwrap(), print_config);
+        assert_eq!(parse("serve --print-config").unwrap(), print_config);
+    }
+
+    #[test]
+    fn parse_send_dark_test() {
+        let command = parse("send-dark --from aa --to bb --amount 10 --sk-file key").unwrap();
+        match command {
+            Command::SendDark(args) => {
+                assert_eq!(args.from, "aa");
+                assert_eq!(args.to, "bb");
+                assert_eq!(args.amount, 10);
+                assert_eq!(args.fee, 0);
+                assert!(!args.dry_run);
+                assert_eq!(args.key.sk_file, Some(PathBuf::from("key")));
+                assert_eq!(args.rpc.rpc, DEFAULT_RPC_URL);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        // the amount is required and unsigned, keys are never arguments
+        assert!(parse("send-dark --from aa --to bb").is_err());
+        assert!(parse("send-dark --from aa --to bb --amount -1").is_err());
+        assert!(parse("send-dark --from aa --to bb --amount 1 --sk 00").is_err());
+        // client arguments are not accepted without their subcommand
+        assert!(parse("--txid 00").is_err());
+    }
+
+    #[test]
+    fn parse_client_commands_test() {
+        match parse("send-script --program relayer --io-file io.json").unwrap() {
+            Command::SendScript(args) => {
+                assert_eq!(args.program, "relayer");
+                assert_eq!(args.index, 0);
+                assert_eq!(args.io_file, PathBuf::from("io.json"));
+                assert!(!args.testnet);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        match parse("status --txid ab --rpc http://node:3030").unwrap() {
+            Command::Status(args) => {
+                assert_eq!(args.txid, "ab");
+                assert_eq!(args.rpc.rpc, "http://node:3030");
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert!(matches!(
+            parse("utxos --address aa").unwrap(),
+            Command::Utxos(UtxosArgs { ref address, .. }) if address == "aa"
+        ));
+        assert!(matches!(
+            parse("decode --hex-file tx.hex").unwrap(),
+            Command::Decode(_)
+        ));
+        match parse("reference --value 100 --payout 120").unwrap() {
+            Command::Reference(args) => {
+                assert_eq!((args.value, args.leverage, args.price), (100, 10, 10000));
+                assert_eq!(args.payout, Some(120));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert!(parse("status").is_err());
+        assert!(parse("unknown").is_err());
+    }
+
+    #[test]
+    fn read_key_test() {
+        let scalar = Scalar::from(7u64);
+        let path = std::env::temp_dir().join(format!("zkos-cli-key-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, hex::encode(scalar.as_bytes())).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(
+                read_key(Some(&path)),
+                Err(CliError::InvalidKey(_))
+            ));
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert_eq!(read_key(Some(&path)).unwrap().scalar, scalar);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(KeyMaterial::from_hex("zz").is_err());
+        assert!(KeyMaterial::from_hex("0011").is_err());
+        // not reduced modulo the group order
+        assert!(KeyMaterial::from_hex(&"ff".repeat(32)).is_err());
+    }
+
+    #[cfg(feature = "cli-e2e")]
+    #[test]
+    fn cli_end_to_end_test() {
+        use crate::rpcserver::{start_rpc_server, RateLimitConfig};
+        use quisquislib::ristretto::RistrettoPublicKey;
+        use utxo_in_memory::db::LocalDBtrait;
+        use utxo_in_memory::UTXO_STORAGE;
+        use zkvm::zkos_types::IOType;
+
+        let server =
+            start_rpc_server(&"127.0.0.1:0".parse().unwrap(), RateLimitConfig::default()).unwrap();
+        let rpc = RpcArgs {
+            rpc: format!("http://{}", server.address()),
+        };
+
+        // a coin of 100 owned by a key written to a key file
+        let mut rng = rand::thread_rng();
+        let scalar = Scalar::random(&mut rng);
+        let sk: RistrettoSecretKey = SecretKey::from_bytes(scalar.as_bytes());
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let commitment = ElGamalCommitment::generate_commitment(
+            &pk,
+            Scalar::random(&mut rng),
+            Scalar::from(100u64),
+        );
+        let account = Account::set_account(pk, commitment);
+        let output = Output::from_quisquis_account(account, Network::Mainnet);
+        let from = output.as_out_coin().unwrap().owner.clone();
+        let utxo = Utxo::random();
+        UTXO_STORAGE
+            .lock()
+            .unwrap()
+            .add(utxo.to_bytes(), output, IOType::Coin as usize)
+            .unwrap();
+        let key_file = std::env::temp_dir().join(format!("zkos-cli-key-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&key_file, hex::encode(scalar.as_bytes())).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&key_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        let (receiver, _) = Account::generate_random_account_with_value(Scalar::zero());
+        let to = Address::standard_address(Network::Mainnet, receiver.get_account().0).as_hex();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let listed = rt
+            .block_on(run(Command::Utxos(UtxosArgs {
+                address: from.clone(),
+                rpc: RpcArgs {
+                    rpc: rpc.rpc.clone(),
+                },
+            })))
+            .unwrap();
+        assert!(listed.contains(&utxo.to_string()));
+
+        let tx_hex = rt
+            .block_on(run(Command::SendDark(SendDarkArgs {
+                from,
+                to,
+                amount: 40,
+                fee: 1,
+                dry_run: true,
+                key: KeyArgs {
+                    sk_file: Some(key_file.clone()),
+                },
+                rpc: RpcArgs {
+                    rpc: rpc.rpc.clone(),
+                },
+            })))
+            .unwrap();
+        let tx = Transaction::from_bytes(&hex::decode(&tx_hex).unwrap()).unwrap();
+        assert!(tx.verify().is_ok());
+        assert_eq!(tx.get_tx_inputs()[0].get_utxo(), utxo);
+
+        // the dry run tx decodes through the node
+        let hex_file = std::env::temp_dir().join(format!("zkos-cli-tx-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&hex_file, &tx_hex).unwrap();
+        let decoded = rt
+            .block_on(run(Command::Decode(DecodeArgs {
+                hex_file: hex_file.clone(),
+                rpc,
+            })))
+            .unwrap();
+        assert!(decoded.contains(&utxo.to_string()));
+
+        std::fs::remove_file(&key_file).unwrap();
+        std::fs::remove_file(&hex_file).unwrap();
+        let _ = UTXO_STORAGE
+            .lock()
+            .unwrap()
+            .remove(utxo.to_bytes(), IOType::Coin as usize);
+    }
+}
@@ -0,0 +1,390 @@
+//! Configuration of the `api_server` node, read like the utxo store config, see
+//! [`utxo_in_memory::config`].
+
+use crate::rpcserver::{
+    AuditConfig, ChainParams, NodeKey, NodeKeyError, RelayConfig, TlsConfig,
+    DEFAULT_AUDIT_RETENTION_DAYS, DEFAULT_MIN_FEE_RATE, DEFAULT_RELAY_ATTEMPTS,
+    DEFAULT_RELAY_BACKOFF_MS, DEFAULT_ZKOS_ORACLE_URL,
+};
+use address::NetworkConfig;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use url::Url;
+use utxo_in_memory::config::{
+    config_line, config_source, ConfigError, ConfigSource, UtxoStoreConfig, REDACTED,
+};
+
+pub const DEFAULT_RPC_BIND_ADDRESS: &str = "0.0.0.0:3030";
+pub const DEFAULT_WS_BIND_ADDRESS: &str = "0.0.0.0:3031";
+pub const DEFAULT_TELEMETRY_PORT: u16 = 2500;
+pub const DEFAULT_NODE_KEY_PATH: &str = "node_key.hex";
+
+/// Keys of the address magic bytes, see [`NetworkConfig::from_lookup`].
+pub const NETWORK_BYTE_KEYS: [&str; 6] = [
+    "MAINNET_STANDARD_BYTE",
+    "MAINNET_SCRIPT_BYTE",
+    "TESTNET_STANDARD_BYTE",
+    "TESTNET_SCRIPT_BYTE",
+    "MAINNET_MULTISIG_BYTE",
+    "TESTNET_MULTISIG_BYTE",
+];
+
+/// Config of the servers of the node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiConfig {
+    /// `RPC_BIND_ADDRESS` of the JSON-RPC server
+    pub rpc_bind_address: SocketAddr,
+    /// `WS_BIND_ADDRESS` of the websocket subscription server
+    pub ws_bind_address: SocketAddr,
+    /// `TELEMETRY_PORT` of the prometheus metrics
+    pub telemetry_port: u16,
+    /// `ZKOS_ORACLE_URL` the txs are committed to
+    pub zkos_oracle_url: Url,
+    /// Number of `RPC_API_KEYS`, the keys are never printed
+    pub api_keys: usize,
+    /// Number of `RPC_ADMIN_API_KEYS`
+    pub admin_api_keys: usize,
+    /// `RELAY_PEERS`, comma separated urls of the peers committed txs are relayed to
+    pub relay_peers: Vec<Url>,
+    /// `RELAY_MAX_ATTEMPTS` of a relay to a peer
+    pub relay_max_attempts: u32,
+    /// `RELAY_BACKOFF_MS` before the first retry of a relay
+    pub relay_backoff_ms: u64,
+    /// `RPC_AUDIT_SALT` of the hashed api keys and addresses of the audit log, never printed
+    pub audit_salt: Option<String>,
+    /// `RPC_AUDIT_RETENTION_DAYS` an audit record is kept
+    pub audit_retention_days: u64,
+    /// `NODE_KEY_PATH` of the key signing the status of the node, see [`NodeKey`]
+    pub node_key_path: PathBuf,
+    /// `RPC_TLS_CERT_PATH` of the certificate the rpc server is served over TLS with
+    pub rpc_tls_cert_path: Option<PathBuf>,
+    /// `RPC_TLS_KEY_PATH` of the key of the certificate
+    pub rpc_tls_key_path: Option<PathBuf>,
+    /// `RPC_TLS_CLIENT_CA_PATH` of the CA issuing the client certificates, for mutual TLS
+    pub rpc_tls_client_ca_path: Option<PathBuf>,
+    /// `RPC_TLS_CLIENT_CERT_METHODS`, comma separated methods needing a client certificate,
+    /// the defaults of [`crate::rpcserver::DEFAULT_CLIENT_CERT_METHODS`] if empty
+    pub rpc_tls_client_cert_methods: Vec<String>,
+    /// `MIN_FEE_RATE`, fee per byte of the encoded tx below which txCommit rejects a tx
+    pub min_fee_rate: f64,
+}
+
+impl ApiConfig {
+    /// Reads and validates the config of the node.
+    pub fn load() -> Result<ApiConfig, ConfigError> {
+        ApiConfig::from_source(config_source()?)
+    }
+
+    pub fn from_source(source: &ConfigSource) -> Result<ApiConfig, ConfigError> {
+        let keys = |key: &str| {
+            source.get(key).map_or(0, |keys| {
+                keys.split(',').filter(|k| !k.trim().is_empty()).count()
+            })
+        };
+        let path = |key: &str| source.get(key).map(PathBuf::from);
+        let (cert_path, key_path) = (path("RPC_TLS_CERT_PATH"), path("RPC_TLS_KEY_PATH"));
+        let client_ca_path = path("RPC_TLS_CLIENT_CA_PATH");
+        // the server is served over TLS with both the certificate and its key, or neither
+        if cert_path.is_some() != key_path.is_some()
+            || (client_ca_path.is_some() && cert_path.is_none())
+        {
+            let key = match (&cert_path, &key_path) {
+                (Some(_), None) => "RPC_TLS_CERT_PATH",
+                (None, Some(_)) => "RPC_TLS_KEY_PATH",
+                _ => "RPC_TLS_CLIENT_CA_PATH",
+            };
+            return Err(ConfigError::Invalid {
+                key,
+                reason: "TLS needs both RPC_TLS_CERT_PATH and RPC_TLS_KEY_PATH".to_string(),
+            });
+        }
+        let min_fee_rate = source.parse_or("MIN_FEE_RATE", DEFAULT_MIN_FEE_RATE)?;
+        if !min_fee_rate.is_finite() || min_fee_rate < 0.0 {
+            return Err(ConfigError::Invalid {
+                key: "MIN_FEE_RATE",
+                reason: format!("{} is not a fee rate", min_fee_rate),
+            });
+        }
+        Ok(ApiConfig {
+            rpc_bind_address: source.parse_or(
+                "RPC_BIND_ADDRESS",
+                DEFAULT_RPC_BIND_ADDRESS.parse().unwrap(),
+            )?,
+            ws_bind_address: source
+                .parse_or("WS_BIND_ADDRESS", DEFAULT_WS_BIND_ADDRESS.parse().unwrap())?,
+            telemetry_port: source.port_or("TELEMETRY_PORT", DEFAULT_TELEMETRY_PORT)?,
+            zkos_oracle_url: source.url_or(
+                "ZKOS_ORACLE_URL",
+                DEFAULT_ZKOS_ORACLE_URL,
+                &["http", "https"],
+            )?,
+            api_keys: keys("RPC_API_KEYS"),
+            admin_api_keys: keys("RPC_ADMIN_API_KEYS"),
+            relay_peers: peer_urls(source, "RELAY_PEERS")?,
+            relay_max_attempts: source.parse_or("RELAY_MAX_ATTEMPTS", DEFAULT_RELAY_ATTEMPTS)?,
+            relay_backoff_ms: source.parse_or("RELAY_BACKOFF_MS", DEFAULT_RELAY_BACKOFF_MS)?,
+            audit_salt: source.get("RPC_AUDIT_SALT"),
+            audit_retention_days: source
+                .parse_or("RPC_AUDIT_RETENTION_DAYS", DEFAULT_AUDIT_RETENTION_DAYS)?,
+            node_key_path: source
+                .get("NODE_KEY_PATH")
+                .map_or_else(|| PathBuf::from(DEFAULT_NODE_KEY_PATH), PathBuf::from),
+            rpc_tls_cert_path: cert_path,
+            rpc_tls_key_path: key_path,
+            rpc_tls_client_ca_path: client_ca_path,
+            rpc_tls_client_cert_methods: source
+                .get("RPC_TLS_CLIENT_CERT_METHODS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|method| !method.is_empty())
+                .map(str::to_string)
+                .collect(),
+            min_fee_rate,
+        })
+    }
+
+    /// Peers and retry policy of the tx relay.
+    pub fn relay_config(&self) -> RelayConfig {
+        RelayConfig {
+            peers: self.relay_peers.clone(),
+            max_attempts: self.relay_max_attempts,
+            backoff: Duration::from_millis(self.relay_backoff_ms),
+        }
+    }
+
+    /// Salt and retention of the audit log, a random salt if none is set.
+    pub fn audit_config(&self) -> AuditConfig {
+        AuditConfig::new(self.audit_salt.clone(), self.audit_retention_days)
+    }
+
+    /// Key of `node_key_path`, generated on the first start.
+    pub fn node_key(&self) -> Result<NodeKey, NodeKeyError> {
+        NodeKey::load_or_generate(&self.node_key_path)
+    }
+
+    /// Parameters of the chain served by `getChainInfo`, with the installed address
+    /// magic bytes.
+    pub fn chain_params(&self) -> ChainParams {
+        ChainParams {
+            min_fee_rate: self.min_fee_rate,
+            ..ChainParams::default()
+        }
+    }
+
+    /// Certificates of the rpc server, None if it is served over plain http.
+    pub fn tls_config(&self) -> Option<TlsConfig> {
+        let (cert_path, key_path) = (
+            self.rpc_tls_cert_path.clone()?,
+            self.rpc_tls_key_path.clone()?,
+        );
+        let mut config = TlsConfig::new(cert_path, key_path);
+        if let Some(client_ca_path) = &self.rpc_tls_client_ca_path {
+            config = config.with_client_ca(client_ca_path.clone());
+        }
+        if !self.rpc_tls_client_cert_methods.is_empty() {
+            config.client_cert_methods = self.rpc_tls_client_cert_methods.iter().cloned().collect();
+        }
+        Some(config)
+    }
+
+    /// The config in the format of the config file, the api keys and the salt redacted.
+    pub fn redacted(&self) -> String {
+        let redacted_keys = |count: usize| vec![REDACTED; count].join(",");
+        let mut lines = String::from("[api]\n");
+        lines += &config_line("RPC_BIND_ADDRESS", &self.rpc_bind_address.to_string());
+        lines += &config_line("WS_BIND_ADDRESS", &self.ws_bind_address.to_string());
+        lines += &config_line("TELEMETRY_PORT", &self.telemetry_port.to_string());
+        lines += &config_line("ZKOS_ORACLE_URL", self.zkos_oracle_url.as_str());
+        lines += &config_line("RPC_API_KEYS", &redacted_keys(self.api_keys));
+        lines += &config_line("RPC_ADMIN_API_KEYS", &redacted_keys(self.admin_api_keys));
+        let peers: Vec<&str> = self.relay_peers.iter().map(|url| url.as_str()).collect();
+        lines += &config_line("RELAY_PEERS", &peers.join(","));
+        lines += &config_line("RELAY_MAX_ATTEMPTS", &self.relay_max_attempts.to_string());
+        lines += &config_line("RELAY_BACKOFF_MS", &self.relay_backoff_ms.to_string());
+        let salt = self.audit_salt.as_ref().map_or("", |_| REDACTED);
+        lines += &config_line("RPC_AUDIT_SALT", salt);
+        lines += &config_line(
+            "RPC_AUDIT_RETENTION_DAYS",
+            &self.audit_retention_days.to_string(),
+        );
+        lines += &config_line("NODE_KEY_PATH", &self.node_key_path.to_string_lossy());
+        let path = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map_or(String::new(), |path| path.to_string_lossy().into_owned())
+        };
+        lines += &config_line("RPC_TLS_CERT_PATH", &path(&self.rpc_tls_cert_path));
+        lines += &config_line("RPC_TLS_KEY_PATH", &path(&self.rpc_tls_key_path));
+        lines += &config_line(
+            "RPC_TLS_CLIENT_CA_PATH",
+            &path(&self.rpc_tls_client_ca_path),
+        );
+        lines += &config_line(
+            "RPC_TLS_CLIENT_CERT_METHODS",
+            &self.rpc_tls_client_cert_methods.join(","),
+        );
+        lines += &config_line("MIN_FEE_RATE", &self.min_fee_rate.to_string());
+        lines
+    }
+}
+
+// comma separated http urls of `key`, none if it is not set
+fn peer_urls(source: &ConfigSource, key: &'static str) -> Result<Vec<Url>, ConfigError> {
+    let value = source.get(key).unwrap_or_default();
+    let mut urls = Vec::new();
+    for peer in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let url = Url::parse(peer).map_err(|e| ConfigError::Invalid {
+            key,
+            reason: format!("{:?}, {}", peer, e),
+        })?;
+        if !["http", "https"].contains(&url.scheme()) {
+            return Err(ConfigError::Invalid {
+                key,
+                reason: format!("scheme {} is not one of http, https", url.scheme()),
+            });
+        }
+        urls.push(url);
+    }
+    Ok(urls)
+}
+
+/// The address magic bytes of `source`, naming the first byte that is not a u8 or
+/// is shared with an earlier key.
+pub fn network_config(source: &ConfigSource) -> Result<NetworkConfig, ConfigError> {
+    let default = NetworkConfig::default();
+    let defaults = [
+        default.mainnet_standard,
+        default.mainnet_script,
+        default.testnet_standard,
+        default.testnet_script,
+        default.mainnet_multisig,
+        default.testnet_multisig,
+    ];
+    let mut bytes = Vec::new();
+    for (key, default) in NETWORK_BYTE_KEYS.into_iter().zip(defaults) {
+        bytes.push(source.parse_or::<u8>(key, default)?);
+    }
+    NetworkConfig::from_lookup(|key| source.get(key)).map_err(|reason| {
+        let key = (1..bytes.len())
+            .find(|&i| bytes[..i].contains(&bytes[i]))
+            .map_or(NETWORK_BYTE_KEYS[0], |i| NETWORK_BYTE_KEYS[i]);
+        ConfigError::Invalid {
+            key,
+            reason: reason.to_string(),
+        }
+    })
+}
+
+/// The utxo store and api configs of the node, the first invalid value fails. The
+/// address magic bytes are checked too, the binary installs them before parsing any
+/// address.
+pub fn load_node_config() -> Result<(UtxoStoreConfig, ApiConfig), ConfigError> {
+    network_config(config_source()?)?;
+    Ok((UtxoStoreConfig::load()?, ApiConfig::load()?))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn api_config_test() {
+        let file = r#"
+            [api]
+            rpc_bind_address = "127.0.0.1:4030"
+            telemetry_port = 2600
+            rpc_api_keys = "first-secret,second-secret"
+            rpc_audit_salt = "salt-secret"
+            relay_peers = ["http://10.0.0.2:3030", "http://10.0.0.3:3030"]
+        "#;
+        let source = ConfigSource::from_parts(&[("TELEMETRY_PORT", "2700")], file).unwrap();
+        let config = ApiConfig::from_source(&source).unwrap();
+        assert_eq!(config.rpc_bind_address, "127.0.0.1:4030".parse().unwrap());
+        assert_eq!(
+            config.ws_bind_address,
+            DEFAULT_WS_BIND_ADDRESS.parse().unwrap()
+        );
+        // the environment over the file
+        assert_eq!(config.telemetry_port, 2700);
+        assert_eq!(config.api_keys, 2);
+        assert_eq!(config.admin_api_keys, 0);
+        let relay = config.relay_config();
+        assert_eq!(relay.peers.len(), 2);
+        assert_eq!(relay.peers[1].as_str(), "http://10.0.0.3:3030/");
+        assert_eq!(relay.max_attempts, DEFAULT_RELAY_ATTEMPTS);
+        let audit = config.audit_config();
+        assert_eq!(audit.salt, "salt-secret");
+        assert_eq!(audit.retention_days, DEFAULT_AUDIT_RETENTION_DAYS);
+        assert_eq!(config.node_key_path, PathBuf::from(DEFAULT_NODE_KEY_PATH));
+        assert_eq!(config.tls_config(), None);
+
+        let printed = config.redacted();
+        assert!(!printed.contains("secret"));
+        assert!(printed.contains("RPC_API_KEYS = \"****,****\""));
+        assert!(printed.contains("RPC_BIND_ADDRESS = \"127.0.0.1:4030\""));
+    }
+
+    #[test]
+    fn api_config_validation_test() {
+        let invalid = |key: &'static str, value: &str| {
+            let source = ConfigSource::from_parts(&[(key, value)], "").unwrap();
+            match ApiConfig::from_source(&source) {
+                Err(ConfigError::Invalid { key: invalid, .. }) => assert_eq!(invalid, key),
+                other => panic!("{} = {:?} gave {:?}", key, value, other),
+            }
+        };
+        invalid("RPC_BIND_ADDRESS", "0.0.0.0");
+        invalid("WS_BIND_ADDRESS", "localhost:99999");
+        invalid("TELEMETRY_PORT", "0");
+        invalid("TELEMETRY_PORT", "65536");
+        invalid("ZKOS_ORACLE_URL", "0.0.0.0:7000");
+        invalid("ZKOS_ORACLE_URL", "ws://0.0.0.0:7000");
+        invalid("RELAY_PEERS", "http://10.0.0.2:3030,10.0.0.3:3030");
+        invalid("RELAY_MAX_ATTEMPTS", "three");
+        invalid("RPC_AUDIT_RETENTION_DAYS", "-1");
+        invalid("RPC_TLS_CERT_PATH", "cert.pem");
+        invalid("RPC_TLS_CLIENT_CA_PATH", "ca.pem");
+        invalid("MIN_FEE_RATE", "-0.5");
+        invalid("MIN_FEE_RATE", "NaN");
+    }
+
+    #[test]
+    fn network_config_validation_test() {
+        let source = ConfigSource::from_parts(&[("TESTNET_SCRIPT_BYTE", "68")], "").unwrap();
+        assert_eq!(network_config(&source).unwrap().testnet_script, 68);
+        let invalid = |env: &[(&str, &str)], key: &'static str| {
+            let source = ConfigSource::from_parts(env, "").unwrap();
+            match network_config(&source) {
+                Err(ConfigError::Invalid { key: invalid, .. }) => assert_eq!(invalid, key),
+                other => panic!("{:?} gave {:?}", env, other),
+            }
+        };
+        invalid(&[("MAINNET_SCRIPT_BYTE", "256")], "MAINNET_SCRIPT_BYTE");
+        // the testnet standard byte shares the default mainnet standard byte
+        invalid(&[("TESTNET_STANDARD_BYTE", "12")], "TESTNET_STANDARD_BYTE");
+    }
+
+    #[test]
+    fn api_tls_config_test() {
+        let file = r#"
+            [api]
+            rpc_tls_cert_path = "/etc/zkos/cert.pem"
+            rpc_tls_key_path = "/etc/zkos/key.pem"
+            rpc_tls_client_ca_path = "/etc/zkos/ca.pem"
+        "#;
+        let env = [("RPC_TLS_CLIENT_CERT_METHODS", "txCommit, reloadTls")];
+        let source = ConfigSource::from_parts(&env, file).unwrap();
+        let tls = ApiConfig::from_source(&source)
+            .unwrap()
+            .tls_config()
+            .unwrap();
+        assert_eq!(tls.key_path, PathBuf::from("/etc/zkos/key.pem"));
+        assert_eq!(tls.client_ca_path, Some(PathBuf::from("/etc/zkos/ca.pem")));
+        assert_eq!(tls.client_cert_methods.len(), 2);
+        assert!(tls.client_cert_methods.contains("reloadTls"));
+    }
+}
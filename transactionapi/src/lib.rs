@@ -1,3 +1,5 @@
+pub mod cli;
+pub mod config;
 pub mod rpcclient;
 pub mod rpcserver;
 pub mod error;
@@ -0,0 +1,927 @@
+//! Async JSON-RPC client.
+//!
+//! `AsyncRpcClient` keeps one pooled `reqwest::Client` for all requests.
+//! Idempotent read methods are retried with jittered exponential backoff when
+//! the transport fails, the server returns a 5xx status or the server reports
+//! it is busy. `txCommit` and `TxQueue` are never retried, since a lost
+//! response does not mean the transaction was not submitted.
+//!
+//! The blocking `RpcRequest::send` goes through the same client on a small
+//! shared runtime, with the TLS config of the environment, see [`super::tls`].
+
+use super::id::Id;
+use super::method::*;
+use super::tls::{ClientTlsConfig, ClientTlsError};
+use super::txrequest::{construct_headers, Payload, RpcBody, RpcResponse};
+use super::typed::*;
+use jsonrpc_core::response::{Output, Response};
+use jsonrpc_core::{ErrorCode, Version};
+use serde::Serialize;
+use std::time::Duration;
+use transaction::Transaction;
+use utxo_in_memory::blockoperations::blockprocessing::AllUtxosQuery;
+use utxo_in_memory::blockoperations::mempool::{SimulateTransferQuery, SimulatedInput};
+use utxo_in_memory::blockoperations::scripteval::EvalScriptQuery;
+use utxo_in_memory::pgsql::{QueryUtxoFromDB, UtxoDetailedQuery};
+use utxo_in_memory::rpcaudit::{AuditLogQuery, RpcAuditFilter};
+use zkvm::zkos_types::Utxo;
+
+/// Error code returned by the server when its query queue is full.
+pub const SERVER_BUSY_CODE: i64 = -32005;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref BLOCKING_RUNTIME: tokio::runtime::Runtime =
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("rpc-client")
+            .enable_all()
+            .build()
+            .unwrap();
+    static ref BLOCKING_CLIENT: reqwest::Client = ClientTlsConfig::from_env()
+        .apply(client_builder(DEFAULT_TIMEOUT))
+        .and_then(|builder| Ok(builder.build()?))
+        .expect("Invalid rpc client TLS config");
+}
+
+/// Retry policy for idempotent methods.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryConfig {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// No retries at all.
+    pub fn none() -> Self {
+        RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        }
+    }
+
+    /// Delay before retry `attempt` (0 based).
+    /// The exponential delay is capped at `max_delay`, and half of it is random.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        let half = delay / 2;
+        let mut bytes = [0u8; 4];
+        let fraction = match getrandom::getrandom(&mut bytes) {
+            Ok(_) => u32::from_le_bytes(bytes) as f64 / u32::MAX as f64,
+            Err(_) => 0.5,
+        };
+        half + half.mul_f64(fraction)
+    }
+}
+
+fn client_builder(timeout: Duration) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .default_headers(construct_headers())
+}
+
+fn pooled_client(timeout: Duration) -> Result<reqwest::Client, reqwest::Error> {
+    client_builder(timeout).build()
+}
+
+fn is_retryable(err: &reqwest::Error) -> bool {
+    if err.is_builder() || err.is_decode() {
+        return false;
+    }
+    match err.status() {
+        Some(status) => status.is_server_error(),
+        None => true,
+    }
+}
+
+fn is_server_busy(resp: &RpcResponse<serde_json::Value>) -> bool {
+    match &resp.result {
+        Err(err) => err.code == ErrorCode::ServerError(SERVER_BUSY_CODE),
+        Ok(_) => false,
+    }
+}
+
+fn output_response(output: Output) -> RpcResponse<serde_json::Value> {
+    match output {
+        Output::Success(s) => RpcResponse {
+            jsonrpc: s.jsonrpc.unwrap_or(Version::V2),
+            id: s.id,
+            result: Ok(s.result),
+        },
+        Output::Failure(f) => RpcResponse {
+            jsonrpc: f.jsonrpc.unwrap_or(Version::V2),
+            id: f.id,
+            result: Err(f.error),
+        },
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AsyncRpcClient {
+    client: reqwest::Client,
+    base_url: String,
+    retry: RetryConfig,
+}
+
+impl AsyncRpcClient {
+    /// Client with the default timeout and retry policy.
+    pub fn new(base_url: String) -> Self {
+        AsyncRpcClient::with_client(pooled_client(DEFAULT_TIMEOUT).unwrap(), base_url)
+    }
+
+    pub fn with_config(
+        base_url: String,
+        timeout: Duration,
+        retry: RetryConfig,
+    ) -> Result<Self, reqwest::Error> {
+        Ok(AsyncRpcClient {
+            client: pooled_client(timeout)?,
+            base_url,
+            retry,
+        })
+    }
+
+    /// Client trusting the root CA and presenting the identity of `tls`, for a node
+    /// served over TLS.
+    pub fn with_tls(
+        base_url: String,
+        timeout: Duration,
+        retry: RetryConfig,
+        tls: &ClientTlsConfig,
+    ) -> Result<Self, ClientTlsError> {
+        Ok(AsyncRpcClient {
+            client: tls.apply(client_builder(timeout))?.build()?,
+            base_url,
+            retry,
+        })
+    }
+
+    fn with_client(client: reqwest::Client, base_url: String) -> Self {
+        AsyncRpcClient {
+            client,
+            base_url,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Posts a serialized request body, retrying if `method` is idempotent.
+    pub async fn send_json(
+        &self,
+        method: Method,
+        body: String,
+    ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
+        let max_retries = if method.is_idempotent() {
+            self.retry.max_retries
+        } else {
+            0
+        };
+        let mut attempt = 0;
+        loop {
+            let result = self.post(body.clone()).await;
+            let retry = attempt < max_retries
+                && match &result {
+                    Ok(resp) => is_server_busy(resp),
+                    Err(err) => is_retryable(err),
+                };
+            if !retry {
+                return result;
+            }
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post(&self, body: String) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
+        let output: Output = self
+            .client
+            .post(&self.base_url)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(output_response(output))
+    }
+
+    /// Sends a typed request to its method, and decodes the result.
+    /// The outer error is the transport one, the inner one is the error returned
+    /// by the server or a result that does not decode.
+    pub async fn request<M: RpcMethod>(
+        &self,
+        params: M,
+    ) -> Result<Result<M::Response, jsonrpc_core::Error>, reqwest::Error> {
+        let resp = self.call_typed(params).await?;
+        Ok(decode_response::<M>(resp))
+    }
+
+    /// Sends a typed request to its method.
+    pub async fn call_typed<M: RpcMethod>(
+        &self,
+        params: M,
+    ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
+        let body = RpcBody::new_typed(params);
+        self.send_json(M::METHOD, serde_json::to_string(&body).unwrap())
+            .await
+    }
+
+    /// Sends typed requests as one batch, and decodes their results in the order of
+    /// `bodies`. A batch rejected as a whole fails every request with the error of the
+    /// server. Batches are never retried, they may hold non idempotent calls.
+    pub async fn send_batch<M: RpcMethod>(
+        &self,
+        bodies: Vec<RpcBody<M>>,
+    ) -> Result<Vec<Result<M::Response, jsonrpc_core::Error>>, reqwest::Error> {
+        let response: Response = self
+            .client
+            .post(&self.base_url)
+            .body(serde_json::to_string(&bodies).unwrap())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let outputs = match response {
+            Response::Batch(outputs) => outputs,
+            Response::Single(Output::Failure(f)) => {
+                return Ok(bodies.iter().map(|_| Err(f.error.clone())).collect())
+            }
+            Response::Single(output) => vec![output],
+        };
+        // the outputs are matched to the requests by id
+        let mut by_id: Vec<(serde_json::Value, RpcResponse<serde_json::Value>)> = outputs
+            .into_iter()
+            .map(|output| {
+                let resp = output_response(output);
+                (serde_json::to_value(&resp.id).unwrap(), resp)
+            })
+            .collect();
+        Ok(bodies
+            .iter()
+            .map(|body| {
+                let id = serde_json::to_value(&body.id).unwrap();
+                match by_id.iter().position(|(output_id, _)| *output_id == id) {
+                    Some(index) => decode_response::<M>(by_id.swap_remove(index).1),
+                    None => Err(jsonrpc_core::Error {
+                        code: ErrorCode::InternalError,
+                        message: "No response to the request in the batch".to_string(),
+                        data: None,
+                    }),
+                }
+            })
+            .collect())
+    }
+
+    /// Sends `params` as the parameters of `method`.
+    pub async fn call<T: Serialize>(
+        &self,
+        method: Method,
+        params: T,
+    ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
+        let body = RpcBody {
+            jsonrpc: Version::V2,
+            id: Id::uuid_v4(),
+            method,
+            params,
+        };
+        self.send_json(method, serde_json::to_string(&body).unwrap())
+            .await
+    }
+
+    pub async fn tx_commit(
+        &self,
+        tx: Transaction,
+    ) -> Result<Result<String, String>, reqwest::Error> {
+        let body = Payload::new(RpcBody {
+            jsonrpc: Version::V2,
+            id: Id::uuid_v4(),
+            method: Method::txCommit,
+            params: tx,
+        });
+        let resp = self
+            .send_json(Method::txCommit, serde_json::to_string(&body).unwrap())
+            .await?;
+        Ok(GetTxCommit::get_txhash(resp))
+    }
+
+    /// Runs the checks of `tx_commit` on the node without committing the tx.
+    pub async fn tx_validate(
+        &self,
+        tx: &Transaction,
+    ) -> Result<TxValidateResponse, reqwest::Error> {
+        let params = TxValidateRequest {
+            tx: hex::encode(tx.to_bytes()),
+        };
+        let resp = self.call_typed(params).await?;
+        Ok(TxValidateResponse::get_response(resp))
+    }
+
+    pub async fn get_utxos(&self, address: String) -> Result<Vec<Utxo>, reqwest::Error> {
+        let resp = self.call_typed(GetUtxosRequest { address }).await?;
+        Ok(GetUtxosResponse::get_response(resp))
+    }
+
+    /// Utxos of `address` with their outputs, decoded from the cached encoding.
+    pub async fn get_utxos_raw(
+        &self,
+        address: String,
+    ) -> Result<GetRawUtxosResponse, reqwest::Error> {
+        let resp = self.call_typed(GetRawUtxosRequest { address }).await?;
+        Ok(GetRawUtxosResponse::get_response(resp))
+    }
+
+    pub async fn get_memo_utxos(&self, address: String) -> Result<Vec<Utxo>, reqwest::Error> {
+        let resp = self.call_typed(GetMemoUtxosRequest { address }).await?;
+        Ok(GetMemoUtxosResponse::get_response(resp))
+    }
+
+    pub async fn get_state_utxos(&self, address: String) -> Result<Vec<Utxo>, reqwest::Error> {
+        let resp = self.call_typed(GetStateUtxosRequest { address }).await?;
+        Ok(GetStateUtxosResponse::get_response(resp))
+    }
+
+    pub async fn all_utxos(&self) -> Result<AllUtxoResponse, reqwest::Error> {
+        let resp = self.call(Method::allUtxos, Vec::<String>::new()).await?;
+        Ok(AllUtxoResponse::get_response(resp))
+    }
+
+    pub async fn all_utxos_chunk(
+        &self,
+        query: AllUtxosQuery,
+    ) -> Result<AllUtxoChunkResponse, reqwest::Error> {
+        let resp = self.call_typed(query).await?;
+        Ok(AllUtxoChunkResponse::get_response(resp))
+    }
+
+    pub async fn all_memo_utxos(&self) -> Result<AllUtxoResponse, reqwest::Error> {
+        let resp = self.call_typed(AllMemoUtxosRequest {}).await?;
+        Ok(AllUtxoResponse::get_response(resp))
+    }
+
+    pub async fn all_state_utxos(&self) -> Result<AllUtxoResponse, reqwest::Error> {
+        let resp = self.call_typed(AllStateUtxosRequest {}).await?;
+        Ok(AllUtxoResponse::get_response(resp))
+    }
+
+    pub async fn all_outputs(&self) -> Result<Vec<zkvm::zkos_types::Output>, reqwest::Error> {
+        let resp = self.call_typed(AllOutputsRequest {}).await?;
+        Ok(AllOutputsResponse::get_response(resp))
+    }
+
+    pub async fn get_output(&self, utxo: &Utxo) -> Result<GetCoinOutputResponse, reqwest::Error> {
+        let resp = self.call_typed(GetOutputRequest { utxo: *utxo }).await?;
+        Ok(GetCoinOutputResponse::get_response(resp))
+    }
+
+    pub async fn get_memo_output(
+        &self,
+        utxo: &Utxo,
+    ) -> Result<GetMemoOutputResponse, reqwest::Error> {
+        let resp = self
+            .call_typed(GetMemoOutputRequest { utxo: *utxo })
+            .await?;
+        Ok(GetMemoOutputResponse::get_response(resp))
+    }
+
+    pub async fn get_state_output(
+        &self,
+        utxo: &Utxo,
+    ) -> Result<GetStateOutputResponse, reqwest::Error> {
+        let resp = self
+            .call_typed(GetStateOutputRequest { utxo: *utxo })
+            .await?;
+        Ok(GetStateOutputResponse::get_response(resp))
+    }
+
+    pub async fn get_utxos_from_db(
+        &self,
+        query: QueryUtxoFromDB,
+    ) -> Result<GetUtxosFromDBResponse, reqwest::Error> {
+        let resp = self.call_typed(query).await?;
+        Ok(GetUtxosFromDBResponse::get_response(resp))
+    }
+
+    pub async fn get_utxos_detailed(
+        &self,
+        query: UtxoDetailedQuery,
+    ) -> Result<GetUtxosDetailedResponse, reqwest::Error> {
+        let resp = self.call_typed(query).await?;
+        Ok(GetUtxosDetailedResponse::get_response(resp))
+    }
+
+    pub async fn get_block_tx_root(
+        &self,
+        height: u64,
+    ) -> Result<GetBlockTxRootResponse, reqwest::Error> {
+        let resp = self.call_typed(GetBlockTxRootRequest { height }).await?;
+        Ok(GetBlockTxRootResponse::get_response(resp))
+    }
+
+    pub async fn get_tx_inclusion_proof(
+        &self,
+        txid: String,
+    ) -> Result<GetTxInclusionProofResponse, reqwest::Error> {
+        let resp = self.call_typed(GetTxInclusionProofRequest { txid }).await?;
+        Ok(GetTxInclusionProofResponse::get_response(resp))
+    }
+
+    pub async fn get_block_fees(
+        &self,
+        height: u64,
+    ) -> Result<GetBlockFeesResponse, reqwest::Error> {
+        let resp = self.call_typed(GetBlockFeesRequest { height }).await?;
+        Ok(GetBlockFeesResponse::get_response(resp))
+    }
+
+    pub async fn get_block_apply_result(
+        &self,
+        height: u64,
+    ) -> Result<GetBlockApplyResultResponse, reqwest::Error> {
+        let resp = self
+            .call_typed(GetBlockApplyResultRequest { height })
+            .await?;
+        Ok(GetBlockApplyResultResponse::get_response(resp))
+    }
+
+    pub async fn get_fee_pool_stats(
+        &self,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<GetFeePoolStatsResponse, reqwest::Error> {
+        let params = GetFeePoolStatsRequest {
+            from_height,
+            to_height,
+        };
+        let resp = self.call_typed(params).await?;
+        Ok(GetFeePoolStatsResponse::get_response(resp))
+    }
+
+    pub async fn get_burn_history(
+        &self,
+        address: String,
+    ) -> Result<GetBurnHistoryResponse, reqwest::Error> {
+        let resp = self.call_typed(GetBurnHistoryRequest { address }).await?;
+        Ok(GetBurnHistoryResponse::get_response(resp))
+    }
+
+    pub async fn get_failed_txs(
+        &self,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<GetFailedTxsResponse, reqwest::Error> {
+        let params = GetFailedTxsRequest {
+            from_height,
+            to_height,
+        };
+        let resp = self.call_typed(params).await?;
+        Ok(GetFailedTxsResponse::get_response(resp))
+    }
+
+    /// Not retried, see `Method::is_idempotent`.
+    pub async fn reprocess_failed_tx(
+        &self,
+        txid: String,
+    ) -> Result<ReprocessFailedTxResponse, reqwest::Error> {
+        let resp = self.call_typed(ReprocessFailedTxRequest { txid }).await?;
+        Ok(ReprocessFailedTxResponse::get_response(resp))
+    }
+
+    /// Newest txs first, pages start at 0.
+    pub async fn get_transactions_by_address(
+        &self,
+        address: String,
+        page: usize,
+    ) -> Result<GetTransactionsByAddressResponse, reqwest::Error> {
+        let params = GetTransactionsByAddressRequest { address, page };
+        let resp = self.call_typed(params).await?;
+        Ok(GetTransactionsByAddressResponse::get_response(resp))
+    }
+
+    /// Utxo events of the txs of `address` in the blocks `from_height..=to_height`,
+    /// empty if the node no longer retains them, see [`super::history`].
+    pub async fn get_utxo_history(
+        &self,
+        address: String,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<GetUtxoHistoryResponse, reqwest::Error> {
+        let params = GetUtxoHistoryRequest {
+            address,
+            from_height,
+            to_height,
+        };
+        let resp = self.call_typed(params).await?;
+        Ok(GetUtxoHistoryResponse::get_response(resp))
+    }
+
+    /// Timings of the `count` most recent blocks, newest first.
+    pub async fn get_block_processing_stats(
+        &self,
+        count: usize,
+    ) -> Result<GetBlockProcessingStatsResponse, reqwest::Error> {
+        let params = GetBlockProcessingStatsRequest { count };
+        let resp = self.call_typed(params).await?;
+        Ok(GetBlockProcessingStatsResponse::get_response(resp))
+    }
+
+    /// `encoding` is "hex" or "base64".
+    pub async fn decode_tx(
+        &self,
+        data: String,
+        encoding: &str,
+    ) -> Result<DecodeTxResponse, reqwest::Error> {
+        let params = DecodeTxRequest {
+            data,
+            encoding: encoding.to_string(),
+        };
+        let resp = self.call_typed(params).await?;
+        Ok(DecodeTxResponse::get_response(resp))
+    }
+
+    /// Contract registered for a script address of either network.
+    pub async fn get_script_contract(
+        &self,
+        address: String,
+    ) -> Result<GetScriptContractResponse, reqwest::Error> {
+        let resp = self
+            .call_typed(GetScriptContractRequest { address })
+            .await?;
+        Ok(GetScriptContractResponse::get_response(resp))
+    }
+
+    pub async fn list_script_contracts(
+        &self,
+    ) -> Result<ListScriptContractsResponse, reqwest::Error> {
+        let resp = self.call_typed(ListScriptContractsRequest {}).await?;
+        Ok(ListScriptContractsResponse::get_response(resp))
+    }
+
+    /// Moves a pruned memo output back to the utxo set, needs an admin API key.
+    pub async fn restore_pruned_output(
+        &self,
+        utxo: Utxo,
+    ) -> Result<RestorePrunedOutputResponse, reqwest::Error> {
+        let resp = self.call_typed(RestorePrunedOutputRequest { utxo }).await?;
+        Ok(RestorePrunedOutputResponse::get_response(resp))
+    }
+
+    /// Audit records of the mutating calls matching `filter`, newest first,
+    /// needs an admin API key.
+    pub async fn get_audit_log(
+        &self,
+        page: usize,
+        filter: RpcAuditFilter,
+    ) -> Result<GetAuditLogResponse, reqwest::Error> {
+        let query = AuditLogQuery { page, filter };
+        let resp = self.call_typed(query).await?;
+        Ok(GetAuditLogResponse::get_response(resp))
+    }
+
+    pub async fn audit_utxo_store(&self) -> Result<AuditUtxoStoreResponse, reqwest::Error> {
+        let resp = self.call_typed(AuditUtxoStoreRequest {}).await?;
+        Ok(AuditUtxoStoreResponse::get_response(resp))
+    }
+
+    /// Entries and encoded sizes of the utxo partitions of the node.
+    pub async fn get_storage_stats(&self) -> Result<GetStorageStatsResponse, reqwest::Error> {
+        let resp = self.call_typed(GetStorageStatsRequest {}).await?;
+        Ok(GetStorageStatsResponse::get_response(resp))
+    }
+
+    /// Shrinks the utxo partitions and the address index of the node, needs an
+    /// admin API key.
+    pub async fn compact_storage(&self) -> Result<CompactStorageResponse, reqwest::Error> {
+        let resp = self.call_typed(CompactStorageRequest {}).await?;
+        Ok(CompactStorageResponse::get_response(resp))
+    }
+
+    /// Status of the node signed by its key, check it with
+    /// [`super::status::verify_signed_status`] against the pinned key of the node.
+    pub async fn get_signed_status(&self) -> Result<GetSignedStatusResponse, reqwest::Error> {
+        let resp = self.call_typed(GetSignedStatusRequest {}).await?;
+        Ok(GetSignedStatusResponse::get_response(resp))
+    }
+
+    /// Parameters of the chain of the node, see [`super::chaininfo::configure_network`].
+    pub async fn get_chain_info(&self) -> Result<GetChainInfoResponse, reqwest::Error> {
+        let resp = self.call_typed(GetChainInfoRequest {}).await?;
+        Ok(GetChainInfoResponse::get_response(resp))
+    }
+
+    /// Reloads the node key from its file, needs an admin API key.
+    pub async fn rotate_node_key(&self) -> Result<RotateNodeKeyResponse, reqwest::Error> {
+        let resp = self.call_typed(RotateNodeKeyRequest {}).await?;
+        Ok(RotateNodeKeyResponse::get_response(resp))
+    }
+
+    /// Reads the TLS certificates of the node again, needs an admin API key and,
+    /// over mutual TLS, a client certificate.
+    pub async fn reload_tls(&self) -> Result<ReloadTlsResponse, reqwest::Error> {
+        let resp = self.call_typed(ReloadTlsRequest {}).await?;
+        Ok(ReloadTlsResponse::get_response(resp))
+    }
+
+    /// Sets the block weight limit of a halted node to `max_block_weight` and applies
+    /// the blocks held since the rejected one, needs an admin API key.
+    pub async fn resume_block_processing(
+        &self,
+        max_block_weight: u64,
+    ) -> Result<ResumeBlockProcessingResponse, reqwest::Error> {
+        let resp = self
+            .call_typed(ResumeBlockProcessingRequest { max_block_weight })
+            .await?;
+        Ok(ResumeBlockProcessingResponse::get_response(resp))
+    }
+
+    /// Checks the inputs of a transfer against the utxo set and the mempool,
+    /// before its proof is generated.
+    pub async fn simulate_transfer(
+        &self,
+        inputs: Vec<SimulatedInput>,
+    ) -> Result<SimulateTransferResponse, reqwest::Error> {
+        let query = SimulateTransferQuery { inputs };
+        let resp = self.call_typed(query).await?;
+        Ok(SimulateTransferResponse::get_response(resp))
+    }
+
+    /// Pending txs of the node, highest fee rate first, and which of them can be
+    /// replaced by a tx paying more.
+    pub async fn get_mempool(&self) -> Result<GetMempoolResponse, reqwest::Error> {
+        let resp = self.call_typed(GetMempoolRequest {}).await?;
+        Ok(GetMempoolResponse::get_response(resp))
+    }
+
+    /// Latest status of a tx, `Unknown` for a tx the node has not seen.
+    pub async fn tx_status(&self, txid: String) -> Result<TxStatusResponse, reqwest::Error> {
+        let resp = self.call_typed(TxStatusRequest { txid }).await?;
+        Ok(TxStatusResponse::get_response(resp))
+    }
+
+    /// Status transitions of a tx, oldest first.
+    pub async fn get_tx_status_history(
+        &self,
+        txid: String,
+    ) -> Result<GetTxStatusHistoryResponse, reqwest::Error> {
+        let resp = self.call_typed(GetTxStatusHistoryRequest { txid }).await?;
+        Ok(GetTxStatusHistoryResponse::get_response(resp))
+    }
+
+    /// Peers the node relays committed txs to, with the txs relayed to each.
+    pub async fn get_peers(&self) -> Result<GetPeersResponse, reqwest::Error> {
+        let resp = self.call_typed(GetPeersRequest {}).await?;
+        Ok(GetPeersResponse::get_response(resp))
+    }
+
+    /// Evaluates a program against utxos of the node and candidate outputs,
+    /// without a proof. Nothing is committed.
+    pub async fn eval_script(
+        &self,
+        query: EvalScriptQuery,
+    ) -> Result<EvalScriptResponse, reqwest::Error> {
+        let resp = self.call_typed(query).await?;
+        Ok(EvalScriptResponse::get_response(resp))
+    }
+
+    /// Balance of an address indexed by the node, None if it is not indexed.
+    pub async fn get_indexed_balance(
+        &self,
+        address: String,
+    ) -> Result<GetIndexedBalanceResponse, reqwest::Error> {
+        let resp = self
+            .call_typed(GetIndexedBalanceRequest { address })
+            .await?;
+        Ok(GetIndexedBalanceResponse::get_response(resp))
+    }
+}
+
+/// Blocking `send_json` on the shared runtime.
+///
+/// # Panics
+///
+/// Panics if called from inside an async runtime, like `reqwest::blocking`.
+pub fn send_json_blocking(
+    url: String,
+    method: Method,
+    body: String,
+) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
+    let client = AsyncRpcClient::with_client(BLOCKING_CLIENT.clone(), url);
+    BLOCKING_RUNTIME.block_on(client.send_json(method, body))
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpcclient::txrequest::RpcRequest;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[derive(Clone)]
+    enum Reply {
+        Json(String),
+        Status(u16),
+        Delay(Duration),
+    }
+
+    fn success(result: &str) -> Reply {
+        Reply::Json(format!(r#"{{"jsonrpc":"2.0","result":{},"id":1}}"#, result))
+    }
+
+    fn server_busy() -> Reply {
+        Reply::Json(format!(
+            r#"{{"jsonrpc":"2.0","error":{{"code":{},"message":"Server busy, retry later"}},"id":1}}"#,
+            SERVER_BUSY_CODE
+        ))
+    }
+
+    /// Answers each request with the next scripted reply, repeating the last one.
+    /// Returns the url and the number of requests served.
+    fn mock_server(replies: Vec<Reply>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let replies = Arc::new(Mutex::new(replies));
+        let counter = Arc::clone(&hits);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let reply = {
+                    let mut replies = replies.lock().unwrap();
+                    if replies.len() > 1 {
+                        replies.remove(0)
+                    } else {
+                        replies[0].clone()
+                    }
+                };
+                thread::spawn(move || serve(stream, reply));
+            }
+        });
+        (url, hits)
+    }
+
+    fn serve(mut stream: TcpStream, reply: Reply) {
+        read_request(&mut stream);
+        let (status, body) = match reply {
+            Reply::Json(body) => (200, body),
+            Reply::Status(status) => (status, String::new()),
+            Reply::Delay(delay) => {
+                thread::sleep(delay);
+                (200, String::new())
+            }
+        };
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+    }
+
+    fn read_request(stream: &mut TcpStream) {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = match stream.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            data.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&data).to_string();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        if name.eq_ignore_ascii_case("content-length") {
+                            value.trim().parse::<usize>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .unwrap_or(0);
+                if data.len() >= end + 4 + length {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn client(url: String, timeout: Duration, max_retries: u32) -> AsyncRpcClient {
+        let retry = RetryConfig {
+            max_retries,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+        };
+        AsyncRpcClient::with_config(url, timeout, retry).unwrap()
+    }
+
+    #[tokio::test]
+    async fn retry_read_method_test() {
+        let (url, hits) = mock_server(vec![
+            Reply::Status(503),
+            server_busy(),
+            success(r#"["a1", "b2"]"#),
+        ]);
+        let client = client(url, Duration::from_secs(5), 3);
+        let utxos = client.all_utxos().await.unwrap();
+        assert_eq!(utxos.all_utxo, vec!["a1".to_string(), "b2".to_string()]);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_limit_test() {
+        let (url, hits) = mock_server(vec![Reply::Status(503)]);
+        let client = client(url, Duration::from_secs(5), 2);
+        let err = client.all_utxos().await.unwrap_err();
+        assert_eq!(err.status().map(|s| s.as_u16()), Some(503));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn timeout_test() {
+        let (url, hits) = mock_server(vec![Reply::Delay(Duration::from_secs(2))]);
+        let client = client(url, Duration::from_millis(200), 1);
+        let err = client.all_utxos().await.unwrap_err();
+        assert!(err.is_timeout());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn tx_commit_not_retried_test() {
+        let (url, hits) = mock_server(vec![Reply::Status(503), success(r#""ok""#)]);
+        let client = client(url, Duration::from_secs(5), 3);
+        let err = client
+            .send_json(Method::txCommit, "{}".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status().map(|s| s.as_u16()), Some(503));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let (url, hits) = mock_server(vec![server_busy(), success(r#""ok""#)]);
+        let client =
+            AsyncRpcClient::with_config(url, Duration::from_secs(5), RetryConfig::default())
+                .unwrap();
+        let resp = client
+            .send_json(Method::txCommit, "{}".to_string())
+            .await
+            .unwrap();
+        assert!(resp.result.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn blocking_send_test() {
+        let (url, hits) = mock_server(vec![server_busy(), success(r#"["a1"]"#)]);
+        let tx_send: RpcBody<Vec<String>> = RpcRequest::new(Vec::new(), Method::allUtxos);
+        let res = tx_send.send(url);
+        let response = AllUtxoResponse::get_response(res.unwrap());
+        assert_eq!(response.all_utxo, vec!["a1".to_string()]);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn backoff_test() {
+        let retry = RetryConfig {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for attempt in 0..40 {
+            let delay = retry.backoff(attempt);
+            let full =
+                Duration::from_millis(100 * 2u64.pow(attempt.min(20))).min(Duration::from_secs(1));
+            assert!(delay >= full / 2 && delay <= full);
+        }
+    }
+}
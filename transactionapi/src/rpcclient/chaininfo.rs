@@ -0,0 +1,50 @@
+//! Configuration of a client from the chain info of its node, see `getChainInfo`.
+//!
+//! A wallet reads the chain info when it connects and installs the address magic
+//! bytes of the node with [`configure_network`] before it parses any address, so it
+//! does not hardcode the bytes of the chain. The bytes are installed once per process:
+//! another node of the same chain is accepted, a node of another chain is refused.
+
+use super::async_client::AsyncRpcClient;
+pub use crate::rpcserver::{ChainInfo, ContractSummary, CHAIN_INFO_VERSION};
+use address::{network_config, set_network_config};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ChainInfoError {
+    #[error("Request failed, {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("The node answered no chain info")]
+    Unavailable,
+
+    #[error("Unsupported chain info version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Address magic bytes of the node not installed, {0}")]
+    NetworkConfig(&'static str),
+}
+
+/// Installs the address magic bytes of `info`, see the module docs.
+pub fn configure_network(info: &ChainInfo) -> Result<(), ChainInfoError> {
+    if info.version > CHAIN_INFO_VERSION {
+        return Err(ChainInfoError::UnsupportedVersion(info.version));
+    }
+    match set_network_config(info.network_config) {
+        Ok(()) => Ok(()),
+        // installed by an earlier connection to the same chain
+        Err(_) if network_config() == info.network_config => Ok(()),
+        Err(e) => Err(ChainInfoError::NetworkConfig(e)),
+    }
+}
+
+/// Reads the chain info of the node of `client` and installs its address magic bytes.
+pub async fn configure_from_node(client: &AsyncRpcClient) -> Result<ChainInfo, ChainInfoError> {
+    let info = client
+        .get_chain_info()
+        .await?
+        .info
+        .ok_or(ChainInfoError::Unavailable)?;
+    configure_network(&info)?;
+    Ok(info)
+}
@@ -0,0 +1,439 @@
+//! Export of the history of the addresses of a wallet, e.g. for accounting.
+//!
+//! The txs of each address in a range of blocks are listed with
+//! `getTransactionsByAddress` and their utxos read with `getUtxoHistory`. A coin is
+//! of the address if its view key opens the owner of the coin and decrypts its value,
+//! so the outputs a transfer gives under an updated address are found, and the coins
+//! of the other accounts of a tx are never counted for the address.
+//!
+//! A tx is classified on the values of the coins of the address it spends and creates:
+//!
+//! - `burn`: coins are spent and the tx creates no coin
+//! - `self_transfer`: coins are spent and every coin created is of the address, the
+//!   amount is the value of the coins created
+//! - `received` or `sent`: the difference of the values created and spent
+//!
+//! A tx spending and creating the same value of the address while other accounts get
+//! coins used the address as a decoy of a QuisQuis transfer, it is left out. The
+//! counterparty is the only other owner of the coins spent by a receipt, or of the
+//! coins created by a payment.
+//!
+//! The addresses of a key, e.g. the updated addresses its transfers give, are listed
+//! together: a tx of several of them is exported once, under the first one.
+//!
+//! The node keeps the utxo events of its latest blocks in memory only. A range it no
+//! longer retains fails the export rather than leaving txs out.
+
+use super::async_client::AsyncRpcClient;
+use super::typed::{GetTransactionsByAddressRequest, GetUtxoHistoryRequest};
+use address::Standard;
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::keys::SecretKey;
+use quisquislib::ristretto::RistrettoSecretKey;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+use thiserror::Error;
+use utxo_in_memory::blockoperations::addresstx::{AddressTx, TxDirection, ADDRESS_TX_PAGE_SIZE};
+use utxo_in_memory::blockoperations::balanceindexer::{decrypt_value, BSGS_TABLE};
+use utxo_in_memory::blockoperations::subscriptions::{UtxoEvent, UtxoEventKind};
+use zkvm::zkos_types::IOType;
+
+/// An address of the wallet and the scalar of its key, used as view key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewKey {
+    /// Hex of the standard address
+    pub address: String,
+    pub scalar: Scalar,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    Received,
+    Sent,
+    SelfTransfer,
+    Burn,
+}
+
+impl HistoryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryKind::Received => "received",
+            HistoryKind::Sent => "sent",
+            HistoryKind::SelfTransfer => "self_transfer",
+            HistoryKind::Burn => "burn",
+        }
+    }
+}
+
+/// A tx moving value of an address of the wallet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Address of the wallet
+    pub address: String,
+    pub txid: String,
+    pub height: u64,
+    /// Time of the block header, empty if the block carried no header
+    pub timestamp: String,
+    pub kind: HistoryKind,
+    pub counterparty: Option<String>,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("Rpc error {0}")]
+    Rpc(#[from] reqwest::Error),
+
+    #[error("{method} failed for {address}, {message}")]
+    Query {
+        method: &'static str,
+        address: String,
+        message: String,
+    },
+
+    #[error("The utxos of tx {txid} of {address} are no longer retained by the node")]
+    NotRetained { address: String, txid: String },
+
+    #[error("Write failed, {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Json encoding failed, {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Writes the history of `keys` in the blocks `from_height..=to_height` to `out`,
+/// in block order, and returns it.
+pub async fn export_history(
+    client: &AsyncRpcClient,
+    keys: &[ViewKey],
+    from_height: u64,
+    to_height: u64,
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> Result<Vec<HistoryEntry>, HistoryError> {
+    let mut entries = Vec::new();
+    // txs exported for a key, by txid and key
+    let mut exported = HashSet::new();
+    for key in keys {
+        for entry in key_history(client, key, from_height, to_height).await? {
+            if exported.insert((entry.txid.clone(), key.scalar.to_bytes())) {
+                entries.push(entry);
+            }
+        }
+    }
+    // stable, the entries of a block keep the order of the keys
+    entries.sort_by_key(|entry| entry.height);
+    write_history(&entries, format, out)?;
+    Ok(entries)
+}
+
+/// Writes `entries` as a JSON array, or as CSV with a header line.
+pub fn write_history(
+    entries: &[HistoryEntry],
+    format: ExportFormat,
+    out: &mut impl Write,
+) -> Result<(), HistoryError> {
+    match format {
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut *out, entries)?,
+        ExportFormat::Csv => {
+            writeln!(
+                out,
+                "address,txid,height,timestamp,kind,counterparty,amount"
+            )?;
+            for entry in entries {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{}",
+                    entry.address,
+                    entry.txid,
+                    entry.height,
+                    entry.timestamp,
+                    entry.kind.as_str(),
+                    entry.counterparty.as_deref().unwrap_or(""),
+                    entry.amount
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Entry of `key` for the utxo events of a tx, None if the tx moved no value of it.
+pub fn classify(key: &ViewKey, events: &[&UtxoEvent]) -> Option<HistoryEntry> {
+    let first = events.first()?;
+    let (mut spent, mut received) = (0u64, 0u64);
+    let (mut owns_spent, mut owns_created, mut creates) = (false, false, false);
+    // owners of the other coins spent and created
+    let mut payers = BTreeSet::new();
+    let mut payees = BTreeSet::new();
+    for event in events.iter().filter(|event| event.io_type == IOType::Coin) {
+        let value = owned_value(key, event);
+        match (event.event, value) {
+            (UtxoEventKind::Removed, Some(value)) => {
+                spent += value;
+                owns_spent = true;
+            }
+            (UtxoEventKind::Removed, None) => {
+                payers.insert(event.address.as_str());
+            }
+            (UtxoEventKind::Added, Some(value)) => {
+                received += value;
+                owns_created = true;
+                creates = true;
+            }
+            (UtxoEventKind::Added, None) => {
+                payees.insert(event.address.as_str());
+                creates = true;
+            }
+        }
+    }
+    let (kind, amount, counterparty) = if !owns_spent && !owns_created {
+        return None;
+    } else if owns_spent && !creates {
+        (HistoryKind::Burn, spent, None)
+    } else if owns_spent && owns_created && payees.is_empty() {
+        (HistoryKind::SelfTransfer, received, None)
+    } else if received > spent {
+        (HistoryKind::Received, received - spent, only(&payers))
+    } else if spent > received {
+        (HistoryKind::Sent, spent - received, only(&payees))
+    } else {
+        // a decoy of the anonymity set
+        return None;
+    };
+    Some(HistoryEntry {
+        address: key.address.clone(),
+        txid: first.txid.clone(),
+        height: first.height,
+        timestamp: first.time.clone(),
+        kind,
+        counterparty,
+        amount,
+    })
+}
+
+// value of the coin of `event` if the key opens its owner
+fn owned_value(key: &ViewKey, event: &UtxoEvent) -> Option<u64> {
+    let coin = event.output.as_out_coin()?;
+    let owner = Standard::from_hex_with_error(&coin.owner).ok()?;
+    let sk: RistrettoSecretKey = SecretKey::from_bytes(key.scalar.as_bytes());
+    owner.public_key.verify_keypair(&sk).ok()?;
+    decrypt_value(&coin.encrypt, &key.scalar, &BSGS_TABLE)
+}
+
+fn only(owners: &BTreeSet<&str>) -> Option<String> {
+    match owners.len() {
+        1 => owners.iter().next().map(|owner| owner.to_string()),
+        _ => None,
+    }
+}
+
+// history of one address
+async fn key_history(
+    client: &AsyncRpcClient,
+    key: &ViewKey,
+    from_height: u64,
+    to_height: u64,
+) -> Result<Vec<HistoryEntry>, HistoryError> {
+    let listed = address_txs(client, &key.address, from_height, to_height).await?;
+    let request = GetUtxoHistoryRequest {
+        address: key.address.clone(),
+        from_height,
+        to_height,
+    };
+    let events = client
+        .request(request)
+        .await?
+        .map_err(|e| HistoryError::Query {
+            method: "getUtxoHistory",
+            address: key.address.clone(),
+            message: e.message,
+        })?;
+
+    // events by tx, in block order
+    let mut txs: Vec<(&str, Vec<&UtxoEvent>)> = Vec::new();
+    for event in events.iter() {
+        match txs.iter_mut().find(|(txid, _)| *txid == event.txid) {
+            Some((_, tx_events)) => tx_events.push(event),
+            None => txs.push((event.txid.as_str(), vec![event])),
+        }
+    }
+    // a tx creating a utxo of the address has events, unless the node dropped them
+    for tx in listed.iter().filter(|tx| tx.direction != TxDirection::In) {
+        if !txs
+            .iter()
+            .any(|(txid, _)| txid.eq_ignore_ascii_case(&tx.txid))
+        {
+            return Err(HistoryError::NotRetained {
+                address: key.address.clone(),
+                txid: tx.txid.clone(),
+            });
+        }
+    }
+    Ok(txs
+        .iter()
+        .filter_map(|(_, tx_events)| classify(key, tx_events))
+        .collect())
+}
+
+// txs of `address` in the blocks of the range, newest first
+async fn address_txs(
+    client: &AsyncRpcClient,
+    address: &str,
+    from_height: u64,
+    to_height: u64,
+) -> Result<Vec<AddressTx>, HistoryError> {
+    let mut txs = Vec::new();
+    for page in 0.. {
+        let request = GetTransactionsByAddressRequest {
+            address: address.to_string(),
+            page,
+        };
+        let entries = client
+            .request(request)
+            .await?
+            .map_err(|e| HistoryError::Query {
+                method: "getTransactionsByAddress",
+                address: address.to_string(),
+                message: e.message,
+            })?;
+        let last_page = entries.len() < ADDRESS_TX_PAGE_SIZE
+            || entries
+                .last()
+                .map_or(true, |tx| tx.block_height < from_height);
+        txs.extend(
+            entries
+                .into_iter()
+                .filter(|tx| tx.block_height >= from_height && tx.block_height <= to_height),
+        );
+        if last_page {
+            break;
+        }
+    }
+    Ok(txs)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use address::{Address, Network};
+    use quisquislib::elgamal::ElGamalCommitment;
+    use quisquislib::keys::PublicKey;
+    use quisquislib::ristretto::RistrettoPublicKey;
+    use zkvm::zkos_types::{Output, OutputCoin, OutputData, Utxo};
+
+    fn key() -> (ViewKey, RistrettoPublicKey) {
+        let mut rng = rand::thread_rng();
+        let scalar = Scalar::random(&mut rng);
+        let sk: RistrettoSecretKey = SecretKey::from_bytes(scalar.as_bytes());
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let address = Address::standard_address(Network::Mainnet, pk).as_hex();
+        (ViewKey { address, scalar }, pk)
+    }
+
+    // coin event of `value` owned by `pk`, under an updated key if `update`
+    fn coin(pk: &RistrettoPublicKey, value: u64, update: bool, kind: UtxoEventKind) -> UtxoEvent {
+        let mut rng = rand::thread_rng();
+        let pk = match update {
+            true => RistrettoPublicKey::update_public_key(pk, Scalar::random(&mut rng)),
+            false => *pk,
+        };
+        let encrypt = ElGamalCommitment::generate_commitment(
+            &pk,
+            Scalar::random(&mut rng),
+            Scalar::from(value),
+        );
+        let owner = Address::standard_address(Network::Mainnet, pk).as_hex();
+        let output = Output::coin(OutputData::Coin(OutputCoin { encrypt, owner }));
+        UtxoEvent::new(Utxo::random(), output, "aa", 7, kind).unwrap()
+    }
+
+    #[test]
+    fn classify_test() {
+        use UtxoEventKind::{Added, Removed};
+        let (alice, alice_pk) = key();
+        let (bob, bob_pk) = key();
+        let classify_all = |events: &[UtxoEvent]| {
+            let events: Vec<&UtxoEvent> = events.iter().collect();
+            classify(&alice, &events).map(|entry| (entry.kind, entry.amount, entry.counterparty))
+        };
+
+        // payment to bob with the change under an updated key
+        let events = [
+            coin(&alice_pk, 50, false, Removed),
+            coin(&bob_pk, 20, false, Added),
+            coin(&alice_pk, 28, true, Added),
+        ];
+        assert_eq!(
+            classify_all(&events),
+            Some((HistoryKind::Sent, 22, Some(events[1].address.clone())))
+        );
+        // the same coins seen by bob
+        let events: Vec<&UtxoEvent> = events.iter().collect();
+        let entry = classify(&bob, &events).unwrap();
+        assert_eq!(entry.kind, HistoryKind::Received);
+        assert_eq!(entry.amount, 20);
+        assert_eq!(entry.counterparty, Some(alice.address.clone()));
+
+        // alice as a decoy of a transfer of bob, then of nobody
+        let events = [
+            coin(&bob_pk, 30, false, Removed),
+            coin(&alice_pk, 10, false, Removed),
+            coin(&bob_pk, 30, true, Added),
+            coin(&alice_pk, 10, true, Added),
+        ];
+        assert_eq!(classify_all(&events), None);
+        assert_eq!(classify_all(&events[..1]), None);
+
+        assert_eq!(
+            classify_all(&[
+                coin(&alice_pk, 10, false, Removed),
+                coin(&alice_pk, 9, true, Added)
+            ]),
+            Some((HistoryKind::SelfTransfer, 9, None))
+        );
+        assert_eq!(
+            classify_all(&[coin(&alice_pk, 10, false, Removed)]),
+            Some((HistoryKind::Burn, 10, None))
+        );
+    }
+
+    #[test]
+    fn write_history_test() {
+        let entries = vec![HistoryEntry {
+            address: "0c0a".to_string(),
+            txid: "aa".to_string(),
+            height: 7,
+            timestamp: "2024-03-04T11:20:13Z".to_string(),
+            kind: HistoryKind::SelfTransfer,
+            counterparty: None,
+            amount: 9,
+        }];
+        let mut csv = Vec::new();
+        write_history(&entries, ExportFormat::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "address,txid,height,timestamp,kind,counterparty,amount\n\
+             0c0a,aa,7,2024-03-04T11:20:13Z,self_transfer,,9\n"
+        );
+
+        let mut json = Vec::new();
+        write_history(&entries, ExportFormat::Json, &mut json).unwrap();
+        let decoded: Vec<HistoryEntry> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(decoded, entries);
+        assert!(String::from_utf8(json)
+            .unwrap()
+            .contains("\"self_transfer\""));
+    }
+}
@@ -0,0 +1,309 @@
+//! Ingestion of the `TransferTx(txId, txByteCode, txFee, ethAddress)` events of the zkos contract.
+//!
+//! The `txByteCode` of an event is decoded with [`decode_transaction`], which checks the
+//! version and the header counts of the tx. The declared `txId` has to be the [`tx_id`]
+//! of the decoded tx, and the declared `txFee` its fee. A tx passing these checks is
+//! committed through the [`AsyncRpcClient`]; a txid committed once is not committed again,
+//! so an event delivered twice by the chain listener is harmless. Every event that can not
+//! be committed is written with its error to a [`DeadLetterStore`] for replay.
+//!
+//! There is no chain listener in this tree, the events are handed over already decoded.
+
+use super::async_client::{AsyncRpcClient, RetryConfig};
+use crate::rpcserver::service::tx_id;
+use prometheus::{register_counter_vec, CounterVec};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+use transaction::decode::decode_transaction;
+use transaction::Transaction;
+use utxo_in_memory::rpcaudit::unix_time;
+
+lazy_static! {
+    static ref TRANSFER_TX_REJECTED: CounterVec = register_counter_vec!(
+        "eth_transfer_tx_rejected",
+        "TransferTx events that were not committed",
+        &["reason"]
+    )
+    .unwrap();
+}
+
+/// A `TransferTx` event of the zkos contract.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferTxEvent {
+    pub tx_id: String,
+    /// Hex encoded tx, with or without a `0x` prefix
+    pub tx_byte_code: String,
+    pub tx_fee: u64,
+    pub eth_address: String,
+}
+
+#[derive(Error, Debug)]
+pub enum IngestError {
+    #[error("Invalid tx bytecode, {0}")]
+    InvalidByteCode(String),
+
+    #[error("TxId mismatch, declared {declared} but the tx has {computed}")]
+    TxIdMismatch { declared: String, computed: String },
+
+    #[error("Fee mismatch, declared {declared} but the tx pays {tx_fee}")]
+    FeeMismatch { declared: u64, tx_fee: u64 },
+
+    #[error("Tx rejected by the node, {0}")]
+    Rejected(String),
+
+    #[error("Rpc error {0}")]
+    Rpc(#[from] reqwest::Error),
+}
+
+impl IngestError {
+    // label of the rejection metric
+    fn reason(&self) -> &'static str {
+        match self {
+            IngestError::InvalidByteCode(_) => "bytecode",
+            IngestError::TxIdMismatch { .. } => "txid",
+            IngestError::FeeMismatch { .. } => "fee",
+            IngestError::Rejected(_) => "rejected",
+            IngestError::Rpc(_) => "rpc",
+        }
+    }
+}
+
+fn strip_hex_prefix(hex: &str) -> &str {
+    let hex = hex.trim();
+    hex.strip_prefix("0x").unwrap_or(hex)
+}
+
+/// Decodes the tx of `event`, checking its declared txid and fee.
+pub fn decode_transfer_event(event: &TransferTxEvent) -> Result<Transaction, IngestError> {
+    let bytes = hex::decode(strip_hex_prefix(&event.tx_byte_code))
+        .map_err(|e| IngestError::InvalidByteCode(e.to_string()))?;
+    let tx = decode_transaction(&bytes).map_err(|e| IngestError::InvalidByteCode(e.to_string()))?;
+    let computed = tx_id(&tx);
+    let declared = strip_hex_prefix(&event.tx_id).to_lowercase();
+    if declared != computed {
+        return Err(IngestError::TxIdMismatch { declared, computed });
+    }
+    if tx.get_tx_fee() != event.tx_fee {
+        return Err(IngestError::FeeMismatch {
+            declared: event.tx_fee,
+            tx_fee: tx.get_tx_fee(),
+        });
+    }
+    Ok(tx)
+}
+
+/// An event that could not be committed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    /// Unix time of the failure in seconds
+    pub timestamp: u64,
+    pub event: TransferTxEvent,
+    pub error: String,
+}
+
+/// Dead letters kept as json lines in a local file.
+#[derive(Debug)]
+pub struct DeadLetterStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl DeadLetterStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        DeadLetterStore {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends the failure of `event` to the store.
+    pub fn record(&self, event: &TransferTxEvent, error: &IngestError) -> std::io::Result<()> {
+        let letter = DeadLetter {
+            timestamp: unix_time(),
+            event: event.clone(),
+            error: error.to_string(),
+        };
+        let line = serde_json::to_string(&letter)?;
+        let _guard = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Dead letters in the order they were recorded, none if the file does not exist.
+    pub fn entries(&self) -> std::io::Result<Vec<DeadLetter>> {
+        let _guard = self.lock.lock().unwrap();
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut letters = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                letters.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(letters)
+    }
+}
+
+/// Commits the txs of `TransferTx` events to a transaction api.
+pub struct TransferTxIngestor {
+    client: AsyncRpcClient,
+    retry: RetryConfig,
+    committed: Mutex<HashSet<String>>,
+    dead_letters: DeadLetterStore,
+}
+
+impl TransferTxIngestor {
+    pub fn new(client: AsyncRpcClient, retry: RetryConfig, dead_letters: DeadLetterStore) -> Self {
+        TransferTxIngestor {
+            client,
+            retry,
+            committed: Mutex::new(HashSet::new()),
+            dead_letters,
+        }
+    }
+
+    pub fn dead_letters(&self) -> &DeadLetterStore {
+        &self.dead_letters
+    }
+
+    /// Commits the tx of `event` and returns its txid.
+    /// A failed event is counted and written to the dead letters.
+    pub async fn ingest(&self, event: &TransferTxEvent) -> Result<String, IngestError> {
+        let result = self.commit(event).await;
+        if let Err(e) = &result {
+            TRANSFER_TX_REJECTED.with_label_values(&[e.reason()]).inc();
+            if let Err(io) = self.dead_letters.record(event, e) {
+                eprintln!(
+                    "Failed to record the dead letter of tx {}: {:?}",
+                    event.tx_id, io
+                );
+            }
+        }
+        result
+    }
+
+    async fn commit(&self, event: &TransferTxEvent) -> Result<String, IngestError> {
+        let tx = decode_transfer_event(event)?;
+        let txid = tx_id(&tx);
+        if self.committed.lock().unwrap().contains(&txid) {
+            return Ok(txid);
+        }
+        // txCommit is not retried by the client, a resent tx spends inputs the node
+        // already holds as pending and is rejected
+        let mut attempt = 0;
+        let response = loop {
+            match self.client.tx_commit(tx.clone()).await {
+                Ok(response) => break response,
+                Err(e) if attempt >= self.retry.max_retries => return Err(e.into()),
+                Err(_) => {
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        };
+        response.map_err(IngestError::Rejected)?;
+        self.committed.lock().unwrap().insert(txid.clone());
+        Ok(txid)
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use transaction::reference_tx::create_dark_reference_transaction;
+
+    fn event(tx: &Transaction, tx_fee: u64) -> TransferTxEvent {
+        TransferTxEvent {
+            tx_id: format!("0x{}", tx_id(tx)),
+            tx_byte_code: format!("0x{}", hex::encode(tx.to_bytes())),
+            tx_fee,
+            eth_address: "0x5aeda56215b167893e80b4fe645ba6d5bab767de".to_string(),
+        }
+    }
+
+    // answers every txCommit with the hash of a committed tx
+    fn commit_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 65536];
+                let _ = stream.read(&mut buf);
+                let body = r#"{"jsonrpc":"2.0","result":"{\"txHash\":\"hash\"}","id":1}"#;
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        (url, hits)
+    }
+
+    #[tokio::test]
+    async fn ingest_transfer_event_test() {
+        let tx = create_dark_reference_transaction();
+        let fee = tx.get_tx_fee();
+        let (url, hits) = commit_server();
+        let path = std::env::temp_dir().join(format!("zkos-dead-letters-{}", uuid::Uuid::new_v4()));
+        let ingestor = TransferTxIngestor::new(
+            AsyncRpcClient::new(url),
+            RetryConfig::none(),
+            DeadLetterStore::new(&path),
+        );
+
+        // a valid event is committed once
+        let valid = event(&tx, fee);
+        assert_eq!(ingestor.ingest(&valid).await.unwrap(), tx_id(&tx));
+        assert_eq!(ingestor.ingest(&valid).await.unwrap(), tx_id(&tx));
+
+        let mismatched = event(&tx, fee + 1);
+        assert!(matches!(
+            ingestor.ingest(&mismatched).await,
+            Err(IngestError::FeeMismatch { .. })
+        ));
+        let mut garbage = event(&tx, fee);
+        garbage.tx_byte_code = "0xdeadbeef".to_string();
+        assert!(matches!(
+            ingestor.ingest(&garbage).await,
+            Err(IngestError::InvalidByteCode(_))
+        ));
+        let mut wrong_id = event(&tx, fee);
+        wrong_id.tx_id = "00".repeat(32);
+        assert!(matches!(
+            ingestor.ingest(&wrong_id).await,
+            Err(IngestError::TxIdMismatch { .. })
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let letters = ingestor.dead_letters().entries().unwrap();
+        let events: Vec<TransferTxEvent> = letters.into_iter().map(|letter| letter.event).collect();
+        assert_eq!(events, vec![mismatched, garbage, wrong_id]);
+        assert!(TRANSFER_TX_REJECTED.with_label_values(&["fee"]).get() >= 1.0);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -11,8 +11,12 @@ pub enum Method {
     /// Sends a transaction and waits until transaction is fully complete.
     // TxCommit,
     txCommit,
-    /// Queries status of a transaction by hash and returns the final transaction result.
-    TxStatus,
+    /// Validates a transaction as txCommit does, without committing it.
+    txValidate,
+    /// Status of a transaction by txid, tagged, with the plain status of the legacy clients.
+    txStatus,
+    /// Status transitions of a transaction by txid, oldest first.
+    getTxStatusHistory,
     getUtxos,
     getMemoUtxos,
     getStateUtxos,
@@ -24,9 +28,57 @@ pub enum Method {
     getMemoOutput,
     getStateOutput,
     getUtxosFromDB,
-    // TestCommand,
+    getUtxosDetailed,
+    getBlockTxRoot,
+    getTxInclusionProof,
+    getBlockFees,
+    getBlockApplyResult,
+    getFeePoolStats,
+    getBurnHistory,
+    getFailedTxs,
+    reprocessFailedTx,
+    getTransactionsByAddress,
+    /// Utxos added and removed by the recent txs of an address.
+    getUtxoHistory,
+    getBlockProcessingStats,
+    decodeTx,
+    registerScriptContract,
+    getScriptContract,
+    listScriptContracts,
+    restorePrunedOutput,
+    /// Audit records of the mutating calls, newest first, needs an admin API key.
+    getAuditLog,
+    auditUtxoStore,
+    getStorageStats,
+    /// Shrinks the utxo partitions and the address index, needs an admin API key.
+    compactStorage,
+    /// Chain height and utxo counts of the node, signed by the node key.
+    getSignedStatus,
+    /// Network, address magic bytes and tx limits of the chain of the node.
+    getChainInfo,
+    /// Reloads the node key from its file, needs an admin API key.
+    rotateNodeKey,
+    /// Reads the TLS certificates of the node again, needs an admin API key.
+    reloadTls,
+    /// Applies the blocks held since a block over the weight limit under a new limit,
+    /// needs an admin API key.
+    resumeBlockProcessing,
+    simulateTransfer,
+    /// Pending txs with their fee rates, and the recent replacements.
+    getMempool,
+    /// Peers the node relays committed txs to, with their relay counts.
+    getPeers,
+    evalScript,
+    getIndexedBalance,
+    TestCommand,
+}
+impl Method {
+    /// Whether the request can be sent again without side effects.
+    /// Transaction submissions are not, a retry could submit twice.
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(self, Method::txCommit | Method::TxQueue)
+    }
 }
-impl Method {}
 
 // allOutputs
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -84,6 +136,31 @@ impl GetUtxosResponse {
         utxo_vec
     }
 }
+// getUtxos, raw format
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetRawUtxosResponse {
+    pub utxos: Vec<(zkvm::zkos_types::Utxo, zkvm::zkos_types::Output)>,
+}
+impl GetRawUtxosResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetRawUtxosResponse {
+        let raw: Vec<utxo_in_memory::blockoperations::blockprocessing::RawUtxoOutput> =
+            match resp.result {
+                Ok(response) => serde_json::from_value(response).unwrap_or_default(),
+                Err(_) => Vec::new(),
+            };
+        let utxos = raw
+            .into_iter()
+            .filter_map(|entry| {
+                let bytes = hex::decode(&entry.output).ok()?;
+                let output = bincode::deserialize(&bytes).ok()?;
+                Some((entry.utxo, output))
+            })
+            .collect();
+        GetRawUtxosResponse { utxos }
+    }
+}
 // getMemoUtxos
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetMemoUtxosResponse {
@@ -154,6 +231,37 @@ impl AllUtxoResponse {
     }
 }
 
+// allUtxos chunked
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AllUtxoChunkResponse {
+    pub all_utxo: Vec<String>,
+    pub next_offset_key: Option<String>,
+}
+impl AllUtxoChunkResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> AllUtxoChunkResponse {
+        let chunk: Option<utxo_in_memory::blockoperations::blockprocessing::UtxoChunk> =
+            match resp.result {
+                Ok(response) => match serde_json::from_value(response) {
+                    Ok(response) => Some(response),
+                    Err(_) => None,
+                },
+                Err(arg) => None,
+            };
+        match chunk {
+            Some(chunk) => AllUtxoChunkResponse {
+                all_utxo: chunk.utxos,
+                next_offset_key: chunk.next_offset_key,
+            },
+            None => AllUtxoChunkResponse {
+                all_utxo: Vec::new(),
+                next_offset_key: None,
+            },
+        }
+    }
+}
+
 // getOutput
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetCoinOutputResponse {
@@ -249,6 +357,645 @@ impl GetUtxosFromDBResponse {
         GetUtxosFromDBResponse { utxo_vec: utxo_vec }
     }
 }
+// getUtxosDetailed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetUtxosDetailedResponse {
+    pub utxos: Option<utxo_in_memory::pgsql::UtxoDetailedResult>,
+}
+impl GetUtxosDetailedResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetUtxosDetailedResponse {
+        let utxos = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetUtxosDetailedResponse { utxos }
+    }
+}
+// getBlockTxRoot
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetBlockTxRootResponse {
+    pub block_tx_root: Option<utxo_in_memory::blockoperations::txroot::BlockTxRoot>,
+}
+impl GetBlockTxRootResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetBlockTxRootResponse {
+        let block_tx_root = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetBlockTxRootResponse { block_tx_root }
+    }
+}
+
+// getTxInclusionProof
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetTxInclusionProofResponse {
+    pub proof: Option<utxo_in_memory::blockoperations::txroot::TxInclusionProof>,
+}
+impl GetTxInclusionProofResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetTxInclusionProofResponse {
+        let proof = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetTxInclusionProofResponse { proof }
+    }
+}
+
+// getBlockFees
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetBlockFeesResponse {
+    pub block_fees: Option<utxo_in_memory::blockoperations::fees::BlockFees>,
+}
+impl GetBlockFeesResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetBlockFeesResponse {
+        let block_fees = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetBlockFeesResponse { block_fees }
+    }
+}
+
+// getBlockApplyResult
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetBlockApplyResultResponse {
+    pub result: Option<utxo_in_memory::blockoperations::blockresult::BlockApplyResult>,
+}
+impl GetBlockApplyResultResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetBlockApplyResultResponse {
+        let result = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetBlockApplyResultResponse { result }
+    }
+}
+
+// getFeePoolStats
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetFeePoolStatsResponse {
+    pub stats: Option<utxo_in_memory::blockoperations::fees::FeePoolStats>,
+}
+impl GetFeePoolStatsResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetFeePoolStatsResponse {
+        let stats = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetFeePoolStatsResponse { stats }
+    }
+}
+
+// getBurnHistory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetBurnHistoryResponse {
+    pub burns: Vec<utxo_in_memory::blockoperations::messages::BurnRecord>,
+}
+impl GetBurnHistoryResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetBurnHistoryResponse {
+        let burns = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        GetBurnHistoryResponse { burns }
+    }
+}
+
+// getFailedTxs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetFailedTxsResponse {
+    pub failed_txs: Vec<utxo_in_memory::blockoperations::failedtx::FailedTx>,
+}
+impl GetFailedTxsResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetFailedTxsResponse {
+        let failed_txs = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        GetFailedTxsResponse { failed_txs }
+    }
+}
+
+// reprocessFailedTx
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReprocessFailedTxResponse {
+    pub failed_tx: Option<utxo_in_memory::blockoperations::failedtx::FailedTx>,
+}
+impl ReprocessFailedTxResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> ReprocessFailedTxResponse {
+        let failed_tx = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        ReprocessFailedTxResponse { failed_tx }
+    }
+}
+
+// getTransactionsByAddress
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetTransactionsByAddressResponse {
+    pub txs: Vec<utxo_in_memory::blockoperations::addresstx::AddressTx>,
+}
+impl GetTransactionsByAddressResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetTransactionsByAddressResponse {
+        let txs = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        GetTransactionsByAddressResponse { txs }
+    }
+}
+
+// getUtxoHistory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetUtxoHistoryResponse {
+    pub events: Vec<utxo_in_memory::blockoperations::subscriptions::UtxoEvent>,
+}
+impl GetUtxoHistoryResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetUtxoHistoryResponse {
+        let events = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        GetUtxoHistoryResponse { events }
+    }
+}
+
+// getBlockProcessingStats
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetBlockProcessingStatsResponse {
+    pub stats: Vec<utxo_in_memory::blockoperations::blockstats::BlockProcessingStats>,
+}
+impl GetBlockProcessingStatsResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetBlockProcessingStatsResponse {
+        let stats = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        GetBlockProcessingStatsResponse { stats }
+    }
+}
+
+// decodeTx
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecodeTxResponse {
+    pub tx: Option<transaction::decode::TransactionView>,
+}
+impl DecodeTxResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> DecodeTxResponse {
+        let tx = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        DecodeTxResponse { tx }
+    }
+}
+
+// getScriptContract
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetScriptContractResponse {
+    pub contract: Option<utxo_in_memory::scriptregistry::ScriptContract>,
+}
+impl GetScriptContractResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetScriptContractResponse {
+        let contract = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        GetScriptContractResponse { contract }
+    }
+}
+
+// listScriptContracts
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListScriptContractsResponse {
+    pub contracts: Vec<utxo_in_memory::scriptregistry::ScriptContract>,
+}
+impl ListScriptContractsResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> ListScriptContractsResponse {
+        let contracts = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        ListScriptContractsResponse { contracts }
+    }
+}
+
+// restorePrunedOutput
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RestorePrunedOutputResponse {
+    pub log: Option<utxo_in_memory::blockoperations::pruning::PruneLog>,
+}
+impl RestorePrunedOutputResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> RestorePrunedOutputResponse {
+        let log = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        RestorePrunedOutputResponse { log }
+    }
+}
+
+// getAuditLog
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetAuditLogResponse {
+    pub records: Vec<utxo_in_memory::rpcaudit::RpcAuditRecord>,
+}
+impl GetAuditLogResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetAuditLogResponse {
+        let records = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(arg) => Vec::new(),
+        };
+        GetAuditLogResponse { records }
+    }
+}
+
+// auditUtxoStore
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditUtxoStoreResponse {
+    pub report: Option<utxo_in_memory::audit::AuditReport>,
+}
+impl AuditUtxoStoreResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> AuditUtxoStoreResponse {
+        let report = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        AuditUtxoStoreResponse { report }
+    }
+}
+
+// getStorageStats
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetStorageStatsResponse {
+    pub stats: Option<utxo_in_memory::db::StorageStats>,
+}
+impl GetStorageStatsResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetStorageStatsResponse {
+        let stats = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        GetStorageStatsResponse { stats }
+    }
+}
+
+// compactStorage
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompactStorageResponse {
+    pub report: Option<utxo_in_memory::db::compaction::CompactionReport>,
+}
+impl CompactStorageResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> CompactStorageResponse {
+        let report = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        CompactStorageResponse { report }
+    }
+}
+
+// getSignedStatus
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetSignedStatusResponse {
+    pub status: Option<crate::rpcserver::SignedStatus>,
+}
+impl GetSignedStatusResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetSignedStatusResponse {
+        let status = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        GetSignedStatusResponse { status }
+    }
+}
+
+// getChainInfo
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetChainInfoResponse {
+    pub info: Option<crate::rpcserver::ChainInfo>,
+}
+impl GetChainInfoResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetChainInfoResponse {
+        let info = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        GetChainInfoResponse { info }
+    }
+}
+
+// rotateNodeKey
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RotateNodeKeyResponse {
+    pub rotation: Option<crate::rpcserver::KeyRotation>,
+}
+impl RotateNodeKeyResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> RotateNodeKeyResponse {
+        let rotation = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        RotateNodeKeyResponse { rotation }
+    }
+}
+
+// reloadTls
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReloadTlsResponse {
+    pub reload: Option<crate::rpcserver::TlsReload>,
+}
+impl ReloadTlsResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> ReloadTlsResponse {
+        let reload = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        ReloadTlsResponse { reload }
+    }
+}
+
+// resumeBlockProcessing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResumeBlockProcessingResponse {
+    pub resume: Option<utxo_in_memory::blockoperations::blockweight::WeightResume>,
+}
+impl ResumeBlockProcessingResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> ResumeBlockProcessingResponse {
+        let resume = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        ResumeBlockProcessingResponse { resume }
+    }
+}
+
+// txValidate
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxValidateResponse {
+    pub report: Option<crate::rpcserver::TxValidationReport>,
+}
+impl TxValidateResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> TxValidateResponse {
+        let report = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        TxValidateResponse { report }
+    }
+}
+
+// simulateTransfer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimulateTransferResponse {
+    pub report: Option<utxo_in_memory::blockoperations::mempool::SimulationReport>,
+}
+impl SimulateTransferResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> SimulateTransferResponse {
+        let report = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(arg) => None,
+        };
+        SimulateTransferResponse { report }
+    }
+}
+
+// getMempool
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetMempoolResponse {
+    pub mempool: Option<utxo_in_memory::blockoperations::mempool::MempoolView>,
+}
+impl GetMempoolResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetMempoolResponse {
+        let mempool = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        GetMempoolResponse { mempool }
+    }
+}
+
+// txStatus
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxStatusResponse {
+    pub status: Option<crate::rpcserver::TxStatusView>,
+}
+impl TxStatusResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> TxStatusResponse {
+        let status = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        TxStatusResponse { status }
+    }
+}
+
+// getTxStatusHistory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetTxStatusHistoryResponse {
+    pub history: Vec<crate::rpcserver::TxStatusRecord>,
+}
+impl GetTxStatusHistoryResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetTxStatusHistoryResponse {
+        let history = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => response,
+                Err(_) => Vec::new(),
+            },
+            Err(_) => Vec::new(),
+        };
+        GetTxStatusHistoryResponse { history }
+    }
+}
+
+// getPeers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetPeersResponse {
+    pub peers: Option<Vec<crate::rpcserver::PeerStats>>,
+}
+impl GetPeersResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetPeersResponse {
+        let peers = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        GetPeersResponse { peers }
+    }
+}
+
+// evalScript
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvalScriptResponse {
+    pub result: Option<transaction::vm_run::EvalResult>,
+}
+impl EvalScriptResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> EvalScriptResponse {
+        let result = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        EvalScriptResponse { result }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetTxCommit {
     pub txHash: String,
@@ -272,3 +1019,23 @@ impl GetTxCommit {
         tx_hash
     }
 }
+
+// getIndexedBalance
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetIndexedBalanceResponse {
+    pub balance: Option<utxo_in_memory::blockoperations::balanceindexer::IndexedBalance>,
+}
+impl GetIndexedBalanceResponse {
+    pub fn get_response(
+        resp: crate::rpcclient::txrequest::RpcResponse<serde_json::Value>,
+    ) -> GetIndexedBalanceResponse {
+        let balance = match resp.result {
+            Ok(response) => match serde_json::from_value(response) {
+                Ok(response) => Some(response),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        GetIndexedBalanceResponse { balance }
+    }
+}
@@ -1,4 +1,13 @@
+pub mod async_client;
+pub mod chaininfo;
+pub mod history;
 pub mod id;
+pub mod ingest;
 pub mod method;
+pub mod outbound;
+pub mod recovery;
+pub mod status;
+pub mod tls;
 pub mod txrequest;
+pub mod typed;
 pub mod utils;
@@ -0,0 +1,670 @@
+//! Durable queue of the txs a relayer submits to a transaction api.
+//!
+//! The relayer enqueues each tx it builds, an order, a settlement or a liquidation,
+//! with an idempotency key of its own. An entry is written to the queue file before
+//! [`OutboundQueue::enqueue`] returns, and every change of its state is written
+//! before the next step, so a relayer restarted after a crash resumes from the file.
+//!
+//! The pump, [`OutboundQueue::pump_once`] or the task of [`spawn_pump`], moves an
+//! entry through these states:
+//! - `Queued`: sent with `txCommit` once due. An api that can not be reached is tried
+//!   again after the exponential backoff of the [`RetryConfig`].
+//! - `Submitting`: written before `txCommit` is sent. A commit without a response
+//!   moves the entry to `Submitted`, the node may hold the tx.
+//! - `Submitted`: committed, the pump polls `getTxInclusionProof` for the txid until
+//!   the tx is in a block and then deletes the entry. An entry not in a block within
+//!   the confirmation timeout is queued again.
+//! - `Rejected`: refused by the node, kept for inspection.
+//!
+//! An entry found `Submitting` when the queue is opened was sent before a crash and is
+//! polled as `Submitted` rather than sent again. A second enqueue of a key, or of a tx
+//! already queued under another key, returns the queued txid, so a relayer replaying
+//! its own log does not submit twice.
+
+use super::async_client::{AsyncRpcClient, RetryConfig};
+use crate::rpcserver::service::tx_id;
+use prometheus::{register_gauge, Gauge};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use transaction::decode::decode_transaction;
+use transaction::Transaction;
+use utxo_in_memory::blockoperations::txroot::{verify_inclusion, TxInclusionProof};
+use utxo_in_memory::rpcaudit::unix_time;
+
+lazy_static! {
+    static ref OUTBOUND_QUEUE_DEPTH: Gauge = register_gauge!(
+        "outbound_queue_depth",
+        "Submissions of the outbound queue not in a block yet"
+    )
+    .unwrap();
+    static ref OUTBOUND_QUEUE_OLDEST_AGE: Gauge = register_gauge!(
+        "outbound_queue_oldest_age_seconds",
+        "Age of the oldest submission of the outbound queue"
+    )
+    .unwrap();
+}
+
+/// Default time a committed tx has to be in a block before it is sent again.
+pub const DEFAULT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Default period of the pump.
+pub const DEFAULT_PUMP_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmissionKind {
+    TradeOrder,
+    LendOrder,
+    Settlement,
+    Liquidation,
+    Other,
+}
+
+/// State of a queued tx, see the module docs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmissionState {
+    Queued,
+    Submitting,
+    Submitted,
+    Rejected,
+}
+
+/// A tx of the queue, as listed by [`OutboundQueue::list_pending_submissions`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingSubmission {
+    pub idempotency_key: String,
+    pub kind: SubmissionKind,
+    pub txid: String,
+    /// Hex encoded tx
+    pub tx: String,
+    pub state: SubmissionState,
+    /// `txCommit` calls sent so far
+    pub attempts: u32,
+    /// Unix time of the enqueue
+    pub enqueued_at: u64,
+    /// Unix time of the last `txCommit`
+    pub submitted_at: Option<u64>,
+    pub last_error: Option<String>,
+    // end of the backoff, not persisted
+    #[serde(skip)]
+    not_before: Option<Instant>,
+}
+
+impl PendingSubmission {
+    fn is_due(&self, now: Instant) -> bool {
+        match self.state {
+            SubmissionState::Queued => self.not_before.map_or(true, |at| at <= now),
+            SubmissionState::Submitted => true,
+            SubmissionState::Submitting | SubmissionState::Rejected => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum QueueError {
+    #[error("Io error {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid queue entry, {0}")]
+    Encoding(#[from] serde_json::Error),
+
+    #[error("Idempotency key {0} is queued with another tx")]
+    KeyConflict(String),
+
+    #[error("No queued submission with the idempotency key {0}")]
+    UnknownKey(String),
+}
+
+/// Counts of one pass of the pump.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PumpReport {
+    pub submitted: usize,
+    pub confirmed: usize,
+    pub rejected: usize,
+    /// Commits that did not reach the api, tried again after the backoff
+    pub deferred: usize,
+}
+
+/// Txs to submit, kept as json lines in a local file.
+#[derive(Debug)]
+pub struct OutboundQueue {
+    path: PathBuf,
+    retry: RetryConfig,
+    confirm_timeout: Duration,
+    entries: Mutex<Vec<PendingSubmission>>,
+}
+
+impl OutboundQueue {
+    /// Opens the queue of the file at `path`, empty if there is no such file.
+    /// Entries sent before a crash are polled for their block before they are sent again.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        retry: RetryConfig,
+        confirm_timeout: Duration,
+    ) -> Result<Self, QueueError> {
+        let path = path.into();
+        let mut entries: Vec<PendingSubmission> = Vec::new();
+        match std::fs::File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if !line.trim().is_empty() {
+                        entries.push(serde_json::from_str(&line)?);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        for entry in entries.iter_mut() {
+            if entry.state == SubmissionState::Submitting {
+                entry.state = SubmissionState::Submitted;
+            }
+        }
+        let queue = OutboundQueue {
+            path,
+            retry,
+            confirm_timeout,
+            entries: Mutex::new(Vec::new()),
+        };
+        queue.persist(&entries)?;
+        *queue.entries.lock().unwrap() = entries;
+        queue.update_metrics();
+        Ok(queue)
+    }
+
+    /// Queues `tx` under `idempotency_key` and returns its txid. A key or a tx already
+    /// in the queue is not queued again.
+    pub fn enqueue(
+        &self,
+        tx: Transaction,
+        kind: SubmissionKind,
+        idempotency_key: impl Into<String>,
+    ) -> Result<String, QueueError> {
+        let idempotency_key = idempotency_key.into();
+        let txid = tx_id(&tx);
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries
+            .iter()
+            .find(|entry| entry.idempotency_key == idempotency_key)
+        {
+            if entry.txid != txid {
+                return Err(QueueError::KeyConflict(idempotency_key));
+            }
+            return Ok(txid);
+        }
+        if entries.iter().any(|entry| entry.txid == txid) {
+            return Ok(txid);
+        }
+        entries.push(PendingSubmission {
+            idempotency_key,
+            kind,
+            txid: txid.clone(),
+            tx: hex::encode(tx.to_bytes()),
+            state: SubmissionState::Queued,
+            attempts: 0,
+            enqueued_at: unix_time(),
+            submitted_at: None,
+            last_error: None,
+            not_before: None,
+        });
+        if let Err(e) = self.persist(&entries) {
+            entries.pop();
+            return Err(e);
+        }
+        drop(entries);
+        self.update_metrics();
+        Ok(txid)
+    }
+
+    /// Txs of the queue in the order they were enqueued.
+    pub fn list_pending_submissions(&self) -> Vec<PendingSubmission> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Number of txs not in a block yet, the rejected ones included.
+    pub fn depth(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Seconds since the enqueue of the oldest tx of the queue, 0 if it is empty.
+    pub fn oldest_age(&self) -> u64 {
+        let now = unix_time();
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| now.saturating_sub(entry.enqueued_at))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Sends the due txs and polls the submitted ones once.
+    pub async fn pump_once(&self, client: &AsyncRpcClient) -> Result<PumpReport, QueueError> {
+        let now = Instant::now();
+        let due: Vec<PendingSubmission> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.is_due(now))
+            .cloned()
+            .collect();
+        let mut report = PumpReport::default();
+        for entry in due {
+            match entry.state {
+                SubmissionState::Queued => self.submit(client, &entry, &mut report).await?,
+                SubmissionState::Submitted => self.confirm(client, &entry, &mut report).await?,
+                _ => {}
+            }
+        }
+        self.update_metrics();
+        Ok(report)
+    }
+
+    async fn submit(
+        &self,
+        client: &AsyncRpcClient,
+        entry: &PendingSubmission,
+        report: &mut PumpReport,
+    ) -> Result<(), QueueError> {
+        let key = &entry.idempotency_key;
+        let tx = match hex::decode(&entry.tx)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| decode_transaction(&bytes).map_err(|e| e.to_string()))
+        {
+            Ok(tx) => tx,
+            Err(error) => {
+                report.rejected += 1;
+                return self.update(key, |entry| {
+                    entry.state = SubmissionState::Rejected;
+                    entry.last_error = Some(error);
+                });
+            }
+        };
+        // written before the commit, a crash from here on leaves the tx to the poll
+        self.update(key, |entry| {
+            entry.state = SubmissionState::Submitting;
+            entry.attempts += 1;
+            entry.submitted_at = Some(unix_time());
+        })?;
+        match client.tx_commit(tx).await {
+            Ok(Ok(_)) => {
+                report.submitted += 1;
+                self.update(key, |entry| {
+                    entry.state = SubmissionState::Submitted;
+                    entry.last_error = None;
+                })
+            }
+            Ok(Err(error)) => {
+                report.rejected += 1;
+                self.update(key, |entry| {
+                    entry.state = SubmissionState::Rejected;
+                    entry.last_error = Some(error);
+                })
+            }
+            // the request did not leave the relayer
+            Err(e) if e.is_connect() => {
+                report.deferred += 1;
+                let backoff = self.retry.backoff(entry.attempts);
+                self.update(key, |entry| {
+                    entry.state = SubmissionState::Queued;
+                    entry.last_error = Some(e.to_string());
+                    entry.not_before = Some(Instant::now() + backoff);
+                })
+            }
+            // the commit may have reached the node, its block decides
+            Err(e) => self.update(key, |entry| {
+                entry.state = SubmissionState::Submitted;
+                entry.last_error = Some(e.to_string());
+            }),
+        }
+    }
+
+    async fn confirm(
+        &self,
+        client: &AsyncRpcClient,
+        entry: &PendingSubmission,
+        report: &mut PumpReport,
+    ) -> Result<(), QueueError> {
+        let key = &entry.idempotency_key;
+        let proof = match client.get_tx_inclusion_proof(entry.txid.clone()).await {
+            Ok(response) => response.proof,
+            // polled again on the next pass
+            Err(_) => return Ok(()),
+        };
+        if proof.map_or(false, |proof| is_included(&entry.txid, &proof)) {
+            report.confirmed += 1;
+            return self.remove(key);
+        }
+        let age = unix_time().saturating_sub(entry.submitted_at.unwrap_or(entry.enqueued_at));
+        if Duration::from_secs(age) >= self.confirm_timeout {
+            self.update(key, |entry| {
+                entry.state = SubmissionState::Queued;
+                entry.last_error = Some("Not in a block within the confirmation timeout".into());
+            })?;
+        }
+        Ok(())
+    }
+
+    // applies `change` to the entry of `key` and writes the queue, the entry is
+    // restored if the queue can not be written
+    fn update(
+        &self,
+        key: &str,
+        change: impl FnOnce(&mut PendingSubmission),
+    ) -> Result<(), QueueError> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries
+            .iter()
+            .position(|entry| entry.idempotency_key == key)
+            .ok_or_else(|| QueueError::UnknownKey(key.to_string()))?;
+        let previous = entries[index].clone();
+        change(&mut entries[index]);
+        if let Err(e) = self.persist(&entries) {
+            entries[index] = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), QueueError> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries
+            .iter()
+            .position(|entry| entry.idempotency_key == key)
+            .ok_or_else(|| QueueError::UnknownKey(key.to_string()))?;
+        let removed = entries.remove(index);
+        if let Err(e) = self.persist(&entries) {
+            entries.insert(index, removed);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // writes the whole queue to a temporary file moved over the queue file, so a
+    // crash leaves either the old or the new queue
+    fn persist(&self, entries: &[PendingSubmission]) -> Result<(), QueueError> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp)?;
+        for entry in entries {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn update_metrics(&self) {
+        OUTBOUND_QUEUE_DEPTH.set(self.depth() as f64);
+        OUTBOUND_QUEUE_OLDEST_AGE.set(self.oldest_age() as f64);
+    }
+}
+
+// whether `proof` proves the txid `txid` under the root it carries
+fn is_included(txid: &str, proof: &TxInclusionProof) -> bool {
+    match hex::decode(txid)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+    {
+        Some(txid) => verify_inclusion(txid, proof, &proof.root),
+        None => false,
+    }
+}
+
+/// Runs the pump of `queue` every `interval` on the current tokio runtime.
+pub fn spawn_pump(
+    queue: Arc<OutboundQueue>,
+    client: AsyncRpcClient,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = queue.pump_once(&client).await {
+                eprintln!("Outbound queue pump failed: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    })
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use transaction::reference_tx::create_dark_reference_transaction;
+    use utxo_in_memory::blockoperations::txroot::{BlockTxRoot, TxIdLeaf};
+
+    // transaction api answering txCommit, and getTxInclusionProof once `included` is set.
+    // While `down` is set the requests are read and the connection is dropped.
+    struct MockApi {
+        url: String,
+        commits: Arc<AtomicUsize>,
+        down: Arc<AtomicBool>,
+        included: Arc<AtomicBool>,
+    }
+
+    fn mock_api(txid: &str) -> MockApi {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api = MockApi {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            commits: Arc::new(AtomicUsize::new(0)),
+            down: Arc::new(AtomicBool::new(false)),
+            included: Arc::new(AtomicBool::new(false)),
+        };
+        let leaf = TxIdLeaf(hex::decode(txid).unwrap().try_into().unwrap());
+        let proof = BlockTxRoot::new(7, vec![TxIdLeaf([1u8; 32]), leaf])
+            .inclusion_proof(1)
+            .unwrap();
+        let proof = serde_json::to_string(&proof).unwrap();
+        let (commits, down, included) =
+            (api.commits.clone(), api.down.clone(), api.included.clone());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let request = read_request(&mut stream);
+                let body = if request.contains("txCommit") {
+                    commits.fetch_add(1, Ordering::SeqCst);
+                    r#"{"jsonrpc":"2.0","result":"{\"txHash\":\"hash\"}","id":1}"#.to_string()
+                } else if included.load(Ordering::SeqCst) {
+                    format!(r#"{{"jsonrpc":"2.0","result":{},"id":1}}"#, proof)
+                } else {
+                    r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"txid not found"},"id":1}"#
+                        .to_string()
+                };
+                if down.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        api
+    }
+
+    fn read_request(stream: &mut TcpStream) -> String {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            data.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&data).to_string();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        if name.eq_ignore_ascii_case("content-length") {
+                            value.trim().parse::<usize>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .unwrap_or(0);
+                if data.len() >= end + 4 + length {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&data).to_string()
+    }
+
+    fn queue_path() -> PathBuf {
+        std::env::temp_dir().join(format!("zkos-outbound-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn retry() -> RetryConfig {
+        RetryConfig {
+            max_retries: 0,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn outbound_queue_downtime_test() {
+        let tx = create_dark_reference_transaction();
+        let path = queue_path();
+        let queue = OutboundQueue::open(&path, retry(), DEFAULT_CONFIRM_TIMEOUT).unwrap();
+        let txid = queue
+            .enqueue(tx.clone(), SubmissionKind::TradeOrder, "order-1")
+            .unwrap();
+        assert_eq!(txid, tx_id(&tx));
+        // enqueued again by a relayer replaying its log
+        assert_eq!(
+            queue
+                .enqueue(tx.clone(), SubmissionKind::TradeOrder, "order-1")
+                .unwrap(),
+            txid
+        );
+        assert_eq!(
+            queue
+                .enqueue(tx.clone(), SubmissionKind::Other, "other")
+                .unwrap(),
+            txid
+        );
+        assert_eq!(queue.depth(), 1);
+
+        // the api is not listening, the commit is deferred
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let offline = AsyncRpcClient::new(format!("http://{}", closed.local_addr().unwrap()));
+        drop(closed);
+        let report = queue.pump_once(&offline).await.unwrap();
+        assert_eq!(report.deferred, 1);
+        let entry = &queue.list_pending_submissions()[0];
+        assert_eq!(entry.state, SubmissionState::Queued);
+        assert_eq!(entry.attempts, 1);
+
+        // the api goes down while the commit is in flight, the tx is not sent again
+        let api = mock_api(&txid);
+        let client = AsyncRpcClient::with_config(
+            api.url.clone(),
+            Duration::from_secs(5),
+            RetryConfig::none(),
+        )
+        .unwrap();
+        api.down.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        queue.pump_once(&client).await.unwrap();
+        assert_eq!(api.commits.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            queue.list_pending_submissions()[0].state,
+            SubmissionState::Submitted
+        );
+
+        api.down.store(false, Ordering::SeqCst);
+        queue.pump_once(&client).await.unwrap();
+        queue.pump_once(&client).await.unwrap();
+        assert_eq!(queue.depth(), 1);
+        api.included.store(true, Ordering::SeqCst);
+        let report = queue.pump_once(&client).await.unwrap();
+        assert_eq!(report.confirmed, 1);
+        assert_eq!(queue.depth(), 0);
+        assert_eq!(api.commits.load(Ordering::SeqCst), 1);
+        assert!(OutboundQueue::open(&path, retry(), DEFAULT_CONFIRM_TIMEOUT)
+            .unwrap()
+            .list_pending_submissions()
+            .is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn outbound_queue_recovery_test() {
+        let tx = create_dark_reference_transaction();
+        let path = queue_path();
+        let queue = OutboundQueue::open(&path, retry(), DEFAULT_CONFIRM_TIMEOUT).unwrap();
+        let txid = queue
+            .enqueue(tx.clone(), SubmissionKind::Settlement, "settle-1")
+            .unwrap();
+        assert!(matches!(
+            queue.enqueue(
+                create_dark_reference_transaction(),
+                SubmissionKind::Settlement,
+                "settle-1"
+            ),
+            Err(QueueError::KeyConflict(_))
+        ));
+        // the relayer crashes once the commit is sent
+        queue
+            .update("settle-1", |entry| {
+                entry.state = SubmissionState::Submitting;
+                entry.attempts += 1;
+                entry.submitted_at = Some(unix_time());
+            })
+            .unwrap();
+        drop(queue);
+
+        let queue = OutboundQueue::open(&path, retry(), DEFAULT_CONFIRM_TIMEOUT).unwrap();
+        let entry = &queue.list_pending_submissions()[0];
+        assert_eq!(entry.state, SubmissionState::Submitted);
+        assert_eq!(entry.kind, SubmissionKind::Settlement);
+        assert_eq!(
+            queue
+                .enqueue(tx, SubmissionKind::Settlement, "settle-1")
+                .unwrap(),
+            txid
+        );
+
+        let api = mock_api(&txid);
+        api.included.store(true, Ordering::SeqCst);
+        let report = queue
+            .pump_once(&AsyncRpcClient::new(api.url.clone()))
+            .await
+            .unwrap();
+        assert_eq!(report.confirmed, 1);
+        assert_eq!(api.commits.load(Ordering::SeqCst), 0);
+        assert_eq!(queue.depth(), 0);
+
+        // a submission not in a block within the timeout is queued again
+        let queue = OutboundQueue::open(&path, retry(), Duration::ZERO).unwrap();
+        let tx = create_dark_reference_transaction();
+        let txid = queue.enqueue(tx, SubmissionKind::Other, "late").unwrap();
+        let api = mock_api(&txid);
+        let client = AsyncRpcClient::new(api.url.clone());
+        queue.pump_once(&client).await.unwrap();
+        queue.pump_once(&client).await.unwrap();
+        let entry = &queue.list_pending_submissions()[0];
+        assert_eq!(entry.state, SubmissionState::Queued);
+        assert!(entry.last_error.is_some());
+        let _ = std::fs::remove_file(&path);
+    }
+}
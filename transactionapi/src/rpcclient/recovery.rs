@@ -0,0 +1,286 @@
+//! Recovery of the utxos of a wallet from its seed.
+//!
+//! The addresses of the wallet are derived with [`derive_batch`] in index order and
+//! queried with `getUtxos`, one JSON-RPC batch per `batch_size` addresses. The scan
+//! goes on until `gap_limit` addresses in a row after the last used one hold no utxo.
+//! A batch failing in transport, or with an error for any of its calls, is sent again
+//! after a backoff; the addresses scanned before it are not queried again.
+//!
+//! `getUtxos` is not paginated, a call returns all the coin utxos of its address.
+
+use super::async_client::{AsyncRpcClient, RetryConfig};
+use super::txrequest::RpcBody;
+use super::typed::GetUtxosRequest;
+use address::{derive_batch, Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::ristretto::RistrettoPublicKey;
+use thiserror::Error;
+use zkvm::zkos_types::Utxo;
+
+/// Default number of addresses queried by a batch.
+pub const DEFAULT_SCAN_BATCH_SIZE: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecoveryConfig {
+    pub network: Network,
+    /// Addresses queried by a batch, at most the batch length allowed by the server
+    pub batch_size: u32,
+    /// Retries of a failed batch
+    pub retry: RetryConfig,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        RecoveryConfig {
+            network: Network::default(),
+            batch_size: DEFAULT_SCAN_BATCH_SIZE,
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+/// A used address of the wallet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveredAddress {
+    pub index: u32,
+    /// Hex of the standard address
+    pub address: String,
+    /// Scalar the base public key is updated with, see [`address::derive_secret_key`]
+    pub scalar: Scalar,
+    pub utxos: Vec<Utxo>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WalletRecovery {
+    /// Addresses holding utxos, in index order
+    pub addresses: Vec<RecoveredAddress>,
+    /// None if no address holds utxos
+    pub highest_used_index: Option<u32>,
+    /// Number of addresses scanned, from index 0
+    pub scanned: u32,
+}
+
+#[derive(Error, Debug)]
+pub enum RecoveryError {
+    #[error("Rpc error {0}")]
+    Rpc(#[from] reqwest::Error),
+
+    #[error("getUtxos failed for the address of index {index}, {message}")]
+    Query { index: u32, message: String },
+}
+
+/// Scans the addresses of the wallet of `seed` and `base_pk` on the node at `rpc_url`,
+/// with the default [`RecoveryConfig`].
+pub async fn recover_wallet(
+    seed: &[u8],
+    base_pk: &RistrettoPublicKey,
+    gap_limit: u32,
+    rpc_url: &str,
+) -> Result<WalletRecovery, RecoveryError> {
+    let client = AsyncRpcClient::new(rpc_url.to_string());
+    let config = RecoveryConfig::default();
+    recover_wallet_with(&client, seed, base_pk, gap_limit, &config).await
+}
+
+/// Scans the addresses of the wallet of `seed` and `base_pk` through `client`.
+/// Fails once a batch has failed more than `config.retry.max_retries` times in a row.
+pub async fn recover_wallet_with(
+    client: &AsyncRpcClient,
+    seed: &[u8],
+    base_pk: &RistrettoPublicKey,
+    gap_limit: u32,
+    config: &RecoveryConfig,
+) -> Result<WalletRecovery, RecoveryError> {
+    let mut recovery = WalletRecovery::default();
+    let batch_size = config.batch_size.max(1);
+    loop {
+        // the scan ends gap_limit addresses after the last used one
+        let end = match recovery.highest_used_index {
+            Some(index) => index.saturating_add(1).saturating_add(gap_limit),
+            None => gap_limit,
+        };
+        let start = recovery.scanned;
+        if start >= end {
+            return Ok(recovery);
+        }
+        let range = start..end.min(start.saturating_add(batch_size));
+        let batch = derive_batch(config.network, base_pk, seed, range);
+        let utxos = query_batch(client, &batch, &config.retry).await?;
+        for ((index, address, scalar), utxos) in batch.into_iter().zip(utxos) {
+            if !utxos.is_empty() {
+                recovery.highest_used_index = Some(index);
+                recovery.addresses.push(RecoveredAddress {
+                    index,
+                    address: address.as_hex(),
+                    scalar,
+                    utxos,
+                });
+            }
+            recovery.scanned = index + 1;
+        }
+    }
+}
+
+// utxos of each address of `batch`, the batch is sent again until it succeeds or the
+// retries run out
+async fn query_batch(
+    client: &AsyncRpcClient,
+    batch: &[(u32, Address, Scalar)],
+    retry: &RetryConfig,
+) -> Result<Vec<Vec<Utxo>>, RecoveryError> {
+    let mut attempt = 0;
+    loop {
+        let bodies: Vec<RpcBody<GetUtxosRequest>> = batch
+            .iter()
+            .map(|(_, address, _)| {
+                RpcBody::new_typed(GetUtxosRequest {
+                    address: address.as_hex(),
+                })
+            })
+            .collect();
+        let result = match client.send_batch(bodies).await {
+            Ok(results) => batch
+                .iter()
+                .zip(results)
+                .map(|((index, _, _), result)| {
+                    result.map_err(|e| RecoveryError::Query {
+                        index: *index,
+                        message: e.message,
+                    })
+                })
+                .collect(),
+            Err(e) => Err(RecoveryError::Rpc(e)),
+        };
+        match result {
+            Ok(utxos) => return Ok(utxos),
+            Err(e) if attempt >= retry.max_retries => return Err(e),
+            Err(_) => {
+                tokio::time::sleep(retry.backoff(attempt)).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use address::keys::derivation_scalar;
+    use quisquislib::keys::{PublicKey, SecretKey};
+    use quisquislib::ristretto::RistrettoSecretKey;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const SEED: &[u8] = b"zkos recovery test seed";
+
+    // answers getUtxos batches from `utxos`, every other request fails with a 503
+    fn flaky_server(utxos: HashMap<String, Utxo>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0u8; length];
+                reader.read_exact(&mut body).unwrap();
+                if counter.fetch_add(1, Ordering::SeqCst) % 2 == 0 {
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    );
+                    continue;
+                }
+                let calls: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+                let outputs: Vec<serde_json::Value> = calls
+                    .iter()
+                    .map(|call| {
+                        let result = match utxos.get(call["params"][0].as_str().unwrap()) {
+                            Some(utxo) => serde_json::json!([utxo]),
+                            None => serde_json::json!("{ Error: Utxo not available }"),
+                        };
+                        serde_json::json!({"jsonrpc": "2.0", "result": result, "id": call["id"]})
+                    })
+                    .collect();
+                let body = serde_json::to_string(&outputs).unwrap();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        (url, hits)
+    }
+
+    #[tokio::test]
+    async fn recover_wallet_retry_test() {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let base_pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let network = Network::default();
+        // index 21 is past the gap limit after index 14
+        let used: HashMap<String, Utxo> = [2u32, 5, 14, 21]
+            .iter()
+            .map(|index| {
+                let (address, _) =
+                    address::derive_standard_address(network, &base_pk, SEED, *index);
+                (address.as_hex(), Utxo::random())
+            })
+            .collect();
+        let (url, hits) = flaky_server(used);
+
+        let config = RecoveryConfig {
+            network,
+            batch_size: 4,
+            retry: RetryConfig {
+                max_retries: 1,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+            },
+        };
+        let client =
+            AsyncRpcClient::with_config(url, Duration::from_secs(5), RetryConfig::none()).unwrap();
+        let recovery = recover_wallet_with(&client, SEED, &base_pk, 5, &config)
+            .await
+            .unwrap();
+        let indices: Vec<u32> = recovery.addresses.iter().map(|a| a.index).collect();
+        assert_eq!(indices, vec![2, 5, 14]);
+        assert_eq!(recovery.highest_used_index, Some(14));
+        assert_eq!(recovery.scanned, 20);
+        for address in recovery.addresses.iter() {
+            assert_eq!(address.scalar, derivation_scalar(SEED, address.index));
+            assert_eq!(address.utxos.len(), 1);
+        }
+        // each of the 5 batches failed once and was sent again
+        assert_eq!(hits.load(Ordering::SeqCst), 10);
+
+        // without retries the first batch fails
+        let config = RecoveryConfig {
+            retry: RetryConfig::none(),
+            ..config
+        };
+        assert!(matches!(
+            recover_wallet_with(&client, SEED, &base_pk, 5, &config).await,
+            Err(RecoveryError::Rpc(_))
+        ));
+    }
+}
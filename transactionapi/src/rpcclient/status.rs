@@ -0,0 +1,164 @@
+//! Verification of the signed status of a node, see `getSignedStatus`.
+//!
+//! A monitor pins the public key of the node and checks every answer against it, an
+//! answer signed by any other key is refused. The signature covers the signing time,
+//! so an answer captured while the node was healthy stays valid, and a proxy could
+//! replay it once the node stalls. [`verify_signed_status`] refuses a status signed
+//! more than `freshness` away from the clock of the monitor, in either direction to
+//! allow for clock skew. The window should be a few times the polling interval, and
+//! shorter than the delay after which a stalled node has to be reported.
+
+use crate::rpcserver::{status_transcript, SignedStatus};
+use curve25519_dalek::ristretto::CompressedRistretto;
+use starsig::{Signature, VerificationKey};
+use std::time::Duration;
+use thiserror::Error;
+use utxo_in_memory::rpcaudit::unix_time;
+
+/// Default age after which a signed status is refused.
+pub const DEFAULT_STATUS_FRESHNESS: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StatusError {
+    #[error("Status signed by the unexpected key {0}")]
+    UnexpectedKey(String),
+
+    #[error("Invalid encoding of the {0}")]
+    Encoding(&'static str),
+
+    #[error("Invalid status signature")]
+    InvalidSignature,
+
+    #[error("Stale status, signed at {timestamp}, checked at {now}")]
+    Stale { timestamp: u64, now: u64 },
+}
+
+/// Checks that `signed` is signed by the key `pubkey_hex` within `freshness` of the
+/// unix time `now`.
+pub fn verify_signed_status_at(
+    signed: &SignedStatus,
+    pubkey_hex: &str,
+    now: u64,
+    freshness: Duration,
+) -> Result<(), StatusError> {
+    if !signed.pubkey_hex.eq_ignore_ascii_case(pubkey_hex) {
+        return Err(StatusError::UnexpectedKey(signed.pubkey_hex.clone()));
+    }
+    let key = hex::decode(pubkey_hex)
+        .ok()
+        .filter(|bytes| bytes.len() == 32)
+        .ok_or(StatusError::Encoding("public key"))?;
+    let key = VerificationKey::from_compressed(CompressedRistretto::from_slice(&key));
+    let mut signature = [0u8; 64];
+    match hex::decode(&signed.signature_hex) {
+        Ok(bytes) if bytes.len() == 64 => signature.copy_from_slice(&bytes),
+        _ => return Err(StatusError::Encoding("signature")),
+    }
+    let signature =
+        Signature::from_bytes(signature).map_err(|_| StatusError::Encoding("signature"))?;
+    signature
+        .verify(
+            &mut status_transcript(&signed.status, signed.timestamp),
+            key,
+        )
+        .map_err(|_| StatusError::InvalidSignature)?;
+    let age = Duration::from_secs(now.abs_diff(signed.timestamp));
+    if age > freshness {
+        return Err(StatusError::Stale {
+            timestamp: signed.timestamp,
+            now,
+        });
+    }
+    Ok(())
+}
+
+/// Checks `signed` against the key `pubkey_hex` and the clock of the host, see the
+/// module docs for the choice of `freshness`.
+pub fn verify_signed_status(
+    signed: &SignedStatus,
+    pubkey_hex: &str,
+    freshness: Duration,
+) -> Result<(), StatusError> {
+    verify_signed_status_at(signed, pubkey_hex, unix_time(), freshness)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpcserver::{NodeKey, NodeStatus};
+
+    fn status() -> NodeStatus {
+        NodeStatus {
+            block_height: 120,
+            utxo_coin_count: 40,
+            utxo_memo_count: 3,
+            utxo_state_count: 1,
+            last_block_height: Some(120),
+            last_block_time: Some(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn verify_signed_status_test() {
+        let key = NodeKey::ephemeral();
+        let pubkey = key.pubkey_hex();
+        let now = 1_700_000_030;
+        let signed = key.sign(status(), now - 10);
+        assert_eq!(
+            verify_signed_status_at(&signed, &pubkey, now, DEFAULT_STATUS_FRESHNESS),
+            Ok(())
+        );
+
+        // a tampered status or time breaks the signature
+        let mut tampered = signed.clone();
+        tampered.status.block_height += 1;
+        assert_eq!(
+            verify_signed_status_at(&tampered, &pubkey, now, DEFAULT_STATUS_FRESHNESS),
+            Err(StatusError::InvalidSignature)
+        );
+        let mut tampered = signed.clone();
+        tampered.timestamp = now;
+        assert_eq!(
+            verify_signed_status_at(&tampered, &pubkey, now, DEFAULT_STATUS_FRESHNESS),
+            Err(StatusError::InvalidSignature)
+        );
+
+        // a status signed by another key, even validly, is refused
+        let other = NodeKey::ephemeral().sign(status(), now);
+        assert!(matches!(
+            verify_signed_status_at(&other, &pubkey, now, DEFAULT_STATUS_FRESHNESS),
+            Err(StatusError::UnexpectedKey(_))
+        ));
+        let mut resigned = other.clone();
+        resigned.pubkey_hex = pubkey.clone();
+        assert_eq!(
+            verify_signed_status_at(&resigned, &pubkey, now, DEFAULT_STATUS_FRESHNESS),
+            Err(StatusError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn stale_signed_status_test() {
+        let key = NodeKey::ephemeral();
+        let pubkey = key.pubkey_hex();
+        let now = 1_700_000_000;
+        let freshness = Duration::from_secs(60);
+        let stale = key.sign(status(), now - 61);
+        assert_eq!(
+            verify_signed_status_at(&stale, &pubkey, now, freshness),
+            Err(StatusError::Stale {
+                timestamp: now - 61,
+                now
+            })
+        );
+        // a status from the future is refused past the same window
+        let ahead = key.sign(status(), now + 61);
+        assert!(verify_signed_status_at(&ahead, &pubkey, now, freshness).is_err());
+        let skewed = key.sign(status(), now + 30);
+        assert!(verify_signed_status_at(&skewed, &pubkey, now, freshness).is_ok());
+        assert!(verify_signed_status(&key.sign(status(), unix_time()), &pubkey, freshness).is_ok());
+    }
+}
@@ -0,0 +1,90 @@
+//! TLS of the rpc clients.
+//!
+//! A node served over TLS with a certificate of a private CA is reached with the
+//! root certificate of that CA, and a node with mutual TLS with a client identity,
+//! needed for txCommit and the admin methods. `RpcRequest::send` reads them from
+//! `RPC_CLIENT_CA_PATH`, `RPC_CLIENT_CERT_PATH` and `RPC_CLIENT_KEY_PATH`, see
+//! [`ClientTlsConfig::from_env`], an `AsyncRpcClient` takes them from
+//! [`super::async_client::AsyncRpcClient::with_tls`].
+
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use utxo_in_memory::config::config_value;
+
+#[derive(Error, Debug)]
+pub enum ClientTlsError {
+    #[error("Io error reading {path}, {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("A client identity needs both RPC_CLIENT_CERT_PATH and RPC_CLIENT_KEY_PATH")]
+    IncompleteIdentity,
+
+    #[error("Invalid TLS client config, {0}")]
+    Reqwest(#[from] reqwest::Error),
+}
+
+/// Root CA and client identity of the connections to the node, the system roots
+/// and no identity if unset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientTlsConfig {
+    /// PEM certificate of a root CA trusted besides the system roots
+    pub root_ca_path: Option<PathBuf>,
+    /// PEM certificate chain of the client identity
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key of the client identity
+    pub key_path: Option<PathBuf>,
+}
+
+impl ClientTlsConfig {
+    /// Reads the paths from `RPC_CLIENT_CA_PATH`, `RPC_CLIENT_CERT_PATH` and
+    /// `RPC_CLIENT_KEY_PATH`.
+    pub fn from_env() -> Self {
+        ClientTlsConfig {
+            root_ca_path: config_value("RPC_CLIENT_CA_PATH").map(PathBuf::from),
+            cert_path: config_value("RPC_CLIENT_CERT_PATH").map(PathBuf::from),
+            key_path: config_value("RPC_CLIENT_KEY_PATH").map(PathBuf::from),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root_ca_path.is_none() && self.cert_path.is_none() && self.key_path.is_none()
+    }
+
+    /// `builder` trusting the root CA and presenting the identity of the config.
+    /// The builder is returned as it is if the config is empty.
+    pub fn apply(
+        &self,
+        builder: reqwest::ClientBuilder,
+    ) -> Result<reqwest::ClientBuilder, ClientTlsError> {
+        if self.is_empty() {
+            return Ok(builder);
+        }
+        let mut builder = builder.use_rustls_tls();
+        if let Some(path) = &self.root_ca_path {
+            let root = reqwest::Certificate::from_pem(&read_pem(path)?)?;
+            builder = builder.add_root_certificate(root);
+        }
+        match (&self.cert_path, &self.key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                // rustls reads the key and the chain from a single PEM
+                let mut pem = read_pem(key_path)?;
+                pem.push(b'\n');
+                pem.extend(read_pem(cert_path)?);
+                builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+            }
+            (None, None) => {}
+            _ => return Err(ClientTlsError::IncompleteIdentity),
+        }
+        Ok(builder)
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, ClientTlsError> {
+    std::fs::read(path).map_err(|source| ClientTlsError::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
@@ -1,3 +1,4 @@
+use super::async_client::send_json_blocking;
 use super::id::Id;
 use super::method::Method;
 // use curve25519_dalek::digest::Output;
@@ -5,6 +6,7 @@ use jsonrpc_core::response::{Failure, Output, Success};
 use jsonrpc_core::Response as JsonRPCResponse;
 use jsonrpc_core::Version;
 use serde_derive::{Deserialize, Serialize};
+use utxo_in_memory::blockoperations::blockprocessing::AllUtxosQuery;
 use utxo_in_memory::pgsql::QueryUtxoFromDB;
 // use super::method::Method;
 use reqwest::blocking::Response;
@@ -13,13 +15,11 @@ use serde_json::Error;
 use transaction::Transaction;
 // pub type TransactionStatusId = String;
 use crate::TransactionStatusId;
-fn construct_headers() -> HeaderMap {
+pub(crate) fn construct_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(USER_AGENT, HeaderValue::from_static("reqwest"));
-    headers.insert(
-        ACCEPT_ENCODING,
-        HeaderValue::from_static("gzip, deflate, br"),
-    );
+    // gzip responses are decompressed by reqwest
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
     headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
     headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
     headers
@@ -97,153 +97,8 @@ impl RpcRequest<Transaction> for RpcBody<Transaction> {
         self,
         url: std::string::String,
     ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
-        match self.method {
-            Method::txCommit => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::TxQueue => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::TxStatus => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-
-            // Method::allOutputs => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::allUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::allMemoUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::allSateUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-
-            // Method::getOutput => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getMemoOutput => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getStateOutput => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getUtxosFromDB => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            _ => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-        }
+        let method = self.method;
+        send_json_blocking(url, method, self.into_json())
     }
 }
 
@@ -358,165 +213,8 @@ impl RpcRequest<TransactionStatusId> for RpcBody<TransactionStatusId> {
         self,
         url: std::string::String,
     ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
-        // let res: Result<reqwest::blocking::Response, reqwest::Error>;
-        match self.method {
-            Method::txCommit => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::TxQueue => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::TxStatus => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            } // _ => {}
-            // Method::allOutputs => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::allUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::allMemoUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::allSateUtxos => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getOutput => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getMemoOutput => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getStateOutput => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            // Method::getUtxosFromDB => {
-            //     let client = reqwest::blocking::Client::new();
-            //     let clint_clone = client.clone();
-            //     let res = clint_clone
-            //         .post(url)
-            //         .headers(construct_headers())
-            //         .body(self.into_json())
-            //         .send();
-
-            //     return rpc_response(res);
-            // }
-            _ => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-        }
-
-        // let client = reqwest::blocking::Client::new();
-        // let clint_clone = client.clone();
-        // let res = clint_clone
-        //     .post(url)
-        //     .headers(construct_headers())
-        //     .body(self.into_json())
-        //     // .body(r#"{"jsonrpc":"2.0","method":"TxQueue","params":1,"id":1}"#)
-        //     // .body(r#"{"jsonrpc":"2.0","method":"TxQueue","params":[],"id":1}"#)
-        //     // .body(r#"{"jsonrpc":"2.0","method":"TxQueue","params":[1],"id":1}"#)
-        //     .send();
-
-        // return rpc_response(res);
+        let method = self.method;
+        send_json_blocking(url, method, self.into_json())
     }
 }
 
@@ -557,162 +255,8 @@ impl RpcRequest<Vec<String>> for RpcBody<Vec<String>> {
         self,
         url: std::string::String,
     ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
-        match self.method {
-            Method::txCommit => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::TxQueue => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::TxStatus => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::allOutputs => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getUtxos => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getStateUtxos => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getMemoUtxos => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::allUtxos => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::allMemoUtxos => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::allSateUtxos => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getOutput => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getMemoOutput => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getStateOutput => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            Method::getUtxosFromDB => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-        }
+        let method = self.method;
+        send_json_blocking(url, method, self.into_json())
     }
 }
 
@@ -753,31 +297,47 @@ impl RpcRequest<QueryUtxoFromDB> for RpcBody<QueryUtxoFromDB> {
         self,
         url: std::string::String,
     ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
-        match self.method {
-            Method::getUtxosFromDB => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
-            _ => {
-                let client = reqwest::blocking::Client::new();
-                let clint_clone = client.clone();
-                let res = clint_clone
-                    .post(url)
-                    .headers(construct_headers())
-                    .body(self.into_json())
-                    .send();
-
-                return rpc_response(res);
-            }
+        let method = self.method;
+        send_json_blocking(url, method, self.into_json())
+    }
+}
+
+impl RpcRequest<AllUtxosQuery> for RpcBody<AllUtxosQuery> {
+    fn new(request: AllUtxosQuery, method: Method) -> Self {
+        Self::new_with_id(Id::uuid_v4(), request, method)
+    }
+
+    fn new_with_id(id: Id, request: AllUtxosQuery, method: Method) -> Self {
+        Self {
+            jsonrpc: Version::V2,
+            id,
+            method: method,
+            params: request,
         }
     }
+
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn params(&self) -> &AllUtxosQuery {
+        &self.params
+    }
+    fn into_json(self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+
+    fn get_method(&self) -> &Method {
+        &self.method
+    }
+
+    fn send(
+        self,
+        url: std::string::String,
+    ) -> Result<RpcResponse<serde_json::Value>, reqwest::Error> {
+        let method = self.method;
+        send_json_blocking(url, method, self.into_json())
+    }
 }
 
 #[cfg(test)]
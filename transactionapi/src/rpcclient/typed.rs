@@ -0,0 +1,649 @@
+//! Typed requests of the rpc methods.
+//!
+//! Every method served by the rpc server has a request type implementing
+//! [`RpcMethod`], which ties it to the method name and to the type of its
+//! result, so a request can only be sent to its own method:
+//!
+//! ```ignore
+//! let body = RpcBody::new_typed(GetUtxosRequest { address });
+//! let utxos: Vec<Utxo> = client.request(GetUtxosRequest { address }).await??;
+//! ```
+//!
+//! Requests of the methods taking positional parameters serialize to the string
+//! array the server parses, the others are the query structs of the server.
+
+use super::async_client::send_json_blocking;
+use super::id::Id;
+use super::method::Method;
+use super::txrequest::{RpcBody, RpcResponse};
+use crate::rpcserver::{
+    ChainInfo, KeyRotation, PeerStats, SignedStatus, TlsReload, TxStatusRecord, TxStatusView,
+    TxValidationReport,
+};
+use jsonrpc_core::{ErrorCode, Version};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use transaction::decode::TransactionView;
+use transaction::vm_run::EvalResult;
+use utxo_in_memory::audit::AuditReport;
+use utxo_in_memory::blockoperations::addresstx::AddressTx;
+use utxo_in_memory::blockoperations::balanceindexer::IndexedBalance;
+use utxo_in_memory::blockoperations::blockprocessing::{AllUtxosQuery, RawUtxoOutput, UtxoChunk};
+use utxo_in_memory::blockoperations::blockresult::BlockApplyResult;
+use utxo_in_memory::blockoperations::blockstats::BlockProcessingStats;
+use utxo_in_memory::blockoperations::blockweight::WeightResume;
+use utxo_in_memory::blockoperations::failedtx::FailedTx;
+use utxo_in_memory::blockoperations::fees::{BlockFees, FeePoolStats};
+use utxo_in_memory::blockoperations::mempool::{
+    MempoolView, SimulateTransferQuery, SimulationReport,
+};
+use utxo_in_memory::blockoperations::messages::BurnRecord;
+use utxo_in_memory::blockoperations::pruning::PruneLog;
+use utxo_in_memory::blockoperations::scripteval::EvalScriptQuery;
+use utxo_in_memory::blockoperations::subscriptions::UtxoEvent;
+use utxo_in_memory::blockoperations::txroot::{BlockTxRoot, TxInclusionProof};
+use utxo_in_memory::db::compaction::CompactionReport;
+use utxo_in_memory::db::StorageStats;
+use utxo_in_memory::pgsql::{
+    QueryUtxoFromDB, TestCommand, UtxoDetailedQuery, UtxoDetailedResult, UtxoHexEncodedResult,
+};
+use utxo_in_memory::rpcaudit::{AuditLogQuery, RpcAuditRecord};
+use utxo_in_memory::scriptregistry::{RegisterScriptContractQuery, ScriptContract};
+use zkvm::zkos_types::{Output, Utxo};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A request of the rpc method `NAME`, answered with a `Response`.
+pub trait RpcMethod: sealed::Sealed + Serialize {
+    const NAME: &'static str;
+    const METHOD: Method;
+    type Response: DeserializeOwned;
+
+    /// Decodes the result of a successful call.
+    fn decode(result: serde_json::Value) -> Result<Self::Response, serde_json::Error> {
+        serde_json::from_value(result)
+    }
+}
+
+impl<M: RpcMethod> RpcBody<M> {
+    /// Body of a request, the method is the one of the request type.
+    pub fn new_typed(params: M) -> Self {
+        RpcBody {
+            jsonrpc: Version::V2,
+            id: Id::uuid_v4(),
+            method: M::METHOD,
+            params,
+        }
+    }
+
+    /// Sends the request, blocking, and decodes the result.
+    /// See [`AsyncRpcClient::request`] for the errors.
+    ///
+    /// [`AsyncRpcClient::request`]: super::async_client::AsyncRpcClient::request
+    pub fn send_typed(
+        self,
+        url: String,
+    ) -> Result<Result<M::Response, jsonrpc_core::Error>, reqwest::Error> {
+        let body = serde_json::to_string(&self).unwrap();
+        let resp = send_json_blocking(url, M::METHOD, body)?;
+        Ok(decode_response::<M>(resp))
+    }
+}
+
+/// Result of a response to a request of `M`, a result that does not decode is a parse error.
+pub fn decode_response<M: RpcMethod>(
+    resp: RpcResponse<serde_json::Value>,
+) -> Result<M::Response, jsonrpc_core::Error> {
+    resp.result.and_then(|result| {
+        M::decode(result).map_err(|e| jsonrpc_core::Error {
+            code: ErrorCode::ParseError,
+            message: format!("Invalid result, {}", e),
+            data: None,
+        })
+    })
+}
+
+macro_rules! rpc_method {
+    ($request:ty, $method:ident, $response:ty) => {
+        impl sealed::Sealed for $request {}
+        impl RpcMethod for $request {
+            const NAME: &'static str = stringify!($method);
+            const METHOD: Method = Method::$method;
+            type Response = $response;
+        }
+    };
+    ($request:ty, $method:ident, $response:ty, $decode:expr) => {
+        impl sealed::Sealed for $request {}
+        impl RpcMethod for $request {
+            const NAME: &'static str = stringify!($method);
+            const METHOD: Method = Method::$method;
+            type Response = $response;
+
+            fn decode(result: serde_json::Value) -> Result<Self::Response, serde_json::Error> {
+                $decode(result)
+            }
+        }
+    };
+}
+
+// request sent as the positional string parameters of the server
+macro_rules! positional_request {
+    ($(#[$doc:meta])* $name:ident { $($field:ident: $ty:ty),* }) => {
+        $(#[$doc])*
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        #[serde(into = "Vec<String>", try_from = "Vec<String>")]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl From<$name> for Vec<String> {
+            #[allow(unused_variables)]
+            fn from(request: $name) -> Self {
+                vec![$(request.$field.to_string()),*]
+            }
+        }
+
+        impl TryFrom<Vec<String>> for $name {
+            type Error = &'static str;
+
+            #[allow(unused_mut, unused_variables)]
+            fn try_from(params: Vec<String>) -> Result<Self, Self::Error> {
+                let mut params = params.into_iter();
+                Ok($name {
+                    $($field: params
+                        .next()
+                        .ok_or(concat!("Error::Missing ", stringify!($field)))?
+                        .trim()
+                        .parse()
+                        .map_err(|_| concat!("Error::Invalid ", stringify!($field)))?,)*
+                })
+            }
+        }
+    };
+}
+
+// the utxo queries answer with a message instead of an empty list
+fn list_or_empty<T: DeserializeOwned>(
+    result: serde_json::Value,
+) -> Result<Vec<T>, serde_json::Error> {
+    match result {
+        serde_json::Value::String(_) => Ok(Vec::new()),
+        result => serde_json::from_value(result),
+    }
+}
+
+// allOutputs answers with the hex encoded bincode of the outputs
+fn hex_outputs(result: serde_json::Value) -> Result<Vec<Output>, serde_json::Error> {
+    let hex_outputs: String = serde_json::from_value(result)?;
+    let bytes = hex::decode(hex_outputs).map_err(serde::de::Error::custom)?;
+    bincode::deserialize(&bytes).map_err(serde::de::Error::custom)
+}
+
+positional_request!(
+    /// Hex encoded bincode of the transaction.
+    TxCommitRequest { tx: String }
+);
+positional_request!(
+    /// Hex encoded bincode of the transaction.
+    TxValidateRequest { tx: String }
+);
+positional_request!(GetUtxosRequest { address: String });
+positional_request!(GetMemoUtxosRequest { address: String });
+positional_request!(GetStateUtxosRequest { address: String });
+positional_request!(AllMemoUtxosRequest {});
+positional_request!(AllStateUtxosRequest {});
+positional_request!(AllOutputsRequest {});
+positional_request!(GetOutputRequest { utxo: Utxo });
+positional_request!(GetMemoOutputRequest { utxo: Utxo });
+positional_request!(GetStateOutputRequest { utxo: Utxo });
+positional_request!(AuditUtxoStoreRequest {});
+positional_request!(GetStorageStatsRequest {});
+positional_request!(CompactStorageRequest {});
+positional_request!(GetSignedStatusRequest {});
+positional_request!(GetChainInfoRequest {});
+positional_request!(RotateNodeKeyRequest {});
+positional_request!(ReloadTlsRequest {});
+positional_request!(ResumeBlockProcessingRequest {
+    max_block_weight: u64
+});
+positional_request!(GetMempoolRequest {});
+positional_request!(GetPeersRequest {});
+positional_request!(GetBlockTxRootRequest { height: u64 });
+positional_request!(GetTxInclusionProofRequest { txid: String });
+positional_request!(GetBlockFeesRequest { height: u64 });
+positional_request!(GetBlockApplyResultRequest { height: u64 });
+positional_request!(GetFeePoolStatsRequest {
+    from_height: u64,
+    to_height: u64
+});
+positional_request!(GetBurnHistoryRequest { address: String });
+positional_request!(GetIndexedBalanceRequest { address: String });
+positional_request!(GetFailedTxsRequest {
+    from_height: u64,
+    to_height: u64
+});
+positional_request!(ReprocessFailedTxRequest { txid: String });
+positional_request!(TxStatusRequest { txid: String });
+positional_request!(GetTxStatusHistoryRequest { txid: String });
+positional_request!(
+    /// Newest txs first, pages start at 0.
+    GetTransactionsByAddressRequest { address: String, page: usize }
+);
+positional_request!(
+    /// Retained utxo events of the txs of an address, heights inclusive.
+    GetUtxoHistoryRequest {
+        address: String,
+        from_height: u64,
+        to_height: u64
+    }
+);
+positional_request!(GetBlockProcessingStatsRequest { count: usize });
+positional_request!(
+    /// `encoding` is "hex" or "base64".
+    DecodeTxRequest { data: String, encoding: String }
+);
+positional_request!(GetScriptContractRequest { address: String });
+positional_request!(ListScriptContractsRequest {});
+positional_request!(RestorePrunedOutputRequest { utxo: Utxo });
+
+/// Utxos of an address with their outputs, in the cached encoding.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(into = "Vec<String>", try_from = "Vec<String>")]
+pub struct GetRawUtxosRequest {
+    pub address: String,
+}
+
+impl From<GetRawUtxosRequest> for Vec<String> {
+    fn from(request: GetRawUtxosRequest) -> Self {
+        vec![request.address, "raw".to_string()]
+    }
+}
+
+impl TryFrom<Vec<String>> for GetRawUtxosRequest {
+    type Error = &'static str;
+
+    fn try_from(params: Vec<String>) -> Result<Self, Self::Error> {
+        match params.as_slice() {
+            [address, format] if format == "raw" => Ok(GetRawUtxosRequest {
+                address: address.clone(),
+            }),
+            _ => Err("Error::Expected an address and the raw format"),
+        }
+    }
+}
+
+/// A chunk of utxos, or all of them for an unbounded query.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AllUtxosResult {
+    Chunk(UtxoChunk),
+    All(Vec<String>),
+}
+
+pub type AllUtxosRequest = AllUtxosQuery;
+pub type GetUtxosFromDBRequest = QueryUtxoFromDB;
+pub type GetUtxosDetailedRequest = UtxoDetailedQuery;
+pub type SimulateTransferRequest = SimulateTransferQuery;
+pub type EvalScriptRequest = EvalScriptQuery;
+pub type RegisterScriptContractRequest = RegisterScriptContractQuery;
+pub type GetAuditLogRequest = AuditLogQuery;
+pub type TestCommandRequest = TestCommand;
+
+rpc_method!(TxCommitRequest, txCommit, String);
+rpc_method!(TxValidateRequest, txValidate, TxValidationReport);
+rpc_method!(GetUtxosRequest, getUtxos, Vec<Utxo>, list_or_empty);
+rpc_method!(
+    GetRawUtxosRequest,
+    getUtxos,
+    Vec<RawUtxoOutput>,
+    list_or_empty
+);
+rpc_method!(GetMemoUtxosRequest, getMemoUtxos, Vec<Utxo>, list_or_empty);
+rpc_method!(
+    GetStateUtxosRequest,
+    getStateUtxos,
+    Vec<Utxo>,
+    list_or_empty
+);
+rpc_method!(AllUtxosRequest, allUtxos, AllUtxosResult);
+rpc_method!(
+    AllMemoUtxosRequest,
+    allMemoUtxos,
+    Vec<String>,
+    list_or_empty
+);
+rpc_method!(
+    AllStateUtxosRequest,
+    allSateUtxos,
+    Vec<String>,
+    list_or_empty
+);
+rpc_method!(AllOutputsRequest, allOutputs, Vec<Output>, hex_outputs);
+rpc_method!(GetOutputRequest, getOutput, Output);
+rpc_method!(GetMemoOutputRequest, getMemoOutput, Output);
+rpc_method!(GetStateOutputRequest, getStateOutput, Output);
+rpc_method!(GetUtxosFromDBRequest, getUtxosFromDB, UtxoHexEncodedResult);
+rpc_method!(
+    GetUtxosDetailedRequest,
+    getUtxosDetailed,
+    UtxoDetailedResult
+);
+rpc_method!(AuditUtxoStoreRequest, auditUtxoStore, AuditReport);
+rpc_method!(GetStorageStatsRequest, getStorageStats, StorageStats);
+rpc_method!(CompactStorageRequest, compactStorage, CompactionReport);
+rpc_method!(GetSignedStatusRequest, getSignedStatus, SignedStatus);
+rpc_method!(GetChainInfoRequest, getChainInfo, ChainInfo);
+rpc_method!(RotateNodeKeyRequest, rotateNodeKey, KeyRotation);
+rpc_method!(ReloadTlsRequest, reloadTls, TlsReload);
+rpc_method!(
+    ResumeBlockProcessingRequest,
+    resumeBlockProcessing,
+    WeightResume
+);
+rpc_method!(SimulateTransferRequest, simulateTransfer, SimulationReport);
+rpc_method!(GetMempoolRequest, getMempool, MempoolView);
+rpc_method!(GetPeersRequest, getPeers, Vec<PeerStats>);
+rpc_method!(EvalScriptRequest, evalScript, EvalResult);
+rpc_method!(GetBlockTxRootRequest, getBlockTxRoot, BlockTxRoot);
+rpc_method!(
+    GetTxInclusionProofRequest,
+    getTxInclusionProof,
+    TxInclusionProof
+);
+rpc_method!(GetBlockFeesRequest, getBlockFees, BlockFees);
+rpc_method!(
+    GetBlockApplyResultRequest,
+    getBlockApplyResult,
+    BlockApplyResult
+);
+rpc_method!(GetFeePoolStatsRequest, getFeePoolStats, FeePoolStats);
+rpc_method!(GetBurnHistoryRequest, getBurnHistory, Vec<BurnRecord>);
+rpc_method!(GetIndexedBalanceRequest, getIndexedBalance, IndexedBalance);
+rpc_method!(GetFailedTxsRequest, getFailedTxs, Vec<FailedTx>);
+rpc_method!(ReprocessFailedTxRequest, reprocessFailedTx, FailedTx);
+rpc_method!(TxStatusRequest, txStatus, TxStatusView);
+rpc_method!(
+    GetTxStatusHistoryRequest,
+    getTxStatusHistory,
+    Vec<TxStatusRecord>
+);
+rpc_method!(
+    GetTransactionsByAddressRequest,
+    getTransactionsByAddress,
+    Vec<AddressTx>
+);
+rpc_method!(GetUtxoHistoryRequest, getUtxoHistory, Vec<UtxoEvent>);
+rpc_method!(
+    GetBlockProcessingStatsRequest,
+    getBlockProcessingStats,
+    Vec<BlockProcessingStats>
+);
+rpc_method!(DecodeTxRequest, decodeTx, TransactionView);
+rpc_method!(
+    RegisterScriptContractRequest,
+    registerScriptContract,
+    ScriptContract
+);
+rpc_method!(GetScriptContractRequest, getScriptContract, ScriptContract);
+rpc_method!(
+    ListScriptContractsRequest,
+    listScriptContracts,
+    Vec<ScriptContract>
+);
+rpc_method!(RestorePrunedOutputRequest, restorePrunedOutput, PruneLog);
+rpc_method!(GetAuditLogRequest, getAuditLog, Vec<RpcAuditRecord>);
+rpc_method!(TestCommandRequest, TestCommand, String);
+
+/// Names of the methods with a typed request, see `rpcserver` for the served ones.
+pub const TYPED_METHODS: &[&str] = &[
+    TxCommitRequest::NAME,
+    TxValidateRequest::NAME,
+    GetUtxosRequest::NAME,
+    GetMemoUtxosRequest::NAME,
+    GetStateUtxosRequest::NAME,
+    AllUtxosRequest::NAME,
+    AllMemoUtxosRequest::NAME,
+    AllStateUtxosRequest::NAME,
+    AllOutputsRequest::NAME,
+    GetOutputRequest::NAME,
+    GetMemoOutputRequest::NAME,
+    GetStateOutputRequest::NAME,
+    GetUtxosFromDBRequest::NAME,
+    GetUtxosDetailedRequest::NAME,
+    AuditUtxoStoreRequest::NAME,
+    GetStorageStatsRequest::NAME,
+    CompactStorageRequest::NAME,
+    GetSignedStatusRequest::NAME,
+    GetChainInfoRequest::NAME,
+    RotateNodeKeyRequest::NAME,
+    ReloadTlsRequest::NAME,
+    ResumeBlockProcessingRequest::NAME,
+    SimulateTransferRequest::NAME,
+    GetMempoolRequest::NAME,
+    GetPeersRequest::NAME,
+    EvalScriptRequest::NAME,
+    GetBlockTxRootRequest::NAME,
+    GetTxInclusionProofRequest::NAME,
+    GetBlockFeesRequest::NAME,
+    GetBlockApplyResultRequest::NAME,
+    GetFeePoolStatsRequest::NAME,
+    GetBurnHistoryRequest::NAME,
+    GetIndexedBalanceRequest::NAME,
+    GetFailedTxsRequest::NAME,
+    ReprocessFailedTxRequest::NAME,
+    TxStatusRequest::NAME,
+    GetTxStatusHistoryRequest::NAME,
+    GetTransactionsByAddressRequest::NAME,
+    GetUtxoHistoryRequest::NAME,
+    GetBlockProcessingStatsRequest::NAME,
+    DecodeTxRequest::NAME,
+    RegisterScriptContractRequest::NAME,
+    GetScriptContractRequest::NAME,
+    ListScriptContractsRequest::NAME,
+    RestorePrunedOutputRequest::NAME,
+    GetAuditLogRequest::NAME,
+    TestCommandRequest::NAME,
+];
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utxo_in_memory::pgsql::TestCommandString;
+    use zkvm::zkos_types::IOType;
+
+    // the body of a request decodes back to the same request, for its own method
+    fn round_trip<M: RpcMethod + DeserializeOwned>(request: M) -> serde_json::Value {
+        let body = serde_json::to_value(RpcBody::new_typed(request)).unwrap();
+        assert_eq!(body["method"], M::NAME);
+        assert_eq!(
+            serde_json::from_value::<Method>(body["method"].clone()).unwrap(),
+            M::METHOD
+        );
+        let decoded: M = serde_json::from_value(body["params"].clone()).unwrap();
+        assert_eq!(serde_json::to_value(decoded).unwrap(), body["params"]);
+        body["params"].clone()
+    }
+
+    #[test]
+    fn positional_round_trip_test() {
+        let address = "0c0a2b".to_string();
+        let utxo = Utxo::random();
+        let strings = |params: &[&str]| serde_json::json!(params);
+
+        assert_eq!(
+            round_trip(TxCommitRequest { tx: "00ff".into() }),
+            strings(&["00ff"])
+        );
+        assert_eq!(
+            round_trip(TxValidateRequest { tx: "00ff".into() }),
+            strings(&["00ff"])
+        );
+        assert_eq!(
+            round_trip(GetUtxosRequest {
+                address: address.clone()
+            }),
+            strings(&["0c0a2b"])
+        );
+        assert_eq!(
+            round_trip(GetRawUtxosRequest {
+                address: address.clone()
+            }),
+            strings(&["0c0a2b", "raw"])
+        );
+        round_trip(GetMemoUtxosRequest {
+            address: address.clone(),
+        });
+        round_trip(GetStateUtxosRequest {
+            address: address.clone(),
+        });
+        assert_eq!(round_trip(AllMemoUtxosRequest {}), strings(&[]));
+        round_trip(AllStateUtxosRequest {});
+        round_trip(AllOutputsRequest {});
+        assert_eq!(
+            round_trip(GetOutputRequest { utxo }),
+            strings(&[utxo.to_string().as_str()])
+        );
+        round_trip(GetMemoOutputRequest { utxo });
+        round_trip(GetStateOutputRequest { utxo });
+        round_trip(AuditUtxoStoreRequest {});
+        round_trip(GetStorageStatsRequest {});
+        assert_eq!(
+            round_trip(GetBlockTxRootRequest { height: 42 }),
+            strings(&["42"])
+        );
+        round_trip(GetTxInclusionProofRequest { txid: "ab".into() });
+        round_trip(GetBlockFeesRequest { height: 7 });
+        round_trip(GetBlockApplyResultRequest { height: 7 });
+        assert_eq!(
+            round_trip(GetFeePoolStatsRequest {
+                from_height: 1,
+                to_height: 9
+            }),
+            strings(&["1", "9"])
+        );
+        round_trip(GetBurnHistoryRequest {
+            address: address.clone(),
+        });
+        round_trip(GetIndexedBalanceRequest {
+            address: address.clone(),
+        });
+        round_trip(GetFailedTxsRequest {
+            from_height: 3,
+            to_height: 4,
+        });
+        round_trip(ReprocessFailedTxRequest { txid: "cd".into() });
+        round_trip(TxStatusRequest { txid: "cd".into() });
+        round_trip(GetTxStatusHistoryRequest { txid: "cd".into() });
+        assert_eq!(
+            round_trip(GetUtxoHistoryRequest {
+                address: address.clone(),
+                from_height: 5,
+                to_height: 9,
+            }),
+            strings(&["0c0a2b", "5", "9"])
+        );
+        assert_eq!(
+            round_trip(GetTransactionsByAddressRequest { address, page: 2 }),
+            strings(&["0c0a2b", "2"])
+        );
+        round_trip(GetBlockProcessingStatsRequest { count: 10 });
+        round_trip(DecodeTxRequest {
+            data: "00".into(),
+            encoding: "hex".into(),
+        });
+        round_trip(GetScriptContractRequest {
+            address: "0c0a2b".into(),
+        });
+        round_trip(ListScriptContractsRequest {});
+        assert_eq!(
+            round_trip(RestorePrunedOutputRequest { utxo }),
+            strings(&[&utxo.to_string()])
+        );
+    }
+
+    #[test]
+    fn query_round_trip_test() {
+        let params = round_trip(AllUtxosRequest {
+            io_type: IOType::Memo,
+            offset_key: None,
+            limit: 10,
+            unbounded: false,
+        });
+        assert_eq!(params["limit"], 10);
+        round_trip(GetUtxosFromDBRequest {
+            start_block: 0,
+            end_block: 100,
+            limit: 5,
+            pagination: 0,
+            io_type: IOType::Coin,
+        });
+        round_trip(GetUtxosDetailedRequest::default());
+        round_trip(SimulateTransferRequest::default());
+        round_trip(GetMempoolRequest {});
+        round_trip(GetPeersRequest {});
+        round_trip(CompactStorageRequest {});
+        round_trip(GetSignedStatusRequest {});
+        round_trip(GetChainInfoRequest {});
+        round_trip(RotateNodeKeyRequest {});
+        round_trip(ReloadTlsRequest {});
+        round_trip(ResumeBlockProcessingRequest {
+            max_block_weight: 60_000_000,
+        });
+        round_trip(RegisterScriptContractRequest {
+            name: "relayer v1".to_string(),
+            programs: vec!["00".to_string()],
+            metadata: serde_json::Value::Null,
+        });
+        let params = round_trip(EvalScriptRequest {
+            program_index: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(params["program_index"], 0);
+        let params = round_trip(GetAuditLogRequest {
+            page: 1,
+            ..Default::default()
+        });
+        assert_eq!(params["page"], 1);
+        round_trip(TestCommandRequest {
+            test_command: TestCommandString::UtxoCoinDbLength,
+        });
+    }
+
+    #[test]
+    fn invalid_params_test() {
+        let params = |params: &[&str]| serde_json::json!(params);
+        assert!(serde_json::from_value::<GetBlockFeesRequest>(params(&["x"])).is_err());
+        assert!(serde_json::from_value::<GetFeePoolStatsRequest>(params(&["1"])).is_err());
+        assert!(serde_json::from_value::<GetOutputRequest>(params(&["00"])).is_err());
+        assert!(serde_json::from_value::<GetRawUtxosRequest>(params(&["0c"])).is_err());
+    }
+
+    #[test]
+    fn decode_result_test() {
+        let message = serde_json::json!("{ Error: Utxo not available for provided address}");
+        assert_eq!(
+            GetUtxosRequest::decode(message.clone()).unwrap(),
+            Vec::new()
+        );
+        assert!(AllMemoUtxosRequest::decode(message).unwrap().is_empty());
+
+        let utxo = Utxo::random();
+        let utxos = GetUtxosRequest::decode(serde_json::json!([utxo])).unwrap();
+        assert_eq!(utxos, vec![utxo]);
+
+        let outputs: Vec<Output> = Vec::new();
+        let encoded = hex::encode(bincode::serialize(&outputs).unwrap());
+        assert!(AllOutputsRequest::decode(serde_json::json!(encoded))
+            .unwrap()
+            .is_empty());
+        assert!(AllOutputsRequest::decode(serde_json::json!("zz")).is_err());
+
+        let all = AllUtxosRequest::decode(serde_json::json!(["a1"])).unwrap();
+        assert_eq!(all, AllUtxosResult::All(vec!["a1".to_string()]));
+    }
+}
@@ -17,3 +17,22 @@ pub fn uuid_str() -> String {
 
     uuid.to_string()
 }
+
+/// Verifies the inclusion proof of a hex encoded txid against a block txid root.
+///
+/// The root should come from a trusted source, e.g. `getBlockTxRoot`,
+/// rather than from the proof returned by the server.
+pub fn verify_inclusion(
+    txid: &str,
+    proof: &utxo_in_memory::blockoperations::txroot::TxInclusionProof,
+    root: &zkvm::merkle::Hash,
+) -> bool {
+    let txid: [u8; 32] = match hex::decode(txid) {
+        Ok(bytes) => match bytes.try_into() {
+            Ok(txid) => txid,
+            Err(_) => return false,
+        },
+        Err(_) => return false,
+    };
+    utxo_in_memory::blockoperations::txroot::verify_inclusion(txid, proof, root)
+}
@@ -0,0 +1,277 @@
+//! Audit log of the mutating rpc calls, see [`utxo_in_memory::rpcaudit`].
+//!
+//! The [`AuditMiddleware`] times each call of [`AUDITED_METHODS`] and queues a record
+//! of it to the persistence store of the node. The target of a call is read from its
+//! params: the txid of a committed tx, the txid of a reprocessed tx, the restored utxo
+//! or the root of a registered contract. The owner address of a committed tx and the
+//! api key of the caller are recorded as hashes under the salt of the node, the params
+//! themselves are never recorded. Records older than the retention are removed by
+//! [`audit_pruning_task`].
+
+use super::ratelimit::ClientMeta;
+use super::service::tx_id;
+use jsonrpc_core::futures::future::{Either, Ready};
+use jsonrpc_core::{BoxFuture, Call, Metadata, Middleware, Output, Params};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use transaction::{Transaction, TransactionType};
+use utxo_in_memory::error::UtxosetError;
+use utxo_in_memory::rpcaudit::{redact, unix_time, AuditOutcome, RpcAuditFilter, RpcAuditRecord};
+use utxo_in_memory::scriptregistry::RegisterScriptContractQuery;
+use utxo_in_memory::UtxoState;
+
+/// Methods changing the state of the node, each call of them is recorded.
+pub const AUDITED_METHODS: &[&str] = &[
+    "txCommit",
+    "reprocessFailedTx",
+    "registerScriptContract",
+    "restorePrunedOutput",
+    "rotateNodeKey",
+    "reloadTls",
+    "resumeBlockProcessing",
+];
+/// Days a record is kept unless `RPC_AUDIT_RETENTION_DAYS` is set.
+pub const DEFAULT_AUDIT_RETENTION_DAYS: u64 = 90;
+/// Wait between two prunings of the audit log.
+pub const AUDIT_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Salt of the hashed values and retention of the audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditConfig {
+    pub salt: String,
+    pub retention_days: u64,
+}
+
+impl AuditConfig {
+    /// Config of `salt`, a random salt if it is None.
+    /// The hashes under a random salt can not be matched across restarts.
+    pub fn new(salt: Option<String>, retention_days: u64) -> Self {
+        AuditConfig {
+            salt: salt.unwrap_or_else(|| uuid::Uuid::new_v4().to_simple().to_string()),
+            retention_days,
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig::new(None, DEFAULT_AUDIT_RETENTION_DAYS)
+    }
+}
+
+/// Audit records of a node, written to the store of its state.
+pub struct AuditLog {
+    config: AuditConfig,
+    state: Arc<UtxoState>,
+}
+
+impl AuditLog {
+    pub fn new(state: Arc<UtxoState>, config: AuditConfig) -> Self {
+        AuditLog { config, state }
+    }
+
+    pub fn config(&self) -> &AuditConfig {
+        &self.config
+    }
+
+    /// Hash of `value` under the salt of the log.
+    pub fn redact(&self, value: &str) -> String {
+        redact(&self.config.salt, value)
+    }
+
+    /// Queues `record` to the store, a record is dropped if the queue is full.
+    pub fn record(&self, record: RpcAuditRecord) {
+        let store = self.state.store();
+        let queued = self.state.sql_queue.lock().unwrap().try_execute(move || {
+            if let Err(e) = store.insert_rpc_audit(&record) {
+                eprintln!(
+                    "Failed to write the audit record of {}: {:?}",
+                    record.method, e
+                );
+            }
+        });
+        if queued.is_err() {
+            eprintln!("sql queue is full, an audit record is dropped");
+        }
+    }
+
+    /// Records of `filter` on page `page`, the newest first.
+    pub fn page(
+        &self,
+        filter: &RpcAuditFilter,
+        page: usize,
+    ) -> Result<Vec<RpcAuditRecord>, UtxosetError> {
+        self.state.store().get_rpc_audit(filter, page)
+    }
+
+    /// Removes the records older than the retention at the unix time `now`.
+    pub fn prune(&self, now: u64) -> Result<u64, UtxosetError> {
+        let retention = self.config.retention_days.saturating_mul(SECS_PER_DAY);
+        self.state
+            .store()
+            .prune_rpc_audit(now.saturating_sub(retention))
+    }
+}
+
+/// Prunes the audit log every [`AUDIT_PRUNE_INTERVAL`], run on its own thread.
+pub fn audit_pruning_task(log: Arc<AuditLog>) {
+    loop {
+        match log.prune(unix_time()) {
+            Ok(0) => {}
+            Ok(count) => println!("{} audit records pruned", count),
+            Err(e) => eprintln!("Failed to prune the audit log: {:?}", e),
+        }
+        std::thread::sleep(AUDIT_PRUNE_INTERVAL);
+    }
+}
+
+// target and owner of a call, read from its params
+fn call_target(log: &AuditLog, method: &str, params: &Params) -> (Option<String>, Option<String>) {
+    let strings = params.clone().parse::<Vec<String>>().unwrap_or_default();
+    let first = strings.first().map(|param| param.trim().to_string());
+    match method {
+        "txCommit" => {
+            let tx = first
+                .and_then(|hex_tx| hex::decode(hex_tx).ok())
+                .and_then(|bytes| Transaction::from_bytes(&bytes).ok());
+            match tx {
+                Some(tx) => {
+                    // a message is committed for the twilight address of its params
+                    let owner = if tx.tx_type == TransactionType::Message {
+                        strings.get(1).cloned()
+                    } else {
+                        tx.get_tx_inputs()
+                            .first()
+                            .and_then(|input| input.as_owner_address().cloned())
+                    };
+                    (
+                        Some(tx_id(&tx)),
+                        owner.map(|owner| log.redact(owner.trim())),
+                    )
+                }
+                None => (None, None),
+            }
+        }
+        "reprocessFailedTx" | "restorePrunedOutput" => (first, None),
+        "registerScriptContract" => {
+            let root = params
+                .clone()
+                .parse::<RegisterScriptContractQuery>()
+                .ok()
+                .and_then(|query| query.to_contract().ok())
+                .map(|contract| contract.root);
+            (root, None)
+        }
+        _ => (None, None),
+    }
+}
+
+// an error result, e.g. of a tx failing validation, is a rejection
+fn call_outcome(output: &Output) -> AuditOutcome {
+    match output {
+        Output::Success(success) => match success.result.as_str() {
+            Some(result) if result.to_lowercase().contains("error") => AuditOutcome::Rejected,
+            _ => AuditOutcome::Ok,
+        },
+        Output::Failure(_) => AuditOutcome::Error,
+    }
+}
+
+/// Records the calls of [`AUDITED_METHODS`] to an [`AuditLog`].
+#[derive(Clone)]
+pub struct AuditMiddleware {
+    log: Arc<AuditLog>,
+}
+
+impl AuditMiddleware {
+    pub fn new(log: Arc<AuditLog>) -> Self {
+        AuditMiddleware { log }
+    }
+}
+
+impl<M: Metadata + ClientMeta> Middleware<M> for AuditMiddleware {
+    type Future = Ready<Option<jsonrpc_core::Response>>;
+    type CallFuture = BoxFuture<Option<Output>>;
+
+    fn on_call<F, X>(&self, call: Call, meta: M, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, M) -> X + Send + Sync,
+        X: Future<Output = Option<Output>> + Send + 'static,
+    {
+        let method_call = match call {
+            Call::MethodCall(ref method_call)
+                if AUDITED_METHODS.contains(&method_call.method.as_str()) =>
+            {
+                method_call.clone()
+            }
+            _ => return Either::Right(next(call, meta)),
+        };
+        let (target, owner) = call_target(&self.log, &method_call.method, &method_call.params);
+        let client_ip = meta.client_key();
+        let api_key_id = meta.api_key().map(|key| self.log.redact(&key));
+        let timestamp = unix_time();
+        let started = Instant::now();
+        let log = self.log.clone();
+        let response = next(call, meta);
+        Either::Left(Box::pin(async move {
+            let output = response.await;
+            log.record(RpcAuditRecord {
+                timestamp,
+                method: method_call.method,
+                client_ip,
+                api_key_id,
+                target,
+                owner,
+                outcome: output.as_ref().map_or(AuditOutcome::Error, call_outcome),
+                latency_ms: started.elapsed().as_millis() as u64,
+            });
+            output
+        }))
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utxo_in_memory::pgsql::{MemoryStore, PersistenceStore};
+
+    #[test]
+    fn audit_prune_test() {
+        let store = Arc::new(MemoryStore::new());
+        let log = AuditLog::new(
+            Arc::new(UtxoState::with_store(store.clone())),
+            AuditConfig::new(Some("salt".to_string()), 2),
+        );
+        let now = 10 * SECS_PER_DAY;
+        for age in [0, 1, 2, 3, 5].iter() {
+            store
+                .insert_rpc_audit(&RpcAuditRecord {
+                    timestamp: now - age * SECS_PER_DAY,
+                    method: "txCommit".to_string(),
+                    client_ip: "10.0.0.1".to_string(),
+                    api_key_id: None,
+                    target: Some(format!("tx{}", age)),
+                    owner: None,
+                    outcome: AuditOutcome::Ok,
+                    latency_ms: 1,
+                })
+                .unwrap();
+        }
+        // the records of the last two days are kept
+        assert_eq!(log.prune(now).unwrap(), 2);
+        let targets: Vec<String> = log
+            .page(&RpcAuditFilter::default(), 0)
+            .unwrap()
+            .into_iter()
+            .filter_map(|record| record.target)
+            .collect();
+        assert_eq!(targets, vec!["tx0", "tx1", "tx2"]);
+        assert_eq!(log.prune(now).unwrap(), 0);
+    }
+}
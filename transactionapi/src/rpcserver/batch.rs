@@ -0,0 +1,296 @@
+//! Batch requests of the JSON-RPC server.
+//!
+//! The calls of a batch are dispatched to the rpc thread pool, at most `parallelism`
+//! of them at a time, and their outputs are returned in the order of the calls.
+//! Notifications have no output, a batch of notifications only has no response.
+//! A batch longer than `max_length` calls, or costing more than `max_cost`, is
+//! rejected as a whole before any of its calls runs. A read call costs 1 and an
+//! expensive one [`EXPENSIVE_CALL_COST`]. Every call of a batch still goes through
+//! the rate limiter and the audit log on its own.
+
+use super::ratelimit::{MethodClass, RPC_REJECTED_REQUESTS};
+use super::service::THREADPOOL_RPC_QUEUE;
+use super::threadpool::env_or;
+use jsonrpc_core::futures::channel::oneshot;
+use jsonrpc_core::futures::future::{self, Either};
+use jsonrpc_core::futures::{stream, StreamExt};
+use jsonrpc_core::types::error::{Error as JsonRpcError, ErrorCode};
+use jsonrpc_core::{BoxFuture, Call, Id, Metadata, Middleware, Output, Request, Response, Version};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Default cap of the number of calls of a batch.
+pub const DEFAULT_MAX_BATCH_LENGTH: usize = 100;
+/// Default cap of the combined cost of the calls of a batch.
+pub const DEFAULT_MAX_BATCH_COST: usize = 200;
+/// Default number of calls of a batch running at the same time.
+pub const DEFAULT_BATCH_PARALLELISM: usize = 4;
+/// Cost of a call of an expensive method, a read call costs 1.
+pub const EXPENSIVE_CALL_COST: usize = 10;
+
+/// JSON-RPC error code of a rejected batch.
+pub const BATCH_TOO_LARGE_CODE: i64 = -32007;
+
+/// Limits of the batch requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchConfig {
+    pub max_length: usize,
+    pub max_cost: usize,
+    pub parallelism: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_length: DEFAULT_MAX_BATCH_LENGTH,
+            max_cost: DEFAULT_MAX_BATCH_COST,
+            parallelism: DEFAULT_BATCH_PARALLELISM,
+        }
+    }
+}
+
+impl BatchConfig {
+    /// Reads the limits from `RPC_MAX_BATCH_LENGTH`, `RPC_MAX_BATCH_COST` and
+    /// `RPC_BATCH_PARALLELISM`.
+    pub fn from_env() -> Self {
+        BatchConfig {
+            max_length: env_or("RPC_MAX_BATCH_LENGTH", DEFAULT_MAX_BATCH_LENGTH),
+            max_cost: env_or("RPC_MAX_BATCH_COST", DEFAULT_MAX_BATCH_COST),
+            parallelism: env_or("RPC_BATCH_PARALLELISM", DEFAULT_BATCH_PARALLELISM),
+        }
+    }
+
+    /// Checks the length and the cost of a batch of `calls`.
+    pub fn check(&self, calls: &[Call]) -> Result<(), JsonRpcError> {
+        let cost = batch_cost(calls);
+        if calls.len() <= self.max_length && cost <= self.max_cost {
+            return Ok(());
+        }
+        Err(JsonRpcError {
+            code: ErrorCode::ServerError(BATCH_TOO_LARGE_CODE),
+            message: "Batch too large".to_string(),
+            data: Some(serde_json::json!({
+                "length": calls.len(),
+                "maxLength": self.max_length,
+                "cost": cost,
+                "maxCost": self.max_cost,
+            })),
+        })
+    }
+}
+
+fn call_cost(call: &Call) -> usize {
+    let method = match call {
+        Call::MethodCall(method_call) => method_call.method.as_str(),
+        Call::Notification(notification) => notification.method.as_str(),
+        Call::Invalid { .. } => return 1,
+    };
+    match MethodClass::of(method) {
+        MethodClass::Read => 1,
+        MethodClass::Expensive => EXPENSIVE_CALL_COST,
+    }
+}
+
+/// Combined cost of the calls of a batch.
+pub fn batch_cost(calls: &[Call]) -> usize {
+    calls.iter().map(call_cost).sum()
+}
+
+// runs the call on the rpc pool, in place if there is no runtime to drive it there
+// or if the pool queue is full
+async fn dispatch<X>(call: X) -> Option<Response>
+where
+    X: Future<Output = Option<Response>> + Send + 'static,
+{
+    let handle = match tokio::runtime::Handle::try_current() {
+        Ok(handle) => handle,
+        Err(_) => return call.await,
+    };
+    // the call is taken back from the job if it can not be queued
+    let slot = Arc::new(Mutex::new(Some(call)));
+    let job_slot = slot.clone();
+    let (sender, receiver) = oneshot::channel();
+    let queued = THREADPOOL_RPC_QUEUE.lock().unwrap().try_execute(move || {
+        if let Some(call) = job_slot.lock().unwrap().take() {
+            let _ = sender.send(handle.block_on(call));
+        }
+    });
+    if queued.is_err() {
+        let call = slot.lock().unwrap().take();
+        if let Some(call) = call {
+            return call.await;
+        }
+    }
+    receiver.await.ok().flatten()
+}
+
+/// Runs the calls of a batch concurrently, within the limits of a [`BatchConfig`].
+#[derive(Clone)]
+pub struct BatchMiddleware {
+    config: BatchConfig,
+}
+
+impl BatchMiddleware {
+    pub fn new(config: BatchConfig) -> Self {
+        BatchMiddleware { config }
+    }
+}
+
+impl<M: Metadata> Middleware<M> for BatchMiddleware {
+    type Future = BoxFuture<Option<Response>>;
+    type CallFuture = future::Ready<Option<Output>>;
+
+    fn on_request<F, X>(&self, request: Request, meta: M, next: F) -> Either<Self::Future, X>
+    where
+        F: Fn(Request, M) -> X + Send + Sync,
+        X: Future<Output = Option<Response>> + Send + 'static,
+    {
+        // an empty batch is answered with an invalid request error by the handler
+        let calls = match request {
+            Request::Batch(calls) if !calls.is_empty() => calls,
+            request => return Either::Right(next(request, meta)),
+        };
+        if let Err(err) = self.config.check(&calls) {
+            RPC_REJECTED_REQUESTS
+                .with_label_values(&["batch_size"])
+                .inc();
+            let output = Output::from(Err(err), Id::Null, Some(Version::V2));
+            return Either::Left(Box::pin(future::ready(Some(Response::Single(output)))));
+        }
+        let pending: Vec<X> = calls
+            .into_iter()
+            .map(|call| next(Request::Single(call), meta.clone()))
+            .collect();
+        let parallelism = self.config.parallelism.max(1);
+        Either::Left(Box::pin(async move {
+            let outputs: Vec<Output> = stream::iter(pending.into_iter().map(dispatch))
+                .buffered(parallelism)
+                .filter_map(|response| {
+                    future::ready(match response {
+                        Some(Response::Single(output)) => Some(output),
+                        _ => None,
+                    })
+                })
+                .collect()
+                .await;
+            if outputs.is_empty() {
+                None
+            } else {
+                Some(Response::Batch(outputs))
+            }
+        }))
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jsonrpc_core::{MetaIoHandler, Params, Value};
+
+    fn handler(config: BatchConfig) -> MetaIoHandler<(), BatchMiddleware> {
+        let mut io = MetaIoHandler::with_middleware(BatchMiddleware::new(config));
+        // answers after a delay decreasing with its param, so later calls finish first
+        io.add_method("echo", |params: Params| async move {
+            let value: Vec<u64> = params.parse()?;
+            tokio::time::sleep(std::time::Duration::from_millis(50 - value[0] * 10)).await;
+            Ok(Value::from(value[0]))
+        });
+        io.add_method("allOutputs", |_params: Params| async {
+            Ok(Value::from("[]"))
+        });
+        io
+    }
+
+    fn echo(id: u64, value: u64) -> serde_json::Value {
+        serde_json::json!({"jsonrpc": "2.0", "method": "echo", "params": [value], "id": id})
+    }
+
+    fn expensive(id: u64) -> serde_json::Value {
+        serde_json::json!({"jsonrpc": "2.0", "method": "allOutputs", "params": [], "id": id})
+    }
+
+    async fn batch(io: &MetaIoHandler<(), BatchMiddleware>, calls: serde_json::Value) -> Value {
+        let response = io.handle_request(&calls.to_string(), ()).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batch_ordering_test() {
+        let io = handler(BatchConfig::default());
+        let calls: Vec<serde_json::Value> = (0..5).map(|i| echo(10 + i, i)).collect();
+        let response = batch(&io, serde_json::json!(calls)).await;
+        let outputs = response.as_array().unwrap();
+        assert_eq!(outputs.len(), 5);
+        for (i, output) in outputs.iter().enumerate() {
+            assert_eq!(output["id"], 10 + i as u64);
+            assert_eq!(output["result"], i as u64);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batch_mixed_calls_test() {
+        let io = handler(BatchConfig::default());
+        let calls = serde_json::json!([
+            echo(1, 1),
+            {"jsonrpc": "2.0", "method": "unknown", "params": [], "id": 2},
+            {"jsonrpc": "2.0", "method": "echo", "params": [3]},
+            {"foo": "bar"},
+            echo(5, 2),
+        ]);
+        let response = batch(&io, calls).await;
+        let outputs = response.as_array().unwrap();
+        // the notification has no output
+        assert_eq!(outputs.len(), 4);
+        assert_eq!(outputs[0]["result"], 1);
+        assert_eq!(outputs[1]["id"], 2);
+        assert_eq!(outputs[1]["error"]["code"], -32601);
+        assert!(outputs[2]["id"].is_null());
+        assert_eq!(outputs[2]["error"]["code"], -32600);
+        assert_eq!(outputs[3]["id"], 5);
+        assert_eq!(outputs[3]["result"], 2);
+
+        // an empty batch is an invalid request
+        let response = batch(&io, serde_json::json!([])).await;
+        assert_eq!(response["error"]["code"], -32600);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn batch_limits_test() {
+        let io = handler(BatchConfig {
+            max_length: 4,
+            max_cost: 21,
+            parallelism: 2,
+        });
+        let calls: Vec<serde_json::Value> = (0..5).map(|i| echo(i, 0)).collect();
+        let response = batch(&io, serde_json::json!(calls)).await;
+        assert_eq!(response["error"]["code"], BATCH_TOO_LARGE_CODE);
+        assert_eq!(response["error"]["data"]["length"], 5);
+        assert_eq!(response["error"]["data"]["maxLength"], 4);
+        assert!(response["id"].is_null());
+
+        // three expensive calls cost more than the cap
+        let response = batch(
+            &io,
+            serde_json::json!([expensive(1), expensive(2), expensive(3)]),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], BATCH_TOO_LARGE_CODE);
+        assert_eq!(response["error"]["data"]["cost"], 3 * EXPENSIVE_CALL_COST);
+
+        let response = batch(
+            &io,
+            serde_json::json!([expensive(1), echo(2, 0), expensive(3)]),
+        )
+        .await;
+        assert_eq!(response.as_array().unwrap().len(), 3);
+        assert!(
+            RPC_REJECTED_REQUESTS
+                .with_label_values(&["batch_size"])
+                .get()
+                >= 2.0
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Parameters of the chain served by a node, for the clients to configure themselves.
+//!
+//! `getChainInfo` returns the network of the node, the magic bytes of its addresses,
+//! the tx versions it parses and the limits a tx is validated against, so a wallet
+//! does not hardcode them. The document carries a `version`, bumped when a field
+//! changes its meaning; fields are only added within a version.
+//!
+//! A client installs the address magic bytes of the document with
+//! [`crate::rpcclient::chaininfo::configure_network`] before parsing any address.
+
+use address::{network_config, Network, NetworkConfig};
+use serde::{Deserialize, Serialize};
+use transaction::weight::max_tx_size;
+use transaction::{QuisQuisConfig, TransactionVersion, DEFAULT_ANONYMITY_SET_SIZE};
+use utxo_in_memory::scriptregistry::{ScriptContract, SCRIPT_REGISTRY};
+use utxo_in_memory::UtxoState;
+
+/// Version of the [`ChainInfo`] document.
+pub const CHAIN_INFO_VERSION: u32 = 1;
+
+/// Fee per byte of the encoded tx a node accepts unless `MIN_FEE_RATE` is set,
+/// any fee.
+pub const DEFAULT_MIN_FEE_RATE: f64 = 0.0;
+
+/// Parameters of the chain a node is configured with, the part of the chain info
+/// not read from its state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainParams {
+    /// Address magic bytes of the node
+    pub network_config: NetworkConfig,
+    /// Fee per byte of the encoded tx below which txCommit rejects a tx
+    pub min_fee_rate: f64,
+}
+
+impl Default for ChainParams {
+    /// The installed address magic bytes, see [`address::set_network_config`], and
+    /// the default minimum fee rate.
+    fn default() -> Self {
+        ChainParams {
+            network_config: network_config(),
+            min_fee_rate: DEFAULT_MIN_FEE_RATE,
+        }
+    }
+}
+
+/// A registered script contract, without its programs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractSummary {
+    pub name: String,
+    pub root: String,
+    pub mainnet_address: String,
+    pub testnet_address: String,
+    pub program_count: usize,
+}
+
+impl From<&ScriptContract> for ContractSummary {
+    fn from(contract: &ScriptContract) -> Self {
+        ContractSummary {
+            name: contract.name.clone(),
+            root: contract.root.clone(),
+            mainnet_address: contract.mainnet_address.clone(),
+            testnet_address: contract.testnet_address.clone(),
+            program_count: contract.programs.len(),
+        }
+    }
+}
+
+/// The chain info served by `getChainInfo`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainInfo {
+    /// Version of the document, [`CHAIN_INFO_VERSION`]
+    pub version: u32,
+    pub network: Network,
+    /// Magic bytes of the addresses of both networks
+    pub network_config: NetworkConfig,
+    /// Tx versions the node parses, in increasing order
+    pub tx_versions: Vec<u16>,
+    /// Version of the txs created by the release of the node
+    pub current_tx_version: u16,
+    /// Fee per byte of the encoded tx
+    pub min_fee_rate: f64,
+    /// Largest encoded tx in bytes a block could hold
+    pub max_tx_size: u64,
+    /// Account counts of a QuisQuis transfer, in increasing order
+    pub anonymity_set_sizes: Vec<usize>,
+    pub default_anonymity_set_size: usize,
+    pub max_block_weight: u64,
+    pub block_height: u64,
+    /// Registered script contracts, by root
+    pub script_contracts: Vec<ContractSummary>,
+}
+
+impl ChainInfo {
+    /// Chain info of `state` configured with `params`, with the contracts of the
+    /// script registry.
+    pub fn of(state: &UtxoState, params: &ChainParams) -> Self {
+        let block_height = state.utxo_storage.lock().unwrap().block_height as u64;
+        let max_block_weight = state.max_block_weight();
+        let script_contracts = SCRIPT_REGISTRY
+            .lock()
+            .unwrap()
+            .contracts
+            .values()
+            .map(ContractSummary::from)
+            .collect();
+        ChainInfo {
+            version: CHAIN_INFO_VERSION,
+            network: state.network(),
+            network_config: params.network_config,
+            tx_versions: TransactionVersion::SUPPORTED
+                .iter()
+                .map(|version| version.to_u16())
+                .collect(),
+            current_tx_version: TransactionVersion::CURRENT.to_u16(),
+            min_fee_rate: params.min_fee_rate,
+            max_tx_size: max_tx_size(max_block_weight),
+            anonymity_set_sizes: QuisQuisConfig::supported_sizes(),
+            default_anonymity_set_size: DEFAULT_ANONYMITY_SET_SIZE,
+            max_block_weight,
+            block_height,
+            script_contracts,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use utxo_in_memory::pgsql::MemoryStore;
+
+    #[test]
+    fn chain_info_test() {
+        let state = UtxoState::secondary(Network::Testnet, Arc::new(MemoryStore::new()));
+        state.set_max_block_weight(1_000_000);
+        state.utxo_storage.lock().unwrap().block_height = 42;
+        let params = ChainParams {
+            network_config: NetworkConfig::new(100, 101, 102, 103).unwrap(),
+            min_fee_rate: 2.5,
+        };
+
+        let info = ChainInfo::of(&state, &params);
+        assert_eq!(info.version, CHAIN_INFO_VERSION);
+        assert_eq!(info.network, Network::Testnet);
+        assert_eq!(info.network_config.testnet_standard, 102);
+        assert_eq!(info.tx_versions, vec![3]);
+        assert_eq!(info.min_fee_rate, 2.5);
+        assert_eq!(info.max_block_weight, 1_000_000);
+        assert_eq!(info.max_tx_size, 100_000);
+        assert_eq!(info.block_height, 42);
+        assert!(info
+            .anonymity_set_sizes
+            .contains(&info.default_anonymity_set_size));
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["network"], "Testnet");
+        assert_eq!(serde_json::from_value::<ChainInfo>(json).unwrap(), info);
+    }
+}
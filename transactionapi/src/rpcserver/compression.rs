@@ -0,0 +1,294 @@
+//! Compression of large JSON-RPC responses.
+//!
+//! jsonrpc-http-server writes responses as they are, so requests whose
+//! `Accept-Encoding` allows gzip or deflate are answered by the
+//! `CompressionMiddleware` instead. It reads the body, handles it with the io handler
+//! and metadata of the server, and compresses responses of at least
+//! `RPC_COMPRESSION_MIN_SIZE` bytes on the compression thread pool, off the IO threads.
+//! Other requests go through the server unchanged, and a response is sent
+//! uncompressed when the pool queue is full.
+
+use super::ratelimit::{body_too_large, BodySizeMiddleware};
+use super::threadpool::{env_or, ThreadPool, DEFAULT_QUEUE_CAPACITY};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use jsonrpc_core::futures::channel::oneshot;
+use jsonrpc_core::{MetaIoHandler, Metadata, Middleware};
+use jsonrpc_http_server::hyper::body::Bytes;
+use jsonrpc_http_server::hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use jsonrpc_http_server::{hyper, RequestMiddleware, RequestMiddlewareAction};
+use prometheus::{register_counter_vec, CounterVec};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Default size in bytes from which a response is compressed.
+pub const DEFAULT_COMPRESSION_MIN_SIZE: usize = 1024;
+
+lazy_static! {
+    pub static ref RPC_COMPRESSION_BYTES: CounterVec = register_counter_vec!(
+        "rpc_compression_bytes",
+        "Bytes of the compressed rpc responses, before and after compression",
+        &["stage"]
+    )
+    .unwrap();
+    pub static ref THREADPOOL_COMPRESSION: Mutex<ThreadPool> = {
+        Mutex::new(ThreadPool::with_capacity(
+            env_or("THREADPOOL_COMPRESSION_SIZE", 2),
+            env_or("THREADPOOL_COMPRESSION_CAPACITY", DEFAULT_QUEUE_CAPACITY),
+            String::from("THREADPOOL_Compression"),
+        ))
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+
+    /// Encoding of the response to a request with this `Accept-Encoding` header.
+    /// gzip is preferred over deflate, and an encoding with `q=0` is refused.
+    pub fn negotiate(accept_encoding: &str) -> Option<ContentEncoding> {
+        let mut gzip = false;
+        let mut deflate = false;
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let refused = parts.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    .map_or(false, |q| q <= 0.0)
+            });
+            match name.as_str() {
+                "gzip" | "x-gzip" => gzip = !refused,
+                "deflate" => deflate = !refused,
+                _ => {}
+            }
+        }
+        if gzip {
+            Some(ContentEncoding::Gzip)
+        } else if deflate {
+            Some(ContentEncoding::Deflate)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compresses `body`. deflate is the zlib format, as HTTP defines it.
+pub fn compress(encoding: ContentEncoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        ContentEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        ContentEncoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Response compression settings of the rpc server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionConfig {
+    /// Responses smaller than this are sent uncompressed
+    pub min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            min_size: DEFAULT_COMPRESSION_MIN_SIZE,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Reads the threshold from `RPC_COMPRESSION_MIN_SIZE`.
+    pub fn from_env() -> Self {
+        CompressionConfig {
+            min_size: env_or("RPC_COMPRESSION_MIN_SIZE", DEFAULT_COMPRESSION_MIN_SIZE),
+        }
+    }
+}
+
+/// Compresses `body` on the compression pool.
+/// Returns None if the pool queue is full or the compression failed.
+async fn compress_on_pool(encoding: ContentEncoding, body: Bytes) -> Option<Vec<u8>> {
+    let (sender, receiver) = oneshot::channel();
+    let queued = THREADPOOL_COMPRESSION.lock().unwrap().try_execute(move || {
+        let _ = sender.send(compress(encoding, &body));
+    });
+    if queued.is_err() {
+        return None;
+    }
+    receiver.await.ok()?.ok()
+}
+
+pub(super) fn json_response(
+    body: hyper::Body,
+    encoding: Option<ContentEncoding>,
+) -> hyper::Response<hyper::Body> {
+    let mut response = hyper::Response::builder()
+        .header(CONTENT_TYPE, "application/json; charset=utf-8")
+        .header(VARY, "Accept-Encoding");
+    if let Some(encoding) = encoding {
+        response = response.header(CONTENT_ENCODING, encoding.as_str());
+    }
+    response.body(body).expect("Failed to build response")
+}
+
+async fn encode_response(
+    body: Bytes,
+    encoding: ContentEncoding,
+    min_size: usize,
+) -> hyper::Response<hyper::Body> {
+    if body.len() >= min_size {
+        if let Some(compressed) = compress_on_pool(encoding, body.clone()).await {
+            RPC_COMPRESSION_BYTES
+                .with_label_values(&["before"])
+                .inc_by(body.len() as f64);
+            RPC_COMPRESSION_BYTES
+                .with_label_values(&["after"])
+                .inc_by(compressed.len() as f64);
+            return json_response(hyper::Body::from(compressed), Some(encoding));
+        }
+    }
+    json_response(hyper::Body::from(body), None)
+}
+
+/// Answers the requests accepting a compressed response, after the body size check.
+pub struct CompressionMiddleware<M: Metadata, S: Middleware<M>> {
+    handler: Arc<MetaIoHandler<M, S>>,
+    extract_meta: fn(&hyper::Request<hyper::Body>) -> M,
+    body_size: BodySizeMiddleware,
+    max_body_size: usize,
+    config: CompressionConfig,
+}
+
+impl<M: Metadata, S: Middleware<M>> CompressionMiddleware<M, S> {
+    pub fn new(
+        handler: Arc<MetaIoHandler<M, S>>,
+        extract_meta: fn(&hyper::Request<hyper::Body>) -> M,
+        max_body_size: usize,
+        config: CompressionConfig,
+    ) -> Self {
+        CompressionMiddleware {
+            handler,
+            extract_meta,
+            body_size: BodySizeMiddleware::new(max_body_size),
+            max_body_size,
+            config,
+        }
+    }
+}
+
+impl<M, S> RequestMiddleware for CompressionMiddleware<M, S>
+where
+    M: Metadata,
+    S: Middleware<M>,
+    S::Future: Unpin,
+    S::CallFuture: Unpin,
+{
+    fn on_request(&self, request: hyper::Request<hyper::Body>) -> RequestMiddlewareAction {
+        let request = match self.body_size.on_request(request) {
+            RequestMiddlewareAction::Proceed { request, .. } => request,
+            action => return action,
+        };
+        let encoding = request
+            .headers()
+            .get(ACCEPT_ENCODING)
+            .and_then(|h| h.to_str().ok())
+            .and_then(ContentEncoding::negotiate);
+        let encoding = match encoding {
+            Some(encoding) if request.method() == hyper::Method::POST => encoding,
+            _ => return request.into(),
+        };
+        let meta = (self.extract_meta)(&request);
+        let handler = self.handler.clone();
+        let max_body_size = self.max_body_size;
+        let min_size = self.config.min_size;
+        RequestMiddlewareAction::Respond {
+            should_validate_hosts: true,
+            response: Box::pin(async move {
+                // the content length is checked above, chunked bodies are checked here
+                let body = hyper::body::to_bytes(request.into_body()).await?;
+                if body.len() > max_body_size {
+                    return Ok(body_too_large(body.len(), max_body_size));
+                }
+                let response = match handler
+                    .handle_request(&String::from_utf8_lossy(&body), meta)
+                    .await
+                {
+                    Some(response) => format!("{}\n", response),
+                    // notifications only
+                    None => String::new(),
+                };
+                Ok::<_, hyper::Error>(
+                    encode_response(Bytes::from(response), encoding, min_size).await,
+                )
+            }),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    #[test]
+    fn negotiate_encoding_test() {
+        let negotiate = ContentEncoding::negotiate;
+        assert_eq!(negotiate("gzip"), Some(ContentEncoding::Gzip));
+        assert_eq!(
+            negotiate("deflate, gzip;q=0.5"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            negotiate("gzip;q=0, deflate"),
+            Some(ContentEncoding::Deflate)
+        );
+        assert_eq!(negotiate(" DEFLATE "), Some(ContentEncoding::Deflate));
+        assert_eq!(negotiate("br, identity"), None);
+        assert_eq!(negotiate("gzip; q=0.0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn compress_round_trip_test() {
+        let body = "{\"jsonrpc\":\"2.0\",\"result\":\"00ff\",\"id\":1}".repeat(100);
+
+        let mut decoded = String::new();
+        let gzip = compress(ContentEncoding::Gzip, body.as_bytes()).unwrap();
+        GzDecoder::new(&gzip[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+        assert!(gzip.len() < body.len());
+
+        let mut decoded = String::new();
+        let deflate = compress(ContentEncoding::Deflate, body.as_bytes()).unwrap();
+        ZlibDecoder::new(&deflate[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+    }
+}
@@ -1,8 +1,40 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+mod audit;
+mod batch;
+mod chaininfo;
+mod compression;
+mod ratelimit;
+mod relay;
 mod server;
 mod service;
+mod status;
+mod subscription;
 mod threadpool;
+mod tls;
 mod types;
+mod validation;
+pub use self::audit::{
+    audit_pruning_task, AuditConfig, AuditLog, AUDITED_METHODS, DEFAULT_AUDIT_RETENTION_DAYS,
+};
+pub use self::batch::{BatchConfig, BATCH_TOO_LARGE_CODE};
+pub use self::chaininfo::{
+    ChainInfo, ChainParams, ContractSummary, CHAIN_INFO_VERSION, DEFAULT_MIN_FEE_RATE,
+};
+pub use self::compression::CompressionConfig;
+pub use self::ratelimit::RateLimitConfig;
+pub use self::relay::{
+    PeerStats, RelayConfig, TxRelay, DEFAULT_RELAY_ATTEMPTS, DEFAULT_RELAY_BACKOFF_MS, RELAYED_HEADER,
+};
 pub use self::server::*;
-pub use self::types::MintOrBurnTx;
+pub use self::service::{set_zkos_oracle_url, DEFAULT_ZKOS_ORACLE_URL};
+pub use self::status::{
+    status_transcript, KeyRotation, NodeKey, NodeKeyError, NodeStatus, SignedStatus,
+};
+pub use self::subscription::{start_ws_subscription_server, ws_subscription_server};
+pub use self::tls::{
+    cert_fingerprint, ClientCert, ServerTls, TlsConfig, TlsError, TlsReload, TlsServer,
+    CLIENT_CERT_REQUIRED_CODE, DEFAULT_CLIENT_CERT_METHODS,
+};
+pub use self::types::{MintOrBurnTx, TxStatus, TxStatusRecord, TxStatusView};
+pub use self::validation::{validate_tx, InputCheck, StateView, TxValidationReport};
@@ -0,0 +1,494 @@
+//! Request size caps and per client rate limits of the JSON-RPC server.
+//!
+//! The body size is checked against the `Content-Length` header by a request
+//! middleware, before the body is read. The rate limit is a token bucket per
+//! client and method class, applied to every call of a request (batches included).
+//! Clients are keyed by the address of `X-Forwarded-For` appended by the outermost
+//! of the `RPC_TRUSTED_PROXIES` reverse proxies in front of the server, or `X-Real-IP`,
+//! and by the peer address of the connection without a proxy. The entries left of
+//! it are set by the client and are not trusted.
+
+use super::threadpool::env_or;
+use jsonrpc_core::futures::future::{self, Either, Ready};
+use jsonrpc_core::types::error::{Error as JsonRpcError, ErrorCode};
+use jsonrpc_core::{Call, Metadata, Middleware, Output};
+use jsonrpc_http_server::{hyper, RequestMiddleware, RequestMiddlewareAction};
+use prometheus::{register_counter_vec, CounterVec};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utxo_in_memory::config::config_value;
+
+/// Default cap of a request body, in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+/// Default burst and refill per second of the read method budget.
+pub const DEFAULT_READ_RATE_LIMIT: usize = 50;
+/// Default burst and refill per second of the expensive method budget.
+pub const DEFAULT_EXPENSIVE_RATE_LIMIT: usize = 5;
+/// Header carrying the API key of exempted clients and admins.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// JSON-RPC error code of a rejected oversized request.
+pub const REQUEST_TOO_LARGE_CODE: i64 = -32006;
+/// JSON-RPC error code of a rate limited call.
+pub const RATE_LIMITED_CODE: i64 = -32005;
+
+// buckets kept before idle ones are dropped
+const MAX_TRACKED_BUCKETS: usize = 100_000;
+// minimum time between two sweeps of the idle buckets, a sweep holds the lock over every bucket
+const EVICTION_INTERVAL: Duration = Duration::from_secs(1);
+// client key of requests without a forwarded or peer address
+const UNKNOWN_CLIENT: &str = "unknown";
+
+lazy_static! {
+    /// Reverse proxies in front of the server appending to `X-Forwarded-For`.
+    pub static ref TRUSTED_PROXIES: usize = env_or("RPC_TRUSTED_PROXIES", 1);
+
+    pub static ref RPC_REJECTED_REQUESTS: CounterVec = register_counter_vec!(
+        "rpc_rejected_requests",
+        "Requests rejected by the rpc server limits",
+        &["reason"]
+    )
+    .unwrap();
+}
+
+/// Methods with their own, smaller, rate limit budget.
+const EXPENSIVE_METHODS: &[&str] = &[
+    "txCommit",
+    "txValidate",
+    "allOutputs",
+    "reprocessFailedTx",
+    "compactStorage",
+    "resumeBlockProcessing",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodClass {
+    Read,
+    Expensive,
+}
+
+impl MethodClass {
+    pub fn of(method: &str) -> MethodClass {
+        if EXPENSIVE_METHODS.contains(&method) {
+            MethodClass::Expensive
+        } else {
+            MethodClass::Read
+        }
+    }
+}
+
+/// Limits of the rpc server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    pub max_body_size: usize,
+    pub read_rate_limit: usize,
+    pub expensive_rate_limit: usize,
+    // keys of clients exempted from the rate limit
+    pub api_keys: HashSet<String>,
+    // keys allowed to call the admin methods
+    pub admin_api_keys: HashSet<String>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            read_rate_limit: DEFAULT_READ_RATE_LIMIT,
+            expensive_rate_limit: DEFAULT_EXPENSIVE_RATE_LIMIT,
+            api_keys: HashSet::new(),
+            admin_api_keys: HashSet::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reads the limits from `RPC_MAX_BODY_SIZE`, `RPC_READ_RATE_LIMIT`,
+    /// `RPC_EXPENSIVE_RATE_LIMIT` and the comma separated `RPC_API_KEYS` and
+    /// `RPC_ADMIN_API_KEYS`.
+    pub fn from_env() -> Self {
+        RateLimitConfig {
+            max_body_size: env_or("RPC_MAX_BODY_SIZE", DEFAULT_MAX_BODY_SIZE),
+            read_rate_limit: env_or("RPC_READ_RATE_LIMIT", DEFAULT_READ_RATE_LIMIT),
+            expensive_rate_limit: env_or("RPC_EXPENSIVE_RATE_LIMIT", DEFAULT_EXPENSIVE_RATE_LIMIT),
+            api_keys: env_keys("RPC_API_KEYS"),
+            admin_api_keys: env_keys("RPC_ADMIN_API_KEYS"),
+        }
+    }
+
+    fn rate_limit(&self, class: MethodClass) -> usize {
+        match class {
+            MethodClass::Read => self.read_rate_limit,
+            MethodClass::Expensive => self.expensive_rate_limit,
+        }
+    }
+}
+
+// comma separated keys of the config value `name`
+fn env_keys(name: &str) -> HashSet<String> {
+    config_value(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect()
+}
+
+/// Token bucket holding up to `capacity` calls, refilled by `capacity` calls per second.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: usize, now: Instant) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+}
+
+/// Token buckets of every client and method class, with the time of the last sweep.
+#[derive(Debug, Default)]
+struct Buckets {
+    buckets: HashMap<(String, MethodClass), TokenBucket>,
+    last_eviction: Option<Instant>,
+}
+
+/// Token buckets of every client and method class.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<Buckets>,
+    max_buckets: usize,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Mutex::new(Buckets::default()),
+            max_buckets: MAX_TRACKED_BUCKETS,
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    pub fn is_exempt(&self, api_key: Option<&str>) -> bool {
+        api_key.map_or(false, |key| self.config.api_keys.contains(key))
+    }
+
+    pub fn is_admin(&self, api_key: Option<&str>) -> bool {
+        api_key.map_or(false, |key| self.config.admin_api_keys.contains(key))
+    }
+
+    /// Takes a token of `client` for `method`. Returns false if the budget is spent.
+    /// Full buckets are dropped once too many are tracked, at most once per `EVICTION_INTERVAL`.
+    pub fn check(&self, client: &str, method: &str, now: Instant) -> bool {
+        let class = MethodClass::of(method);
+        let mut state = self.buckets.lock().unwrap();
+        let eviction_due = state.last_eviction.map_or(true, |last| {
+            now.saturating_duration_since(last) >= EVICTION_INTERVAL
+        });
+        if state.buckets.len() >= self.max_buckets && eviction_due {
+            state.last_eviction = Some(now);
+            state.buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                !bucket.is_full()
+            });
+        }
+        state
+            .buckets
+            .entry((client.to_string(), class))
+            .or_insert_with(|| TokenBucket::new(self.config.rate_limit(class), now))
+            .try_take(now)
+    }
+}
+
+/// Peer address of the connection of a request, an extension of the requests of the
+/// TLS server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerAddr(pub SocketAddr);
+
+/// Client key of a request, see [`client_key_behind`] with the `TRUSTED_PROXIES`.
+pub fn client_key(req: &hyper::Request<hyper::Body>) -> String {
+    client_key_behind(req, *TRUSTED_PROXIES)
+}
+
+/// Client key of a request through `trusted_proxies` reverse proxies: the entry of
+/// `X-Forwarded-For` appended by the outermost of them, counted from the right, or
+/// the leftmost entry if the header is shorter. `X-Real-IP` is read without the
+/// forwarded header. Without a proxy, or a forwarded address, the key is the peer address.
+pub fn client_key_behind(req: &hyper::Request<hyper::Body>, trusted_proxies: usize) -> String {
+    let header = |name: &str| req.headers().get(name).and_then(|h| h.to_str().ok());
+    let forwarded = match trusted_proxies {
+        0 => None,
+        _ => header("X-Forwarded-For")
+            .and_then(|h| {
+                let entries: Vec<&str> = h.split(',').map(str::trim).collect();
+                let outermost = entries.len().saturating_sub(trusted_proxies);
+                entries.get(outermost).copied()
+            })
+            .or_else(|| header("X-Real-IP"))
+            .map(|h| h.trim().to_string()),
+    };
+    let peer = req
+        .extensions()
+        .get::<PeerAddr>()
+        .map(|peer| peer.0.ip().to_string());
+    match forwarded.filter(|client| !client.is_empty()).or(peer) {
+        Some(client) => client,
+        None => UNKNOWN_CLIENT.to_string(),
+    }
+}
+
+/// Metadata the rate limit and client certificate middlewares need from the request.
+pub trait ClientMeta {
+    fn client_key(&self) -> String;
+    fn api_key(&self) -> Option<String>;
+    /// Fingerprint of the verified client certificate, only over mutual TLS
+    fn client_cert(&self) -> Option<String>;
+}
+
+/// Rejects the calls of clients over their budget.
+#[derive(Clone)]
+pub struct RateLimitMiddleware {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitMiddleware {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        RateLimitMiddleware { limiter }
+    }
+}
+
+impl<M: Metadata + ClientMeta> Middleware<M> for RateLimitMiddleware {
+    type Future = Ready<Option<jsonrpc_core::Response>>;
+    type CallFuture = Ready<Option<Output>>;
+
+    fn on_call<F, X>(&self, call: Call, meta: M, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, M) -> X + Send + Sync,
+        X: Future<Output = Option<Output>> + Send + 'static,
+    {
+        if let Call::MethodCall(ref method_call) = call {
+            let api_key = meta.api_key();
+            if !self.limiter.is_exempt(api_key.as_deref())
+                && !self
+                    .limiter
+                    .check(&meta.client_key(), &method_call.method, Instant::now())
+            {
+                RPC_REJECTED_REQUESTS
+                    .with_label_values(&["rate_limit"])
+                    .inc();
+                let err = JsonRpcError {
+                    code: ErrorCode::ServerError(RATE_LIMITED_CODE),
+                    message: format!("Rate limit exceeded for {}", method_call.method),
+                    data: None,
+                };
+                return Either::Left(future::ready(Some(Output::from(
+                    Err(err),
+                    method_call.id.clone(),
+                    method_call.jsonrpc,
+                ))));
+            }
+        }
+        Either::Right(next(call, meta))
+    }
+}
+
+/// Rejects requests whose `Content-Length` is over the cap, before the body is read.
+/// Bodies without a length are capped by the server builder.
+pub struct BodySizeMiddleware {
+    max_body_size: usize,
+}
+
+impl BodySizeMiddleware {
+    pub fn new(max_body_size: usize) -> Self {
+        BodySizeMiddleware { max_body_size }
+    }
+}
+
+/// Response to a request body of `length` bytes over `max_body_size`.
+pub fn body_too_large(length: usize, max_body_size: usize) -> hyper::Response<hyper::Body> {
+    RPC_REJECTED_REQUESTS
+        .with_label_values(&["body_size"])
+        .inc();
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": {
+            "code": REQUEST_TOO_LARGE_CODE,
+            "message": format!(
+                "Request body of {} bytes exceeds the limit of {} bytes",
+                length, max_body_size
+            ),
+        },
+        "id": null,
+    });
+    hyper::Response::builder()
+        .status(hyper::StatusCode::PAYLOAD_TOO_LARGE)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(body.to_string()))
+        .expect("Failed to build response")
+}
+
+impl RequestMiddleware for BodySizeMiddleware {
+    fn on_request(&self, request: hyper::Request<hyper::Body>) -> RequestMiddlewareAction {
+        let length = request
+            .headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.parse::<usize>().ok());
+        match length {
+            Some(length) if length > self.max_body_size => RequestMiddlewareAction::Respond {
+                should_validate_hosts: false,
+                response: Box::pin(future::ready(Ok(body_too_large(
+                    length,
+                    self.max_body_size,
+                )))),
+            },
+            _ => request.into(),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn config(read: usize, expensive: usize) -> RateLimitConfig {
+        RateLimitConfig {
+            read_rate_limit: read,
+            expensive_rate_limit: expensive,
+            api_keys: ["secret".to_string()].into_iter().collect(),
+            admin_api_keys: ["admin".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        }
+    }
+
+    #[test]
+    fn token_bucket_refill_test() {
+        let limiter = RateLimiter::new(config(2, 1));
+        let start = Instant::now();
+        assert!(limiter.check("a", "getUtxos", start));
+        assert!(limiter.check("a", "getUtxos", start));
+        assert!(!limiter.check("a", "getUtxos", start));
+        // other clients and method classes have their own budget
+        assert!(limiter.check("b", "getUtxos", start));
+        assert!(limiter.check("a", "txCommit", start));
+        assert!(!limiter.check("a", "allOutputs", start));
+        // half a second refills one read token
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check("a", "getUtxos", later));
+        assert!(!limiter.check("a", "getUtxos", later));
+    }
+
+    #[test]
+    fn bucket_eviction_interval_test() {
+        let mut limiter = RateLimiter::new(config(1, 1));
+        limiter.max_buckets = 2;
+        let tracked = |limiter: &RateLimiter| limiter.buckets.lock().unwrap().buckets.len();
+        let start = Instant::now();
+        assert!(limiter.check("a", "getUtxos", start));
+        assert!(limiter.check("b", "getUtxos", start));
+        // the buckets of a and b are full again and dropped by the sweep
+        let later = start + Duration::from_secs(2);
+        assert!(limiter.check("c", "getUtxos", later));
+        assert_eq!(tracked(&limiter), 1);
+        assert!(limiter.check("d", "getUtxos", later));
+        // no second sweep within the interval
+        assert!(limiter.check("e", "getUtxos", later + Duration::from_millis(10)));
+        assert_eq!(tracked(&limiter), 3);
+        assert!(limiter.check("f", "getUtxos", later + EVICTION_INTERVAL));
+        assert_eq!(tracked(&limiter), 1);
+    }
+
+    fn request(
+        forwarded: Option<&str>,
+        real_ip: Option<&str>,
+        peer: Option<&str>,
+    ) -> hyper::Request<hyper::Body> {
+        let mut builder = hyper::Request::builder();
+        if let Some(forwarded) = forwarded {
+            builder = builder.header("X-Forwarded-For", forwarded);
+        }
+        if let Some(real_ip) = real_ip {
+            builder = builder.header("X-Real-IP", real_ip);
+        }
+        let mut req = builder.body(hyper::Body::empty()).unwrap();
+        if let Some(peer) = peer {
+            req.extensions_mut().insert(PeerAddr(peer.parse().unwrap()));
+        }
+        req
+    }
+
+    #[test]
+    fn client_key_test() {
+        // the client controls the entries left of the ones appended by the proxies
+        let req = request(
+            Some("6.6.6.6, 1.1.1.1, 2.2.2.2"),
+            None,
+            Some("3.3.3.3:4000"),
+        );
+        assert_eq!(client_key_behind(&req, 1), "2.2.2.2");
+        assert_eq!(client_key_behind(&req, 2), "1.1.1.1");
+        assert_eq!(client_key_behind(&req, 5), "6.6.6.6");
+        // without a trusted proxy the forwarded headers are ignored
+        assert_eq!(client_key_behind(&req, 0), "3.3.3.3");
+
+        let real_ip = request(None, Some("1.1.1.1"), Some("3.3.3.3:4000"));
+        assert_eq!(client_key_behind(&real_ip, 1), "1.1.1.1");
+        let direct = request(None, None, Some("3.3.3.3:4000"));
+        assert_eq!(client_key_behind(&direct, 1), "3.3.3.3");
+        assert_eq!(
+            client_key_behind(&request(Some(""), None, None), 1),
+            UNKNOWN_CLIENT
+        );
+    }
+
+    #[test]
+    fn api_key_exemption_test() {
+        let limiter = RateLimiter::new(config(1, 1));
+        assert!(limiter.is_exempt(Some("secret")));
+        assert!(!limiter.is_exempt(Some("guess")));
+        assert!(!limiter.is_exempt(None));
+        // rate limit exemption does not grant admin access
+        assert!(limiter.is_admin(Some("admin")));
+        assert!(!limiter.is_admin(Some("secret")));
+        assert!(!limiter.is_admin(None));
+    }
+}
@@ -0,0 +1,270 @@
+//! One-hop relay of committed txs to the peer transaction apis.
+//!
+//! After `txCommit` accepts a transfer or script tx into the mempool, the tx is
+//! posted to the `txCommit` of every peer of `RELAY_PEERS` with the [`RELAYED_HEADER`]
+//! set to its txid. A peer commits a relayed tx like any other tx but does not relay
+//! it again. Failed posts are retried with a doubling backoff, and a txid relayed
+//! recently is not relayed twice. Relay failures are only counted, they never change
+//! the result of the local commit.
+
+use super::threadpool::{ThreadPool, DEFAULT_QUEUE_CAPACITY};
+use prometheus::{register_counter_vec, CounterVec};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+/// Header of a relayed `txCommit`, holding the txid of the relaying node.
+pub const RELAYED_HEADER: &str = "X-Relayed";
+/// Posts of a tx to a peer before it counts as failed.
+pub const DEFAULT_RELAY_ATTEMPTS: u32 = 3;
+/// Wait before the first retry, doubled for each further one.
+pub const DEFAULT_RELAY_BACKOFF_MS: u64 = 200;
+/// Txids remembered as relayed.
+pub const RELAY_DEDUP_CAPACITY: usize = 10_000;
+
+lazy_static! {
+    static ref RELAY_SUCCESS: CounterVec =
+        register_counter_vec!("tx_relay_success", "Txs relayed to a peer", &["peer"]).unwrap();
+    static ref RELAY_FAILURE: CounterVec = register_counter_vec!(
+        "tx_relay_failure",
+        "Txs that could not be relayed to a peer",
+        &["peer"]
+    )
+    .unwrap();
+}
+
+/// Peers of the node and the retry policy of a relay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayConfig {
+    /// Base urls of the peer rpc servers
+    pub peers: Vec<Url>,
+    pub max_attempts: u32,
+    pub backoff: Duration,
+}
+
+impl RelayConfig {
+    pub fn new(peers: Vec<Url>) -> Self {
+        RelayConfig {
+            peers,
+            ..RelayConfig::default()
+        }
+    }
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        RelayConfig {
+            peers: Vec::new(),
+            max_attempts: DEFAULT_RELAY_ATTEMPTS,
+            backoff: Duration::from_millis(DEFAULT_RELAY_BACKOFF_MS),
+        }
+    }
+}
+
+/// Relay counts of a peer, as answered by `getPeers`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PeerStats {
+    pub url: String,
+    /// Txs the peer answered
+    pub relayed: u64,
+    /// Txs given up on after the last attempt, or not queued
+    pub failed: u64,
+}
+
+struct Peer {
+    url: Url,
+    relayed: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Peer {
+    fn record(&self, success: bool) {
+        let label = self.url.as_str();
+        if success {
+            self.relayed.fetch_add(1, Ordering::SeqCst);
+            RELAY_SUCCESS.with_label_values(&[label]).inc();
+        } else {
+            self.failed.fetch_add(1, Ordering::SeqCst);
+            RELAY_FAILURE.with_label_values(&[label]).inc();
+        }
+    }
+}
+
+// txids relayed recently, the oldest is dropped first
+struct RecentTxids {
+    txids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl RecentTxids {
+    // false if `txid` is already known
+    fn insert(&mut self, txid: &str) -> bool {
+        if !self.txids.insert(txid.to_string()) {
+            return false;
+        }
+        self.order.push_back(txid.to_string());
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.txids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Forwards committed txs to the peers of a node in the background.
+pub struct TxRelay {
+    config: RelayConfig,
+    peers: Vec<Arc<Peer>>,
+    recent: Mutex<RecentTxids>,
+    queue: Mutex<ThreadPool>,
+}
+
+impl TxRelay {
+    pub fn new(config: RelayConfig) -> Self {
+        let peers = config
+            .peers
+            .iter()
+            .map(|url| {
+                Arc::new(Peer {
+                    url: url.clone(),
+                    relayed: AtomicU64::new(0),
+                    failed: AtomicU64::new(0),
+                })
+            })
+            .collect::<Vec<_>>();
+        let queue = ThreadPool::with_capacity(
+            peers.len().max(1),
+            DEFAULT_QUEUE_CAPACITY,
+            String::from("TX_RELAY_Queue"),
+        );
+        TxRelay {
+            config,
+            peers,
+            recent: Mutex::new(RecentTxids {
+                txids: HashSet::new(),
+                order: VecDeque::new(),
+                capacity: RELAY_DEDUP_CAPACITY,
+            }),
+            queue: Mutex::new(queue),
+        }
+    }
+
+    /// Relay counts of each peer, in the order of the config.
+    pub fn peers(&self) -> Vec<PeerStats> {
+        self.peers
+            .iter()
+            .map(|peer| PeerStats {
+                url: peer.url.to_string(),
+                relayed: peer.relayed.load(Ordering::SeqCst),
+                failed: peer.failed.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    /// Queues the hex encoded tx `txid` for each peer.
+    /// Returns false if there are no peers or the txid was relayed recently.
+    pub fn relay(&self, txid: &str, tx_hex: &str) -> bool {
+        if self.peers.is_empty() || !self.recent.lock().unwrap().insert(txid) {
+            return false;
+        }
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "txCommit",
+            "params": [tx_hex],
+            "id": 1,
+        })
+        .to_string();
+        let queue = self.queue.lock().unwrap();
+        for peer in self.peers.iter() {
+            let peer = peer.clone();
+            let (txid, body) = (txid.to_string(), body.clone());
+            let (max_attempts, backoff) = (self.config.max_attempts, self.config.backoff);
+            let queued = queue.try_execute(move || {
+                let success = post_to_peer(&peer.url, &txid, &body, max_attempts, backoff);
+                peer.record(success);
+            });
+            if queued.is_err() {
+                peer.record(false);
+            }
+        }
+        true
+    }
+}
+
+// posts `body` until the peer answers, waiting `backoff` doubled between attempts
+fn post_to_peer(url: &Url, txid: &str, body: &str, max_attempts: u32, backoff: Duration) -> bool {
+    let client = reqwest::blocking::Client::new();
+    let mut wait = backoff;
+    for attempt in 1..=max_attempts.max(1) {
+        let response = client
+            .post(url.as_str())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(RELAYED_HEADER, txid)
+            .body(body.to_string())
+            .timeout(Duration::from_secs(10))
+            .send();
+        match response {
+            // a peer rejecting the tx answers with a result too, a retry would not help
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) => println!(
+                "relay of tx {} to {} failed: {}",
+                txid,
+                url,
+                response.status()
+            ),
+            Err(e) => println!("relay of tx {} to {} failed: {}", txid, url, e),
+        }
+        if attempt < max_attempts {
+            std::thread::sleep(wait);
+            wait *= 2;
+        }
+    }
+    false
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relay_dedup_test() {
+        // nothing listens on the peer, every attempt fails
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        let relay = TxRelay::new(RelayConfig {
+            peers: vec![url.clone()],
+            max_attempts: 2,
+            backoff: Duration::from_millis(10),
+        });
+        assert!(relay.relay("txid", "00"));
+        assert!(!relay.relay("txid", "00"));
+        for _ in 0..100 {
+            if relay.peers()[0].failed > 0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(
+            relay.peers(),
+            vec![PeerStats {
+                url: url.to_string(),
+                relayed: 0,
+                failed: 1,
+            }]
+        );
+
+        // a node without peers relays nothing
+        let relay = TxRelay::new(RelayConfig::default());
+        assert!(!relay.relay("txid", "00"));
+        assert!(relay.peers().is_empty());
+    }
+}
@@ -1,276 +1,492 @@
+use super::audit::{AuditConfig, AuditLog, AuditMiddleware};
+use super::batch::{BatchConfig, BatchMiddleware};
+use super::chaininfo::{ChainInfo, ChainParams};
+use super::compression::{CompressionConfig, CompressionMiddleware};
+use super::ratelimit::{
+    client_key, ClientMeta, RateLimitConfig, RateLimitMiddleware, RateLimiter,
+    API_KEY_HEADER,
+};
+use super::relay::{TxRelay, RELAYED_HEADER};
 use super::service;
+use super::service::{tx_id, ORACLE_CONFIG};
+use super::status::{NodeKey, NodeStatus};
+use super::tls::{start_tls_server, ClientCert, ClientCertMiddleware, ServerTls, TlsServer};
+use super::validation::{validate_tx, StateView};
 // use crate::rpcserver::types::*;
+use crate::TransactionStatusId;
 use jsonrpc_core::types::error::Error as JsonRpcError;
 use jsonrpc_core::*;
 use jsonrpc_http_server::jsonrpc_core::{MetaIoHandler, Metadata, Params};
 use jsonrpc_http_server::{hyper, ServerBuilder};
 
+use address::Network;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use transaction::decode::decode_transaction_view;
 use transaction::{TransactionData, TransactionType};
 use utxo_in_memory::blockoperations::blockprocessing::{
-    all_coin_type_output, all_coin_type_utxo, all_memo_type_utxo, all_state_type_utxo,
+    all_coin_type_output, all_memo_type_utxo, all_state_type_utxo, all_type_utxo,
+    all_type_utxo_chunk, get_block_apply_result, reprocess_failed_tx, utxo_read_view,
+    AllUtxosQuery, MAX_UNBOUNDED_UTXO_COUNT,
     search_coin_type_utxo_by_address, search_coin_type_utxo_by_utxo_key,
     search_memo_type_utxo_by_address, search_memo_type_utxo_by_utxo_key,
-    search_state_type_utxo_by_address, search_state_type_utxo_by_utxo_key, verify_utxo,
+    raw_outputs_by_address, search_raw_output_by_utxo_key, search_raw_utxo_by_address,
+    search_state_type_utxo_by_address, search_state_type_utxo_by_utxo_key, utxos_by_address,
+    RawUtxoOutput,
 };
+use utxo_in_memory::audit::compare_memory_to_psql;
+use utxo_in_memory::blockoperations::addresstx::ADDRESS_TXS;
+use utxo_in_memory::blockoperations::blockstats::BLOCK_STATS_RETENTION;
+use utxo_in_memory::blockoperations::failedtx::FAILED_TXS;
+use utxo_in_memory::blockoperations::fees::BLOCK_FEES;
+use utxo_in_memory::blockoperations::mempool::{simulate_transfer, SimulateTransferQuery, MEMPOOL};
+use utxo_in_memory::blockoperations::messages::BURN_HISTORY;
+use utxo_in_memory::blockoperations::pruning::restore_pruned_output;
+use utxo_in_memory::blockoperations::scripteval::{eval_script, EvalScriptQuery};
+use utxo_in_memory::blockoperations::txroot::BLOCK_TX_ROOTS;
+use utxo_in_memory::blockoperations::txstatus::TxStatus;
 use utxo_in_memory::db::LocalDBtrait;
-use utxo_in_memory::UTXO_STORAGE;
+use utxo_in_memory::network::{address_network, NetworkStates};
+use utxo_in_memory::rpcaudit::{unix_time, AuditLogQuery};
+use utxo_in_memory::scriptregistry::{
+    register_script_contract, RegisterScriptContractQuery, SCRIPT_REGISTRY,
+};
+use utxo_in_memory::{global_state, UtxoState, UTXO_STORAGE};
 /***************** POstgreSQL Insert Code *********/
+use utxo_in_memory::error::UtxosetError;
 use utxo_in_memory::pgsql::{
-    get_utxo_from_db_by_block_height_range, QueryUtxoFromDB, TestCommand, TestCommandString,
-    UtxoHexEncodedResult,
+    get_utxo_from_db_by_block_height_range, get_utxos_detailed, persistence_store,
+    QueryUtxoFromDB, TestCommand, TestCommandString, UtxoDetailedQuery, UtxoHexEncodedResult,
+    UTXO_DETAILED_MAX_LIMIT,
 };
 /**************** POstgreSQL Insert Code End **********/
 
-use zkvm::zkos_types::{MessageType, Utxo};
+use zkvm::zkos_types::json::OutputJson;
+use zkvm::zkos_types::{IOType, MessageType, Output, Utxo};
 #[derive(Default, Clone, Debug)]
 struct Meta {
     metadata: HashMap<String, Option<String>>,
 }
 impl Metadata for Meta {}
 
-pub fn rpcserver() {
+impl ClientMeta for Meta {
+    fn client_key(&self) -> String {
+        self.metadata
+            .get("client_ip")
+            .cloned()
+            .flatten()
+            .unwrap_or_default()
+    }
+    fn api_key(&self) -> Option<String> {
+        self.metadata.get("api_key").cloned().flatten()
+    }
+    fn client_cert(&self) -> Option<String> {
+        self.metadata.get("client_cert").cloned().flatten()
+    }
+}
+
+/// Utxo state the tx methods of a server commit against, the relay of the
+/// committed txs to the peers, the audit log of the mutating calls, the key
+/// signing the status of the node, the states of the tracked networks, the
+/// certificates of a TLS server and the parameters of the chain. The other
+/// methods read the global state.
+#[derive(Clone)]
+pub struct RpcNode {
+    pub state: Arc<UtxoState>,
+    pub relay: Arc<TxRelay>,
+    pub audit: Arc<AuditLog>,
+    pub node_key: Arc<NodeKey>,
+    pub networks: Arc<NetworkStates>,
+    pub tls: Option<Arc<ServerTls>>,
+    pub chain: ChainParams,
+}
+
+impl RpcNode {
+    /// Node auditing with a random salt and the default retention, see [`RpcNode::with_audit`],
+    /// and signing with an ephemeral key, see [`RpcNode::with_node_key`].
+    pub fn new(state: Arc<UtxoState>, relay: TxRelay) -> Self {
+        RpcNode {
+            audit: Arc::new(AuditLog::new(state.clone(), AuditConfig::default())),
+            networks: Arc::new(NetworkStates::new(state.clone())),
+            state,
+            relay: Arc::new(relay),
+            node_key: Arc::new(NodeKey::ephemeral()),
+            tls: None,
+            chain: ChainParams::default(),
+        }
+    }
+
+    /// The node with the audit salt and retention of `config`.
+    pub fn with_audit(mut self, config: AuditConfig) -> Self {
+        self.audit = Arc::new(AuditLog::new(self.state.clone(), config));
+        self
+    }
+
+    /// The node signing its status with `node_key`.
+    pub fn with_node_key(mut self, node_key: NodeKey) -> Self {
+        self.node_key = Arc::new(node_key);
+        self
+    }
+
+    /// The node answering the address queries of the networks of `networks` as well,
+    /// see [`utxo_in_memory::network`].
+    pub fn with_networks(mut self, networks: NetworkStates) -> Self {
+        self.networks = Arc::new(networks);
+        self
+    }
+
+    /// The node served over TLS with the certificates of `tls`, see [`start_rpc_node_tls`].
+    pub fn with_tls(mut self, tls: ServerTls) -> Self {
+        self.tls = Some(Arc::new(tls));
+        self
+    }
+
+    /// The node configured with the chain parameters `chain`, see [`super::chaininfo`].
+    pub fn with_chain_params(mut self, chain: ChainParams) -> Self {
+        self.chain = chain;
+        self
+    }
+
+    /// The global state, without peers.
+    pub fn global() -> Self {
+        RpcNode::new(global_state(), TxRelay::new(Default::default()))
+    }
+
+    fn state_view(&self) -> StateView {
+        StateView::new(
+            &self.state.utxo_storage,
+            &self.state.mempool,
+            ORACLE_CONFIG.as_ref(),
+        )
+        .with_nullifiers(&self.state.nullifiers)
+        .with_max_block_weight(self.state.max_block_weight())
+        .with_min_fee_rate(self.chain.min_fee_rate)
+    }
+}
+
+/// Runs the rpc server on `addr`, the `RPC_BIND_ADDRESS` of [`crate::config::ApiConfig`].
+pub fn rpcserver(addr: SocketAddr, node: RpcNode) {
 
     println!("Starting rpc server");
-    // let mut io = IoHandler::default();
-    let mut io = MetaIoHandler::default();
+    eprintln!("Starting jsonRPC server @ {}", addr);
+    if node.tls.is_some() {
+        let server = start_rpc_node_tls(&addr, RateLimitConfig::from_env(), node).unwrap();
+        println!("started rpc api server over TLS");
+        server.wait();
+        return;
+    }
+    let server = start_rpc_node(&addr, RateLimitConfig::from_env(), node).unwrap();
+    println!("started rpc api server");
+    server.wait();
+}
 
-    io.add_method_with_meta("txCommit", move |params: Params, _meta: Meta| async move {
-        let tx: transaction::Transaction;
-        // extract the params vector from the request
-        let vector_params: Vec<String> = match params.parse::<Vec<String>>() {
-            Ok(vec) => {
-                if vec.is_empty() {
-                    let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-                    return Err(err);
-                }
-                vec
-            }
-            Err(args) => {
-                let err = JsonRpcError::invalid_params(format!(
-                    "Incorrect Parameters: Expected a Vec hex string from client, {:?}",
-                    args
-                ));
-                return Err(err);
-            }
-        };
-        // extract the tx hex string
-        let hex_tx = vector_params[0].clone();
-        if hex_tx.trim().is_empty() {
-            let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+/// Starts the rpc server on `addr` with the request size and rate limits of `config`.
+pub fn start_rpc_server(
+    addr: &SocketAddr,
+    config: RateLimitConfig,
+) -> std::io::Result<jsonrpc_http_server::Server> {
+    start_rpc_node(addr, config, RpcNode::global())
+}
+
+/// Starts the rpc server of `node` on `addr`, see [`start_rpc_server`].
+pub fn start_rpc_node(
+    addr: &SocketAddr,
+    config: RateLimitConfig,
+    node: RpcNode,
+) -> std::io::Result<jsonrpc_http_server::Server> {
+    let limiter = Arc::new(RateLimiter::new(config));
+    let max_body_size = limiter.config().max_body_size;
+    let io = rpc_handler(limiter, node);
+
+    // requests accepting a compressed response are handled by the compression middleware
+    let compression = CompressionMiddleware::new(
+        Arc::new(io.clone()),
+        request_meta,
+        max_body_size,
+        CompressionConfig::from_env(),
+    );
+    ServerBuilder::new(io)
+        .threads(5)
+        .max_request_body_size(max_body_size)
+        .request_middleware(compression)
+        .meta_extractor(request_meta)
+        .start_http(addr)
+}
+
+/// Starts the rpc server of `node` on `addr` over TLS, with the certificates of
+/// [`RpcNode::with_tls`]. See [`super::tls`] for the client certificates.
+pub fn start_rpc_node_tls(
+    addr: &SocketAddr,
+    config: RateLimitConfig,
+    node: RpcNode,
+) -> std::io::Result<TlsServer> {
+    let tls = node.tls.clone().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "node has no TLS config")
+    })?;
+    let limiter = Arc::new(RateLimiter::new(config));
+    let max_body_size = limiter.config().max_body_size;
+    let io = rpc_handler(limiter, node);
+    start_tls_server(
+        addr,
+        tls,
+        Arc::new(io),
+        request_meta,
+        max_body_size,
+        CompressionConfig::from_env(),
+    )
+}
+
+/// Decodes the hex tx param of txCommit and txValidate.
+fn decode_tx_hex(hex_tx: &str) -> std::result::Result<transaction::Transaction, JsonRpcError> {
+    // Decode the tx hex string to bytes
+    let tx_bytes = match hex::decode(hex_tx) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            let err = JsonRpcError::invalid_params(format!("Expected a valid hex string, {:?}", e));
             return Err(err);
         }
+    };
+    // reconstruct the tx from bytes, unknown versions are rejected
+    match transaction::Transaction::from_bytes(&tx_bytes) {
+        Ok(t) => Ok(t),
+        Err(e) => {
+            let err = JsonRpcError::invalid_params(format!("Expected a valid Tx, {:?}", e));
+            Err(err)
+        }
+    }
+}
 
-        //let hex_tx = match params.parse::<Vec<String>>() {
-        // Ok(vec) => {
-        //    if vec.is_empty() {
-        //     let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-        //     return Err(err);
-        //   }
-        //    let hex_tx = vec[0].clone();
-        //  if hex_tx.trim().is_empty() {
-        //    let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-        //    return Err(err);
-        //  }
-        //  hex_tx
-        // }
-        // Err(args) => {
-        //  let err =
-        //    JsonRpcError::invalid_params(format!("Expected a hex string, {:?}", args));
-        //  return Err(err);
-        // }
-        // };
-        // Decode the tx hex string to bytes
-        let tx_bytes = match hex::decode(hex_tx) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                let err =
-                    JsonRpcError::invalid_params(format!("Expected a valid hex string, {:?}", e));
-                return Err(err);
-            }
-        };
-        // reconstruct the tx from bytes
-        tx = match bincode::deserialize(&tx_bytes) {
-            Ok(t) => t,
-            Err(e) => {
-                let err = JsonRpcError::invalid_params(format!("Expected a valid Tx, {:?}", e));
+/// Txid param of txStatus and getTxStatusHistory, positional or the
+/// `TransactionStatusId` object sent by the legacy clients.
+fn txid_param(params: Params) -> std::result::Result<String, JsonRpcError> {
+    let txid = match params.clone().parse::<Vec<String>>() {
+        Ok(vec) => vec.first().cloned().unwrap_or_default(),
+        Err(_) => match params.parse::<TransactionStatusId>() {
+            Ok(id) => id.txid,
+            Err(args) => {
+                let err = JsonRpcError::invalid_params(format!("Expected txid, {:?}", args));
                 return Err(err);
             }
-        };
+        },
+    };
+    if txid.trim().is_empty() {
+        let err = JsonRpcError::invalid_params("Expected txid.".to_string());
+        return Err(err);
+    }
+    Ok(txid.trim().to_lowercase())
+}
+
+/// Handler of the rpc methods, behind the batch limits, the rate limiter, the client
+/// certificate policy of a TLS node and the audit log.
+fn rpc_handler(
+    limiter: Arc<RateLimiter>,
+    node: RpcNode,
+) -> MetaIoHandler<
+    Meta,
+    (
+        BatchMiddleware,
+        RateLimitMiddleware,
+        ClientCertMiddleware,
+        AuditMiddleware,
+    ),
+> {
+    // let mut io = IoHandler::default();
+    let admin_limiter = limiter.clone();
+    let restore_limiter = limiter.clone();
+    let audit_limiter = limiter.clone();
+    let compaction_limiter = limiter.clone();
+    let rotation_limiter = limiter.clone();
+    let reload_limiter = limiter.clone();
+    let resume_limiter = limiter.clone();
+    let mut io = MetaIoHandler::with_middleware((
+        BatchMiddleware::new(BatchConfig::from_env()),
+        RateLimitMiddleware::new(limiter),
+        ClientCertMiddleware::new(node.tls.clone()),
+        AuditMiddleware::new(node.audit.clone()),
+    ));
 
-        // check if tx is message type
-        let twilight_address = if tx.tx_type == TransactionType::Message {
-            let address = vector_params[1].clone();
-            if address.trim().is_empty() {
+    let commit_node = node.clone();
+    io.add_method_with_meta("txCommit", move |params: Params, meta: Meta| {
+        let node = commit_node.clone();
+        // txid of the relaying peer, a relayed tx is not relayed again
+        let relayed = meta.metadata.get("relayed").cloned().flatten();
+        async move {
+            let tx: transaction::Transaction;
+            // extract the params vector from the request
+            let vector_params: Vec<String> = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+                        return Err(err);
+                    }
+                    vec
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!(
+                        "Incorrect Parameters: Expected a Vec hex string from client, {:?}",
+                        args
+                    ));
+                    return Err(err);
+                }
+            };
+            // extract the tx hex string
+            let hex_tx = vector_params[0].clone();
+            if hex_tx.trim().is_empty() {
                 let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
                 return Err(err);
             }
-            address
-        } else {
-            "".to_string()
-        };
 
-        println!("{:?}", twilight_address);
+            //let hex_tx = match params.parse::<Vec<String>>() {
+            // Ok(vec) => {
+            //    if vec.is_empty() {
+            //     let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+            //     return Err(err);
+            //   }
+            //    let hex_tx = vec[0].clone();
+            //  if hex_tx.trim().is_empty() {
+            //    let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+            //    return Err(err);
+            //  }
+            //  hex_tx
+            // }
+            // Err(args) => {
+            //  let err =
+            //    JsonRpcError::invalid_params(format!("Expected a hex string, {:?}", args));
+            //  return Err(err);
+            // }
+            // };
+            tx = decode_tx_hex(&hex_tx)?;
+            if let Some(relayed_txid) = &relayed {
+                if *relayed_txid != tx_id(&tx) {
+                    let err = JsonRpcError::invalid_params("Relayed txid mismatch".to_string());
+                    return Err(err);
+                }
+            }
 
-        // verify the inputs from utxo set for the tx
-        let utxo_verified = verify_utxo(tx.clone());
-        if utxo_verified == false {
-            let response_body = "Error: failed to verify utxo".to_string();
-            let response_body = serde_json::Value::String(response_body);
-            Ok(response_body)
-        } else {
-            // verify the tx
-            //let transfer_tx = TransactionData::to_transfer(tx.clone().tx).unwrap();
-            let tx_verified = tx.clone().verify();
-
-            //let tx_verified = verify_transaction(tx.clone());
-            match tx_verified {
-                Ok(()) => {
-                    // get the tx fee from verified tx
-                    let fee = tx.get_tx_fee();
-                    // commit the tx
-                    // check if transaction is Transfer/BurnMessage
-                    match tx.tx_type {
-                        TransactionType::Transfer | TransactionType::Script => {
-                            println!("Transfer Tx / Script tx");
-                            let result = service::tx_commit(tx.clone(), fee).await;
-                            let response: String = match result {
+            // check if tx is message type
+            let twilight_address = if tx.tx_type == TransactionType::Message {
+                let address = vector_params[1].clone();
+                if address.trim().is_empty() {
+                    let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+                    return Err(err);
+                }
+                address
+            } else {
+                "".to_string()
+            };
+
+            println!("{:?}", twilight_address);
+            let txid = tx_id(&tx);
+            node.state.record_tx_status(&txid, TxStatus::Received);
+
+            // the same checks as txValidate, the tx is only committed if it passes them all
+            let report = validate_tx(&tx, &node.state_view());
+            if let Some(err) = report.errors.first() {
+                let status = TxStatus::Failed {
+                    reason: err.clone(),
+                };
+                node.state.record_tx_status(&txid, status);
+                let response_body = serde_json::Value::String(err.clone());
+                return Ok(response_body);
+            }
+            // get the tx fee from verified tx
+            let fee = tx.get_tx_fee();
+            // commit the tx
+            // check if transaction is Transfer/BurnMessage
+            match tx.tx_type {
+                TransactionType::Transfer | TransactionType::Script => {
+                    println!("Transfer Tx / Script tx");
+                    let result = service::tx_commit(&node.state, tx.clone(), fee).await;
+                    let response: String = match result {
+                        Ok(response_body) => {
+                            // in the background, the local result does not depend on the peers
+                            if relayed.is_none() {
+                                node.relay.relay(&report.txid, &hex_tx);
+                            }
+                            response_body
+                        }
+                        Err(err) => err.to_string(),
+                    };
+                    let response_body = serde_json::Value::String(response);
+                    Ok(response_body)
+                }
+                TransactionType::Message => {
+                    println!("Message tx");
+                    let message = match tx.tx.clone() {
+                        TransactionData::Message(message) => message,
+                        _ => {
+                            let err =
+                                JsonRpcError::invalid_params(format!("Expected a valid Message"));
+                            return Err(err);
+                        }
+                    };
+
+                    match message.msg_type {
+                        MessageType::Burn => {
+                            // send the ZkOS burn tx to the Zkos Oracle
+                            let result = service::tx_commit(&node.state, tx.clone(), fee).await;
+                            //match result {
+                            // Ok(_) => {
+                            println!("ZkOS burn tx submitted to Zkos Oracle");
+                            // The ZkOS burn tx was sucessfully submitted.
+                            // Now the Zkos server needs to send the MintorBurnTx after some delay to the oracle
+                            // The oracle will send the MintorBurnTx to the chain
+                            // seleep the process for 5 seconds
+                            //  std::thread::sleep(std::time::Duration::from_secs(5));
+                            // send the MintorBurnTx initialization to the oracle
+                            // let account = message.input.to_quisquis_account().unwrap();
+                            // let result = service::mint_burn_tx_initiate(message.proof.amount,
+                            //   &account, &message.proof.encrypt_scalar, twilight_address).await;
+                            let response_body = match result {
                                 Ok(response_body) => response_body,
                                 Err(err) => err.to_string(),
                             };
-                            let response_body = serde_json::Value::String(response);
-                            Ok(response_body)
-                        }
-                        TransactionType::Message => {
-                            println!("Message tx");
-                            let message = match tx.tx.clone() {
-                                TransactionData::Message(message) => message,
-                                _ => {
-                                    let err = JsonRpcError::invalid_params(format!(
-                                        "Expected a valid Message"
-                                    ));
-                                    return Err(err);
-                                }
-                            };
-
-                            match message.msg_type {
-                                MessageType::Burn => {
-                                    // send the ZkOS burn tx to the Zkos Oracle
-                                    let result = service::tx_commit(tx.clone(), fee).await;
-                                    //match result {
-                                    // Ok(_) => {
-                                    println!("ZkOS burn tx submitted to Zkos Oracle");
-                                    // The ZkOS burn tx was sucessfully submitted.
-                                    // Now the Zkos server needs to send the MintorBurnTx after some delay to the oracle
-                                    // The oracle will send the MintorBurnTx to the chain
-                                    // seleep the process for 5 seconds
-                                    //  std::thread::sleep(std::time::Duration::from_secs(5));
-                                    // send the MintorBurnTx initialization to the oracle
-                                    // let account = message.input.to_quisquis_account().unwrap();
-                                    // let result = service::mint_burn_tx_initiate(message.proof.amount,
-                                    //   &account, &message.proof.encrypt_scalar, twilight_address).await;
-                                    let response_body = match result {
-                                        Ok(response_body) => response_body,
-                                        Err(err) => err.to_string(),
-                                    };
-                                    let response_body = serde_json::Value::String(response_body);
-                                    return Ok(response_body);
-                                    // }
-                                    // Err(err) => {
-                                    // let err = JsonRpcError::invalid_params(format!(
-                                    //  "Burn Message Error: The burn ZkOS tx was not commited properly"
-                                    // ));
-                                    // return Err(err);
-                                    // }
-                                    //}
-                                    // let response_body = serde_json::Value::String(response_body);
-                                    // Ok(response_body)
-                                }
-                                _ => {
-                                    let err = JsonRpcError::invalid_params(format!(
-                                        "Expected a valid Burn Message"
-                                    ));
-                                    return Err(err);
-                                }
-                            }
-                            // let response_body = service::tx_commit(tx.clone()).await;
+                            let response_body = serde_json::Value::String(response_body);
+                            return Ok(response_body);
+                            // }
+                            // Err(err) => {
+                            // let err = JsonRpcError::invalid_params(format!(
+                            //  "Burn Message Error: The burn ZkOS tx was not commited properly"
+                            // ));
+                            // return Err(err);
+                            // }
+                            //}
                             // let response_body = serde_json::Value::String(response_body);
                             // Ok(response_body)
                         }
                         _ => {
                             let err = JsonRpcError::invalid_params(format!(
-                                "Expected a valid Transfer/Burn Message"
+                                "Expected a valid Burn Message"
                             ));
                             return Err(err);
                         }
                     }
+                    // let response_body = service::tx_commit(tx.clone()).await;
+                    // let response_body = serde_json::Value::String(response_body);
+                    // Ok(response_body)
                 }
-                Err(err_msg) => {
-                    let response_body = format!("Verification Error: {}", err_msg);
-                    let response_body = serde_json::Value::String(response_body);
-                    Ok(response_body)
-                }
-            }
-        }
-    });
-
-    io.add_method_with_meta("getUtxos", move |params: Params, _meta: Meta| async move {
-        let address: address::Standard;
-
-        let hex_str = match params.parse::<Vec<String>>() {
-            Ok(vec) => {
-                if vec.is_empty() {
-                    let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-                    return Err(err);
-                }
-                let hex_address = vec[0].clone();
-                if hex_address.trim().is_empty() {
-                    let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+                _ => {
+                    let err = JsonRpcError::invalid_params(format!(
+                        "Expected a valid Transfer/Burn Message"
+                    ));
                     return Err(err);
                 }
-                hex_address
-            }
-            Err(args) => {
-                let err =
-                    JsonRpcError::invalid_params(format!("Expected a hex string, {:?}", args));
-                return Err(err);
-            }
-        };
-        address = match address::Standard::from_hex_with_error(&hex_str) {
-            Ok(addr) => addr,
-            Err(e) => {
-                let err = JsonRpcError::invalid_params(e.to_string());
-                return Err(err);
             }
-        };
-
-        let utxos = search_coin_type_utxo_by_address(address);
-        if utxos.len() > 0 {
-            let response_body = serde_json::to_value(&utxos).expect("Failed to serialize to JSON");
-            Ok(response_body)
-        } else {
-            let result = format!("{{ Error: Utxo not available for provided address}}");
-            let response_body = serde_json::to_value(result).expect("Failed to serialize to JSON");
-            Ok(response_body)
         }
     });
-    io.add_method_with_meta(
-        "getMemoUtxos",
-        move |params: Params, _meta: Meta| async move {
-            let address: address::Standard;
 
-            let hex_str = match params.parse::<Vec<String>>() {
+    let validate_node = node.clone();
+    io.add_method_with_meta("txValidate", move |params: Params, _meta: Meta| {
+        let node = validate_node.clone();
+        async move {
+            let hex_tx = match params.parse::<Vec<String>>() {
                 Ok(vec) => {
-                    if vec.is_empty() {
+                    if vec.is_empty() || vec[0].trim().is_empty() {
                         let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
                         return Err(err);
                     }
-                    let hex_address = vec[0].clone();
-                    if hex_address.trim().is_empty() {
-                        let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-                        return Err(err);
-                    }
-                    hex_address
+                    vec[0].clone()
                 }
                 Err(args) => {
                     let err =
@@ -278,15 +494,29 @@ pub fn rpcserver() {
                     return Err(err);
                 }
             };
-            address = match address::Standard::from_hex_with_error(&hex_str) {
-                Ok(addr) => addr,
-                Err(e) => {
-                    let err = JsonRpcError::invalid_params(e.to_string());
-                    return Err(err);
-                }
-            };
+            let tx = decode_tx_hex(&hex_tx)?;
+            // dry run of txCommit, nothing is queued
+            let report = validate_tx(&tx, &node.state_view());
+            Ok(serde_json::to_value(&report).expect("Failed to serialize to JSON"))
+        }
+    });
 
-            let utxos = search_memo_type_utxo_by_address(address);
+    let utxos_node = node.clone();
+    io.add_method_with_meta("getUtxos", move |params: Params, _meta: Meta| {
+        let node = utxos_node.clone();
+        async move {
+            let (address, raw, state) = address_query(&node, params)?;
+            if !state.is_primary() {
+                return Ok(network_utxos_to_value(&state, &address, IOType::Coin, raw));
+            }
+
+            if raw {
+                return Ok(raw_utxos_to_value(search_raw_utxo_by_address(
+                    address,
+                    IOType::Coin,
+                )));
+            }
+            let utxos = search_coin_type_utxo_by_address(address);
             if utxos.len() > 0 {
                 let response_body =
                     serde_json::to_value(&utxos).expect("Failed to serialize to JSON");
@@ -297,40 +527,51 @@ pub fn rpcserver() {
                     serde_json::to_value(result).expect("Failed to serialize to JSON");
                 Ok(response_body)
             }
-        },
-    );
-    io.add_method_with_meta(
-        "getStateUtxos",
-        move |params: Params, _meta: Meta| async move {
-            let address: address::Standard;
+        }
+    });
+    let memo_utxos_node = node.clone();
+    io.add_method_with_meta("getMemoUtxos", move |params: Params, _meta: Meta| {
+        let node = memo_utxos_node.clone();
+        async move {
+            let (address, raw, state) = address_query(&node, params)?;
+            if !state.is_primary() {
+                return Ok(network_utxos_to_value(&state, &address, IOType::Memo, raw));
+            }
 
-            let hex_str = match params.parse::<Vec<String>>() {
-                Ok(vec) => {
-                    if vec.is_empty() {
-                        let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-                        return Err(err);
-                    }
-                    let hex_address = vec[0].clone();
-                    if hex_address.trim().is_empty() {
-                        let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
-                        return Err(err);
-                    }
-                    hex_address
-                }
-                Err(args) => {
-                    let err =
-                        JsonRpcError::invalid_params(format!("Expected a hex string, {:?}", args));
-                    return Err(err);
-                }
-            };
-            address = match address::Standard::from_hex_with_error(&hex_str) {
-                Ok(addr) => addr,
-                Err(e) => {
-                    let err = JsonRpcError::invalid_params(e.to_string());
-                    return Err(err);
-                }
-            };
+            if raw {
+                return Ok(raw_utxos_to_value(search_raw_utxo_by_address(
+                    address,
+                    IOType::Memo,
+                )));
+            }
+            let utxos = search_memo_type_utxo_by_address(address);
+            if utxos.len() > 0 {
+                let response_body =
+                    serde_json::to_value(&utxos).expect("Failed to serialize to JSON");
+                Ok(response_body)
+            } else {
+                let result = format!("{{ Error: Utxo not available for provided address}}");
+                let response_body =
+                    serde_json::to_value(result).expect("Failed to serialize to JSON");
+                Ok(response_body)
+            }
+        }
+    });
+    let state_utxos_node = node.clone();
+    io.add_method_with_meta("getStateUtxos", move |params: Params, _meta: Meta| {
+        let node = state_utxos_node.clone();
+        async move {
+            let (address, raw, state) = address_query(&node, params)?;
+            if !state.is_primary() {
+                return Ok(network_utxos_to_value(&state, &address, IOType::State, raw));
+            }
 
+            if raw {
+                return Ok(raw_utxos_to_value(search_raw_utxo_by_address(
+                    address,
+                    IOType::State,
+                )));
+            }
             let utxos = search_state_type_utxo_by_address(address);
             if utxos.len() > 0 {
                 let response_body =
@@ -342,18 +583,50 @@ pub fn rpcserver() {
                     serde_json::to_value(result).expect("Failed to serialize to JSON");
                 Ok(response_body)
             }
-        },
-    );
+        }
+    });
 
     io.add_method_with_meta("allUtxos", move |params: Params, _meta: Meta| async move {
-        let utxos: Vec<String> = all_coin_type_utxo();
-        if utxos.len() > 0 {
-            let response_body = serde_json::to_value(&utxos).expect("Failed to serialize to JSON");
-            Ok(response_body)
-        } else {
-            let result = format!("{{ Error: UTXO do not exist for this type}}");
-            let response_body = serde_json::to_value(result).expect("Failed to serialize to JSON");
-            Ok(response_body)
+        let query: AllUtxosQuery = match params {
+            Params::None => AllUtxosQuery::default(),
+            params => match params.parse::<AllUtxosQuery>() {
+                Ok(query) => query,
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                    return Err(err);
+                }
+            },
+        };
+
+        // the whole partition is only returned for small stores
+        if query.unbounded {
+            // the count and the utxos are read from the same view
+            let view = utxo_read_view();
+            let count = view.count(query.io_type.to_usize());
+            if count > MAX_UNBOUNDED_UTXO_COUNT {
+                let err = JsonRpcError::invalid_params(format!(
+                    "Unbounded request not allowed, utxo count {} exceeds {}",
+                    count, MAX_UNBOUNDED_UTXO_COUNT
+                ));
+                return Err(err);
+            }
+            let utxos = all_type_utxo(&view, query.io_type);
+            if utxos.len() > 0 {
+                let response_body =
+                    serde_json::to_value(&utxos).expect("Failed to serialize to JSON");
+                return Ok(response_body);
+            } else {
+                let result = format!("{{ Error: UTXO do not exist for this type}}");
+                let response_body =
+                    serde_json::to_value(result).expect("Failed to serialize to JSON");
+                return Ok(response_body);
+            }
+        }
+
+        match all_type_utxo_chunk(&query) {
+            Ok(chunk) => Ok(serde_json::to_value(&chunk).expect("Failed to serialize to JSON")),
+            Err(e) => Err(JsonRpcError::invalid_params(e.to_string())),
         }
     });
     io.add_method_with_meta(
@@ -406,7 +679,7 @@ pub fn rpcserver() {
     );
 
     io.add_method_with_meta("getOutput", move |params: Params, _meta: Meta| async move {
-        let hex_str = match params.parse::<Vec<String>>() {
+        let (hex_str, compact, raw) = match params.parse::<Vec<String>>() {
             Ok(vec) => {
                 if vec.is_empty() {
                     let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
@@ -417,7 +690,9 @@ pub fn rpcserver() {
                     let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
                     return Err(err);
                 }
-                hex_utxo
+                let compact = vec.get(1).map_or(false, |format| format == "compact");
+                let raw = vec.get(1).map_or(false, |format| format == "raw");
+                (hex_utxo, compact, raw)
             }
             Err(args) => {
                 let err =
@@ -425,22 +700,24 @@ pub fn rpcserver() {
                 return Err(err);
             }
         };
-        let utxo = match hex::decode(hex_str) {
-            Ok(bytes) => match Utxo::from_bytes(&bytes) {
-                Some(utxo) => utxo,
-                None => {
-                    let err = JsonRpcError::invalid_params(format!("invalid Hex"));
-                    return Err(err);
-                }
-            },
+        // <txid_hex>:<output_index>, or the hex of the encoded utxo
+        let utxo = match hex_str.parse::<Utxo>() {
+            Ok(utxo) => utxo,
             Err(args) => {
-                let err = JsonRpcError::invalid_params(format!("invalid Hex, {:?}", args));
+                let err = JsonRpcError::invalid_params(format!("invalid utxo, {}", args));
                 return Err(err);
             }
         };
 
+        if raw {
+            let response_body = match search_raw_output_by_utxo_key(utxo, IOType::Coin) {
+                Ok(output_hex) => serde_json::to_value(&output_hex),
+                Err(err) => serde_json::to_value(&err),
+            };
+            return Ok(response_body.expect("Failed to serialize to JSON"));
+        }
         let response_body = match search_coin_type_utxo_by_utxo_key(utxo) {
-            Ok(output) => serde_json::to_value(&output).expect("Failed to serialize to JSON"),
+            Ok(output) => output_to_value(&output, compact),
             Err(err) => serde_json::to_value(&err).expect("Failed to serialize to JSON"),
         };
 
@@ -450,7 +727,7 @@ pub fn rpcserver() {
     io.add_method_with_meta(
         "getMemoOutput",
         move |params: Params, _meta: Meta| async move {
-            let hex_str = match params.parse::<Vec<String>>() {
+            let (hex_str, compact, raw) = match params.parse::<Vec<String>>() {
                 Ok(vec) => {
                     if vec.is_empty() {
                         let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
@@ -461,7 +738,9 @@ pub fn rpcserver() {
                         let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
                         return Err(err);
                     }
-                    hex_utxo
+                    let compact = vec.get(1).map_or(false, |format| format == "compact");
+                    let raw = vec.get(1).map_or(false, |format| format == "raw");
+                    (hex_utxo, compact, raw)
                 }
                 Err(args) => {
                     let err =
@@ -469,22 +748,24 @@ pub fn rpcserver() {
                     return Err(err);
                 }
             };
-            let utxo = match hex::decode(hex_str) {
-                Ok(bytes) => match Utxo::from_bytes(&bytes) {
-                    Some(utxo) => utxo,
-                    None => {
-                        let err = JsonRpcError::invalid_params(format!("invalid Hex"));
-                        return Err(err);
-                    }
-                },
+            // <txid_hex>:<output_index>, or the hex of the encoded utxo
+            let utxo = match hex_str.parse::<Utxo>() {
+                Ok(utxo) => utxo,
                 Err(args) => {
-                    let err = JsonRpcError::invalid_params(format!("invalid Hex, {:?}", args));
+                    let err = JsonRpcError::invalid_params(format!("invalid utxo, {}", args));
                     return Err(err);
                 }
             };
 
+            if raw {
+                let response_body = match search_raw_output_by_utxo_key(utxo, IOType::Memo) {
+                    Ok(output_hex) => serde_json::to_value(&output_hex),
+                    Err(err) => serde_json::to_value(&err),
+                };
+                return Ok(response_body.expect("Failed to serialize to JSON"));
+            }
             let response_body = match search_memo_type_utxo_by_utxo_key(utxo) {
-                Ok(output) => serde_json::to_value(&output).expect("Failed to serialize to JSON"),
+                Ok(output) => output_to_value(&output, compact),
                 Err(err) => serde_json::to_value(&err).expect("Failed to serialize to JSON"),
             };
 
@@ -495,7 +776,7 @@ pub fn rpcserver() {
     io.add_method_with_meta(
         "getStateOutput",
         move |params: Params, _meta: Meta| async move {
-            let hex_str = match params.parse::<Vec<String>>() {
+            let (hex_str, compact, raw) = match params.parse::<Vec<String>>() {
                 Ok(vec) => {
                     if vec.is_empty() {
                         let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
@@ -506,7 +787,9 @@ pub fn rpcserver() {
                         let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
                         return Err(err);
                     }
-                    hex_utxo
+                    let compact = vec.get(1).map_or(false, |format| format == "compact");
+                    let raw = vec.get(1).map_or(false, |format| format == "raw");
+                    (hex_utxo, compact, raw)
                 }
                 Err(args) => {
                     let err =
@@ -514,22 +797,24 @@ pub fn rpcserver() {
                     return Err(err);
                 }
             };
-            let utxo = match hex::decode(hex_str) {
-                Ok(bytes) => match Utxo::from_bytes(&bytes) {
-                    Some(utxo) => utxo,
-                    None => {
-                        let err = JsonRpcError::invalid_params(format!("invalid Hex"));
-                        return Err(err);
-                    }
-                },
+            // <txid_hex>:<output_index>, or the hex of the encoded utxo
+            let utxo = match hex_str.parse::<Utxo>() {
+                Ok(utxo) => utxo,
                 Err(args) => {
-                    let err = JsonRpcError::invalid_params(format!("invalid Hex, {:?}", args));
+                    let err = JsonRpcError::invalid_params(format!("invalid utxo, {}", args));
                     return Err(err);
                 }
             };
 
+            if raw {
+                let response_body = match search_raw_output_by_utxo_key(utxo, IOType::State) {
+                    Ok(output_hex) => serde_json::to_value(&output_hex),
+                    Err(err) => serde_json::to_value(&err),
+                };
+                return Ok(response_body.expect("Failed to serialize to JSON"));
+            }
             let response_body = match search_state_type_utxo_by_utxo_key(utxo) {
-                Ok(output) => serde_json::to_value(&output).expect("Failed to serialize to JSON"),
+                Ok(output) => output_to_value(&output, compact),
                 Err(err) => serde_json::to_value(&err).expect("Failed to serialize to JSON"),
             };
 
@@ -554,6 +839,15 @@ pub fn rpcserver() {
                                 &UtxoHexEncodedResult::encode_to_hex(value.result),
                             )
                             .unwrap()),
+                            // the sql query queue is full, the client should retry
+                            Err(args) if args.kind() == std::io::ErrorKind::WouldBlock => {
+                                let err = JsonRpcError {
+                                    code: ErrorCode::ServerError(-32005),
+                                    message: format!("Server busy, retry later: {}", args),
+                                    data: None,
+                                };
+                                Err(err)
+                            }
                             Err(args) => {
                                 let err =
                                     JsonRpcError::invalid_params(format!("Error: , {:?}", args));
@@ -577,25 +871,699 @@ pub fn rpcserver() {
     );
 
     io.add_method_with_meta(
-        "TestCommand",
+        "getUtxosDetailed",
         move |params: Params, _meta: Meta| async move {
-            match params.parse::<TestCommand>() {
-                Ok(queryparams) => match queryparams.test_command {
-                    TestCommandString::TakeSnapshotintoLevelDB => {
-                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-                        let _res = utxo_storage.take_snapshot();
-                        Ok(serde_json::to_value("".to_string()).unwrap())
-                    }
-                    TestCommandString::LoadBackupFromLevelDB => {
-                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-                        let _ = utxo_storage.load_from_snapshot();
-                        Ok(serde_json::to_value("".to_string()).unwrap())
-                    }
-                    TestCommandString::TakeSnapshotintoPostgreSQL => {
-                        utxo_in_memory::db::takesnapshotfrom_memory_to_postgresql_bulk();
-                        Ok(serde_json::to_value("".to_string()).unwrap())
-                    }
-                    TestCommandString::UtxoCoinDbLength => {
+            let query = match params.parse::<UtxoDetailedQuery>() {
+                Ok(query) => query,
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                    return Err(err);
+                }
+            };
+            if query.limit < 1 || query.limit > UTXO_DETAILED_MAX_LIMIT || query.offset < 0 {
+                let err = JsonRpcError::invalid_params(format!(
+                    "Invalid parameters, limit must be between 1 and {} and offset not negative",
+                    UTXO_DETAILED_MAX_LIMIT
+                ));
+                return Err(err);
+            }
+            match get_utxos_detailed(query) {
+                Ok(result) => Ok(serde_json::to_value(&result).unwrap()),
+                // the sql query queue is full, the client should retry
+                Err(UtxosetError::IOError(args))
+                    if args.kind() == std::io::ErrorKind::WouldBlock =>
+                {
+                    let err = JsonRpcError {
+                        code: ErrorCode::ServerError(-32005),
+                        message: format!("Server busy, retry later: {}", args),
+                        data: None,
+                    };
+                    Err(err)
+                }
+                Err(args) => {
+                    let err = JsonRpcError {
+                        code: ErrorCode::InternalError,
+                        message: format!("Database query failed: {}", args),
+                        data: None,
+                    };
+                    Err(err)
+                }
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "getStorageStats",
+        move |_params: Params, _meta: Meta| async move {
+            // read from the size counters of the partitions, the utxo set is not scanned
+            let stats = UTXO_STORAGE.lock().unwrap().stats();
+            Ok(serde_json::to_value(&stats).expect("Failed to serialize to JSON"))
+        },
+    );
+
+    io.add_method_with_meta(
+        "auditUtxoStore",
+        move |_params: Params, _meta: Meta| async move {
+            // the audit reads the whole utxo set, run it off the rpc workers
+            let (sender, receiver) = futures::channel::oneshot::channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(compare_memory_to_psql());
+            });
+            match receiver.await {
+                // "consistent" is false if memory and PostgreSQL diverge
+                Ok(Ok(report)) => Ok(serde_json::to_value(&report).unwrap()),
+                Ok(Err(args)) => {
+                    let err = JsonRpcError {
+                        code: ErrorCode::InternalError,
+                        message: format!("Utxo audit failed: {}", args),
+                        data: None,
+                    };
+                    Err(err)
+                }
+                Err(_) => Err(JsonRpcError::internal_error()),
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "simulateTransfer",
+        move |params: Params, _meta: Meta| async move {
+            let query = match params.parse::<SimulateTransferQuery>() {
+                Ok(query) => query,
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                    return Err(err);
+                }
+            };
+            if query.inputs.is_empty() {
+                let err = JsonRpcError::invalid_params("Expected at least one input.".to_string());
+                return Err(err);
+            }
+            // cheap checks only, no proof is needed
+            let report = simulate_transfer(&query.inputs);
+            Ok(serde_json::to_value(&report).expect("Failed to serialize to JSON"))
+        },
+    );
+
+    let mempool_node = node.clone();
+    io.add_method_with_meta("getMempool", move |_params: Params, _meta: Meta| {
+        let state = mempool_node.state.clone();
+        async move {
+            let mut mempool = state.mempool.lock().unwrap();
+            let now = std::time::Instant::now();
+            mempool.prune(now);
+            Ok(serde_json::to_value(&mempool.view(now)).expect("Failed to serialize to JSON"))
+        }
+    });
+
+    let peers_node = node.clone();
+    io.add_method_with_meta("getPeers", move |_params: Params, _meta: Meta| {
+        let peers = peers_node.relay.peers();
+        async move { Ok(serde_json::to_value(&peers).expect("Failed to serialize to JSON")) }
+    });
+
+    let status_node = node.clone();
+    io.add_method_with_meta("txStatus", move |params: Params, _meta: Meta| {
+        let state = status_node.state.clone();
+        async move {
+            // the tagged status, with the plain status string of the legacy clients
+            let txid = txid_param(params)?;
+            let status = state.tx_status(&txid);
+            Ok(serde_json::to_value(&status).expect("Failed to serialize to JSON"))
+        }
+    });
+
+    let history_node = node.clone();
+    io.add_method_with_meta("getTxStatusHistory", move |params: Params, _meta: Meta| {
+        let state = history_node.state.clone();
+        async move {
+            let txid = txid_param(params)?;
+            let history = state.tx_status_history(&txid);
+            Ok(serde_json::to_value(&history).expect("Failed to serialize to JSON"))
+        }
+    });
+
+    io.add_method_with_meta(
+        "evalScript",
+        move |params: Params, _meta: Meta| async move {
+            let query = match params.parse::<EvalScriptQuery>() {
+                Ok(query) => query,
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                    return Err(err);
+                }
+            };
+            // read only, no proof or signature is needed and nothing is committed
+            match eval_script(&query) {
+                Ok(result) => {
+                    Ok(serde_json::to_value(&result).expect("Failed to serialize to JSON"))
+                }
+                Err(e) => {
+                    let err = JsonRpcError::invalid_params(e);
+                    Err(err)
+                }
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "getBlockTxRoot",
+        move |params: Params, _meta: Meta| async move {
+            let height: u64 = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected block height.".to_string());
+                        return Err(err);
+                    }
+                    match vec[0].trim().parse::<u64>() {
+                        Ok(height) => height,
+                        Err(args) => {
+                            let err = JsonRpcError::invalid_params(format!(
+                                "Invalid block height, {:?}",
+                                args
+                            ));
+                            return Err(err);
+                        }
+                    }
+                }
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Expected block height, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            let block_tx_roots = BLOCK_TX_ROOTS.lock().unwrap();
+            let response_body = match block_tx_roots.get_root(height) {
+                Some(block_tx_root) => {
+                    serde_json::to_value(block_tx_root).expect("Failed to serialize to JSON")
+                }
+                None => {
+                    let result = format!("{{ Error: Tx root not available for provided height}}");
+                    serde_json::to_value(result).expect("Failed to serialize to JSON")
+                }
+            };
+            Ok(response_body)
+        },
+    );
+
+    io.add_method_with_meta(
+        "getTxInclusionProof",
+        move |params: Params, _meta: Meta| async move {
+            let hex_str = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+                        return Err(err);
+                    }
+                    let hex_txid = vec[0].clone();
+                    if hex_txid.trim().is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+                        return Err(err);
+                    }
+                    hex_txid
+                }
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Expected a hex string, {:?}", args));
+                    return Err(err);
+                }
+            };
+            let txid: [u8; 32] = match hex::decode(hex_str) {
+                Ok(bytes) => match bytes.try_into() {
+                    Ok(txid) => txid,
+                    Err(_) => {
+                        let err = JsonRpcError::invalid_params(format!("invalid txid length"));
+                        return Err(err);
+                    }
+                },
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!("invalid Hex, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            let block_tx_roots = BLOCK_TX_ROOTS.lock().unwrap();
+            let response_body = match block_tx_roots.inclusion_proof(&txid) {
+                Some(proof) => serde_json::to_value(&proof).expect("Failed to serialize to JSON"),
+                None => {
+                    let result = format!("{{ Error: Tx not available in recent blocks}}");
+                    serde_json::to_value(result).expect("Failed to serialize to JSON")
+                }
+            };
+            Ok(response_body)
+        },
+    );
+
+    io.add_method_with_meta(
+        "getBlockFees",
+        move |params: Params, _meta: Meta| async move {
+            let height: u64 = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected block height.".to_string());
+                        return Err(err);
+                    }
+                    match vec[0].trim().parse::<u64>() {
+                        Ok(height) => height,
+                        Err(args) => {
+                            let err = JsonRpcError::invalid_params(format!(
+                                "Invalid block height, {:?}",
+                                args
+                            ));
+                            return Err(err);
+                        }
+                    }
+                }
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Expected block height, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            let fee_store = BLOCK_FEES.lock().unwrap();
+            let response_body = match fee_store.get(height) {
+                Some(block_fees) => {
+                    serde_json::to_value(block_fees).expect("Failed to serialize to JSON")
+                }
+                None => {
+                    let result = format!("{{ Error: Fees not available for provided height}}");
+                    serde_json::to_value(result).expect("Failed to serialize to JSON")
+                }
+            };
+            Ok(response_body)
+        },
+    );
+
+    io.add_method_with_meta(
+        "getBlockApplyResult",
+        move |params: Params, _meta: Meta| async move {
+            let height: u64 = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected block height.".to_string());
+                        return Err(err);
+                    }
+                    match vec[0].trim().parse::<u64>() {
+                        Ok(height) => height,
+                        Err(args) => {
+                            let err = JsonRpcError::invalid_params(format!(
+                                "Invalid block height, {:?}",
+                                args
+                            ));
+                            return Err(err);
+                        }
+                    }
+                }
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Expected block height, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            // the latest blocks are served from memory, older ones from psql
+            match get_block_apply_result(height) {
+                Some(result) => {
+                    Ok(serde_json::to_value(result).expect("Failed to serialize to JSON"))
+                }
+                None => Err(JsonRpcError::invalid_params(
+                    "Result not available for provided height.".to_string(),
+                )),
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "getFeePoolStats",
+        move |params: Params, _meta: Meta| async move {
+            let (from_height, to_height): (u64, u64) = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.len() < 2 {
+                        let err = JsonRpcError::invalid_params(
+                            "Expected from and to block heights.".to_string(),
+                        );
+                        return Err(err);
+                    }
+                    match (vec[0].trim().parse::<u64>(), vec[1].trim().parse::<u64>()) {
+                        (Ok(from), Ok(to)) => (from, to),
+                        _ => {
+                            let err =
+                                JsonRpcError::invalid_params("Invalid block height.".to_string());
+                            return Err(err);
+                        }
+                    }
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!(
+                        "Expected from and to block heights, {:?}",
+                        args
+                    ));
+                    return Err(err);
+                }
+            };
+
+            let fee_store = BLOCK_FEES.lock().unwrap();
+            match fee_store.stats(from_height, to_height) {
+                Ok(stats) => Ok(serde_json::to_value(stats).expect("Failed to serialize to JSON")),
+                Err(args) => Err(JsonRpcError::invalid_params(args.to_string())),
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "getBurnHistory",
+        move |params: Params, _meta: Meta| async move {
+            let address: String = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected address.".to_string());
+                        return Err(err);
+                    }
+                    vec[0].trim().to_string()
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!("Expected address, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            let burn_history = BURN_HISTORY.lock().unwrap();
+            let burns = burn_history.history(&address);
+            Ok(serde_json::to_value(burns).expect("Failed to serialize to JSON"))
+        },
+    );
+
+    let indexed_balance_node = node.clone();
+    io.add_method_with_meta("getIndexedBalance", move |params: Params, _meta: Meta| {
+        let node = indexed_balance_node.clone();
+        async move {
+            let address: String = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected address.".to_string());
+                        return Err(err);
+                    }
+                    vec[0].trim().to_string()
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!("Expected address, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            // cached by the balance indexer of the network of the address, only the
+            // addresses of its key file are indexed
+            let state = node
+                .networks
+                .state(address_network(&address).ok())
+                .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+            let indexer = state.balance_indexer.lock().unwrap();
+            match indexer.balance(&address) {
+                Some(balance) => {
+                    Ok(serde_json::to_value(balance).expect("Failed to serialize to JSON"))
+                }
+                None => Err(JsonRpcError::invalid_params(
+                    "Address is not indexed.".to_string(),
+                )),
+            }
+        }
+    });
+
+    io.add_method_with_meta(
+        "getFailedTxs",
+        move |params: Params, _meta: Meta| async move {
+            let (from_height, to_height): (u64, u64) = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.len() < 2 {
+                        let err = JsonRpcError::invalid_params(
+                            "Expected from and to block heights.".to_string(),
+                        );
+                        return Err(err);
+                    }
+                    match (vec[0].trim().parse::<u64>(), vec[1].trim().parse::<u64>()) {
+                        (Ok(from), Ok(to)) => (from, to),
+                        _ => {
+                            let err =
+                                JsonRpcError::invalid_params("Invalid block height.".to_string());
+                            return Err(err);
+                        }
+                    }
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!(
+                        "Expected from and to block heights, {:?}",
+                        args
+                    ));
+                    return Err(err);
+                }
+            };
+
+            let failed_store = FAILED_TXS.lock().unwrap();
+            match failed_store.range(from_height, to_height) {
+                Ok(failed) => Ok(serde_json::to_value(failed).expect("Failed to serialize to JSON")),
+                Err(args) => Err(JsonRpcError::invalid_params(args.to_string())),
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "reprocessFailedTx",
+        move |params: Params, _meta: Meta| async move {
+            let tx_id: String = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err = JsonRpcError::invalid_params("Expected txid.".to_string());
+                        return Err(err);
+                    }
+                    vec[0].trim().to_string()
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!("Expected txid, {:?}", args));
+                    return Err(err);
+                }
+            };
+
+            // the outcome is recorded in the failed tx, resolved if the tx is applied
+            match reprocess_failed_tx(&tx_id) {
+                Ok(failed_tx) => {
+                    Ok(serde_json::to_value(failed_tx).expect("Failed to serialize to JSON"))
+                }
+                Err(args) => Err(JsonRpcError::invalid_params(args.to_string())),
+            }
+        },
+    );
+
+    let address_txs_node = node.clone();
+    io.add_method_with_meta(
+        "getTransactionsByAddress",
+        move |params: Params, _meta: Meta| {
+            let node = address_txs_node.clone();
+            async move {
+                // address, page and network, the primary network if missing
+                let (address, page, network) = match params.parse::<Vec<String>>() {
+                    Ok(vec) => {
+                        if vec.is_empty() {
+                            let err = JsonRpcError::invalid_params("Expected address.".to_string());
+                            return Err(err);
+                        }
+                        let page = match vec.get(1) {
+                            Some(page) => match page.trim().parse::<usize>() {
+                                Ok(page) => page,
+                                Err(_) => {
+                                    let err =
+                                        JsonRpcError::invalid_params("Invalid page.".to_string());
+                                    return Err(err);
+                                }
+                            },
+                            None => 0,
+                        };
+                        let network = match vec.get(2) {
+                            Some(network) => match network.parse::<Network>() {
+                                Ok(network) => Some(network),
+                                Err(e) => {
+                                    let err = JsonRpcError::invalid_params(format!(
+                                        "Invalid network {:?}, {}",
+                                        network, e
+                                    ));
+                                    return Err(err);
+                                }
+                            },
+                            None => None,
+                        };
+                        (vec[0].trim().to_string(), page, network)
+                    }
+                    Err(args) => {
+                        let err =
+                            JsonRpcError::invalid_params(format!("Expected address, {:?}", args));
+                        return Err(err);
+                    }
+                };
+
+                let state = node
+                    .networks
+                    .state_for_address(network, &address)
+                    .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+                if !state.is_primary() {
+                    // the history of a secondary network is in its store and index only
+                    let txs = match state.store().get_address_txs(&address, page) {
+                        Ok(txs) => txs,
+                        Err(_) => state.address_txs.lock().unwrap().page(&address, page),
+                    };
+                    return Ok(serde_json::to_value(txs).expect("Failed to serialize to JSON"));
+                }
+
+                // psql holds the full history, the in memory index only the most recent txs
+                let txs = match persistence_store().get_address_txs(&address, page) {
+                    Ok(txs) => txs,
+                    Err(_) => ADDRESS_TXS.lock().unwrap().page(&address, page),
+                };
+                Ok(serde_json::to_value(txs).expect("Failed to serialize to JSON"))
+            }
+        },
+    );
+
+    let utxo_history_node = node.clone();
+    io.add_method_with_meta("getUtxoHistory", move |params: Params, _meta: Meta| {
+        let node = utxo_history_node.clone();
+        async move {
+            // address, from and to block heights and network, the primary network if missing
+            let (address, from_height, to_height, network) = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.len() < 3 {
+                        let err = JsonRpcError::invalid_params(
+                            "Expected address, from and to block heights.".to_string(),
+                        );
+                        return Err(err);
+                    }
+                    let (from, to) =
+                        match (vec[1].trim().parse::<u64>(), vec[2].trim().parse::<u64>()) {
+                            (Ok(from), Ok(to)) => (from, to),
+                            _ => {
+                                let err = JsonRpcError::invalid_params(
+                                    "Invalid block height.".to_string(),
+                                );
+                                return Err(err);
+                            }
+                        };
+                    let network = match vec.get(3) {
+                        Some(network) => match network.parse::<Network>() {
+                            Ok(network) => Some(network),
+                            Err(e) => {
+                                let err = JsonRpcError::invalid_params(format!(
+                                    "Invalid network {:?}, {}",
+                                    network, e
+                                ));
+                                return Err(err);
+                            }
+                        },
+                        None => None,
+                    };
+                    (vec[0].trim().to_string(), from, to, network)
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!(
+                        "Expected address, from and to block heights, {:?}",
+                        args
+                    ));
+                    return Err(err);
+                }
+            };
+
+            let state = node
+                .networks
+                .state_for_address(network, &address)
+                .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+            // txs of the address without a utxo of it, e.g. a transfer to a fresh account
+            let txids = state
+                .address_txs
+                .lock()
+                .unwrap()
+                .txids(&address, from_height, to_height);
+            // the events are only retained in memory, see getTransactionsByAddress for older txs
+            let subscriptions = state.subscriptions.lock().unwrap();
+            match subscriptions.history(&address, &txids, from_height, to_height) {
+                Ok(events) => {
+                    Ok(serde_json::to_value(events).expect("Failed to serialize to JSON"))
+                }
+                Err(args) => Err(JsonRpcError::invalid_params(args.to_string())),
+            }
+        }
+    });
+
+    let block_stats_node = node.clone();
+    io.add_method_with_meta(
+        "getBlockProcessingStats",
+        move |params: Params, _meta: Meta| {
+            let node = block_stats_node.clone();
+            async move {
+                // number of most recent blocks, all retained blocks if missing, and network,
+                // the primary network if missing
+                let (count, network) = match params.parse::<Vec<String>>() {
+                    Ok(vec) => {
+                        let count = match vec.get(0) {
+                            Some(count) => match count.trim().parse::<usize>() {
+                                Ok(count) => count.min(BLOCK_STATS_RETENTION),
+                                Err(_) => {
+                                    let err =
+                                        JsonRpcError::invalid_params("Invalid count.".to_string());
+                                    return Err(err);
+                                }
+                            },
+                            None => BLOCK_STATS_RETENTION,
+                        };
+                        let network = match vec.get(1) {
+                            Some(network) => match network.parse::<Network>() {
+                                Ok(network) => Some(network),
+                                Err(e) => {
+                                    let err = JsonRpcError::invalid_params(format!(
+                                        "Invalid network {:?}, {}",
+                                        network, e
+                                    ));
+                                    return Err(err);
+                                }
+                            },
+                            None => None,
+                        };
+                        (count, network)
+                    }
+                    Err(_) => (BLOCK_STATS_RETENTION, None),
+                };
+
+                let state = node
+                    .networks
+                    .state(network)
+                    .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+                let stats = state.block_stats.lock().unwrap().latest(count);
+                Ok(serde_json::to_value(stats).expect("Failed to serialize to JSON"))
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "TestCommand",
+        move |params: Params, _meta: Meta| async move {
+            match params.parse::<TestCommand>() {
+                Ok(queryparams) => match queryparams.test_command {
+                    TestCommandString::TakeSnapshotintoLevelDB => {
+                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+                        let _res = utxo_storage.take_snapshot();
+                        Ok(serde_json::to_value("".to_string()).unwrap())
+                    }
+                    TestCommandString::LoadBackupFromLevelDB => {
+                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+                        let _ = utxo_storage.load_from_snapshot();
+                        Ok(serde_json::to_value("".to_string()).unwrap())
+                    }
+                    TestCommandString::TakeSnapshotintoPostgreSQL => {
+                        utxo_in_memory::db::takesnapshotfrom_memory_to_postgresql_bulk();
+                        Ok(serde_json::to_value("".to_string()).unwrap())
+                    }
+                    TestCommandString::UtxoCoinDbLength => {
                         let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
                         let mut length_count = Vec::new();
                         for (i, v) in utxo_storage.data.get_mut(&0).unwrap().iter() {
@@ -609,72 +1577,1693 @@ pub fn rpcserver() {
 
                         Ok(serde_json::to_value("".to_string()).unwrap())
                     }
-                    TestCommandString::UtxoMemoDbLength => {
-                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-                        let mut length_count = Vec::new();
-                        for (i, v) in utxo_storage.data.get_mut(&1).unwrap().iter() {
-                            length_count.push(v);
-                        }
-                        println!(
-                            "State length : {}",
-                            // utxo_storage.data.get_mut(&2).unwrap().len()
-                            length_count.len()
-                        );
-                        Ok(serde_json::to_value("".to_string()).unwrap())
+                    TestCommandString::UtxoMemoDbLength => {
+                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+                        let mut length_count = Vec::new();
+                        for (i, v) in utxo_storage.data.get_mut(&1).unwrap().iter() {
+                            length_count.push(v);
+                        }
+                        println!(
+                            "State length : {}",
+                            // utxo_storage.data.get_mut(&2).unwrap().len()
+                            length_count.len()
+                        );
+                        Ok(serde_json::to_value("".to_string()).unwrap())
+                    }
+                    TestCommandString::UtxoStateDbLength => {
+                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+                        let mut length_count = Vec::new();
+                        for (i, v) in utxo_storage.data.get_mut(&2).unwrap().iter() {
+                            length_count.push(v);
+                        }
+                        println!(
+                            "State length : {}",
+                            // utxo_storage.data.get_mut(&2).unwrap().len()
+                            length_count.len()
+                        );
+                        Ok(serde_json::to_value("".to_string()).unwrap())
+                    }
+                    _ => {
+                        let err = JsonRpcError::invalid_params(format!(
+                            "Invalid parameters, enum not exist"
+                        ));
+                        Err(err)
+                    }
+                },
+                Err(args) => {
+                    let err =
+                        JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                    Err(err)
+                }
+            }
+        },
+    );
+
+    io.add_method_with_meta("decodeTx", move |params: Params, _meta: Meta| async move {
+        // params: [tx bytes, encoding ("hex" | "base64", default "hex")]
+        let (data, encoding) = match params.parse::<Vec<String>>() {
+            Ok(vec) => {
+                if vec.is_empty() || vec[0].trim().is_empty() {
+                    let err = JsonRpcError::invalid_params("Expected tx bytes.".to_string());
+                    return Err(err);
+                }
+                let encoding = vec.get(1).cloned().unwrap_or_else(|| "hex".to_string());
+                (vec[0].trim().to_string(), encoding)
+            }
+            Err(args) => {
+                let err = JsonRpcError::invalid_params(format!("Expected tx bytes, {:?}", args));
+                return Err(err);
+            }
+        };
+        let tx_bytes = match encoding.as_str() {
+            "hex" => hex::decode(&data).map_err(|e| format!("{:?}", e)),
+            "base64" => base64::decode(&data).map_err(|e| format!("{:?}", e)),
+            _ => Err(format!("unknown encoding {}", encoding)),
+        };
+        let tx_bytes = match tx_bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let err = JsonRpcError::invalid_params(format!("Invalid tx bytes, {}", e));
+                return Err(err);
+            }
+        };
+        // decoding only, the tx is not verified
+        match decode_transaction_view(&tx_bytes) {
+            Ok(mut view) => {
+                let registry = SCRIPT_REGISTRY.lock().unwrap();
+                view.label_script_addresses(|address| registry.name(address));
+                drop(registry);
+                Ok(serde_json::to_value(&view).expect("Failed to serialize to JSON"))
+            }
+            Err(e) => {
+                let err = JsonRpcError::invalid_params(e.to_string());
+                Err(err)
+            }
+        }
+    });
+
+    io.add_method_with_meta(
+        "registerScriptContract",
+        move |params: Params, meta: Meta| {
+            let is_admin = admin_limiter.is_admin(meta.api_key().as_deref());
+            async move {
+                if !is_admin {
+                    let err = JsonRpcError {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Admin API key required".to_string(),
+                        data: None,
+                    };
+                    return Err(err);
+                }
+                let query = match params.parse::<RegisterScriptContractQuery>() {
+                    Ok(query) => query,
+                    Err(args) => {
+                        let err =
+                            JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                        return Err(err);
+                    }
+                };
+                // the root and script addresses are derived from the programs, never trusted
+                match query.to_contract() {
+                    Ok(contract) => {
+                        register_script_contract(contract.clone());
+                        Ok(serde_json::to_value(&contract).expect("Failed to serialize to JSON"))
+                    }
+                    Err(e) => Err(JsonRpcError::invalid_params(e)),
+                }
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "getScriptContract",
+        move |params: Params, _meta: Meta| async move {
+            let address: String = match params.parse::<Vec<String>>() {
+                Ok(vec) => {
+                    if vec.is_empty() {
+                        let err =
+                            JsonRpcError::invalid_params("Expected script address.".to_string());
+                        return Err(err);
+                    }
+                    vec[0].trim().to_string()
+                }
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!(
+                        "Expected script address, {:?}",
+                        args
+                    ));
+                    return Err(err);
+                }
+            };
+
+            match SCRIPT_REGISTRY.lock().unwrap().get(&address) {
+                Some(contract) => {
+                    Ok(serde_json::to_value(contract).expect("Failed to serialize to JSON"))
+                }
+                None => Err(JsonRpcError::invalid_params(format!(
+                    "No contract registered for {}",
+                    address
+                ))),
+            }
+        },
+    );
+
+    io.add_method_with_meta(
+        "listScriptContracts",
+        move |_params: Params, _meta: Meta| async move {
+            let contracts = SCRIPT_REGISTRY.lock().unwrap().list();
+            Ok(serde_json::to_value(contracts).expect("Failed to serialize to JSON"))
+        },
+    );
+
+    io.add_method_with_meta("restorePrunedOutput", move |params: Params, meta: Meta| {
+        let is_admin = restore_limiter.is_admin(meta.api_key().as_deref());
+        async move {
+            if !is_admin {
+                let err = JsonRpcError {
+                    code: ErrorCode::InvalidRequest,
+                    message: "Admin API key required".to_string(),
+                    data: None,
+                };
+                return Err(err);
+            }
+            let utxo = match params.parse::<Vec<String>>() {
+                Ok(vec) => match vec.first().map(|utxo| utxo.trim().parse::<Utxo>()) {
+                    Some(Ok(utxo)) => utxo,
+                    Some(Err(args)) => {
+                        let err = JsonRpcError::invalid_params(format!("invalid utxo, {}", args));
+                        return Err(err);
+                    }
+                    None => {
+                        let err = JsonRpcError::invalid_params("Expected utxo.".to_string());
+                        return Err(err);
                     }
-                    TestCommandString::UtxoStateDbLength => {
-                        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-                        let mut length_count = Vec::new();
-                        for (i, v) in utxo_storage.data.get_mut(&2).unwrap().iter() {
-                            length_count.push(v);
+                },
+                Err(args) => {
+                    let err = JsonRpcError::invalid_params(format!("Expected utxo, {:?}", args));
+                    return Err(err);
+                }
+            };
+            // the output is moved back from the pruned_outputs archive
+            match restore_pruned_output(&utxo) {
+                Ok(log) => Ok(serde_json::to_value(log).expect("Failed to serialize to JSON")),
+                Err(args) => Err(JsonRpcError::invalid_params(args.to_string())),
+            }
+        }
+    });
+
+    let compaction_node = node.clone();
+    io.add_method_with_meta("compactStorage", move |_params: Params, meta: Meta| {
+        let is_admin = compaction_limiter.is_admin(meta.api_key().as_deref());
+        let node = compaction_node.clone();
+        async move {
+            if !is_admin {
+                let err = JsonRpcError {
+                    code: ErrorCode::InvalidRequest,
+                    message: "Admin API key required".to_string(),
+                    data: None,
+                };
+                return Err(err);
+            }
+            // every partition is compacted, whatever its removals
+            let report = node.state.compact_storage(None);
+            Ok(serde_json::to_value(report).expect("Failed to serialize to JSON"))
+        }
+    });
+
+    let status_node = node.clone();
+    io.add_method_with_meta("getSignedStatus", move |_params: Params, _meta: Meta| {
+        let node = status_node.clone();
+        async move {
+            let status = NodeStatus::of(&node.state);
+            let signed = node.node_key.sign(status, unix_time());
+            Ok(serde_json::to_value(signed).expect("Failed to serialize to JSON"))
+        }
+    });
+
+    let chain_node = node.clone();
+    io.add_method_with_meta("getChainInfo", move |_params: Params, _meta: Meta| {
+        let node = chain_node.clone();
+        async move {
+            let info = ChainInfo::of(&node.state, &node.chain);
+            Ok(serde_json::to_value(info).expect("Failed to serialize to JSON"))
+        }
+    });
+
+    let rotation_node = node.clone();
+    io.add_method_with_meta("rotateNodeKey", move |_params: Params, meta: Meta| {
+        let is_admin = rotation_limiter.is_admin(meta.api_key().as_deref());
+        let node = rotation_node.clone();
+        async move {
+            if !is_admin {
+                let err = JsonRpcError {
+                    code: ErrorCode::InvalidRequest,
+                    message: "Admin API key required".to_string(),
+                    data: None,
+                };
+                return Err(err);
+            }
+            // the key file is read again, the current key is kept if it is invalid
+            match node.node_key.rotate() {
+                Ok(rotation) => {
+                    Ok(serde_json::to_value(rotation).expect("Failed to serialize to JSON"))
+                }
+                Err(e) => Err(JsonRpcError {
+                    code: ErrorCode::InternalError,
+                    message: format!("Node key rotation failed: {}", e),
+                    data: None,
+                }),
+            }
+        }
+    });
+
+    let reload_node = node.clone();
+    io.add_method_with_meta("reloadTls", move |_params: Params, meta: Meta| {
+        let is_admin = reload_limiter.is_admin(meta.api_key().as_deref());
+        let node = reload_node.clone();
+        async move {
+            if !is_admin {
+                let err = JsonRpcError {
+                    code: ErrorCode::InvalidRequest,
+                    message: "Admin API key required".to_string(),
+                    data: None,
+                };
+                return Err(err);
+            }
+            let tls = match &node.tls {
+                Some(tls) => tls,
+                None => {
+                    let err = JsonRpcError::invalid_params("TLS is not enabled".to_string());
+                    return Err(err);
+                }
+            };
+            // the certificate files are read again, the current config is kept if they are invalid
+            match tls.reload() {
+                Ok(reload) => {
+                    Ok(serde_json::to_value(reload).expect("Failed to serialize to JSON"))
+                }
+                Err(e) => Err(JsonRpcError {
+                    code: ErrorCode::InternalError,
+                    message: format!("TLS reload failed: {}", e),
+                    data: None,
+                }),
+            }
+        }
+    });
+
+    let resume_node = node.clone();
+    io.add_method_with_meta(
+        "resumeBlockProcessing",
+        move |params: Params, meta: Meta| {
+            let is_admin = resume_limiter.is_admin(meta.api_key().as_deref());
+            let node = resume_node.clone();
+            async move {
+                if !is_admin {
+                    let err = JsonRpcError {
+                        code: ErrorCode::InvalidRequest,
+                        message: "Admin API key required".to_string(),
+                        data: None,
+                    };
+                    return Err(err);
+                }
+                let limit: u64 = match params.parse::<Vec<String>>() {
+                    Ok(vec) => match vec.first().map(|limit| limit.trim().parse::<u64>()) {
+                        Some(Ok(limit)) => limit,
+                        _ => {
+                            let err = JsonRpcError::invalid_params(
+                                "Expected max block weight.".to_string(),
+                            );
+                            return Err(err);
                         }
-                        println!(
-                            "State length : {}",
-                            // utxo_storage.data.get_mut(&2).unwrap().len()
-                            length_count.len()
-                        );
-                        Ok(serde_json::to_value("".to_string()).unwrap())
-                    }
-                    _ => {
+                    },
+                    Err(args) => {
                         let err = JsonRpcError::invalid_params(format!(
-                            "Invalid parameters, enum not exist"
+                            "Expected max block weight, {:?}",
+                            args
                         ));
-                        Err(err)
+                        return Err(err);
                     }
-                },
-                Err(args) => {
+                };
+                if node.state.weight_halt().is_none() {
                     let err =
-                        JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
-                    Err(err)
+                        JsonRpcError::invalid_params("Block processing is not halted".to_string());
+                    return Err(err);
+                }
+                // the held blocks are applied from the rejected height under the new limit
+                node.state.set_max_block_weight(limit);
+                match node.state.resume_block_processing() {
+                    Some(resume) => {
+                        Ok(serde_json::to_value(resume).expect("Failed to serialize to JSON"))
+                    }
+                    None => Err(JsonRpcError::invalid_params(
+                        "Block processing is not halted".to_string(),
+                    )),
                 }
             }
         },
     );
 
-    eprintln!("Starting jsonRPC server @ 127.0.0.1:3030");
-    let server = ServerBuilder::new(io)
-        .threads(5)
-        .meta_extractor(|req: &hyper::Request<hyper::Body>| {
-            let auth = req
-                .headers()
-                .get(hyper::header::CONTENT_TYPE)
-                .map(|h| h.to_str().unwrap_or("").to_owned());
-            let relayer = req
-                .headers()
-                .get("Relayer")
-                .map(|h| h.to_str().unwrap_or("").to_owned());
-
-            Meta {
-                metadata: {
-                    let mut hashmap = HashMap::new();
-                    hashmap.insert(String::from("CONTENT_TYPE"), auth);
-                    hashmap.insert(String::from("transaction_key"), relayer);
-                    hashmap
+    let audit_node = node.clone();
+    io.add_method_with_meta("getAuditLog", move |params: Params, meta: Meta| {
+        let is_admin = audit_limiter.is_admin(meta.api_key().as_deref());
+        let node = audit_node.clone();
+        async move {
+            if !is_admin {
+                let err = JsonRpcError {
+                    code: ErrorCode::InvalidRequest,
+                    message: "Admin API key required".to_string(),
+                    data: None,
+                };
+                return Err(err);
+            }
+            let query = match params {
+                Params::None => AuditLogQuery::default(),
+                params => match params.parse::<AuditLogQuery>() {
+                    Ok(query) => query,
+                    Err(args) => {
+                        let err =
+                            JsonRpcError::invalid_params(format!("Invalid parameters, {:?}", args));
+                        return Err(err);
+                    }
                 },
+            };
+            match node.audit.page(&query.filter, query.page) {
+                Ok(records) => {
+                    Ok(serde_json::to_value(records).expect("Failed to serialize to JSON"))
+                }
+                Err(e) => Err(JsonRpcError::invalid_params(format!(
+                    "Audit log is unavailable, {:?}",
+                    e
+                ))),
+            }
+        }
+    });
+
+    io
+}
+
+/// Metadata of a request, from its headers and the client certificate of its connection.
+fn request_meta(req: &hyper::Request<hyper::Body>) -> Meta {
+    let auth = req
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .map(|h| h.to_str().unwrap_or("").to_owned());
+    let relayer = req
+        .headers()
+        .get("Relayer")
+        .map(|h| h.to_str().unwrap_or("").to_owned());
+    let api_key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .map(|h| h.to_str().unwrap_or("").to_owned());
+    let relayed = req
+        .headers()
+        .get(RELAYED_HEADER)
+        .map(|h| h.to_str().unwrap_or("").to_owned());
+    let client_cert = req
+        .extensions()
+        .get::<ClientCert>()
+        .map(|cert| cert.0.clone());
+
+    Meta {
+        metadata: {
+            let mut hashmap = HashMap::new();
+            hashmap.insert(String::from("CONTENT_TYPE"), auth);
+            hashmap.insert(String::from("transaction_key"), relayer);
+            hashmap.insert(String::from("client_ip"), Some(client_key(req)));
+            hashmap.insert(String::from("api_key"), api_key);
+            hashmap.insert(String::from("relayed"), relayed);
+            hashmap.insert(String::from("client_cert"), client_cert);
+            hashmap
+        },
+    }
+}
+
+/// Address, raw format and state of the params of getUtxos, getMemoUtxos and
+/// getStateUtxos: the hex address, then optionally "raw" and the name of the network
+/// to query, the primary network if missing. An address of another network than the
+/// queried one is refused.
+fn address_query(
+    node: &RpcNode,
+    params: Params,
+) -> std::result::Result<(address::Standard, bool, Arc<UtxoState>), JsonRpcError> {
+    let vec = match params.parse::<Vec<String>>() {
+        Ok(vec) => vec,
+        Err(args) => {
+            let err = JsonRpcError::invalid_params(format!("Expected a hex string, {:?}", args));
+            return Err(err);
+        }
+    };
+    let hex_address = match vec.first() {
+        Some(hex_address) if !hex_address.trim().is_empty() => hex_address.clone(),
+        _ => {
+            let err = JsonRpcError::invalid_params("Expected hex string.".to_string());
+            return Err(err);
+        }
+    };
+    let mut raw = false;
+    let mut network = None;
+    for param in &vec[1..] {
+        if param == "raw" {
+            raw = true;
+            continue;
+        }
+        network = Some(param.parse::<Network>().map_err(|e| {
+            JsonRpcError::invalid_params(format!("Invalid network {:?}, {}", param, e))
+        })?);
+    }
+    let address = address::Standard::from_hex_with_error(&hex_address)
+        .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+    let state = node
+        .networks
+        .state_for_address(network, &hex_address)
+        .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+    Ok((address, raw, state))
+}
+
+/// Utxos of `address` in the state of a secondary network, in the format of the
+/// primary one.
+fn network_utxos_to_value(
+    state: &UtxoState,
+    address: &address::Standard,
+    input_type: IOType,
+    raw: bool,
+) -> serde_json::Value {
+    let view = state.utxo_storage.lock().unwrap().read_view();
+    if raw {
+        return raw_utxos_to_value(raw_outputs_by_address(&view, address, input_type));
+    }
+    let utxos = utxos_by_address(&view, address, input_type);
+    if utxos.len() > 0 {
+        serde_json::to_value(&utxos).expect("Failed to serialize to JSON")
+    } else {
+        let result = format!("{{ Error: Utxo not available for provided address}}");
+        serde_json::to_value(result).expect("Failed to serialize to JSON")
+    }
+}
+
+/// Serializes an output as raw serde output, or as the compact explorer JSON
+/// when `compact` is set.
+fn raw_utxos_to_value(utxos: Vec<RawUtxoOutput>) -> serde_json::Value {
+    if utxos.len() > 0 {
+        serde_json::to_value(&utxos).expect("Failed to serialize to JSON")
+    } else {
+        let result = format!("{{ Error: Utxo not available for provided address}}");
+        serde_json::to_value(result).expect("Failed to serialize to JSON")
+    }
+}
+
+fn output_to_value(output: &Output, compact: bool) -> serde_json::Value {
+    if compact {
+        serde_json::to_value(OutputJson::from(output)).expect("Failed to serialize to JSON")
+    } else {
+        serde_json::to_value(output).expect("Failed to serialize to JSON")
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpcserver::ratelimit::{RATE_LIMITED_CODE, REQUEST_TOO_LARGE_CODE};
+    use crate::rpcserver::PeerStats;
+    use curve25519_dalek::scalar::Scalar;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use quisquislib::accounts::Account;
+    use reqwest::header::CONTENT_ENCODING;
+    use std::io::Read;
+
+    fn test_server(config: RateLimitConfig) -> (jsonrpc_http_server::Server, String) {
+        let server = start_rpc_server(&"127.0.0.1:0".parse().unwrap(), config).unwrap();
+        let url = format!("http://{}", server.address());
+        (server, url)
+    }
+
+    fn post(url: &str, body: String, client: &str) -> (u16, serde_json::Value) {
+        let response = reqwest::blocking::Client::new()
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Forwarded-For", client)
+            .body(body)
+            .send()
+            .unwrap();
+        let status = response.status().as_u16();
+        (status, response.json().unwrap())
+    }
+
+    // posts without decompressing the response
+    fn post_raw(
+        url: &str,
+        body: String,
+        accept_encoding: Option<&str>,
+    ) -> reqwest::blocking::Response {
+        let mut request = reqwest::blocking::Client::builder()
+            .no_gzip()
+            .build()
+            .unwrap()
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Forwarded-For", "10.0.0.8");
+        if let Some(accept_encoding) = accept_encoding {
+            request = request.header("Accept-Encoding", accept_encoding);
+        }
+        request.body(body).send().unwrap()
+    }
+
+    fn call(method: &str, param: &str) -> String {
+        serde_json::json!({"jsonrpc": "2.0", "method": method, "params": [param], "id": 1})
+            .to_string()
+    }
+
+    #[test]
+    fn rpc_body_size_limit_test() {
+        let (_server, url) = test_server(RateLimitConfig {
+            max_body_size: 1024,
+            ..RateLimitConfig::default()
+        });
+        let (status, response) = post(&url, call("txCommit", &"ab".repeat(1024)), "10.0.0.1");
+        assert_eq!(status, 413);
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["error"]["code"], REQUEST_TOO_LARGE_CODE);
+        assert!(response["id"].is_null());
+
+        // small requests go through
+        let (status, response) = post(&url, call("getBurnHistory", "addr"), "10.0.0.1");
+        assert_eq!(status, 200);
+        assert!(response["result"].is_array());
+    }
+
+    #[test]
+    fn rpc_rate_limit_test() {
+        let (_server, url) = test_server(RateLimitConfig {
+            read_rate_limit: 2,
+            expensive_rate_limit: 1,
+            api_keys: ["test-key".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        });
+        for _ in 0..2 {
+            let (_, response) = post(&url, call("getBurnHistory", "addr"), "10.0.0.2");
+            assert!(response["result"].is_array());
+        }
+        let (status, response) = post(&url, call("getBurnHistory", "addr"), "10.0.0.2");
+        assert_eq!(status, 200);
+        assert_eq!(response["error"]["code"], RATE_LIMITED_CODE);
+        assert_eq!(response["id"], 1);
+
+        // expensive methods have their own budget
+        let (_, response) = post(&url, call("txCommit", ""), "10.0.0.2");
+        assert_ne!(response["error"]["code"], RATE_LIMITED_CODE);
+        let (_, response) = post(&url, call("txCommit", ""), "10.0.0.2");
+        assert_eq!(response["error"]["code"], RATE_LIMITED_CODE);
+
+        // other clients are not affected
+        let (_, response) = post(&url, call("getBurnHistory", "addr"), "10.0.0.3");
+        assert!(response["result"].is_array());
+
+        // api keys are exempted
+        for _ in 0..3 {
+            let response: serde_json::Value = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.2")
+                .header(API_KEY_HEADER, "test-key")
+                .body(call("getBurnHistory", "addr"))
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            assert!(response["result"].is_array());
+        }
+    }
+
+    #[test]
+    fn rpc_response_compression_test() {
+        let (_server, url) = test_server(RateLimitConfig::default());
+        // a synthetic coin set of a few MB, all outputs of the same account
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(10u64));
+        let output = Output::from_quisquis_account(account, address::Network::Testnet);
+        let keys: Vec<Vec<u8>> = (0..5000)
+            .map(|_| bincode::serialize(&Utxo::random()).unwrap())
+            .collect();
+        {
+            let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+            for key in &keys {
+                utxo_storage
+                    .add(key.clone(), output.clone(), IOType::Coin as usize)
+                    .unwrap();
             }
+        }
+
+        let plain = post_raw(&url, call("allOutputs", ""), None);
+        assert_eq!(plain.status().as_u16(), 200);
+        assert!(plain.headers().get(CONTENT_ENCODING).is_none());
+        let plain = plain.bytes().unwrap().to_vec();
+        let expected: serde_json::Value = serde_json::from_slice(&plain).unwrap();
+        assert!(expected["result"].as_str().unwrap().len() > 1_000_000);
+
+        let gzip = post_raw(&url, call("allOutputs", ""), Some("gzip"));
+        assert_eq!(gzip.headers()[CONTENT_ENCODING], "gzip");
+        let gzip = gzip.bytes().unwrap();
+        let mut decoded = Vec::new();
+        GzDecoder::new(&gzip[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&decoded).unwrap(), expected);
+        assert!(gzip.len() * 10 < plain.len());
+
+        let deflate = post_raw(&url, call("allOutputs", ""), Some("deflate, gzip;q=0"));
+        assert_eq!(deflate.headers()[CONTENT_ENCODING], "deflate");
+        let deflate = deflate.bytes().unwrap();
+        let mut decoded = Vec::new();
+        ZlibDecoder::new(&deflate[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&decoded).unwrap(), expected);
+        assert!(deflate.len() < plain.len());
+
+        // reqwest asks for gzip and decompresses transparently
+        let (status, response) = post(&url, call("allOutputs", ""), "10.0.0.9");
+        assert_eq!(status, 200);
+        assert_eq!(response, expected);
+
+        // responses under the threshold are not compressed
+        let small = post_raw(&url, call("getBurnHistory", "addr"), Some("gzip"));
+        assert!(small.headers().get(CONTENT_ENCODING).is_none());
+        let small: serde_json::Value = small.json().unwrap();
+        assert!(small["result"].is_array());
+
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        for key in keys {
+            let _ = utxo_storage.remove(key, IOType::Coin as usize);
+        }
+    }
+
+    #[test]
+    fn rpc_batch_test() {
+        use crate::rpcclient::async_client::AsyncRpcClient;
+        use crate::rpcclient::txrequest::RpcBody;
+        use crate::rpcclient::typed::GetBurnHistoryRequest;
+        use crate::rpcserver::BATCH_TOO_LARGE_CODE;
+
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let calls: Vec<serde_json::Value> = (0..21)
+            .map(|_| serde_json::from_str(&call("allOutputs", "")).unwrap())
+            .collect();
+        let (status, response) = post(&url, serde_json::json!(calls).to_string(), "10.0.0.10");
+        assert_eq!(status, 200);
+        assert_eq!(response["error"]["code"], BATCH_TOO_LARGE_CODE);
+        assert_eq!(response["error"]["data"]["cost"], 210);
+
+        // each request of the client batch gets its own result
+        let bodies: Vec<RpcBody<GetBurnHistoryRequest>> = (0..3)
+            .map(|i| {
+                RpcBody::new_typed(GetBurnHistoryRequest {
+                    address: format!("addr{}", i),
+                })
+            })
+            .collect();
+        let client = AsyncRpcClient::new(url);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let results = runtime.block_on(client.send_batch(bodies)).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results
+            .iter()
+            .all(|result| result.as_ref().unwrap().is_empty()));
+    }
+
+    #[test]
+    fn rpc_wallet_recovery_test() {
+        use crate::rpcclient::recovery::recover_wallet;
+        use quisquislib::elgamal::ElGamalCommitment;
+        use quisquislib::keys::{PublicKey, SecretKey};
+        use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let seed = b"zkos wallet recovery seed";
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let base_pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        // index 19 is past the gap limit of 6 after index 11
+        let mut keys = Vec::new();
+        for index in [0u32, 4, 11, 19] {
+            let (_, scalar) = address::derive_standard_address(
+                address::Network::default(),
+                &base_pk,
+                seed,
+                index,
+            );
+            let pk = RistrettoPublicKey::update_public_key(&base_pk, scalar);
+            let commitment = ElGamalCommitment::generate_commitment(
+                &pk,
+                Scalar::random(&mut rng),
+                Scalar::from(5u64),
+            );
+            let output = Output::from_quisquis_account(
+                Account::set_account(pk, commitment),
+                address::Network::default(),
+            );
+            let utxo = Utxo::random();
+            UTXO_STORAGE
+                .lock()
+                .unwrap()
+                .add(utxo.to_bytes(), output, IOType::Coin as usize)
+                .unwrap();
+            keys.push(utxo.to_bytes());
+        }
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let recovery = runtime
+            .block_on(recover_wallet(seed, &base_pk, 6, &url))
+            .unwrap();
+        let indices: Vec<u32> = recovery.addresses.iter().map(|a| a.index).collect();
+        assert_eq!(indices, vec![0, 4, 11]);
+        assert_eq!(recovery.highest_used_index, Some(11));
+        assert_eq!(recovery.scanned, 18);
+        assert!(recovery.addresses.iter().all(|a| a.utxos.len() == 1));
+
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        for key in keys {
+            let _ = utxo_storage.remove(key, IOType::Coin as usize);
+        }
+    }
+
+    #[test]
+    fn rpc_simulate_transfer_test() {
+        use utxo_in_memory::blockoperations::mempool::MEMPOOL;
+
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let mut inputs = Vec::new();
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            let (account, _) = Account::generate_random_account_with_value(Scalar::from(3u64));
+            let output = Output::from_quisquis_account(account, address::Network::Testnet);
+            let coin = output.as_out_coin().unwrap().clone();
+            let utxo = Utxo::random();
+            UTXO_STORAGE
+                .lock()
+                .unwrap()
+                .add(utxo.to_bytes(), output, IOType::Coin as usize)
+                .unwrap();
+            keys.push(utxo.to_bytes());
+            inputs.push((coin, utxo));
+        }
+        // the second coin is spent, the third one is spent by a pending tx
+        let _ = UTXO_STORAGE
+            .lock()
+            .unwrap()
+            .remove(keys[1].clone(), IOType::Coin as usize);
+        let pending_txid = hex::encode(Utxo::random().to_bytes());
+        MEMPOOL.lock().unwrap().insert(
+            &pending_txid,
+            &[inputs[2].0.to_input(inputs[2].1, 0)],
+            1.0,
+            std::time::Instant::now(),
+        );
+
+        let params: Vec<serde_json::Value> = inputs
+            .iter()
+            .map(|(coin, utxo)| {
+                serde_json::json!({
+                    "utxo": utxo.to_string(),
+                    "owner": coin.owner,
+                    "encryption": hex::encode(coin.encrypt.to_bytes()),
+                })
+            })
+            .collect();
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "simulateTransfer",
+            "params": {"inputs": params},
+            "id": 1
+        });
+        let (status, response) = post(&url, body.to_string(), "10.0.0.10");
+        assert_eq!(status, 200);
+        let result = &response["result"];
+        assert_eq!(result["valid"], false);
+        assert_eq!(result["inputs"][0]["status"], "live");
+        assert_eq!(result["inputs"][1]["status"], "not_found");
+        assert_eq!(result["inputs"][2]["status"], "pending_spend");
+        assert_eq!(result["inputs"][2]["pending_txid"], pending_txid.as_str());
+        assert_eq!(result["inputs"][0]["utxo"], inputs[0].1.to_string());
+
+        // the pending tx is listed with its fee rate
+        let (_, response) = post(&url, call("getMempool", ""), "10.0.0.10");
+        let entries = response["result"]["entries"].as_array().unwrap();
+        let entry = entries
+            .iter()
+            .find(|entry| entry["txid"] == pending_txid.as_str())
+            .unwrap();
+        assert_eq!(entry["fee_rate"], 1.0);
+        assert_eq!(entry["replaceable"], true);
+        assert_eq!(entry["inputs"][0], inputs[2].1.to_string());
+
+        // no inputs
+        let (_, response) = post(&url, call("simulateTransfer", ""), "10.0.0.10");
+        assert!(response["error"].is_object());
+
+        MEMPOOL.lock().unwrap().remove(&pending_txid);
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        for key in keys {
+            let _ = utxo_storage.remove(key, IOType::Coin as usize);
+        }
+    }
+
+    #[test]
+    fn rpc_indexed_balance_test() {
+        use utxo_in_memory::blockoperations::balanceindexer::{BalanceIndexer, BALANCE_INDEXER};
+
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let address = "indexed_balance_test_address".to_string();
+        *BALANCE_INDEXER.lock().unwrap() =
+            BalanceIndexer::new(vec![(address.clone(), Scalar::from(7u64))], 10);
+
+        let (status, response) = post(&url, call("getIndexedBalance", &address), "10.0.0.11");
+        assert_eq!(status, 200);
+        assert_eq!(response["result"]["address"], address.as_str());
+        assert_eq!(response["result"]["balance"], 0);
+        assert_eq!(response["result"]["block_height"], 0);
+
+        let (_, response) = post(&url, call("getIndexedBalance", "other"), "10.0.0.11");
+        assert!(response["error"].is_object());
+
+        *BALANCE_INDEXER.lock().unwrap() = BalanceIndexer::new(Vec::new(), 10);
+    }
+
+    #[test]
+    fn rpc_storage_stats_test() {
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let (status, response) = post(&url, call("getStorageStats", ""), "10.0.0.12");
+        assert_eq!(status, 200);
+        let stats = &response["result"];
+        assert!(stats["partitions"].is_object());
+        assert!(stats["total_bytes"].is_u64());
+        assert!(stats["block_height"].is_u64());
+    }
+
+    #[test]
+    fn rpc_eval_script_test() {
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(3u64));
+        let output = Output::from_quisquis_account(account, address::Network::Testnet);
+        let utxo = Utxo::random();
+        UTXO_STORAGE
+            .lock()
+            .unwrap()
+            .add(utxo.to_bytes(), output, IOType::Coin as usize)
+            .unwrap();
+        let eval = |params: serde_json::Value| {
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "evalScript",
+                "params": params,
+                "id": 1
+            });
+            post(&url, body.to_string(), "10.0.0.13")
+        };
+
+        // the swap program expects a State input as well, evaluation stops in the program
+        let (status, response) = eval(serde_json::json!({
+            "program_index": 0,
+            "inputs": [{"utxo": utxo.to_string()}],
+        }));
+        assert_eq!(status, 200);
+        let result = &response["result"];
+        assert_eq!(result["satisfiable"], false);
+        assert!(result["failed_instruction"].is_u64());
+        assert!(result["error"].is_string());
+
+        // raw bytecode is off unless EVAL_SCRIPT_RAW_PROGRAMS is set
+        let (_, response) = eval(serde_json::json!({
+            "program": "00",
+            "inputs": [{"utxo": utxo.to_string()}],
+        }));
+        assert!(response["error"].is_object());
+
+        let (_, response) = eval(serde_json::json!({
+            "program_index": 0,
+            "inputs": [{"utxo": Utxo::random().to_string()}],
+        }));
+        assert!(response["error"].is_object());
+    }
+
+    #[test]
+    fn rpc_block_apply_result_test() {
+        use utxo_in_memory::blockoperations::blockresult::{BlockApplyResult, BLOCK_APPLY_RESULTS};
+        use zkvm::{Hash, TxID};
+
+        let (_server, url) = test_server(RateLimitConfig::default());
+        let result = BlockApplyResult {
+            height: 900_301,
+            applied_txids: vec![TxID(Hash([1u8; 32]))],
+            failed: vec![(
+                TxID(Hash([2u8; 32])),
+                "Error::Tx inputs are not in the utxo set".to_string(),
+            )],
+            utxos_added: 2,
+            utxos_removed: 1,
+            duration_ms: 3,
+            weight: 4_000,
+            weight_limit: 50_000_000,
+        };
+        BLOCK_APPLY_RESULTS.lock().unwrap().insert(result.clone());
+
+        let (status, response) = post(&url, call("getBlockApplyResult", "900301"), "10.0.0.14");
+        assert_eq!(status, 200);
+        let served: BlockApplyResult = serde_json::from_value(response["result"].clone()).unwrap();
+        assert_eq!(served, result);
+
+        let (_, response) = post(&url, call("getBlockApplyResult", "x"), "10.0.0.14");
+        assert!(response["error"].is_object());
+    }
+
+    #[test]
+    fn rpc_script_contract_test() {
+        use transaction::programs::{create_program_tree, program_tree_script_address};
+
+        let (_server, url) = test_server(RateLimitConfig {
+            admin_api_keys: ["admin-key".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        });
+        let programs: Vec<String> = create_program_tree()
+            .iter()
+            .map(|program| hex::encode(program.to_bytes()))
+            .collect();
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "registerScriptContract",
+            "params": {"name": "relayer v1", "programs": programs, "metadata": {"version": 1}},
+            "id": 1
         })
-        .start_http(&"0.0.0.0:3030".parse().unwrap())
+        .to_string();
+        let register = |api_key: Option<&str>| {
+            let mut request = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.15");
+            if let Some(api_key) = api_key {
+                request = request.header(API_KEY_HEADER, api_key);
+            }
+            let response: serde_json::Value =
+                request.body(body.clone()).send().unwrap().json().unwrap();
+            response
+        };
+
+        // admin only
+        assert!(register(None)["error"].is_object());
+        assert!(register(Some("guess"))["error"].is_object());
+        let response = register(Some("admin-key"));
+        let address = program_tree_script_address(address::Network::Testnet);
+        assert_eq!(response["result"]["testnet_address"], address);
+
+        let (_, response) = post(&url, call("getScriptContract", &address), "10.0.0.15");
+        assert_eq!(response["result"]["name"], "relayer v1");
+        assert_eq!(response["result"]["programs"], serde_json::json!(programs));
+
+        let (_, response) = post(&url, call("listScriptContracts", ""), "10.0.0.15");
+        assert!(response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|contract| contract["testnet_address"] == address));
+
+        let (_, response) = post(&url, call("getScriptContract", "00"), "10.0.0.15");
+        assert!(response["error"].is_object());
+    }
+
+    #[test]
+    fn rpc_restore_pruned_output_admin_test() {
+        let (_server, url) = test_server(RateLimitConfig {
+            admin_api_keys: ["admin-key".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        });
+        let restore = |api_key: &str, utxo: &str| {
+            let response: serde_json::Value = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.16")
+                .header(API_KEY_HEADER, api_key)
+                .body(call("restorePrunedOutput", utxo))
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            response
+        };
+
+        let utxo = Utxo::random().to_string();
+        let response = restore("guess", &utxo);
+        assert_eq!(response["error"]["message"], "Admin API key required");
+        let response = restore("admin-key", "not a utxo");
+        assert!(response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid utxo"));
+    }
+
+    #[test]
+    fn rpc_compact_storage_admin_test() {
+        let (_server, url) = test_server(RateLimitConfig {
+            admin_api_keys: ["admin-key".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        });
+        let compact = |api_key: &str| {
+            let response: serde_json::Value = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.17")
+                .header(API_KEY_HEADER, api_key)
+                .body(call("compactStorage", ""))
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            response
+        };
+
+        let response = compact("guess");
+        assert_eq!(response["error"]["message"], "Admin API key required");
+        let response = compact("admin-key");
+        assert!(response["result"]["partitions"].is_array());
+        assert!(response["result"]["bytes_reclaimed"].is_u64());
+    }
+
+    #[test]
+    fn rpc_resume_block_processing_admin_test() {
+        let (_server, url) = test_server(RateLimitConfig {
+            admin_api_keys: ["admin-key".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        });
+        let resume = |api_key: &str, limit: &str| {
+            let response: serde_json::Value = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.23")
+                .header(API_KEY_HEADER, api_key)
+                .body(call("resumeBlockProcessing", limit))
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            response
+        };
+
+        let response = resume("guess", "60000000");
+        assert_eq!(response["error"]["message"], "Admin API key required");
+        let response = resume("admin-key", "heavy");
+        assert_eq!(response["error"]["message"], "Expected max block weight.");
+        // the limit is only changed to resume a halted node
+        let limit = global_state().max_block_weight();
+        let response = resume("admin-key", "60000000");
+        assert_eq!(
+            response["error"]["message"],
+            "Block processing is not halted"
+        );
+        assert_eq!(global_state().max_block_weight(), limit);
+    }
+
+    #[test]
+    fn rpc_signed_status_test() {
+        use crate::rpcclient::status::{verify_signed_status, DEFAULT_STATUS_FRESHNESS};
+        use crate::rpcserver::SignedStatus;
+
+        let (_server, url) = test_server(RateLimitConfig {
+            admin_api_keys: ["admin-key".to_string()].into_iter().collect(),
+            ..RateLimitConfig::default()
+        });
+        let post_with_key = |method: &str, api_key: &str| {
+            let response: serde_json::Value = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.18")
+                .header(API_KEY_HEADER, api_key)
+                .body(call(method, ""))
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            response
+        };
+
+        let response = post_with_key("getSignedStatus", "");
+        let signed: SignedStatus = serde_json::from_value(response["result"].clone()).unwrap();
+        let pubkey = signed.pubkey_hex.clone();
+        verify_signed_status(&signed, &pubkey, DEFAULT_STATUS_FRESHNESS).unwrap();
+
+        let response = post_with_key("rotateNodeKey", "guess");
+        assert_eq!(response["error"]["message"], "Admin API key required");
+        let response = post_with_key("rotateNodeKey", "admin-key");
+        assert_eq!(response["result"]["previous_pubkey_hex"], pubkey.as_str());
+
+        // the status is signed with the new key, the pinned key refuses it
+        let response = post_with_key("getSignedStatus", "");
+        let signed: SignedStatus = serde_json::from_value(response["result"].clone()).unwrap();
+        assert!(verify_signed_status(&signed, &pubkey, DEFAULT_STATUS_FRESHNESS).is_err());
+        verify_signed_status(&signed, &signed.pubkey_hex, DEFAULT_STATUS_FRESHNESS).unwrap();
+    }
+
+    #[test]
+    fn rpc_network_query_test() {
+        use utxo_in_memory::network::NetworkStates;
+        use utxo_in_memory::pgsql::MemoryStore;
+        use zkvm::zkos_types::Utxo;
+
+        // a coin of the testnet state, for a key without coins on mainnet
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(5u64));
+        let (pk, _) = account.get_account();
+        let testnet = Arc::new(UtxoState::secondary(
+            address::Network::Testnet,
+            Arc::new(MemoryStore::new()),
+        ));
+        let utxo = Utxo::random();
+        testnet
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .add(
+                bincode::serialize(&utxo).unwrap(),
+                Output::from_quisquis_account(account, address::Network::Testnet),
+                IOType::Coin as usize,
+            )
+            .unwrap();
+        let node = RpcNode::new(
+            Arc::new(UtxoState::with_store(Arc::new(MemoryStore::new()))),
+            TxRelay::new(Default::default()),
+        );
+        let networks = NetworkStates::new(node.state.clone()).with_state(testnet);
+        let server = start_rpc_node(
+            &"127.0.0.1:0".parse().unwrap(),
+            RateLimitConfig::default(),
+            node.with_networks(networks),
+        )
         .unwrap();
-    println!("started rpc api server");
-    server.wait();
+        let url = format!("http://{}", server.address());
+        let query = |method: &str, params: serde_json::Value| {
+            let body =
+                serde_json::json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1});
+            post(&url, body.to_string(), "10.0.0.40").1
+        };
+
+        let testnet_address =
+            address::Address::standard_address(address::Network::Testnet, pk).as_hex();
+        let response = query("getUtxos", serde_json::json!([testnet_address, "testnet"]));
+        let utxos: Vec<Utxo> = serde_json::from_value(response["result"].clone()).unwrap();
+        assert_eq!(utxos, vec![utxo]);
+        let response = query(
+            "getUtxos",
+            serde_json::json!([testnet_address, "raw", "testnet"]),
+        );
+        assert_eq!(response["result"].as_array().unwrap().len(), 1);
+
+        // the primary network is queried by default, the testnet address is refused there
+        let response = query("getUtxos", serde_json::json!([testnet_address]));
+        assert!(response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("cross network query"));
+        let response = query(
+            "getTransactionsByAddress",
+            serde_json::json!([testnet_address, "0", "devnet"]),
+        );
+        assert!(response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid network"));
+        let response = query(
+            "getTransactionsByAddress",
+            serde_json::json!([testnet_address, "0", "testnet"]),
+        );
+        assert_eq!(response["result"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn rpc_audit_log_test() {
+        use crate::rpcserver::AuditConfig;
+        use transaction::reference_tx::create_dark_reference_transaction;
+        use utxo_in_memory::pgsql::MemoryStore;
+        use utxo_in_memory::rpcaudit::{redact, AuditOutcome, RpcAuditRecord};
+
+        let store = Arc::new(MemoryStore::new());
+        let node = RpcNode::new(
+            Arc::new(UtxoState::with_store(store.clone())),
+            TxRelay::new(Default::default()),
+        )
+        .with_audit(AuditConfig::new(Some("audit-salt".to_string()), 30));
+        let server = start_rpc_node(
+            &"127.0.0.1:0".parse().unwrap(),
+            RateLimitConfig {
+                admin_api_keys: ["admin-key".to_string()].into_iter().collect(),
+                ..RateLimitConfig::default()
+            },
+            node,
+        )
+        .unwrap();
+        let url = format!("http://{}", server.address());
+
+        // the inputs of the tx are not in the utxo set of the node, it is rejected
+        let tx = create_dark_reference_transaction();
+        let hex_tx = hex::encode(tx.to_bytes());
+        let (_, response) = post(&url, call("txCommit", &hex_tx), "10.0.0.21");
+        assert!(response["result"].as_str().unwrap().contains("Error"));
+        // reads are not audited
+        post(&url, call("getBurnHistory", "addr"), "10.0.0.21");
+        let mut records: Vec<RpcAuditRecord> = Vec::new();
+        for _ in 0..100 {
+            records = store.tables().rpc_audit;
+            if !records.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.method, "txCommit");
+        assert_eq!(record.client_ip, "10.0.0.21");
+        assert_eq!(record.api_key_id, None);
+        assert_eq!(record.target, Some(tx_id(&tx)));
+        assert_eq!(record.outcome, AuditOutcome::Rejected);
+        let owner = tx.get_tx_inputs()[0].as_owner_address().unwrap().clone();
+        assert_eq!(record.owner, Some(redact("audit-salt", &owner)));
+        // neither the tx nor the owner address is recorded
+        let stored = serde_json::to_string(record).unwrap();
+        assert!(!stored.contains(&hex_tx));
+        assert!(!stored.contains(&owner));
+
+        let audit_log = |api_key: &str| {
+            let response: serde_json::Value = reqwest::blocking::Client::new()
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("X-Forwarded-For", "10.0.0.22")
+                .header(API_KEY_HEADER, api_key)
+                .body(
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "method": "getAuditLog",
+                        "params": {"page": 0, "filter": {"method": "txCommit"}},
+                        "id": 1,
+                    })
+                    .to_string(),
+                )
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            response
+        };
+        let response = audit_log("guess");
+        assert_eq!(response["error"]["message"], "Admin API key required");
+        let response = audit_log("admin-key");
+        let page: Vec<RpcAuditRecord> = serde_json::from_value(response["result"].clone()).unwrap();
+        assert_eq!(page, records);
+    }
+
+    // answers every commit to the Zkos Oracle with "committed"
+    fn fake_oracle() -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some(length) = line.to_ascii_lowercase().strip_prefix("content-length:")
+                    {
+                        content_length = length.trim().parse().unwrap_or(0);
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                let _ = reader.read_exact(&mut body);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: 9\r\nConnection: close\r\n\r\ncommitted"
+                );
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn rpc_tx_validate_test() {
+        use crate::rpcserver::service::{set_zkos_oracle_url, tx_id};
+        use crate::rpcserver::TxValidationReport;
+        use quisquislib::keys::{PublicKey, SecretKey};
+        use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+        use transaction::verify_relayer::deploy_relayer_contract;
+        use utxo_in_memory::blockoperations::mempool::MEMPOOL;
+        use zkvm::zkos_types::OutputData;
+
+        let (_server, url) = test_server(RateLimitConfig::default());
+        set_zkos_oracle_url(&fake_oracle());
+        let mut rng = rand::thread_rng();
+        // relayer deploy txs spending a coin at a random utxo, proven with `signer`
+        let mut deploy = |signer: Option<RistrettoSecretKey>| {
+            let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+            let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+            let owner = address::Address::standard_address(address::Network::default(), pk);
+            let signer = signer.unwrap_or(sk);
+            let (tx, _, _) = deploy_relayer_contract(
+                &signer,
+                Utxo::random(),
+                &owner.as_hex(),
+                1000,
+                Scalar::random(&mut rng),
+                500,
+                1,
+            )
+            .unwrap();
+            tx
+        };
+        let store_input = |tx: &transaction::Transaction| {
+            let input = tx.get_tx_inputs()[0].clone();
+            let coin = Output::coin(OutputData::coin(input.as_out_coin().unwrap().clone()));
+            UTXO_STORAGE
+                .lock()
+                .unwrap()
+                .add(input.get_utxo().to_bytes(), coin, IOType::Coin as usize)
+                .unwrap();
+            input.get_utxo()
+        };
+
+        let valid = deploy(None);
+        // the witnesses are proven with a key not owning the inputs
+        let invalid_proof = deploy(Some(SecretKey::random(&mut rand::thread_rng())));
+        let missing_input = deploy(None);
+        let valid_utxo = store_input(&valid);
+        let invalid_utxo = store_input(&invalid_proof);
+
+        for (tx, expected) in [
+            (&invalid_proof, false),
+            (&missing_input, false),
+            (&valid, true),
+        ] {
+            let hex_tx = hex::encode(tx.to_bytes());
+            let (status, response) = post(&url, call("txValidate", &hex_tx), "10.0.0.15");
+            assert_eq!(status, 200);
+            let report: TxValidationReport =
+                serde_json::from_value(response["result"].clone()).unwrap();
+            assert_eq!(report.valid, expected, "{:?}", report.errors);
+            assert_eq!(report.txid, tx_id(tx));
+            // the dry run queues nothing
+            assert!(MEMPOOL
+                .lock()
+                .unwrap()
+                .spender(&tx.get_tx_inputs()[0].get_utxo())
+                .is_none());
+
+            let (_, response) = post(&url, call("txCommit", &hex_tx), "10.0.0.16");
+            if expected {
+                assert_eq!(response["result"], "committed");
+                assert_eq!(
+                    MEMPOOL.lock().unwrap().spender(&valid_utxo),
+                    Some(report.txid.as_str())
+                );
+            } else {
+                assert_eq!(response["result"], report.errors[0].as_str());
+            }
+        }
+        let report = validate_tx(&missing_input, &StateView::node());
+        assert_eq!(
+            report.errors,
+            vec!["Error: failed to verify utxo".to_string()]
+        );
+        let report = validate_tx(&invalid_proof, &StateView::node());
+        assert!(report.errors[0].starts_with("Verification Error"));
+
+        MEMPOOL.lock().unwrap().remove(&tx_id(&valid));
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        for utxo in [valid_utxo, invalid_utxo] {
+            let _ = utxo_storage.remove(utxo.to_bytes(), IOType::Coin as usize);
+        }
+    }
+
+    #[test]
+    fn rpc_tx_relay_test() {
+        use crate::rpcserver::service::set_zkos_oracle_url;
+        use crate::rpcserver::RelayConfig;
+        use quisquislib::keys::{PublicKey, SecretKey};
+        use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+        use transaction::verify_relayer::deploy_relayer_contract;
+        use zkvm::zkos_types::OutputData;
+
+        set_zkos_oracle_url(&fake_oracle());
+        let peer_config = |url: &str| RelayConfig {
+            peers: vec![url::Url::parse(url).unwrap()],
+            max_attempts: 3,
+            backoff: std::time::Duration::from_millis(20),
+        };
+        // the address of the first node is taken before the second one relays to it
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let first_addr = listener.local_addr().unwrap();
+        drop(listener);
+        let first_url = format!("http://{}", first_addr);
+
+        let second = RpcNode::new(
+            Arc::new(UtxoState::new()),
+            TxRelay::new(peer_config(&first_url)),
+        );
+        let second_server = start_rpc_node(
+            &"127.0.0.1:0".parse().unwrap(),
+            RateLimitConfig::default(),
+            second.clone(),
+        )
+        .unwrap();
+        let second_url = format!("http://{}", second_server.address());
+        let first = RpcNode::new(
+            Arc::new(UtxoState::new()),
+            TxRelay::new(peer_config(&second_url)),
+        );
+        let _first_server =
+            start_rpc_node(&first_addr, RateLimitConfig::default(), first.clone()).unwrap();
+
+        // a relayer deploy tx whose input coin both nodes hold
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let owner = address::Address::standard_address(address::Network::default(), pk);
+        let (tx, _, _) = deploy_relayer_contract(
+            &sk,
+            Utxo::random(),
+            &owner.as_hex(),
+            1000,
+            Scalar::random(&mut rng),
+            500,
+            1,
+        )
+        .unwrap();
+        let input = tx.get_tx_inputs()[0].clone();
+        let coin = Output::coin(OutputData::coin(input.as_out_coin().unwrap().clone()));
+        for node in [&first, &second] {
+            node.state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    input.get_utxo().to_bytes(),
+                    coin.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+        }
+        let txid = tx_id(&tx);
+        let hex_tx = hex::encode(tx.to_bytes());
+
+        // submitted twice to the first node, relayed once
+        for _ in 0..2 {
+            let (_, response) = post(&first_url, call("txCommit", &hex_tx), "10.0.0.17");
+            assert_eq!(response["result"], "committed");
+        }
+        let relayed = |url: &str| {
+            let (_, response) = post(url, call("getPeers", ""), "10.0.0.18");
+            serde_json::from_value::<Vec<PeerStats>>(response["result"].clone()).unwrap()
+        };
+        for _ in 0..100 {
+            if relayed(&first_url)[0].relayed > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(relayed(&first_url)[0].relayed, 1);
+        assert_eq!(relayed(&first_url)[0].failed, 0);
+
+        let (_, response) = post(&second_url, call("getMempool", ""), "10.0.0.19");
+        let entries = response["result"]["entries"].as_array().unwrap();
+        let pending = entries
+            .iter()
+            .filter(|entry| entry["txid"] == txid.as_str());
+        assert_eq!(pending.count(), 1);
+        // the second node does not relay the tx back
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(relayed(&second_url)[0].relayed, 0);
+        assert_eq!(relayed(&second_url)[0].failed, 0);
+
+        // a relayed tx has to keep its txid
+        let response = reqwest::blocking::Client::new()
+            .post(&second_url)
+            .header("Content-Type", "application/json")
+            .header(RELAYED_HEADER, "another txid")
+            .body(call("txCommit", &hex_tx))
+            .send()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .unwrap();
+        assert_eq!(response["error"]["message"], "Relayed txid mismatch");
+    }
+
+    #[test]
+    fn rpc_tx_status_test() {
+        use crate::rpcserver::service::set_zkos_oracle_url;
+        use crate::rpcserver::{TxStatus, TxStatusRecord, TxStatusView};
+        use quisquislib::keys::{PublicKey, SecretKey};
+        use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+        use transaction::reference_tx::create_dark_reference_transaction;
+        use transaction::verify_relayer::deploy_relayer_contract;
+        use utxo_in_memory::blockoperations::blockprocessing::{Block, TransactionMessage};
+        use utxo_in_memory::pgsql::MemoryStore;
+        use zkvm::zkos_types::OutputData;
+
+        set_zkos_oracle_url(&fake_oracle());
+        let node = RpcNode::new(
+            Arc::new(UtxoState::with_store(Arc::new(MemoryStore::new()))),
+            TxRelay::new(Default::default()),
+        );
+        let server = start_rpc_node(
+            &"127.0.0.1:0".parse().unwrap(),
+            RateLimitConfig::default(),
+            node.clone(),
+        )
+        .unwrap();
+        let url = format!("http://{}", server.address());
+        let status = |txid: &str| {
+            let (_, response) = post(&url, call("txStatus", txid), "10.0.0.22");
+            serde_json::from_value::<TxStatusView>(response["result"].clone()).unwrap()
+        };
+        let history = |txid: &str| {
+            let (_, response) = post(&url, call("getTxStatusHistory", txid), "10.0.0.22");
+            let records: Vec<TxStatusRecord> =
+                serde_json::from_value(response["result"].clone()).unwrap();
+            records
+                .into_iter()
+                .map(|record| record.status)
+                .collect::<Vec<TxStatus>>()
+        };
+
+        // a relayer deploy tx whose input coin the node holds
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let owner = address::Address::standard_address(address::Network::default(), pk);
+        let (tx, _, _) = deploy_relayer_contract(
+            &sk,
+            Utxo::random(),
+            &owner.as_hex(),
+            1000,
+            Scalar::random(&mut rng),
+            500,
+            1,
+        )
+        .unwrap();
+        let input = tx.get_tx_inputs()[0].clone();
+        let coin = Output::coin(OutputData::coin(input.as_out_coin().unwrap().clone()));
+        node.state
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .add(input.get_utxo().to_bytes(), coin, IOType::Coin as usize)
+            .unwrap();
+        let txid = tx_id(&tx);
+        assert_eq!(status(&txid).status, TxStatus::Unknown);
+
+        // submit -> block inclusion
+        let hex_tx = hex::encode(tx.to_bytes());
+        let (_, response) = post(&url, call("txCommit", &hex_tx), "10.0.0.22");
+        assert_eq!(response["result"], "committed");
+        let view = status(&txid);
+        assert_eq!(view.status, TxStatus::Broadcast);
+        assert_eq!(view.legacy_status, "broadcast");
+        assert_eq!(
+            history(&txid),
+            vec![TxStatus::Received, TxStatus::Queued, TxStatus::Broadcast]
+        );
+        let result = node.state.process_block(Block {
+            block_hash: "tx_status".to_string(),
+            block_height: 900_961,
+            transactions: vec![TransactionMessage {
+                tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+                tx_id: txid.clone(),
+                tx_byte_code: Some(hex::encode(bincode::serialize(&tx).unwrap())),
+                zk_oracle_address: None,
+                mint_or_burn: None,
+                btc_value: None,
+                qq_account: None,
+                encrypt_scalar: None,
+                twilight_address: None,
+            }],
+            ..Default::default()
+        });
+        assert_eq!(result.suceess_tx.len(), 1);
+        let confirmed = TxStatus::Confirmed {
+            height: 900_961,
+            block_index: 0,
+        };
+        // the tagged status, with the plain status of the legacy clients
+        let (_, response) = post(&url, call("txStatus", &txid), "10.0.0.22");
+        assert_eq!(response["result"]["status"]["status"], "confirmed");
+        assert_eq!(response["result"]["status"]["height"], 900_961);
+        assert_eq!(response["result"]["legacy_status"], "confirmed");
+        assert_eq!(history(&txid).last(), Some(&confirmed));
+        assert_eq!(history(&txid).len(), 4);
+
+        // submit -> failed verification, the inputs are not in the utxo set
+        let rejected = create_dark_reference_transaction();
+        let rejected_txid = tx_id(&rejected);
+        let hex_tx = hex::encode(rejected.to_bytes());
+        let (_, response) = post(&url, call("txCommit", &hex_tx), "10.0.0.22");
+        let reason = response["result"].as_str().unwrap().to_string();
+        assert!(reason.contains("Error"));
+        assert_eq!(
+            history(&rejected_txid),
+            vec![TxStatus::Received, TxStatus::Failed { reason }]
+        );
+        assert_eq!(status(&rejected_txid).legacy_status, "failed");
+
+        // legacy clients send the txid as an object
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "txStatus",
+            "params": {"txid": txid},
+            "id": 1
+        })
+        .to_string();
+        let (_, response) = post(&url, body, "10.0.0.22");
+        assert_eq!(response["result"]["legacy_status"], "confirmed");
+        let (_, response) = post(&url, call("txStatus", ""), "10.0.0.22");
+        assert!(response["error"].is_object());
+    }
+
+    #[test]
+    fn rpc_client_coverage_test() {
+        use crate::rpcclient::method::Method;
+        use crate::rpcclient::typed::TYPED_METHODS;
+        use std::collections::HashSet;
+
+        // every served method has a client method and a typed request, and the other way around
+        let io = rpc_handler(
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            RpcNode::global(),
+        );
+        let served: HashSet<&str> = io.iter().map(|(name, _)| name.as_str()).collect();
+        for name in served.iter() {
+            assert!(
+                serde_json::from_value::<Method>(serde_json::json!(name)).is_ok(),
+                "{} has no client method",
+                name
+            );
+        }
+        let typed: HashSet<&str> = TYPED_METHODS.iter().copied().collect();
+        assert_eq!(typed.len(), TYPED_METHODS.len());
+        assert_eq!(served, typed);
+    }
 }
@@ -1,4 +1,5 @@
-use super::threadpool::ThreadPool;
+use super::threadpool::{env_or, QueueFull, ThreadPool, DEFAULT_QUEUE_CAPACITY};
+use super::validation::fee_rate;
 use super::MintOrBurnTx;
 use address::{Address, Network};
 use curve25519_dalek::scalar::Scalar;
@@ -10,46 +11,123 @@ use sha3::{Digest, Keccak256};
 use std::error::Error;
 // use std::sync::mpsc;
 // use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
 // use std::thread;
 use crate::TransactionStatusId;
+use transaction::oracle::OracleConfig;
 use transaction::Transaction;
+use utxo_in_memory::blockoperations::txstatus::TxStatus;
+use utxo_in_memory::{global_state, UtxoState};
+use utxo_in_memory::config::config_value;
 use prometheus::{Encoder, TextEncoder, Counter, Gauge, register_counter, register_gauge};
 // #[macro_use]
 // extern crate lazy_static;
 lazy_static! {
-    pub static ref THREADPOOL_RPC_QUEUE: Mutex<ThreadPool> =
-        Mutex::new(ThreadPool::new(10, String::from("THREADPOOL_RPC_Queue")));
+    pub static ref THREADPOOL_RPC_QUEUE: Mutex<ThreadPool> = {
+        Mutex::new(ThreadPool::with_capacity(
+            env_or("THREADPOOL_RPC_QUEUE_SIZE", 10),
+            env_or("THREADPOOL_RPC_QUEUE_CAPACITY", DEFAULT_QUEUE_CAPACITY),
+            String::from("THREADPOOL_RPC_Queue"),
+        ))
+    };
     pub static ref TOTAL_TX_COUNTER: Gauge = register_gauge!("tx_counter", "A counter for tx").unwrap();
+    /// Oracle signing the tx_data of price sensitive programs, None if ORACLE_ADDRESS is not set
+    pub static ref ORACLE_CONFIG: Option<OracleConfig> = {
+        OracleConfig::from_lookup(config_value).expect("Invalid oracle configuration")
+    };
+    /// Base url of the Zkos Oracle the txs are committed to
+    static ref ZKOS_ORACLE_URL: RwLock<String> = {
+        let url = config_value("ZKOS_ORACLE_URL")
+            .unwrap_or_else(|| DEFAULT_ZKOS_ORACLE_URL.to_string());
+        RwLock::new(url.trim_end_matches('/').to_string())
+    };
 }
-pub fn tx_queue(transaction: Transaction, fee: u64) {
-    {
-        let queue = THREADPOOL_RPC_QUEUE.lock().unwrap();
-        queue.execute(move || {
-            tx_commit(transaction, fee);
-        });
-    } // Mutex lock is automatically dropped here
+
+pub const DEFAULT_ZKOS_ORACLE_URL: &str = "http://0.0.0.0:7000";
+
+/// Points the tx commits to another Zkos Oracle, e.g. a fake one in tests.
+pub fn set_zkos_oracle_url(url: &str) {
+    *ZKOS_ORACLE_URL.write().unwrap() = url.trim_end_matches('/').to_string();
 }
 
-pub async fn tx_commit(transaction: Transaction, fee: u64) -> Result<String, String> {
-    let client = Client::new();
-    let url = "http://0.0.0.0:7000/transaction";
+fn zkos_oracle_url(path: &str) -> String {
+    format!("{}/{}", ZKOS_ORACLE_URL.read().unwrap(), path)
+}
+/// Queues the tx for commit. Fails with `QueueFull` instead of blocking the caller
+/// when the queue is full, the client should retry later.
+pub fn tx_queue(transaction: Transaction, fee: u64) -> Result<(), QueueFull> {
+    let queue = THREADPOOL_RPC_QUEUE.lock().unwrap();
+    queue.try_execute(move || {
+        tx_commit(&global_state(), transaction, fee);
+    })
+    // Mutex lock is automatically dropped here
+}
 
-    let serialized: Vec<u8> = bincode::serialize(&transaction).unwrap();
-    let tx_hex = hex::encode(serialized.clone());
+/// Id a tx is committed under, the keccak256 of its encoding.
+pub fn tx_id(transaction: &Transaction) -> String {
     //Creating dummy TxiD of ZKOS Transaction to be used as transaction id
     let mut hasher = Keccak256::new();
-    hasher.update(&serialized);
-    let checksum = hasher.finalize();
+    hasher.update(&transaction.to_bytes());
+    hex::encode(hasher.finalize().to_vec())
+}
+
+/// Commits the tx to the Zkos Oracle, its inputs pending in the mempool of `state` until
+/// it is in a block. The transitions of the tx are recorded in `state`, see
+/// [`utxo_in_memory::blockoperations::txstatus`].
+pub async fn tx_commit(
+    state: &UtxoState,
+    transaction: Transaction,
+    fee: u64,
+) -> Result<String, String> {
+    let mempool = &state.mempool;
+    let client = Client::new();
+    let url = zkos_oracle_url("transaction");
+
+    let tx_hex = hex::encode(transaction.to_bytes());
     let payload = Payload {
-        id: hex::encode(checksum.to_vec()),
+        id: tx_id(&transaction),
         tx: tx_hex,
         fee,
     };
+    let txid = payload.id.clone();
+    // the inputs are pending spends until the tx is in a block, a tx paying enough
+    // evicts the pending txs spending them
+    let evicted = mempool.lock().unwrap().insert_or_replace(
+        &txid,
+        &transaction.get_tx_inputs(),
+        fee_rate(&transaction),
+        std::time::Instant::now(),
+    );
+    match evicted {
+        Ok(evicted) => {
+            state.record_tx_status(&txid, TxStatus::Queued);
+            for replaced in evicted {
+                println!("tx {} replaces pending tx {}", txid, replaced);
+                let status = TxStatus::Replaced {
+                    by_txid: txid.clone(),
+                };
+                state.record_tx_status(&replaced, status);
+            }
+        }
+        Err(e) => {
+            let reason = e.to_string();
+            state.record_tx_status(&txid, TxStatus::Failed { reason });
+            return Err(format!(r#"{{"error": "{}"}}"#, e));
+        }
+    }
+    // a tx the Zkos Oracle did not accept is no longer pending
+    let rejected = |reason: &str| {
+        mempool.lock().unwrap().remove(&txid);
+        let status = TxStatus::Failed {
+            reason: reason.to_string(),
+        };
+        state.record_tx_status(&txid, status);
+    };
     // let json_data = serde_json::to_string(&payload)?;
     let json_data = match serde_json::to_string(&payload) {
         Ok(json_data) => json_data,
         Err(e) => {
+            rejected("error in transaction Payload (faulty data)");
             return Err(format!(
                 r#"{{"error": "error in transaction Payload (faulty data)"}}"#
             ))
@@ -64,12 +142,16 @@ pub async fn tx_commit(transaction: Transaction, fee: u64) -> Result<String, Str
         .await
     {
         Ok(response) => response,
-        Err(e) => return Err(format!(r#"{{"error": "error in commiting transaction"}}"#)),
+        Err(e) => {
+            rejected("error in commiting transaction");
+            return Err(format!(r#"{{"error": "error in commiting transaction"}}"#));
+        }
     };
     let response_body: String = match response.text().await {
         Ok(response_body) => response_body,
         Err(e) => return Err(format!(r#"{{"error": "error in commiting transaction"}}"#)),
     };
+    state.record_tx_status(&txid, TxStatus::Broadcast);
     TOTAL_TX_COUNTER.inc();
     Ok(response_body)
 }
@@ -81,12 +163,12 @@ pub async fn mint_burn_tx_initiate(
     twilight_address: String,
 ) -> Result<String, Box<dyn Error>> {
     let client = Client::new();
-    let url = "http://0.0.0.0:7000/burnmessage";
+    let url = zkos_oracle_url("burnmessage");
 
     // convert qq_account into hex string
     let qq_account_hex = account_to_hex_str(qq_account, Network::default());
     // convert encrypt_scalar into hex string
-    let encrypt_scalar_hex = hex::encode(encrypt_scalar.to_bytes());
+    let encrypt_scalar_hex = zkvm::encoding::scalar_to_hex(encrypt_scalar);
     // create payload
     let payload = MintOrBurnPayload {
         btc_value: value,
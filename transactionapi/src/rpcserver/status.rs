@@ -0,0 +1,229 @@
+//! Signed status of the node, for external availability monitors.
+//!
+//! `getSignedStatus` returns the height, the utxo counts and the last processed block
+//! of the node, signed with the node key so that a proxy between the node and a monitor
+//! can not pass off a stalled node as a healthy one. The status and the signing time are
+//! signed as their bincode encoding, see [`status_transcript`], and a monitor checks them
+//! with [`crate::rpcclient::status::verify_signed_status`] against the key it pinned.
+//!
+//! The node key is a starsig secret scalar, hex encoded in the file of `NODE_KEY_PATH`
+//! and generated on the first start. `rotateNodeKey` reads the file again, like a reload
+//! on SIGHUP: the operator replaces or removes the file, a removed file is replaced by a
+//! new key, and the node signs with the key of the file without a restart.
+
+use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
+use serde::{Deserialize, Serialize};
+use starsig::{Signature, VerificationKey};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+use utxo_in_memory::blockoperations::blockstats::BLOCK_STATS;
+use utxo_in_memory::UtxoState;
+use zkvm::zkos_types::IOType;
+
+/// Label of the transcript a status is signed in.
+pub const NODE_STATUS_LABEL: &[u8] = b"ZkOS.NodeStatus";
+
+/// The view of the chain of a node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeStatus {
+    pub block_height: u64,
+    pub utxo_coin_count: u64,
+    pub utxo_memo_count: u64,
+    pub utxo_state_count: u64,
+    /// Height of the last processed block, None until a block is processed
+    pub last_block_height: Option<u64>,
+    /// Unix time the last block was processed at
+    pub last_block_time: Option<u64>,
+}
+
+impl NodeStatus {
+    /// Status of `state`, read from a view of its last committed block.
+    pub fn of(state: &UtxoState) -> Self {
+        let view = state.utxo_storage.lock().unwrap().read_view();
+        let last_block = BLOCK_STATS.lock().unwrap().latest(1).pop();
+        NodeStatus {
+            block_height: view.block_height as u64,
+            utxo_coin_count: view.count(IOType::Coin as usize),
+            utxo_memo_count: view.count(IOType::Memo as usize),
+            utxo_state_count: view.count(IOType::State as usize),
+            last_block_height: last_block.as_ref().map(|block| block.block_height),
+            last_block_time: last_block.map(|block| block.processed_at),
+        }
+    }
+}
+
+/// A status signed by the node key at the unix time `timestamp`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SignedStatus {
+    pub status: NodeStatus,
+    pub signature_hex: String,
+    pub pubkey_hex: String,
+    pub timestamp: u64,
+}
+
+/// Result of a `rotateNodeKey` call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyRotation {
+    pub previous_pubkey_hex: String,
+    pub pubkey_hex: String,
+}
+
+#[derive(Error, Debug)]
+pub enum NodeKeyError {
+    #[error("Io error {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid node key, {0}")]
+    InvalidKey(String),
+}
+
+/// Transcript of `status` signed at `timestamp`, the same for the node and the monitors.
+pub fn status_transcript(status: &NodeStatus, timestamp: u64) -> Transcript {
+    let message = bincode::serialize(&(status, timestamp)).expect("status is serializable");
+    let mut transcript = Transcript::new(NODE_STATUS_LABEL);
+    transcript.append_message(b"status", &message);
+    transcript
+}
+
+/// Secret key signing the status of the node.
+pub struct NodeKey {
+    // file of the key, None for a key living as long as the process
+    path: Option<PathBuf>,
+    secret: Mutex<Scalar>,
+}
+
+impl NodeKey {
+    /// Random key, not stored. The monitors can not pin it across restarts.
+    pub fn ephemeral() -> Self {
+        NodeKey {
+            path: None,
+            secret: Mutex::new(Scalar::random(&mut rand::thread_rng())),
+        }
+    }
+
+    /// Key of the file at `path`, a new key is written to it if there is no such file.
+    /// A key file readable by other users is refused.
+    pub fn load_or_generate(path: &Path) -> Result<Self, NodeKeyError> {
+        Ok(NodeKey {
+            secret: Mutex::new(read_or_generate_key(path)?),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Replaces the key with the one of the key file, see the module docs. An
+    /// ephemeral key is replaced by a new random key. The key is kept on an error.
+    pub fn rotate(&self) -> Result<KeyRotation, NodeKeyError> {
+        let secret = match &self.path {
+            Some(path) => read_or_generate_key(path)?,
+            None => Scalar::random(&mut rand::thread_rng()),
+        };
+        let previous_pubkey_hex = self.pubkey_hex();
+        *self.secret.lock().unwrap() = secret;
+        Ok(KeyRotation {
+            previous_pubkey_hex,
+            pubkey_hex: self.pubkey_hex(),
+        })
+    }
+
+    pub fn pubkey_hex(&self) -> String {
+        let secret = self.secret.lock().unwrap();
+        hex::encode(VerificationKey::from_secret(&secret).to_bytes())
+    }
+
+    /// Signs `status` at the unix time `timestamp`.
+    pub fn sign(&self, status: NodeStatus, timestamp: u64) -> SignedStatus {
+        let secret = *self.secret.lock().unwrap();
+        let signature = Signature::sign(&mut status_transcript(&status, timestamp), secret);
+        SignedStatus {
+            status,
+            signature_hex: hex::encode(signature.to_bytes()),
+            pubkey_hex: hex::encode(VerificationKey::from_secret(&secret).to_bytes()),
+            timestamp,
+        }
+    }
+}
+
+fn read_or_generate_key(path: &Path) -> Result<Scalar, NodeKeyError> {
+    if !path.exists() {
+        let secret = Scalar::random(&mut rand::thread_rng());
+        write_key(path, &secret)?;
+        println!("Generated a new node key at {}", path.display());
+        return Ok(secret);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(NodeKeyError::InvalidKey(format!(
+                "{} is accessible by other users, mode {:o}",
+                path.display(),
+                mode & 0o777
+            )));
+        }
+    }
+    let bytes = hex::decode(std::fs::read_to_string(path)?.trim())
+        .map_err(|_| NodeKeyError::InvalidKey("expected a hex string".to_string()))?;
+    if bytes.len() != 32 {
+        return Err(NodeKeyError::InvalidKey("expected 32 bytes".to_string()));
+    }
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&bytes);
+    Scalar::from_canonical_bytes(scalar)
+        .ok_or_else(|| NodeKeyError::InvalidKey("not a canonical scalar".to_string()))
+}
+
+// the key file is created readable by its owner only
+fn write_key(path: &Path, secret: &Scalar) -> Result<(), NodeKeyError> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    std::io::Write::write_all(&mut file, hex::encode(secret.as_bytes()).as_bytes())?;
+    Ok(())
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn node_key_file_test() {
+        let path = std::env::temp_dir().join(format!("zkos-node-key-{}", uuid::Uuid::new_v4()));
+        let key = NodeKey::load_or_generate(&path).unwrap();
+        let pubkey = key.pubkey_hex();
+        // the key is read back on the next start
+        assert_eq!(
+            NodeKey::load_or_generate(&path).unwrap().pubkey_hex(),
+            pubkey
+        );
+        assert_eq!(key.rotate().unwrap().pubkey_hex, pubkey);
+
+        // a removed key file is replaced on rotation
+        std::fs::remove_file(&path).unwrap();
+        let rotation = key.rotate().unwrap();
+        assert_eq!(rotation.previous_pubkey_hex, pubkey);
+        assert_ne!(rotation.pubkey_hex, pubkey);
+        assert_eq!(key.pubkey_hex(), rotation.pubkey_hex);
+
+        // an invalid key file keeps the current key
+        std::fs::write(&path, "ff".repeat(32)).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert!(matches!(key.rotate(), Err(NodeKeyError::InvalidKey(_))));
+        assert_eq!(key.pubkey_hex(), rotation.pubkey_hex);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
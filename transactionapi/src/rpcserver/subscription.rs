@@ -0,0 +1,262 @@
+//! WebSocket endpoint pushing the utxos added and removed for a set of addresses.
+//!
+//! A client connects to `/ws/address` and sends
+//! `{"method": "subscribe", "addresses": [...], "since_height": 1200}`, with
+//! `since_height` optional. The server answers `{"subscribed": id}` and then pushes
+//! one `UtxoEvent` per text message. A later subscribe replaces the subscription.
+//!
+//! Events are queued per connection in a bounded outbox. If a client does not keep
+//! up the outbox overflows and the connection is closed, the client reconnects with
+//! the height of the last event it received as `since_height`.
+
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{accept_hdr, Error as WsError, Message, WebSocket};
+use utxo_in_memory::blockoperations::subscriptions::UTXO_SUBSCRIPTIONS;
+
+/// Path of the subscription endpoint.
+pub const WS_ADDRESS_PATH: &str = "/ws/address";
+
+// interval at which the outbox of a connection is flushed
+const WS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum SubscriptionRequest {
+    Subscribe {
+        addresses: Vec<String>,
+        #[serde(default)]
+        since_height: Option<u64>,
+    },
+    Unsubscribe,
+}
+
+/// Runs the subscription server on `addr`, the `WS_BIND_ADDRESS` of [`crate::config::ApiConfig`].
+pub fn ws_subscription_server(addr: SocketAddr) {
+    eprintln!("Starting websocket subscription server @ {}", addr);
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to start websocket subscription server: {}", e);
+            return;
+        }
+    };
+    serve_ws_subscriptions(listener);
+}
+
+/// Starts the subscription server on `addr` in a background thread and
+/// returns the bound address.
+pub fn start_ws_subscription_server(addr: &SocketAddr) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    thread::spawn(move || serve_ws_subscriptions(listener));
+    Ok(local_addr)
+}
+
+// one thread per connection
+fn serve_ws_subscriptions(listener: TcpListener) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || handle_ws_connection(stream));
+            }
+            Err(e) => eprintln!("websocket connection failed: {}", e),
+        }
+    }
+}
+
+fn handle_ws_connection(stream: TcpStream) {
+    let check_path = |request: &Request, response: Response| {
+        if request.uri().path() == WS_ADDRESS_PATH {
+            Ok(response)
+        } else {
+            let mut error = ErrorResponse::new(Some("Not found".to_string()));
+            *error.status_mut() = tungstenite::http::StatusCode::NOT_FOUND;
+            Err(error)
+        }
+    };
+    let mut socket = match accept_hdr(stream, check_path) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("websocket handshake failed: {}", e);
+            return;
+        }
+    };
+    // reads time out so the outbox is flushed while the client is silent
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(WS_POLL_INTERVAL)) {
+        eprintln!("websocket read timeout not set: {}", e);
+        return;
+    }
+
+    let mut subscription: Option<u64> = None;
+    if let Err(e) = run_ws_connection(&mut socket, &mut subscription) {
+        if !matches!(e, WsError::ConnectionClosed | WsError::AlreadyClosed) {
+            eprintln!("websocket connection error: {}", e);
+        }
+    }
+    // disconnect cleanup
+    if let Some(id) = subscription {
+        UTXO_SUBSCRIPTIONS.lock().unwrap().unsubscribe(id);
+    }
+}
+
+fn run_ws_connection(
+    socket: &mut WebSocket<TcpStream>,
+    subscription: &mut Option<u64>,
+) -> Result<(), WsError> {
+    loop {
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                let reply = handle_ws_request(&text, subscription);
+                socket.write_message(Message::Text(reply.to_string()))?;
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(WsError::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+
+        let id = match subscription {
+            Some(id) => *id,
+            None => continue,
+        };
+        let drained = UTXO_SUBSCRIPTIONS.lock().unwrap().drain(id);
+        match drained {
+            Ok(events) => {
+                for event in events {
+                    let event = serde_json::to_string(&event).expect("Failed to serialize to JSON");
+                    socket.write_message(Message::Text(event))?;
+                }
+            }
+            Err(reason) => {
+                socket.close(Some(CloseFrame {
+                    code: CloseCode::Again,
+                    reason: reason.into(),
+                }))?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+// answers a request of the client, replacing the subscription of the connection
+fn handle_ws_request(text: &str, subscription: &mut Option<u64>) -> serde_json::Value {
+    let request: SubscriptionRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return serde_json::json!({ "error": format!("Invalid request, {}", e) }),
+    };
+    let mut registry = UTXO_SUBSCRIPTIONS.lock().unwrap();
+    if let Some(id) = subscription.take() {
+        registry.unsubscribe(id);
+    }
+    match request {
+        SubscriptionRequest::Subscribe {
+            addresses,
+            since_height,
+        } => match registry.subscribe(addresses, since_height) {
+            Ok(id) => {
+                *subscription = Some(id);
+                serde_json::json!({ "subscribed": id })
+            }
+            Err(e) => serde_json::json!({ "error": e }),
+        },
+        SubscriptionRequest::Unsubscribe => serde_json::json!({ "unsubscribed": true }),
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+    use utxo_in_memory::blockoperations::blockprocessing::{
+        process_block_for_utxo_insert, Block, TransactionMessage,
+    };
+    use utxo_in_memory::init_utxo;
+
+    // mint of a coin to a new address, returns the message and the address
+    fn mint_message() -> (TransactionMessage, String) {
+        let (acc, _prv) = Account::generate_random_account_with_value(Scalar::from(100u64));
+        let (pk, encrypt) = acc.get_account();
+        let owner = address::Address::standard_address(address::Network::default(), pk);
+        let mut qq_account = owner.as_bytes();
+        qq_account.extend_from_slice(&encrypt.to_bytes());
+        let mut tx_id = uuid::Uuid::new_v4().as_bytes().to_vec();
+        tx_id.extend_from_slice(uuid::Uuid::new_v4().as_bytes());
+        let message = TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgMintBurnTradingBtc".to_string(),
+            tx_id: hex::encode(tx_id),
+            tx_byte_code: None,
+            zk_oracle_address: None,
+            mint_or_burn: Some(true),
+            btc_value: Some("100".to_string()),
+            qq_account: Some(hex::encode(qq_account)),
+            encrypt_scalar: None,
+            twilight_address: None,
+        };
+        (message, owner.as_hex())
+    }
+
+    fn read_text(socket: &mut WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>) -> String {
+        loop {
+            match socket.read_message().unwrap() {
+                Message::Text(text) => return text,
+                _ => continue,
+            }
+        }
+    }
+
+    // cargo test -- --nocapture --test ws_subscription_test --test-threads 1
+    #[test]
+    fn ws_subscription_test() {
+        init_utxo();
+        let addr = start_ws_subscription_server(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let (mut socket, _) =
+            tungstenite::connect(format!("ws://{}{}", addr, WS_ADDRESS_PATH)).unwrap();
+
+        let (message, owner) = mint_message();
+        let request = serde_json::json!({ "method": "subscribe", "addresses": [owner] });
+        socket
+            .write_message(Message::Text(request.to_string()))
+            .unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&read_text(&mut socket)).unwrap();
+        assert!(reply["subscribed"].is_u64());
+
+        let block = Block {
+            block_hash: "subscription".to_string(),
+            block_height: 800_501,
+            transactions: vec![message.clone()],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx.len(), 1);
+
+        let event: serde_json::Value = serde_json::from_str(&read_text(&mut socket)).unwrap();
+        assert_eq!(event["event"], "added");
+        assert_eq!(event["address"], owner);
+        assert_eq!(event["height"], 800_501);
+        assert_eq!(event["utxo"], format!("{}:0", message.tx_id));
+
+        // exactly one event is pushed
+        if let tungstenite::stream::MaybeTlsStream::Plain(stream) = socket.get_mut() {
+            stream.set_read_timeout(Some(5 * WS_POLL_INTERVAL)).unwrap();
+        }
+        assert!(socket.read_message().is_err());
+    }
+
+    #[test]
+    fn ws_subscription_path_test() {
+        let addr = start_ws_subscription_server(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(tungstenite::connect(format!("ws://{}/ws/unknown", addr)).is_err());
+    }
+}
@@ -0,0 +1,522 @@
+//! TLS and mutual TLS of the JSON-RPC server.
+//!
+//! jsonrpc-http-server only serves plain http, so a node with `RPC_TLS_CERT_PATH`
+//! and `RPC_TLS_KEY_PATH` set accepts the connections itself, does the rustls
+//! handshake and serves them with hyper, through the io handler and the compression
+//! middleware of the plain server.
+//!
+//! With `RPC_TLS_CLIENT_CA_PATH` set the server asks for a client certificate
+//! issued by that CA. The certificate is optional at the handshake, so the public
+//! read methods stay open to anonymous clients, and the `ClientCertMiddleware`
+//! rejects calls of the methods of `RPC_TLS_CLIENT_CERT_METHODS` made without one,
+//! by default txCommit and the admin methods. The admin methods still need an admin
+//! API key.
+//!
+//! The certificates are read again on SIGHUP and by `reloadTls`, the running
+//! config is kept if the new files are invalid. Open connections keep the config
+//! of their handshake.
+
+use super::compression::{json_response, CompressionConfig, CompressionMiddleware};
+use super::ratelimit::{body_too_large, ClientMeta, PeerAddr, RPC_REJECTED_REQUESTS};
+use jsonrpc_core::futures::channel::oneshot;
+use jsonrpc_core::futures::future::{self, Either, Ready};
+use jsonrpc_core::types::error::{Error as JsonRpcError, ErrorCode};
+use jsonrpc_core::{Call, MetaIoHandler, Metadata, Middleware, Output};
+use jsonrpc_http_server::hyper::server::conn::Http;
+use jsonrpc_http_server::hyper::service::service_fn;
+use jsonrpc_http_server::{hyper, RequestMiddleware, RequestMiddlewareAction};
+use prometheus::{register_counter, Counter};
+use rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use serde_derive::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use thiserror::Error;
+use tokio_rustls::TlsAcceptor;
+
+/// JSON-RPC error code of a call needing a client certificate made without one.
+pub const CLIENT_CERT_REQUIRED_CODE: i64 = -32008;
+
+/// Methods needing a client certificate unless `RPC_TLS_CLIENT_CERT_METHODS` is set.
+pub const DEFAULT_CLIENT_CERT_METHODS: &[&str] = &[
+    "txCommit",
+    "registerScriptContract",
+    "restorePrunedOutput",
+    "compactStorage",
+    "rotateNodeKey",
+    "getAuditLog",
+    "reloadTls",
+    "resumeBlockProcessing",
+];
+
+lazy_static! {
+    pub static ref RPC_TLS_HANDSHAKE_FAILURES: Counter = register_counter!(
+        "rpc_tls_handshake_failures",
+        "TLS handshakes of the rpc server which failed"
+    )
+    .unwrap();
+}
+
+/// Certificate files of the TLS rpc server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// PEM certificate chain of the server, leaf first
+    pub cert_path: PathBuf,
+    /// PEM private key of the server, PKCS#8, PKCS#1 or SEC1
+    pub key_path: PathBuf,
+    /// PEM certificates of the CAs issuing the client certificates, no mutual TLS if None
+    pub client_ca_path: Option<PathBuf>,
+    /// Methods only served to clients with a certificate
+    pub client_cert_methods: HashSet<String>,
+}
+
+impl TlsConfig {
+    /// Config of the server certificate, without mutual TLS.
+    pub fn new(cert_path: PathBuf, key_path: PathBuf) -> Self {
+        TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+            client_cert_methods: DEFAULT_CLIENT_CERT_METHODS
+                .iter()
+                .map(|method| method.to_string())
+                .collect(),
+        }
+    }
+
+    /// The config asking for a client certificate issued by the CAs of `client_ca_path`.
+    pub fn with_client_ca(mut self, client_ca_path: PathBuf) -> Self {
+        self.client_ca_path = Some(client_ca_path);
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("Io error reading {path}, {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("No certificate in {0}")]
+    NoCertificate(String),
+
+    #[error("No private key in {0}")]
+    NoPrivateKey(String),
+
+    #[error("Invalid client CA certificate, {0}")]
+    InvalidClientCa(String),
+
+    #[error("Invalid TLS config, {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Result of a `reloadTls` call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TlsReload {
+    /// Sha3-256 of the DER of the server certificate now served
+    pub cert_fingerprint: String,
+    /// Whether client certificates are asked for
+    pub client_ca: bool,
+}
+
+/// Sha3-256 of the DER of a certificate, the client certificate of a request is
+/// known by it.
+pub fn cert_fingerprint(der: &[u8]) -> String {
+    hex::encode(Sha3_256::digest(der))
+}
+
+/// Fingerprint of the verified client certificate of a connection, an extension of
+/// the requests made over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCert(pub String);
+
+/// Server config of the certificate files, reloadable while the server runs.
+pub struct ServerTls {
+    config: TlsConfig,
+    current: RwLock<(TlsAcceptor, TlsReload)>,
+}
+
+impl ServerTls {
+    /// Reads the certificate files of `config`.
+    pub fn load(config: TlsConfig) -> Result<Self, TlsError> {
+        let current = read_server_config(&config)?;
+        Ok(ServerTls {
+            config,
+            current: RwLock::new(current),
+        })
+    }
+
+    /// Reads the certificate files again, the current config is kept on an error.
+    pub fn reload(&self) -> Result<TlsReload, TlsError> {
+        let current = read_server_config(&self.config)?;
+        let reload = current.1.clone();
+        *self.current.write().unwrap() = current;
+        Ok(reload)
+    }
+
+    pub fn config(&self) -> &TlsConfig {
+        &self.config
+    }
+
+    /// Acceptor of the new connections.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        self.current.read().unwrap().0.clone()
+    }
+
+    /// Fingerprint of the server certificate and client auth of the current config.
+    pub fn current(&self) -> TlsReload {
+        self.current.read().unwrap().1.clone()
+    }
+
+    /// Whether calls of `method` need a client certificate, only with mutual TLS.
+    pub fn requires_client_cert(&self, method: &str) -> bool {
+        self.config.client_ca_path.is_some() && self.config.client_cert_methods.contains(method)
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> TlsError + '_ {
+    move |source| TlsError::Io {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(io_error(path))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let pem = read_file(path)?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(&pem[..])).map_err(io_error(path))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.display().to_string()));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &Path) -> Result<PrivateKey, TlsError> {
+    let pem = read_file(path)?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(&pem[..])).map_err(io_error(path))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::NoPrivateKey(path.display().to_string()))
+}
+
+fn read_server_config(config: &TlsConfig) -> Result<(TlsAcceptor, TlsReload), TlsError> {
+    let certs = read_certs(&config.cert_path)?;
+    let key = read_key(&config.key_path)?;
+    let cert_fingerprint = cert_fingerprint(&certs[0].0);
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let server_config = match &config.client_ca_path {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots
+                    .add(&cert)
+                    .map_err(|e| TlsError::InvalidClientCa(e.to_string()))?;
+            }
+            builder
+                .with_client_cert_verifier(
+                    AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed(),
+                )
+                .with_single_cert(certs, key)?
+        }
+        None => builder.with_no_client_auth().with_single_cert(certs, key)?,
+    };
+    let reload = TlsReload {
+        cert_fingerprint,
+        client_ca: config.client_ca_path.is_some(),
+    };
+    Ok((TlsAcceptor::from(Arc::new(server_config)), reload))
+}
+
+/// Rejects the calls needing a client certificate made without one, see the module docs.
+#[derive(Clone)]
+pub struct ClientCertMiddleware {
+    tls: Option<Arc<ServerTls>>,
+}
+
+impl ClientCertMiddleware {
+    /// Middleware of a server with the config `tls`, letting every call through if None.
+    pub fn new(tls: Option<Arc<ServerTls>>) -> Self {
+        ClientCertMiddleware { tls }
+    }
+}
+
+impl<M: Metadata + ClientMeta> Middleware<M> for ClientCertMiddleware {
+    type Future = Ready<Option<jsonrpc_core::Response>>;
+    type CallFuture = Ready<Option<Output>>;
+
+    fn on_call<F, X>(&self, call: Call, meta: M, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, M) -> X + Send + Sync,
+        X: Future<Output = Option<Output>> + Send + 'static,
+    {
+        if let (Call::MethodCall(ref method_call), Some(tls)) = (&call, &self.tls) {
+            if tls.requires_client_cert(&method_call.method) && meta.client_cert().is_none() {
+                RPC_REJECTED_REQUESTS
+                    .with_label_values(&["client_cert"])
+                    .inc();
+                let err = JsonRpcError {
+                    code: ErrorCode::ServerError(CLIENT_CERT_REQUIRED_CODE),
+                    message: format!("Client certificate required for {}", method_call.method),
+                    data: None,
+                };
+                return Either::Left(future::ready(Some(Output::from(
+                    Err(err),
+                    method_call.id.clone(),
+                    method_call.jsonrpc,
+                ))));
+            }
+        }
+        Either::Right(next(call, meta))
+    }
+}
+
+/// Handle of a running TLS rpc server.
+pub struct TlsServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl TlsServer {
+    pub fn address(&self) -> &SocketAddr {
+        &self.address
+    }
+
+    /// Blocks until the server is closed.
+    pub fn wait(self) {
+        let _ = self.thread.join();
+    }
+
+    /// Stops accepting connections and waits for the accept loop to end.
+    pub fn close(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// Serves `handler` over TLS on `addr`, on a runtime of its own. The requests go
+/// through the compression middleware, then the body size check, like on the
+/// plain server.
+pub fn start_tls_server<M, S>(
+    addr: &SocketAddr,
+    tls: Arc<ServerTls>,
+    handler: Arc<MetaIoHandler<M, S>>,
+    extract_meta: fn(&hyper::Request<hyper::Body>) -> M,
+    max_body_size: usize,
+    compression: CompressionConfig,
+) -> std::io::Result<TlsServer>
+where
+    M: Metadata,
+    S: Middleware<M>,
+    S::Future: Unpin,
+    S::CallFuture: Unpin,
+{
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let address = listener.local_addr()?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(5)
+        .thread_name("rpc-tls")
+        .enable_all()
+        .build()?;
+    let compression = Arc::new(CompressionMiddleware::new(
+        handler.clone(),
+        extract_meta,
+        max_body_size,
+        compression,
+    ));
+    let (shutdown, mut closed) = oneshot::channel::<()>();
+    let thread = thread::spawn(move || {
+        runtime.block_on(async move {
+            let listener = match tokio::net::TcpListener::from_std(listener) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("TLS rpc server failed to listen: {}", e);
+                    return;
+                }
+            };
+            #[cfg(unix)]
+            tokio::spawn(reload_on_sighup(tls.clone()));
+            loop {
+                let (stream, peer) = tokio::select! {
+                    _ = &mut closed => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(_) => continue,
+                    },
+                };
+                let acceptor = tls.acceptor();
+                let handler = handler.clone();
+                let compression = compression.clone();
+                tokio::spawn(async move {
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(_) => {
+                            RPC_TLS_HANDSHAKE_FAILURES.inc();
+                            return;
+                        }
+                    };
+                    // the certificate was checked against the client CA at the handshake
+                    let client_cert = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .map(|cert| ClientCert(cert_fingerprint(&cert.0)));
+                    let service = service_fn(move |mut request: hyper::Request<hyper::Body>| {
+                        request.extensions_mut().insert(PeerAddr(peer));
+                        if let Some(client_cert) = client_cert.clone() {
+                            request.extensions_mut().insert(client_cert);
+                        }
+                        respond(
+                            handler.clone(),
+                            compression.clone(),
+                            extract_meta,
+                            max_body_size,
+                            request,
+                        )
+                    });
+                    let _ = Http::new().serve_connection(stream, service).await;
+                });
+            }
+        })
+    });
+    Ok(TlsServer {
+        address,
+        shutdown,
+        thread,
+    })
+}
+
+async fn respond<M, S>(
+    handler: Arc<MetaIoHandler<M, S>>,
+    compression: Arc<CompressionMiddleware<M, S>>,
+    extract_meta: fn(&hyper::Request<hyper::Body>) -> M,
+    max_body_size: usize,
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, hyper::Error>
+where
+    M: Metadata,
+    S: Middleware<M>,
+    S::Future: Unpin,
+    S::CallFuture: Unpin,
+{
+    let request = match compression.on_request(request) {
+        RequestMiddlewareAction::Proceed { request, .. } => request,
+        RequestMiddlewareAction::Respond { response, .. } => return response.await,
+    };
+    if request.method() != hyper::Method::POST {
+        return Ok(hyper::Response::builder()
+            .status(hyper::StatusCode::METHOD_NOT_ALLOWED)
+            .body(hyper::Body::empty())
+            .expect("Failed to build response"));
+    }
+    let meta = extract_meta(&request);
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    if body.len() > max_body_size {
+        return Ok(body_too_large(body.len(), max_body_size));
+    }
+    let response = match handler
+        .handle_request(&String::from_utf8_lossy(&body), meta)
+        .await
+    {
+        Some(response) => format!("{}\n", response),
+        // notifications only
+        None => String::new(),
+    };
+    Ok(json_response(hyper::Body::from(response), None))
+}
+
+/// Reloads the certificates of `tls` on each SIGHUP of the process.
+#[cfg(unix)]
+pub async fn reload_on_sighup(tls: Arc<ServerTls>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            eprintln!("SIGHUP reload of the TLS certificates disabled: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match tls.reload() {
+            Ok(reload) => println!("TLS certificates reloaded, {}", reload.cert_fingerprint),
+            Err(e) => eprintln!("TLS certificates not reloaded: {}", e),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // self-signed certificate and key of localhost, as PEM
+    fn self_signed() -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        (
+            cert.serialize_pem().unwrap(),
+            cert.serialize_private_key_pem(),
+        )
+    }
+
+    fn write_pem(dir: &Path, name: &str, pem: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, pem).unwrap();
+        path
+    }
+
+    #[test]
+    fn server_tls_reload_test() {
+        let dir = std::env::temp_dir().join(format!("zkos-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = self_signed();
+        let cert_path = write_pem(&dir, "cert.pem", &cert);
+        let key_path = write_pem(&dir, "key.pem", &key);
+
+        let tls = ServerTls::load(TlsConfig::new(cert_path.clone(), key_path.clone())).unwrap();
+        let first = tls.current();
+        assert!(!first.client_ca);
+        assert!(!tls.requires_client_cert("txCommit"));
+
+        // a key file without a key keeps the running config
+        write_pem(&dir, "key.pem", &cert);
+        assert!(matches!(tls.reload(), Err(TlsError::NoPrivateKey(_))));
+        assert_eq!(tls.current(), first);
+
+        let (cert, key) = self_signed();
+        write_pem(&dir, "cert.pem", &cert);
+        write_pem(&dir, "key.pem", &key);
+        let reload = tls.reload().unwrap();
+        assert_ne!(reload.cert_fingerprint, first.cert_fingerprint);
+        assert_eq!(tls.current(), reload);
+
+        // the client certificates are only asked for with a client CA
+        let ca_path = write_pem(&dir, "ca.pem", &self_signed().0);
+        let tls =
+            ServerTls::load(TlsConfig::new(cert_path, key_path).with_client_ca(ca_path)).unwrap();
+        assert!(tls.current().client_ca);
+        assert!(tls.requires_client_cert("txCommit"));
+        assert!(!tls.requires_client_cert("getUtxos"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
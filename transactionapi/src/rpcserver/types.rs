@@ -1,5 +1,7 @@
 
 use serde::{Deserialize, Serialize};
+// defined with the block processing, which records the confirmed and failed txs
+pub use utxo_in_memory::blockoperations::txstatus::{TxStatus, TxStatusRecord, TxStatusView};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MintOrBurnTx {
     // value in satoshi
@@ -0,0 +1,368 @@
+//! Validation of a transaction against the state of the node.
+//!
+//! txCommit and txValidate run the same `validate_tx`, the latter without queueing
+//! the tx, so both give the same verdict. The validation only reads the utxo set
+//! and the mempool. A coin spent by a recent block is found by its nullifier before
+//! the utxo set is read. The node assembles no blocks, a tx heavier than the block weight
+//! limit is rejected by the weight function of block processing.
+
+use super::chaininfo::DEFAULT_MIN_FEE_RATE;
+use super::service::{tx_id, ORACLE_CONFIG};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Instant;
+use transaction::oracle::OracleConfig;
+use transaction::weight::tx_weight;
+use transaction::{Transaction, TransactionType};
+use utxo_in_memory::blockoperations::blockweight::max_block_weight;
+use utxo_in_memory::blockoperations::mempool::{Mempool, MEMPOOL};
+use utxo_in_memory::blockoperations::nullifiers::{
+    input_nullifiers, NullifierIndex, NULLIFIER_INDEX,
+};
+use utxo_in_memory::db::{utxo_key, LocalDBtrait, LocalStorage};
+use utxo_in_memory::UTXO_STORAGE;
+use zkvm::zkos_types::{IOType, Input, Output, Utxo};
+
+/// Utxo set, mempool and oracle a tx is validated against.
+pub struct StateView<'a> {
+    utxo_storage: &'a Mutex<LocalStorage<Output>>,
+    mempool: &'a Mutex<Mempool>,
+    // the spends of the recent blocks, left to the utxo set if None
+    nullifiers: Option<&'a Mutex<NullifierIndex>>,
+    oracle: Option<&'a OracleConfig>,
+    max_block_weight: u64,
+    min_fee_rate: f64,
+}
+
+impl<'a> StateView<'a> {
+    pub fn new(
+        utxo_storage: &'a Mutex<LocalStorage<Output>>,
+        mempool: &'a Mutex<Mempool>,
+        oracle: Option<&'a OracleConfig>,
+    ) -> Self {
+        StateView {
+            utxo_storage,
+            mempool,
+            nullifiers: None,
+            oracle,
+            max_block_weight: max_block_weight(),
+            min_fee_rate: DEFAULT_MIN_FEE_RATE,
+        }
+    }
+
+    /// View rejecting the coins spent by the recent blocks of `nullifiers`.
+    pub fn with_nullifiers(mut self, nullifiers: &'a Mutex<NullifierIndex>) -> Self {
+        self.nullifiers = Some(nullifiers);
+        self
+    }
+
+    /// View bounding the weight of a tx by `limit` instead of the configured limit.
+    pub fn with_max_block_weight(mut self, limit: u64) -> Self {
+        self.max_block_weight = limit;
+        self
+    }
+
+    /// View rejecting a tx paying less than `rate` per byte.
+    pub fn with_min_fee_rate(mut self, rate: f64) -> Self {
+        self.min_fee_rate = rate;
+        self
+    }
+}
+
+impl StateView<'static> {
+    /// The utxo set, the mempool and the nullifier index of the node.
+    pub fn node() -> Self {
+        StateView::new(&UTXO_STORAGE, &MEMPOOL, ORACLE_CONFIG.as_ref())
+            .with_nullifiers(&NULLIFIER_INDEX)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputCheck {
+    pub utxo: Utxo,
+    /// Whether the utxo set holds the output spent by the input
+    pub found: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxValidationReport {
+    pub valid: bool,
+    /// Id the tx is committed under
+    pub txid: String,
+    /// Fee per byte of the encoded tx
+    pub fee_rate: f64,
+    /// Weight of the tx in a block
+    #[serde(default)]
+    pub weight: u64,
+    /// Inputs spending a utxo, decoy inputs and references to outputs of the tx aside
+    pub inputs: Vec<InputCheck>,
+    /// Reasons for rejecting the tx, in the order they were found
+    pub errors: Vec<String>,
+}
+
+// the stored output of the input has to be the one the input spends
+fn input_found(
+    utxo_storage: &mut LocalStorage<Output>,
+    tx_type: TransactionType,
+    input: &Input,
+) -> bool {
+    // transfers and messages only spend coins
+    if tx_type != TransactionType::Script && input.in_type != IOType::Coin {
+        return false;
+    }
+    let utxo = match input.as_utxo() {
+        Some(utxo) => utxo,
+        None => return false,
+    };
+    let stored = match utxo_storage.get_utxo_by_id(utxo_key(utxo), input.in_type as usize) {
+        Ok(stored) => stored,
+        Err(_) => return false,
+    };
+    match input.in_type {
+        IOType::Coin => stored.as_out_coin() == input.as_out_coin(),
+        IOType::Memo => stored.as_out_memo() == input.as_out_memo(),
+        IOType::State => stored.as_out_state() == input.as_out_state(),
+    }
+}
+
+/// Fee per byte of the encoded tx, the rate replacements are compared by.
+pub fn fee_rate(tx: &Transaction) -> f64 {
+    let size = tx.to_bytes().len().max(1);
+    tx.get_tx_fee() as f64 / size as f64
+}
+
+/// Checks the outputs, the structure and the inputs of `tx` against `view`, and
+/// verifies its proofs if these pass. Neither the utxo set nor the mempool change.
+/// An input spent by another pending tx is rejected unless `tx` pays enough to
+/// replace it, resubmitting the same tx is not.
+pub fn validate_tx(tx: &Transaction, view: &StateView) -> TxValidationReport {
+    let txid = tx_id(tx);
+    let fee_rate = fee_rate(tx);
+    let weight = tx_weight(tx);
+    let mut errors = Vec::new();
+
+    // a tx no block can hold is never applied
+    if weight > view.max_block_weight {
+        errors.push(format!(
+            "Error: tx weight {} exceeds the block weight limit {}",
+            weight, view.max_block_weight
+        ));
+    }
+    if fee_rate < view.min_fee_rate {
+        errors.push(format!(
+            "Error: fee rate {} is below the minimum fee rate {}",
+            fee_rate, view.min_fee_rate
+        ));
+    }
+    // reject malformed points or addresses
+    if let Some(err) = tx
+        .get_tx_outputs()
+        .iter()
+        .find_map(|output| output.validate().err())
+    {
+        errors.push(format!("Error: invalid output, {}", err));
+    }
+    // reject inputs spending the same utxo twice
+    if let Err(err) = tx.validate_structure() {
+        errors.push(format!("Error: invalid transaction, {}", err));
+    }
+
+    let decoy_utxo = Utxo::default();
+    let spending: Vec<Input> = tx
+        .get_tx_inputs()
+        .into_iter()
+        .filter(|input| {
+            input
+                .as_utxo()
+                .map_or(false, |utxo| *utxo != decoy_utxo && !utxo.is_pending())
+        })
+        .collect();
+    // coins spent by a recent block are rejected with their spender, before the utxo
+    // set is read
+    if let Some(nullifiers) = view.nullifiers {
+        let spent = input_nullifiers(&spending);
+        if let Some(conflict) = nullifiers.lock().unwrap().conflict(&txid, &spent) {
+            errors.push(format!("Error: {}", conflict));
+        }
+    }
+    let (inputs, height) = {
+        let mut utxo_storage = view.utxo_storage.lock().unwrap();
+        let inputs: Vec<InputCheck> = spending
+            .iter()
+            .map(|input| InputCheck {
+                utxo: input.get_utxo(),
+                found: input_found(&mut utxo_storage, tx.tx_type, input),
+            })
+            .collect();
+        (inputs, utxo_storage.block_height as u64)
+    };
+    if inputs.iter().any(|input| !input.found) {
+        errors.push("Error: failed to verify utxo".to_string());
+    }
+    let utxos: Vec<Utxo> = inputs.iter().map(|input| input.utxo).collect();
+    let mempool = view.mempool.lock().unwrap();
+    if let Err(err) = mempool.check_replacement(&txid, &utxos, fee_rate, Instant::now()) {
+        errors.push(format!("Error: {}", err));
+    }
+    drop(mempool);
+
+    // the proofs are only verified for a tx that could be applied
+    if errors.is_empty() {
+        // tx_data of price sensitive programs has to be signed by the oracle for the current height
+        if let Err(err) = tx.verify_with_oracle(view.oracle, height) {
+            errors.push(format!("Verification Error: {}", err));
+        }
+    }
+
+    TxValidationReport {
+        valid: errors.is_empty(),
+        txid,
+        fee_rate,
+        weight,
+        inputs,
+        errors,
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use address::Network;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::keys::{PublicKey, SecretKey};
+    use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+    use std::time::Duration;
+    use transaction::verify_relayer::deploy_relayer_contract;
+    use zkvm::zkos_types::OutputData;
+
+    // a relayer deploy tx, its coin input is spent from `utxo`
+    fn deploy_tx(utxo: Utxo) -> Transaction {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let owner = address::Address::standard_address(Network::default(), pk).as_hex();
+        let (tx, _, _) =
+            deploy_relayer_contract(&sk, utxo, &owner, 1000, Scalar::random(&mut rng), 500, 1)
+                .unwrap();
+        tx
+    }
+
+    // stores the coin spent by the first input of `tx`
+    fn store_input(utxo_storage: &mut LocalStorage<Output>, tx: &Transaction) {
+        let input = tx.get_tx_inputs()[0].clone();
+        let coin = Output::coin(OutputData::coin(input.as_out_coin().unwrap().clone()));
+        utxo_storage
+            .add(input.get_utxo().to_bytes(), coin, IOType::Coin as usize)
+            .unwrap();
+    }
+
+    #[test]
+    fn validate_tx_test() {
+        let utxo_storage = Mutex::new(LocalStorage::<Output>::new(3));
+        let mempool = Mutex::new(Mempool::new(Duration::from_secs(60)));
+        let view = StateView::new(&utxo_storage, &mempool, None);
+
+        let utxo = Utxo::random();
+        let tx = deploy_tx(utxo);
+        let report = validate_tx(&tx, &view);
+        assert!(!report.valid);
+        assert_eq!(report.inputs, vec![InputCheck { utxo, found: false }]);
+        assert_eq!(
+            report.errors,
+            vec!["Error: failed to verify utxo".to_string()]
+        );
+
+        store_input(&mut utxo_storage.lock().unwrap(), &tx);
+        let report = validate_tx(&tx, &view);
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.txid, tx_id(&tx));
+        assert!(report.fee_rate > 0.0);
+        assert_eq!(report.weight, tx_weight(&tx));
+        assert!(report.inputs[0].found);
+        // nothing is queued
+        assert!(mempool.lock().unwrap().is_empty());
+
+        // the same tx may be resubmitted, another tx spending its input only if
+        // the fee rate of the tx exceeds its rate by the increment
+        let rate = report.fee_rate;
+        let now = Instant::now();
+        mempool
+            .lock()
+            .unwrap()
+            .insert(&report.txid, &tx.get_tx_inputs(), rate, now);
+        assert!(validate_tx(&tx, &view).valid);
+        mempool.lock().unwrap().remove(&report.txid);
+        mempool
+            .lock()
+            .unwrap()
+            .insert("other", &tx.get_tx_inputs(), rate / 1.05, now);
+        let report = validate_tx(&tx, &view);
+        assert!(!report.valid);
+        assert!(report.errors[0].starts_with("Error: input spent by pending tx other"));
+        mempool.lock().unwrap().remove("other");
+        mempool
+            .lock()
+            .unwrap()
+            .insert("other", &tx.get_tx_inputs(), rate / 2.0, now);
+        assert!(validate_tx(&tx, &view).valid);
+
+        // a tx of exactly the block weight limit fits a block, a heavier one does not
+        let weight = tx_weight(&tx);
+        let view = StateView::new(&utxo_storage, &mempool, None).with_max_block_weight(weight);
+        assert!(validate_tx(&tx, &view).valid);
+        let view = view.with_max_block_weight(weight - 1);
+        let report = validate_tx(&tx, &view);
+        assert!(!report.valid);
+        assert_eq!(
+            report.errors,
+            vec![format!(
+                "Error: tx weight {} exceeds the block weight limit {}",
+                weight,
+                weight - 1
+            )]
+        );
+
+        // a tx paying less than the minimum fee rate is rejected
+        let view = StateView::new(&utxo_storage, &mempool, None).with_min_fee_rate(rate);
+        assert!(validate_tx(&tx, &view).valid);
+        let view = view.with_min_fee_rate(rate * 2.0);
+        assert_eq!(
+            validate_tx(&tx, &view).errors,
+            vec![format!(
+                "Error: fee rate {} is below the minimum fee rate {}",
+                rate,
+                rate * 2.0
+            )]
+        );
+    }
+
+    #[test]
+    fn validate_tx_nullifier_test() {
+        let utxo_storage = Mutex::new(LocalStorage::<Output>::new(3));
+        let mempool = Mutex::new(Mempool::new(Duration::from_secs(60)));
+        let nullifiers = Mutex::new(NullifierIndex::new(10));
+        let view = StateView::new(&utxo_storage, &mempool, None).with_nullifiers(&nullifiers);
+        let tx = deploy_tx(Utxo::random());
+        store_input(&mut utxo_storage.lock().unwrap(), &tx);
+        assert!(validate_tx(&tx, &view).valid);
+
+        // the coin is spent by a block the utxo set does not reflect yet
+        let spent = input_nullifiers(&tx.get_tx_inputs());
+        assert_eq!(spent.len(), 1);
+        nullifiers.lock().unwrap().insert(12, "aa", &spent);
+        let report = validate_tx(&tx, &view);
+        assert!(!report.valid);
+        assert_eq!(
+            report.errors,
+            vec!["Error: input already spent by tx aa in block 12".to_string()]
+        );
+
+        // a tx is not in conflict with its own spends, and a reorg drops them
+        nullifiers.lock().unwrap().rollback_to(11);
+        nullifiers.lock().unwrap().insert(12, &tx_id(&tx), &spent);
+        assert!(validate_tx(&tx, &view).valid);
+    }
+}
@@ -0,0 +1,101 @@
+//! A wallet configuring its address parser from the chain info of a node whose
+//! address magic bytes and fee minimum are not the defaults.
+//!
+//! The magic bytes are installed once per process, so this runs as its own test binary.
+//!
+//! cargo test -p transactionapi --test chaininfo
+
+use address::{Address, Network, NetworkConfig, Standard};
+use quisquislib::keys::{PublicKey, SecretKey};
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use std::sync::Arc;
+use transaction::programs::get_swap_program;
+use transactionapi::rpcclient::async_client::AsyncRpcClient;
+use transactionapi::rpcclient::chaininfo::{
+    configure_from_node, configure_network, ChainInfoError,
+};
+use transactionapi::rpcserver::{
+    start_rpc_node, ChainParams, RateLimitConfig, RpcNode, TxRelay, CHAIN_INFO_VERSION,
+};
+use utxo_in_memory::pgsql::MemoryStore;
+use utxo_in_memory::scriptregistry::{ScriptContract, SCRIPT_REGISTRY};
+use utxo_in_memory::UtxoState;
+
+#[tokio::test]
+async fn chain_info_configures_client_test() {
+    let state = UtxoState::secondary(Network::Testnet, Arc::new(MemoryStore::new()));
+    state.set_max_block_weight(2_000_000);
+    state.utxo_storage.lock().unwrap().block_height = 77;
+    let contract = ScriptContract::new(
+        "swap".to_string(),
+        &[get_swap_program().to_bytes()],
+        serde_json::Value::Null,
+    )
+    .unwrap();
+    SCRIPT_REGISTRY.lock().unwrap().insert(contract.clone());
+
+    let network_config = NetworkConfig::new(100, 101, 102, 103)
+        .unwrap()
+        .with_multisig(104, 105)
+        .unwrap();
+    let node = RpcNode::new(Arc::new(state), TxRelay::new(Default::default())).with_chain_params(
+        ChainParams {
+            network_config,
+            min_fee_rate: 1.5,
+        },
+    );
+    let server = start_rpc_node(
+        &"127.0.0.1:0".parse().unwrap(),
+        RateLimitConfig::default(),
+        node,
+    )
+    .unwrap();
+    let client = AsyncRpcClient::new(format!("http://{}", server.address()));
+
+    // an address of the default magic bytes, before the client is configured
+    let mut rng = rand::thread_rng();
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    let default_hex = Address::standard_address(Network::Testnet, pk).as_hex();
+    assert!(Standard::from_hex_with_error(&default_hex).is_ok());
+
+    let info = configure_from_node(&client).await.unwrap();
+    assert_eq!(info.version, CHAIN_INFO_VERSION);
+    assert_eq!(info.network, Network::Testnet);
+    assert_eq!(info.network_config, network_config);
+    assert_eq!(info.min_fee_rate, 1.5);
+    assert_eq!(info.max_block_weight, 2_000_000);
+    assert_eq!(info.max_tx_size, 200_000);
+    assert_eq!(info.block_height, 77);
+    assert_eq!(info.tx_versions, vec![3]);
+    let summary = info
+        .script_contracts
+        .iter()
+        .find(|summary| summary.root == contract.root)
+        .unwrap();
+    assert_eq!(summary.name, "swap");
+    assert_eq!(summary.program_count, 1);
+
+    // the addresses of the client carry the magic bytes of the node
+    let hex = Address::standard_address(Network::Testnet, pk).as_hex();
+    assert!(hex.starts_with(&format!("{:02x}", 102)));
+    let parsed = Standard::from_hex_with_error(&hex).unwrap();
+    assert_eq!(parsed.as_hex(), hex);
+    assert!(Standard::from_hex_with_error(&default_hex).is_err());
+
+    // reconnecting to the chain is fine, another chain is refused
+    assert!(configure_network(&info).is_ok());
+    let mut other = info.clone();
+    other.network_config = NetworkConfig::default();
+    assert!(matches!(
+        configure_network(&other),
+        Err(ChainInfoError::NetworkConfig(_))
+    ));
+    other.version = CHAIN_INFO_VERSION + 1;
+    assert!(matches!(
+        configure_network(&other),
+        Err(ChainInfoError::UnsupportedVersion(_))
+    ));
+
+    server.close();
+}
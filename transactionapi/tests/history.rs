@@ -0,0 +1,324 @@
+//! Export of the history of a wallet from a node that applied a sequence of transfers:
+//! receipts, payments, a QuisQuis decoy, a self transfer, a receipt into a fresh
+//! account under an updated key and a burn.
+//!
+//! cargo test -p transactionapi --test history
+
+use address::{Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::accounts::Account;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::keys::{PublicKey, SecretKey};
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use rand::Rng;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use transaction::reference_tx::{convert_output_to_input, RecordUtxo};
+use transaction::{ScriptTransaction, Transaction, TransactionData};
+use transactionapi::rpcclient::async_client::AsyncRpcClient;
+use transactionapi::rpcclient::history::{
+    export_history, ExportFormat, HistoryEntry, HistoryKind, ViewKey,
+};
+use transactionapi::rpcserver::{start_rpc_node, RateLimitConfig, RpcNode, TxRelay};
+use utxo_in_memory::blockoperations::blockprocessing::{Block, TransactionMessage};
+use utxo_in_memory::pgsql::MemoryStore;
+use utxo_in_memory::UtxoState;
+use zkvm::zkos_types::{IOType, Input, Output, Utxo};
+use zkvm::Hash;
+
+struct Key {
+    scalar: Scalar,
+    pk: RistrettoPublicKey,
+}
+
+impl Key {
+    fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let scalar = Scalar::random(&mut rng);
+        let sk: RistrettoSecretKey = SecretKey::from_bytes(scalar.as_bytes());
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        Key { scalar, pk }
+    }
+
+    fn address(&self) -> String {
+        Address::standard_address(Network::Testnet, self.pk).as_hex()
+    }
+
+    fn view(&self, address: &str) -> ViewKey {
+        ViewKey {
+            address: address.to_string(),
+            scalar: self.scalar,
+        }
+    }
+
+    // account of `value` under the key, or under an updated key of it
+    fn account(&self, value: u64, update: bool) -> Account {
+        let mut rng = rand::thread_rng();
+        let pk = match update {
+            true => RistrettoPublicKey::update_public_key(&self.pk, Scalar::random(&mut rng)),
+            false => self.pk,
+        };
+        let commitment = ElGamalCommitment::generate_commitment(
+            &pk,
+            Scalar::random(&mut rng),
+            Scalar::from(value),
+        );
+        Account::set_account(pk, commitment)
+    }
+
+    fn coin(&self, value: u64, update: bool) -> Output {
+        Output::from_quisquis_account(self.account(value, update), Network::Testnet)
+    }
+}
+
+fn owner(record: &RecordUtxo) -> String {
+    record.value.as_out_coin().unwrap().owner.clone()
+}
+
+fn time(height: u64) -> String {
+    format!("2024-03-04T11:{:02}:00Z", height)
+}
+
+// applies a dummy script tx spending `inputs` in the block at `height`, block
+// processing does not verify its proof
+fn apply(
+    state: &UtxoState,
+    height: u64,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+) -> (String, Vec<RecordUtxo>) {
+    let tx_id: [u8; 32] = rand::thread_rng().gen();
+    let script_tx = ScriptTransaction::create_utxo_dummy_script_transaction(&inputs, &outputs);
+    let tx = Transaction::transaction_script(TransactionData::TransactionScript(script_tx));
+    let txid = hex::encode(tx_id);
+    let result = state.process_block(Block {
+        block_hash: format!("history{}", height),
+        block_height: height,
+        transactions: vec![TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: txid.clone(),
+            tx_byte_code: Some(hex::encode(bincode::serialize(&tx).unwrap())),
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account: None,
+            encrypt_scalar: None,
+            twilight_address: None,
+        }],
+        time: time(height),
+        ..Default::default()
+    });
+    assert_eq!(result.suceess_tx.len(), 1, "block {}", height);
+    let created = outputs
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| RecordUtxo {
+            utx: Utxo::from_hash(Hash(tx_id), index as u16),
+            value,
+        })
+        .collect();
+    (txid, created)
+}
+
+fn spend(record: &RecordUtxo) -> Input {
+    convert_output_to_input(record.clone()).unwrap()
+}
+
+fn entry(
+    address: &str,
+    txid: &str,
+    height: u64,
+    kind: HistoryKind,
+    counterparty: Option<&str>,
+    amount: u64,
+) -> HistoryEntry {
+    HistoryEntry {
+        address: address.to_string(),
+        txid: txid.to_string(),
+        height,
+        timestamp: time(height),
+        kind,
+        counterparty: counterparty.map(str::to_string),
+        amount,
+    }
+}
+
+#[tokio::test]
+async fn export_history_test() {
+    let store = Arc::new(MemoryStore::new());
+    let state = Arc::new(UtxoState::secondary(Network::Testnet, store.clone()));
+    let (alice, bob, carol, dave) = (Key::new(), Key::new(), Key::new(), Key::new());
+
+    // the coin carol starts with
+    let carol_coin = RecordUtxo {
+        utx: Utxo::random(),
+        value: carol.coin(100, false),
+    };
+    state
+        .utxo_storage
+        .lock()
+        .unwrap()
+        .add(
+            carol_coin.utx.to_bytes(),
+            carol_coin.value.clone(),
+            IOType::Coin as usize,
+        )
+        .unwrap();
+
+    // carol pays alice 70
+    let (tx10, created) = apply(
+        &state,
+        10,
+        vec![spend(&carol_coin)],
+        vec![alice.coin(70, false), carol.coin(30, false)],
+    );
+    let (alice_70, carol_30) = (created[0].clone(), created[1].clone());
+    // alice pays bob 20, her change goes to an updated address
+    let (tx11, created) = apply(
+        &state,
+        11,
+        vec![spend(&alice_70)],
+        vec![bob.coin(20, false), alice.coin(45, true)],
+    );
+    let (bob_20, alice_45) = (created[0].clone(), created[1].clone());
+    // carol pays dave with the coin of alice as a decoy
+    let (_, created) = apply(
+        &state,
+        12,
+        vec![spend(&carol_30), spend(&alice_45)],
+        vec![dave.coin(30, false), alice.coin(45, true)],
+    );
+    let (dave_30, alice_decoy) = (created[0].clone(), created[1].clone());
+    // bob moves his coin
+    let (tx13, _) = apply(&state, 13, vec![spend(&bob_20)], vec![bob.coin(20, false)]);
+    // dave pays 12 into a fresh account of alice, received under an updated key
+    let fresh = Input::input_from_quisquis_account(
+        &alice.account(0, false),
+        Utxo::default(),
+        0,
+        Network::Testnet,
+    );
+    let (tx14, created) = apply(
+        &state,
+        14,
+        vec![spend(&dave_30), fresh],
+        vec![alice.coin(12, true), dave.coin(18, false)],
+    );
+    let dave_18 = created[1].clone();
+    // alice burns the coin she got back as a decoy
+    let (tx15, _) = apply(&state, 15, vec![spend(&alice_decoy)], vec![]);
+    // after the exported range
+    apply(&state, 16, vec![spend(&dave_18)], vec![bob.coin(18, false)]);
+
+    // the address index of a secondary network is read from its store
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !store
+        .tables()
+        .address_txs
+        .iter()
+        .any(|tx| tx.block_height == 16)
+        && Instant::now() < deadline
+    {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    let server = start_rpc_node(
+        &"127.0.0.1:0".parse().unwrap(),
+        RateLimitConfig::default(),
+        RpcNode::new(state.clone(), TxRelay::new(Default::default())),
+    )
+    .unwrap();
+    let client = AsyncRpcClient::new(format!("http://{}", server.address()));
+
+    let alice_address = alice.address();
+    let (alice_change, alice_decoy_address) = (owner(&alice_45), owner(&alice_decoy));
+    let keys = vec![
+        alice.view(&alice_address),
+        alice.view(&alice_change),
+        alice.view(&alice_decoy_address),
+        bob.view(&bob.address()),
+    ];
+    let mut csv = Vec::new();
+    let entries = export_history(&client, &keys, 10, 15, ExportFormat::Csv, &mut csv)
+        .await
+        .unwrap();
+    let (carol_address, bob_address) = (carol.address(), bob.address());
+    let expected = vec![
+        entry(
+            &alice_address,
+            &tx10,
+            10,
+            HistoryKind::Received,
+            Some(&carol_address),
+            70,
+        ),
+        entry(
+            &alice_address,
+            &tx11,
+            11,
+            HistoryKind::Sent,
+            Some(&bob_address),
+            25,
+        ),
+        entry(
+            &bob_address,
+            &tx11,
+            11,
+            HistoryKind::Received,
+            Some(&alice_address),
+            20,
+        ),
+        entry(&bob_address, &tx13, 13, HistoryKind::SelfTransfer, None, 20),
+        entry(
+            &alice_address,
+            &tx14,
+            14,
+            HistoryKind::Received,
+            Some(&dave.address()),
+            12,
+        ),
+        entry(&alice_decoy_address, &tx15, 15, HistoryKind::Burn, None, 45),
+    ];
+    assert_eq!(entries, expected);
+
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "address,txid,height,timestamp,kind,counterparty,amount"
+    );
+    assert_eq!(
+        lines[2],
+        format!(
+            "{},{},11,{},sent,{},25",
+            alice_address,
+            tx11,
+            time(11),
+            bob_address
+        )
+    );
+    assert_eq!(
+        lines[6],
+        format!("{},{},15,{},burn,,45", alice_decoy_address, tx15, time(15))
+    );
+
+    let mut json = Vec::new();
+    export_history(&client, &keys, 10, 15, ExportFormat::Json, &mut json)
+        .await
+        .unwrap();
+    let decoded: Vec<HistoryEntry> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(decoded, expected);
+
+    // a range without activity of the keys
+    let mut csv = Vec::new();
+    let entries = export_history(&client, &keys, 17, 20, ExportFormat::Csv, &mut csv)
+        .await
+        .unwrap();
+    assert!(entries.is_empty());
+    assert_eq!(
+        csv.len(),
+        "address,txid,height,timestamp,kind,counterparty,amount\n".len()
+    );
+
+    server.close();
+}
@@ -0,0 +1,316 @@
+//! Wallet -> transaction -> rpc submit -> block -> utxo store, in a single process.
+//!
+//! The Zkos Oracle is replaced by a local http listener recording the committed txs,
+//! and psql by the in memory `MemoryStore`. The recorded txs are put into a block,
+//! as the chain would, which is applied with `process_block_for_utxo_insert`.
+//!
+//! cargo test -p transactionapi --features integration-tests --test pipeline
+
+use address::{Address, Network};
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::accounts::Account;
+use quisquislib::elgamal::ElGamalCommitment;
+use quisquislib::keys::{PublicKey, SecretKey};
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use transaction::reference_tx::{create_genesis_block, RecordUtxo};
+use transaction::verify_relayer::deploy_relayer_contract;
+use transaction::Transaction;
+use transactionapi::cli::{run, Command, KeyArgs, RpcArgs, SendDarkArgs};
+use transactionapi::rpcclient::async_client::AsyncRpcClient;
+use transactionapi::rpcserver::{set_zkos_oracle_url, start_rpc_server, RateLimitConfig};
+use utxo_in_memory::blockoperations::blockprocessing::{
+    process_block_for_utxo_insert, Block, TransactionMessage, TOTAL_SCRIPT_TX, TOTAL_TRANSFER_TX,
+};
+use utxo_in_memory::blockoperations::mempool::MEMPOOL;
+use utxo_in_memory::db::{KeyId, LocalDBtrait};
+use utxo_in_memory::pgsql::{set_persistence_store, MemoryStore, MemoryTables};
+use utxo_in_memory::{reset_utxo_storage, UTXO_STORAGE};
+use zkvm::zkos_types::{IOType, Output, Utxo};
+use zkvm::Hash;
+
+// txs committed to the oracle, as (txid, tx hex)
+type Committed = Arc<Mutex<Vec<(String, String)>>>;
+
+// serves the /transaction endpoint of the Zkos Oracle, answering with the txid
+fn fake_oracle() -> (String, Committed) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let committed: Committed = Arc::new(Mutex::new(Vec::new()));
+    let txs = committed.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some(length) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap_or(0);
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let txid = payload["id"].as_str().unwrap().to_string();
+            let tx = payload["tx"].as_str().unwrap().to_string();
+            txs.lock().unwrap().push((txid.clone(), tx));
+            let response = serde_json::json!({ "txHash": txid }).to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            );
+        }
+    });
+    (url, committed)
+}
+
+// a coin owned by a new key, with the scalars to spend it
+struct Wallet {
+    sk: RistrettoSecretKey,
+    key_scalar: Scalar,
+    encrypt_scalar: Scalar,
+    owner: String,
+    output: Output,
+}
+
+impl Wallet {
+    fn new(value: u64) -> Self {
+        let mut rng = rand::thread_rng();
+        let key_scalar = Scalar::random(&mut rng);
+        let sk: RistrettoSecretKey = SecretKey::from_bytes(key_scalar.as_bytes());
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let encrypt_scalar = Scalar::random(&mut rng);
+        let commitment =
+            ElGamalCommitment::generate_commitment(&pk, encrypt_scalar, Scalar::from(value));
+        let account = Account::set_account(pk, commitment);
+        let output = Output::from_quisquis_account(account, Network::Mainnet);
+        Wallet {
+            sk,
+            key_scalar,
+            encrypt_scalar,
+            owner: output.as_out_coin().unwrap().owner.clone(),
+            output,
+        }
+    }
+
+    // key file read by the cli, readable by the owner only
+    fn key_file(&self) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("zkos-pipeline-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, hex::encode(self.key_scalar.as_bytes())).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        path
+    }
+}
+
+fn utxo_key(utxo: &Utxo) -> KeyId {
+    bincode::serialize(utxo).unwrap()
+}
+
+// outputs of a committed tx, with the utxos the block gives them
+fn tx_outputs(txid: &str, tx_hex: &str) -> Vec<(Utxo, Output)> {
+    let tx = Transaction::from_bytes(&hex::decode(tx_hex).unwrap()).unwrap();
+    let txid: [u8; 32] = hex::decode(txid).unwrap().try_into().unwrap();
+    tx.get_tx_outputs()
+        .into_iter()
+        .enumerate()
+        .map(|(index, output)| (Utxo::from_hash(Hash(txid), index as u16), output))
+        .collect()
+}
+
+// the sql queue writes asynchronously
+fn wait_for_store(store: &MemoryStore, done: impl Fn(&MemoryTables) -> bool) -> MemoryTables {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let tables = store.tables();
+        if done(&tables) || Instant::now() > deadline {
+            return tables;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn wallet_to_utxo_store_pipeline_test() {
+    let store = Arc::new(MemoryStore::new());
+    set_persistence_store(store.clone());
+    let (oracle_url, committed) = fake_oracle();
+    set_zkos_oracle_url(&oracle_url);
+
+    // genesis set of random outputs, with a coin of the sender and of the deployer
+    let (base_account, _) = Account::generate_random_account_with_value(Scalar::from(20u64));
+    let mut genesis = create_genesis_block(30, 3, base_account);
+    let sender = Wallet::new(100);
+    let deployer = Wallet::new(1000);
+    let sender_utxo = Utxo::random();
+    let deployer_utxo = Utxo::random();
+    genesis.push(RecordUtxo {
+        utx: sender_utxo,
+        value: sender.output.clone(),
+    });
+    genesis.push(RecordUtxo {
+        utx: deployer_utxo,
+        value: deployer.output.clone(),
+    });
+    reset_utxo_storage(&genesis);
+
+    let server =
+        start_rpc_server(&"127.0.0.1:0".parse().unwrap(), RateLimitConfig::default()).unwrap();
+    let rpc = format!("http://{}", server.address());
+    let client = AsyncRpcClient::new(rpc.clone());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    // dark transfer built by the wallet cli, script tx deploying the relayer contract
+    let (receiver, _) = Account::generate_random_account_with_value(Scalar::zero());
+    let to = Address::standard_address(Network::Mainnet, receiver.get_account().0).as_hex();
+    let key_file = sender.key_file();
+    let transfer_txid = rt
+        .block_on(run(Command::SendDark(SendDarkArgs {
+            from: sender.owner.clone(),
+            to,
+            amount: 40,
+            fee: 1,
+            dry_run: false,
+            key: KeyArgs {
+                sk_file: Some(key_file.clone()),
+            },
+            rpc: RpcArgs { rpc: rpc.clone() },
+        })))
+        .unwrap();
+    std::fs::remove_file(&key_file).unwrap();
+
+    let (deploy_tx, _, _) = deploy_relayer_contract(
+        &deployer.sk,
+        deployer_utxo,
+        &deployer.owner,
+        1000,
+        deployer.encrypt_scalar,
+        500,
+        1,
+    )
+    .unwrap();
+    let script_txid = rt.block_on(client.tx_commit(deploy_tx)).unwrap().unwrap();
+
+    // both txs reached the oracle and are pending spends
+    let committed = committed.lock().unwrap().clone();
+    let txids: Vec<&str> = committed.iter().map(|(txid, _)| txid.as_str()).collect();
+    assert_eq!(txids, vec![transfer_txid.as_str(), script_txid.as_str()]);
+    assert_eq!(MEMPOOL.lock().unwrap().len(), 2);
+
+    let height = 1;
+    let block = Block {
+        block_hash: "pipeline".to_string(),
+        block_height: height,
+        transactions: committed
+            .iter()
+            .map(|(txid, tx)| TransactionMessage {
+                tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+                tx_id: txid.clone(),
+                tx_byte_code: Some(tx.clone()),
+                zk_oracle_address: None,
+                mint_or_burn: None,
+                btc_value: None,
+                qq_account: None,
+                encrypt_scalar: None,
+                twilight_address: None,
+            })
+            .collect(),
+        parent_hash: String::new(),
+        app_hash: String::new(),
+        time: String::new(),
+        proposer_address: String::new(),
+    };
+    let result = process_block_for_utxo_insert(block);
+    assert!(result.failed_tx.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.suceess_tx.len(), 2);
+
+    // utxo set: the coins are spent, the outputs of both txs are added
+    let outputs: Vec<(Utxo, Output)> = committed
+        .iter()
+        .flat_map(|(txid, tx)| tx_outputs(txid, tx))
+        .collect();
+    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    for spent in [sender_utxo, deployer_utxo] {
+        assert!(!utxo_storage
+            .search_key(&utxo_key(&spent), IOType::Coin as usize)
+            .unwrap());
+    }
+    for (utxo, output) in outputs.iter() {
+        let stored = utxo_storage
+            .get_utxo_by_id(utxo_key(utxo), output.out_type as usize)
+            .unwrap();
+        assert_eq!(&stored, output);
+    }
+    drop(utxo_storage);
+    assert!(MEMPOOL.lock().unwrap().is_empty());
+
+    // metrics
+    assert_eq!(TOTAL_TRANSFER_TX.get(), 1.0);
+    assert_eq!(TOTAL_SCRIPT_TX.get(), 1.0);
+
+    // persisted rows
+    let tables = wait_for_store(&store, |tables| {
+        tables.block_results.iter().any(|r| r.height == height)
+            && tables.address_txs.len() >= 2
+            && outputs
+                .iter()
+                .all(|(utxo, _)| tables.utxos.contains_key(&utxo_key(utxo)))
+    });
+    assert!(!tables.utxos.contains_key(&utxo_key(&sender_utxo)));
+    assert!(!tables.utxos.contains_key(&utxo_key(&deployer_utxo)));
+    assert!(tables
+        .utxos
+        .keys()
+        .all(|key| outputs.iter().any(|(utxo, _)| &utxo_key(utxo) == key)));
+    assert_eq!(tables.block_tx_roots.len(), 1);
+    assert!(tables.failed_txs.is_empty());
+
+    // status of the txs, as served to the wallet
+    rt.block_on(async {
+        for txid in [&transfer_txid, &script_txid] {
+            let proof = client
+                .get_tx_inclusion_proof(txid.to_string())
+                .await
+                .unwrap();
+            assert_eq!(proof.proof.unwrap().block_height, height);
+        }
+        let applied = client.get_block_apply_result(height).await.unwrap();
+        let applied = applied.result.unwrap();
+        assert_eq!(applied.applied_txids.len(), 2);
+        assert!(applied.failed.is_empty());
+        assert_eq!(applied.utxos_removed, 2);
+        assert_eq!(applied.utxos_added, outputs.len() as u64);
+
+        // address index, read back through the store
+        for (owner, txid) in [
+            (&sender.owner, &transfer_txid),
+            (&deployer.owner, &script_txid),
+        ] {
+            let txs = client
+                .get_transactions_by_address(owner.to_string(), 0)
+                .await
+                .unwrap()
+                .txs;
+            assert!(txs
+                .iter()
+                .any(|tx| &tx.txid == txid && tx.block_height == height));
+        }
+    });
+}
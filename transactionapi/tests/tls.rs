@@ -0,0 +1,149 @@
+//! The rpc server over mutual TLS, with certificates of a self-signed test CA.
+//!
+//! Anonymous clients reach the read methods, txCommit and the admin methods need
+//! a client certificate issued by the CA, and `reloadTls` serves a new server
+//! certificate to the new connections.
+//!
+//! cargo test -p transactionapi --test tls
+
+use address::Network;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use transactionapi::rpcclient::async_client::{AsyncRpcClient, RetryConfig};
+use transactionapi::rpcclient::tls::ClientTlsConfig;
+use transactionapi::rpcserver::{
+    start_rpc_node_tls, RateLimitConfig, RpcNode, ServerTls, TlsConfig, TxRelay,
+    CLIENT_CERT_REQUIRED_CODE,
+};
+use utxo_in_memory::pgsql::MemoryStore;
+use utxo_in_memory::UtxoState;
+
+const ADMIN_KEY: &str = "admin-key";
+
+fn params(common_name: &str, usage: Option<ExtendedKeyUsagePurpose>) -> CertificateParams {
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    params.distinguished_name = name;
+    params.extended_key_usages = usage.into_iter().collect();
+    params
+}
+
+// certificate signed by `ca` and its key, as PEM files in `dir`
+fn issue(
+    dir: &Path,
+    name: &str,
+    ca: &Certificate,
+    usage: ExtendedKeyUsagePurpose,
+) -> (PathBuf, PathBuf) {
+    let cert = Certificate::from_params(params(name, Some(usage))).unwrap();
+    let cert_path = dir.join(format!("{}.pem", name));
+    let key_path = dir.join(format!("{}_key.pem", name));
+    std::fs::write(&cert_path, cert.serialize_pem_with_signer(ca).unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    (cert_path, key_path)
+}
+
+// posts a call of `method` without params, returns the json answer
+async fn call(client: &reqwest::Client, url: &str, method: &str) -> serde_json::Value {
+    let body = serde_json::json!({"jsonrpc": "2.0", "method": method, "params": [], "id": 1});
+    client
+        .post(url)
+        .header("X-Api-Key", ADMIN_KEY)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn rpc_mutual_tls_test() {
+    let dir = std::env::temp_dir().join(format!("zkos-rpc-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut ca_params = params("zkos test CA", None);
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = Certificate::from_params(ca_params).unwrap();
+    let ca_path = dir.join("ca.pem");
+    std::fs::write(&ca_path, ca.serialize_pem().unwrap()).unwrap();
+    let (cert_path, key_path) = issue(&dir, "server", &ca, ExtendedKeyUsagePurpose::ServerAuth);
+    let (client_cert, client_key) = issue(&dir, "client", &ca, ExtendedKeyUsagePurpose::ClientAuth);
+
+    let state = UtxoState::secondary(Network::Mainnet, Arc::new(MemoryStore::new()));
+    let tls = TlsConfig::new(cert_path, key_path).with_client_ca(ca_path.clone());
+    let node = RpcNode::new(Arc::new(state), TxRelay::new(Default::default()))
+        .with_tls(ServerTls::load(tls).unwrap());
+    let config = RateLimitConfig {
+        admin_api_keys: [ADMIN_KEY.to_string()].into_iter().collect(),
+        ..RateLimitConfig::default()
+    };
+    let server = start_rpc_node_tls(&"127.0.0.1:0".parse().unwrap(), config, node).unwrap();
+    let url = format!("https://localhost:{}", server.address().port());
+
+    let anonymous_tls = ClientTlsConfig {
+        root_ca_path: Some(ca_path.clone()),
+        ..ClientTlsConfig::default()
+    };
+    let identity_tls = ClientTlsConfig {
+        root_ca_path: Some(ca_path),
+        cert_path: Some(client_cert),
+        key_path: Some(client_key),
+    };
+    let client = |tls: &ClientTlsConfig| {
+        tls.apply(reqwest::Client::builder())
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+    let (anonymous, authenticated) = (client(&anonymous_tls), client(&identity_tls));
+
+    // the server certificate is only trusted with the root of the test CA
+    assert!(reqwest::Client::new().post(&url).send().await.is_err());
+
+    // the read methods are open to anonymous clients
+    let rpc = AsyncRpcClient::with_tls(
+        url.clone(),
+        Duration::from_secs(10),
+        RetryConfig::none(),
+        &anonymous_tls,
+    )
+    .unwrap();
+    assert!(rpc.get_signed_status().await.unwrap().status.is_some());
+
+    // txCommit and the admin methods are refused without a client certificate
+    for method in ["txCommit", "reloadTls"] {
+        let response = call(&anonymous, &url, method).await;
+        assert_eq!(
+            response["error"]["code"], CLIENT_CERT_REQUIRED_CODE,
+            "{}",
+            method
+        );
+    }
+
+    // with one they get to the method itself
+    let response = call(&authenticated, &url, "txCommit").await;
+    assert_eq!(response["error"]["message"], "Expected hex string.");
+    let first = call(&authenticated, &url, "reloadTls").await;
+    assert_eq!(first["result"]["client_ca"], true);
+
+    // a new server certificate is served after a reload
+    issue(&dir, "server", &ca, ExtendedKeyUsagePurpose::ServerAuth);
+    let second = call(&authenticated, &url, "reloadTls").await;
+    assert_ne!(
+        second["result"]["cert_fingerprint"],
+        first["result"]["cert_fingerprint"]
+    );
+    let fresh = client(&anonymous_tls);
+    let response = call(&fresh, &url, "getSignedStatus").await;
+    assert!(response["result"]["signature_hex"].is_string());
+
+    server.close();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,74 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::accounts::Account;
+use utxo_in_memory::db::flatsnapshot::{write_flat_snapshot, FlatSnapshot};
+use utxo_in_memory::db::{utxo_key, LocalDBtrait, LocalStorage, UtxokeyidOutput};
+use zkvm::zkos_types::{IOType, Output, OutputCoin, OutputData, Utxo};
+use zkvm::Hash;
+
+const UTXOS: u32 = 1_000_000;
+
+// rows of UTXOS coin outputs as read from psql, and the flat snapshot of the same set
+fn synthetic_set() -> (Vec<UtxokeyidOutput<Vec<u8>>>, std::path::PathBuf) {
+    let (acc, _prv) = Account::generate_random_account_with_value(Scalar::from(20u64));
+    let (pk, enc) = acc.get_account();
+    let owner = address::Address::standard_address(address::Network::Mainnet, pk);
+    let output = Output::coin(OutputData::Coin(OutputCoin {
+        encrypt: enc,
+        owner: owner.as_hex(),
+    }));
+    let encoded = bincode::serialize(&output).unwrap();
+
+    let mut storage = LocalStorage::<Output>::new(3);
+    let mut rows = Vec::with_capacity(UTXOS as usize);
+    for i in 0..UTXOS {
+        let mut id = [0u8; 32];
+        id[..4].copy_from_slice(&i.to_be_bytes());
+        let key = utxo_key(&Utxo::from_hash(Hash(id), 0));
+        storage
+            .add(key.clone(), output.clone(), IOType::Coin as usize)
+            .unwrap();
+        rows.push(UtxokeyidOutput {
+            keyid: key,
+            output: encoded.clone(),
+        });
+    }
+    let path = std::env::temp_dir().join(format!("flat_snapshot_bench_{}", std::process::id()));
+    write_flat_snapshot(&path, 1, 3, &storage.encoded).unwrap();
+    (rows, path)
+}
+
+fn startup(c: &mut Criterion) {
+    let (rows, path) = synthetic_set();
+    c.bench_function("startup: 1M utxos from psql rows", move |b| {
+        b.iter(|| {
+            // the psql path decodes every row and encodes it again for the cache
+            let mut storage = LocalStorage::<Output>::new(3);
+            for row in rows.iter() {
+                let output: Output = bincode::deserialize(&row.output).unwrap();
+                storage
+                    .add(row.keyid.clone(), output, IOType::Coin as usize)
+                    .unwrap();
+            }
+            storage
+        })
+    });
+    c.bench_function("startup: 1M utxos from the flat snapshot", move |b| {
+        b.iter(|| {
+            let snapshot = FlatSnapshot::open(&path).unwrap();
+            let mut storage = LocalStorage::<Output>::new(3);
+            storage.load_from_flat_snapshot(&snapshot).unwrap();
+            storage
+        })
+    });
+}
+
+criterion_group! {
+    name = flat_snapshot;
+    config = Criterion::default().sample_size(10);
+    targets = startup,
+}
+criterion_main!(flat_snapshot);
@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate criterion;
+use criterion::Criterion;
+
+use curve25519_dalek::scalar::Scalar;
+use quisquislib::accounts::Account;
+use utxo_in_memory::blockoperations::blockprocessing::raw_outputs_by_address;
+use utxo_in_memory::db::{LocalDBtrait, LocalStorage};
+use zkvm::zkos_types::{IOType, Output, OutputCoin, OutputData, Utxo};
+use zkvm::Hash;
+
+const ADDRESS_UTXOS: u32 = 10_000;
+
+// storage holding ADDRESS_UTXOS coin outputs of one address
+fn address_storage() -> (LocalStorage<Output>, address::Standard) {
+    let (acc, _prv) = Account::generate_random_account_with_value(Scalar::from(20u64));
+    let (pk, enc) = acc.get_account();
+    let owner = address::Address::standard_address(address::Network::Mainnet, pk);
+    let output = Output::coin(OutputData::Coin(OutputCoin {
+        encrypt: enc,
+        owner: owner.as_hex(),
+    }));
+
+    let mut storage = LocalStorage::<Output>::new(3);
+    for i in 0..ADDRESS_UTXOS {
+        let mut id = [0u8; 32];
+        id[..4].copy_from_slice(&i.to_be_bytes());
+        let utxo = Utxo::from_hash(Hash(id), 0);
+        storage
+            .add(utxo.to_bytes(), output.clone(), IOType::Coin as usize)
+            .unwrap();
+    }
+    let address = address::Standard::from_hex(&owner.as_hex());
+    (storage, address)
+}
+
+fn getutxos_cloned(c: &mut Criterion) {
+    let (storage, address) = address_storage();
+    c.bench_function("getUtxos: clone and encode 10k outputs", move |b| {
+        b.iter(|| {
+            let mut outputs: Vec<(Utxo, String)> = Vec::new();
+            for (key, output) in storage.data.get(&(IOType::Coin as usize)).unwrap().iter() {
+                let addr = output.output.get_owner_address().unwrap();
+                if address::Standard::from_hex(addr).public_key == address.public_key {
+                    let output = output.clone();
+                    outputs.push((
+                        bincode::deserialize(key).unwrap(),
+                        hex::encode(bincode::serialize(&output).unwrap()),
+                    ));
+                }
+            }
+            serde_json::to_value(&outputs).unwrap()
+        })
+    });
+}
+
+fn getutxos_raw(c: &mut Criterion) {
+    let (storage, address) = address_storage();
+    let view = storage.read_view();
+    c.bench_function("getUtxos: cached encoding of 10k outputs", move |b| {
+        b.iter(|| {
+            let outputs = raw_outputs_by_address(&view, &address, IOType::Coin);
+            serde_json::to_value(&outputs).unwrap()
+        })
+    });
+}
+
+criterion_group! {
+    name = raw_outputs;
+    config = Criterion::default().sample_size(10);
+    targets = getutxos_cloned, getutxos_raw,
+}
+criterion_main!(raw_outputs);
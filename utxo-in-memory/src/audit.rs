@@ -0,0 +1,323 @@
+//! Integrity audit of the in-memory utxo set against PostgreSQL.
+//!
+//! Both sides are read in key order, one page at a time, and merged per
+//! partition. The store lock is only held while a single page is copied, so
+//! block processing is never blocked for longer than one page read. The size
+//! of a page is set with `AUDIT_PAGE_SIZE`.
+//!
+//! Blocks processed while the audit runs can show up as divergence. The report
+//! carries the block height at start and end, so a run over a moving height
+//! should be repeated before raising an alert.
+
+use crate::db::{KeyId, LocalDBtrait};
+use crate::error::UtxosetError;
+use crate::pgsql::get_psql_connection;
+use crate::threadpool::env_or;
+use crate::UTXO_STORAGE;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use zkvm::zkos_types::Utxo;
+
+/// Number of utxos read per page from either side.
+pub const DEFAULT_AUDIT_PAGE_SIZE: usize = 5_000;
+/// Number of example keys kept per divergence class.
+pub const AUDIT_EXAMPLE_LIMIT: usize = 20;
+
+// utxo tables by partition, same order as IOType
+const AUDIT_TABLES: [&str; 3] = ["utxo_coin_logs", "utxo_memo_logs", "utxo_state_logs"];
+
+/// Utxo key and the bincode of its output.
+pub type AuditEntry = (KeyId, Vec<u8>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AuditDivergence {
+    pub count: u64,
+    // at most AUDIT_EXAMPLE_LIMIT utxos, as <txid>:<output_index>
+    pub examples: Vec<String>,
+}
+
+impl AuditDivergence {
+    fn record(&mut self, key: &KeyId) {
+        self.count += 1;
+        if self.examples.len() < AUDIT_EXAMPLE_LIMIT {
+            self.examples.push(match Utxo::from_bytes(key) {
+                Some(utxo) => utxo.to_string(),
+                None => hex::encode(key),
+            });
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PartitionAudit {
+    pub io_type: usize,
+    pub memory_count: u64,
+    pub db_count: u64,
+    pub missing_in_memory: AuditDivergence,
+    pub missing_in_db: AuditDivergence,
+    pub value_mismatch: AuditDivergence,
+}
+
+impl PartitionAudit {
+    pub fn divergence_count(&self) -> u64 {
+        self.missing_in_memory.count + self.missing_in_db.count + self.value_mismatch.count
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AuditReport {
+    // true if no partition diverges
+    pub consistent: bool,
+    pub partitions: Vec<PartitionAudit>,
+    pub block_height_start: usize,
+    pub block_height_end: usize,
+    // longest time the utxo store lock was held for a page
+    pub max_lock_held_ms: u64,
+}
+
+impl AuditReport {
+    pub fn divergence_count(&self) -> u64 {
+        self.partitions
+            .iter()
+            .map(|partition| partition.divergence_count())
+            .sum()
+    }
+}
+
+// reads pages of a sorted source, each page starting after the last key read
+struct PageStream {
+    buffer: VecDeque<AuditEntry>,
+    last_key: Option<KeyId>,
+    done: bool,
+}
+
+impl PageStream {
+    fn new() -> Self {
+        PageStream {
+            buffer: VecDeque::new(),
+            last_key: None,
+            done: false,
+        }
+    }
+
+    fn next<F>(&mut self, fetch: &mut F) -> Result<Option<AuditEntry>, UtxosetError>
+    where
+        F: FnMut(Option<&KeyId>) -> Result<Vec<AuditEntry>, UtxosetError>,
+    {
+        if self.buffer.is_empty() && !self.done {
+            let page = fetch(self.last_key.as_ref())?;
+            match page.last() {
+                Some((key, _)) => self.last_key = Some(key.clone()),
+                None => self.done = true,
+            }
+            self.buffer.extend(page);
+        }
+        Ok(self.buffer.pop_front())
+    }
+}
+
+/// Merges two key ordered sources of a partition and records every difference.
+/// Each source returns the page of entries after the given key, and an empty page at the end.
+pub fn audit_partition<M, D>(
+    io_type: usize,
+    mut memory_page: M,
+    mut db_page: D,
+) -> Result<PartitionAudit, UtxosetError>
+where
+    M: FnMut(Option<&KeyId>) -> Result<Vec<AuditEntry>, UtxosetError>,
+    D: FnMut(Option<&KeyId>) -> Result<Vec<AuditEntry>, UtxosetError>,
+{
+    let mut audit = PartitionAudit {
+        io_type,
+        ..PartitionAudit::default()
+    };
+    let mut memory = PageStream::new();
+    let mut db = PageStream::new();
+    let mut memory_entry = memory.next(&mut memory_page)?;
+    let mut db_entry = db.next(&mut db_page)?;
+    loop {
+        let order = match (&memory_entry, &db_entry) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((memory_key, _)), Some((db_key, _))) => memory_key.cmp(db_key),
+        };
+        match order {
+            Ordering::Less => {
+                let (key, _) = memory_entry.take().unwrap();
+                audit.memory_count += 1;
+                audit.missing_in_db.record(&key);
+                memory_entry = memory.next(&mut memory_page)?;
+            }
+            Ordering::Greater => {
+                let (key, _) = db_entry.take().unwrap();
+                audit.db_count += 1;
+                audit.missing_in_memory.record(&key);
+                db_entry = db.next(&mut db_page)?;
+            }
+            Ordering::Equal => {
+                let (key, memory_value) = memory_entry.take().unwrap();
+                let (_, db_value) = db_entry.take().unwrap();
+                audit.memory_count += 1;
+                audit.db_count += 1;
+                if memory_value != db_value {
+                    audit.value_mismatch.record(&key);
+                }
+                memory_entry = memory.next(&mut memory_page)?;
+                db_entry = db.next(&mut db_page)?;
+            }
+        }
+    }
+    Ok(audit)
+}
+
+/// Compares every partition of the in-memory utxo set with its PostgreSQL table.
+pub fn compare_memory_to_psql() -> Result<AuditReport, UtxosetError> {
+    // a zero page size would read both sides as empty
+    let page_size = env_or("AUDIT_PAGE_SIZE", DEFAULT_AUDIT_PAGE_SIZE).max(1);
+    let (partition_size, block_height_start) = {
+        let utxo_storage = UTXO_STORAGE.lock().unwrap();
+        (utxo_storage.partition_size, utxo_storage.block_height)
+    };
+    let mut client = get_psql_connection()?;
+    let mut max_lock_held = Duration::from_millis(0);
+    let mut partitions: Vec<PartitionAudit> = Vec::new();
+
+    for (io_type, table) in AUDIT_TABLES.iter().enumerate().take(partition_size) {
+        let query = format!(
+            "SELECT utxo, output FROM public.{} WHERE ($1::BYTEA IS NULL OR utxo > $1) ORDER BY utxo ASC LIMIT $2;",
+            table
+        );
+        let memory_page = |offset_key: Option<&KeyId>| -> Result<Vec<AuditEntry>, UtxosetError> {
+            let started = Instant::now();
+            let page = {
+                let utxo_storage = UTXO_STORAGE.lock().unwrap();
+                utxo_storage.get_utxos_page(io_type, offset_key, page_size)?
+            };
+            max_lock_held = max_lock_held.max(started.elapsed());
+            let mut entries: Vec<AuditEntry> = Vec::with_capacity(page.entries.len());
+            for entry in page.entries {
+                entries.push((entry.keyid, bincode::serialize(&entry.output)?));
+            }
+            Ok(entries)
+        };
+        let db_page = |offset_key: Option<&KeyId>| -> Result<Vec<AuditEntry>, UtxosetError> {
+            let rows = client.query(query.as_str(), &[&offset_key, &(page_size as i64)])?;
+            Ok(rows
+                .iter()
+                .map(|row| (row.get("utxo"), row.get("output")))
+                .collect())
+        };
+        partitions.push(audit_partition(io_type, memory_page, db_page)?);
+    }
+
+    let block_height_end = UTXO_STORAGE.lock().unwrap().block_height;
+    Ok(AuditReport {
+        consistent: partitions
+            .iter()
+            .all(|partition| partition.divergence_count() == 0),
+        partitions,
+        block_height_start,
+        block_height_end,
+        max_lock_held_ms: max_lock_held.as_millis() as u64,
+    })
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use zkvm::Hash;
+
+    fn key(i: u8) -> KeyId {
+        Utxo::from_hash(Hash([i; 32]), 0).to_bytes()
+    }
+
+    // pages of `source` after the offset key, `page_size` entries at a time
+    fn pages(
+        source: &BTreeMap<KeyId, Vec<u8>>,
+        page_size: usize,
+    ) -> impl FnMut(Option<&KeyId>) -> Result<Vec<AuditEntry>, UtxosetError> + '_ {
+        move |offset_key: Option<&KeyId>| {
+            let lower = match offset_key {
+                Some(key) => Bound::Excluded(key.clone()),
+                None => Bound::Unbounded,
+            };
+            Ok(source
+                .range((lower, Bound::Unbounded))
+                .take(page_size)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn audit_partition_consistent_test() {
+        let mut memory: BTreeMap<KeyId, Vec<u8>> = BTreeMap::new();
+        for i in 0..50u8 {
+            memory.insert(key(i), vec![i]);
+        }
+        let db = memory.clone();
+        let audit = audit_partition(0, pages(&memory, 7), pages(&db, 3)).unwrap();
+        assert_eq!(audit.memory_count, 50);
+        assert_eq!(audit.db_count, 50);
+        assert_eq!(audit.divergence_count(), 0);
+    }
+
+    #[test]
+    fn audit_partition_divergence_test() {
+        let mut memory: BTreeMap<KeyId, Vec<u8>> = BTreeMap::new();
+        for i in 0..50u8 {
+            memory.insert(key(i), vec![i]);
+        }
+        let mut db = memory.clone();
+        // lost by the database
+        db.remove(&key(0));
+        db.remove(&key(25));
+        // lost by the memory store
+        memory.remove(&key(49));
+        // changed output
+        db.insert(key(10), vec![0xff]);
+
+        let audit = audit_partition(1, pages(&memory, 4), pages(&db, 6)).unwrap();
+        assert_eq!(audit.io_type, 1);
+        assert_eq!(audit.memory_count, 49);
+        assert_eq!(audit.db_count, 48);
+        assert_eq!(audit.missing_in_db.count, 2);
+        assert_eq!(
+            audit.missing_in_db.examples,
+            vec![
+                Utxo::from_bytes(&key(0)).unwrap().to_string(),
+                Utxo::from_bytes(&key(25)).unwrap().to_string()
+            ]
+        );
+        assert_eq!(audit.missing_in_memory.count, 1);
+        assert_eq!(
+            audit.missing_in_memory.examples,
+            vec![Utxo::from_bytes(&key(49)).unwrap().to_string()]
+        );
+        assert_eq!(audit.value_mismatch.count, 1);
+        assert_eq!(
+            audit.value_mismatch.examples,
+            vec![Utxo::from_bytes(&key(10)).unwrap().to_string()]
+        );
+    }
+
+    #[test]
+    fn audit_partition_example_limit_test() {
+        let mut memory: BTreeMap<KeyId, Vec<u8>> = BTreeMap::new();
+        for i in 0..100u8 {
+            memory.insert(key(i), vec![i]);
+        }
+        let db: BTreeMap<KeyId, Vec<u8>> = BTreeMap::new();
+        let audit = audit_partition(2, pages(&memory, 9), pages(&db, 9)).unwrap();
+        assert_eq!(audit.missing_in_db.count, 100);
+        assert_eq!(audit.missing_in_db.examples.len(), AUDIT_EXAMPLE_LIMIT);
+    }
+}
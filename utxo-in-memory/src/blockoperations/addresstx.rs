@@ -0,0 +1,271 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Index of the applied transactions by the addresses they involve.
+//!
+//! Every owner address of the inputs and outputs of a tx is indexed, including
+//! the decoys of the anonymity set of a QuisQuis transfer, so the index does not
+//! tell the real sender or receiver apart from the decoys.
+
+use crate::state::global_state;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+use zkvm::zkos_types::{Input, Output};
+
+/// Number of most recent index entries kept in memory.
+pub const ADDRESS_TX_RETENTION: usize = 100_000;
+
+/// Number of txs returned per page.
+pub const ADDRESS_TX_PAGE_SIZE: usize = 50;
+
+lazy_static! {
+    /// Address index of the global state, see [`crate::state`].
+    pub static ref ADDRESS_TXS: Arc<Mutex<AddressTxIndex>> = global_state().address_txs.clone();
+}
+
+/// Side of the tx an address appears on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxDirection {
+    In,
+    Out,
+    Both,
+}
+
+impl TxDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxDirection::In => "in",
+            TxDirection::Out => "out",
+            TxDirection::Both => "both",
+        }
+    }
+
+    pub fn from_str(direction: &str) -> Result<TxDirection, &'static str> {
+        match direction {
+            "in" => Ok(TxDirection::In),
+            "out" => Ok(TxDirection::Out),
+            "both" => Ok(TxDirection::Both),
+            _ => Err("Error::Invalid tx direction"),
+        }
+    }
+
+    fn merge(self, other: TxDirection) -> TxDirection {
+        if self == other {
+            self
+        } else {
+            TxDirection::Both
+        }
+    }
+}
+
+/// A tx involving an address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AddressTx {
+    pub address: String,
+    pub txid: String,
+    pub block_height: u64,
+    pub direction: TxDirection,
+}
+
+/// One entry per distinct owner address of the inputs and outputs of a tx,
+/// in order of first appearance.
+pub fn address_txs(
+    txid: &str,
+    block_height: u64,
+    inputs: &[Input],
+    outputs: &[Output],
+) -> Vec<AddressTx> {
+    let owners = inputs
+        .iter()
+        .filter_map(|input| {
+            input
+                .as_owner_address()
+                .map(|owner| (owner, TxDirection::In))
+        })
+        .chain(outputs.iter().filter_map(|output| {
+            output
+                .output
+                .get_owner_address()
+                .map(|owner| (owner, TxDirection::Out))
+        }));
+    let mut entries: Vec<AddressTx> = Vec::new();
+    for (owner, direction) in owners {
+        match entries.iter_mut().find(|entry| &entry.address == owner) {
+            Some(entry) => entry.direction = entry.direction.merge(direction),
+            None => entries.push(AddressTx {
+                address: owner.clone(),
+                txid: txid.to_string(),
+                block_height,
+                direction,
+            }),
+        }
+    }
+    entries
+}
+
+/// In-memory index of the most recent entries, by address.
+#[derive(Debug, Clone, Default)]
+pub struct AddressTxIndex {
+    pub retention: usize,
+    // addresses in order of insertion, for eviction
+    order: VecDeque<String>,
+    by_address: HashMap<String, VecDeque<AddressTx>>,
+}
+
+impl AddressTxIndex {
+    pub fn new(retention: usize) -> Self {
+        AddressTxIndex {
+            retention,
+            order: VecDeque::new(),
+            by_address: HashMap::new(),
+        }
+    }
+
+    /// Inserts an entry, evicting the oldest entry once the retention is reached.
+    /// An entry already indexed for the same address and tx is ignored.
+    pub fn insert(&mut self, entry: AddressTx) -> bool {
+        let txs = self.by_address.entry(entry.address.clone()).or_default();
+        if txs.iter().any(|tx| tx.txid == entry.txid) {
+            return false;
+        }
+        self.order.push_back(entry.address.clone());
+        txs.push_back(entry);
+        while self.order.len() > self.retention {
+            let address = match self.order.pop_front() {
+                Some(address) => address,
+                None => break,
+            };
+            if let Some(txs) = self.by_address.get_mut(&address) {
+                txs.pop_front();
+                if txs.is_empty() {
+                    self.by_address.remove(&address);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Page of the txs involving `address`, newest first. Pages start at 0.
+    pub fn page(&self, address: &str, page: usize) -> Vec<AddressTx> {
+        match self.by_address.get(address) {
+            Some(txs) => txs
+                .iter()
+                .rev()
+                .skip(page * ADDRESS_TX_PAGE_SIZE)
+                .take(ADDRESS_TX_PAGE_SIZE)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Txids of the retained txs involving `address` in the blocks
+    /// `from_height..=to_height`, oldest first.
+    pub fn txids(&self, address: &str, from_height: u64, to_height: u64) -> Vec<String> {
+        match self.by_address.get(address) {
+            Some(txs) => txs
+                .iter()
+                .filter(|tx| tx.block_height >= from_height && tx.block_height <= to_height)
+                .map(|tx| tx.txid.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Drops the addresses left without entries and shrinks the maps to their
+    /// lengths. Returns the approximate number of bytes reclaimed.
+    pub fn compact(&mut self) -> u64 {
+        let before = self.allocated_bytes();
+        self.by_address.retain(|_, txs| !txs.is_empty());
+        for txs in self.by_address.values_mut() {
+            txs.shrink_to_fit();
+        }
+        self.by_address.shrink_to_fit();
+        self.order.shrink_to_fit();
+        before.saturating_sub(self.allocated_bytes())
+    }
+
+    // table and deque allocations, the strings are not counted
+    fn allocated_bytes(&self) -> u64 {
+        let entries: usize = self.by_address.values().map(|txs| txs.capacity()).sum();
+        (self.by_address.capacity() * (size_of::<(String, VecDeque<AddressTx>)>() + 1)
+            + entries * size_of::<AddressTx>()
+            + self.order.capacity() * size_of::<String>()) as u64
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(address: &str, txid: &str, block_height: u64) -> AddressTx {
+        AddressTx {
+            address: address.to_string(),
+            txid: txid.to_string(),
+            block_height,
+            direction: TxDirection::In,
+        }
+    }
+
+    #[test]
+    fn address_tx_index_test() {
+        let mut index = AddressTxIndex::new(3);
+        assert!(index.insert(entry("alice", "aa", 10)));
+        assert!(index.insert(entry("bob", "aa", 10)));
+        assert!(!index.insert(entry("alice", "aa", 10)));
+        assert!(index.insert(entry("alice", "bb", 11)));
+        assert_eq!(index.len(), 3);
+
+        let txs = index.page("alice", 0);
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].txid, "bb");
+        assert!(index.page("alice", 1).is_empty());
+        assert_eq!(index.txids("alice", 0, 20), vec!["aa", "bb"]);
+        assert_eq!(index.txids("alice", 11, 11), vec!["bb"]);
+
+        // the oldest entry is evicted
+        assert!(index.insert(entry("carol", "cc", 12)));
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.page("alice", 0).len(), 1);
+        assert_eq!(index.page("bob", 0).len(), 1);
+    }
+
+    #[test]
+    fn address_tx_index_compact_test() {
+        let mut index = AddressTxIndex::new(10);
+        for i in 0..1000 {
+            index.insert(entry(&format!("address-{}", i), "aa", 10));
+        }
+        // the evicted addresses leave the table at its largest size
+        assert_eq!(index.by_address.len(), 10);
+        assert!(index.by_address.capacity() > 100);
+        let reclaimed = index.compact();
+        assert!(reclaimed > 0);
+        assert!(index.by_address.capacity() < 100);
+        assert_eq!(index.page("address-999", 0).len(), 1);
+        assert_eq!(index.compact(), 0);
+    }
+
+    #[test]
+    fn tx_direction_test() {
+        assert_eq!(TxDirection::In.merge(TxDirection::In), TxDirection::In);
+        assert_eq!(TxDirection::In.merge(TxDirection::Out), TxDirection::Both);
+        for direction in [TxDirection::In, TxDirection::Out, TxDirection::Both] {
+            assert_eq!(TxDirection::from_str(direction.as_str()), Ok(direction));
+        }
+        assert!(TxDirection::from_str("sideways").is_err());
+    }
+}
@@ -0,0 +1,740 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Background index of the balances of the addresses owned by the node operator.
+//!
+//! The indexer is enabled by `BALANCE_INDEXER_CONFIG`, the path of a json file
+//! listing the indexed addresses with their view keys:
+//!
+//! ```json
+//! [{ "address": "0c0a2b...", "view_key": "<hex encoded scalar>" }]
+//! ```
+//!
+//! The file holds secret keys, it is refused unless only its owner can read it.
+//! Each [`UtxoState`] indexes the addresses of its network. After a block is applied,
+//! the utxo events of the indexed addresses are handed to the indexer thread, which
+//! decrypts the value of the added coins with the shared baby-step giant-step table
+//! and keeps the balance and the value of every coin of the address. Spent coins are
+//! subtracted, and the changes of the most recent blocks are kept so a rolled back
+//! block can be undone with `rollback_to`. Balances and coin values are persisted to
+//! the store of the state and loaded at startup.
+
+use crate::blockoperations::subscriptions::{UtxoEvent, UtxoEventKind};
+use crate::db::utxo_key;
+use crate::error::UtxosetError;
+use crate::network::address_network;
+use crate::pgsql::PersistenceStore;
+use crate::state::{global_state, UtxoState};
+use crate::threadpool::{env_or, ThreadPool, DEFAULT_QUEUE_CAPACITY};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use quisquislib::elgamal::ElGamalCommitment;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use zkvm::zkos_types::IOType;
+
+/// Number of baby steps of the decryption table.
+pub const DEFAULT_BSGS_BABY_STEPS: usize = 1 << 16;
+
+/// Number of giant steps tried per decryption, values up to 2^32 are found.
+pub const DEFAULT_BSGS_GIANT_STEPS: usize = 1 << 16;
+
+/// Number of most recent blocks that can be rolled back.
+pub const DEFAULT_BALANCE_ROLLBACK_DEPTH: usize = 100;
+
+lazy_static! {
+    pub static ref BSGS_TABLE: BsgsTable = {
+        BsgsTable::new(
+            env_or("BSGS_BABY_STEPS", DEFAULT_BSGS_BABY_STEPS),
+            env_or("BSGS_GIANT_STEPS", DEFAULT_BSGS_GIANT_STEPS),
+        )
+    };
+    pub static ref BALANCE_INDEXER: Arc<Mutex<BalanceIndexer>> =
+        global_state().balance_indexer.clone();
+    // a single thread, the blocks are indexed in order
+    pub static ref THREADPOOL_BALANCE_INDEXER: Mutex<ThreadPool> = {
+        Mutex::new(ThreadPool::with_capacity(
+            1,
+            env_or("THREADPOOL_BALANCE_INDEXER_CAPACITY", DEFAULT_QUEUE_CAPACITY),
+            String::from("THREADPOOL_BALANCE_INDEXER"),
+        ))
+    };
+}
+
+/// Baby-step giant-step table solving `v·B = P` for the small values of coins.
+pub struct BsgsTable {
+    // compressed j·B -> j, for j < step
+    baby_steps: HashMap<[u8; 32], u64>,
+    step: u64,
+    giant_steps: u64,
+    // -step·B
+    giant: RistrettoPoint,
+}
+
+impl BsgsTable {
+    pub fn new(baby_steps: usize, giant_steps: usize) -> Self {
+        let step = baby_steps.max(1) as u64;
+        let mut table = HashMap::with_capacity(step as usize);
+        let mut point = RistrettoPoint::identity();
+        for j in 0..step {
+            table.insert(point.compress().to_bytes(), j);
+            point += RISTRETTO_BASEPOINT_POINT;
+        }
+        BsgsTable {
+            baby_steps: table,
+            step,
+            giant_steps: giant_steps.max(1) as u64,
+            giant: -point,
+        }
+    }
+
+    /// Largest value the table can find.
+    pub fn max_value(&self) -> u64 {
+        self.step.saturating_mul(self.giant_steps) - 1
+    }
+
+    /// The value `v` such that `point = v·B`, None if it is above `max_value`.
+    pub fn solve(&self, point: RistrettoPoint) -> Option<u64> {
+        let mut target = point;
+        for i in 0..self.giant_steps {
+            if let Some(j) = self.baby_steps.get(&target.compress().to_bytes()) {
+                return Some(i * self.step + j);
+            }
+            target += self.giant;
+        }
+        None
+    }
+}
+
+/// Decrypts the value of a coin with the view key of its owner, `v·B = d - x·c`.
+pub fn decrypt_value(
+    encrypt: &ElGamalCommitment,
+    view_key: &Scalar,
+    table: &BsgsTable,
+) -> Option<u64> {
+    let bytes = encrypt.to_bytes();
+    let c = CompressedRistretto::from_slice(&bytes[..32]).decompress()?;
+    let d = CompressedRistretto::from_slice(&bytes[32..64]).decompress()?;
+    table.solve(d - view_key * c)
+}
+
+/// An address of the config file and its view key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedKey {
+    pub address: String,
+    // hex encoded scalar
+    pub view_key: String,
+}
+
+impl IndexedKey {
+    pub fn scalar(&self) -> Result<Scalar, &'static str> {
+        let bytes = hex::decode(self.view_key.trim()).map_err(|_| "Error::Invalid view key")?;
+        let mut scalar = [0u8; 32];
+        if bytes.len() != 32 {
+            return Err("Error::Invalid view key length");
+        }
+        scalar.copy_from_slice(&bytes);
+        Scalar::from_canonical_bytes(scalar).ok_or("Error::Invalid view key")
+    }
+}
+
+/// Reads the indexed keys from `path`, refusing a file readable by other users.
+pub fn load_indexer_config(path: &str) -> Result<Vec<IndexedKey>, UtxosetError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(UtxosetError::InsecureFile {
+                path: path.to_string(),
+                mode: mode & 0o777,
+            });
+        }
+    }
+    let contents = std::fs::read_to_string(path)?;
+    let keys: Vec<IndexedKey> =
+        serde_json::from_str(&contents).map_err(|e| UtxosetError::CorruptFile {
+            path: path.to_string(),
+            reason: e.to_string(),
+        })?;
+    for key in keys.iter() {
+        key.scalar().map_err(|e| UtxosetError::CorruptFile {
+            path: path.to_string(),
+            reason: format!("{} of {}", e, key.address),
+        })?;
+    }
+    Ok(keys)
+}
+
+/// Cached balance of an indexed address and the height of its last change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedBalance {
+    pub address: String,
+    pub balance: u64,
+    pub block_height: u64,
+}
+
+/// Decrypted value of a coin of an indexed address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedUtxo {
+    // bincode encoded utxo
+    pub utxo: Vec<u8>,
+    pub address: String,
+    pub value: u64,
+}
+
+/// A utxo event of an indexed address, with the value of an added coin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecryptedEvent {
+    pub address: String,
+    pub utxo: Vec<u8>,
+    pub kind: UtxoEventKind,
+    // None for a removed utxo, or an added coin whose value was not found
+    pub value: Option<u64>,
+}
+
+/// Changes to persist after a block is indexed or rolled back.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BalanceUpdate {
+    pub balances: Vec<IndexedBalance>,
+    pub added: Vec<IndexedUtxo>,
+    pub removed: Vec<Vec<u8>>,
+}
+
+impl BalanceUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.balances.is_empty() && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexedAddress {
+    view_key: Scalar,
+    balance: u64,
+    block_height: u64,
+    // encoded utxo -> value
+    utxos: HashMap<Vec<u8>, u64>,
+}
+
+// a coin added or removed by a block, kept to undo it
+#[derive(Debug, Clone)]
+struct BalanceChange {
+    height: u64,
+    address: String,
+    utxo: Vec<u8>,
+    value: u64,
+    kind: UtxoEventKind,
+}
+
+/// Balances and coin values of the indexed addresses.
+#[derive(Debug, Clone)]
+pub struct BalanceIndexer {
+    pub rollback_depth: u64,
+    addresses: HashMap<String, IndexedAddress>,
+    journal: VecDeque<BalanceChange>,
+    // height of the most recent change dropped from the journal
+    pruned_height: Option<u64>,
+}
+
+impl BalanceIndexer {
+    pub fn new(keys: Vec<(String, Scalar)>, rollback_depth: u64) -> Self {
+        let addresses = keys
+            .into_iter()
+            .map(|(address, view_key)| {
+                (
+                    address,
+                    IndexedAddress {
+                        view_key,
+                        balance: 0,
+                        block_height: 0,
+                        utxos: HashMap::new(),
+                    },
+                )
+            })
+            .collect();
+        BalanceIndexer {
+            rollback_depth,
+            addresses,
+            journal: VecDeque::new(),
+            pruned_height: None,
+        }
+    }
+
+    /// Disabled indexer with the rollback depth of `BALANCE_ROLLBACK_DEPTH`.
+    pub fn from_config() -> Self {
+        BalanceIndexer::new(
+            Vec::new(),
+            env_or("BALANCE_ROLLBACK_DEPTH", DEFAULT_BALANCE_ROLLBACK_DEPTH) as u64,
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+
+    pub fn is_indexed(&self, address: &str) -> bool {
+        self.addresses.contains_key(address)
+    }
+
+    pub fn view_keys(&self) -> HashMap<String, Scalar> {
+        self.addresses
+            .iter()
+            .map(|(address, indexed)| (address.clone(), indexed.view_key))
+            .collect()
+    }
+
+    pub fn balance(&self, address: &str) -> Option<IndexedBalance> {
+        self.addresses.get(address).map(|indexed| IndexedBalance {
+            address: address.to_string(),
+            balance: indexed.balance,
+            block_height: indexed.block_height,
+        })
+    }
+
+    /// Restores the state persisted to the store, for the addresses still indexed.
+    pub fn load(&mut self, balances: Vec<IndexedBalance>, utxos: Vec<IndexedUtxo>) {
+        for balance in balances {
+            if let Some(indexed) = self.addresses.get_mut(&balance.address) {
+                indexed.balance = balance.balance;
+                indexed.block_height = balance.block_height;
+            }
+        }
+        for utxo in utxos {
+            if let Some(indexed) = self.addresses.get_mut(&utxo.address) {
+                indexed.utxos.insert(utxo.utxo, utxo.value);
+            }
+        }
+    }
+
+    /// Applies the decrypted events of the block at `height`.
+    /// An added coin whose value was not found is left out of the balance.
+    pub fn apply_events(&mut self, height: u64, events: Vec<DecryptedEvent>) -> BalanceUpdate {
+        let mut update = BalanceUpdate::default();
+        let mut changed = Vec::new();
+        for event in events {
+            let indexed = match self.addresses.get_mut(&event.address) {
+                Some(indexed) => indexed,
+                None => continue,
+            };
+            let value = match event.kind {
+                UtxoEventKind::Added => {
+                    let value = match event.value {
+                        Some(value) => value,
+                        None => {
+                            eprintln!(
+                                "Balance indexer: value of a coin of {} not found",
+                                event.address
+                            );
+                            continue;
+                        }
+                    };
+                    indexed.utxos.insert(event.utxo.clone(), value);
+                    indexed.balance += value;
+                    update.added.push(IndexedUtxo {
+                        utxo: event.utxo.clone(),
+                        address: event.address.clone(),
+                        value,
+                    });
+                    value
+                }
+                UtxoEventKind::Removed => match indexed.utxos.remove(&event.utxo) {
+                    Some(value) => {
+                        indexed.balance -= value;
+                        update.removed.push(event.utxo.clone());
+                        value
+                    }
+                    None => continue,
+                },
+            };
+            indexed.block_height = height;
+            changed.push(event.address.clone());
+            self.journal.push_back(BalanceChange {
+                height,
+                address: event.address,
+                utxo: event.utxo,
+                value,
+                kind: event.kind,
+            });
+        }
+        while let Some(change) = self.journal.front() {
+            if change.height + self.rollback_depth > height {
+                break;
+            }
+            self.pruned_height = Some(change.height);
+            self.journal.pop_front();
+        }
+        update.balances = self.balances_of(changed);
+        update
+    }
+
+    /// Undoes the blocks above `height`, most recent first.
+    /// Fails if one of them is older than the kept changes.
+    pub fn rollback_to(&mut self, height: u64) -> Result<BalanceUpdate, &'static str> {
+        if matches!(self.pruned_height, Some(pruned) if pruned > height) {
+            return Err("Error::Rollback deeper than the kept balance changes");
+        }
+        let mut update = BalanceUpdate::default();
+        let mut changed = Vec::new();
+        while matches!(self.journal.back(), Some(change) if change.height > height) {
+            let change = self.journal.pop_back().unwrap();
+            let indexed = match self.addresses.get_mut(&change.address) {
+                Some(indexed) => indexed,
+                None => continue,
+            };
+            match change.kind {
+                UtxoEventKind::Added => {
+                    indexed.utxos.remove(&change.utxo);
+                    indexed.balance -= change.value;
+                    update.removed.push(change.utxo);
+                }
+                UtxoEventKind::Removed => {
+                    indexed.utxos.insert(change.utxo.clone(), change.value);
+                    indexed.balance += change.value;
+                    update.added.push(IndexedUtxo {
+                        utxo: change.utxo,
+                        address: change.address.clone(),
+                        value: change.value,
+                    });
+                }
+            }
+            indexed.block_height = height;
+            changed.push(change.address);
+        }
+        update.balances = self.balances_of(changed);
+        Ok(update)
+    }
+
+    fn balances_of(&self, mut addresses: Vec<String>) -> Vec<IndexedBalance> {
+        addresses.sort();
+        addresses.dedup();
+        addresses
+            .iter()
+            .filter_map(|address| self.balance(address))
+            .collect()
+    }
+}
+
+/// Decrypts the added coins of `events` owned by the addresses of `view_keys`.
+pub fn decrypt_events(
+    view_keys: &HashMap<String, Scalar>,
+    events: &[UtxoEvent],
+    table: &BsgsTable,
+) -> Vec<DecryptedEvent> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let view_key = view_keys.get(&event.address)?;
+            let value = match (event.event, event.io_type) {
+                (UtxoEventKind::Added, IOType::Coin) => {
+                    let coin = event.output.as_out_coin()?;
+                    decrypt_value(&coin.encrypt, view_key, table)
+                }
+                (UtxoEventKind::Added, _) => return None,
+                (UtxoEventKind::Removed, _) => None,
+            };
+            Some(DecryptedEvent {
+                address: event.address.clone(),
+                utxo: utxo_key(&event.utxo),
+                kind: event.event,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Loads the keys of `BALANCE_INDEXER_CONFIG` of the addresses of the network of `state`,
+/// and their balances persisted to its store.
+/// The indexer stays disabled if the variable is not set.
+pub fn init_balance_indexer(state: &UtxoState) -> Result<(), UtxosetError> {
+    let path = match crate::config::config_value("BALANCE_INDEXER_CONFIG") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let keys: Vec<(String, Scalar)> = load_indexer_config(&path)?
+        .into_iter()
+        .filter(|key| address_network(&key.address).ok() == Some(state.network()))
+        .filter_map(|key| Some((key.address.clone(), key.scalar().ok()?)))
+        .collect();
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let rollback_depth = state.balance_indexer.lock().unwrap().rollback_depth;
+    let mut indexer = BalanceIndexer::new(keys, rollback_depth);
+    let (balances, utxos) = state.store().load_indexed_balances()?;
+    indexer.load(balances, utxos);
+    *state.balance_indexer.lock().unwrap() = indexer;
+    // built once, before the first block
+    lazy_static::initialize(&BSGS_TABLE);
+    Ok(())
+}
+
+/// Hands the events of the indexed addresses in the block at `height` of `state` to the
+/// indexer thread.
+pub fn index_utxo_events(state: &UtxoState, height: u64, events: &[UtxoEvent]) {
+    let indexer = state.balance_indexer.lock().unwrap();
+    if !indexer.is_enabled() {
+        return;
+    }
+    let events: Vec<UtxoEvent> = events
+        .iter()
+        .filter(|event| indexer.is_indexed(&event.address))
+        .cloned()
+        .collect();
+    drop(indexer);
+    if events.is_empty() {
+        return;
+    }
+    let indexer = state.balance_indexer.clone();
+    let store = state.store();
+    let sql_queue = state.sql_queue.clone();
+    let threadpool = THREADPOOL_BALANCE_INDEXER.lock().unwrap();
+    threadpool.execute(move || {
+        // values are decrypted without holding the indexer
+        let view_keys = indexer.lock().unwrap().view_keys();
+        let events = decrypt_events(&view_keys, &events, &BSGS_TABLE);
+        let update = indexer.lock().unwrap().apply_events(height, events);
+        persist_balance_update(update, store, &sql_queue);
+    });
+}
+
+/// Undoes the blocks above `height` in the index of `state`, once the pending blocks
+/// are indexed.
+pub fn rollback_indexed_balances(state: &UtxoState, height: u64) {
+    let indexer = state.balance_indexer.clone();
+    let store = state.store();
+    let sql_queue = state.sql_queue.clone();
+    let threadpool = THREADPOOL_BALANCE_INDEXER.lock().unwrap();
+    threadpool.execute(move || {
+        let result = indexer.lock().unwrap().rollback_to(height);
+        match result {
+            Ok(update) => persist_balance_update(update, store, &sql_queue),
+            Err(e) => eprintln!("Balance indexer rollback to {} failed: {}", height, e),
+        }
+    });
+}
+
+fn persist_balance_update(
+    update: BalanceUpdate,
+    store: Arc<dyn PersistenceStore>,
+    sql_queue: &Mutex<ThreadPool>,
+) {
+    if update.is_empty() {
+        return;
+    }
+    /***************** POstgreSQL Insert Code *********/
+    let treadpool_sql_queue = sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.update_indexed_balances(&update) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to update_indexed_balances: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use zkvm::zkos_types::{Output, OutputCoin, OutputData, Utxo};
+    use zkvm::Hash;
+
+    // encryption of `value` to the view key `x`, c = r·G, d = v·B + r·x·G
+    fn encrypt(value: u64, x: &Scalar) -> ElGamalCommitment {
+        let mut rng = rand::thread_rng();
+        let g = RistrettoPoint::random(&mut rng);
+        let r = Scalar::random(&mut rng);
+        let c = r * g;
+        let d = Scalar::from(value) * RISTRETTO_BASEPOINT_POINT + r * x * g;
+        let mut bytes = c.compress().to_bytes().to_vec();
+        bytes.extend_from_slice(&d.compress().to_bytes());
+        ElGamalCommitment::from_bytes(&bytes).unwrap()
+    }
+
+    fn utxo(height: u64, index: u16) -> Utxo {
+        let mut id = [0u8; 32];
+        id[..8].copy_from_slice(&height.to_be_bytes());
+        Utxo::from_hash(Hash(id), index)
+    }
+
+    fn event(
+        address: &str,
+        x: &Scalar,
+        utxo: Utxo,
+        value: u64,
+        height: u64,
+        kind: UtxoEventKind,
+    ) -> UtxoEvent {
+        let output = Output::coin(OutputData::Coin(OutputCoin {
+            encrypt: encrypt(value, x),
+            owner: address.to_string(),
+        }));
+        UtxoEvent::new(utxo, output, &utxo.tx_id_to_hex(), height, kind).unwrap()
+    }
+
+    fn index_block(
+        indexer: &mut BalanceIndexer,
+        table: &BsgsTable,
+        height: u64,
+        events: &[UtxoEvent],
+    ) -> BalanceUpdate {
+        let events = decrypt_events(&indexer.view_keys(), events, table);
+        indexer.apply_events(height, events)
+    }
+
+    #[test]
+    fn bsgs_table_test() {
+        let table = BsgsTable::new(16, 8);
+        assert_eq!(table.max_value(), 127);
+        for value in [0u64, 1, 15, 16, 100, 127] {
+            let point = Scalar::from(value) * RISTRETTO_BASEPOINT_POINT;
+            assert_eq!(table.solve(point), Some(value));
+        }
+        assert_eq!(
+            table.solve(Scalar::from(128u64) * RISTRETTO_BASEPOINT_POINT),
+            None
+        );
+
+        let x = Scalar::random(&mut rand::thread_rng());
+        assert_eq!(decrypt_value(&encrypt(42, &x), &x, &table), Some(42));
+        let other = Scalar::random(&mut rand::thread_rng());
+        assert_eq!(decrypt_value(&encrypt(42, &x), &other, &table), None);
+    }
+
+    #[test]
+    fn balance_indexer_test() {
+        let table = BsgsTable::new(64, 64);
+        let x = Scalar::random(&mut rand::thread_rng());
+        let bob_key = Scalar::random(&mut rand::thread_rng());
+        let mut indexer = BalanceIndexer::new(vec![("alice".to_string(), x)], 2);
+        assert!(indexer.is_enabled());
+        assert!(indexer.balance("bob").is_none());
+
+        // block 1 pays alice twice and bob once
+        let update = index_block(
+            &mut indexer,
+            &table,
+            1,
+            &[
+                event("alice", &x, utxo(1, 0), 30, 1, UtxoEventKind::Added),
+                event("alice", &x, utxo(1, 1), 12, 1, UtxoEventKind::Added),
+                event("bob", &bob_key, utxo(1, 2), 7, 1, UtxoEventKind::Added),
+            ],
+        );
+        assert_eq!(update.added.len(), 2);
+        assert_eq!(update.balances, vec![indexer.balance("alice").unwrap()]);
+        assert_eq!(indexer.balance("alice").unwrap().balance, 42);
+        assert_eq!(indexer.balance("alice").unwrap().block_height, 1);
+
+        // block 2 spends the coin of 30 and pays 5 back, bob's spend is ignored
+        let update = index_block(
+            &mut indexer,
+            &table,
+            2,
+            &[
+                event("alice", &x, utxo(1, 0), 30, 2, UtxoEventKind::Removed),
+                event("bob", &bob_key, utxo(1, 2), 7, 2, UtxoEventKind::Removed),
+                event("alice", &x, utxo(2, 0), 5, 2, UtxoEventKind::Added),
+            ],
+        );
+        assert_eq!(update.removed, vec![utxo(1, 0).to_bytes()]);
+        assert_eq!(indexer.balance("alice").unwrap().balance, 17);
+
+        // block 3 pays a value above the table, left out of the balance
+        index_block(
+            &mut indexer,
+            &table,
+            3,
+            &[event(
+                "alice",
+                &x,
+                utxo(3, 0),
+                5000,
+                3,
+                UtxoEventKind::Added,
+            )],
+        );
+        assert_eq!(indexer.balance("alice").unwrap().balance, 17);
+        assert_eq!(indexer.balance("alice").unwrap().block_height, 2);
+
+        // block 2 is rolled back
+        let update = indexer.rollback_to(1).unwrap();
+        assert_eq!(update.removed, vec![utxo(2, 0).to_bytes()]);
+        assert_eq!(update.added[0].utxo, utxo(1, 0).to_bytes());
+        let alice = indexer.balance("alice").unwrap();
+        assert_eq!((alice.balance, alice.block_height), (42, 1));
+        assert_eq!(update.balances, vec![alice]);
+
+        // an other block 2 spends both coins
+        index_block(
+            &mut indexer,
+            &table,
+            2,
+            &[
+                event("alice", &x, utxo(1, 0), 30, 2, UtxoEventKind::Removed),
+                event("alice", &x, utxo(1, 1), 12, 2, UtxoEventKind::Removed),
+            ],
+        );
+        assert_eq!(indexer.balance("alice").unwrap().balance, 0);
+
+        // block 1 is out of the rollback depth once block 3 is indexed
+        index_block(&mut indexer, &table, 3, &[]);
+        assert!(indexer.rollback_to(0).is_err());
+
+        // restored from psql
+        let mut restored = BalanceIndexer::new(vec![("alice".to_string(), x)], 2);
+        restored.load(
+            vec![IndexedBalance {
+                address: "alice".to_string(),
+                balance: 5,
+                block_height: 4,
+            }],
+            vec![IndexedUtxo {
+                utxo: utxo(4, 0).to_bytes(),
+                address: "alice".to_string(),
+                value: 5,
+            }],
+        );
+        index_block(
+            &mut restored,
+            &table,
+            5,
+            &[event("alice", &x, utxo(4, 0), 5, 5, UtxoEventKind::Removed)],
+        );
+        assert_eq!(restored.balance("alice").unwrap().balance, 0);
+    }
+
+    #[test]
+    fn indexer_config_test() {
+        let dir = std::env::temp_dir().join(format!("indexer_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keys.json");
+        let x = Scalar::random(&mut rand::thread_rng());
+        let contents = format!(
+            "[{{\"address\": \"alice\", \"view_key\": \"{}\"}}]",
+            hex::encode(x.as_bytes())
+        );
+        std::fs::write(&path, contents).unwrap();
+        let path_str = path.to_str().unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(load_indexer_config(path_str).is_err());
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let keys = load_indexer_config(path_str).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].scalar(), Ok(x));
+
+        std::fs::write(&path, "[{\"address\": \"alice\", \"view_key\": \"00\"}]").unwrap();
+        assert!(load_indexer_config(path_str).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+#![allow(missing_docs)]
+//! Typed block headers of the chain and continuity checks between consecutive blocks.
+
+use crate::state::global_state;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    /// Header of the last block received by the oracle subscriber of the global state.
+    pub static ref LAST_BLOCK_HEADER: Arc<Mutex<Option<BlockHeader>>> =
+        global_state().last_block_header.clone();
+}
+
+/// Errors raised when a block does not extend the chain seen so far.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    #[error("block height gap: expected {expected}, found {found}")]
+    HeightGap { expected: u64, found: u64 },
+
+    #[error("parent hash mismatch at height {height}: expected {expected}, found {found}")]
+    ParentMismatch {
+        height: u64,
+        expected: String,
+        found: String,
+    },
+
+    #[error("invalid block header: {0}")]
+    InvalidHeader(String),
+}
+
+/// Header of a block as reported by the Tendermint RPC.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub height: u64,
+    pub block_hash: String,
+    pub parent_hash: String,
+    pub app_hash: String,
+    pub time: String,
+    pub proposer_address: String,
+}
+
+// Subset of the Tendermint `/block` response used for the header
+#[derive(Deserialize)]
+struct RpcBlockResponse {
+    result: RpcBlockResult,
+}
+
+#[derive(Deserialize)]
+struct RpcBlockResult {
+    block_id: RpcBlockId,
+    block: RpcBlock,
+}
+
+#[derive(Deserialize)]
+struct RpcBlockId {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct RpcBlock {
+    header: RpcHeader,
+}
+
+#[derive(Deserialize)]
+struct RpcHeader {
+    height: String,
+    time: String,
+    last_block_id: RpcBlockId,
+    app_hash: String,
+    proposer_address: String,
+}
+
+impl BlockHeader {
+    /// Parses the header from the JSON response of the Tendermint `/block` RPC.
+    pub fn from_rpc_response(json: &str) -> Result<BlockHeader, ChainError> {
+        let response: RpcBlockResponse =
+            serde_json::from_str(json).map_err(|e| ChainError::InvalidHeader(e.to_string()))?;
+        let header = response.result.block.header;
+        let height = header
+            .height
+            .parse::<u64>()
+            .map_err(|e| ChainError::InvalidHeader(e.to_string()))?;
+        Ok(BlockHeader {
+            height,
+            block_hash: response.result.block_id.hash,
+            parent_hash: header.last_block_id.hash,
+            app_hash: header.app_hash,
+            time: header.time,
+            proposer_address: header.proposer_address,
+        })
+    }
+
+    /// Checks that this header directly follows `prev`.
+    /// The parent hash is only compared if the block carries one.
+    pub fn validate_follows(&self, prev: &BlockHeader) -> Result<(), ChainError> {
+        let expected = prev.height + 1;
+        if self.height != expected {
+            return Err(ChainError::HeightGap {
+                expected,
+                found: self.height,
+            });
+        }
+        if !self.parent_hash.is_empty() && self.parent_hash != prev.block_hash {
+            return Err(ChainError::ParentMismatch {
+                height: self.height,
+                expected: prev.block_hash.clone(),
+                found: self.parent_hash.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Validates `header` against the last tracked header of the global state and tracks it.
+pub fn track_block_header(header: BlockHeader) -> Result<(), ChainError> {
+    track_block_header_in(&LAST_BLOCK_HEADER, header)
+}
+
+/// Validates `header` against the header in `last` and replaces it.
+/// The header is tracked even if the check fails, so a single gap is only reported once.
+pub fn track_block_header_in(
+    last: &Mutex<Option<BlockHeader>>,
+    header: BlockHeader,
+) -> Result<(), ChainError> {
+    let mut last = last.lock().unwrap();
+    let result = match last.as_ref() {
+        Some(prev) => header.validate_follows(prev),
+        None => Ok(()),
+    };
+    *last = Some(header);
+    result
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockoperations::blockprocessing::Block;
+
+    // Captured from the `/block?height=1204` RPC of a nyks node, commit and txs trimmed
+    const BLOCK_1204: &str = r#"{
+        "jsonrpc": "2.0",
+        "id": -1,
+        "result": {
+            "block_id": {
+                "hash": "6A3F2E0C0B1B6F3DB8F0C1A1D1E3B2E7A7C6D0C1E7A4F3B2C1D0E9F8A7B6C5D4",
+                "parts": {
+                    "total": 1,
+                    "hash": "0E5B8E1F8E3A7A3B7C59F0A1D8C5E1F6A0B2C3D4E5F60718293A4B5C6D7E8F90"
+                }
+            },
+            "block": {
+                "header": {
+                    "version": { "block": "11" },
+                    "chain_id": "nyks",
+                    "height": "1204",
+                    "time": "2024-03-04T11:20:13.412853281Z",
+                    "last_block_id": {
+                        "hash": "1C9E3B5A7D2F4E6A8B0C2D4E6F8A0B2C4D6E8F0A2B4C6D8E0F2A4B6C8D0E2F4A",
+                        "parts": {
+                            "total": 1,
+                            "hash": "7F6E5D4C3B2A19080706050403020100F0E0D0C0B0A090807060504030201000"
+                        }
+                    },
+                    "last_commit_hash": "A1B2C3D4E5F60718293A4B5C6D7E8F90A1B2C3D4E5F60718293A4B5C6D7E8F90",
+                    "data_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+                    "validators_hash": "5D4C3B2A19080706F5E4D3C2B1A0908F7E6D5C4B3A29180F0E0D0C0B0A090807",
+                    "next_validators_hash": "5D4C3B2A19080706F5E4D3C2B1A0908F7E6D5C4B3A29180F0E0D0C0B0A090807",
+                    "consensus_hash": "048091BC7DDC283F77BFBF91D73C44DA58C3DF8A9CBC867405D8B7F3DAADA22F",
+                    "app_hash": "9B8A7C6D5E4F30211203F4E5D6C7B8A99A8B7C6D5E4F30211203F4E5D6C7B8A9",
+                    "last_results_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+                    "evidence_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+                    "proposer_address": "3E8F1C5D7A9B2E4F6A8C0D2E4F6A8B0C2D4E6F8A"
+                },
+                "data": { "txs": [] },
+                "evidence": { "evidence": [] }
+            }
+        }
+    }"#;
+
+    // Captured from the `/block?height=1205` RPC of the same node
+    const BLOCK_1205: &str = r#"{
+        "jsonrpc": "2.0",
+        "id": -1,
+        "result": {
+            "block_id": {
+                "hash": "B7C6D5E4F3A2918070F6E5D4C3B2A1908F7E6D5C4B3A29180706F5E4D3C2B1A0",
+                "parts": {
+                    "total": 1,
+                    "hash": "2A4B6C8D0E2F4A6B8C0D2E4F6A8B0C2D4E6F8A0B2C4D6E8F0A2B4C6D8E0F2A4B"
+                }
+            },
+            "block": {
+                "header": {
+                    "version": { "block": "11" },
+                    "chain_id": "nyks",
+                    "height": "1205",
+                    "time": "2024-03-04T11:20:18.530114617Z",
+                    "last_block_id": {
+                        "hash": "6A3F2E0C0B1B6F3DB8F0C1A1D1E3B2E7A7C6D0C1E7A4F3B2C1D0E9F8A7B6C5D4",
+                        "parts": {
+                            "total": 1,
+                            "hash": "0E5B8E1F8E3A7A3B7C59F0A1D8C5E1F6A0B2C3D4E5F60718293A4B5C6D7E8F90"
+                        }
+                    },
+                    "last_commit_hash": "C3D4E5F60718293A4B5C6D7E8F90A1B2C3D4E5F60718293A4B5C6D7E8F90A1B2",
+                    "data_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+                    "validators_hash": "5D4C3B2A19080706F5E4D3C2B1A0908F7E6D5C4B3A29180F0E0D0C0B0A090807",
+                    "next_validators_hash": "5D4C3B2A19080706F5E4D3C2B1A0908F7E6D5C4B3A29180F0E0D0C0B0A090807",
+                    "consensus_hash": "048091BC7DDC283F77BFBF91D73C44DA58C3DF8A9CBC867405D8B7F3DAADA22F",
+                    "app_hash": "0F1E2D3C4B5A69788796A5B4C3D2E1F00F1E2D3C4B5A69788796A5B4C3D2E1F0",
+                    "last_results_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+                    "evidence_hash": "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855",
+                    "proposer_address": "8A0B2C4D6E8F0A2B4C6D8E0F2A4B6C8D0E2F4A6B"
+                },
+                "data": { "txs": [] },
+                "evidence": { "evidence": [] }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn header_from_rpc_response_test() {
+        let header = BlockHeader::from_rpc_response(BLOCK_1204).unwrap();
+        assert_eq!(header.height, 1204);
+        assert_eq!(
+            header.block_hash,
+            "6A3F2E0C0B1B6F3DB8F0C1A1D1E3B2E7A7C6D0C1E7A4F3B2C1D0E9F8A7B6C5D4"
+        );
+        assert_eq!(
+            header.parent_hash,
+            "1C9E3B5A7D2F4E6A8B0C2D4E6F8A0B2C4D6E8F0A2B4C6D8E0F2A4B6C8D0E2F4A"
+        );
+        assert_eq!(
+            header.app_hash,
+            "9B8A7C6D5E4F30211203F4E5D6C7B8A99A8B7C6D5E4F30211203F4E5D6C7B8A9"
+        );
+        assert_eq!(header.time, "2024-03-04T11:20:13.412853281Z");
+        assert_eq!(
+            header.proposer_address,
+            "3E8F1C5D7A9B2E4F6A8C0D2E4F6A8B0C2D4E6F8A"
+        );
+
+        assert!(matches!(
+            BlockHeader::from_rpc_response(&BLOCK_1204.replace("\"1204\"", "\"12o4\"")),
+            Err(ChainError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn header_validate_follows_test() {
+        let prev = BlockHeader::from_rpc_response(BLOCK_1204).unwrap();
+        let next = BlockHeader::from_rpc_response(BLOCK_1205).unwrap();
+        assert_eq!(next.validate_follows(&prev), Ok(()));
+
+        // a skipped block
+        let mut gap = next.clone();
+        gap.height = 1206;
+        assert_eq!(
+            gap.validate_follows(&prev),
+            Err(ChainError::HeightGap {
+                expected: 1205,
+                found: 1206,
+            })
+        );
+
+        // a block of another fork
+        let mut fork = next.clone();
+        fork.parent_hash = "00".repeat(32);
+        assert_eq!(
+            fork.validate_follows(&prev),
+            Err(ChainError::ParentMismatch {
+                height: 1205,
+                expected: prev.block_hash.clone(),
+                found: "00".repeat(32),
+            })
+        );
+
+        // blocks without a parent hash are only checked by height
+        let mut legacy = next.clone();
+        legacy.parent_hash = String::new();
+        assert_eq!(legacy.validate_follows(&prev), Ok(()));
+    }
+
+    #[test]
+    fn oracle_block_header_test() {
+        let json = r#"{
+            "Blockhash": "B7C6D5E4F3A2918070F6E5D4C3B2A1908F7E6D5C4B3A29180706F5E4D3C2B1A0",
+            "Blockheight": "1205",
+            "Transactions": [],
+            "ParentHash": "6A3F2E0C0B1B6F3DB8F0C1A1D1E3B2E7A7C6D0C1E7A4F3B2C1D0E9F8A7B6C5D4",
+            "AppHash": "0F1E2D3C4B5A69788796A5B4C3D2E1F00F1E2D3C4B5A69788796A5B4C3D2E1F0",
+            "Time": "2024-03-04T11:20:18.530114617Z",
+            "ProposerAddress": "8A0B2C4D6E8F0A2B4C6D8E0F2A4B6C8D0E2F4A6B"
+        }"#;
+        let block: Block = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            block.header(),
+            BlockHeader::from_rpc_response(BLOCK_1205).unwrap()
+        );
+        let prev = BlockHeader::from_rpc_response(BLOCK_1204).unwrap();
+        assert_eq!(block.validate_follows(&prev), Ok(()));
+
+        // blocks of older oracles have no header fields
+        let legacy: Block = serde_json::from_str(
+            r#"{"Blockhash": "abc", "Blockheight": "1205", "Transactions": []}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.header().parent_hash, "");
+        assert_eq!(legacy.validate_follows(&prev), Ok(()));
+    }
+}
@@ -3,10 +3,26 @@
 //! Block processing to update Utxo set.
 
 use crate::db::*;
+use crate::db::checkedfile::{read_checked_file, write_checked_file};
 /***************** POstgreSQL Insert Code *********/
-use crate::pgsql::{PGSQLDataInsert, PGSQLTransaction, THREADPOOL_SQL_QUEUE};
+use crate::pgsql::{PGSQLDataInsert, PGSQLTransaction};
 /**************** POstgreSQL Insert Code End **********/
-
+use crate::blockoperations::addresstx::address_txs;
+use crate::blockoperations::blockheader::{BlockHeader, ChainError};
+use crate::blockoperations::blockresult::BlockApplyResult;
+use crate::blockoperations::blockstats::{
+    record_block_stats, BlockPhase, BlockTimings, SLOW_TX_THRESHOLD,
+};
+use crate::blockoperations::blockweight::{block_weight, WeightResume, WeightViolation};
+use crate::blockoperations::subscriptions::{UtxoEvent, UtxoEventKind};
+use crate::blockoperations::balanceindexer::{index_utxo_events, rollback_indexed_balances};
+use crate::blockoperations::failedtx::FailedTx;
+use crate::blockoperations::fees::BlockFees;
+use crate::blockoperations::messages::{record_applied_message, BurnRecord};
+use crate::blockoperations::nullifiers::input_nullifiers;
+use crate::blockoperations::txroot::{BlockTxRoot, TxIdLeaf};
+
+use crate::state::{global_state, UtxoState};
 use crate::UTXO_STORAGE;
 use hex;
 
@@ -15,21 +31,20 @@ use quisquislib::elgamal::elgamal::ElGamalCommitment;
 use rand::Rng;
 use serde::de::{self, Deserializer, Visitor};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
-use std::fs;
 use serde_ini;
-use std::fs::File;
-use std::io::Write;
+use std::time::Instant;
 
 use transaction::reference_tx::{
     convert_output_to_input, create_dark_reference_tx_for_utxo_test, RecordUtxo,
 };
 
-use transaction::{ScriptTransaction, Transaction, TransactionData, TransactionType};
+use transaction::{Message, ScriptTransaction, Transaction, TransactionData, TransactionType};
 use zkvm::constraints::Commitment;
 use zkvm::tx::TxID;
 use zkvm::zkos_types::{
-    IOType, Input, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo,
+    IOType, Input, MessageType, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo,
 };
 use zkvm::Hash;
 
@@ -39,9 +54,10 @@ use quisquislib::{accounts::Account, ristretto::RistrettoSecretKey};
 use prometheus::{Encoder, TextEncoder, Counter, Gauge, register_counter, register_gauge};
 
 lazy_static! {
-    pub static ref  TOTAL_DARK_SATS_MINTED: Gauge = register_gauge!("dark_sats_minted", "A counter for dark Sats minted").unwrap();
-    pub static ref  TOTAL_TRANSFER_TX: Gauge = register_gauge!("transfer_tx_count", "A counter for transfer tx").unwrap();
-    pub static ref  TOTAL_SCRIPT_TX: Gauge = register_gauge!("script_tx_count", "A counter for script tx").unwrap();
+    /// Tx gauges of the global state, see [`crate::state`].
+    pub static ref  TOTAL_DARK_SATS_MINTED: Gauge = global_state().metrics.dark_sats_minted.clone();
+    pub static ref  TOTAL_TRANSFER_TX: Gauge = global_state().metrics.transfer_tx_count.clone();
+    pub static ref  TOTAL_SCRIPT_TX: Gauge = global_state().metrics.script_tx_count.clone();
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,17 +71,26 @@ struct TelemetryStats {
 pub struct BlockResult {
     pub suceess_tx: Vec<TxID>,
     pub failed_tx: Vec<TxID>,
+    // reason of each failed tx, in the order of failed_tx
+    #[serde(default)]
+    pub errors: Vec<String>,
 }
 impl BlockResult {
     pub fn new() -> Self {
         BlockResult {
             suceess_tx: Vec::new(),
             failed_tx: Vec::new(),
+            errors: Vec::new(),
         }
     }
+
+    pub fn add_failed(&mut self, tx_id: TxID, error: &str) {
+        self.failed_tx.push(tx_id);
+        self.errors.push(error.to_string());
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Block {
     #[serde(rename = "Blockhash")]
     pub block_hash: String,
@@ -73,6 +98,33 @@ pub struct Block {
     pub block_height: u64,
     #[serde(rename = "Transactions")]
     pub transactions: Vec<TransactionMessage>,
+    // header fields, missing in blocks of older oracles
+    #[serde(rename = "ParentHash", default)]
+    pub parent_hash: String,
+    #[serde(rename = "AppHash", default)]
+    pub app_hash: String,
+    #[serde(rename = "Time", default)]
+    pub time: String,
+    #[serde(rename = "ProposerAddress", default)]
+    pub proposer_address: String,
+}
+
+impl Block {
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            height: self.block_height,
+            block_hash: self.block_hash.clone(),
+            parent_hash: self.parent_hash.clone(),
+            app_hash: self.app_hash.clone(),
+            time: self.time.clone(),
+            proposer_address: self.proposer_address.clone(),
+        }
+    }
+
+    /// Checks that the block directly follows `prev`, see [`BlockHeader::validate_follows`].
+    pub fn validate_follows(&self, prev: &BlockHeader) -> Result<(), ChainError> {
+        self.header().validate_follows(prev)
+    }
 }
 
 // #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -135,22 +187,38 @@ pub struct TransactionMessage {
 //     Transfer(TransactionMessage),
 // }
 
-pub fn read_telemetry_stats_from_file() -> Result<(), Box<dyn std::error::Error>> {
-    let contents = fs::read_to_string("telemetry.ini")?;
+pub fn read_telemetry_stats_from_file(state: &UtxoState) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = match read_checked_file("telemetry.ini")? {
+        Some(contents) => contents,
+        None => return Ok(()),
+    };
     let config: TelemetryStats = serde_ini::from_str(&contents)?;
 
-    TOTAL_DARK_SATS_MINTED.set(config.total_dark_sats_minted as f64);
-    TOTAL_TRANSFER_TX.set(config.total_transfer_tx as f64);
-    TOTAL_SCRIPT_TX.set(config.total_script_tx as f64);
+    state
+        .metrics
+        .dark_sats_minted
+        .set(config.total_dark_sats_minted as f64);
+    state
+        .metrics
+        .transfer_tx_count
+        .set(config.total_transfer_tx as f64);
+    state
+        .metrics
+        .script_tx_count
+        .set(config.total_script_tx as f64);
 
     Ok(())
 }
 
-fn write_telemetry_stats_to_file() -> Result<(), Box<dyn std::error::Error>> {
-    let mut file = File::create("telemetry.ini")?;
-    write!(file, "total_dark_sats_minted={}\n", TOTAL_DARK_SATS_MINTED.get())?;
-    write!(file, "total_transfer_tx={}\n", TOTAL_TRANSFER_TX.get())?;
-    write!(file, "total_script_tx={}\n", TOTAL_SCRIPT_TX.get())?;
+fn write_telemetry_stats_to_file(state: &UtxoState) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = format!(
+        "total_dark_sats_minted={}\ntotal_transfer_tx={}\ntotal_script_tx={}",
+        state.metrics.dark_sats_minted.get(),
+        state.metrics.transfer_tx_count.get(),
+        state.metrics.script_tx_count.get()
+    );
+    // written atomically, a torn write would reset the counters on restart
+    write_checked_file("telemetry.ini", &contents)?;
 
     Ok(())
 }
@@ -176,19 +244,136 @@ where
     deserializer.deserialize_str(StringVisitor)
 }
 
-pub fn process_transfer(transaction: TransactionMessage, height: u64, tx_result: &mut BlockResult) {
-    let tx_bytes = hex::decode(transaction.tx_byte_code.unwrap()).expect("Decoding failed");
-    let transaction_info: Transaction = bincode::deserialize(&tx_bytes).unwrap();
+pub fn process_transfer(
+    state: &UtxoState,
+    transaction: TransactionMessage,
+    height: u64,
+    tx_result: &mut BlockResult,
+    block_fees: &mut BlockFees,
+    utxo_events: &mut Vec<UtxoEvent>,
+    timings: &mut BlockTimings,
+) {
+    let verify_start = Instant::now();
+    let tx_byte_code = transaction.tx_byte_code.clone().unwrap();
+    let tx_bytes = hex::decode(&tx_byte_code).expect("Decoding failed");
     let tx_id: [u8; 32] = hex::decode(transaction.tx_id.clone())
         .unwrap()
         .try_into()
         .unwrap();
-    let tx_input = transaction_info.get_tx_inputs();
+    // the tx was applied by an earlier run of this block
+    if state.applied_txs.lock().unwrap().contains(height, &tx_id) {
+        println!("TX ALREADY APPLIED : {}", transaction.tx_id);
+        tx_result.suceess_tx.push(TxID(Hash(tx_id)));
+        return;
+    }
+    // e.g. an output whose type does not match its data
+    let transaction_info: Transaction = match Transaction::from_bytes(&tx_bytes) {
+        Ok(transaction_info) => transaction_info,
+        Err(err) => {
+            timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
+            println!("TX DECODING FAILED : {} {}", transaction.tx_id, err);
+            record_failed_tx(
+                state,
+                &transaction.tx_id,
+                height,
+                &tx_byte_code,
+                &err.to_string(),
+            );
+            tx_result.add_failed(TxID(Hash(tx_id)), &err.to_string());
+            return;
+        }
+    };
+    // inputs referring to outputs of the tx spend the utxos created under its txid
+    let tx_input = transaction_info.resolve_pending_inputs(TxID(Hash(tx_id)));
     let tx_output = transaction_info.get_tx_outputs();
 
     let transaction_type = transaction_info.tx_type;
+    let tx_fee = transaction_info.get_tx_fee();
+
+    // inputs spending the same utxo twice
+    if let Err(err) = transaction_info.validate_structure() {
+        timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
+        println!("TX STRUCTURE INVALID : {} {}", transaction.tx_id, err);
+        record_failed_tx(
+            state,
+            &transaction.tx_id,
+            height,
+            &tx_byte_code,
+            &err.to_string(),
+        );
+        tx_result.add_failed(TxID(Hash(tx_id)), &err.to_string());
+        return;
+    }
+
+    // coins spent by a recent block, found before any proof is verified
+    let nullifiers = input_nullifiers(&transaction_info.get_tx_inputs());
+    let conflict = state
+        .nullifiers
+        .lock()
+        .unwrap()
+        .conflict(&transaction.tx_id, &nullifiers);
+    if let Some(conflict) = conflict {
+        timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
+        let err = format!("Error::Tx {}", conflict);
+        println!("TX DOUBLE SPEND : {} {}", transaction.tx_id, err);
+        record_failed_tx(state, &transaction.tx_id, height, &tx_byte_code, &err);
+        tx_result.add_failed(TxID(Hash(tx_id)), &err);
+        return;
+    }
+
+    let message = match &transaction_info.tx {
+        TransactionData::Message(message) => Some(message.clone()),
+        _ => None,
+    };
+    // app messages have no effect on the utxo set, they are only tracked by txid
+    if let Some(message) = &message {
+        if message.msg_type == MessageType::App {
+            timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
+            record_applied_message(state, &transaction.tx_id, MessageType::App, height);
+            record_address_txs(
+                state,
+                &transaction.tx_id,
+                height,
+                &tx_input,
+                &tx_output,
+                timings,
+            );
+            state.applied_txs.lock().unwrap().insert(height, tx_id);
+            tx_result.suceess_tx.push(TxID(Hash(tx_id)));
+            return;
+        }
+        // reveal proof and signature of the burn
+        if let Err(err) = transaction_info.verify() {
+            timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
+            println!("BURN MESSAGE INVALID : {} {}", transaction.tx_id, err);
+            let err = err.to_string();
+            record_failed_tx(state, &transaction.tx_id, height, &tx_byte_code, &err);
+            tx_result.add_failed(TxID(Hash(tx_id)), &err);
+            return;
+        }
+    }
+
+    // fresh receiver accounts are not in the utxo set, verify_utxo only skips their
+    // inputs once their zero balance proofs verified
+    let fresh_inputs = tx_input
+        .iter()
+        .any(|input| input.get_utxo() == Utxo::default());
+    if transaction_type == TransactionType::Transfer && fresh_inputs {
+        if let Err(err) = transaction_info.verify() {
+            timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
+            println!(
+                "TX ZERO BALANCE PROOF INVALID : {} {}",
+                transaction.tx_id, err
+            );
+            let err = err.to_string();
+            record_failed_tx(state, &transaction.tx_id, height, &tx_byte_code, &err);
+            tx_result.add_failed(TxID(Hash(tx_id)), &err);
+            return;
+        }
+    }
 
-    let utxo_verified = verify_utxo(transaction_info);
+    let utxo_verified = verify_utxo(state, transaction_info);
+    timings.add_tx_verify(&transaction.tx_id, verify_start.elapsed());
 
     // if transaction_info.tx_type == TransactionType::Script{
     //     for input in &tx_input {
@@ -217,7 +402,7 @@ pub fn process_transfer(transaction: TransactionMessage, height: u64, tx_result:
     // for (output_index, output_set) in tx_output.iter().enumerate() {
     //     println!("inside outputs");
     //     let utxo_key =
-    //         bincode::serialize(&Utxo::from_hash(Hash(tx_id), output_index as u8)).unwrap();
+    //         bincode::serialize(&Utxo::from_hash(Hash(tx_id), output_index as u16)).unwrap();
     //     let utxo_output_type = output_set.out_type as usize;
     //     let bool = utxo_storage.search_key(&utxo_key, utxo_output_type);
     //     if bool {
@@ -226,9 +411,42 @@ pub fn process_transfer(transaction: TransactionMessage, height: u64, tx_result:
     //     }
     // }
     //proccess tx
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let mut utxo_storage = state.utxo_storage.lock().unwrap();
+
+    // malformed points or addresses must never reach the utxo store
+    if let Some(err) = tx_output.iter().find_map(|output| output.validate().err()) {
+        println!("TX OUTPUT INVALID : {} {}", transaction.tx_id, err);
+        record_failed_tx(
+            state,
+            &transaction.tx_id,
+            height,
+            &tx_byte_code,
+            &err.to_string(),
+        );
+        tx_result.add_failed(TxID(Hash(tx_id)), &err.to_string());
+        return;
+    }
+
+    // a key held by an output of another type would make the utxo ambiguous
+    if let Some(err) = output_key_collision(&utxo_storage, tx_id, &tx_output) {
+        println!("TX OUTPUT COLLISION : {} {}", transaction.tx_id, err);
+        record_failed_tx(state, &transaction.tx_id, height, &tx_byte_code, &err);
+        tx_result.add_failed(TxID(Hash(tx_id)), &err);
+        return;
+    }
 
     if utxo_verified {
+        // every owner of the inputs and outputs, decoys of the anonymity set included
+        record_address_txs(
+            state,
+            &transaction.tx_id,
+            height,
+            &tx_input,
+            &tx_output,
+            timings,
+        );
+
+        let apply_start = Instant::now();
         /***************** POstgreSQL Insert Code *********/
         /************************************************ */
         let mut pg_insert_data = PGSQLTransaction::default();
@@ -237,15 +455,25 @@ pub fn process_transfer(transaction: TransactionMessage, height: u64, tx_result:
 
         /**************** POstgreSQL Insert Code End **********/
         /**************************************************** */
+        let mut spent_utxos: Vec<Utxo> = Vec::new();
         for input in tx_input {
-            let utxo_key = bincode::serialize(&input.as_utxo().unwrap()).unwrap();
+            let utxo_key = utxo_key(input.as_utxo().unwrap());
             let utxo_input_type = input.in_type as usize;
             let utxo_test = Utxo::new(TxID(Hash([0; 32])), 0);
             let utxo = input.as_utxo().unwrap();
-            if utxo.to_owned() != utxo_test {
+            // outputs of the tx itself are added below, not spent from the utxo set
+            if utxo.to_owned() != utxo_test && *utxo.tx_id() != TxID(Hash(tx_id)) {
                 let _result = utxo_storage.remove(utxo_key.clone(), utxo_input_type);
                 match _result {
-                    Ok(_) => {
+                    Ok(removed) => {
+                        utxo_events.extend(UtxoEvent::new(
+                            *utxo,
+                            removed,
+                            &transaction.tx_id,
+                            height,
+                            UtxoEventKind::Removed,
+                        ));
+                        spent_utxos.push(*utxo);
                         /***************** POstgreSQL Insert Code *********/
                         /************************************************ */
                         pg_insert_data.remove_utxo.push(utxo_key.clone());
@@ -261,12 +489,18 @@ pub fn process_transfer(transaction: TransactionMessage, height: u64, tx_result:
         }
         //Add all output
         for (output_index, output_set) in tx_output.iter().enumerate() {
-            let utxo_key =
-                bincode::serialize(&Utxo::from_hash(Hash(tx_id), output_index as u8)).unwrap();
+            let utxo_key = utxo_key(&Utxo::from_hash(Hash(tx_id), output_index as u16));
             let utxo_output_type = output_set.out_type as usize;
             let _result = utxo_storage.add(utxo_key.clone(), output_set.clone(), utxo_output_type);
             match _result {
                 Ok(_) => {
+                    utxo_events.extend(UtxoEvent::new(
+                        Utxo::from_hash(Hash(tx_id), output_index as u16),
+                        output_set.clone(),
+                        &transaction.tx_id,
+                        height,
+                        UtxoEventKind::Added,
+                    ));
                     /***************** POstgreSQL Insert Code *********/
                     /************************************************ */
                     match utxo_output_type {
@@ -317,43 +551,169 @@ pub fn process_transfer(transaction: TransactionMessage, height: u64, tx_result:
         }
 
         // let _ = utxo_storage.data_meta_update(height as usize);
+        // pending txs spending these utxos can no longer be applied
+        state.mempool.lock().unwrap().remove_spent(&spent_utxos);
+        state
+            .nullifiers
+            .lock()
+            .unwrap()
+            .insert(height, &transaction.tx_id, &nullifiers);
+        timings.add(BlockPhase::Apply, apply_start.elapsed());
 
         /***************** POstgreSQL Insert Code *********/
         /************************************************ */
-        let treadpool_sql_queue = THREADPOOL_SQL_QUEUE.lock().unwrap();
+        let persist_start = Instant::now();
+        let store = state.store();
+        let treadpool_sql_queue = state.sql_queue.lock().unwrap();
         treadpool_sql_queue.execute(move || {
-            let _ = pg_insert_data.update_utxo_log();
+            let _ = store.update_utxo_log(&pg_insert_data);
         });
         drop(treadpool_sql_queue);
+        timings.add(BlockPhase::Persist, persist_start.elapsed());
         /**************** POstgreSQL Insert Code End **********/
         /**************************************************** */
         
         if transaction_type == TransactionType::Script{
-            TOTAL_SCRIPT_TX.inc();
-            write_telemetry_stats_to_file();
+            state.metrics.script_tx_count.inc();
+            write_telemetry_stats_to_file(state);
         }
         else if transaction_type == TransactionType::Transfer{
-            TOTAL_TRANSFER_TX.inc();
-            write_telemetry_stats_to_file();
+            state.metrics.transfer_tx_count.inc();
+            write_telemetry_stats_to_file(state);
+        }
+        else if let Some(message) = message {
+            record_burn(state, &transaction.tx_id, &message, height);
+        }
+
+        match block_fees.add_fee(tx_fee) {
+            Ok(_) => {}
+            Err(err) => println!("FEE NOT ACCOUNTED : {}", err),
         }
 
+        state.applied_txs.lock().unwrap().insert(height, tx_id);
         tx_result.suceess_tx.push(TxID(Hash(tx_id)));
     } else {
-        tx_result.failed_tx.push(TxID(Hash(tx_id)));
+        let err = "Error::Tx inputs are not in the utxo set";
+        record_failed_tx(state, &transaction.tx_id, height, &tx_byte_code, err);
+        tx_result.add_failed(TxID(Hash(tx_id)), err);
+    }
+}
+
+// indexes an applied tx by the owner addresses of its inputs and outputs
+fn record_address_txs(
+    state: &UtxoState,
+    tx_id: &str,
+    height: u64,
+    inputs: &[Input],
+    outputs: &[Output],
+    timings: &mut BlockTimings,
+) {
+    let index_start = Instant::now();
+    let entries = address_txs(tx_id, height, inputs, outputs);
+    let mut address_index = state.address_txs.lock().unwrap();
+    for entry in entries.iter() {
+        address_index.insert(entry.clone());
+    }
+    drop(address_index);
+    timings.add(BlockPhase::Index, index_start.elapsed());
+
+    /***************** POstgreSQL Insert Code *********/
+    let persist_start = Instant::now();
+    let store = state.store();
+    let treadpool_sql_queue = state.sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.insert_address_txs(&entries) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_address_txs: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    timings.add(BlockPhase::Persist, persist_start.elapsed());
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+// records a tx of the block that failed to apply, for inspection and reprocessing
+fn record_failed_tx(state: &UtxoState, tx_id: &str, height: u64, tx_byte_code: &str, error: &str) {
+    let mut failed_store = state.failed_txs.lock().unwrap();
+    let failed_tx = failed_store.insert(FailedTx::new(tx_id, height, tx_byte_code, error));
+    drop(failed_store);
+
+    /***************** POstgreSQL Insert Code *********/
+    let store = state.store();
+    let treadpool_sql_queue = state.sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.insert_failed_tx(&failed_tx) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_failed_tx: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+/// Re-runs a failed tx of the global state, see [`UtxoState::reprocess_failed_tx`].
+pub fn reprocess_failed_tx(tx_id: &str) -> Result<FailedTx, &'static str> {
+    global_state().reprocess_failed_tx(tx_id)
+}
+
+// records the coin burned by a burn message applied in the block
+fn record_burn(state: &UtxoState, tx_id: &str, message: &Message, height: u64) {
+    let burn = BurnRecord {
+        txid: tx_id.to_string(),
+        address: message.msg_data.clone(),
+        amount: message.proof.get_amount(),
+        block_height: height,
+    };
+    record_applied_message(state, tx_id, MessageType::Burn, height);
+    let mut burn_history = state.burn_history.lock().unwrap();
+    if !burn_history.insert(burn.clone()) {
+        return;
     }
+    drop(burn_history);
+    state.metrics.burned.inc_by(burn.amount as f64);
+
+    /***************** POstgreSQL Insert Code *********/
+    let store = state.store();
+    let treadpool_sql_queue = state.sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.insert_burn(&burn) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_burn: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
 }
 
 pub fn process_trade_mint(
+    state: &UtxoState,
     transaction: TransactionMessage,
     height: u64,
     tx_result: &mut BlockResult,
+    utxo_events: &mut Vec<UtxoEvent>,
+    timings: &mut BlockTimings,
 ) {
     println!("In Process trade mint  tx :=:  {:?}", transaction);
 
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-    let tx_id = hex::decode(transaction.tx_id.clone()).expect("error decoding tx id");
-    let tx_id = TxID(Hash(tx_id.try_into().unwrap()));
-    let utxo_key = bincode::serialize(&Utxo::new(tx_id, 0 as u8)).unwrap();
+    let apply_start = Instant::now();
+    let tx_id_bytes: [u8; 32] = hex::decode(transaction.tx_id.clone())
+        .expect("error decoding tx id")
+        .try_into()
+        .unwrap();
+    let tx_id = TxID(Hash(tx_id_bytes));
+    // the mint was applied by an earlier run of this block
+    if state
+        .applied_txs
+        .lock()
+        .unwrap()
+        .contains(height, &tx_id_bytes)
+    {
+        println!("TX ALREADY APPLIED : {}", transaction.tx_id);
+        tx_result.suceess_tx.push(tx_id);
+        return;
+    }
+    let mut utxo_storage = state.utxo_storage.lock().unwrap();
+    let utxo_key = utxo_key(&Utxo::new(tx_id, 0));
     let mut qq_account_bytes =
         hex::decode(transaction.qq_account.unwrap()).expect("Decoding failed");
     let elgamal = qq_account_bytes.split_off(qq_account_bytes.len() - 64);
@@ -367,13 +727,39 @@ pub fn process_trade_mint(
             encrypt: elgamal,
             owner: address.as_hex(),
         }));
-        utxo_storage.add(utxo_key.clone(), output.clone(), output.out_type as usize);
+        if let Err(err) =
+            utxo_storage.add(utxo_key.clone(), output.clone(), output.out_type as usize)
+        {
+            let err = format!(
+                "mint output collides with utxo {}, {}",
+                Utxo::new(tx_id, 0),
+                err
+            );
+            println!("MINT FAILED : {} {}", transaction.tx_id, err);
+            record_failed_tx(state, &transaction.tx_id, height, "", &err);
+            tx_result.add_failed(tx_id, &err);
+            return;
+        }
+        utxo_events.extend(UtxoEvent::new(
+            Utxo::new(tx_id, 0),
+            output.clone(),
+            &transaction.tx_id,
+            height,
+            UtxoEventKind::Added,
+        ));
 
         let pk = address.as_hex();
+        state
+            .applied_txs
+            .lock()
+            .unwrap()
+            .insert(height, tx_id_bytes);
         tx_result.suceess_tx.push(tx_id);
+        timings.add(BlockPhase::Apply, apply_start.elapsed());
 
         /***************** POstgreSQL Insert Code *********/
         //*********************************************** */
+        let persist_start = Instant::now();
         let mut pg_insert_data = PGSQLTransaction::default();
         pg_insert_data.txid = transaction.tx_id.clone();
         pg_insert_data.block_height = height;
@@ -385,11 +771,13 @@ pub fn process_trade_mint(
             &"".to_string(),
             0,
         ));
-        let treadpool_sql_queue = THREADPOOL_SQL_QUEUE.lock().unwrap();
+        let store = state.store();
+        let treadpool_sql_queue = state.sql_queue.lock().unwrap();
         treadpool_sql_queue.execute(move || {
-            let _ = pg_insert_data.update_utxo_log();
+            let _ = store.update_utxo_log(&pg_insert_data);
         });
         drop(treadpool_sql_queue);
+        timings.add(BlockPhase::Persist, persist_start.elapsed());
         /**************** POstgreSQL Insert Code End **********/
         /**************************************************** */
 
@@ -402,8 +790,8 @@ pub fn process_trade_mint(
             },
         };
 
-        TOTAL_DARK_SATS_MINTED.add(float_value);
-        write_telemetry_stats_to_file();
+        state.metrics.dark_sats_minted.add(float_value);
+        write_telemetry_stats_to_file(state);
         println!("UTXO ADDED MINT")
     }
     else if transaction.mint_or_burn.unwrap() == false {
@@ -414,15 +802,15 @@ pub fn process_trade_mint(
                 0.0  // Use a default value (like 0.0) in case of an error
             },
         };
-        TOTAL_DARK_SATS_MINTED.sub(float_value);
-        write_telemetry_stats_to_file();
+        state.metrics.dark_sats_minted.sub(float_value);
+        write_telemetry_stats_to_file(state);
         
     }
     // UTXO IS ALREADY REMOVED THROUGH THE ZKOS Burn Message TX that appears as Transfer Tx now
     // Therefore no need to do anything for Tendermint Burn Tx.
     // The tx is only needed for the chain to update the twilight balance
     /*else {
-        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let mut utxo_storage = state.utxo_storage.lock().unwrap();
         let input_type = IOType::Coin as usize;
         let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
 
@@ -439,7 +827,7 @@ pub fn process_trade_mint(
             pg_insert_data.block_height = height;
             pg_insert_data.io_type = IOType::Coin as usize;
             pg_insert_data.remove_utxo.push(utxo_key.clone());
-            let treadpool_sql_queue = THREADPOOL_SQL_QUEUE.lock().unwrap();
+            let treadpool_sql_queue = state.sql_queue.lock().unwrap();
             treadpool_sql_queue.execute(move || {
                 let _ = pg_insert_data.update_utxo_log();
             });
@@ -451,72 +839,582 @@ pub fn process_trade_mint(
     }*/
 }
 
-pub fn process_block_for_utxo_insert(block: Block) -> BlockResult {
-    let mut tx_result: BlockResult = BlockResult::new();
-    for transaction in block.transactions {
-        match transaction.tx_type.as_str() {
-            "/twilightproject.nyks.zkos.MsgTransferTx" => {
-                process_transfer(transaction, block.block_height, &mut tx_result)
-            }
-            "/twilightproject.nyks.zkos.MsgMintBurnTradingBtc" => {
-                process_trade_mint(transaction, block.block_height, &mut tx_result)
-            }
-            _ => {} // you might want to handle any other cases or just ignore them
+// merkle root over the txids of the block, in block order
+pub fn update_block_tx_root(state: &UtxoState, block: &Block) -> Result<BlockTxRoot, &'static str> {
+    let block_tx_root = BlockTxRoot::new(block.block_height, block_txid_leaves(block)?);
+
+    let mut block_tx_roots = state.block_tx_roots.lock().unwrap();
+    block_tx_roots.insert(block_tx_root.clone());
+    drop(block_tx_roots);
+
+    /***************** POstgreSQL Insert Code *********/
+    let pg_block_tx_root = block_tx_root.clone();
+    let store = state.store();
+    let treadpool_sql_queue = state.sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.insert_block_tx_root(&pg_block_tx_root) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_block_tx_root: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+
+    Ok(block_tx_root)
+}
+
+// leaves of the txid tree of the block, in wire order
+// a txid that is not 32 hex encoded bytes fails the tree, a root without it would not
+// commit to the block
+fn block_txid_leaves(block: &Block) -> Result<Vec<TxIdLeaf>, &'static str> {
+    block
+        .transactions
+        .iter()
+        .map(|tx| {
+            hex::decode(&tx.tx_id)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(TxIdLeaf)
+                .ok_or("Error::Invalid txid in block")
+        })
+        .collect()
+}
+
+// records the fees collected in a block
+pub fn update_block_fees(state: &UtxoState, block_fees: BlockFees) {
+    state
+        .metrics
+        .fees_collected
+        .inc_by(block_fees.total_fees as f64);
+
+    let mut fee_store = state.block_fees.lock().unwrap();
+    fee_store.insert(block_fees);
+    drop(fee_store);
+
+    /***************** POstgreSQL Insert Code *********/
+    let store = state.store();
+    let treadpool_sql_queue = state.sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.insert_block_fees(&block_fees) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_block_fees: {}", e),
         };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+// an output whose key is held by another partition, e.g. a coin under the key of a memo output
+fn output_key_collision(
+    utxo_storage: &LocalStorage<Output>,
+    tx_id: [u8; 32],
+    tx_output: &[Output],
+) -> Option<String> {
+    tx_output
+        .iter()
+        .enumerate()
+        .find_map(|(output_index, output)| {
+            let utxo = Utxo::from_hash(Hash(tx_id), output_index as u16);
+            let key = utxo_key(&utxo);
+            utxo_storage
+                .data
+                .keys()
+                .find(|partition| {
+                    **partition != output.out_type as usize
+                        && utxo_storage.contains_key(&key, **partition)
+                })
+                .map(|partition| {
+                    format!(
+                        "output {} collides with utxo {} of partition {}",
+                        output_index, utxo, partition
+                    )
+                })
+        })
+}
+
+// utxo keys consumed and created by a transfer tx of the block
+// returns None for messages that do not carry a zkos tx
+fn transaction_utxo_keys(transaction: &TransactionMessage) -> Option<(Vec<KeyId>, Vec<KeyId>)> {
+    if transaction.tx_type != "/twilightproject.nyks.zkos.MsgTransferTx" {
+        return None;
     }
-    tx_result
+    let tx_bytes = hex::decode(transaction.tx_byte_code.as_ref()?).ok()?;
+    let transaction_info: Transaction = Transaction::from_bytes(&tx_bytes).ok()?;
+    let tx_id: [u8; 32] = hex::decode(&transaction.tx_id).ok()?.try_into().ok()?;
+    let utxo_test = Utxo::new(TxID(Hash([0; 32])), 0);
+
+    let consumed: Vec<KeyId> = transaction_info
+        .get_tx_inputs()
+        .iter()
+        .filter_map(|input| input.as_utxo().cloned())
+        .filter(|utxo| *utxo != utxo_test && !utxo.is_pending())
+        .map(|utxo| utxo_key(&utxo))
+        .collect();
+    let created: Vec<KeyId> = (0..transaction_info.get_tx_outputs().len())
+        .map(|output_index| utxo_key(&Utxo::from_hash(Hash(tx_id), output_index as u16)))
+        .collect();
+    Some((consumed, created))
 }
 
-pub fn all_coin_type_utxo() -> Vec<String> {
-    let mut result: Vec<String> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-    let input_type = IOType::Coin as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
-    for (key, output_data) in utxos {
-        match bincode::deserialize(&key) {
-            Ok(value) => {
-                let utxo: Utxo = value;
-                let hex_str: String = utxo.to_hex();
-                result.push(hex_str)
+/// Orders the transactions of a block so that a tx spending an output created in the
+/// same block is applied after the tx creating it.
+/// Independent transactions keep their wire order.
+/// Transactions in a dependency cycle are appended in wire order and fail utxo verification.
+pub fn order_block_transactions(transactions: Vec<TransactionMessage>) -> Vec<TransactionMessage> {
+    let utxo_keys: Vec<Option<(Vec<KeyId>, Vec<KeyId>)>> =
+        transactions.iter().map(transaction_utxo_keys).collect();
+
+    // creating tx of each output created in the block
+    let mut created_by: HashMap<KeyId, usize> = HashMap::new();
+    for (index, keys) in utxo_keys.iter().enumerate() {
+        if let Some((_, created)) = keys {
+            for key in created {
+                created_by.insert(key.clone(), index);
             }
-            Err(args) => {
-                let err = format!("Deserialization error, {:?}", args);
-                println!("{}", err)
+        }
+    }
+
+    // in-block dependency graph
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); transactions.len()];
+    let mut parent_count: Vec<usize> = vec![0; transactions.len()];
+    for (index, keys) in utxo_keys.iter().enumerate() {
+        if let Some((consumed, _)) = keys {
+            let mut parents: BTreeSet<usize> = BTreeSet::new();
+            for key in consumed {
+                if let Some(parent) = created_by.get(key) {
+                    if *parent != index {
+                        parents.insert(*parent);
+                    }
+                }
+            }
+            for parent in parents {
+                children[parent].push(index);
+                parent_count[index] += 1;
             }
         }
     }
-    return result;
+
+    // topological order, ties broken by wire order
+    let mut ready: BTreeSet<usize> = (0..transactions.len())
+        .filter(|index| parent_count[*index] == 0)
+        .collect();
+    let mut order: Vec<usize> = Vec::with_capacity(transactions.len());
+    while let Some(index) = ready.iter().next().cloned() {
+        ready.remove(&index);
+        order.push(index);
+        for child in children[index].iter() {
+            parent_count[*child] -= 1;
+            if parent_count[*child] == 0 {
+                ready.insert(*child);
+            }
+        }
+    }
+    if order.len() < transactions.len() {
+        let ordered: HashSet<usize> = order.iter().cloned().collect();
+        for index in 0..transactions.len() {
+            if !ordered.contains(&index) {
+                order.push(index);
+            }
+        }
+    }
+
+    let mut transactions: Vec<Option<TransactionMessage>> =
+        transactions.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|index| transactions[index].take())
+        .collect()
 }
-pub fn all_memo_type_utxo() -> Vec<String> {
-    let mut result: Vec<String> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-    let input_type = IOType::Memo as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
-    for (key, output_data) in utxos {
-        match bincode::deserialize(&key) {
-            Ok(value) => {
-                let utxo: Utxo = value;
-                let hex_str: String = utxo.to_hex();
-                result.push(hex_str)
+
+// checks if the block has already been processed with the same transactions
+fn is_block_processed(state: &UtxoState, block: &Block) -> bool {
+    // no root is recorded for a block with an invalid txid
+    let txids = match block_txid_leaves(block) {
+        Ok(txids) => txids,
+        Err(_) => return false,
+    };
+    let block_tx_roots = state.block_tx_roots.lock().unwrap();
+    match block_tx_roots.get_root(block.block_height) {
+        Some(processed) => processed.txids == txids,
+        None => false,
+    }
+}
+
+// whether a block at the height of `block` or above was applied, `block` then replaces it
+fn is_height_reapplied(state: &UtxoState, block: &Block) -> bool {
+    let block_tx_roots = state.block_tx_roots.lock().unwrap();
+    let mut applied = block_tx_roots.roots.range(block.block_height..);
+    applied.next().is_some()
+}
+
+impl UtxoState {
+    /// Applies the transactions of `block` to the utxo set, a block already applied is skipped.
+    /// A block over the weight limit is not applied and halts the block processing, the
+    /// blocks received while halted are held, see [`crate::blockoperations::blockweight`].
+    pub fn process_block(&self, block: Block) -> BlockResult {
+        let mut halted_blocks = self.halted_blocks.lock().unwrap();
+        self.apply_block(block, &mut halted_blocks)
+    }
+
+    /// Resumes the block processing halted by a block over the weight limit. The rejected
+    /// block and the blocks held since are applied again, in order, before any block
+    /// received later. The processing halts again on a block still over the limit, raise
+    /// it first with [`UtxoState::set_max_block_weight`].
+    pub fn resume_block_processing(&self) -> Option<WeightResume> {
+        let mut halted_blocks = self.halted_blocks.lock().unwrap();
+        let resumed = self.weight_halt.lock().unwrap().take()?;
+        let mut applied_heights = Vec::new();
+        for block in std::mem::take(&mut *halted_blocks) {
+            let height = block.block_height;
+            self.apply_block(block, &mut halted_blocks);
+            if self.weight_halt().is_none() {
+                applied_heights.push(height);
             }
-            Err(args) => {
-                let err = format!("Deserialization error, {:?}", args);
-                println!("{}", err)
+        }
+        Some(WeightResume {
+            resumed,
+            applied_heights,
+            halt: self.weight_halt(),
+        })
+    }
+
+    // applies `block` unless halted, a block not applied for its weight is held
+    fn apply_block(&self, block: Block, halted_blocks: &mut Vec<Block>) -> BlockResult {
+        let mut tx_result: BlockResult = BlockResult::new();
+        // re-processing a block is a no-op
+        if is_block_processed(self, &block) {
+            println!("BLOCK ALREADY PROCESSED : {}", block.block_height);
+            return tx_result;
+        }
+        if let Some(violation) = self.weight_halt() {
+            eprintln!(
+                "BLOCK {} HELD, block processing is halted: {}",
+                block.block_height, violation
+            );
+            halted_blocks.push(block);
+            return tx_result;
+        }
+        let weight = block_weight(&block);
+        let weight_limit = self.max_block_weight();
+        self.metrics.block_weight.set(weight as f64);
+        if weight > weight_limit {
+            let violation = WeightViolation {
+                height: block.block_height,
+                block_hash: block.block_hash.clone(),
+                weight,
+                limit: weight_limit,
+            };
+            eprintln!("BLOCK REJECTED, halting block processing: {}", violation);
+            self.metrics.block_weight_violations.inc();
+            *self.weight_halt.lock().unwrap() = Some(violation);
+            update_block_apply_result(
+                self,
+                BlockApplyResult {
+                    height: block.block_height,
+                    applied_txids: Vec::new(),
+                    failed: Vec::new(),
+                    utxos_added: 0,
+                    utxos_removed: 0,
+                    duration_ms: 0,
+                    weight,
+                    weight_limit,
+                },
+            );
+            halted_blocks.push(block);
+            return tx_result;
+        }
+        // a different block at an applied height replaces the blocks from that height, the
+        // nullifiers they spent and the balances they indexed are undone first
+        if is_height_reapplied(self, &block) {
+            let height = block.block_height.saturating_sub(1);
+            let dropped = self.rollback_nullifiers(height);
+            rollback_indexed_balances(self, height);
+            println!(
+                "BLOCK {} REAPPLIED : {} nullifiers dropped",
+                block.block_height, dropped
+            );
+        }
+        let block_start = Instant::now();
+        let mut timings = BlockTimings::new(*SLOW_TX_THRESHOLD);
+        let mut block_fees = BlockFees::new(block.block_height);
+        let mut utxo_events = Vec::new();
+        // the read views see the utxos of the block once all its transactions are applied
+        self.utxo_storage.lock().unwrap().begin_block();
+        for transaction in order_block_transactions(block.transactions.clone()) {
+            match transaction.tx_type.as_str() {
+                "/twilightproject.nyks.zkos.MsgTransferTx" => process_transfer(
+                    self,
+                    transaction,
+                    block.block_height,
+                    &mut tx_result,
+                    &mut block_fees,
+                    &mut utxo_events,
+                    &mut timings,
+                ),
+                "/twilightproject.nyks.zkos.MsgMintBurnTradingBtc" => process_trade_mint(
+                    self,
+                    transaction,
+                    block.block_height,
+                    &mut tx_result,
+                    &mut utxo_events,
+                    &mut timings,
+                ),
+                _ => {} // you might want to handle any other cases or just ignore them
+            };
+        }
+        self.utxo_storage.lock().unwrap().commit_block();
+        self.metrics
+            .failed_tx_count
+            .inc_by(tx_result.failed_tx.len() as f64);
+        self.metrics
+            .block_failed_tx
+            .set(tx_result.failed_tx.len() as f64);
+        let utxos_added = utxo_events
+            .iter()
+            .filter(|event| event.event == UtxoEventKind::Added)
+            .count() as u64;
+        let utxos_removed = utxo_events.len() as u64 - utxos_added;
+        for event in utxo_events.iter_mut() {
+            event.time = block.time.clone();
+        }
+        // notify the subscribers of the addresses, once the block is applied
+        index_utxo_events(self, block.block_height, &utxo_events);
+        self.subscriptions.lock().unwrap().publish(utxo_events);
+        // the block is marked as processed only once all its transactions are applied
+        let persist_start = Instant::now();
+        if let Err(err) = update_block_tx_root(self, &block) {
+            eprintln!(
+                "BLOCK {} TX ROOT NOT RECORDED : {}",
+                block.block_height, err
+            );
+        }
+        update_block_fees(self, block_fees);
+        self.persist_block_nullifiers(block.block_height);
+        timings.add(BlockPhase::Persist, persist_start.elapsed());
+        record_block_stats(
+            self,
+            block.block_height,
+            block.transactions.len(),
+            tx_result.failed_tx.len(),
+            &timings,
+            block_start.elapsed(),
+        );
+        let result = BlockApplyResult {
+            height: block.block_height,
+            applied_txids: tx_result.suceess_tx.clone(),
+            failed: tx_result
+                .failed_tx
+                .iter()
+                .cloned()
+                .zip(tx_result.errors.iter().cloned())
+                .collect(),
+            utxos_added,
+            utxos_removed,
+            duration_ms: block_start.elapsed().as_millis() as u64,
+            weight,
+            weight_limit,
+        };
+        let block_txids: Vec<String> = block
+            .transactions
+            .iter()
+            .map(|transaction| transaction.tx_id.to_lowercase())
+            .collect();
+        self.record_block_tx_statuses(&result, &block_txids);
+        update_block_apply_result(self, result);
+        tx_result
+    }
+
+    /// Re-runs the verification and application of a failed tx against the current utxo set.
+    /// Returns the record of the failed tx, resolved if the tx is applied.
+    /// The fee of a reprocessed tx is not added to the fees of its block.
+    pub fn reprocess_failed_tx(&self, tx_id: &str) -> Result<FailedTx, &'static str> {
+        let failed_tx = match self.failed_txs.lock().unwrap().get(tx_id) {
+            Some(failed_tx) => failed_tx.clone(),
+            None => return Err("Error::Failed tx not found"),
+        };
+        if failed_tx.is_resolved() {
+            return Err("Error::Failed tx is already applied");
+        }
+        let transaction = TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: failed_tx.txid.clone(),
+            tx_byte_code: Some(failed_tx.tx_byte_code.clone()),
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account: None,
+            encrypt_scalar: None,
+            twilight_address: None,
+        };
+        let mut tx_result = BlockResult::new();
+        let mut block_fees = BlockFees::new(failed_tx.block_height);
+        let mut utxo_events = Vec::new();
+        // a reprocessed tx is not part of the stats of its block
+        let mut timings = BlockTimings::new(*SLOW_TX_THRESHOLD);
+        process_transfer(
+            self,
+            transaction,
+            failed_tx.block_height,
+            &mut tx_result,
+            &mut block_fees,
+            &mut utxo_events,
+            &mut timings,
+        );
+        index_utxo_events(self, failed_tx.block_height, &utxo_events);
+        self.subscriptions.lock().unwrap().publish(utxo_events);
+        // a failed attempt is recorded again by process_transfer
+        if tx_result.suceess_tx.is_empty() {
+            return self
+                .failed_txs
+                .lock()
+                .unwrap()
+                .get(tx_id)
+                .cloned()
+                .ok_or("Error::Failed tx not found");
+        }
+
+        let resolved = self
+            .failed_txs
+            .lock()
+            .unwrap()
+            .resolve(tx_id)
+            .ok_or("Error::Failed tx not found")?;
+        let record = resolved.clone();
+        /***************** POstgreSQL Insert Code *********/
+        let store = self.store();
+        let treadpool_sql_queue = self.sql_queue.lock().unwrap();
+        treadpool_sql_queue.execute(move || {
+            match store.insert_failed_tx(&record) {
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to insert_failed_tx: {}", e),
+            };
+        });
+        drop(treadpool_sql_queue);
+        /**************** POstgreSQL Insert Code End **********/
+        Ok(resolved)
+    }
+
+    /// Result of applying the block at `height`, from memory for the latest blocks
+    /// and from psql for older ones.
+    pub fn block_apply_result(&self, height: u64) -> Option<BlockApplyResult> {
+        if let Some(result) = self.block_results.lock().unwrap().get(height) {
+            return Some(result.clone());
+        }
+        match self.store().get_block_result(height) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Failed to get_block_result: {}", e);
+                None
             }
         }
     }
-    return result;
 }
-pub fn all_state_type_utxo() -> Vec<String> {
+
+/// Applies `block` to the global state, see [`UtxoState::process_block`].
+pub fn process_block_for_utxo_insert(block: Block) -> BlockResult {
+    global_state().process_block(block)
+}
+
+// records the result of an applied block
+pub fn update_block_apply_result(state: &UtxoState, result: BlockApplyResult) {
+    let mut result_store = state.block_results.lock().unwrap();
+    result_store.insert(result.clone());
+    drop(result_store);
+
+    /***************** POstgreSQL Insert Code *********/
+    let store = state.store();
+    let treadpool_sql_queue = state.sql_queue.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match store.insert_block_result(&result) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_block_result: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+/// Result of applying the block at `height` to the global state,
+/// see [`UtxoState::block_apply_result`].
+pub fn get_block_apply_result(height: u64) -> Option<BlockApplyResult> {
+    global_state().block_apply_result(height)
+}
+
+// max number of utxos returned in a single allUtxos chunk
+pub const MAX_UTXO_CHUNK_LIMIT: usize = 10000;
+// unbounded allUtxos is only served when the partition is at most this size
+pub const MAX_UNBOUNDED_UTXO_COUNT: u64 = 10000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AllUtxosQuery {
+    pub io_type: IOType,
+    // utxo after which the chunk starts, as <txid_hex>:<output_index>
+    pub offset_key: Option<String>,
+    pub limit: usize,
+    #[serde(default)]
+    pub unbounded: bool,
+}
+
+impl Default for AllUtxosQuery {
+    fn default() -> Self {
+        AllUtxosQuery {
+            io_type: IOType::Coin,
+            offset_key: None,
+            limit: MAX_UTXO_CHUNK_LIMIT,
+            unbounded: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UtxoChunk {
+    pub utxos: Vec<String>,
+    pub next_offset_key: Option<String>,
+}
+
+pub fn all_type_utxo_chunk(query: &AllUtxosQuery) -> Result<UtxoChunk, &'static str> {
+    let offset_key = match &query.offset_key {
+        Some(key) => Some(
+            key.parse::<Utxo>()
+                .map_err(|_| "Invalid offset key")?
+                .to_bytes(),
+        ),
+        None => None,
+    };
+    let limit = query.limit.max(1).min(MAX_UTXO_CHUNK_LIMIT);
+    let page = utxo_read_view()
+        .page(query.io_type.to_usize(), offset_key.as_ref(), limit)
+        .map_err(|_| "Invalid io type")?;
+
+    // keys are bincode serialized utxos, returned as <txid_hex>:<output_index>
+    let utxo_string = |key: &Vec<u8>| match Utxo::from_bytes(key) {
+        Some(utxo) => utxo.to_string(),
+        None => hex::encode(key),
+    };
+    Ok(UtxoChunk {
+        utxos: page.entries.iter().map(|entry| utxo_string(&entry.keyid)).collect(),
+        next_offset_key: page.next_offset_key.as_ref().map(utxo_string),
+    })
+}
+
+/// Read view of the global utxo set. A query reads a single view, so that it is not
+/// affected by a block applied while it runs.
+pub fn utxo_read_view() -> ReadView<Output> {
+    UTXO_STORAGE.lock().unwrap().read_view()
+}
+
+/// Utxos of a partition of `view`, as <txid_hex>:<output_index>.
+pub fn all_type_utxo(view: &ReadView<Output>, input_type: IOType) -> Vec<String> {
     let mut result: Vec<String> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-    let input_type = IOType::State as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
-    for (key, output_data) in utxos {
-        match bincode::deserialize(&key) {
+    let utxos = match view.partition(input_type.to_usize()) {
+        Some(utxos) => utxos,
+        None => return result,
+    };
+    for key in utxos.keys() {
+        match bincode::deserialize(key) {
             Ok(value) => {
                 let utxo: Utxo = value;
-                let hex_str: String = utxo.to_hex();
+                let hex_str: String = utxo.to_string();
                 result.push(hex_str)
             }
             Err(args) => {
@@ -525,14 +1423,24 @@ pub fn all_state_type_utxo() -> Vec<String> {
             }
         }
     }
-    return result;
+    result
+}
+
+pub fn all_coin_type_utxo() -> Vec<String> {
+    all_type_utxo(&utxo_read_view(), IOType::Coin)
+}
+pub fn all_memo_type_utxo() -> Vec<String> {
+    all_type_utxo(&utxo_read_view(), IOType::Memo)
+}
+pub fn all_state_type_utxo() -> Vec<String> {
+    all_type_utxo(&utxo_read_view(), IOType::State)
 }
 
 pub fn all_coin_type_output() -> String {
     let mut result: Vec<Output> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let view = utxo_read_view();
     let input_type = IOType::Coin as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
+    let utxos = view.partition(input_type).unwrap();
     for (key, output_data) in utxos {
         result.push(output_data.clone());
     }
@@ -542,9 +1450,9 @@ pub fn all_coin_type_output() -> String {
 
 pub fn search_coin_type_utxo_by_address(address: address::Standard) -> Vec<Utxo> {
     let mut filtered_utxo: Vec<Utxo> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let view = utxo_read_view();
     let input_type = IOType::Coin as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
+    let utxos = view.partition(input_type).unwrap();
 
     for (key, output_data) in utxos {
         let addr = output_data.output.get_owner_address().unwrap();
@@ -565,9 +1473,9 @@ pub fn search_coin_type_utxo_by_address(address: address::Standard) -> Vec<Utxo>
 }
 pub fn search_memo_type_utxo_by_address(address: address::Standard) -> Vec<Utxo> {
     let mut filtered_utxo: Vec<Utxo> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let view = utxo_read_view();
     let input_type = IOType::Memo as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
+    let utxos = view.partition(input_type).unwrap();
 
     for (key, output_data) in utxos {
         let addr = output_data.output.get_owner_address().unwrap();
@@ -588,9 +1496,9 @@ pub fn search_memo_type_utxo_by_address(address: address::Standard) -> Vec<Utxo>
 }
 pub fn search_state_type_utxo_by_address(address: address::Standard) -> Vec<Utxo> {
     let mut filtered_utxo: Vec<Utxo> = Vec::new();
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let view = utxo_read_view();
     let input_type = IOType::State as usize;
-    let utxos = utxo_storage.data.get_mut(&input_type).unwrap();
+    let utxos = view.partition(input_type).unwrap();
 
     for (key, output_data) in utxos {
         let addr = output_data.output.get_owner_address().unwrap();
@@ -610,69 +1518,146 @@ pub fn search_state_type_utxo_by_address(address: address::Standard) -> Vec<Utxo
     return filtered_utxo;
 }
 
+// utxo with the hex of its bincode encoded output
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RawUtxoOutput {
+    pub utxo: Utxo,
+    pub output: String,
+}
+
+/// Utxos of `address` with their cached output encoding. Outputs are neither cloned
+/// nor re-encoded. The partition is scanned, utxos are not indexed by address, so
+/// the result can not drift from the utxo set.
+pub fn raw_outputs_by_address(
+    view: &ReadView<Output>,
+    address: &address::Standard,
+    input_type: IOType,
+) -> Vec<RawUtxoOutput> {
+    let mut filtered: Vec<RawUtxoOutput> = Vec::new();
+    let input_type = input_type.to_usize();
+    let utxos = match view.partition(input_type) {
+        Some(utxos) => utxos,
+        None => return filtered,
+    };
+    for (key, output_data) in utxos {
+        let addr = match output_data.output.get_owner_address() {
+            Some(addr) => addr,
+            None => continue,
+        };
+        if address::Standard::from_hex(addr).public_key != address.public_key {
+            continue;
+        }
+        let encoded = match view.get_encoded(key, input_type) {
+            Some(encoded) => encoded,
+            None => continue,
+        };
+        match bincode::deserialize(&key) {
+            Ok(utxo) => filtered.push(RawUtxoOutput {
+                utxo,
+                output: hex::encode(encoded),
+            }),
+            Err(args) => {
+                let err = format!("Deserialization error, {:?}", args);
+                println!("{}", err)
+            }
+        }
+    }
+    filtered
+}
+
+/// Utxos of `address` in a partition of `view`, e.g. the view of the state of a
+/// secondary network. The owners are matched by public key, the network of `address`
+/// is checked by the caller, see [`crate::network::NetworkStates::state_for_address`].
+pub fn utxos_by_address(
+    view: &ReadView<Output>,
+    address: &address::Standard,
+    input_type: IOType,
+) -> Vec<Utxo> {
+    let utxos = match view.partition(input_type.to_usize()) {
+        Some(utxos) => utxos,
+        None => return Vec::new(),
+    };
+    utxos
+        .iter()
+        .filter(|(_, output)| {
+            output.output.get_owner_address().map_or(false, |owner| {
+                address::Standard::from_hex(owner).public_key == address.public_key
+            })
+        })
+        .filter_map(|(key, _)| bincode::deserialize(key).ok())
+        .collect()
+}
+
+pub fn search_raw_utxo_by_address(
+    address: address::Standard,
+    input_type: IOType,
+) -> Vec<RawUtxoOutput> {
+    raw_outputs_by_address(&utxo_read_view(), &address, input_type)
+}
+
+/// Hex of the cached output encoding of `utxo`.
+pub fn search_raw_output_by_utxo_key(utxo: Utxo, input_type: IOType) -> Result<String, &'static str> {
+    match utxo_read_view().get_encoded(&utxo_key(&utxo), input_type.to_usize()) {
+        Some(encoded) => Ok(hex::encode(encoded)),
+        None => Err("Utxo not found "),
+    }
+}
+
 pub fn search_coin_type_utxo_by_utxo_key(utxo: Utxo) -> Result<Output, &'static str> {
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
     let input_type = IOType::Coin as usize;
-    let result = match utxo_storage.get_utxo_by_id(utxo.to_bytes(), input_type) {
-        Ok(output) => output,
-        Err(_err) => return Err("Utxo not found "),
+    let result = match utxo_read_view().get(&utxo_key(&utxo), input_type) {
+        Some(output) => output.clone(),
+        None => return Err("Utxo not found "),
     };
     return Ok(result);
 }
 
 pub fn search_utxo_by_utxo_key(utxo: Utxo, input_type: IOType) -> Result<Output, &'static str> {
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-
-    let result = match utxo_storage.get_utxo_by_id(utxo.to_bytes(), input_type.to_usize()) {
-        Ok(output) => output,
-        Err(_err) => return Err("Utxo not found "),
+    let result = match utxo_read_view().get(&utxo_key(&utxo), input_type.to_usize()) {
+        Some(output) => output.clone(),
+        None => return Err("Utxo not found "),
     };
     return Ok(result);
 }
 pub fn search_memo_type_utxo_by_utxo_key(utxo: Utxo) -> Result<Output, &'static str> {
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
     let input_type = IOType::Memo as usize;
-    let result = match utxo_storage.get_utxo_by_id(utxo.to_bytes(), input_type) {
-        Ok(output) => output,
-        Err(_err) => return Err("Utxo not found "),
+    let result = match utxo_read_view().get(&utxo_key(&utxo), input_type) {
+        Some(output) => output.clone(),
+        None => return Err("Utxo not found "),
     };
     return Ok(result);
 }
 pub fn search_state_type_utxo_by_utxo_key(utxo: Utxo) -> Result<Output, &'static str> {
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
     let input_type = IOType::State as usize;
-    let result = match utxo_storage.get_utxo_by_id(utxo.to_bytes(), input_type) {
-        Ok(output) => output,
-        Err(_err) => return Err("Utxo not found "),
+    let result = match utxo_read_view().get(&utxo_key(&utxo), input_type) {
+        Some(output) => output.clone(),
+        None => return Err("Utxo not found "),
     };
     return Ok(result);
 }
 pub fn total_memo_type_utxos() -> u64{
     println!("inside total memo");
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
     let input_type = IOType::Memo as usize;
-    let result = utxo_storage.get_count_by_type(input_type); 
+    let result = utxo_read_view().count(input_type);
     println!("{}", result);
     return result;
 }
 
 pub fn total_state_type_utxos() -> u64{
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
     let input_type = IOType::State as usize;
-    let result = utxo_storage.get_count_by_type(input_type); 
+    let result = utxo_read_view().count(input_type);
     println!("{}", result);
     return result;
 }
 
 pub fn total_coin_type_utxos() -> u64{
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
     let input_type = IOType::Coin as usize;
-    let result = utxo_storage.get_count_by_type(input_type); 
+    let result = utxo_read_view().count(input_type);
     println!("{}", result);
     return result;
 }
-pub fn verify_utxo(transaction: transaction::Transaction) -> bool {
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+pub fn verify_utxo(state: &UtxoState, transaction: transaction::Transaction) -> bool {
+    let mut utxo_storage = state.utxo_storage.lock().unwrap();
 
     let tx_inputs = transaction.get_tx_inputs();
     if transaction.tx_type == TransactionType::Script {
@@ -684,9 +1669,10 @@ pub fn verify_utxo(transaction: transaction::Transaction) -> bool {
                 IOType::State => OutputData::State(input.as_out_state().unwrap().clone()),
             };
             let utxo_test = Utxo::new(TxID(Hash([0; 32])), 0);
-            if utxo.to_owned() != utxo_test {
+            // outputs of the tx itself are checked by validate_structure
+            if utxo.to_owned() != utxo_test && !utxo.is_pending() {
                 let utxo_input_type = input.in_type as usize;
-                let utxo_key = bincode::serialize(input.as_utxo().unwrap()).unwrap();
+                let utxo_key = utxo_key(input.as_utxo().unwrap());
 
                 let utxo_output_from_chain_result =
                     utxo_storage.get_utxo_by_id(utxo_key.clone(), utxo_input_type);
@@ -748,7 +1734,7 @@ pub fn verify_utxo(transaction: transaction::Transaction) -> bool {
             };
             let utxo_test = Utxo::new(TxID(Hash([0; 32])), 0);
             if utxo.to_owned() != utxo_test {
-                let utxo_key = bincode::serialize(utxo).unwrap();
+                let utxo_key = utxo_key(utxo);
 
                 let utxo_output_from_chain_result =
                     utxo_storage.get_utxo_by_id(utxo_key.clone(), 0);
@@ -786,7 +1772,7 @@ pub fn verify_utxo(transaction: transaction::Transaction) -> bool {
                 IOType::Coin => OutputData::Coin(input.as_out_coin().unwrap().clone()),
                 _ => return false,
             };
-            let utxo_key = bincode::serialize(utxo).unwrap();
+            let utxo_key = utxo_key(utxo);
             let utxo_output_from_chain_result = utxo_storage.get_utxo_by_id(utxo_key.clone(), 0);
             match utxo_output_from_chain_result {
                 Ok(utxo_output_from_chain) => match input.in_type {
@@ -1003,6 +1989,7 @@ pub fn create_utxo_test_block(
         block_hash: "abc123".to_string(),
         block_height: prev_height + 1,
         transactions: txs,
+        ..Default::default()
     };
     //append new utxo set with old one to update the recent outputs
     set.append(&mut new_set);
@@ -1015,6 +2002,14 @@ mod test {
 
     use crate::blockoperations::blockprocessing::create_utxo_test_block;
     use crate::blockoperations::blockprocessing::process_block_for_utxo_insert;
+    use crate::blockoperations::blockprocessing::{
+        order_block_transactions, Block, BlockResult, TransactionMessage,
+    };
+    use rand::Rng;
+    use transaction::reference_tx::{convert_output_to_input, RecordUtxo};
+    use transaction::{ScriptTransaction, Transaction, TransactionData};
+    use zkvm::zkos_types::{IOType, Input, Output, OutputData, Utxo};
+    use zkvm::{Hash, VMError};
     use crate::db::*;
     use crate::{init_utxo, UTXO_STORAGE};
     use curve25519_dalek::scalar::Scalar;
@@ -1053,4 +2048,1958 @@ mod test {
         println!("Block Height{:?} ", block.block_height);
         println!("Block Txs{:?} ", block.transactions);
     }
+
+    // script tx `tx_id` spending `input` into a single coin output
+    fn chained_transfer_message(
+        tx_id: [u8; 32],
+        input: RecordUtxo,
+    ) -> (TransactionMessage, RecordUtxo) {
+        let output = input.value.clone();
+        transfer_message(tx_id, input, output)
+    }
+
+    // script tx `tx_id` spending `input` into `output`
+    fn transfer_message(
+        tx_id: [u8; 32],
+        input: RecordUtxo,
+        output: Output,
+    ) -> (TransactionMessage, RecordUtxo) {
+        let inputs = vec![convert_output_to_input(input).unwrap()];
+        let message = script_message(tx_id, &inputs, &[output.clone()]);
+        let created = RecordUtxo {
+            utx: Utxo::from_hash(Hash(tx_id), 0),
+            value: output,
+        };
+        (message, created)
+    }
+
+    // dummy script tx, block processing does not verify its proof
+    fn script_message(tx_id: [u8; 32], inputs: &[Input], outputs: &[Output]) -> TransactionMessage {
+        let script_tx = ScriptTransaction::create_utxo_dummy_script_transaction(inputs, outputs);
+        let tx = Transaction::transaction_script(TransactionData::TransactionScript(script_tx));
+        tx_message(tx_id, &tx)
+    }
+
+    // transfer message of `tx` under `tx_id`
+    fn tx_message(tx_id: [u8; 32], tx: &Transaction) -> TransactionMessage {
+        TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: hex::encode(tx_id),
+            tx_byte_code: Some(hex::encode(bincode::serialize(tx).unwrap())),
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account: None,
+            encrypt_scalar: None,
+            twilight_address: None,
+        }
+    }
+
+    // adds the coins spent by the inputs of `tx` to the global utxo set
+    fn seed_inputs(tx: &Transaction) {
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        for input in tx.get_tx_inputs() {
+            let coin = Output::coin(OutputData::coin(input.as_out_coin().unwrap().clone()));
+            let _ = utxo_storage.add(
+                bincode::serialize(input.as_utxo().unwrap()).unwrap(),
+                coin,
+                IOType::Coin as usize,
+            );
+        }
+    }
+
+    // chain a -> b -> c of same block transactions spending a genesis coin
+    fn chained_block_transactions() -> (RecordUtxo, Vec<TransactionMessage>) {
+        let (acc, _prv) = Account::generate_random_account_with_value(Scalar::from(20u64));
+        let genesis = create_genesis_block(30, 1, acc)
+            .into_iter()
+            .find(|record| record.value.out_type == IOType::Coin)
+            .unwrap();
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill(&mut seed);
+        let tx_id = |n: u8| {
+            let mut id = seed;
+            id[31] = n;
+            id
+        };
+        let (tx_a, out_a) = chained_transfer_message(tx_id(1), genesis.clone());
+        let (tx_b, out_b) = chained_transfer_message(tx_id(2), out_a);
+        let (tx_c, _) = chained_transfer_message(tx_id(3), out_b);
+        (genesis, vec![tx_a, tx_b, tx_c])
+    }
+
+    #[test]
+    fn order_block_transactions_test() {
+        let (_genesis, chain) = chained_block_transactions();
+        let (tx_a, tx_b, tx_c) = (chain[0].clone(), chain[1].clone(), chain[2].clone());
+        let mut independent = tx_a.clone();
+        independent.tx_type = "testtype".to_string();
+        independent.tx_id = "independent".to_string();
+
+        let wire_order = vec![tx_c.clone(), independent.clone(), tx_b.clone(), tx_a.clone()];
+        let ordered: Vec<String> = order_block_transactions(wire_order)
+            .into_iter()
+            .map(|tx| tx.tx_id)
+            .collect();
+        assert_eq!(ordered, vec![independent.tx_id, tx_a.tx_id, tx_b.tx_id, tx_c.tx_id]);
+    }
+
+    // cargo test -- --nocapture --test chained_block_replay_test --test-threads 1
+    #[test]
+    fn chained_block_replay_test() {
+        let (genesis, chain) = chained_block_transactions();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            bincode::serialize(&genesis.utx).unwrap(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+
+        // wire order is the reverse of the spend order
+        let mut transactions = chain.clone();
+        transactions.reverse();
+        let block = Block {
+            block_hash: "chained".to_string(),
+            block_height: 800_001,
+            transactions,
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block.clone());
+        assert_eq!(result.suceess_tx.len(), 3);
+        assert!(result.failed_tx.is_empty());
+
+        let last_output = bincode::serialize(&Utxo::from_hash(
+            Hash(hex::decode(&chain[2].tx_id).unwrap().try_into().unwrap()),
+            0,
+        ))
+        .unwrap();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let count = utxo_storage.get_count_by_type(IOType::Coin as usize);
+        assert!(utxo_storage
+            .search_key(&last_output, IOType::Coin as usize)
+            .unwrap());
+        drop(utxo_storage);
+
+        // replaying the block leaves the utxo set untouched
+        let replay = process_block_for_utxo_insert(block);
+        assert_eq!(replay, BlockResult::new());
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert_eq!(utxo_storage.get_count_by_type(IOType::Coin as usize), count);
+    }
+
+    // cargo test -- --nocapture --test interrupted_block_replay_test --test-threads 1
+    #[test]
+    fn interrupted_block_replay_test() {
+        let (genesis, chain) = chained_block_transactions();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            bincode::serialize(&genesis.utx).unwrap(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+
+        // a run of the block stopped after b, the outputs of a are spent by b
+        let partial = Block {
+            block_hash: "interrupted".to_string(),
+            block_height: 800_301,
+            transactions: chain[..2].to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(process_block_for_utxo_insert(partial).suceess_tx.len(), 2);
+
+        // a and b are skipped as applied at the height, not failed for their spent inputs
+        let block = Block {
+            block_hash: "interrupted".to_string(),
+            block_height: 800_301,
+            transactions: chain.clone(),
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx.len(), 3);
+        assert!(result.failed_tx.is_empty());
+
+        let output = |tx: &TransactionMessage| {
+            bincode::serialize(&Utxo::from_hash(
+                Hash(hex::decode(&tx.tx_id).unwrap().try_into().unwrap()),
+                0,
+            ))
+            .unwrap()
+        };
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert!(!utxo_storage
+            .search_key(&output(&chain[1]), IOType::Coin as usize)
+            .unwrap());
+        assert!(utxo_storage
+            .search_key(&output(&chain[2]), IOType::Coin as usize)
+            .unwrap());
+    }
+
+    #[test]
+    fn block_tx_root_invalid_txid_test() {
+        use crate::blockoperations::blockprocessing::update_block_tx_root;
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+
+        let state = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        let (_, chain) = chained_block_transactions();
+        let mut block = Block {
+            block_hash: "tx_root".to_string(),
+            block_height: 800_401,
+            transactions: chain,
+            ..Default::default()
+        };
+        assert_eq!(update_block_tx_root(&state, &block).unwrap().txids.len(), 3);
+
+        // a txid that can not be decoded fails the root instead of being left out of it
+        block.block_height = 800_402;
+        block.transactions[1].tx_id = "not a txid".to_string();
+        assert_eq!(
+            update_block_tx_root(&state, &block),
+            Err("Error::Invalid txid in block")
+        );
+        assert!(state
+            .block_tx_roots
+            .lock()
+            .unwrap()
+            .get_root(800_402)
+            .is_none());
+    }
+
+    // cargo test -- --nocapture --test block_apply_result_test --test-threads 1
+    #[test]
+    fn block_apply_result_test() {
+        use crate::blockoperations::blockprocessing::get_block_apply_result;
+        use crate::blockoperations::blockresult::BlockApplyResult;
+
+        let (genesis, chain) = chained_block_transactions();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            bincode::serialize(&genesis.utx).unwrap(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+
+        // c spends the output of b, which is missing from the block
+        let block = Block {
+            block_hash: "apply_result".to_string(),
+            block_height: 800_201,
+            transactions: vec![chain[0].clone(), chain[2].clone()],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx.len(), 1);
+        assert_eq!(result.failed_tx.len(), 1);
+
+        let stored = get_block_apply_result(800_201).unwrap();
+        assert_eq!(stored.height, 800_201);
+        assert_eq!(stored.applied_txids, result.suceess_tx);
+        assert_eq!(
+            stored.failed,
+            vec![(
+                result.failed_tx[0],
+                "Error::Tx inputs are not in the utxo set".to_string()
+            )]
+        );
+        assert_eq!((stored.utxos_added, stored.utxos_removed), (1, 1));
+        // the psql row holds the same result
+        assert_eq!(BlockApplyResult::from_bytes(&stored.to_bytes()).unwrap(), stored);
+    }
+
+    #[test]
+    fn block_fee_accounting_test() {
+        use crate::blockoperations::blockprocessing::update_block_fees;
+        use crate::blockoperations::fees::{BlockFees, BLOCK_FEES, TOTAL_FEES_COLLECTED};
+        use crate::state::global_state;
+
+        let collected_before = TOTAL_FEES_COLLECTED.get();
+        for (height, fees) in [(900_001u64, vec![3u64, 7]), (900_002, vec![20])] {
+            let mut block_fees = BlockFees::new(height);
+            for fee in fees {
+                block_fees.add_fee(fee).unwrap();
+            }
+            update_block_fees(&global_state(), block_fees);
+        }
+
+        let fee_store = BLOCK_FEES.lock().unwrap();
+        assert_eq!(fee_store.get(900_001).unwrap().total_fees, 10);
+        let stats = fee_store.stats(900_001, 900_002).unwrap();
+        assert_eq!(stats.total_fees, 30);
+        assert_eq!(stats.tx_count, 3);
+        assert_eq!(stats.average_fee_per_block, 15.0);
+        assert_eq!(stats.average_fee_per_tx, 10.0);
+        assert!(TOTAL_FEES_COLLECTED.get() - collected_before >= 30.0);
+    }
+
+    #[test]
+    fn raw_outputs_by_address_test() {
+        use crate::blockoperations::blockprocessing::raw_outputs_by_address;
+
+        let (genesis, _) = chained_block_transactions();
+        let mut utxo_storage = LocalStorage::<Output>::new(3);
+        utxo_storage
+            .add(
+                bincode::serialize(&genesis.utx).unwrap(),
+                genesis.value.clone(),
+                IOType::Coin as usize,
+            )
+            .unwrap();
+        let owner =
+            address::Standard::from_hex(genesis.value.output.get_owner_address().unwrap());
+
+        let view = utxo_storage.read_view();
+        let raw = raw_outputs_by_address(&view, &owner, IOType::Coin);
+        assert_eq!(raw.len(), 1);
+        assert_eq!(raw[0].utxo, genesis.utx);
+        // same bytes as encoding the output on demand
+        assert_eq!(
+            raw[0].output,
+            hex::encode(bincode::serialize(&genesis.value).unwrap())
+        );
+        assert!(raw_outputs_by_address(&view, &owner, IOType::Memo).is_empty());
+    }
+
+    #[test]
+    fn invalid_output_rejected_test() {
+        use quisquislib::elgamal::ElGamalCommitment;
+
+        let (genesis, _) = chained_block_transactions();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            bincode::serialize(&genesis.utx).unwrap(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+
+        let coin = genesis.value.output.get_output_coin().unwrap().clone();
+        let mut bad_encryption = coin.clone();
+        // 0xff..ff is not a canonical point encoding
+        bad_encryption.encrypt = ElGamalCommitment::from_bytes(&[0xffu8; 64]).unwrap();
+        let mut bad_owner = coin;
+        bad_owner.owner = "00".to_string();
+        let cases = vec![
+            (bad_encryption, VMError::InvalidOutputEncryption),
+            (bad_owner, VMError::InvalidOutputAddress),
+        ];
+
+        for (i, (coin, expected)) in cases.into_iter().enumerate() {
+            let output = Output::coin(OutputData::Coin(coin));
+            assert_eq!(output.validate(), Err(expected));
+
+            let mut tx_id = [0u8; 32];
+            rand::thread_rng().fill(&mut tx_id);
+            let (message, created) = transfer_message(tx_id, genesis.clone(), output);
+            let block = Block {
+                block_hash: "invalid_output".to_string(),
+                block_height: 800_101 + i as u64,
+                transactions: vec![message],
+                ..Default::default()
+            };
+            let result = process_block_for_utxo_insert(block);
+            assert!(result.suceess_tx.is_empty());
+            assert_eq!(result.failed_tx.len(), 1);
+
+            let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+            assert!(!utxo_storage
+                .search_key(&bincode::serialize(&created.utx).unwrap(), IOType::Coin as usize)
+                .unwrap());
+            assert!(utxo_storage
+                .search_key(&bincode::serialize(&genesis.utx).unwrap(), IOType::Coin as usize)
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn output_type_mismatch_rejected_test() {
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+        use zkvm::zkos_types::{OutputMemo, OutputState};
+
+        let (genesis, _) = chained_block_transactions();
+        let coin = genesis.value.output.get_output_coin().unwrap().clone();
+
+        // outputs converted with From are stored in the partition of their kind
+        let mut storage = LocalStorage::<Output>::new(3);
+        let outputs: Vec<Output> = vec![
+            coin.clone().into(),
+            OutputMemo::default().into(),
+            OutputState::default().into(),
+        ];
+        for (index, output) in outputs.into_iter().enumerate() {
+            let key = utxo_key(&Utxo::from_hash(Hash([9u8; 32]), index as u16));
+            storage
+                .add(key, output.clone(), output.out_type as usize)
+                .unwrap();
+        }
+        for io_type in [IOType::Coin, IOType::Memo, IOType::State] {
+            assert_eq!(storage.get_count_by_type(io_type as usize), 1);
+        }
+
+        // a tx carrying a coin tagged as a state fails to decode and is not applied
+        let state = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        state
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .add(
+                utxo_key(&genesis.utx),
+                genesis.value.clone(),
+                IOType::Coin as usize,
+            )
+            .unwrap();
+        let mismatched = Output {
+            out_type: IOType::State,
+            output: OutputData::Coin(coin),
+        };
+        assert_eq!(mismatched.validate(), Err(VMError::OutputTypeMismatch));
+        let mut tx_id = [0u8; 32];
+        rand::thread_rng().fill(&mut tx_id);
+        let (message, created) = transfer_message(tx_id, genesis.clone(), mismatched);
+        let result = state.process_block(Block {
+            block_hash: "type_mismatch".to_string(),
+            block_height: 800_151,
+            transactions: vec![message],
+            ..Default::default()
+        });
+        assert!(result.suceess_tx.is_empty());
+        assert!(result.errors[0].contains(&VMError::OutputTypeMismatch.to_string()));
+
+        let mut utxo_storage = state.utxo_storage.lock().unwrap();
+        for io_type in [IOType::Coin, IOType::Memo, IOType::State] {
+            assert!(!utxo_storage
+                .search_key(&utxo_key(&created.utx), io_type as usize)
+                .unwrap());
+        }
+        assert_eq!(utxo_storage.get_count_by_type(IOType::State as usize), 0);
+        assert!(utxo_storage
+            .search_key(&utxo_key(&genesis.utx), IOType::Coin as usize)
+            .unwrap());
+    }
+
+    // cargo test -- --nocapture --test burn_message_block_test --test-threads 1
+    #[test]
+    fn burn_message_block_test() {
+        use crate::blockoperations::messages::{get_applied_message, BURN_HISTORY};
+        use address::Network;
+        use quisquislib::elgamal::ElGamalCommitment;
+        use quisquislib::keys::PublicKey;
+        use quisquislib::ristretto::RistrettoPublicKey;
+        use transaction::{Message, Receiver, Sender, TransferTransaction};
+        use zkvm::zkos_types::{Input, MessageType};
+
+        // same flow as test_create_burn_message of the transaction crate
+        // dark transfer of 500 to a zero balance burn account
+        let mut rng = rand::thread_rng();
+        let (bob_account, bob_sk) = Account::generate_random_account_with_value(500u64.into());
+        let (bob_pk, _) = bob_account.get_account();
+        let burn_pk = RistrettoPublicKey::update_public_key(&bob_pk, Scalar::random(&mut rng));
+        let burn_comm_scalar = Scalar::random(&mut rng);
+        let burn_commitment =
+            ElGamalCommitment::generate_commitment(&burn_pk, burn_comm_scalar, Scalar::from(0u64));
+        let burn_account = Account::set_account(burn_pk, burn_commitment);
+
+        let burn_reciever = Receiver::set_receiver(500, burn_account);
+        let bob_sender = Sender::set_sender(-500, bob_account, vec![burn_reciever]);
+        let (value_vector, account_vector, sender_count, receiver_count) =
+            Sender::generate_value_and_account_vector(vec![bob_sender]).unwrap();
+        let bob_input =
+            Input::input_from_quisquis_account(&bob_account, Utxo::random(), 0, Network::default());
+        let burn_input = Input::input_from_quisquis_account(
+            &burn_account,
+            Utxo::default(),
+            0,
+            Network::default(),
+        );
+        let (transfer, comm_scalar_final) = TransferTransaction::create_private_transfer_transaction(
+            &value_vector,
+            &account_vector,
+            &vec![500],
+            &vec![500],
+            &vec![bob_input, burn_input.clone()],
+            &vec![bob_sk],
+            sender_count,
+            receiver_count,
+            Some(&vec![burn_comm_scalar]),
+            0u64,
+        )
+        .unwrap();
+        let transfer_tx =
+            Transaction::transaction_transfer(TransactionData::TransactionTransfer(transfer));
+
+        // the burn account output as a coin of the utxo set
+        let burn_output = transfer_tx.get_tx_outputs()[1].clone();
+        let mut seed = [0u8; 32];
+        rng.fill(&mut seed);
+        let burn_utxo = Utxo::from_hash(Hash(seed), 1);
+        let burn_utxo_key = bincode::serialize(&burn_utxo).unwrap();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            burn_utxo_key.clone(),
+            burn_output.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+
+        let burn_address = burn_input.as_owner_address().unwrap().to_owned();
+        let burn_message = Message::create_burn_message(
+            burn_output.as_out_coin().unwrap().to_input(burn_utxo, 0),
+            500u64,
+            comm_scalar_final.unwrap()[0].clone(),
+            bob_sk,
+            burn_address.clone(),
+        );
+        let burn_tx = Transaction::from(burn_message);
+        assert!(burn_tx.verify().is_ok());
+
+        let mut tx_id = [0u8; 32];
+        rng.fill(&mut tx_id);
+        let message = TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: hex::encode(tx_id),
+            tx_byte_code: Some(hex::encode(bincode::serialize(&burn_tx).unwrap())),
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account: None,
+            encrypt_scalar: None,
+            twilight_address: None,
+        };
+        let block = Block {
+            block_hash: "burn".to_string(),
+            block_height: 800_201,
+            transactions: vec![message.clone()],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx.len(), 1);
+        assert!(result.failed_tx.is_empty());
+
+        // the burned coin is no longer spendable
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert!(!utxo_storage
+            .search_key(&burn_utxo_key, IOType::Coin as usize)
+            .unwrap());
+        drop(utxo_storage);
+
+        let history = BURN_HISTORY.lock().unwrap().history(&burn_address);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].txid, message.tx_id);
+        assert_eq!(history[0].amount, 500);
+        assert_eq!(history[0].block_height, 800_201);
+        assert_eq!(
+            get_applied_message(&message.tx_id).unwrap().msg_type,
+            MessageType::Burn
+        );
+    }
+
+    // cargo test -- --nocapture --test failed_tx_reprocess_test --test-threads 1
+    #[test]
+    fn failed_tx_reprocess_test() {
+        use crate::blockoperations::blockprocessing::reprocess_failed_tx;
+        use crate::blockoperations::failedtx::FAILED_TXS;
+
+        // the genesis coin spent by the chain is not in the utxo set yet
+        let (genesis, chain) = chained_block_transactions();
+        let block = Block {
+            block_hash: "dead-letter".to_string(),
+            block_height: 800_301,
+            transactions: vec![chain[0].clone()],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert!(result.suceess_tx.is_empty());
+        assert_eq!(result.failed_tx.len(), 1);
+
+        let failed_tx = FAILED_TXS.lock().unwrap().get(&chain[0].tx_id).cloned().unwrap();
+        assert_eq!(failed_tx.block_height, 800_301);
+        assert_eq!(Some(failed_tx.tx_byte_code.clone()), chain[0].tx_byte_code);
+        assert!(!failed_tx.is_resolved());
+        let failed = FAILED_TXS.lock().unwrap().range(800_301, 800_301).unwrap();
+        assert!(failed.iter().any(|tx| tx.txid == chain[0].tx_id));
+
+        // still missing, the attempt is recorded again
+        let retried = reprocess_failed_tx(&chain[0].tx_id).unwrap();
+        assert!(!retried.is_resolved());
+
+        // create the missing utxo and reprocess
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            bincode::serialize(&genesis.utx).unwrap(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+        let resolved = reprocess_failed_tx(&chain[0].tx_id).unwrap();
+        assert!(resolved.is_resolved());
+        assert_eq!(resolved.block_height, 800_301);
+
+        let output = bincode::serialize(&Utxo::from_hash(
+            Hash(hex::decode(&chain[0].tx_id).unwrap().try_into().unwrap()),
+            0,
+        ))
+        .unwrap();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert!(utxo_storage.search_key(&output, IOType::Coin as usize).unwrap());
+        assert!(!utxo_storage
+            .search_key(&bincode::serialize(&genesis.utx).unwrap(), IOType::Coin as usize)
+            .unwrap());
+        drop(utxo_storage);
+        assert!(reprocess_failed_tx(&chain[0].tx_id).is_err());
+        assert!(reprocess_failed_tx("unknown").is_err());
+    }
+    // cargo test -- --nocapture --test address_tx_index_block_test --test-threads 1
+    #[test]
+    fn address_tx_index_block_test() {
+        use crate::blockoperations::addresstx::ADDRESS_TXS;
+        use transaction::reference_tx::create_qq_reference_transaction;
+
+        // quisquis transfer, the inputs carry the anonymity set
+        let tx = create_qq_reference_transaction();
+        seed_inputs(&tx);
+        let mut tx_id = [0u8; 32];
+        rand::thread_rng().fill(&mut tx_id);
+        let message = TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: hex::encode(tx_id),
+            tx_byte_code: Some(hex::encode(tx.to_bytes())),
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account: None,
+            encrypt_scalar: None,
+            twilight_address: None,
+        };
+        let block = Block {
+            block_hash: "address-index".to_string(),
+            block_height: 800_401,
+            transactions: vec![message.clone()],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx.len(), 1);
+
+        // real and decoy owners all resolve to the tx
+        let inputs = tx.get_tx_inputs();
+        let outputs = tx.get_tx_outputs();
+        let owners: Vec<String> = inputs
+            .iter()
+            .filter_map(|input| input.as_owner_address().cloned())
+            .chain(
+                outputs
+                    .iter()
+                    .filter_map(|output| output.output.get_owner_address().cloned()),
+            )
+            .collect();
+        assert!(owners.len() > 2);
+        let index = ADDRESS_TXS.lock().unwrap();
+        for owner in owners.iter() {
+            let txs = index.page(owner, 0);
+            let entry = txs.iter().find(|entry| entry.txid == message.tx_id).unwrap();
+            assert_eq!(entry.block_height, 800_401);
+        }
+    }
+
+    // cargo test -- --nocapture --test block_processing_stats_test --test-threads 1
+    #[test]
+    fn block_processing_stats_test() {
+        use crate::blockoperations::blockstats::{
+            BLOCK_APPLY_SECONDS, BLOCK_STATS, BLOCK_STATS_RETENTION,
+        };
+        use transaction::reference_tx::create_qq_reference_transaction;
+
+        let phases = ["verify", "apply", "index", "persist"];
+        let counts: Vec<u64> = phases
+            .iter()
+            .map(|phase| BLOCK_APPLY_SECONDS.with_label_values(&[phase]).get_sample_count())
+            .collect();
+
+        let tx = create_qq_reference_transaction();
+        seed_inputs(&tx);
+        let mut tx_id = [0u8; 32];
+        rand::thread_rng().fill(&mut tx_id);
+        let message = TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: hex::encode(tx_id),
+            tx_byte_code: Some(hex::encode(tx.to_bytes())),
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account: None,
+            encrypt_scalar: None,
+            twilight_address: None,
+        };
+        let block = Block {
+            block_hash: "block-stats".to_string(),
+            block_height: 800_601,
+            transactions: vec![message],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx.len(), 1);
+
+        for (phase, count) in phases.iter().zip(counts) {
+            let histogram = BLOCK_APPLY_SECONDS.with_label_values(&[phase]);
+            assert!(histogram.get_sample_count() > count, "{} not observed", phase);
+            assert!(histogram.get_sample_sum() > 0.0, "{} not timed", phase);
+        }
+        let stats = BLOCK_STATS.lock().unwrap().latest(BLOCK_STATS_RETENTION);
+        let stats = stats.iter().find(|stats| stats.block_height == 800_601).unwrap();
+        assert_eq!(stats.tx_count, 1);
+        assert_eq!(stats.failed_tx_count, 0);
+        assert!(stats.verify_millis > 0.0);
+        assert!(stats.apply_millis > 0.0);
+        assert!(stats.index_millis > 0.0);
+        assert!(stats.persist_millis > 0.0);
+        assert!(stats.total_millis >= stats.verify_millis + stats.apply_millis);
+    }
+
+    // cargo test -- --nocapture --test pending_input_block_test --test-threads 1
+    #[test]
+    fn pending_input_block_test() {
+        use address::{Address, Network};
+        use zkvm::zkos_types::{InputData, OutputMemo};
+        use zkvm::{Commitment, TxID};
+
+        let (genesis, _) = chained_block_transactions();
+        let genesis_key = bincode::serialize(&genesis.utx).unwrap();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            genesis_key.clone(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        drop(utxo_storage);
+
+        // the order memo is created and referred to by the same tx
+        let memo = OutputMemo::new(
+            Address::script_address(Network::default(), [7u8; 32]).as_hex(),
+            genesis.value.output.get_owner_address().unwrap().clone(),
+            Commitment::Closed(Commitment::blinded(5u64).to_point()),
+            None,
+            0,
+        );
+        let outputs = vec![Output::memo(OutputData::Memo(memo.clone()))];
+        let inputs = |output_index: u16| {
+            vec![
+                convert_output_to_input(genesis.clone()).unwrap(),
+                Input::memo(InputData::memo(
+                    Utxo::pending(output_index),
+                    memo.clone(),
+                    1,
+                    None,
+                )),
+            ]
+        };
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill(&mut seed);
+        let tx_id = |n: u8| {
+            let mut id = seed;
+            id[31] = n;
+            id
+        };
+
+        // the tx has no output 4
+        let block = Block {
+            block_hash: "pending_dangling".to_string(),
+            block_height: 800_701,
+            transactions: vec![script_message(tx_id(1), &inputs(4), &outputs)],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert!(result.suceess_tx.is_empty());
+        assert_eq!(
+            result.errors,
+            vec!["Input 1 refers to output 4 the transaction does not have".to_string()]
+        );
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert!(utxo_storage
+            .search_key(&genesis_key, IOType::Coin as usize)
+            .unwrap());
+        drop(utxo_storage);
+
+        let block = Block {
+            block_hash: "pending".to_string(),
+            block_height: 800_702,
+            transactions: vec![script_message(tx_id(2), &inputs(0), &outputs)],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert_eq!(result.suceess_tx, vec![TxID(Hash(tx_id(2)))]);
+        // the coin is spent, the memo stays in the utxo set under the txid
+        let memo_key = bincode::serialize(&Utxo::from_hash(Hash(tx_id(2)), 0)).unwrap();
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert!(!utxo_storage
+            .search_key(&genesis_key, IOType::Coin as usize)
+            .unwrap());
+        assert!(utxo_storage
+            .search_key(&memo_key, IOType::Memo as usize)
+            .unwrap());
+    }
+
+    #[test]
+    fn output_key_collision_block_test() {
+        let (genesis, _) = chained_block_transactions();
+        let genesis_key = utxo_key(&genesis.utx);
+        let mut tx_id = [0u8; 32];
+        rand::thread_rng().fill(&mut tx_id);
+        // the key of the coin output is held by the memo partition
+        let output_key = utxo_key(&Utxo::from_hash(Hash(tx_id), 0));
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let _ = utxo_storage.add(
+            genesis_key.clone(),
+            genesis.value.clone(),
+            IOType::Coin as usize,
+        );
+        utxo_storage
+            .add(
+                output_key.clone(),
+                genesis.value.clone(),
+                IOType::Memo as usize,
+            )
+            .unwrap();
+        drop(utxo_storage);
+
+        let (message, _) = transfer_message(tx_id, genesis.clone(), genesis.value.clone());
+        let block = Block {
+            block_hash: "collision".to_string(),
+            block_height: 800_703,
+            transactions: vec![message],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        assert!(result.suceess_tx.is_empty());
+        assert_eq!(result.failed_tx, vec![TxID(Hash(tx_id))]);
+        assert!(result.errors[0].starts_with("output 0 collides with utxo"));
+
+        // nothing is spent or added, the colliding output is kept
+        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        assert!(utxo_storage
+            .search_key(&genesis_key, IOType::Coin as usize)
+            .unwrap());
+        assert!(!utxo_storage
+            .search_key(&output_key, IOType::Coin as usize)
+            .unwrap());
+        assert_eq!(
+            utxo_storage
+                .get_utxo_by_id(output_key, IOType::Memo as usize)
+                .unwrap(),
+            genesis.value
+        );
+    }
+
+    // cargo test -- --nocapture --test independent_state_test --test-threads 1
+    #[test]
+    fn independent_state_test() {
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+
+        let (genesis, chain) = chained_block_transactions();
+        let first = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        let second = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        for state in [&first, &second] {
+            state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    utxo_key(&genesis.utx),
+                    genesis.value.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+        }
+        let block = |transactions: Vec<TransactionMessage>| Block {
+            block_hash: "independent".to_string(),
+            block_height: 800_801,
+            transactions,
+            ..Default::default()
+        };
+        let output_key = |tx: &TransactionMessage| {
+            let tx_id: [u8; 32] = hex::decode(&tx.tx_id).unwrap().try_into().unwrap();
+            utxo_key(&Utxo::from_hash(Hash(tx_id), 0))
+        };
+
+        // the same height is applied to each state, with different txs
+        assert_eq!(
+            first.process_block(block(chain.clone())).suceess_tx.len(),
+            3
+        );
+        assert_eq!(
+            second
+                .process_block(block(vec![chain[0].clone()]))
+                .suceess_tx
+                .len(),
+            1
+        );
+        assert!(first
+            .process_block(block(chain.clone()))
+            .suceess_tx
+            .is_empty());
+
+        let mut first_storage = first.utxo_storage.lock().unwrap();
+        let mut second_storage = second.utxo_storage.lock().unwrap();
+        assert!(first_storage
+            .search_key(&output_key(&chain[2]), IOType::Coin as usize)
+            .unwrap());
+        assert!(!second_storage
+            .search_key(&output_key(&chain[2]), IOType::Coin as usize)
+            .unwrap());
+        assert!(second_storage
+            .search_key(&output_key(&chain[0]), IOType::Coin as usize)
+            .unwrap());
+        drop((first_storage, second_storage));
+
+        let applied = |state: &UtxoState| state.block_apply_result(800_801).unwrap().applied_txids;
+        assert_eq!((applied(&first).len(), applied(&second).len()), (3, 1));
+        assert_eq!(first.metrics.script_tx_count.get(), 3.0);
+        assert_eq!(second.metrics.script_tx_count.get(), 1.0);
+        // the global state did not see either block
+        assert!(!UTXO_STORAGE
+            .lock()
+            .unwrap()
+            .search_key(&output_key(&chain[0]), IOType::Coin as usize)
+            .unwrap());
+    }
+
+    // cargo test -- --nocapture --test network_isolation_test --test-threads 1
+    #[test]
+    fn network_isolation_test() {
+        use crate::blockoperations::blockheader::BlockHeader;
+        use crate::network::{NetworkError, NetworkStates};
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use address::{Address, Network};
+        use std::sync::Arc;
+        use zkvm::zkos_types::OutputCoin;
+
+        // one key, with a coin on each network
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(20u64));
+        let (pk, encryption) = account.get_account();
+        let address = |network: Network| Address::standard_address(network, pk);
+        let coin = |network: Network| {
+            Output::coin(OutputData::coin(OutputCoin::new(
+                encryption.clone(),
+                address(network).as_hex(),
+            )))
+        };
+        let mainnet = Arc::new(UtxoState::with_store(Arc::new(MemoryStore::new())));
+        assert_eq!(mainnet.network(), Network::Mainnet);
+        let testnet = Arc::new(UtxoState::secondary(
+            Network::Testnet,
+            Arc::new(MemoryStore::new()),
+        ));
+        let mut created = Vec::new();
+        for (state, height) in [(&mainnet, 800_900u64), (&testnet, 7)] {
+            let mut id = [0u8; 32];
+            rand::thread_rng().fill(&mut id);
+            let genesis = RecordUtxo {
+                utx: Utxo::from_hash(Hash(id), 0),
+                value: coin(state.network()),
+            };
+            state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    utxo_key(&genesis.utx),
+                    genesis.value.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+            id[0] ^= 0xff;
+            let (tx, output) = transfer_message(id, genesis, coin(state.network()));
+            let block = Block {
+                block_hash: format!("{}-{}", state.network(), height),
+                block_height: height,
+                transactions: vec![tx],
+                ..Default::default()
+            };
+            assert_eq!(state.process_block(block).suceess_tx.len(), 1);
+            state
+                .track_block_header(BlockHeader {
+                    height,
+                    block_hash: format!("{}-{}", state.network(), height),
+                    ..Default::default()
+                })
+                .unwrap();
+            created.push(output.utx);
+        }
+
+        // each network sees its own utxos, height and last header
+        let view = |state: &UtxoState| state.utxo_storage.lock().unwrap().read_view();
+        let (mainnet_view, testnet_view) = (view(&mainnet), view(&testnet));
+        assert_eq!(
+            (mainnet_view.block_height, testnet_view.block_height),
+            (800_900, 7)
+        );
+        let standard = address::Standard::from_hex(&address(Network::Mainnet).as_hex());
+        assert_eq!(
+            utxos_by_address(&mainnet_view, &standard, IOType::Coin),
+            vec![created[0]]
+        );
+        assert_eq!(
+            utxos_by_address(&testnet_view, &standard, IOType::Coin),
+            vec![created[1]]
+        );
+        let header = |state: &UtxoState| state.last_block_header.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            (header(&mainnet).height, header(&testnet).height),
+            (800_900, 7)
+        );
+
+        // an address is looked up on its own network only
+        let states = NetworkStates::new(mainnet.clone()).with_state(testnet.clone());
+        let testnet_address = address(Network::Testnet).as_hex();
+        assert!(Arc::ptr_eq(
+            &states
+                .state_for_address(Some(Network::Testnet), &testnet_address)
+                .unwrap(),
+            &testnet
+        ));
+        assert_eq!(
+            states.state_for_address(None, &testnet_address).err(),
+            Some(NetworkError::Mismatch {
+                address_network: Network::Testnet,
+                network: Network::Mainnet
+            })
+        );
+
+        // the metrics of a state carry its network
+        assert_eq!(testnet.metrics.script_tx_count.get(), 1.0);
+        for family in testnet.metrics.registry().gather() {
+            for metric in family.get_metric() {
+                assert!(metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == "network" && label.get_value() == "testnet"));
+            }
+        }
+    }
+
+    // cargo test -- --nocapture --test secondary_network_store_test --test-threads 1
+    #[test]
+    fn secondary_network_store_test() {
+        use crate::blockoperations::balanceindexer::{BalanceIndexer, BSGS_TABLE};
+        use crate::pgsql::{MemoryStore, PersistenceStore};
+        use crate::state::UtxoState;
+        use address::{Address, Network};
+        use quisquislib::elgamal::ElGamalCommitment;
+        use quisquislib::keys::{PublicKey, SecretKey};
+        use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        use zkvm::zkos_types::OutputCoin;
+
+        // one key, paid a coin of 12 on each network
+        let mut rng = rand::thread_rng();
+        let scalar = Scalar::random(&mut rng);
+        let sk: RistrettoSecretKey = SecretKey::from_bytes(scalar.as_bytes());
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let address = |network: Network| Address::standard_address(network, pk).as_hex();
+        let coin = |network: Network| {
+            let encrypt = ElGamalCommitment::generate_commitment(
+                &pk,
+                Scalar::random(&mut rand::thread_rng()),
+                Scalar::from(12u64),
+            );
+            Output::coin(OutputData::coin(OutputCoin::new(encrypt, address(network))))
+        };
+        let wait_for = |what: &str, done: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(30);
+            while !done() {
+                assert!(Instant::now() < deadline, "{} timed out", what);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        lazy_static::initialize(&BSGS_TABLE);
+
+        // both networks apply a block at the same height
+        let mainnet_store = Arc::new(MemoryStore::new());
+        let testnet_store = Arc::new(MemoryStore::new());
+        let mainnet = UtxoState::with_store(mainnet_store.clone());
+        let testnet = UtxoState::secondary(Network::Testnet, testnet_store.clone());
+        let mut created = Vec::new();
+        for state in [&mainnet, &testnet] {
+            *state.balance_indexer.lock().unwrap() =
+                BalanceIndexer::new(vec![(address(state.network()), scalar)], 10);
+            let mut id = [0u8; 32];
+            rand::thread_rng().fill(&mut id);
+            let genesis = RecordUtxo {
+                utx: Utxo::from_hash(Hash(id), 0),
+                value: coin(state.network()),
+            };
+            state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    utxo_key(&genesis.utx),
+                    genesis.value.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+            id[0] ^= 0xff;
+            let (tx, output) = transfer_message(id, genesis, coin(state.network()));
+            let block = Block {
+                block_hash: format!("{}-store", state.network()),
+                block_height: 800_950,
+                transactions: vec![tx],
+                ..Default::default()
+            };
+            assert_eq!(state.process_block(block).suceess_tx.len(), 1);
+            created.push(output.utx);
+        }
+
+        // the stats and the indexed balances of the height are kept by network
+        for state in [&mainnet, &testnet] {
+            let stats = state.block_stats.lock().unwrap().latest(2);
+            assert_eq!(stats.len(), 1);
+            assert_eq!(stats[0].block_height, 800_950);
+        }
+        let balance = |state: &UtxoState, network: Network| {
+            let indexer = state.balance_indexer.lock().unwrap();
+            indexer
+                .balance(&address(network))
+                .map(|balance| balance.balance)
+        };
+        wait_for("indexing", &|| {
+            balance(&mainnet, Network::Mainnet) == Some(12)
+                && balance(&testnet, Network::Testnet) == Some(12)
+        });
+        assert_eq!(balance(&mainnet, Network::Testnet), None);
+        assert_eq!(balance(&testnet, Network::Mainnet), None);
+
+        // a restarted secondary is loaded back from its own store
+        wait_for("persistence", &|| {
+            let tables = testnet_store.tables();
+            tables.block_results.len() == 1
+                && tables.utxos.len() == 1
+                && !tables.balance_updates.is_empty()
+        });
+        let restarted = UtxoState::secondary(Network::Testnet, testnet_store.clone());
+        restarted.load_stored(testnet_store.load_state().unwrap());
+        let view = restarted.utxo_storage.lock().unwrap().read_view();
+        assert_eq!(view.block_height, 800_950);
+        let standard = address::Standard::from_hex(&address(Network::Mainnet));
+        assert_eq!(
+            utxos_by_address(&view, &standard, IOType::Coin),
+            vec![created[1]]
+        );
+        assert!(restarted
+            .block_results
+            .lock()
+            .unwrap()
+            .get(800_950)
+            .is_some());
+        assert_eq!(restarted.metrics.utxo_coin_count.get(), 1.0);
+        let mut indexer = BalanceIndexer::new(vec![(address(Network::Testnet), scalar)], 10);
+        let (balances, utxos) = testnet_store.load_indexed_balances().unwrap();
+        indexer.load(balances, utxos);
+        assert_eq!(
+            indexer.balance(&address(Network::Testnet)).unwrap().balance,
+            12
+        );
+
+        // the store of the secondary holds nothing of the primary network
+        let mainnet_key = utxo_key(&created[0]);
+        assert!(!testnet_store.tables().utxos.contains_key(&mainnet_key));
+        wait_for("primary persistence", &|| {
+            mainnet_store.tables().utxos.contains_key(&mainnet_key)
+        });
+    }
+
+    // cargo test -- --nocapture --test reapplied_height_rollback_test --test-threads 1
+    #[test]
+    fn reapplied_height_rollback_test() {
+        use crate::blockoperations::balanceindexer::{BalanceIndexer, BSGS_TABLE};
+        use crate::blockoperations::nullifiers::input_nullifiers;
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use address::{Address, Network};
+        use quisquislib::elgamal::ElGamalCommitment;
+        use quisquislib::keys::{PublicKey, SecretKey};
+        use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        use zkvm::zkos_types::OutputCoin;
+
+        let mut rng = rand::thread_rng();
+        let scalar = Scalar::random(&mut rng);
+        let sk: RistrettoSecretKey = SecretKey::from_bytes(scalar.as_bytes());
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        let address = Address::standard_address(Network::Mainnet, pk).as_hex();
+        let coin = || {
+            let encrypt = ElGamalCommitment::generate_commitment(
+                &pk,
+                Scalar::random(&mut rand::thread_rng()),
+                Scalar::from(12u64),
+            );
+            Output::coin(OutputData::coin(OutputCoin::new(encrypt, address.clone())))
+        };
+        let wait_for = |what: &str, done: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(30);
+            while !done() {
+                assert!(Instant::now() < deadline, "{} timed out", what);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        lazy_static::initialize(&BSGS_TABLE);
+
+        let store = Arc::new(MemoryStore::new());
+        let state = UtxoState::with_store(store.clone());
+        *state.balance_indexer.lock().unwrap() =
+            BalanceIndexer::new(vec![(address.clone(), scalar)], 10);
+        let mut id = [0u8; 32];
+        rand::thread_rng().fill(&mut id);
+        let genesis = RecordUtxo {
+            utx: Utxo::from_hash(Hash(id), 0),
+            value: coin(),
+        };
+        state
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .add(
+                utxo_key(&genesis.utx),
+                genesis.value.clone(),
+                IOType::Coin as usize,
+            )
+            .unwrap();
+        id[0] ^= 0xff;
+        let (tx, _) = transfer_message(id, genesis, coin());
+        let nullifiers = {
+            let bytes = hex::decode(tx.tx_byte_code.as_ref().unwrap()).unwrap();
+            input_nullifiers(&Transaction::from_bytes(&bytes).unwrap().get_tx_inputs())
+        };
+        let result = state.process_block(Block {
+            block_hash: "replaced".to_string(),
+            block_height: 800_990,
+            transactions: vec![tx],
+            ..Default::default()
+        });
+        assert_eq!(result.suceess_tx.len(), 1);
+        let spent = |state: &UtxoState| {
+            let index = state.nullifiers.lock().unwrap();
+            index.conflict("other", &nullifiers).is_some()
+        };
+        assert!(spent(&state));
+        let balance = |state: &UtxoState| {
+            let indexer = state.balance_indexer.lock().unwrap();
+            indexer.balance(&address).map(|balance| balance.balance)
+        };
+        wait_for("indexing", &|| balance(&state) == Some(12));
+        wait_for("persistence", &|| {
+            !store.tables().block_nullifiers.is_empty()
+        });
+
+        // another block at the same height replaces it, its spends and balances are undone
+        state.process_block(Block {
+            block_hash: "replacing".to_string(),
+            block_height: 800_990,
+            ..Default::default()
+        });
+        assert!(!spent(&state));
+        wait_for("rollback", &|| balance(&state) == Some(0));
+        wait_for("nullifier deletion", &|| {
+            store.tables().block_nullifiers.is_empty()
+        });
+    }
+
+    // cargo test -- --nocapture --test block_weight_limit_test --test-threads 1
+    #[test]
+    fn block_weight_limit_test() {
+        use crate::blockoperations::blockweight::block_weight;
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+
+        let (genesis, chain) = chained_block_transactions();
+        let block = |height: u64, transactions: Vec<TransactionMessage>| Block {
+            block_hash: format!("weight-{}", height),
+            block_height: height,
+            transactions,
+            ..Default::default()
+        };
+        let state = |limit: u64| {
+            let state = UtxoState::with_store(Arc::new(MemoryStore::new()));
+            state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    utxo_key(&genesis.utx),
+                    genesis.value.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+            state.set_max_block_weight(limit);
+            state
+        };
+        let weight = block_weight(&block(800_901, chain.clone()));
+        assert!(weight > 0);
+
+        // a block of exactly the limit is applied
+        let at_limit = state(weight);
+        let result = at_limit.process_block(block(800_901, chain.clone()));
+        assert_eq!(result.suceess_tx.len(), 3);
+        let applied = at_limit.block_apply_result(800_901).unwrap();
+        assert_eq!((applied.weight, applied.weight_limit), (weight, weight));
+        assert!(!applied.exceeds_weight_limit());
+        assert!(at_limit.weight_halt().is_none());
+
+        // one unit over it is rejected and halts the state
+        let over_limit = state(weight - 1);
+        let result = over_limit.process_block(block(800_901, chain.clone()));
+        assert_eq!(result, BlockResult::new());
+        let rejected = over_limit.block_apply_result(800_901).unwrap();
+        assert!(rejected.exceeds_weight_limit());
+        assert!(rejected.applied_txids.is_empty());
+        let violation = over_limit.weight_halt().unwrap();
+        assert_eq!(
+            (violation.height, violation.weight, violation.limit),
+            (800_901, weight, weight - 1)
+        );
+        assert_eq!(over_limit.metrics.block_weight_violations.get(), 1.0);
+        assert_eq!(
+            over_limit.metrics.max_block_weight.get(),
+            (weight - 1) as f64
+        );
+        // the genesis coin is not spent
+        assert!(over_limit
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .search_key(&utxo_key(&genesis.utx), IOType::Coin as usize)
+            .unwrap());
+
+        // a light block is held while halted
+        assert!(over_limit
+            .process_block(block(800_902, Vec::new()))
+            .suceess_tx
+            .is_empty());
+        assert!(over_limit.block_apply_result(800_902).is_none());
+
+        // resuming under the same limit halts again on the rejected block
+        let resume = over_limit.resume_block_processing().unwrap();
+        assert_eq!(resume.resumed, violation);
+        assert!(resume.applied_heights.is_empty());
+        assert_eq!(resume.halt, Some(violation.clone()));
+        assert_eq!(over_limit.halted_blocks.lock().unwrap().len(), 2);
+
+        // the operator raises the limit and resumes, the held blocks are applied in order
+        over_limit.set_max_block_weight(weight);
+        let resume = over_limit.resume_block_processing().unwrap();
+        assert_eq!(resume.applied_heights, vec![800_901, 800_902]);
+        assert_eq!(resume.halt, None);
+        assert!(over_limit.halted_blocks.lock().unwrap().is_empty());
+        assert!(!over_limit
+            .block_apply_result(800_901)
+            .unwrap()
+            .exceeds_weight_limit());
+        assert!(over_limit.block_apply_result(800_902).is_some());
+        assert!(!over_limit
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .search_key(&utxo_key(&genesis.utx), IOType::Coin as usize)
+            .unwrap());
+        assert_eq!(over_limit.resume_block_processing(), None);
+    }
+
+    // cargo test -- --nocapture --test zero_balance_input_block_test --test-threads 1
+    #[test]
+    fn zero_balance_input_block_test() {
+        use address::Network;
+        use quisquislib::elgamal::ElGamalCommitment;
+        use quisquislib::keys::PublicKey;
+        use quisquislib::ristretto::RistrettoPublicKey;
+        use transaction::{Receiver, Sender, TransferTransaction};
+
+        // dark transfer of 500 from a coin of the utxo set to a fresh account holding `balance`
+        let transfer_message = |balance: u64| {
+            let mut rng = rand::thread_rng();
+            let (bob_account, bob_sk) = Account::generate_random_account_with_value(500u64.into());
+            let mut seed = [0u8; 32];
+            rng.fill(&mut seed);
+            let bob_utxo = Utxo::from_hash(Hash(seed), 0);
+            let bob_output = Output::from_quisquis_account(bob_account, Network::default());
+            let _ = UTXO_STORAGE.lock().unwrap().add(
+                utxo_key(&bob_utxo),
+                bob_output.clone(),
+                IOType::Coin as usize,
+            );
+
+            let (bob_pk, _) = bob_account.get_account();
+            let alice_pk = RistrettoPublicKey::update_public_key(&bob_pk, Scalar::random(&mut rng));
+            let alice_comm_scalar = Scalar::random(&mut rng);
+            let alice_commitment = ElGamalCommitment::generate_commitment(
+                &alice_pk,
+                alice_comm_scalar,
+                Scalar::from(balance),
+            );
+            let alice_account = Account::set_account(alice_pk, alice_commitment);
+            let alice_reciever = Receiver::set_receiver(500, alice_account);
+            let bob_sender = Sender::set_sender(-500, bob_account, vec![alice_reciever]);
+            let (value_vector, account_vector, sender_count, receiver_count) =
+                Sender::generate_value_and_account_vector(vec![bob_sender]).unwrap();
+            let inputs = vec![
+                bob_output.as_out_coin().unwrap().to_input(bob_utxo, 0),
+                Input::input_from_quisquis_account(
+                    &alice_account,
+                    Utxo::default(),
+                    0,
+                    Network::default(),
+                ),
+            ];
+            let (transfer, _) = TransferTransaction::create_private_transfer_transaction(
+                &value_vector,
+                &account_vector,
+                &[0],
+                &[500],
+                &inputs,
+                &[bob_sk],
+                sender_count,
+                receiver_count,
+                Some(&[alice_comm_scalar]),
+                0u64,
+            )
+            .unwrap();
+            let tx =
+                Transaction::transaction_transfer(TransactionData::TransactionTransfer(transfer));
+            let mut tx_id = [0u8; 32];
+            rng.fill(&mut tx_id);
+            TransactionMessage {
+                tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+                tx_id: hex::encode(tx_id),
+                tx_byte_code: Some(hex::encode(bincode::serialize(&tx).unwrap())),
+                zk_oracle_address: None,
+                mint_or_burn: None,
+                btc_value: None,
+                qq_account: None,
+                encrypt_scalar: None,
+                twilight_address: None,
+            }
+        };
+
+        let fresh = transfer_message(0);
+        // an account holding 100 passed off as a fresh receiver
+        let smuggled = transfer_message(100);
+        let block = Block {
+            block_hash: "zero-balance".to_string(),
+            block_height: 800_951,
+            transactions: vec![fresh.clone(), smuggled.clone()],
+            ..Default::default()
+        };
+        let result = process_block_for_utxo_insert(block);
+        let txid = |message: &TransactionMessage| {
+            TxID(Hash(
+                hex::decode(&message.tx_id).unwrap().try_into().unwrap(),
+            ))
+        };
+        assert_eq!(result.suceess_tx, vec![txid(&fresh)]);
+        assert_eq!(result.failed_tx, vec![txid(&smuggled)]);
+        assert_eq!(
+            result.errors,
+            vec!["Tx Verification failed. Zero balance proof is not valid."]
+        );
+    }
+
+    // cargo test -- --nocapture --test script_reference_block_test --test-threads 1
+    #[test]
+    fn script_reference_block_test() {
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+        use transaction::reference_tx::{
+            create_script_reference_transaction, create_settlement_reference_transaction_with,
+            reference_tx_id,
+        };
+
+        let order = create_script_reference_transaction();
+        let settlement = create_settlement_reference_transaction_with(&order, 9000).unwrap();
+        let state = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        for coin in order.funding.iter() {
+            state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    utxo_key(&coin.utx),
+                    coin.value.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+        }
+        let message = |tx: &Transaction| tx_message(reference_tx_id(tx), tx);
+        let block = |height: u64, transactions: Vec<TransactionMessage>| Block {
+            block_hash: format!("script-reference-{}", height),
+            block_height: height,
+            transactions,
+            ..Default::default()
+        };
+
+        // the pool is deployed and the order opened, then the order is settled
+        let result = state.process_block(block(
+            800_961,
+            vec![message(&order.pool_deploy), message(&order.tx)],
+        ));
+        assert_eq!(result.suceess_tx.len(), 2, "{:?}", result.errors);
+        let result = state.process_block(block(800_962, vec![message(&settlement.tx)]));
+        assert_eq!(result.suceess_tx.len(), 1, "{:?}", result.errors);
+
+        let mut storage = state.utxo_storage.lock().unwrap();
+        assert!(!storage
+            .search_key(&utxo_key(&order.memo_utxo), IOType::Memo as usize)
+            .unwrap());
+        assert!(!storage
+            .search_key(&utxo_key(&order.pool_utxo), IOType::State as usize)
+            .unwrap());
+        assert!(storage
+            .search_key(&utxo_key(&settlement.pool_utxo), IOType::State as usize)
+            .unwrap());
+        let payout = Utxo::from_hash(Hash(reference_tx_id(&settlement.tx)), 0);
+        assert!(storage
+            .search_key(&utxo_key(&payout), IOType::Coin as usize)
+            .unwrap());
+    }
+
+    // the lookups follow a utxo whose owner is replaced by a script output
+    #[test]
+    fn owner_change_address_lookup_test() {
+        use crate::blockoperations::blockprocessing::utxos_by_address;
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+        use transaction::reference_tx::{
+            create_script_reference_transaction, create_settlement_reference_transaction_with,
+            reference_tx_id,
+        };
+
+        let order = create_script_reference_transaction();
+        let settlement = create_settlement_reference_transaction_with(&order, 9000).unwrap();
+        let state = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        for coin in order.funding.iter() {
+            state
+                .utxo_storage
+                .lock()
+                .unwrap()
+                .add(
+                    utxo_key(&coin.utx),
+                    coin.value.clone(),
+                    IOType::Coin as usize,
+                )
+                .unwrap();
+        }
+        let message = |tx: &Transaction| tx_message(reference_tx_id(tx), tx);
+        let block = |height: u64, transactions: Vec<TransactionMessage>| Block {
+            block_hash: format!("owner-change-{}", height),
+            block_height: height,
+            transactions,
+            ..Default::default()
+        };
+        let result = state.process_block(block(
+            800_971,
+            vec![message(&order.pool_deploy), message(&order.tx)],
+        ));
+        assert_eq!(result.suceess_tx.len(), 2, "{:?}", result.errors);
+
+        // another memo of the trader, untouched by the settlement
+        let mut storage = state.utxo_storage.lock().unwrap();
+        let memo = storage
+            .get_utxo_by_id(utxo_key(&order.memo_utxo), IOType::Memo as usize)
+            .unwrap();
+        let trader = address::Standard::from_hex(memo.output.get_owner_address().unwrap());
+        let other_memo = Utxo::random();
+        storage
+            .add(utxo_key(&other_memo), memo.clone(), IOType::Memo as usize)
+            .unwrap();
+        let view = storage.read_view();
+        let memos = utxos_by_address(&view, &trader, IOType::Memo);
+        assert_eq!(memos.len(), 2);
+        assert!(memos.contains(&order.memo_utxo));
+        drop(storage);
+
+        // the settlement spends the memo of the trader into a coin of the trader
+        let result = state.process_block(block(800_972, vec![message(&settlement.tx)]));
+        assert_eq!(result.suceess_tx.len(), 1, "{:?}", result.errors);
+        let view = state.utxo_storage.lock().unwrap().read_view();
+        assert_eq!(
+            utxos_by_address(&view, &trader, IOType::Memo),
+            vec![other_memo]
+        );
+        let payout = Utxo::from_hash(Hash(reference_tx_id(&settlement.tx)), 0);
+        assert!(utxos_by_address(&view, &trader, IOType::Coin).contains(&payout));
+    }
+
+    // utxos of one owner added and removed while the lookups read the utxo set
+    #[test]
+    fn address_lookup_interleaved_test() {
+        use crate::blockoperations::blockprocessing::utxos_by_address;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        let (genesis, _) = chained_block_transactions();
+        let owner = address::Standard::from_hex(genesis.value.output.get_owner_address().unwrap());
+        let storage = Arc::new(Mutex::new(LocalStorage::<Output>::new(3)));
+        let stable: Vec<Utxo> = (0..4).map(|_| Utxo::random()).collect();
+        for utxo in stable.iter() {
+            storage
+                .lock()
+                .unwrap()
+                .add(utxo_key(utxo), genesis.value.clone(), IOType::Coin as usize)
+                .unwrap();
+        }
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = storage.clone();
+                let done = done.clone();
+                let stable = stable.clone();
+                std::thread::spawn(move || {
+                    while !done.load(Ordering::SeqCst) {
+                        let view = storage.lock().unwrap().read_view();
+                        let utxos = utxos_by_address(&view, &owner, IOType::Coin);
+                        assert!(stable.iter().all(|utxo| utxos.contains(utxo)));
+                    }
+                })
+            })
+            .collect();
+
+        // each writer keeps one of every two utxos it adds
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = storage.clone();
+                let output = genesis.value.clone();
+                std::thread::spawn(move || {
+                    let mut kept = Vec::new();
+                    for i in 0..100 {
+                        let utxo = Utxo::random();
+                        storage
+                            .lock()
+                            .unwrap()
+                            .add(utxo_key(&utxo), output.clone(), IOType::Coin as usize)
+                            .unwrap();
+                        std::thread::yield_now();
+                        if i % 2 == 0 {
+                            storage
+                                .lock()
+                                .unwrap()
+                                .remove(utxo_key(&utxo), IOType::Coin as usize)
+                                .unwrap();
+                        } else {
+                            kept.push(utxo);
+                        }
+                    }
+                    kept
+                })
+            })
+            .collect();
+        let mut expected = stable.clone();
+        for writer in writers {
+            expected.extend(writer.join().unwrap());
+        }
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let view = storage.lock().unwrap().read_view();
+        let mut utxos = utxos_by_address(&view, &owner, IOType::Coin);
+        assert_eq!(utxos.len(), stable.len() + 4 * 50);
+        utxos.sort_by_key(utxo_key);
+        expected.sort_by_key(utxo_key);
+        assert_eq!(utxos, expected);
+    }
+
+    // cargo test -- --nocapture --test tx_status_block_test --test-threads 1
+    #[test]
+    fn tx_status_block_test() {
+        use crate::blockoperations::txstatus::TxStatus;
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+
+        let (genesis, chain) = chained_block_transactions();
+        // spends the genesis coin of another chain, not in the utxo set
+        let (_, missing) = chained_block_transactions();
+        let store = Arc::new(MemoryStore::new());
+        let state = UtxoState::with_store(store.clone());
+        state
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .add(
+                utxo_key(&genesis.utx),
+                genesis.value.clone(),
+                IOType::Coin as usize,
+            )
+            .unwrap();
+        for tx in [&chain[0], &missing[0]] {
+            state.record_tx_status(&tx.tx_id, TxStatus::Received);
+            state.record_tx_status(&tx.tx_id, TxStatus::Queued);
+        }
+
+        let result = state.process_block(Block {
+            block_hash: "tx_status".to_string(),
+            block_height: 800_961,
+            transactions: vec![missing[0].clone(), chain[0].clone(), chain[1].clone()],
+            ..Default::default()
+        });
+        assert_eq!(result.suceess_tx.len(), 2);
+
+        // confirmed at the position of the tx in the block
+        let statuses = |txid: &str| -> Vec<TxStatus> {
+            state
+                .tx_status_history(txid)
+                .into_iter()
+                .map(|record| record.status)
+                .collect()
+        };
+        let confirmed = |block_index: u32| TxStatus::Confirmed {
+            height: 800_961,
+            block_index,
+        };
+        assert_eq!(
+            statuses(&chain[0].tx_id),
+            vec![TxStatus::Received, TxStatus::Queued, confirmed(1)]
+        );
+        assert_eq!(statuses(&chain[1].tx_id), vec![confirmed(2)]);
+        // failed with the reason of the block result
+        let failed = TxStatus::Failed {
+            reason: "Error::Tx inputs are not in the utxo set".to_string(),
+        };
+        assert_eq!(
+            statuses(&missing[0].tx_id),
+            vec![TxStatus::Received, TxStatus::Queued, failed.clone()]
+        );
+        let view = state.tx_status(&missing[0].tx_id);
+        assert_eq!(view.status, failed);
+        assert_eq!(view.legacy_status, "failed");
+        assert_eq!(state.tx_status(&chain[2].tx_id).status, TxStatus::Unknown);
+
+        // the transitions are written to the store
+        let mut written = 0;
+        for _ in 0..100 {
+            written = store.tables().tx_statuses.len();
+            if written == 7 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(written, 7);
+        state.tx_statuses.lock().unwrap().retention = 0;
+        state.record_tx_status("evicted", TxStatus::Received);
+        assert_eq!(statuses(&chain[1].tx_id), vec![confirmed(2)]);
+    }
+
+    #[test]
+    fn nullifier_block_test() {
+        use crate::blockoperations::mempool::Mempool;
+        use crate::blockoperations::nullifiers::{input_nullifiers, NullifierConflict};
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use std::sync::Arc;
+
+        let (genesis, chain) = chained_block_transactions();
+        // another tx spending the genesis coin
+        let (double_spend, _) = chained_transfer_message([7u8; 32], genesis.clone());
+        let inputs = |message: &TransactionMessage| {
+            let bytes = hex::decode(message.tx_byte_code.as_ref().unwrap()).unwrap();
+            Transaction::from_bytes(&bytes).unwrap().get_tx_inputs()
+        };
+        let nullifiers = input_nullifiers(&inputs(&double_spend));
+        assert_eq!(nullifiers, input_nullifiers(&inputs(&chain[0])));
+
+        let store = Arc::new(MemoryStore::new());
+        let state = UtxoState::with_store(store.clone());
+        state
+            .utxo_storage
+            .lock()
+            .unwrap()
+            .add(
+                utxo_key(&genesis.utx),
+                genesis.value.clone(),
+                IOType::Coin as usize,
+            )
+            .unwrap();
+        // the double spend is pending when the block spends its coin
+        *state.mempool.lock().unwrap() = Mempool::new(std::time::Duration::from_secs(60));
+        state.mempool.lock().unwrap().insert(
+            &double_spend.tx_id,
+            &inputs(&double_spend),
+            1.0,
+            std::time::Instant::now(),
+        );
+
+        let result = state.process_block(Block {
+            block_hash: "nullifiers".to_string(),
+            block_height: 800_971,
+            transactions: vec![chain[0].clone()],
+            ..Default::default()
+        });
+        assert_eq!(result.suceess_tx.len(), 1);
+        assert!(state.mempool.lock().unwrap().is_empty());
+        let spent = NullifierConflict {
+            txid: chain[0].tx_id.clone(),
+            height: 800_971,
+        };
+        let conflict = state
+            .nullifiers
+            .lock()
+            .unwrap()
+            .conflict(&double_spend.tx_id, &nullifiers);
+        assert_eq!(conflict, Some(spent.clone()));
+
+        // a later block spending the coin again is rejected with the first spender
+        let result = state.process_block(Block {
+            block_hash: "double_spend".to_string(),
+            block_height: 800_972,
+            transactions: vec![double_spend.clone()],
+            ..Default::default()
+        });
+        assert_eq!(result.failed_tx.len(), 1);
+        assert_eq!(result.errors[0], format!("Error::Tx {}", spent));
+
+        // the nullifiers of the block are written, and dropped on a reorg below it
+        let written = || {
+            for _ in 0..100 {
+                if !store.tables().block_nullifiers.is_empty() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+            store.tables().block_nullifiers
+        };
+        assert_eq!(written()[0].height, 800_971);
+        assert_eq!(state.rollback_nullifiers(800_970), 1);
+        let conflict = state
+            .nullifiers
+            .lock()
+            .unwrap()
+            .conflict(&double_spend.tx_id, &nullifiers);
+        assert_eq!(conflict, None);
+        for _ in 0..100 {
+            if store.tables().block_nullifiers.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(store.tables().block_nullifiers.is_empty());
+    }
+
+    // cargo test -- --nocapture --test spent_decoy_block_test --test-threads 1
+    #[test]
+    fn spent_decoy_block_test() {
+        use crate::pgsql::MemoryStore;
+        use crate::state::UtxoState;
+        use address::Network;
+        use std::sync::Arc;
+        use transaction::{QuisQuisConfig, Receiver, Sender, TransferTransaction};
+
+        // quisquis transfer of 500 from bob to alice, every account a coin of the utxo set.
+        // the inputs are arranged as [bob, alice, decoys..]
+        let mut rng = rand::thread_rng();
+        let config = QuisQuisConfig::default();
+        let (bob_account, bob_sk) = Account::generate_random_account_with_value(1000u64.into());
+        let (alice_account, _) = Account::generate_random_account_with_value(0u64.into());
+        let alice = Receiver::set_receiver(500, alice_account);
+        let bob = Sender::set_sender(-500, bob_account, vec![alice]);
+        let (mut value_vector, mut account_vector, senders_count, receivers_count) =
+            Sender::generate_value_and_account_vector_for(vec![bob], &config).unwrap();
+        let (anonymity_accounts, _) =
+            Sender::create_anonymity_set(senders_count, receivers_count, &config);
+        let diff = anonymity_accounts.len();
+        value_vector.extend(vec![0; diff]);
+        account_vector.extend(anonymity_accounts);
+        let coins: Vec<(Utxo, Output)> = account_vector
+            .iter()
+            .map(|account| {
+                let mut seed = [0u8; 32];
+                rng.fill(&mut seed);
+                let output = Output::from_quisquis_account(*account, Network::default());
+                (Utxo::from_hash(Hash(seed), 0), output)
+            })
+            .collect();
+        let inputs: Vec<Input> = coins
+            .iter()
+            .map(|(utxo, output)| output.as_out_coin().unwrap().to_input(*utxo, 0))
+            .collect();
+        let transfer = TransferTransaction::create_quisquis_transaction(
+            &inputs,
+            &value_vector,
+            &account_vector,
+            &[500],
+            &[500],
+            &[bob_sk],
+            senders_count,
+            receivers_count,
+            diff,
+            None,
+            0u64,
+            &config,
+        )
+        .unwrap();
+        let tx = Transaction::transaction_transfer(TransactionData::TransactionTransfer(transfer));
+        let mut tx_id = [0u8; 32];
+        rng.fill(&mut tx_id);
+        let block = Block {
+            block_hash: "spent-decoy".to_string(),
+            block_height: 800_981,
+            transactions: vec![tx_message(tx_id, &tx)],
+            ..Default::default()
+        };
+        // state holding the coins, the coin at `spent` was spent by an earlier block
+        let state = |spent: Option<usize>| {
+            let state = UtxoState::with_store(Arc::new(MemoryStore::new()));
+            let mut storage = state.utxo_storage.lock().unwrap();
+            for (utxo, output) in coins.iter() {
+                storage
+                    .add(utxo_key(utxo), output.clone(), IOType::Coin as usize)
+                    .unwrap();
+            }
+            if let Some(spent) = spent {
+                storage
+                    .remove(utxo_key(&coins[spent].0), IOType::Coin as usize)
+                    .unwrap();
+            }
+            drop(storage);
+            state
+        };
+        let unspent = |state: &UtxoState| {
+            let mut storage = state.utxo_storage.lock().unwrap();
+            coins
+                .iter()
+                .filter(|(utxo, _)| {
+                    storage
+                        .search_key(&utxo_key(utxo), IOType::Coin as usize)
+                        .unwrap()
+                })
+                .count()
+        };
+
+        // every input in the utxo set, the tx applies
+        let intact = state(None);
+        let result = intact.process_block(block.clone());
+        assert_eq!(result.suceess_tx, vec![TxID(Hash(tx_id))]);
+        assert_eq!(unspent(&intact), 0);
+
+        // a spent decoy fails the tx like a spent sender input, nothing is spent
+        for spent in [coins.len() - 1, 0] {
+            let state = state(Some(spent));
+            let result = state.process_block(block.clone());
+            assert_eq!(result.failed_tx, vec![TxID(Hash(tx_id))]);
+            assert_eq!(
+                result.errors,
+                vec!["Error::Tx inputs are not in the utxo set"]
+            );
+            assert_eq!(unspent(&state), coins.len() - 1);
+        }
+    }
 }
@@ -0,0 +1,289 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Results of applying a block to the utxo set.
+//!
+//! A result is stored in memory for the latest blocks and in the `block_results`
+//! psql table for every block, in the compact encoding below:
+//!
+//! ```ascii
+//! height || duration_ms || utxos_added || utxos_removed    u64-LE each
+//! n || txid_1 || ... || txid_n                             applied txs
+//! m || (txid || len || utf8 error) * m                     failed txs
+//! weight || weight_limit                                   u64-LE each
+//! ```
+//!
+//! `n`, `m` and `len` are u32-LE, txids are 32 bytes. Results of older nodes end
+//! after the failed txs, their weights decode as 0.
+
+use crate::state::global_state;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use zkvm::encoding::{
+    Decodable, Encodable, ReadError, Reader, ReaderExt, WriteError, Writer, WriterExt,
+};
+use zkvm::{Hash, TxID};
+
+/// Number of most recent blocks kept in memory.
+pub const BLOCK_APPLY_RESULT_RETENTION: usize = 1_000;
+
+lazy_static! {
+    /// Block results of the global state, see [`crate::state`].
+    pub static ref BLOCK_APPLY_RESULTS: Arc<Mutex<BlockApplyResultStore>> =
+        global_state().block_results.clone();
+}
+
+/// Outcome of applying the transactions of a block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockApplyResult {
+    pub height: u64,
+    pub applied_txids: Vec<TxID>,
+    /// Failed txs with the reason of the failure
+    pub failed: Vec<(TxID, String)>,
+    pub utxos_added: u64,
+    pub utxos_removed: u64,
+    pub duration_ms: u64,
+    /// Weight of the txs of the block, see [`crate::blockoperations::blockweight`]
+    #[serde(default)]
+    pub weight: u64,
+    /// Weight limit the block was applied under
+    #[serde(default)]
+    pub weight_limit: u64,
+}
+
+impl BlockApplyResult {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReadError> {
+        (&bytes[..]).read_all(|r| Self::decode(r))
+    }
+
+    /// Whether the block was rejected for its weight, its txs are not applied.
+    pub fn exceeds_weight_limit(&self) -> bool {
+        self.weight > self.weight_limit && self.weight_limit > 0
+    }
+}
+
+fn read_txid(r: &mut impl Reader) -> Result<TxID, ReadError> {
+    Ok(TxID(Hash(r.read_u8x32()?)))
+}
+
+impl Encodable for BlockApplyResult {
+    fn encode(&self, w: &mut impl Writer) -> Result<(), WriteError> {
+        w.write_u64(b"height", self.height)?;
+        w.write_u64(b"duration_ms", self.duration_ms)?;
+        w.write_u64(b"utxos_added", self.utxos_added)?;
+        w.write_u64(b"utxos_removed", self.utxos_removed)?;
+        w.write_size(b"n", self.applied_txids.len())?;
+        for txid in self.applied_txids.iter() {
+            w.write(b"txid", &(txid.0).0)?;
+        }
+        w.write_size(b"m", self.failed.len())?;
+        for (txid, error) in self.failed.iter() {
+            w.write(b"txid", &(txid.0).0)?;
+            w.write_size(b"len", error.len())?;
+            w.write(b"error", error.as_bytes())?;
+        }
+        w.write_u64(b"weight", self.weight)?;
+        w.write_u64(b"weight_limit", self.weight_limit)?;
+        Ok(())
+    }
+
+    fn encoded_size_hint(&self) -> Option<usize> {
+        let failed: usize = self.failed.iter().map(|(_, error)| 36 + error.len()).sum();
+        Some(56 + 32 * self.applied_txids.len() + failed)
+    }
+}
+
+impl Decodable for BlockApplyResult {
+    fn decode(r: &mut impl Reader) -> Result<Self, ReadError> {
+        let height = r.read_u64()?;
+        let duration_ms = r.read_u64()?;
+        let utxos_added = r.read_u64()?;
+        let utxos_removed = r.read_u64()?;
+        let n = r.read_size()?;
+        let applied_txids = r.read_vec(n, read_txid)?;
+        let m = r.read_size()?;
+        let failed = r.read_vec(m, |r| {
+            let txid = read_txid(r)?;
+            let len = r.read_size()?;
+            let error =
+                String::from_utf8(r.read_bytes(len)?).map_err(|_| ReadError::InvalidFormat)?;
+            Ok((txid, error))
+        })?;
+        let (weight, weight_limit) = if r.remaining_bytes() > 0 {
+            (r.read_u64()?, r.read_u64()?)
+        } else {
+            (0, 0)
+        };
+        Ok(BlockApplyResult {
+            height,
+            applied_txids,
+            failed,
+            utxos_added,
+            utxos_removed,
+            duration_ms,
+            weight,
+            weight_limit,
+        })
+    }
+}
+
+impl fmt::Display for BlockApplyResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "height={} applied={} failed={} utxos_added={} utxos_removed={} duration={}ms weight={}/{}",
+            self.height,
+            self.applied_txids.len(),
+            self.failed.len(),
+            self.utxos_added,
+            self.utxos_removed,
+            self.duration_ms,
+            self.weight,
+            self.weight_limit
+        )
+    }
+}
+
+/// In-memory store of the results of the latest blocks.
+#[derive(Debug, Clone)]
+pub struct BlockApplyResultStore {
+    pub results: BTreeMap<u64, BlockApplyResult>,
+    pub retention: usize,
+}
+
+impl BlockApplyResultStore {
+    pub fn new(retention: usize) -> Self {
+        BlockApplyResultStore {
+            results: BTreeMap::new(),
+            retention,
+        }
+    }
+
+    /// Adds the result of a block and drops the oldest blocks beyond the retention limit.
+    pub fn insert(&mut self, result: BlockApplyResult) {
+        self.results.insert(result.height, result);
+        while self.results.len() > self.retention {
+            let oldest = match self.results.keys().next() {
+                Some(height) => *height,
+                None => break,
+            };
+            self.results.remove(&oldest);
+        }
+    }
+
+    pub fn get(&self, height: u64) -> Option<&BlockApplyResult> {
+        self.results.get(&height)
+    }
+}
+
+/// Txids applied by the latest blocks, by height. A block re-processed after an
+/// interrupted run skips the txs recorded for its height.
+#[derive(Debug, Clone)]
+pub struct AppliedTxIndex {
+    pub heights: BTreeMap<u64, HashSet<[u8; 32]>>,
+    pub retention: usize,
+}
+
+impl AppliedTxIndex {
+    pub fn new(retention: usize) -> Self {
+        AppliedTxIndex {
+            heights: BTreeMap::new(),
+            retention,
+        }
+    }
+
+    /// Records `txid` as applied at `height` and drops the oldest heights beyond the
+    /// retention limit.
+    pub fn insert(&mut self, height: u64, txid: [u8; 32]) {
+        self.heights.entry(height).or_default().insert(txid);
+        while self.heights.len() > self.retention {
+            let oldest = match self.heights.keys().next() {
+                Some(height) => *height,
+                None => break,
+            };
+            self.heights.remove(&oldest);
+        }
+    }
+
+    pub fn contains(&self, height: u64, txid: &[u8; 32]) -> bool {
+        self.heights
+            .get(&height)
+            .map_or(false, |txids| txids.contains(txid))
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(height: u64) -> BlockApplyResult {
+        BlockApplyResult {
+            height,
+            applied_txids: vec![TxID(Hash([1u8; 32])), TxID(Hash([2u8; 32]))],
+            failed: vec![(
+                TxID(Hash([3u8; 32])),
+                "Error::Tx inputs are not in the utxo set".to_string(),
+            )],
+            utxos_added: 4,
+            utxos_removed: 2,
+            duration_ms: 17,
+            weight: 9_000,
+            weight_limit: 10_000,
+        }
+    }
+
+    #[test]
+    fn block_apply_result_encoding_test() {
+        let result = result(5);
+        let bytes = result.to_bytes();
+        assert_eq!(Some(bytes.len()), result.encoded_size_hint());
+        assert_eq!(BlockApplyResult::from_bytes(&bytes).unwrap(), result);
+        // truncated and trailing bytes are rejected
+        assert!(BlockApplyResult::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(BlockApplyResult::from_bytes(&trailing).is_err());
+        // a result of an older node has no weights
+        let legacy = BlockApplyResult::from_bytes(&bytes[..bytes.len() - 16]).unwrap();
+        assert_eq!((legacy.weight, legacy.weight_limit), (0, 0));
+        assert!(!legacy.exceeds_weight_limit());
+
+        assert_eq!(
+            result.to_string(),
+            "height=5 applied=2 failed=1 utxos_added=4 utxos_removed=2 duration=17ms weight=9000/10000"
+        );
+    }
+
+    #[test]
+    fn block_apply_result_retention_test() {
+        let mut store = BlockApplyResultStore::new(2);
+        for height in 1..=3 {
+            store.insert(result(height));
+        }
+        assert!(store.get(1).is_none());
+        assert_eq!(store.get(3), Some(&result(3)));
+    }
+
+    #[test]
+    fn applied_tx_index_test() {
+        let mut index = AppliedTxIndex::new(2);
+        let (a, b) = ([1u8; 32], [2u8; 32]);
+        index.insert(1, a);
+        index.insert(2, b);
+        assert!(index.contains(1, &a));
+        // a tx is applied at the height it was recorded for
+        assert!(!index.contains(2, &a));
+        index.insert(3, a);
+        assert!(!index.contains(1, &a));
+        assert!(index.contains(2, &b));
+        assert!(index.contains(3, &a));
+    }
+}
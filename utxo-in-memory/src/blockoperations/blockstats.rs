@@ -0,0 +1,280 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Timings of block processing, by phase.
+//!
+//! - verify: decoding of the txs and the checks of their proofs and inputs
+//! - apply: removal and insertion of the utxos in the in-memory store
+//! - index: update of the address index
+//! - persist: hand-off of the psql writes to the sql queue, which blocks once the queue is full
+//!
+//! The total of a block is observed in the `block_apply_seconds` histogram of each phase, and
+//! the most recent blocks of a state are kept for the `getBlockProcessingStats` RPC.
+
+use crate::state::{global_state, UtxoState};
+use crate::threadpool::env_or;
+use prometheus::{register_histogram_vec, HistogramVec};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of most recent blocks kept.
+pub const BLOCK_STATS_RETENTION: usize = 100;
+
+/// Verification time above which a tx is logged, unless `SLOW_TX_THRESHOLD_MS` is set.
+pub const DEFAULT_SLOW_TX_THRESHOLD_MS: usize = 500;
+
+lazy_static! {
+    pub static ref BLOCK_APPLY_SECONDS: HistogramVec = register_histogram_vec!(
+        "block_apply_seconds",
+        "Time spent processing a block, by phase",
+        &["phase"]
+    )
+    .unwrap();
+    pub static ref BLOCK_STATS: Arc<Mutex<BlockStatsHistory>> = global_state().block_stats.clone();
+    pub static ref SLOW_TX_THRESHOLD: Duration = {
+        Duration::from_millis(env_or("SLOW_TX_THRESHOLD_MS", DEFAULT_SLOW_TX_THRESHOLD_MS) as u64)
+    };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockPhase {
+    Verify,
+    Apply,
+    Index,
+    Persist,
+}
+
+impl BlockPhase {
+    pub const ALL: [BlockPhase; 4] = [
+        BlockPhase::Verify,
+        BlockPhase::Apply,
+        BlockPhase::Index,
+        BlockPhase::Persist,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockPhase::Verify => "verify",
+            BlockPhase::Apply => "apply",
+            BlockPhase::Index => "index",
+            BlockPhase::Persist => "persist",
+        }
+    }
+}
+
+/// A tx of the block whose verification took longer than the threshold.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SlowTx {
+    pub txid: String,
+    pub verify_millis: f64,
+}
+
+/// Time spent in each phase while a block is processed.
+#[derive(Debug, Clone, Default)]
+pub struct BlockTimings {
+    phases: [Duration; 4],
+    slow_tx_threshold: Duration,
+    pub slow_txs: Vec<SlowTx>,
+}
+
+impl BlockTimings {
+    pub fn new(slow_tx_threshold: Duration) -> Self {
+        BlockTimings {
+            phases: [Duration::ZERO; 4],
+            slow_tx_threshold,
+            slow_txs: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, phase: BlockPhase, elapsed: Duration) {
+        self.phases[phase as usize] += elapsed;
+    }
+
+    pub fn get(&self, phase: BlockPhase) -> Duration {
+        self.phases[phase as usize]
+    }
+
+    /// Adds the verification time of a tx, which is logged if it is above the threshold.
+    pub fn add_tx_verify(&mut self, txid: &str, elapsed: Duration) {
+        self.add(BlockPhase::Verify, elapsed);
+        if elapsed > self.slow_tx_threshold {
+            let slow_tx = SlowTx {
+                txid: txid.to_string(),
+                verify_millis: millis(elapsed),
+            };
+            eprintln!(
+                "WARN SLOW TX : {} verified in {:.1} ms",
+                slow_tx.txid, slow_tx.verify_millis
+            );
+            self.slow_txs.push(slow_tx);
+        }
+    }
+}
+
+/// Timings of a processed block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockProcessingStats {
+    pub block_height: u64,
+    pub tx_count: usize,
+    pub failed_tx_count: usize,
+    pub verify_millis: f64,
+    pub apply_millis: f64,
+    pub index_millis: f64,
+    pub persist_millis: f64,
+    pub total_millis: f64,
+    pub slow_txs: Vec<SlowTx>,
+    /// Unix time the block was processed at
+    #[serde(default)]
+    pub processed_at: u64,
+}
+
+impl BlockProcessingStats {
+    pub fn new(
+        block_height: u64,
+        tx_count: usize,
+        failed_tx_count: usize,
+        timings: &BlockTimings,
+        total: Duration,
+    ) -> Self {
+        BlockProcessingStats {
+            block_height,
+            tx_count,
+            failed_tx_count,
+            verify_millis: millis(timings.get(BlockPhase::Verify)),
+            apply_millis: millis(timings.get(BlockPhase::Apply)),
+            index_millis: millis(timings.get(BlockPhase::Index)),
+            persist_millis: millis(timings.get(BlockPhase::Persist)),
+            total_millis: millis(total),
+            slow_txs: timings.slow_txs.clone(),
+            processed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+impl std::fmt::Display for BlockProcessingStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "height={} txs={} failed={} verify={:.1}ms apply={:.1}ms index={:.1}ms persist={:.1}ms total={:.1}ms",
+            self.block_height,
+            self.tx_count,
+            self.failed_tx_count,
+            self.verify_millis,
+            self.apply_millis,
+            self.index_millis,
+            self.persist_millis,
+            self.total_millis
+        )
+    }
+}
+
+/// Stats of the most recent blocks, in order of processing.
+#[derive(Debug, Clone, Default)]
+pub struct BlockStatsHistory {
+    pub retention: usize,
+    stats: VecDeque<BlockProcessingStats>,
+}
+
+impl BlockStatsHistory {
+    pub fn new(retention: usize) -> Self {
+        BlockStatsHistory {
+            retention,
+            stats: VecDeque::new(),
+        }
+    }
+
+    pub fn insert(&mut self, stats: BlockProcessingStats) {
+        self.stats.push_back(stats);
+        while self.stats.len() > self.retention {
+            self.stats.pop_front();
+        }
+    }
+
+    /// The `count` most recent blocks, newest first.
+    pub fn latest(&self, count: usize) -> Vec<BlockProcessingStats> {
+        self.stats.iter().rev().take(count).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+}
+
+/// Observes the timings of a processed block in the histograms, logs its summary line
+/// and keeps its stats in `state`.
+pub fn record_block_stats(
+    state: &UtxoState,
+    block_height: u64,
+    tx_count: usize,
+    failed_tx_count: usize,
+    timings: &BlockTimings,
+    total: Duration,
+) -> BlockProcessingStats {
+    for phase in BlockPhase::ALL {
+        BLOCK_APPLY_SECONDS
+            .with_label_values(&[phase.as_str()])
+            .observe(timings.get(phase).as_secs_f64());
+    }
+    let stats = BlockProcessingStats::new(block_height, tx_count, failed_tx_count, timings, total);
+    println!("BLOCK PROCESSED : {} {}", state.network(), stats);
+    state.block_stats.lock().unwrap().insert(stats.clone());
+    stats
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn block_timings_test() {
+        let mut timings = BlockTimings::new(Duration::from_millis(10));
+        timings.add_tx_verify("aa", Duration::from_millis(4));
+        timings.add_tx_verify("bb", Duration::from_millis(12));
+        timings.add(BlockPhase::Apply, Duration::from_millis(3));
+        assert_eq!(timings.get(BlockPhase::Verify), Duration::from_millis(16));
+        assert_eq!(timings.get(BlockPhase::Index), Duration::ZERO);
+        assert_eq!(timings.slow_txs.len(), 1);
+        assert_eq!(timings.slow_txs[0].txid, "bb");
+
+        let stats = BlockProcessingStats::new(7, 2, 0, &timings, Duration::from_millis(20));
+        assert_eq!(stats.verify_millis, 16.0);
+        assert_eq!(stats.apply_millis, 3.0);
+        assert!(stats.to_string().starts_with("height=7 txs=2 failed=0"));
+    }
+
+    #[test]
+    fn block_stats_history_test() {
+        let mut history = BlockStatsHistory::new(2);
+        let timings = BlockTimings::default();
+        for height in 1..=3 {
+            history.insert(BlockProcessingStats::new(
+                height,
+                0,
+                0,
+                &timings,
+                Duration::ZERO,
+            ));
+        }
+        assert_eq!(history.len(), 2);
+        let latest = history.latest(5);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].block_height, 3);
+        assert_eq!(latest[1].block_height, 2);
+    }
+}
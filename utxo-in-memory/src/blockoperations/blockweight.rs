@@ -0,0 +1,128 @@
+//! Weight limit of the blocks applied to the utxo set.
+//!
+//! The weight of a block is the summed weight of its txs, see `transaction::weight`.
+//! A block heavier than the limit of the state is not applied. The chain accepted a
+//! block the node cannot verify in time, so the state halts: no further block is
+//! applied until an operator resumes it with a raised limit. The blocks received while
+//! halted are held, and a resume applies them again from the rejected height.
+
+use crate::blockoperations::blockprocessing::{Block, TransactionMessage};
+use crate::threadpool::env_or;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use transaction::weight::{bytes_weight, estimate_gas, DEFAULT_MAX_BLOCK_WEIGHT};
+use transaction::Transaction;
+
+/// Block weight limit of the config, `DEFAULT_MAX_BLOCK_WEIGHT` unless `MAX_BLOCK_WEIGHT` is set.
+pub fn max_block_weight() -> u64 {
+    env_or("MAX_BLOCK_WEIGHT", DEFAULT_MAX_BLOCK_WEIGHT as usize) as u64
+}
+
+/// Weight of a block message, the weight of its tx or of the account of a mint or burn.
+pub fn message_weight(message: &TransactionMessage) -> u64 {
+    match message
+        .tx_byte_code
+        .as_ref()
+        .and_then(|code| hex::decode(code).ok())
+    {
+        Some(bytes) => {
+            // a tx that does not decode fails before any proof is verified
+            let gas = Transaction::from_bytes(&bytes)
+                .map(|tx| estimate_gas(&tx))
+                .unwrap_or(0);
+            bytes_weight(bytes.len()).saturating_add(gas)
+        }
+        None => bytes_weight(
+            message
+                .qq_account
+                .as_ref()
+                .map_or(0, |account| account.len() / 2),
+        ),
+    }
+}
+
+/// Weight of the txs of `block`.
+pub fn block_weight(block: &Block) -> u64 {
+    block.transactions.iter().fold(0u64, |weight, message| {
+        weight.saturating_add(message_weight(message))
+    })
+}
+
+/// A block rejected for its weight, the state is halted at its height.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WeightViolation {
+    pub height: u64,
+    pub block_hash: String,
+    pub weight: u64,
+    pub limit: u64,
+}
+
+impl fmt::Display for WeightViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {} at height {} weighs {}, over the limit of {}",
+            self.block_hash, self.height, self.weight, self.limit
+        )
+    }
+}
+
+/// Blocks applied again by a resume, see `UtxoState::resume_block_processing`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WeightResume {
+    /// block that halted the state
+    pub resumed: WeightViolation,
+    /// heights of the held blocks applied, the rejected height first
+    pub applied_heights: Vec<u64>,
+    /// block halting the state again, still over the limit
+    pub halt: Option<WeightViolation>,
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use transaction::reference_tx::create_dark_reference_transaction;
+    use transaction::weight::tx_weight;
+
+    fn message(tx_byte_code: Option<String>, qq_account: Option<String>) -> TransactionMessage {
+        TransactionMessage {
+            tx_type: "/twilightproject.nyks.zkos.MsgTransferTx".to_string(),
+            tx_id: hex::encode([1u8; 32]),
+            tx_byte_code,
+            zk_oracle_address: None,
+            mint_or_burn: None,
+            btc_value: None,
+            qq_account,
+            encrypt_scalar: None,
+            twilight_address: None,
+        }
+    }
+
+    #[test]
+    fn block_weight_test() {
+        let tx = create_dark_reference_transaction();
+        let transfer = message(Some(hex::encode(tx.to_bytes())), None);
+        // a canonical tx weighs the same in a block as in txCommit
+        assert_eq!(message_weight(&transfer), tx_weight(&tx));
+
+        let mint = message(None, Some(hex::encode([2u8; 101])));
+        assert_eq!(message_weight(&mint), bytes_weight(101));
+        let garbage = message(Some(hex::encode([3u8; 40])), None);
+        assert_eq!(message_weight(&garbage), bytes_weight(40));
+
+        let block = Block {
+            block_hash: "weight".to_string(),
+            block_height: 5,
+            transactions: vec![transfer, mint, garbage],
+            ..Default::default()
+        };
+        assert_eq!(
+            block_weight(&block),
+            tx_weight(&tx) + bytes_weight(101) + bytes_weight(40)
+        );
+        assert_eq!(block_weight(&Block::default()), 0);
+    }
+}
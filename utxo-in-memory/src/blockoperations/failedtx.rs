@@ -0,0 +1,140 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Dead-letter store of block transactions that failed to apply.
+
+use crate::state::global_state;
+use prometheus::{Counter, Gauge};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    /// Failed txs of the global state, see [`crate::state`].
+    pub static ref FAILED_TXS: Arc<Mutex<FailedTxStore>> = global_state().failed_txs.clone();
+    pub static ref TOTAL_FAILED_TX: Counter = global_state().metrics.failed_tx_count.clone();
+    pub static ref BLOCK_FAILED_TX: Gauge = global_state().metrics.block_failed_tx.clone();
+}
+
+/// Block transaction that failed to apply.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FailedTx {
+    pub txid: String,
+    pub block_height: u64,
+    // hex encoded tx bytes, as received in the block
+    pub tx_byte_code: String,
+    // error of the last attempt
+    pub error: String,
+    // unix time of the last attempt
+    pub timestamp: u64,
+    // unix time of the reprocessing that applied the tx
+    pub resolved_at: Option<u64>,
+}
+
+impl FailedTx {
+    pub fn new(txid: &str, block_height: u64, tx_byte_code: &str, error: &str) -> Self {
+        FailedTx {
+            txid: txid.to_string(),
+            block_height,
+            tx_byte_code: tx_byte_code.to_string(),
+            error: error.to_string(),
+            timestamp: unix_timestamp(),
+            resolved_at: None,
+        }
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        self.resolved_at.is_some()
+    }
+}
+
+/// In-memory store of the failed txs, by txid.
+#[derive(Debug, Clone, Default)]
+pub struct FailedTxStore {
+    pub failed: HashMap<String, FailedTx>,
+}
+
+impl FailedTxStore {
+    pub fn new() -> Self {
+        FailedTxStore {
+            failed: HashMap::new(),
+        }
+    }
+
+    /// Records a failed attempt. A tx failing again keeps its block height and
+    /// gets the error and time of the new attempt.
+    pub fn insert(&mut self, failed_tx: FailedTx) -> FailedTx {
+        let record = self
+            .failed
+            .entry(failed_tx.txid.clone())
+            .and_modify(|record| {
+                record.error = failed_tx.error.clone();
+                record.timestamp = failed_tx.timestamp;
+                record.resolved_at = None;
+            })
+            .or_insert(failed_tx);
+        record.clone()
+    }
+
+    /// Marks a failed tx as applied.
+    pub fn resolve(&mut self, txid: &str) -> Option<FailedTx> {
+        let record = self.failed.get_mut(txid)?;
+        record.resolved_at = Some(unix_timestamp());
+        Some(record.clone())
+    }
+
+    pub fn get(&self, txid: &str) -> Option<&FailedTx> {
+        self.failed.get(txid)
+    }
+
+    /// Failed txs of the blocks in `from_height..=to_height`, by block height.
+    pub fn range(&self, from_height: u64, to_height: u64) -> Result<Vec<FailedTx>, &'static str> {
+        if from_height > to_height {
+            return Err("Error::Invalid block range");
+        }
+        let mut failed: Vec<FailedTx> = self
+            .failed
+            .values()
+            .filter(|tx| tx.block_height >= from_height && tx.block_height <= to_height)
+            .cloned()
+            .collect();
+        failed.sort_by(|a, b| (a.block_height, &a.txid).cmp(&(b.block_height, &b.txid)));
+        Ok(failed)
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failed_tx_store_test() {
+        let mut store = FailedTxStore::new();
+        store.insert(FailedTx::new("bb", 12, "00", "input missing"));
+        store.insert(FailedTx::new("aa", 12, "00", "input missing"));
+        store.insert(FailedTx::new("cc", 20, "00", "invalid output"));
+
+        let failed = store.range(10, 15).unwrap();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].txid, "aa");
+        assert!(store.range(15, 10).is_err());
+
+        // a retry keeps the block height of the first failure
+        let retried = store.insert(FailedTx::new("cc", 30, "00", "input missing"));
+        assert_eq!(retried.block_height, 20);
+        assert_eq!(retried.error, "input missing");
+
+        assert!(store.resolve("cc").unwrap().is_resolved());
+        assert!(store.resolve("dd").is_none());
+    }
+}
@@ -0,0 +1,179 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Fee totals of processed blocks and fee pool statistics.
+
+use crate::state::global_state;
+use prometheus::Counter;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use zkvm::CheckedFee;
+
+/// Number of most recent blocks kept in memory.
+pub const BLOCK_FEES_RETENTION: usize = 10_000;
+
+lazy_static! {
+    /// Fees of the global state, see [`crate::state`].
+    pub static ref BLOCK_FEES: Arc<Mutex<BlockFeeStore>> = global_state().block_fees.clone();
+    pub static ref TOTAL_FEES_COLLECTED: Counter = global_state().metrics.fees_collected.clone();
+}
+
+/// Fees collected from the successfully applied transactions of a block.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockFees {
+    pub block_height: u64,
+    pub total_fees: u64,
+    pub tx_count: u64,
+}
+
+impl BlockFees {
+    pub fn new(block_height: u64) -> Self {
+        BlockFees {
+            block_height,
+            total_fees: 0,
+            tx_count: 0,
+        }
+    }
+
+    /// Adds the fee of an applied transaction.
+    /// Fails if the fee is above `MAX_FEE` or the block total overflows.
+    pub fn add_fee(&mut self, fee: u64) -> Result<(), &'static str> {
+        CheckedFee::new(fee).ok_or("Error::Fee is above the maximum allowed fee")?;
+        self.total_fees = self
+            .total_fees
+            .checked_add(fee)
+            .ok_or("Error::Block fee total overflow")?;
+        self.tx_count += 1;
+        Ok(())
+    }
+}
+
+/// Fee totals and averages over a range of blocks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeePoolStats {
+    pub from_height: u64,
+    pub to_height: u64,
+    pub block_count: u64,
+    pub tx_count: u64,
+    pub total_fees: u64,
+    pub average_fee_per_block: f64,
+    pub average_fee_per_tx: f64,
+}
+
+/// In-memory store of the fee totals of the latest blocks.
+#[derive(Debug, Clone)]
+pub struct BlockFeeStore {
+    pub fees: BTreeMap<u64, BlockFees>,
+    pub retention: usize,
+}
+
+impl BlockFeeStore {
+    pub fn new(retention: usize) -> Self {
+        BlockFeeStore {
+            fees: BTreeMap::new(),
+            retention,
+        }
+    }
+
+    /// Adds the fees of a block and drops the oldest blocks beyond the retention limit.
+    pub fn insert(&mut self, block_fees: BlockFees) {
+        self.fees.insert(block_fees.block_height, block_fees);
+        while self.fees.len() > self.retention {
+            let oldest = match self.fees.keys().next() {
+                Some(height) => *height,
+                None => break,
+            };
+            self.fees.remove(&oldest);
+        }
+    }
+
+    pub fn get(&self, block_height: u64) -> Option<&BlockFees> {
+        self.fees.get(&block_height)
+    }
+
+    /// Totals and averages over the stored blocks in `[from_height, to_height]`.
+    pub fn stats(&self, from_height: u64, to_height: u64) -> Result<FeePoolStats, &'static str> {
+        if from_height > to_height {
+            return Err("Error::Invalid block range");
+        }
+        let mut block_count: u64 = 0;
+        let mut tx_count: u64 = 0;
+        let mut total_fees: u64 = 0;
+        for (_, block_fees) in self.fees.range(from_height..=to_height) {
+            block_count += 1;
+            tx_count += block_fees.tx_count;
+            total_fees = total_fees
+                .checked_add(block_fees.total_fees)
+                .ok_or("Error::Fee total overflow")?;
+        }
+        let average = |count: u64| {
+            if count == 0 {
+                0.0
+            } else {
+                total_fees as f64 / count as f64
+            }
+        };
+        Ok(FeePoolStats {
+            from_height,
+            to_height,
+            block_count,
+            tx_count,
+            total_fees,
+            average_fee_per_block: average(block_count),
+            average_fee_per_tx: average(tx_count),
+        })
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use zkvm::MAX_FEE;
+
+    #[test]
+    fn block_fees_add_fee_test() {
+        let mut block_fees = BlockFees::new(1);
+        block_fees.add_fee(10).unwrap();
+        block_fees.add_fee(MAX_FEE).unwrap();
+        assert_eq!(block_fees.total_fees, 10 + MAX_FEE);
+        assert_eq!(block_fees.tx_count, 2);
+
+        assert!(block_fees.add_fee(MAX_FEE + 1).is_err());
+        assert_eq!(block_fees.tx_count, 2);
+    }
+
+    #[test]
+    fn fee_pool_stats_test() {
+        let mut store = BlockFeeStore::new(3);
+        for (height, fees) in [
+            (1u64, vec![5u64]),
+            (2, vec![1, 2, 3]),
+            (3, vec![]),
+            (4, vec![10, 20]),
+        ] {
+            let mut block_fees = BlockFees::new(height);
+            for fee in fees {
+                block_fees.add_fee(fee).unwrap();
+            }
+            store.insert(block_fees);
+        }
+        // block 1 is dropped by retention
+        assert!(store.get(1).is_none());
+        assert_eq!(store.get(2).unwrap().total_fees, 6);
+
+        let stats = store.stats(1, 4).unwrap();
+        assert_eq!(stats.block_count, 3);
+        assert_eq!(stats.tx_count, 5);
+        assert_eq!(stats.total_fees, 36);
+        assert_eq!(stats.average_fee_per_block, 12.0);
+        assert_eq!(stats.average_fee_per_tx, 7.2);
+
+        let empty = store.stats(3, 3).unwrap();
+        assert_eq!(empty.total_fees, 0);
+        assert_eq!(empty.average_fee_per_tx, 0.0);
+        assert!(store.stats(4, 2).is_err());
+    }
+}
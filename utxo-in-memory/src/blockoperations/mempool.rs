@@ -0,0 +1,626 @@
+//! Transactions committed through the rpc server and not yet seen in a block.
+//!
+//! The mempool keeps the utxos spent by each pending tx, so that the inputs of a new
+//! transfer can be checked against them before its proof is generated. A pending tx
+//! is dropped once one of its inputs is spent by a block, when its commit fails, or
+//! after `MEMPOOL_TTL_SECS`.
+//!
+//! A tx spending an input of a pending tx replaces it if its fee rate exceeds the
+//! rate of every conflicting tx by `MEMPOOL_REPLACE_INCREMENT_PCT` percent. The
+//! conflicting txs are evicted and the replacement is recorded for the ttl.
+
+use crate::db::{utxo_key, LocalDBtrait, LocalStorage};
+use crate::error::UtxosetError;
+use crate::state::global_state;
+use crate::threadpool::env_or;
+use crate::UTXO_STORAGE;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zkvm::zkos_types::{IOType, Input, Output, Utxo};
+
+/// Seconds a pending tx is kept, unless `MEMPOOL_TTL_SECS` is set.
+pub const DEFAULT_MEMPOOL_TTL_SECS: usize = 600;
+
+/// Percent a replacement has to add to the fee rate of the txs it evicts, unless
+/// `MEMPOOL_REPLACE_INCREMENT_PCT` is set.
+pub const DEFAULT_MEMPOOL_REPLACE_INCREMENT_PCT: usize = 10;
+
+lazy_static! {
+    /// Mempool of the global state, see [`crate::state`].
+    pub static ref MEMPOOL: Arc<Mutex<Mempool>> = global_state().mempool.clone();
+}
+
+#[derive(Debug, Clone)]
+struct PendingTx {
+    inputs: Vec<Utxo>,
+    fee_rate: f64,
+    received: Instant,
+}
+
+/// A pending tx, as listed by getMempool.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MempoolEntry {
+    pub txid: String,
+    /// Fee per byte of the encoded tx
+    pub fee_rate: f64,
+    pub inputs: Vec<Utxo>,
+    /// Seconds since the tx was received
+    pub age_secs: u64,
+    /// Whether a tx spending one of its inputs can evict it, i.e. it is the pending
+    /// spender of at least one input
+    pub replaceable: bool,
+}
+
+/// Pending tx `txid` evicted by `replaced_by`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pub txid: String,
+    pub replaced_by: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MempoolView {
+    /// Highest fee rate first
+    pub entries: Vec<MempoolEntry>,
+    /// Replacements within the ttl
+    pub replaced: Vec<Replacement>,
+    pub replace_increment_pct: u64,
+}
+
+/// Pending txs and the utxos they spend.
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    pub ttl: Duration,
+    /// Percent a replacement adds at least to the fee rate of the txs it evicts
+    pub replace_increment_pct: u64,
+    txs: HashMap<String, PendingTx>,
+    // encoded utxo -> txid of the first pending tx spending it
+    spent: HashMap<Vec<u8>, String>,
+    // evicted txid -> txid of its replacement, and when it was replaced
+    replaced: HashMap<String, (String, Instant)>,
+}
+
+// utxos spent by `inputs`, decoy inputs and inputs referring to outputs of the tx aside
+fn spent_utxos(inputs: &[Input]) -> Vec<Utxo> {
+    let decoy_utxo = Utxo::default();
+    inputs
+        .iter()
+        .filter_map(|input| input.as_utxo().copied())
+        .filter(|utxo| *utxo != decoy_utxo && !utxo.is_pending())
+        .collect()
+}
+
+impl Mempool {
+    /// Mempool with the ttl and replacement increment of the config.
+    pub fn from_config() -> Self {
+        let mut mempool = Mempool::new(Duration::from_secs(env_or(
+            "MEMPOOL_TTL_SECS",
+            DEFAULT_MEMPOOL_TTL_SECS,
+        ) as u64));
+        mempool.replace_increment_pct = env_or(
+            "MEMPOOL_REPLACE_INCREMENT_PCT",
+            DEFAULT_MEMPOOL_REPLACE_INCREMENT_PCT,
+        ) as u64;
+        mempool
+    }
+
+    pub fn new(ttl: Duration) -> Self {
+        Mempool {
+            ttl,
+            replace_increment_pct: DEFAULT_MEMPOOL_REPLACE_INCREMENT_PCT as u64,
+            txs: HashMap::new(),
+            spent: HashMap::new(),
+            replaced: HashMap::new(),
+        }
+    }
+
+    /// Adds the pending tx `txid` spending `inputs`, decoy inputs and inputs referring
+    /// to outputs of the tx itself aside.
+    /// A utxo already spent by another pending tx keeps its first spender.
+    pub fn insert(&mut self, txid: &str, inputs: &[Input], fee_rate: f64, now: Instant) {
+        self.prune(now);
+        let inputs = spent_utxos(inputs);
+        for utxo in &inputs {
+            self.spent
+                .entry(utxo_key(utxo))
+                .or_insert_with(|| txid.to_string());
+        }
+        self.txs.insert(
+            txid.to_string(),
+            PendingTx {
+                inputs,
+                fee_rate,
+                received: now,
+            },
+        );
+    }
+
+    /// Live pending txs other than `txid` spending one of `utxos`, the txs a tx
+    /// `txid` spending them would replace.
+    pub fn conflicts(&self, txid: &str, utxos: &[Utxo], now: Instant) -> Vec<String> {
+        let mut conflicts: Vec<String> = utxos
+            .iter()
+            .filter_map(|utxo| self.live_spender(utxo, now))
+            .filter(|spender| *spender != txid)
+            .map(str::to_string)
+            .collect();
+        conflicts.sort();
+        conflicts.dedup();
+        conflicts
+    }
+
+    /// Pending txs the tx `txid` spending `utxos` at `fee_rate` would evict. Fails with
+    /// `MempoolConflict` naming the conflicting tx of the highest fee rate, if
+    /// `fee_rate` does not exceed it by `replace_increment_pct` percent.
+    pub fn check_replacement(
+        &self,
+        txid: &str,
+        utxos: &[Utxo],
+        fee_rate: f64,
+        now: Instant,
+    ) -> Result<Vec<String>, UtxosetError> {
+        let conflicts = self.conflicts(txid, utxos, now);
+        let highest = conflicts
+            .iter()
+            .filter_map(|conflict| self.txs.get(conflict).map(|tx| (conflict, tx.fee_rate)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((conflict, conflict_rate)) = highest {
+            let required = conflict_rate * (100 + self.replace_increment_pct) as f64 / 100.0;
+            if fee_rate <= conflict_rate || fee_rate < required {
+                return Err(UtxosetError::MempoolConflict {
+                    txid: conflict.clone(),
+                    required,
+                });
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Adds the pending tx `txid` as `insert` does, evicting the txs it replaces in
+    /// the same step. Returns the evicted txids, fails as `check_replacement` does
+    /// and leaves the mempool unchanged then.
+    pub fn insert_or_replace(
+        &mut self,
+        txid: &str,
+        inputs: &[Input],
+        fee_rate: f64,
+        now: Instant,
+    ) -> Result<Vec<String>, UtxosetError> {
+        self.prune(now);
+        let evicted = self.check_replacement(txid, &spent_utxos(inputs), fee_rate, now)?;
+        for replaced in &evicted {
+            self.remove(replaced);
+            self.replaced
+                .insert(replaced.clone(), (txid.to_string(), now));
+        }
+        self.replaced.remove(txid);
+        self.insert(txid, inputs, fee_rate, now);
+        Ok(evicted)
+    }
+
+    /// Pending tx that replaced `txid`, within the ttl of the replacement.
+    pub fn replaced_by(&self, txid: &str) -> Option<&str> {
+        self.replaced
+            .get(txid)
+            .map(|(replacement, _)| replacement.as_str())
+    }
+
+    /// Drops the pending tx `txid`. Returns false if it is not pending.
+    pub fn remove(&mut self, txid: &str) -> bool {
+        match self.txs.remove(txid) {
+            Some(tx) => {
+                for utxo in tx.inputs {
+                    let key = utxo_key(&utxo);
+                    if self
+                        .spent
+                        .get(&key)
+                        .map_or(false, |spender| spender == txid)
+                    {
+                        self.spent.remove(&key);
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops the pending txs spending one of `utxos`, once these are spent by a block.
+    /// Returns the number of txs dropped.
+    pub fn remove_spent(&mut self, utxos: &[Utxo]) -> usize {
+        let txids: Vec<String> = self
+            .txs
+            .iter()
+            .filter(|(_, tx)| tx.inputs.iter().any(|input| utxos.contains(input)))
+            .map(|(txid, _)| txid.clone())
+            .collect();
+        for txid in &txids {
+            self.remove(txid);
+        }
+        txids.len()
+    }
+
+    /// Drops the pending txs and the replacements older than the ttl.
+    pub fn prune(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.replaced
+            .retain(|_, (_, replaced)| now.saturating_duration_since(*replaced) <= ttl);
+        let expired: Vec<String> = self
+            .txs
+            .iter()
+            .filter(|(_, tx)| now.saturating_duration_since(tx.received) > ttl)
+            .map(|(txid, _)| txid.clone())
+            .collect();
+        for txid in expired {
+            self.remove(&txid);
+        }
+    }
+
+    /// Pending tx spending `utxo`, if any.
+    pub fn spender(&self, utxo: &Utxo) -> Option<&str> {
+        self.spent.get(&utxo_key(utxo)).map(String::as_str)
+    }
+
+    /// Pending tx spending `utxo` that is not expired at `now`.
+    /// Unlike `prune`, leaves the mempool unchanged.
+    pub fn live_spender(&self, utxo: &Utxo, now: Instant) -> Option<&str> {
+        let txid = self.spent.get(&utxo_key(utxo))?;
+        let tx = self.txs.get(txid)?;
+        if now.saturating_duration_since(tx.received) > self.ttl {
+            None
+        } else {
+            Some(txid.as_str())
+        }
+    }
+
+    /// Utxos spent by the pending txs.
+    pub fn pending_spent(&self) -> Vec<Utxo> {
+        self.spent
+            .keys()
+            .filter_map(|key| Utxo::from_bytes(key))
+            .collect()
+    }
+
+    /// The pending txs, highest fee rate first, and the recent replacements.
+    pub fn view(&self, now: Instant) -> MempoolView {
+        let mut entries: Vec<MempoolEntry> = self
+            .txs
+            .iter()
+            .map(|(txid, tx)| MempoolEntry {
+                txid: txid.clone(),
+                fee_rate: tx.fee_rate,
+                inputs: tx.inputs.clone(),
+                age_secs: now.saturating_duration_since(tx.received).as_secs(),
+                replaceable: tx
+                    .inputs
+                    .iter()
+                    .any(|utxo| self.spender(utxo) == Some(txid.as_str())),
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.fee_rate
+                .total_cmp(&a.fee_rate)
+                .then_with(|| a.txid.cmp(&b.txid))
+        });
+        let mut replaced: Vec<Replacement> = self
+            .replaced
+            .iter()
+            .map(|(txid, (replaced_by, _))| Replacement {
+                txid: txid.clone(),
+                replaced_by: replaced_by.clone(),
+            })
+            .collect();
+        replaced.sort_by(|a, b| a.txid.cmp(&b.txid));
+        MempoolView {
+            entries,
+            replaced,
+            replace_increment_pct: self.replace_increment_pct,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+}
+
+/// Coin input a wallet intends to spend, as it expects to find it in the utxo set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimulatedInput {
+    pub utxo: Utxo,
+    /// Hex of the owner address
+    pub owner: String,
+    /// Hex of the ElGamal encryption of the value
+    pub encryption: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SimulateTransferQuery {
+    pub inputs: Vec<SimulatedInput>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputStatus {
+    /// Unspent, and not spent by a pending tx
+    Live,
+    /// Not a coin of the utxo set, it is spent or never existed
+    NotFound,
+    OwnerMismatch,
+    EncryptionMismatch,
+    /// Spent by a pending tx of the mempool
+    PendingSpend,
+    /// Same utxo as an earlier input
+    Duplicate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputVerdict {
+    pub utxo: Utxo,
+    pub status: InputStatus,
+    /// Pending tx spending the input, if any
+    pub pending_txid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    /// Whether every input is live
+    pub valid: bool,
+    pub inputs: Vec<InputVerdict>,
+}
+
+fn input_status(
+    utxo_storage: &mut LocalStorage<Output>,
+    mempool: &Mempool,
+    input: &SimulatedInput,
+) -> InputStatus {
+    let output = match utxo_storage.get_utxo_by_id(utxo_key(&input.utxo), IOType::Coin as usize) {
+        Ok(output) => output,
+        Err(_) => return InputStatus::NotFound,
+    };
+    let coin = match output.as_out_coin() {
+        Some(coin) => coin,
+        None => return InputStatus::NotFound,
+    };
+    if !coin.owner.eq_ignore_ascii_case(input.owner.trim()) {
+        return InputStatus::OwnerMismatch;
+    }
+    if !hex::encode(coin.encrypt.to_bytes()).eq_ignore_ascii_case(input.encryption.trim()) {
+        return InputStatus::EncryptionMismatch;
+    }
+    if mempool.spender(&input.utxo).is_some() {
+        return InputStatus::PendingSpend;
+    }
+    InputStatus::Live
+}
+
+/// Checks `inputs` as block processing would, without proofs: each input must be a
+/// coin of `utxo_storage` with the expected owner and encryption, spent by no pending
+/// tx of `mempool` and by no earlier input.
+pub fn simulate_inputs(
+    utxo_storage: &mut LocalStorage<Output>,
+    mempool: &Mempool,
+    inputs: &[SimulatedInput],
+) -> SimulationReport {
+    let verdicts: Vec<InputVerdict> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let status = if inputs[..index]
+                .iter()
+                .any(|earlier| earlier.utxo == input.utxo)
+            {
+                InputStatus::Duplicate
+            } else {
+                input_status(utxo_storage, mempool, input)
+            };
+            InputVerdict {
+                utxo: input.utxo,
+                status,
+                pending_txid: mempool.spender(&input.utxo).map(str::to_string),
+            }
+        })
+        .collect();
+    SimulationReport {
+        valid: verdicts
+            .iter()
+            .all(|verdict| verdict.status == InputStatus::Live),
+        inputs: verdicts,
+    }
+}
+
+/// Checks the inputs of a prospective transfer against the utxo set and the mempool.
+pub fn simulate_transfer(inputs: &[SimulatedInput]) -> SimulationReport {
+    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let mut mempool = MEMPOOL.lock().unwrap();
+    mempool.prune(Instant::now());
+    simulate_inputs(&mut utxo_storage, &mempool, inputs)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+
+    // a coin of a random account in `utxo_storage`, and the input spending it
+    fn live_coin(utxo_storage: &mut LocalStorage<Output>) -> (SimulatedInput, Input) {
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(5u64));
+        let output = Output::from_quisquis_account(account, address::Network::Testnet);
+        let utxo = Utxo::random();
+        utxo_storage
+            .add(utxo.to_bytes(), output.clone(), IOType::Coin as usize)
+            .unwrap();
+        let coin = output.as_out_coin().unwrap().clone();
+        let simulated = SimulatedInput {
+            utxo,
+            owner: coin.owner.clone(),
+            encryption: hex::encode(coin.encrypt.to_bytes()),
+        };
+        (simulated, coin.to_input(utxo, 0))
+    }
+
+    #[test]
+    fn simulate_inputs_test() {
+        let mut utxo_storage = LocalStorage::<Output>::new(3);
+        let mut mempool = Mempool::new(Duration::from_secs(60));
+        let (live, _) = live_coin(&mut utxo_storage);
+        let (spent, _) = live_coin(&mut utxo_storage);
+        let (pending, pending_input) = live_coin(&mut utxo_storage);
+        utxo_storage
+            .remove(spent.utxo.to_bytes(), IOType::Coin as usize)
+            .unwrap();
+        mempool.insert("aa", &[pending_input], 1.0, Instant::now());
+
+        let report = simulate_inputs(&mut utxo_storage, &mempool, &[live.clone()]);
+        assert!(report.valid);
+        assert_eq!(report.inputs[0].status, InputStatus::Live);
+
+        let report = simulate_inputs(
+            &mut utxo_storage,
+            &mempool,
+            &[live.clone(), spent, pending.clone(), live.clone()],
+        );
+        assert!(!report.valid);
+        let statuses: Vec<InputStatus> = report.inputs.iter().map(|v| v.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                InputStatus::Live,
+                InputStatus::NotFound,
+                InputStatus::PendingSpend,
+                InputStatus::Duplicate
+            ]
+        );
+        assert_eq!(report.inputs[2].pending_txid.as_deref(), Some("aa"));
+
+        // the expected coin must match the stored one
+        let mut wrong_owner = live.clone();
+        wrong_owner.owner = pending.owner.clone();
+        let mut wrong_encryption = live.clone();
+        wrong_encryption.encryption = pending.encryption.clone();
+        let report = simulate_inputs(
+            &mut utxo_storage,
+            &mempool,
+            &[wrong_owner, wrong_encryption],
+        );
+        assert_eq!(report.inputs[0].status, InputStatus::OwnerMismatch);
+        assert_eq!(report.inputs[1].status, InputStatus::EncryptionMismatch);
+    }
+
+    #[test]
+    fn mempool_test() {
+        let mut utxo_storage = LocalStorage::<Output>::new(3);
+        let (a, input_a) = live_coin(&mut utxo_storage);
+        let (b, input_b) = live_coin(&mut utxo_storage);
+        let decoy = input_a.as_out_coin().unwrap().to_input(Utxo::default(), 1);
+        let start = Instant::now();
+
+        let mut mempool = Mempool::new(Duration::from_secs(60));
+        mempool.insert("aa", &[input_a.clone(), decoy], 1.0, start);
+        // a second spender of the same utxo does not take it over
+        mempool.insert("bb", &[input_a, input_b.clone()], 1.0, start);
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool.spender(&a.utxo), Some("aa"));
+        assert_eq!(mempool.spender(&b.utxo), Some("bb"));
+        assert_eq!(mempool.pending_spent().len(), 2);
+
+        // both txs spend `a`, they are dropped once a block spends it
+        assert_eq!(mempool.remove_spent(&[a.utxo]), 2);
+        assert!(mempool.is_empty());
+        assert!(mempool.pending_spent().is_empty());
+
+        mempool.insert("cc", &[], 1.0, start);
+        assert!(mempool.remove("cc"));
+        assert!(!mempool.remove("cc"));
+
+        // expired spenders are ignored without pruning them
+        mempool.insert("ee", &[input_b], 1.0, start);
+        assert_eq!(mempool.live_spender(&b.utxo, start), Some("ee"));
+        assert_eq!(
+            mempool.live_spender(&b.utxo, start + Duration::from_secs(61)),
+            None
+        );
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.remove("ee"));
+
+        mempool.insert("dd", &[], 1.0, start);
+        mempool.prune(start + Duration::from_secs(61));
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn replace_by_fee_test() {
+        let mut utxo_storage = LocalStorage::<Output>::new(3);
+        let (a, input_a) = live_coin(&mut utxo_storage);
+        let (b, input_b) = live_coin(&mut utxo_storage);
+        let (c, input_c) = live_coin(&mut utxo_storage);
+        let start = Instant::now();
+        let mut mempool = Mempool::new(Duration::from_secs(60));
+        assert_eq!(mempool.replace_increment_pct, 10);
+        mempool.insert("aa", &[input_a.clone()], 2.0, start);
+
+        // not above the increment, the original is kept
+        match mempool.insert_or_replace("bb", &[input_a.clone()], 2.1, start) {
+            Err(UtxosetError::MempoolConflict { txid, required }) => {
+                assert_eq!(txid, "aa");
+                assert!((required - 2.2).abs() < 1e-9);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.spender(&a.utxo), Some("aa"));
+        assert_eq!(mempool.replaced_by("aa"), None);
+
+        // a bump evicts the original
+        let evicted = mempool
+            .insert_or_replace("bb", &[input_a.clone()], 2.5, start)
+            .unwrap();
+        assert_eq!(evicted, vec!["aa".to_string()]);
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.spender(&a.utxo), Some("bb"));
+        assert_eq!(mempool.replaced_by("aa"), Some("bb"));
+        // the same tx again replaces nothing
+        let evicted = mempool
+            .insert_or_replace("bb", &[input_a.clone()], 2.5, start)
+            .unwrap();
+        assert!(evicted.is_empty());
+
+        // a bump conflicting with two txs has to exceed the higher fee rate
+        mempool.insert("cc", &[input_b.clone()], 4.0, start);
+        let inputs = [input_a, input_b, input_c];
+        match mempool.insert_or_replace("dd", &inputs, 4.3, start) {
+            Err(UtxosetError::MempoolConflict { txid, .. }) => assert_eq!(txid, "cc"),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(mempool.len(), 2);
+        let evicted = mempool
+            .insert_or_replace("dd", &inputs, 5.0, start)
+            .unwrap();
+        assert_eq!(evicted, vec!["bb".to_string(), "cc".to_string()]);
+        assert_eq!(mempool.len(), 1);
+        for utxo in [a.utxo, b.utxo, c.utxo] {
+            assert_eq!(mempool.spender(&utxo), Some("dd"));
+        }
+        assert_eq!(mempool.replaced_by("cc"), Some("dd"));
+
+        let view = mempool.view(start);
+        assert_eq!(view.entries.len(), 1);
+        assert_eq!(view.entries[0].fee_rate, 5.0);
+        assert!(view.entries[0].replaceable);
+        assert_eq!(view.replaced.len(), 3);
+        assert_eq!(view.replace_increment_pct, 10);
+
+        // replacements are forgotten after the ttl
+        mempool.prune(start + Duration::from_secs(61));
+        assert!(mempool.view(start).replaced.is_empty());
+    }
+}
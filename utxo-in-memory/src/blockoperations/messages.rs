@@ -0,0 +1,119 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Burn history and applied message transactions.
+
+use crate::state::{global_state, UtxoState};
+use prometheus::Counter;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use zkvm::zkos_types::MessageType;
+
+lazy_static! {
+    /// Burns of the global state, see [`crate::state`].
+    pub static ref BURN_HISTORY: Arc<Mutex<BurnStore>> = global_state().burn_history.clone();
+    pub static ref APPLIED_MESSAGES: Arc<Mutex<HashMap<String, AppliedMessage>>> =
+        global_state().applied_messages.clone();
+    pub static ref TOTAL_BURNED: Counter = global_state().metrics.burned.clone();
+}
+
+/// Coin burned by a burn message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BurnRecord {
+    pub txid: String,
+    // initial address of the burned account, as carried in the message data
+    pub address: String,
+    pub amount: u64,
+    pub block_height: u64,
+}
+
+/// Message transaction applied by block processing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMessage {
+    pub msg_type: MessageType,
+    pub block_height: u64,
+}
+
+/// In-memory store of the burns of every address.
+#[derive(Debug, Clone, Default)]
+pub struct BurnStore {
+    pub burns: HashMap<String, Vec<BurnRecord>>,
+}
+
+impl BurnStore {
+    pub fn new() -> Self {
+        BurnStore {
+            burns: HashMap::new(),
+        }
+    }
+
+    /// Adds a burn. A burn already recorded for the same txid is ignored.
+    pub fn insert(&mut self, burn: BurnRecord) -> bool {
+        let history = self.burns.entry(burn.address.clone()).or_default();
+        if history.iter().any(|recorded| recorded.txid == burn.txid) {
+            return false;
+        }
+        history.push(burn);
+        true
+    }
+
+    /// Burns of `address`, oldest first.
+    pub fn history(&self, address: &str) -> Vec<BurnRecord> {
+        self.burns.get(address).cloned().unwrap_or_default()
+    }
+}
+
+/// Records a message applied in a block.
+pub fn record_applied_message(
+    state: &UtxoState,
+    txid: &str,
+    msg_type: MessageType,
+    block_height: u64,
+) {
+    let mut messages = state.applied_messages.lock().unwrap();
+    messages.insert(
+        txid.to_string(),
+        AppliedMessage {
+            msg_type,
+            block_height,
+        },
+    );
+}
+
+/// Returns the applied message with `txid`, if any.
+pub fn get_applied_message(txid: &str) -> Option<AppliedMessage> {
+    APPLIED_MESSAGES.lock().unwrap().get(txid).cloned()
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn burn(txid: &str, address: &str, amount: u64) -> BurnRecord {
+        BurnRecord {
+            txid: txid.to_string(),
+            address: address.to_string(),
+            amount,
+            block_height: 1,
+        }
+    }
+
+    #[test]
+    fn burn_store_history_test() {
+        let mut store = BurnStore::new();
+        assert!(store.insert(burn("aa", "addr1", 10)));
+        assert!(store.insert(burn("bb", "addr1", 20)));
+        assert!(store.insert(burn("cc", "addr2", 30)));
+        // same burn seen again on a replayed block
+        assert!(!store.insert(burn("aa", "addr1", 10)));
+
+        let history = store.history("addr1");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].amount, 10);
+        assert_eq!(history[1].amount, 20);
+        assert!(store.history("addr3").is_empty());
+    }
+}
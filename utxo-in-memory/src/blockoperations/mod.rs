@@ -1,6 +1,22 @@
 // mod utxodb_operations;
 // pub use self::utxodb_operations::*;
+pub mod addresstx;
+pub mod balanceindexer;
+pub mod blockheader;
 pub mod blockprocessing;
+pub mod blockresult;
+pub mod blockstats;
+pub mod blockweight;
+pub mod failedtx;
+pub mod fees;
+pub mod mempool;
+pub mod messages;
+pub mod nullifiers;
+pub mod pruning;
+pub mod scripteval;
+pub mod subscriptions;
+pub mod txroot;
+pub mod txstatus;
 mod initialset;
 pub use self::initialset::*;
 
@@ -0,0 +1,311 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Nullifiers of the coins spent by the latest blocks.
+//!
+//! A coin input is identified by its nullifier, see [`Input::nullifier`], so that a
+//! double spend is rejected by a lookup before the utxo set is read or a proof is
+//! verified. The index keeps the nullifiers of the last `NULLIFIER_DEPTH` blocks,
+//! older spends are rejected by the utxo set. Pending txs spending the same coin are
+//! found by the mempool, see [`crate::blockoperations::mempool`].
+//!
+//! The nullifiers of each block are written to the `block_nullifiers` psql table
+//! and loaded at startup. A reorg drops the blocks above the fork point from both.
+
+use crate::state::{global_state, UtxoState};
+use crate::threadpool::env_or;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use zkvm::merkle::Hash;
+use zkvm::zkos_types::Input;
+
+/// Number of latest blocks whose nullifiers are kept, unless `NULLIFIER_DEPTH` is set.
+pub const DEFAULT_NULLIFIER_DEPTH: usize = 100;
+
+lazy_static! {
+    /// Nullifier index of the global state, see [`crate::state`].
+    pub static ref NULLIFIER_INDEX: Arc<Mutex<NullifierIndex>> = global_state().nullifiers.clone();
+}
+
+/// Nullifier spent by the tx `txid`, hex encoded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SpentNullifier {
+    pub nullifier: String,
+    pub txid: String,
+}
+
+/// Nullifiers spent by the txs of the block at `height`, as persisted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockNullifiers {
+    pub height: u64,
+    pub entries: Vec<SpentNullifier>,
+}
+
+/// Coin of a tx already spent by the tx `txid` of the block at `height`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NullifierConflict {
+    pub txid: String,
+    pub height: u64,
+}
+
+impl fmt::Display for NullifierConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "input already spent by tx {} in block {}",
+            self.txid, self.height
+        )
+    }
+}
+
+/// Nullifiers of the coin inputs of `inputs`, decoys included and fresh accounts aside.
+pub fn input_nullifiers(inputs: &[Input]) -> Vec<Hash> {
+    inputs.iter().filter_map(Input::nullifier).collect()
+}
+
+/// Nullifiers spent by the latest blocks, with the tx spending each.
+#[derive(Debug, Clone)]
+pub struct NullifierIndex {
+    /// Number of latest blocks kept
+    pub depth: u64,
+    blocks: BTreeMap<u64, Vec<(Hash, String)>>,
+    // nullifier -> txid spending it and the height of its block
+    spent: HashMap<Hash, (String, u64)>,
+}
+
+impl NullifierIndex {
+    /// Index of the depth of the config.
+    pub fn from_config() -> Self {
+        NullifierIndex::new(env_or("NULLIFIER_DEPTH", DEFAULT_NULLIFIER_DEPTH) as u64)
+    }
+
+    pub fn new(depth: u64) -> Self {
+        NullifierIndex {
+            depth,
+            blocks: BTreeMap::new(),
+            spent: HashMap::new(),
+        }
+    }
+
+    /// Adds the nullifiers spent by the tx `txid` of the block at `height`, dropping
+    /// the blocks more than the depth below the latest. A nullifier keeps its first
+    /// spender.
+    pub fn insert(&mut self, height: u64, txid: &str, nullifiers: &[Hash]) {
+        if nullifiers.is_empty() {
+            return;
+        }
+        let block = self.blocks.entry(height).or_default();
+        for nullifier in nullifiers {
+            if self.spent.contains_key(nullifier) {
+                continue;
+            }
+            self.spent.insert(*nullifier, (txid.to_string(), height));
+            block.push((*nullifier, txid.to_string()));
+        }
+        let top = self.blocks.keys().next_back().copied().unwrap_or(height);
+        let lowest = top.saturating_sub(self.depth.saturating_sub(1));
+        let pruned: Vec<u64> = self.blocks.range(..lowest).map(|(h, _)| *h).collect();
+        for height in pruned {
+            self.remove_block(height);
+        }
+    }
+
+    /// Adds the persisted nullifiers of a block.
+    pub fn insert_block(&mut self, block: &BlockNullifiers) {
+        for entry in block.entries.iter() {
+            if let Ok(bytes) = hex::decode(&entry.nullifier) {
+                if let Ok(nullifier) = <[u8; 32]>::try_from(bytes) {
+                    self.insert(block.height, &entry.txid, &[Hash(nullifier)]);
+                }
+            }
+        }
+    }
+
+    /// Nullifiers of the block at `height`, for persistence.
+    pub fn block(&self, height: u64) -> Option<BlockNullifiers> {
+        let entries = self.blocks.get(&height)?;
+        Some(BlockNullifiers {
+            height,
+            entries: entries
+                .iter()
+                .map(|(nullifier, txid)| SpentNullifier {
+                    nullifier: hex::encode(nullifier.0),
+                    txid: txid.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Tx spending `nullifier` and the height of its block, if any.
+    pub fn spender(&self, nullifier: &Hash) -> Option<(&str, u64)> {
+        self.spent
+            .get(nullifier)
+            .map(|(txid, height)| (txid.as_str(), *height))
+    }
+
+    /// First of `nullifiers` spent by a tx other than `txid`, resubmitting or
+    /// reapplying the same tx is not a conflict.
+    pub fn conflict(&self, txid: &str, nullifiers: &[Hash]) -> Option<NullifierConflict> {
+        nullifiers.iter().find_map(|nullifier| {
+            let (spender, height) = self.spender(nullifier)?;
+            if spender.eq_ignore_ascii_case(txid) {
+                return None;
+            }
+            Some(NullifierConflict {
+                txid: spender.to_string(),
+                height,
+            })
+        })
+    }
+
+    /// Drops the blocks above `height`, on a reorg. Returns the number of nullifiers
+    /// dropped.
+    pub fn rollback_to(&mut self, height: u64) -> usize {
+        let heights: Vec<u64> = self.blocks.range(height + 1..).map(|(h, _)| *h).collect();
+        heights.into_iter().map(|h| self.remove_block(h)).sum()
+    }
+
+    fn remove_block(&mut self, height: u64) -> usize {
+        let entries = self.blocks.remove(&height).unwrap_or_default();
+        for (nullifier, _) in entries.iter() {
+            self.spent.remove(nullifier);
+        }
+        entries.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spent.is_empty()
+    }
+}
+
+impl UtxoState {
+    /// Writes the nullifiers spent by the block at `height`, if it spent any.
+    pub fn persist_block_nullifiers(&self, height: u64) {
+        let block = match self.nullifiers.lock().unwrap().block(height) {
+            Some(block) => block,
+            None => return,
+        };
+
+        /***************** POstgreSQL Insert Code *********/
+        let store = self.store();
+        let treadpool_sql_queue = self.sql_queue.lock().unwrap();
+        treadpool_sql_queue.execute(move || {
+            match store.insert_block_nullifiers(&block) {
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to insert_block_nullifiers: {}", e),
+            };
+        });
+        drop(treadpool_sql_queue);
+        /**************** POstgreSQL Insert Code End **********/
+    }
+
+    /// Drops the nullifiers of the blocks above `height` from memory and from the
+    /// store, on a reorg to `height`. Returns the number dropped from memory.
+    pub fn rollback_nullifiers(&self, height: u64) -> usize {
+        let dropped = self.nullifiers.lock().unwrap().rollback_to(height);
+
+        /***************** POstgreSQL Insert Code *********/
+        let store = self.store();
+        let treadpool_sql_queue = self.sql_queue.lock().unwrap();
+        treadpool_sql_queue.execute(move || {
+            match store.delete_block_nullifiers_above(height) {
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to delete_block_nullifiers_above: {}", e),
+            };
+        });
+        drop(treadpool_sql_queue);
+        /**************** POstgreSQL Insert Code End **********/
+        dropped
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nullifier(byte: u8) -> Hash {
+        Hash([byte; 32])
+    }
+
+    #[test]
+    fn input_nullifiers_test() {
+        use curve25519_dalek::scalar::Scalar;
+        use quisquislib::accounts::Account;
+        use zkvm::zkos_types::Utxo;
+
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(5u64));
+        let utxo = Utxo::random();
+        let input = Input::input_from_quisquis_account(&account, utxo, 0, Default::default());
+        // the witness index is not bound by a proof, a coin is nullified whatever it carries
+        let mut marked = input.clone();
+        marked.replace_witness_index(u8::MAX);
+        let fresh =
+            Input::input_from_quisquis_account(&account, Utxo::default(), 0, Default::default());
+        let nullifiers = input_nullifiers(&[input.clone(), marked, fresh]);
+        assert_eq!(nullifiers.len(), 2);
+        assert_eq!(nullifiers[0], nullifiers[1]);
+        assert_eq!(Some(nullifiers[0]), input.nullifier());
+    }
+
+    #[test]
+    fn nullifier_index_test() {
+        let mut index = NullifierIndex::new(3);
+        index.insert(10, "aa", &[nullifier(1), nullifier(2)]);
+        index.insert(11, "bb", &[nullifier(3)]);
+        // a coin keeps its first spender
+        index.insert(11, "cc", &[nullifier(1)]);
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.spender(&nullifier(1)), Some(("aa", 10)));
+
+        assert_eq!(
+            index.conflict("dd", &[nullifier(4), nullifier(3)]),
+            Some(NullifierConflict {
+                txid: "bb".to_string(),
+                height: 11
+            })
+        );
+        assert_eq!(index.conflict("bb", &[nullifier(3)]), None);
+        assert_eq!(
+            index.conflict("dd", &[nullifier(2)]).unwrap().to_string(),
+            "input already spent by tx aa in block 10"
+        );
+
+        // round trip through the persisted form
+        let block = index.block(10).unwrap();
+        assert_eq!(block.entries.len(), 2);
+        let mut loaded = NullifierIndex::new(3);
+        loaded.insert_block(&block);
+        assert_eq!(loaded.spender(&nullifier(2)), Some(("aa", 10)));
+
+        // blocks below the depth are dropped
+        index.insert(13, "ee", &[nullifier(5)]);
+        assert_eq!(index.spender(&nullifier(1)), None);
+        assert_eq!(index.spender(&nullifier(3)), Some(("bb", 11)));
+    }
+
+    #[test]
+    fn nullifier_rollback_test() {
+        let mut index = NullifierIndex::new(10);
+        index.insert(10, "aa", &[nullifier(1)]);
+        index.insert(11, "bb", &[nullifier(2), nullifier(3)]);
+        index.insert(12, "cc", &[nullifier(4)]);
+
+        assert_eq!(index.rollback_to(10), 3);
+        assert_eq!(index.len(), 1);
+        assert!(index.block(11).is_none());
+        assert_eq!(index.conflict("dd", &[nullifier(2)]), None);
+        assert!(index.conflict("dd", &[nullifier(1)]).is_some());
+
+        // the coins of the dropped blocks can be spent by the new branch
+        index.insert(11, "dd", &[nullifier(2)]);
+        assert_eq!(index.spender(&nullifier(2)), Some(("dd", 11)));
+    }
+}
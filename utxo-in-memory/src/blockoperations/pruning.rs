@@ -0,0 +1,479 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Opt-in pruning of expired memo outputs.
+//!
+//! A memo output with timebounds of a script address with a pruning policy may be
+//! pruned once the chain is more than `grace` blocks past its timebounds. Memos
+//! without timebounds, and memos of script addresses without a policy, are kept.
+//!
+//! The grace of a script address is read from the `prune_grace` metadata of its
+//! registered contract, or from `MEMO_PRUNE_GRACE`, a comma separated list of
+//! `script_address:blocks` taking precedence over the registry.
+//!
+//! A background task periodically removes the prunable memos from the utxo set and
+//! moves their rows from `utxo_memo_logs` to the `pruned_outputs` archive table.
+//! `restore_pruned_output` moves one back. Every prune and restore is recorded in
+//! the `prune_logs` table.
+
+use crate::db::{utxo_key, KeyId, LocalDBtrait, LocalStorage};
+use crate::error::UtxosetError;
+use crate::pgsql::{
+    archive_pruned_outputs_in_psql, get_pruned_output_from_psql, insert_prune_logs_in_psql,
+    restore_pruned_output_in_psql, THREADPOOL_SQL_QUEUE,
+};
+use crate::scriptregistry::{ScriptRegistry, SCRIPT_REGISTRY};
+use crate::threadpool::env_or;
+use crate::UTXO_STORAGE;
+use prometheus::{register_counter, Counter};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+use zkvm::zkos_types::{IOType, Output, Utxo};
+
+/// Contract metadata key of the grace of its script addresses.
+pub const PRUNE_GRACE_METADATA: &str = "prune_grace";
+
+/// Default seconds between two scans of the memo partition.
+pub const DEFAULT_PRUNE_INTERVAL_SECS: usize = 600;
+
+/// Number of most recent prune logs kept in memory.
+pub const PRUNE_LOG_RETENTION: usize = 10_000;
+
+lazy_static! {
+    pub static ref PRUNE_LOGS: Mutex<PruneLogStore> =
+        Mutex::new(PruneLogStore::new(PRUNE_LOG_RETENTION));
+    pub static ref TOTAL_PRUNED_OUTPUTS: Counter = register_counter!(
+        "pruned_memo_count",
+        "A counter for memo outputs pruned from the utxo set"
+    )
+    .unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PruneAction {
+    Prune,
+    Restore,
+}
+
+impl PruneAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PruneAction::Prune => "prune",
+            PruneAction::Restore => "restore",
+        }
+    }
+
+    pub fn from_str(action: &str) -> Result<PruneAction, &'static str> {
+        match action {
+            "prune" => Ok(PruneAction::Prune),
+            "restore" => Ok(PruneAction::Restore),
+            _ => Err("Error::Invalid prune action"),
+        }
+    }
+}
+
+/// A memo output pruned from, or restored to, the utxo set at `block_height`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PruneLog {
+    pub block_height: u64,
+    pub action: PruneAction,
+    pub utxo: String,
+    pub script_address: String,
+    pub timebounds: u32,
+}
+
+impl PruneLog {
+    fn new(block_height: u64, action: PruneAction, key: &KeyId, output: &Output) -> Self {
+        let (script_address, timebounds) = match output.as_out_memo() {
+            Some(memo) => (memo.script_address.clone(), memo.timebounds),
+            None => (String::new(), 0),
+        };
+        let utxo = match Utxo::from_bytes(key) {
+            Some(utxo) => utxo.to_string(),
+            None => hex::encode(key),
+        };
+        PruneLog {
+            block_height,
+            action,
+            utxo,
+            script_address,
+            timebounds,
+        }
+    }
+}
+
+impl fmt::Display for PruneLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "height={} action={} utxo={} script_address={} timebounds={}",
+            self.block_height,
+            self.action.as_str(),
+            self.utxo,
+            self.script_address,
+            self.timebounds
+        )
+    }
+}
+
+/// In-memory store of the latest prune logs, oldest first.
+#[derive(Debug, Clone)]
+pub struct PruneLogStore {
+    pub logs: VecDeque<PruneLog>,
+    pub retention: usize,
+}
+
+impl PruneLogStore {
+    pub fn new(retention: usize) -> Self {
+        PruneLogStore {
+            logs: VecDeque::new(),
+            retention,
+        }
+    }
+
+    pub fn insert(&mut self, log: PruneLog) {
+        self.logs.push_back(log);
+        while self.logs.len() > self.retention {
+            self.logs.pop_front();
+        }
+    }
+}
+
+/// Grace in blocks by script address.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PruningPolicy {
+    pub grace: HashMap<String, u64>,
+}
+
+impl PruningPolicy {
+    /// Parses a comma separated list of `script_address:blocks`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut grace = HashMap::new();
+        for entry in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (address, blocks) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("Expected script_address:blocks, got {}", entry))?;
+            let blocks = blocks
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid grace of {}", address))?;
+            grace.insert(address.trim().to_string(), blocks);
+        }
+        Ok(PruningPolicy { grace })
+    }
+
+    /// Grace of the script addresses of the contracts with a `prune_grace` metadata.
+    pub fn from_registry(registry: &ScriptRegistry) -> Self {
+        let mut grace = HashMap::new();
+        for contract in registry.list() {
+            if let Some(blocks) = contract.metadata[PRUNE_GRACE_METADATA].as_u64() {
+                for address in contract.addresses() {
+                    grace.insert(address.clone(), blocks);
+                }
+            }
+        }
+        PruningPolicy { grace }
+    }
+
+    /// Policy of the registry, overridden by `MEMO_PRUNE_GRACE`.
+    pub fn current() -> Self {
+        let mut policy = PruningPolicy::from_registry(&SCRIPT_REGISTRY.lock().unwrap());
+        if let Some(spec) = crate::config::config_value("MEMO_PRUNE_GRACE") {
+            match PruningPolicy::parse(&spec) {
+                Ok(env_policy) => policy.grace.extend(env_policy.grace),
+                Err(e) => eprintln!("Ignoring MEMO_PRUNE_GRACE, {}", e),
+            }
+        }
+        policy
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grace.is_empty()
+    }
+
+    /// Whether `output` may be pruned at block `height`: it is a memo with timebounds,
+    /// its script address has a policy and `height` is past `timebounds + grace`.
+    pub fn is_prunable(&self, output: &Output, height: u64) -> bool {
+        let memo = match output.as_out_memo() {
+            Some(memo) => memo,
+            None => return false,
+        };
+        if memo.timebounds == 0 {
+            return false;
+        }
+        match self.grace.get(&memo.script_address) {
+            Some(grace) => height > (memo.timebounds as u64).saturating_add(*grace),
+            None => false,
+        }
+    }
+}
+
+/// Removes the prunable memo outputs of `storage` at block `height`.
+pub fn prune_memo_outputs(
+    storage: &mut LocalStorage<Output>,
+    policy: &PruningPolicy,
+    height: u64,
+) -> Vec<(KeyId, PruneLog)> {
+    let input_type = IOType::Memo as usize;
+    let pruned: Vec<(KeyId, PruneLog)> = match storage.data.get(&input_type) {
+        Some(partition) => partition
+            .iter()
+            .filter(|(_, output)| policy.is_prunable(output, height))
+            .map(|(key, output)| {
+                let log = PruneLog::new(height, PruneAction::Prune, key, output);
+                (key.clone(), log)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    for (key, _) in pruned.iter() {
+        let _ = storage.remove(key.clone(), input_type);
+    }
+    pruned
+}
+
+/// Adds the pruned memo `output` back to `storage`. Fails if `key` is in the set.
+pub fn restore_memo_output(
+    storage: &mut LocalStorage<Output>,
+    key: KeyId,
+    output: Output,
+    height: u64,
+) -> Result<PruneLog, UtxosetError> {
+    let input_type = IOType::Memo as usize;
+    if output.as_out_memo().is_none() {
+        return Err(UtxosetError::UtxoNotFound);
+    }
+    if storage.search_key(&key, input_type)? {
+        return Err(UtxosetError::UtxoAlreadyExists);
+    }
+    let log = PruneLog::new(height, PruneAction::Restore, &key, &output);
+    storage.add(key, output, input_type)?;
+    Ok(log)
+}
+
+fn record_prune_logs(logs: Vec<PruneLog>) {
+    let mut store = PRUNE_LOGS.lock().unwrap();
+    for log in logs.iter() {
+        println!("memo pruning {}", log);
+        store.insert(log.clone());
+    }
+    drop(store);
+
+    /***************** POstgreSQL Insert Code *********/
+    let treadpool_sql_queue = THREADPOOL_SQL_QUEUE.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match insert_prune_logs_in_psql(&logs) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_prune_logs_in_psql: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+/// Prunes the expired memos of the utxo set under the current policy.
+pub fn prune_expired_memos() -> Vec<PruneLog> {
+    let policy = PruningPolicy::current();
+    if policy.is_empty() {
+        return Vec::new();
+    }
+    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let height = utxo_storage.block_height as u64;
+    let pruned = prune_memo_outputs(&mut utxo_storage, &policy, height);
+    drop(utxo_storage);
+    if pruned.is_empty() {
+        return Vec::new();
+    }
+    TOTAL_PRUNED_OUTPUTS.inc_by(pruned.len() as f64);
+    let (keys, logs): (Vec<KeyId>, Vec<PruneLog>) = pruned.into_iter().unzip();
+
+    // queued behind the inserts of the blocks that created the memos
+    /***************** POstgreSQL Insert Code *********/
+    let treadpool_sql_queue = THREADPOOL_SQL_QUEUE.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match archive_pruned_outputs_in_psql(keys, height) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to archive_pruned_outputs_in_psql: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+
+    record_prune_logs(logs.clone());
+    logs
+}
+
+/// Moves the pruned memo `utxo` from the `pruned_outputs` archive back to the utxo set.
+pub fn restore_pruned_output(utxo: &Utxo) -> Result<PruneLog, UtxosetError> {
+    let key = utxo_key(utxo);
+    let output = get_pruned_output_from_psql(&key)?.ok_or(UtxosetError::UtxoNotFound)?;
+    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    let height = utxo_storage.block_height as u64;
+    if utxo_storage.search_key(&key, IOType::Memo as usize)? {
+        return Err(UtxosetError::UtxoAlreadyExists);
+    }
+    // the archive row is moved first, so a failed restore leaves the output archived
+    restore_pruned_output_in_psql(&key)?;
+    let log = restore_memo_output(&mut utxo_storage, key, output, height)?;
+    drop(utxo_storage);
+    record_prune_logs(vec![log.clone()]);
+    Ok(log)
+}
+
+/// Scans the memo partition every `MEMO_PRUNE_INTERVAL_SECS` seconds.
+pub fn memo_pruning_task() {
+    let interval = env_or("MEMO_PRUNE_INTERVAL_SECS", DEFAULT_PRUNE_INTERVAL_SECS);
+    loop {
+        std::thread::sleep(Duration::from_secs(interval as u64));
+        let pruned = prune_expired_memos();
+        if !pruned.is_empty() {
+            println!("pruned {} expired memo outputs", pruned.len());
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scriptregistry::ScriptContract;
+    use address::{Address, Network};
+    use zkvm::zkos_types::{OutputData, OutputMemo};
+    use zkvm::{Commitment, Hash, TxID};
+
+    fn script_address(seed: u8) -> String {
+        Address::script_address(Network::default(), [seed; 32]).as_hex()
+    }
+
+    fn memo(script_address: &str, timebounds: u32) -> Output {
+        Output::memo(OutputData::Memo(OutputMemo {
+            script_address: script_address.to_string(),
+            owner: script_address.to_string(),
+            commitment: Commitment::blinded(10u64),
+            data: None,
+            timebounds,
+        }))
+    }
+
+    fn key(index: u16) -> KeyId {
+        Utxo::new(TxID(Hash([7u8; 32])), index).to_bytes()
+    }
+
+    #[test]
+    fn prune_eligibility_test() {
+        let orders = script_address(1);
+        let policy = PruningPolicy::parse(&format!("{}:10", orders)).unwrap();
+        let output = memo(&orders, 50);
+
+        // prunable once the chain is past timebounds + grace
+        assert!(!policy.is_prunable(&output, 59));
+        assert!(!policy.is_prunable(&output, 60));
+        assert!(policy.is_prunable(&output, 61));
+
+        // memos without timebounds and of addresses without a policy are kept
+        assert!(!policy.is_prunable(&memo(&orders, 0), u64::MAX));
+        assert!(!policy.is_prunable(&memo(&script_address(2), 50), u64::MAX));
+        // no overflow at the largest timebounds and grace
+        let policy = PruningPolicy::parse(&format!("{}:{}", orders, u64::MAX)).unwrap();
+        assert!(!policy.is_prunable(&memo(&orders, u32::MAX), u64::MAX));
+
+        assert!(PruningPolicy::parse("").unwrap().is_empty());
+        assert!(PruningPolicy::parse("address").is_err());
+        assert!(PruningPolicy::parse("address:x").is_err());
+    }
+
+    #[test]
+    fn pruning_policy_from_registry_test() {
+        let programs: Vec<Vec<u8>> = transaction::programs::create_program_tree()
+            .iter()
+            .map(|program| program.to_bytes())
+            .collect();
+        let mut registry = ScriptRegistry::new();
+        let contract = ScriptContract::new(
+            "orders".to_string(),
+            &programs,
+            serde_json::json!({ PRUNE_GRACE_METADATA: 100 }),
+        )
+        .unwrap();
+        registry.insert(contract.clone());
+        let policy = PruningPolicy::from_registry(&registry);
+        assert_eq!(policy.grace.get(&contract.mainnet_address), Some(&100));
+        assert_eq!(policy.grace.get(&contract.testnet_address), Some(&100));
+
+        let mut registry = ScriptRegistry::new();
+        registry.insert(
+            ScriptContract::new("orders".to_string(), &programs, serde_json::Value::Null).unwrap(),
+        );
+        assert!(PruningPolicy::from_registry(&registry).is_empty());
+    }
+
+    #[test]
+    fn prune_and_restore_test() {
+        let orders = script_address(3);
+        let policy = PruningPolicy::parse(&format!("{}:10", orders)).unwrap();
+        let mut storage = LocalStorage::<Output>::new(3);
+        let memo_type = IOType::Memo as usize;
+        storage.add(key(0), memo(&orders, 50), memo_type).unwrap();
+        storage.add(key(1), memo(&orders, 80), memo_type).unwrap();
+        storage.add(key(2), memo(&orders, 0), memo_type).unwrap();
+        storage
+            .add(key(3), memo(&script_address(4), 50), memo_type)
+            .unwrap();
+
+        assert!(prune_memo_outputs(&mut storage, &policy, 60).is_empty());
+        let pruned = prune_memo_outputs(&mut storage, &policy, 61);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].0, key(0));
+        assert_eq!(pruned[0].1.action, PruneAction::Prune);
+        assert_eq!(pruned[0].1.timebounds, 50);
+        assert_eq!(pruned[0].1.script_address, orders);
+        assert_eq!(storage.get_count_by_type(memo_type), 3);
+        assert!(!storage.search_key(&key(0), memo_type).unwrap());
+
+        let log = restore_memo_output(&mut storage, key(0), memo(&orders, 50), 75).unwrap();
+        assert_eq!(log.action, PruneAction::Restore);
+        assert_eq!(log.block_height, 75);
+        assert_eq!(
+            storage.get_utxo_by_id(key(0), memo_type).unwrap(),
+            memo(&orders, 50)
+        );
+        // a restored output can not be restored twice
+        assert!(matches!(
+            restore_memo_output(&mut storage, key(0), memo(&orders, 50), 75),
+            Err(UtxosetError::UtxoAlreadyExists)
+        ));
+        // the next scan prunes both expired memos
+        assert_eq!(prune_memo_outputs(&mut storage, &policy, 91).len(), 2);
+    }
+
+    #[test]
+    fn prune_log_store_test() {
+        let mut store = PruneLogStore::new(2);
+        for height in 0..3 {
+            store.insert(PruneLog::new(
+                height,
+                PruneAction::Prune,
+                &key(0),
+                &memo("", 1),
+            ));
+        }
+        assert_eq!(store.logs.len(), 2);
+        assert_eq!(store.logs[0].block_height, 1);
+        assert_eq!(
+            store.logs[1].to_string(),
+            format!(
+                "height=2 action=prune utxo={} script_address= timebounds=1",
+                Utxo::from_bytes(&key(0)).unwrap()
+            )
+        );
+    }
+}
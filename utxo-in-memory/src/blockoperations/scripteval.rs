@@ -0,0 +1,201 @@
+//! Read-only evaluation of a script program against utxos of the utxo set.
+//!
+//! The inputs are resolved from the utxo set by their utxo id, the outputs and
+//! the tx_data are the candidates of a tx that is not built yet. The program runs
+//! without a proof or signatures, see `Verifier::eval_program_bundle`. Stored
+//! outputs carry closed commitments, so constraints over the values of the inputs
+//! are reported as unresolved rather than satisfied.
+//!
+//! Programs are picked from the relayer program tree by index. Raw bytecode is
+//! only accepted if `EVAL_SCRIPT_RAW_PROGRAMS` is set to a non zero value.
+
+use crate::db::{utxo_key, LocalDBtrait, LocalStorage};
+use crate::threadpool::env_or;
+use crate::UTXO_STORAGE;
+use serde_derive::{Deserialize, Serialize};
+use transaction::programs::create_program_tree;
+use transaction::vm_run::{EvalResult, Verifier};
+use zkvm::zkos_types::{IOType, Input, InputData, Output, Utxo};
+
+lazy_static! {
+    /// Accept raw program bytecode in `eval_script`, meant for debugging programs
+    pub static ref EVAL_RAW_PROGRAMS: bool = {
+        env_or("EVAL_SCRIPT_RAW_PROGRAMS", 0) != 0
+    };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EvalInput {
+    pub utxo: Utxo,
+    /// Additional variables of a State input
+    #[serde(default)]
+    pub script_data: Option<Vec<zkvm::String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EvalScriptQuery {
+    /// Position of the program in the relayer program tree
+    #[serde(default)]
+    pub program_index: Option<usize>,
+    /// Hex encoded bytecode, used if no program index is given
+    #[serde(default)]
+    pub program: Option<String>,
+    pub inputs: Vec<EvalInput>,
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+    #[serde(default)]
+    pub tx_data: Option<zkvm::String>,
+    /// Initialize the stack for a contract deploy
+    #[serde(default)]
+    pub contract_deploy: bool,
+}
+
+/// Bytecode of the program selected by `query`.
+fn query_program(query: &EvalScriptQuery, allow_raw: bool) -> Result<Vec<u8>, String> {
+    match (query.program_index, &query.program) {
+        (Some(index), _) => create_program_tree()
+            .get(index)
+            .map(|program| program.to_bytes())
+            .ok_or_else(|| format!("No program at index {}", index)),
+        (None, Some(_)) if !allow_raw => Err("Raw programs are not enabled".to_string()),
+        (None, Some(program)) => {
+            hex::decode(program.trim()).map_err(|e| format!("Invalid program hex, {:?}", e))
+        }
+        (None, None) => Err("Expected a program index or program bytecode".to_string()),
+    }
+}
+
+/// Input spending the stored output of `input.utxo`.
+fn resolve_input(
+    utxo_storage: &mut LocalStorage<Output>,
+    input: &EvalInput,
+) -> Result<Input, String> {
+    let key = utxo_key(&input.utxo);
+    for io_type in [IOType::Coin, IOType::Memo, IOType::State].iter() {
+        let output = match utxo_storage.get_utxo_by_id(key.clone(), io_type.to_usize()) {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        let resolved = match io_type {
+            IOType::Coin => output
+                .as_out_coin()
+                .map(|coin| coin.to_input(input.utxo, 0)),
+            IOType::Memo => output
+                .as_out_memo()
+                .map(|memo| Input::memo(InputData::memo(input.utxo, memo.clone(), 0, None))),
+            IOType::State => output.as_out_state().map(|state| {
+                Input::state(InputData::state(
+                    input.utxo,
+                    state.clone(),
+                    input.script_data.clone(),
+                    0,
+                ))
+            }),
+        };
+        return resolved.ok_or_else(|| format!("Invalid output for utxo {}", input.utxo));
+    }
+    Err(format!("Utxo {} not found", input.utxo))
+}
+
+/// Evaluates the program of `query` against the utxos of `utxo_storage`.
+pub fn eval_script_in(
+    utxo_storage: &mut LocalStorage<Output>,
+    query: &EvalScriptQuery,
+    allow_raw: bool,
+) -> Result<EvalResult, String> {
+    let program = query_program(query, allow_raw)?;
+    let inputs = query
+        .inputs
+        .iter()
+        .map(|input| resolve_input(utxo_storage, input))
+        .collect::<Result<Vec<Input>, String>>()?;
+    Verifier::eval_program_bundle(
+        &[program],
+        &inputs,
+        &query.outputs,
+        query.contract_deploy,
+        query.tx_data.clone(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Evaluates the program of `query` against the utxo set.
+pub fn eval_script(query: &EvalScriptQuery) -> Result<EvalResult, String> {
+    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+    eval_script_in(&mut utxo_storage, query, *EVAL_RAW_PROGRAMS)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+    use zkvm::Program;
+
+    // a coin of a random account in `utxo_storage`
+    fn stored_coin(utxo_storage: &mut LocalStorage<Output>) -> Utxo {
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(5u64));
+        let output = Output::from_quisquis_account(account, address::Network::Testnet);
+        let utxo = Utxo::random();
+        utxo_storage
+            .add(utxo.to_bytes(), output, IOType::Coin as usize)
+            .unwrap();
+        utxo
+    }
+
+    fn raw_query(utxo: Utxo, a: u64, b: u64) -> EvalScriptQuery {
+        let program = Program::build(|p| {
+            p.push(a).scalar().push(b).scalar().eq().verify();
+        });
+        EvalScriptQuery {
+            program_index: None,
+            program: Some(hex::encode(program.to_bytes())),
+            inputs: vec![EvalInput {
+                utxo,
+                script_data: None,
+            }],
+            outputs: Vec::new(),
+            tx_data: None,
+            contract_deploy: false,
+        }
+    }
+
+    #[test]
+    fn eval_script_test() {
+        let mut utxo_storage = LocalStorage::<Output>::new(3);
+        let utxo = stored_coin(&mut utxo_storage);
+
+        let result = eval_script_in(&mut utxo_storage, &raw_query(utxo, 3, 3), true).unwrap();
+        assert_eq!(result.satisfiable, Some(true));
+        // the items of the coin input are left on the stack
+        assert!(!result.stack.is_empty());
+
+        let result = eval_script_in(&mut utxo_storage, &raw_query(utxo, 3, 4), true).unwrap();
+        assert_eq!(result.satisfiable, Some(false));
+        assert!(result.error.is_some());
+    }
+
+    #[test]
+    fn eval_script_query_test() {
+        let mut utxo_storage = LocalStorage::<Output>::new(3);
+        let utxo = stored_coin(&mut utxo_storage);
+
+        // raw bytecode needs the debug flag
+        assert_eq!(
+            eval_script_in(&mut utxo_storage, &raw_query(utxo, 3, 3), false),
+            Err("Raw programs are not enabled".to_string())
+        );
+        let mut query = raw_query(utxo, 3, 3);
+        query.program = None;
+        query.program_index = Some(create_program_tree().len());
+        assert!(eval_script_in(&mut utxo_storage, &query, false).is_err());
+
+        let query = raw_query(Utxo::random(), 3, 3);
+        assert!(eval_script_in(&mut utxo_storage, &query, true)
+            .unwrap_err()
+            .ends_with("not found"));
+    }
+}
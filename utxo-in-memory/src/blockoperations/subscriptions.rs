@@ -0,0 +1,333 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Registry of the subscriptions to the utxos of an address.
+//!
+//! Block processing publishes an event for every utxo it adds or removes. The
+//! event is queued in the bounded outbox of every subscriber of the owner
+//! address, and kept in a bounded history so a subscriber reconnecting can
+//! replay the events it missed with `since_height`, and a wallet can read the
+//! utxo history of its addresses with `getUtxoHistory`.
+
+use crate::state::global_state;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use zkvm::zkos_types::{IOType, Output, Utxo};
+
+/// Max number of events queued for a subscriber.
+pub const SUBSCRIPTION_OUTBOX_CAPACITY: usize = 1024;
+
+/// Number of most recent events kept for replay.
+pub const UTXO_EVENT_RETENTION: usize = 10_000;
+
+lazy_static! {
+    /// Subscriptions of the global state, see [`crate::state`].
+    pub static ref UTXO_SUBSCRIPTIONS: Arc<Mutex<SubscriptionRegistry>> =
+        global_state().subscriptions.clone();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UtxoEventKind {
+    Added,
+    Removed,
+}
+
+/// A utxo of `address` added or removed by the tx `txid` of the block at `height`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UtxoEvent {
+    pub address: String,
+    pub utxo: Utxo,
+    pub output: Output,
+    pub io_type: IOType,
+    pub height: u64,
+    pub event: UtxoEventKind,
+    /// Hex encoded id of the tx, missing in the events of older nodes
+    #[serde(default)]
+    pub txid: String,
+    /// Time of the block header, empty if the block carries no header
+    #[serde(default)]
+    pub time: String,
+}
+
+impl UtxoEvent {
+    /// Event of a utxo, None if the output has no owner.
+    pub fn new(
+        utxo: Utxo,
+        output: Output,
+        txid: &str,
+        height: u64,
+        event: UtxoEventKind,
+    ) -> Option<Self> {
+        let address = output.output.get_owner_address()?.clone();
+        Some(UtxoEvent {
+            address,
+            utxo,
+            io_type: output.out_type,
+            output,
+            height,
+            event,
+            txid: txid.to_string(),
+            time: String::new(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Subscriber {
+    addresses: HashSet<String>,
+    outbox: VecDeque<UtxoEvent>,
+    // events were dropped, the subscriber has to reconnect
+    overflowed: bool,
+}
+
+/// Subscribers by id and the history of the most recent events.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionRegistry {
+    pub outbox_capacity: usize,
+    pub retention: usize,
+    next_id: u64,
+    subscribers: HashMap<u64, Subscriber>,
+    history: VecDeque<UtxoEvent>,
+    // height of the most recent event evicted from the history
+    evicted_height: Option<u64>,
+}
+
+impl SubscriptionRegistry {
+    pub fn new(outbox_capacity: usize, retention: usize) -> Self {
+        SubscriptionRegistry {
+            outbox_capacity,
+            retention,
+            next_id: 0,
+            subscribers: HashMap::new(),
+            history: VecDeque::new(),
+            evicted_height: None,
+        }
+    }
+
+    /// Registers a subscriber of `addresses` and returns its id.
+    /// With `since_height` the retained events from that height on are queued first.
+    pub fn subscribe(
+        &mut self,
+        addresses: Vec<String>,
+        since_height: Option<u64>,
+    ) -> Result<u64, &'static str> {
+        if addresses.is_empty() {
+            return Err("Error::No address to subscribe to");
+        }
+        let addresses: HashSet<String> = addresses.into_iter().collect();
+        let mut outbox = VecDeque::new();
+        if let Some(since_height) = since_height {
+            if matches!(self.evicted_height, Some(evicted) if evicted >= since_height) {
+                return Err("Error::since_height is older than the retained events");
+            }
+            outbox.extend(
+                self.history
+                    .iter()
+                    .filter(|event| {
+                        event.height >= since_height && addresses.contains(&event.address)
+                    })
+                    .cloned(),
+            );
+            if outbox.len() > self.outbox_capacity {
+                return Err("Error::Too many events to replay");
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.insert(
+            id,
+            Subscriber {
+                addresses,
+                outbox,
+                overflowed: false,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Removes a subscriber, e.g. on disconnect.
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        self.subscribers.remove(&id).is_some()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    /// Queues the events for the subscribers of their address and keeps them for replay.
+    /// A subscriber with a full outbox is marked as overflowed and gets no more events.
+    pub fn publish(&mut self, events: Vec<UtxoEvent>) {
+        for event in events {
+            for subscriber in self.subscribers.values_mut() {
+                if subscriber.overflowed || !subscriber.addresses.contains(&event.address) {
+                    continue;
+                }
+                if subscriber.outbox.len() >= self.outbox_capacity {
+                    subscriber.overflowed = true;
+                    subscriber.outbox.clear();
+                    continue;
+                }
+                subscriber.outbox.push_back(event.clone());
+            }
+            self.history.push_back(event);
+            while self.history.len() > self.retention {
+                if let Some(evicted) = self.history.pop_front() {
+                    self.evicted_height = Some(evicted.height);
+                }
+            }
+        }
+    }
+
+    /// Takes the queued events of a subscriber.
+    /// Fails if the subscriber is unknown or its outbox overflowed.
+    pub fn drain(&mut self, id: u64) -> Result<Vec<UtxoEvent>, &'static str> {
+        let subscriber = self
+            .subscribers
+            .get_mut(&id)
+            .ok_or("Error::Unknown subscription")?;
+        if subscriber.overflowed {
+            return Err("Error::Subscription outbox overflowed, resubscribe with since_height");
+        }
+        Ok(subscriber.outbox.drain(..).collect())
+    }
+
+    /// Retained events of the txs of the blocks `from_height..=to_height` adding or
+    /// removing a utxo of `address`, or listed in `txids`, in block order. All the
+    /// events of those txs are included, e.g. the output a transfer gives the owner
+    /// under an updated address. Fails if `from_height` is no longer retained.
+    pub fn history(
+        &self,
+        address: &str,
+        txids: &[String],
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<UtxoEvent>, &'static str> {
+        if matches!(self.evicted_height, Some(evicted) if evicted >= from_height) {
+            return Err("Error::from_height is older than the retained events");
+        }
+        let in_range =
+            |event: &&UtxoEvent| event.height >= from_height && event.height <= to_height;
+        let mut txs: HashSet<&str> = txids.iter().map(String::as_str).collect();
+        txs.extend(
+            self.history
+                .iter()
+                .filter(in_range)
+                .filter(|event| event.address == address)
+                .map(|event| event.txid.as_str()),
+        );
+        Ok(self
+            .history
+            .iter()
+            .filter(in_range)
+            .filter(|event| txs.contains(event.txid.as_str()))
+            .cloned()
+            .collect())
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+    use zkvm::zkos_types::{OutputCoin, OutputData};
+    use zkvm::Hash;
+
+    fn event(address: &str, height: u64, kind: UtxoEventKind) -> UtxoEvent {
+        let (acc, _) = Account::generate_random_account_with_value(Scalar::from(5u64));
+        let (_, encrypt) = acc.get_account();
+        let output = Output::coin(OutputData::Coin(OutputCoin {
+            encrypt,
+            owner: address.to_string(),
+        }));
+        let mut id = [0u8; 32];
+        id[..8].copy_from_slice(&height.to_be_bytes());
+        let txid = hex::encode(id);
+        UtxoEvent::new(Utxo::from_hash(Hash(id), 0), output, &txid, height, kind).unwrap()
+    }
+
+    #[test]
+    fn subscription_publish_test() {
+        let mut registry = SubscriptionRegistry::new(2, 10);
+        assert!(registry.subscribe(Vec::new(), None).is_err());
+        let alice = registry.subscribe(vec!["alice".to_string()], None).unwrap();
+        let bob = registry.subscribe(vec!["bob".to_string()], None).unwrap();
+
+        registry.publish(vec![
+            event("alice", 1, UtxoEventKind::Added),
+            event("carol", 1, UtxoEventKind::Added),
+        ]);
+        let events = registry.drain(alice).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, UtxoEventKind::Added);
+        assert_eq!(events[0].io_type, IOType::Coin);
+        assert!(registry.drain(alice).unwrap().is_empty());
+        assert!(registry.drain(bob).unwrap().is_empty());
+
+        // the outbox of bob overflows
+        registry.publish(vec![
+            event("bob", 2, UtxoEventKind::Added),
+            event("bob", 3, UtxoEventKind::Removed),
+            event("bob", 4, UtxoEventKind::Added),
+        ]);
+        assert!(registry.drain(bob).is_err());
+
+        assert!(registry.unsubscribe(bob));
+        assert!(!registry.unsubscribe(bob));
+        assert!(registry.drain(bob).is_err());
+        assert_eq!(registry.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn subscription_replay_test() {
+        let mut registry = SubscriptionRegistry::new(10, 3);
+        registry.publish(vec![
+            event("alice", 1, UtxoEventKind::Added),
+            event("alice", 2, UtxoEventKind::Added),
+            event("bob", 2, UtxoEventKind::Added),
+            event("alice", 3, UtxoEventKind::Removed),
+        ]);
+        let alice = registry
+            .subscribe(vec!["alice".to_string()], Some(2))
+            .unwrap();
+        let events = registry.drain(alice).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].height, 3);
+
+        // the event of height 1 is no longer retained
+        assert!(registry
+            .subscribe(vec!["alice".to_string()], Some(1))
+            .is_err());
+    }
+    #[test]
+    fn utxo_history_test() {
+        let mut registry = SubscriptionRegistry::new(10, 5);
+        // the events of a height are of the same tx, alice pays carol at height 2
+        registry.publish(vec![
+            event("alice", 1, UtxoEventKind::Added),
+            event("alice", 2, UtxoEventKind::Removed),
+            event("carol", 2, UtxoEventKind::Added),
+            event("bob", 3, UtxoEventKind::Added),
+            event("alice", 4, UtxoEventKind::Added),
+        ]);
+
+        let history = registry.history("alice", &[], 1, 3).unwrap();
+        let addresses: Vec<&str> = history.iter().map(|e| e.address.as_str()).collect();
+        assert_eq!(addresses, vec!["alice", "alice", "carol"]);
+        assert_eq!(history[2].txid, history[1].txid);
+        assert_eq!(registry.history("alice", &[], 4, 10).unwrap().len(), 1);
+        assert!(registry.history("dave", &[], 0, 10).unwrap().is_empty());
+        // a tx listed for dave by the address index, e.g. dave received under an updated address
+        let listed = vec![event("bob", 3, UtxoEventKind::Added).txid];
+        assert_eq!(registry.history("dave", &listed, 0, 10).unwrap().len(), 1);
+
+        // the event of height 1 is evicted
+        registry.publish(vec![event("bob", 5, UtxoEventKind::Added)]);
+        assert!(registry.history("alice", &[], 1, 10).is_err());
+        assert_eq!(registry.history("alice", &[], 2, 10).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,203 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Merkle roots over the txids of processed blocks and inclusion proofs for light clients.
+
+use crate::state::global_state;
+use merlin::Transcript;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use zkvm::merkle::{Hash, Hasher, MerkleItem, MerkleTree, Path};
+
+/// Label used for hashing the txid tree of a block.
+pub const BLOCK_TX_ROOT_LABEL: &'static [u8] = b"ZkOS.BlockTxRoot";
+
+/// Number of most recent blocks kept in memory.
+pub const BLOCK_TX_ROOT_RETENTION: usize = 10_000;
+
+lazy_static! {
+    /// Block tx roots of the global state, see [`crate::state`].
+    pub static ref BLOCK_TX_ROOTS: Arc<Mutex<BlockTxRootStore>> =
+        global_state().block_tx_roots.clone();
+}
+
+/// Leaf of the block txid tree.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TxIdLeaf(pub [u8; 32]);
+
+impl MerkleItem for TxIdLeaf {
+    fn commit(&self, t: &mut Transcript) {
+        t.append_message(b"txid", &self.0);
+    }
+}
+
+/// Merkle root and ordered txids of a processed block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockTxRoot {
+    pub block_height: u64,
+    pub root: Hash,
+    pub txids: Vec<TxIdLeaf>,
+}
+
+impl BlockTxRoot {
+    pub fn new(block_height: u64, txids: Vec<TxIdLeaf>) -> Self {
+        let root = compute_tx_root(&txids);
+        BlockTxRoot {
+            block_height,
+            root,
+            txids,
+        }
+    }
+
+    /// Builds the inclusion proof of the txid at `index`.
+    pub fn inclusion_proof(&self, index: usize) -> Option<TxInclusionProof> {
+        if index >= self.txids.len() {
+            return None;
+        }
+        let hasher = Hasher::new(BLOCK_TX_ROOT_LABEL);
+        let path = Path::new(&self.txids, index, &hasher)?;
+        Some(TxInclusionProof {
+            block_height: self.block_height,
+            root: self.root,
+            index,
+            path,
+        })
+    }
+}
+
+/// Proof that a txid is included in the txid tree of a block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxInclusionProof {
+    pub block_height: u64,
+    pub root: Hash,
+    pub index: usize,
+    pub path: Path,
+}
+
+/// Computes the merkle root over the ordered txids of a block.
+pub fn compute_tx_root(txids: &[TxIdLeaf]) -> Hash {
+    MerkleTree::root(BLOCK_TX_ROOT_LABEL, txids.iter())
+}
+
+/// Verifies that `txid` is included under `root` using the provided proof.
+pub fn verify_inclusion(txid: [u8; 32], proof: &TxInclusionProof, root: &Hash) -> bool {
+    let hasher = Hasher::new(BLOCK_TX_ROOT_LABEL);
+    proof.path.verify_root(root, &TxIdLeaf(txid), &hasher)
+}
+
+/// In-memory store of the txid roots of the latest blocks.
+#[derive(Debug, Clone)]
+pub struct BlockTxRootStore {
+    pub roots: BTreeMap<u64, BlockTxRoot>,
+    pub tx_index: HashMap<[u8; 32], (u64, usize)>,
+    pub retention: usize,
+}
+
+impl BlockTxRootStore {
+    pub fn new(retention: usize) -> Self {
+        BlockTxRootStore {
+            roots: BTreeMap::new(),
+            tx_index: HashMap::new(),
+            retention,
+        }
+    }
+
+    /// Adds the root of a block and drops the oldest blocks beyond the retention limit.
+    /// A txid included again by a later block keeps pointing to that block.
+    pub fn insert(&mut self, block: BlockTxRoot) {
+        if let Some(old) = self.roots.remove(&block.block_height) {
+            self.remove_from_index(&old);
+        }
+        for (index, txid) in block.txids.iter().enumerate() {
+            self.tx_index.insert(txid.0, (block.block_height, index));
+        }
+        self.roots.insert(block.block_height, block);
+
+        while self.roots.len() > self.retention {
+            let oldest = match self.roots.keys().next() {
+                Some(height) => *height,
+                None => break,
+            };
+            if let Some(old) = self.roots.remove(&oldest) {
+                self.remove_from_index(&old);
+            }
+        }
+    }
+
+    // drops the txids of `old` still indexed to its height
+    fn remove_from_index(&mut self, old: &BlockTxRoot) {
+        for txid in old.txids.iter() {
+            let indexed = self.tx_index.get(&txid.0).map(|(height, _)| *height);
+            if indexed == Some(old.block_height) {
+                self.tx_index.remove(&txid.0);
+            }
+        }
+    }
+
+    pub fn get_root(&self, block_height: u64) -> Option<&BlockTxRoot> {
+        self.roots.get(&block_height)
+    }
+
+    pub fn inclusion_proof(&self, txid: &[u8; 32]) -> Option<TxInclusionProof> {
+        let (height, index) = self.tx_index.get(txid)?;
+        self.roots.get(height)?.inclusion_proof(*index)
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn synthetic_block(count: u8) -> BlockTxRoot {
+        let txids: Vec<TxIdLeaf> = (0..count).map(|i| TxIdLeaf([i; 32])).collect();
+        BlockTxRoot::new(7, txids)
+    }
+
+    #[test]
+    fn inclusion_proof_first_middle_last_test() {
+        let block = synthetic_block(7);
+        for index in [0usize, 3, 6] {
+            let proof = block.inclusion_proof(index).unwrap();
+            assert_eq!(proof.root, block.root);
+            assert!(verify_inclusion(block.txids[index].0, &proof, &block.root));
+        }
+    }
+
+    #[test]
+    fn inclusion_proof_tampered_path_test() {
+        let block = synthetic_block(7);
+        let mut proof = block.inclusion_proof(3).unwrap();
+        proof.path.neighbors[0] = Hash([0xff; 32]);
+        assert!(!verify_inclusion(block.txids[3].0, &proof, &block.root));
+
+        // proof of one txid does not verify another
+        let proof = block.inclusion_proof(3).unwrap();
+        assert!(!verify_inclusion(block.txids[4].0, &proof, &block.root));
+    }
+
+    #[test]
+    fn block_tx_root_store_retention_test() {
+        let mut store = BlockTxRootStore::new(2);
+        for height in 1..=3u64 {
+            store.insert(BlockTxRoot::new(height, vec![TxIdLeaf([height as u8; 32])]));
+        }
+        assert!(store.get_root(1).is_none());
+        assert!(store.inclusion_proof(&[1u8; 32]).is_none());
+        let proof = store.inclusion_proof(&[3u8; 32]).unwrap();
+        assert_eq!(proof.block_height, 3);
+        assert!(verify_inclusion([3u8; 32], &proof, &proof.root));
+
+        // a txid of an evicted block keeps the later block including it
+        store.insert(BlockTxRoot::new(4, vec![TxIdLeaf([3u8; 32])]));
+        store.insert(BlockTxRoot::new(5, vec![TxIdLeaf([5u8; 32])]));
+        assert!(store.get_root(3).is_none());
+        assert_eq!(store.inclusion_proof(&[3u8; 32]).unwrap().block_height, 4);
+        // as does one of a replaced block
+        store.insert(BlockTxRoot::new(6, vec![TxIdLeaf([5u8; 32])]));
+        store.insert(BlockTxRoot::new(5, vec![TxIdLeaf([7u8; 32])]));
+        assert_eq!(store.inclusion_proof(&[5u8; 32]).unwrap().block_height, 6);
+    }
+}
@@ -0,0 +1,271 @@
+#![allow(non_snake_case)]
+#![allow(missing_docs)]
+//! Lifecycle of the txs submitted to the node.
+//!
+//! A tx is `Received` by txCommit, `Queued` once its inputs are pending spends in the
+//! mempool and `Broadcast` once the Zkos Oracle accepted it. It ends `Confirmed` at
+//! its position in a block, `Failed` with the reason of its rejection by the node or
+//! by block processing, or `Replaced` by a pending tx paying more.
+//!
+//! Every transition is kept in memory for the latest txs and in the `tx_status` psql
+//! table, the status of a tx is the latest of its transitions.
+
+use crate::blockoperations::blockresult::BlockApplyResult;
+use crate::rpcaudit::unix_time;
+use crate::state::UtxoState;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Number of most recent txs whose transitions are kept in memory.
+pub const TX_STATUS_RETENTION: usize = 100_000;
+
+/// Status of a tx, serialized tagged by `status`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TxStatus {
+    Received,
+    Queued,
+    Broadcast,
+    Confirmed { height: u64, block_index: u32 },
+    Failed { reason: String },
+    Replaced { by_txid: String },
+    Unknown,
+}
+
+impl TxStatus {
+    /// Name of the status, the string served to the clients of the legacy status.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxStatus::Received => "received",
+            TxStatus::Queued => "queued",
+            TxStatus::Broadcast => "broadcast",
+            TxStatus::Confirmed { .. } => "confirmed",
+            TxStatus::Failed { .. } => "failed",
+            TxStatus::Replaced { .. } => "replaced",
+            TxStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// A transition of a tx to `status` at the unix time `timestamp`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxStatusRecord {
+    pub txid: String,
+    pub status: TxStatus,
+    pub timestamp: u64,
+}
+
+impl TxStatusRecord {
+    pub fn new(txid: &str, status: TxStatus) -> Self {
+        TxStatusRecord {
+            txid: txid.to_string(),
+            status,
+            timestamp: unix_time(),
+        }
+    }
+}
+
+/// Status of a tx as served by txStatus, `legacy_status` is the name of the status
+/// for the clients reading a plain string.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxStatusView {
+    pub txid: String,
+    pub status: TxStatus,
+    /// Unix time of the latest transition, None for an unknown tx
+    pub updated_at: Option<u64>,
+    pub legacy_status: String,
+}
+
+impl TxStatusView {
+    pub fn new(txid: &str, latest: Option<&TxStatusRecord>) -> Self {
+        let status = latest
+            .map(|record| record.status.clone())
+            .unwrap_or(TxStatus::Unknown);
+        TxStatusView {
+            txid: txid.to_string(),
+            legacy_status: status.as_str().to_string(),
+            status,
+            updated_at: latest.map(|record| record.timestamp),
+        }
+    }
+}
+
+/// Transitions of the latest txs, oldest first per tx.
+#[derive(Debug, Clone)]
+pub struct TxStatusStore {
+    pub retention: usize,
+    // txids in order of their first transition, for eviction
+    order: VecDeque<String>,
+    history: HashMap<String, Vec<TxStatusRecord>>,
+}
+
+impl TxStatusStore {
+    pub fn new(retention: usize) -> Self {
+        TxStatusStore {
+            retention,
+            order: VecDeque::new(),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Adds a transition, evicting the oldest tx once the retention is reached.
+    /// Returns false if the tx already has the status.
+    pub fn insert(&mut self, record: TxStatusRecord) -> bool {
+        match self.history.get_mut(&record.txid) {
+            Some(history) => {
+                if history.last().map(|last| &last.status) == Some(&record.status) {
+                    return false;
+                }
+                history.push(record);
+            }
+            None => {
+                self.order.push_back(record.txid.clone());
+                self.history.insert(record.txid.clone(), vec![record]);
+            }
+        }
+        while self.order.len() > self.retention {
+            match self.order.pop_front() {
+                Some(txid) => self.history.remove(&txid),
+                None => break,
+            };
+        }
+        true
+    }
+
+    pub fn latest(&self, txid: &str) -> Option<&TxStatusRecord> {
+        self.history.get(txid).and_then(|history| history.last())
+    }
+
+    pub fn history(&self, txid: &str) -> Option<&Vec<TxStatusRecord>> {
+        self.history.get(txid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl UtxoState {
+    /// Records the transition of the tx `txid` to `status`, unless it already has it.
+    pub fn record_tx_status(&self, txid: &str, status: TxStatus) {
+        let record = TxStatusRecord::new(txid, status);
+        if !self.tx_statuses.lock().unwrap().insert(record.clone()) {
+            return;
+        }
+
+        /***************** POstgreSQL Insert Code *********/
+        let store = self.store();
+        let treadpool_sql_queue = self.sql_queue.lock().unwrap();
+        treadpool_sql_queue.execute(move || {
+            match store.insert_tx_status(&record) {
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to insert_tx_status: {}", e),
+            };
+        });
+        drop(treadpool_sql_queue);
+        /**************** POstgreSQL Insert Code End **********/
+    }
+
+    /// Records the applied txs of the block result `result` as confirmed at their
+    /// position in `block_txids`, the txids of the block in block order, and the
+    /// failed txs as failed with their reason.
+    pub fn record_block_tx_statuses(&self, result: &BlockApplyResult, block_txids: &[String]) {
+        for txid in result.applied_txids.iter() {
+            let txid = hex::encode((txid.0).0);
+            let block_index = block_txids
+                .iter()
+                .position(|block_txid| *block_txid == txid)
+                .unwrap_or(0) as u32;
+            let status = TxStatus::Confirmed {
+                height: result.height,
+                block_index,
+            };
+            self.record_tx_status(&txid, status);
+        }
+        for (txid, reason) in result.failed.iter() {
+            let status = TxStatus::Failed {
+                reason: reason.clone(),
+            };
+            self.record_tx_status(&hex::encode((txid.0).0), status);
+        }
+    }
+
+    /// Transitions of the tx `txid`, oldest first, from memory for the latest txs
+    /// and from psql for older ones.
+    pub fn tx_status_history(&self, txid: &str) -> Vec<TxStatusRecord> {
+        if let Some(history) = self.tx_statuses.lock().unwrap().history(txid) {
+            return history.clone();
+        }
+        match self.store().get_tx_status_history(txid) {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!("Failed to get_tx_status_history: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Latest status of the tx `txid`, `Unknown` if it has no transition.
+    pub fn tx_status(&self, txid: &str) -> TxStatusView {
+        let history = self.tx_status_history(txid);
+        TxStatusView::new(txid, history.last())
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tx_status_serialization_test() {
+        let confirmed = TxStatus::Confirmed {
+            height: 7,
+            block_index: 2,
+        };
+        assert_eq!(
+            serde_json::to_value(&confirmed).unwrap(),
+            serde_json::json!({"status": "confirmed", "height": 7, "block_index": 2})
+        );
+        assert_eq!(
+            serde_json::to_value(&TxStatus::Queued).unwrap(),
+            serde_json::json!({"status": "queued"})
+        );
+        let replaced: TxStatus =
+            serde_json::from_str(r#"{"status": "replaced", "by_txid": "ab"}"#).unwrap();
+        assert_eq!(replaced.as_str(), "replaced");
+
+        let view = TxStatusView::new("ab", None);
+        assert_eq!(view.status, TxStatus::Unknown);
+        assert_eq!(view.legacy_status, "unknown");
+        assert_eq!(view.updated_at, None);
+    }
+
+    #[test]
+    fn tx_status_store_test() {
+        let mut store = TxStatusStore::new(2);
+        assert!(store.insert(TxStatusRecord::new("a", TxStatus::Received)));
+        assert!(store.insert(TxStatusRecord::new("a", TxStatus::Queued)));
+        // a repeated status is not a transition
+        assert!(!store.insert(TxStatusRecord::new("a", TxStatus::Queued)));
+        let statuses: Vec<&str> = store
+            .history("a")
+            .unwrap()
+            .iter()
+            .map(|record| record.status.as_str())
+            .collect();
+        assert_eq!(statuses, vec!["received", "queued"]);
+
+        store.insert(TxStatusRecord::new("b", TxStatus::Received));
+        store.insert(TxStatusRecord::new("c", TxStatus::Received));
+        assert_eq!(store.len(), 2);
+        assert!(store.history("a").is_none());
+        assert_eq!(store.latest("c").unwrap().status, TxStatus::Received);
+    }
+}
@@ -0,0 +1,222 @@
+//! Files written atomically and with a checksum, so a torn write is detected.
+//!
+//! A file is written to `<path>.tmp`, synced and renamed over `<path>`. Its last
+//! line is `checksum:<hex>`, the SHA3-256 of the contents before it. A file that
+//! exists but whose checksum is missing or does not match is reported as corrupt
+//! rather than read as a default value.
+
+use crate::error::UtxosetError;
+use sha3::{Digest, Sha3_256};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the checksum line.
+pub const CHECKSUM_PREFIX: &str = "checksum:";
+
+/// File of the height of the last processed block, unless `BLOCK_HEIGHT_FILE` is set.
+pub const DEFAULT_BLOCK_HEIGHT_FILE: &str = "height.txt";
+
+/// Command line flag to start from a given height even if the height file is corrupt.
+pub const FORCE_RESYNC_FLAG: &str = "--force-resync-from";
+
+fn checksum(contents: &str) -> String {
+    hex::encode(Sha3_256::digest(contents.as_bytes()))
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+fn corrupt(path: &Path, reason: &str) -> UtxosetError {
+    UtxosetError::CorruptFile {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Writes `contents` followed by its checksum line, replacing `path` atomically.
+pub fn write_checked_file<P: AsRef<Path>>(path: P, contents: &str) -> Result<(), UtxosetError> {
+    let path = path.as_ref();
+    let tmp = tmp_path(path);
+    let mut file = File::create(&tmp)?;
+    write!(
+        file,
+        "{}\n{}{}\n",
+        contents,
+        CHECKSUM_PREFIX,
+        checksum(contents)
+    )?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Reads the contents of a file written by [`write_checked_file`].
+/// Returns None if the file does not exist and `CorruptFile` if its checksum does not match.
+pub fn read_checked_file<P: AsRef<Path>>(path: P) -> Result<Option<String>, UtxosetError> {
+    let path = path.as_ref();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let text = text
+        .strip_suffix('\n')
+        .ok_or_else(|| corrupt(path, "truncated checksum line"))?;
+    let (contents, checksum_line) = text
+        .rsplit_once('\n')
+        .ok_or_else(|| corrupt(path, "missing checksum line"))?;
+    let expected = checksum_line
+        .strip_prefix(CHECKSUM_PREFIX)
+        .ok_or_else(|| corrupt(path, "missing checksum line"))?;
+    if expected != checksum(contents) {
+        return Err(corrupt(path, "checksum mismatch"));
+    }
+    Ok(Some(contents.to_string()))
+}
+
+/// Path of the block height file.
+pub fn block_height_file() -> String {
+    crate::config::config_value("BLOCK_HEIGHT_FILE")
+        .unwrap_or_else(|| DEFAULT_BLOCK_HEIGHT_FILE.to_string())
+}
+
+pub fn write_block_height<P: AsRef<Path>>(path: P, height: u64) -> Result<(), UtxosetError> {
+    write_checked_file(path, &height.to_string())
+}
+
+pub fn read_block_height<P: AsRef<Path>>(path: P) -> Result<Option<u64>, UtxosetError> {
+    let path = path.as_ref();
+    match read_checked_file(path)? {
+        Some(contents) => match contents.trim().parse::<u64>() {
+            Ok(height) => Ok(Some(height)),
+            Err(_) => Err(corrupt(path, "invalid block height")),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Height given with `--force-resync-from <height>`, if any.
+pub fn force_resync_from_arg(args: &[String]) -> Result<Option<u64>, String> {
+    let position = match args.iter().position(|arg| arg == FORCE_RESYNC_FLAG) {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    match args.get(position + 1).map(|height| height.parse::<u64>()) {
+        Some(Ok(height)) => Ok(Some(height)),
+        _ => Err(format!("{} expects a block height", FORCE_RESYNC_FLAG)),
+    }
+}
+
+/// Height to start from, checked before the node starts.
+///
+/// With `--force-resync-from <height>` in `args` the height file is overwritten with
+/// that height. Otherwise the height file is read, and an error telling the operator
+/// how to recover is returned if it is corrupt, instead of resyncing from scratch.
+pub fn startup_block_height<P: AsRef<Path>>(
+    path: P,
+    args: &[String],
+) -> Result<Option<u64>, String> {
+    let path = path.as_ref();
+    if let Some(height) = force_resync_from_arg(args)? {
+        println!("Forced resync from block height {}", height);
+        write_block_height(path, height).map_err(|e| e.to_string())?;
+        return Ok(Some(height));
+    }
+    read_block_height(path).map_err(|e| {
+        format!(
+            "Refusing to start, {}. Restore {} from a backup, or restart with {} <height> to resync from a known height.",
+            e,
+            path.display(),
+            FORCE_RESYNC_FLAG
+        )
+    })
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn checked_file_round_trip_test() {
+        let path = test_path("checked-file");
+        assert!(read_checked_file(&path).unwrap().is_none());
+
+        write_checked_file(&path, "total_script_tx=3\ntotal_transfer_tx=7").unwrap();
+        assert_eq!(
+            read_checked_file(&path).unwrap().unwrap(),
+            "total_script_tx=3\ntotal_transfer_tx=7"
+        );
+        // the temp file is renamed over the file
+        assert!(!tmp_path(&path).exists());
+
+        // a temp file left by an interrupted write is ignored
+        fs::write(tmp_path(&path), "12").unwrap();
+        write_block_height(&path, 1_204_311).unwrap();
+        assert_eq!(read_block_height(&path).unwrap(), Some(1_204_311));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn checked_file_torn_write_test() {
+        let path = test_path("height");
+        write_block_height(&path, 1_204_311).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        for len in 0..bytes.len() {
+            fs::write(&path, &bytes[..len]).unwrap();
+            assert!(
+                matches!(
+                    read_block_height(&path),
+                    Err(UtxosetError::CorruptFile { .. })
+                ),
+                "torn write of {} bytes read as valid",
+                len
+            );
+        }
+        // a height written without checksum is not trusted either
+        fs::write(&path, "1204311\n").unwrap();
+        assert!(read_block_height(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn startup_block_height_test() {
+        let path = test_path("startup-height");
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        // first start, no height file
+        assert_eq!(startup_block_height(&path, &args(&["node"])), Ok(None));
+
+        write_block_height(&path, 5_000).unwrap();
+        assert_eq!(
+            startup_block_height(&path, &args(&["node"])),
+            Ok(Some(5_000))
+        );
+
+        // torn write, the node refuses to start
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        let err = startup_block_height(&path, &args(&["node"])).unwrap_err();
+        assert!(err.contains(FORCE_RESYNC_FLAG));
+
+        // unless a resync height is given explicitly
+        let forced = args(&["node", FORCE_RESYNC_FLAG, "4200"]);
+        assert_eq!(startup_block_height(&path, &forced), Ok(Some(4_200)));
+        assert_eq!(read_block_height(&path).unwrap(), Some(4_200));
+
+        assert!(force_resync_from_arg(&args(&["node", FORCE_RESYNC_FLAG])).is_err());
+        assert!(force_resync_from_arg(&args(&["node", FORCE_RESYNC_FLAG, "tip"])).is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
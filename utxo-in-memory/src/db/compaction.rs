@@ -0,0 +1,105 @@
+//! Compaction of the utxo storage after large deletions.
+//!
+//! The partitions are B-trees and release their nodes as utxos are removed, but the
+//! encoding cache of a partition is a hash map keeping the capacity of its largest
+//! size. A compaction shrinks the cache of a partition, in place if no read view holds
+//! it, else by rebuilding it so the views keep the map of their epoch. The content of
+//! the storage is unchanged and the epoch is not advanced. The address index is
+//! compacted with the storage.
+//!
+//! The maintenance task compacts a partition once the utxos removed since its last
+//! compaction exceed `COMPACTION_REMOVED_PERCENT` percent of its size, checked every
+//! `COMPACTION_INTERVAL_SECS` seconds. Nothing is compacted while a block is staged.
+
+use crate::state::global_state;
+use crate::threadpool::env_or;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Default share of the entries of a partition removed before it is compacted, in percent.
+pub const DEFAULT_COMPACTION_REMOVED_PERCENT: usize = 50;
+/// Default period of the compaction checks.
+pub const DEFAULT_COMPACTION_INTERVAL_SECS: usize = 300;
+/// Removals below which a partition is not compacted by the maintenance task.
+pub const MIN_COMPACTION_REMOVES: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionConfig {
+    pub removed_percent: usize,
+    pub interval_secs: usize,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        CompactionConfig {
+            removed_percent: DEFAULT_COMPACTION_REMOVED_PERCENT,
+            interval_secs: DEFAULT_COMPACTION_INTERVAL_SECS,
+        }
+    }
+}
+
+impl CompactionConfig {
+    /// Reads `COMPACTION_REMOVED_PERCENT` and `COMPACTION_INTERVAL_SECS`.
+    pub fn from_env() -> Self {
+        CompactionConfig {
+            removed_percent: env_or(
+                "COMPACTION_REMOVED_PERCENT",
+                DEFAULT_COMPACTION_REMOVED_PERCENT,
+            ),
+            interval_secs: env_or("COMPACTION_INTERVAL_SECS", DEFAULT_COMPACTION_INTERVAL_SECS),
+        }
+    }
+
+    /// Whether a partition of `entries` with `removed` utxos removed since its last
+    /// compaction is compacted by the maintenance task. The share is taken of the
+    /// entries and the removed utxos together.
+    pub fn is_due(&self, entries: u64, removed: u64) -> bool {
+        let total = entries as u128 + removed as u128;
+        removed >= MIN_COMPACTION_REMOVES
+            && removed as u128 * 100 > total * self.removed_percent as u128
+    }
+}
+
+/// Compaction of the encoding cache of a partition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartitionCompaction {
+    pub input_type: usize,
+    pub entries: u64,
+    /// Utxos removed since the previous compaction
+    pub removed: u64,
+    pub capacity_before: usize,
+    pub capacity_after: usize,
+    pub bytes_reclaimed: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CompactionReport {
+    /// Epoch of the storage when compacted
+    pub epoch: u64,
+    pub partitions: Vec<PartitionCompaction>,
+    pub address_index_bytes: u64,
+    /// Partitions and address index together
+    pub bytes_reclaimed: u64,
+}
+
+// approximate table size of a cache map of `capacity` entries, the control byte included
+pub(crate) fn encoded_map_bytes(capacity: usize) -> u64 {
+    let entry = std::mem::size_of::<(super::KeyId, Vec<u8>)>() + 1;
+    (capacity * entry) as u64
+}
+
+/// Compacts the storage of the global state every `COMPACTION_INTERVAL_SECS` seconds.
+pub fn storage_compaction_task() {
+    let config = CompactionConfig::from_env();
+    loop {
+        std::thread::sleep(Duration::from_secs(config.interval_secs as u64));
+        let report = global_state().compact_storage(Some(&config));
+        if !report.partitions.is_empty() {
+            println!(
+                "compacted {} utxo partitions, {} bytes reclaimed",
+                report.partitions.len(),
+                report.bytes_reclaimed
+            );
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Flat copy of the utxo set written with every snapshot, for a fast restart.
+//!
+//! The file is memory mapped and scanned in order, the values are the cached
+//! encodings of the outputs, so loading it skips PostgreSQL and the re-encoding of
+//! every output. Its block height and checksum are recorded in PostgreSQL, and the
+//! file is only trusted if they match the latest record and no later block was
+//! applied since.
+//!
+//! Layout, integers little endian:
+//!
+//! | section | contents                                                        |
+//! |---------|-----------------------------------------------------------------|
+//! | header  | `ZKOSFLAT`, version u32, partitions u32, block height u64,      |
+//! |         | checksum [u8; 32], entries u64 of every partition               |
+//! | index   | key [u8; 34], value offset u64, value length u32, sorted by key |
+//! |         | within each partition, the partitions in order                 |
+//! | values  | the encodings, at their offsets from the start of the section   |
+//!
+//! The checksum is the SHA3-256 of the index and the values.
+
+use crate::db::KeyId;
+use crate::error::UtxosetError;
+use crate::pgsql::PersistenceStore;
+use memmap2::Mmap;
+use serde_derive::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+pub const FLAT_SNAPSHOT_MAGIC: &[u8; 8] = b"ZKOSFLAT";
+pub const FLAT_SNAPSHOT_VERSION: u32 = 1;
+
+/// Length of a utxo key, see [`crate::db::utxo_key`].
+pub const FLAT_KEY_LEN: usize = 34;
+
+const INDEX_ENTRY_LEN: usize = FLAT_KEY_LEN + 12;
+// magic, version, partitions, block height and checksum
+const FIXED_HEADER_LEN: usize = 8 + 4 + 4 + 8 + 32;
+
+/// Path of the flat snapshot of the snapshots at `snapshot_path`.
+pub fn flat_snapshot_path(snapshot_path: &str) -> String {
+    format!("{}-flat", snapshot_path)
+}
+
+/// Block height and checksum of a flat snapshot, as recorded in PostgreSQL.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlatSnapshotRecord {
+    pub block_height: u64,
+    /// Hex of the SHA3-256 of the file after its header
+    pub checksum: String,
+    pub entries: u64,
+}
+
+fn corrupt(path: &Path, reason: &str) -> UtxosetError {
+    UtxosetError::CorruptFile {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// Writes the encodings of `partition_count` partitions at `block_height` to `path`,
+/// replacing it atomically. Fails if a key is not a utxo key.
+pub fn write_flat_snapshot<P: AsRef<Path>>(
+    path: P,
+    block_height: u64,
+    partition_count: usize,
+    encoded: &HashMap<usize, Arc<HashMap<KeyId, Vec<u8>>>>,
+) -> Result<FlatSnapshotRecord, UtxosetError> {
+    let path = path.as_ref();
+    let partitions: Vec<Vec<(&KeyId, &Vec<u8>)>> = (0..partition_count)
+        .map(|partition| {
+            let mut entries: Vec<(&KeyId, &Vec<u8>)> = encoded
+                .get(&partition)
+                .map(|partition| partition.iter().collect())
+                .unwrap_or_default();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            entries
+        })
+        .collect();
+
+    let entries: usize = partitions.iter().map(Vec::len).sum();
+    let mut index = Vec::with_capacity(entries * INDEX_ENTRY_LEN);
+    let mut offset = 0u64;
+    for (key, value) in partitions.iter().flatten() {
+        if key.len() != FLAT_KEY_LEN {
+            return Err(corrupt(path, "utxo key of unexpected length"));
+        }
+        index.extend_from_slice(key);
+        index.extend_from_slice(&offset.to_le_bytes());
+        index.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        offset += value.len() as u64;
+    }
+    let mut hasher = Sha3_256::new();
+    hasher.update(&index);
+    for (_, value) in partitions.iter().flatten() {
+        hasher.update(value);
+    }
+    let checksum = hasher.finalize();
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = BufWriter::new(File::create(&tmp)?);
+    file.write_all(FLAT_SNAPSHOT_MAGIC)?;
+    file.write_all(&FLAT_SNAPSHOT_VERSION.to_le_bytes())?;
+    file.write_all(&(partition_count as u32).to_le_bytes())?;
+    file.write_all(&block_height.to_le_bytes())?;
+    file.write_all(&checksum)?;
+    for partition in partitions.iter() {
+        file.write_all(&(partition.len() as u64).to_le_bytes())?;
+    }
+    file.write_all(&index)?;
+    for (_, value) in partitions.iter().flatten() {
+        file.write_all(value)?;
+    }
+    let file = file.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+
+    Ok(FlatSnapshotRecord {
+        block_height,
+        checksum: hex::encode(checksum),
+        entries: entries as u64,
+    })
+}
+
+/// A flat snapshot mapped read only, its header and checksum verified.
+pub struct FlatSnapshot {
+    mmap: Mmap,
+    block_height: u64,
+    checksum: [u8; 32],
+    // first index entry and number of entries of every partition
+    partitions: Vec<(usize, usize)>,
+    index_start: usize,
+    values_start: usize,
+}
+
+impl FlatSnapshot {
+    /// Maps the file at `path`. Fails with `CorruptFile` if it is truncated, of
+    /// another version or its checksum does not match.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FlatSnapshot, UtxosetError> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // the file is replaced by rename and never written in place, so the
+        // mapping is not modified while it is alive
+        let mmap = unsafe { Mmap::map(&file)? };
+        let bytes = &mmap[..];
+        if bytes.len() < FIXED_HEADER_LEN || &bytes[..8] != FLAT_SNAPSHOT_MAGIC {
+            return Err(corrupt(path, "not a flat snapshot"));
+        }
+        if read_u32(bytes, 8) != FLAT_SNAPSHOT_VERSION {
+            return Err(corrupt(path, "unsupported flat snapshot version"));
+        }
+        let partition_count = read_u32(bytes, 12) as usize;
+        let block_height = read_u64(bytes, 16);
+        let checksum: [u8; 32] = bytes[24..56].try_into().unwrap();
+        let index_start = FIXED_HEADER_LEN + partition_count * 8;
+        if bytes.len() < index_start {
+            return Err(corrupt(path, "truncated header"));
+        }
+
+        let mut partitions = Vec::with_capacity(partition_count);
+        let mut entries = 0usize;
+        for partition in 0..partition_count {
+            let count = read_u64(bytes, FIXED_HEADER_LEN + partition * 8) as usize;
+            partitions.push((entries, count));
+            entries = entries.saturating_add(count);
+        }
+        let values_start = entries
+            .checked_mul(INDEX_ENTRY_LEN)
+            .and_then(|len| len.checked_add(index_start))
+            .filter(|start| *start <= bytes.len())
+            .ok_or_else(|| corrupt(path, "truncated index"))?;
+
+        let values_len = (bytes.len() - values_start) as u64;
+        for entry in 0..entries {
+            let at = index_start + entry * INDEX_ENTRY_LEN + FLAT_KEY_LEN;
+            let end = read_u64(bytes, at).saturating_add(read_u32(bytes, at + 8) as u64);
+            if end > values_len {
+                return Err(corrupt(path, "value out of bounds"));
+            }
+        }
+        if Sha3_256::digest(&bytes[index_start..]).as_slice() != checksum {
+            return Err(corrupt(path, "checksum mismatch"));
+        }
+
+        Ok(FlatSnapshot {
+            mmap,
+            block_height,
+            checksum,
+            partitions,
+            index_start,
+            values_start,
+        })
+    }
+
+    pub fn block_height(&self) -> u64 {
+        self.block_height
+    }
+
+    pub fn partition_count(&self) -> usize {
+        self.partitions.len()
+    }
+
+    pub fn record(&self) -> FlatSnapshotRecord {
+        FlatSnapshotRecord {
+            block_height: self.block_height,
+            checksum: hex::encode(self.checksum),
+            entries: self.partitions.iter().map(|(_, count)| *count as u64).sum(),
+        }
+    }
+
+    fn entry(&self, entry: usize) -> (&[u8], &[u8]) {
+        let at = self.index_start + entry * INDEX_ENTRY_LEN;
+        let offset = read_u64(&self.mmap, at + FLAT_KEY_LEN) as usize;
+        let len = read_u32(&self.mmap, at + FLAT_KEY_LEN + 8) as usize;
+        let value = self.values_start + offset;
+        (
+            &self.mmap[at..at + FLAT_KEY_LEN],
+            &self.mmap[value..value + len],
+        )
+    }
+
+    /// Keys and encoded values of `partition` in key order, read from the mapping.
+    pub fn entries(&self, partition: usize) -> impl Iterator<Item = (&[u8], &[u8])> + '_ {
+        let (first, count) = self.partitions.get(partition).copied().unwrap_or((0, 0));
+        (first..first + count).map(move |entry| self.entry(entry))
+    }
+
+    /// Encoded value of `key` in `partition`, found by binary search of the index.
+    pub fn get(&self, partition: usize, key: &[u8]) -> Option<&[u8]> {
+        let (first, count) = self.partitions.get(partition).copied()?;
+        let (mut low, mut high) = (first, first + count);
+        while low < high {
+            let middle = (low + high) / 2;
+            let (middle_key, value) = self.entry(middle);
+            match middle_key.cmp(key) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Some(value),
+            }
+        }
+        None
+    }
+
+    /// Fails with `StaleSnapshot` unless the snapshot is the latest one recorded in
+    /// `store` and no block after its height was applied.
+    pub fn check_current(&self, store: &dyn PersistenceStore) -> Result<(), UtxosetError> {
+        let record = self.record();
+        match store.get_latest_flat_snapshot()? {
+            Some(latest) if latest == record => {}
+            Some(latest) => {
+                return Err(UtxosetError::StaleSnapshot(format!(
+                    "the latest snapshot is at height {} with checksum {}",
+                    latest.block_height, latest.checksum
+                )))
+            }
+            None => {
+                return Err(UtxosetError::StaleSnapshot(
+                    "no snapshot is recorded".to_string(),
+                ))
+            }
+        }
+        match store.get_latest_block_height()? {
+            Some(height) if height > self.block_height => Err(UtxosetError::StaleSnapshot(
+                format!("blocks up to height {} were applied since", height),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockoperations::blockresult::BlockApplyResult;
+    use crate::db::{utxo_key, LocalDBtrait, LocalStorage};
+    use crate::pgsql::MemoryStore;
+    use std::path::PathBuf;
+    use zkvm::zkos_types::Utxo;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    // storage of `count` values spread over three partitions
+    fn storage(count: usize) -> LocalStorage<String> {
+        let mut storage = LocalStorage::<String>::new(3);
+        for i in 0..count {
+            storage
+                .add(utxo_key(&Utxo::random()), format!("output-{}", i), i % 3)
+                .unwrap();
+        }
+        storage.block_height = 42;
+        storage
+    }
+
+    fn block_result(height: u64) -> BlockApplyResult {
+        BlockApplyResult {
+            height,
+            applied_txids: Vec::new(),
+            failed: Vec::new(),
+            utxos_added: 0,
+            utxos_removed: 0,
+            duration_ms: 0,
+            weight: 0,
+            weight_limit: 0,
+        }
+    }
+
+    #[test]
+    fn flat_snapshot_round_trip_test() {
+        let path = test_path("flat_snapshot");
+        let stored = storage(100);
+        let record = write_flat_snapshot(&path, 42, 3, &stored.encoded).unwrap();
+        assert_eq!(record.entries, 100);
+
+        let snapshot = FlatSnapshot::open(&path).unwrap();
+        assert_eq!(snapshot.record(), record);
+        for (input_type, partition) in stored.data.iter() {
+            let keys: Vec<&[u8]> = snapshot.entries(*input_type).map(|(key, _)| key).collect();
+            let expected: Vec<&[u8]> = partition.keys().map(|key| key.as_slice()).collect();
+            assert_eq!(keys, expected);
+            for key in partition.keys() {
+                assert_eq!(
+                    snapshot.get(*input_type, key),
+                    stored.get_encoded_by_id(key, *input_type)
+                );
+            }
+        }
+        assert!(snapshot.get(0, &utxo_key(&Utxo::random())).is_none());
+
+        let mut loaded = LocalStorage::<String>::new(3);
+        loaded.load_from_flat_snapshot(&snapshot).unwrap();
+        assert_eq!(loaded.data, stored.data);
+        assert_eq!(loaded.encoded, stored.encoded);
+        assert_eq!(loaded.stats().partitions, stored.stats().partitions);
+        assert_eq!(loaded.block_height, 42);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn flat_snapshot_corrupt_test() {
+        let path = test_path("flat_snapshot_corrupt");
+        write_flat_snapshot(&path, 42, 3, &storage(10).encoded).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        // a flipped value byte, a truncated file and another format
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        for contents in [
+            flipped,
+            bytes[..bytes.len() - 1].to_vec(),
+            b"height".to_vec(),
+        ] {
+            fs::write(&path, contents).unwrap();
+            assert!(matches!(
+                FlatSnapshot::open(&path),
+                Err(UtxosetError::CorruptFile { .. })
+            ));
+        }
+        let _ = fs::remove_file(path);
+        assert!(matches!(
+            FlatSnapshot::open(test_path("flat_snapshot_missing")),
+            Err(UtxosetError::IOError(_))
+        ));
+    }
+
+    #[test]
+    fn flat_snapshot_stale_test() {
+        let path = test_path("flat_snapshot_stale");
+        let record = write_flat_snapshot(&path, 42, 3, &storage(10).encoded).unwrap();
+        let snapshot = FlatSnapshot::open(&path).unwrap();
+        let stale = |store: &MemoryStore| {
+            matches!(
+                snapshot.check_current(store),
+                Err(UtxosetError::StaleSnapshot(_))
+            )
+        };
+
+        // not recorded
+        let store = MemoryStore::new();
+        assert!(stale(&store));
+
+        // recorded with another checksum
+        let mut other = record.clone();
+        other.checksum = hex::encode([0u8; 32]);
+        store.insert_flat_snapshot(&other).unwrap();
+        assert!(stale(&store));
+
+        store.insert_flat_snapshot(&record).unwrap();
+        store.insert_block_result(&block_result(42)).unwrap();
+        assert!(snapshot.check_current(&store).is_ok());
+
+        // a later block was applied, or a later snapshot taken
+        store.insert_block_result(&block_result(43)).unwrap();
+        assert!(stale(&store));
+        let store = MemoryStore::new();
+        store.insert_flat_snapshot(&record).unwrap();
+        let mut later = record.clone();
+        later.block_height = 50;
+        store.insert_flat_snapshot(&later).unwrap();
+        assert!(stale(&store));
+
+        // a file of another partition count is not loaded
+        let mut loaded = LocalStorage::<String>::new(2);
+        assert!(matches!(
+            loaded.load_from_flat_snapshot(&snapshot),
+            Err(UtxosetError::StaleSnapshot(_))
+        ));
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,7 +1,11 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+pub mod checkedfile;
+pub mod compaction;
+pub mod flatsnapshot;
 mod snap_rules;
 mod snapshot;
+pub mod stats;
 pub use self::snapshot::*;
 
 pub use self::snapshot::SnapShot;
@@ -10,5 +14,9 @@ pub use self::utxostore::takesnapshotfrom_memory_to_postgresql_bulk;
 pub use self::utxostore::KeyId;
 pub use self::utxostore::LocalDBtrait;
 pub use self::utxostore::LocalStorage;
+pub use self::utxostore::ReadView;
 pub use self::utxostore::SequenceNumber;
 pub use self::utxostore::UtxokeyidOutput;
+pub use self::utxostore::utxo_key;
+pub use self::utxostore::{get_partition_page, UtxoPage};
+pub use self::stats::{PartitionStats, StorageStats};
@@ -1,5 +1,4 @@
 use crate::{db::SequenceNumber, error::UtxosetError};
-use std::path::Path;
 use serde_derive::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -11,26 +10,12 @@ pub struct SnapRules {
 
 impl SnapRules {
     pub fn env() -> Result<SnapRules, UtxosetError> {
-        dotenv::from_path(Path::new("/testnet/ZkOS/utxo-in-memory/.env")).ok();
-        // dotenv::dotenv().expect("Failed loading dotenv");
-
-        // let snapshot_file_location: String = std::env::var("SNAPSHOT_FILE_LOCATION")
-        //     .expect("missing environment variable SNAPSHOT_FILE_LOCATION");
-        // let snapshot_blockheight_threshold: SequenceNumber =
-        //     std::env::var("SNAPSHOT_BLOCKHEIGHT_THRESHOLD")
-        //         .expect("missing environment variable SNAPSHOT_BLOCKHEIGHT_THRESHOLD")
-        //         .parse::<SequenceNumber>()
-        //         .unwrap();
-        // let snapshot_duration_threshold: SequenceNumber =
-        //     std::env::var("SNAPSHOT_DURATION_THRESHOLD")
-        //         .expect("missing environment variable SNAPSHOT_DURATION_THRESHOLD")
-        //         .parse::<SequenceNumber>()
-        //         .unwrap();
-        let snapshot_file_location: String = std::env::var("SNAPSHOT_FILE_LOCATION")?;
+        let config = crate::config::config_source()?;
+        let snapshot_file_location: String = config.required("SNAPSHOT_FILE_LOCATION")?;
         let snapshot_blockheight_threshold: SequenceNumber =
-            std::env::var("SNAPSHOT_BLOCKHEIGHT_THRESHOLD")?.parse::<SequenceNumber>()?;
-        let snapshot_duration_threshold: SequenceNumber = std::env::var("SNAPSHOT_DURATION_THRESHOLD")?
-            .parse::<SequenceNumber>()?;    
+            config.required("SNAPSHOT_BLOCKHEIGHT_THRESHOLD")?.parse::<SequenceNumber>()?;
+        let snapshot_duration_threshold: SequenceNumber =
+            config.required("SNAPSHOT_DURATION_THRESHOLD")?.parse::<SequenceNumber>()?;
         Ok(SnapRules {
             path: snapshot_file_location,
             block_size_threshold: snapshot_blockheight_threshold,
@@ -0,0 +1,171 @@
+//! Size accounting of the utxo partitions.
+//!
+//! The counters are kept up to date by `add` and `remove` of [`LocalStorage`]
+//! from the length of the cached encodings, so reading them never rescans a
+//! partition. A bulk load recomputes the entries and byte counts, the add and
+//! remove counters count the changes since the process started.
+//!
+//! [`LocalStorage`]: super::LocalStorage
+
+use prometheus::{register_gauge_vec, GaugeVec};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zkvm::IOType;
+
+lazy_static! {
+    pub static ref UTXO_PARTITION_BYTES: GaugeVec = register_gauge_vec!(
+        "utxo_partition_bytes",
+        "Encoded bytes of the outputs stored in a utxo partition",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref UTXO_PARTITION_ENTRIES: GaugeVec = register_gauge_vec!(
+        "utxo_partition_entries",
+        "Outputs stored in a utxo partition",
+        &["type"]
+    )
+    .unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PartitionStats {
+    pub entries: u64,
+    /// Sum of the bincode encoded sizes of the stored outputs
+    pub approx_bytes: u64,
+    /// Largest encoded output seen since the partition was loaded, removals do not lower it
+    pub largest_entry_bytes: u64,
+    pub adds_since_start: u64,
+    pub removes_since_start: u64,
+    /// Removals since the partition was last compacted, see [`super::compaction`]
+    #[serde(default)]
+    pub removes_since_compaction: u64,
+}
+
+impl PartitionStats {
+    /// Records an added entry of `bytes`, replacing an entry of `replaced` bytes if any.
+    pub fn record_add(&mut self, bytes: usize, replaced: Option<usize>) {
+        match replaced {
+            Some(replaced) => self.approx_bytes -= replaced as u64,
+            None => self.entries += 1,
+        }
+        self.approx_bytes += bytes as u64;
+        self.largest_entry_bytes = self.largest_entry_bytes.max(bytes as u64);
+        self.adds_since_start += 1;
+    }
+
+    pub fn record_remove(&mut self, bytes: usize) {
+        self.entries -= 1;
+        self.approx_bytes -= bytes as u64;
+        self.removes_since_start += 1;
+        self.removes_since_compaction += 1;
+    }
+
+    /// Sets the entry and byte counts from the encoded sizes of a loaded partition.
+    /// The add and remove counters are kept.
+    pub fn reset_sizes<I: Iterator<Item = usize>>(&mut self, sizes: I) {
+        self.entries = 0;
+        self.approx_bytes = 0;
+        self.largest_entry_bytes = 0;
+        for bytes in sizes {
+            self.entries += 1;
+            self.approx_bytes += bytes as u64;
+            self.largest_entry_bytes = self.largest_entry_bytes.max(bytes as u64);
+        }
+    }
+
+    pub fn average_entry_bytes(&self) -> u64 {
+        if self.entries == 0 {
+            0
+        } else {
+            self.approx_bytes / self.entries
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StorageStats {
+    pub block_height: u64,
+    pub total_entries: u64,
+    pub total_bytes: u64,
+    pub average_entry_bytes: u64,
+    /// Stats by IOType name, e.g. "Coin"
+    pub partitions: BTreeMap<String, PartitionStats>,
+}
+
+impl StorageStats {
+    pub fn new<'a, I>(block_height: u64, partitions: I) -> Self
+    where
+        I: Iterator<Item = (usize, &'a PartitionStats)>,
+    {
+        let mut stats = StorageStats {
+            block_height,
+            ..Default::default()
+        };
+        for (input_type, partition) in partitions {
+            stats.total_entries += partition.entries;
+            stats.total_bytes += partition.approx_bytes;
+            stats
+                .partitions
+                .insert(partition_name(input_type), partition.clone());
+        }
+        if stats.total_entries > 0 {
+            stats.average_entry_bytes = stats.total_bytes / stats.total_entries;
+        }
+        stats
+    }
+
+    /// Sets the Prometheus gauges of the partitions.
+    pub fn publish_gauges(&self) {
+        for (name, partition) in self.partitions.iter() {
+            UTXO_PARTITION_BYTES
+                .with_label_values(&[name])
+                .set(partition.approx_bytes as f64);
+            UTXO_PARTITION_ENTRIES
+                .with_label_values(&[name])
+                .set(partition.entries as f64);
+        }
+    }
+}
+
+// IOType name of a partition, the index for partitions without an IOType
+fn partition_name(input_type: usize) -> String {
+    match u8::try_from(input_type)
+        .ok()
+        .and_then(|byte| IOType::from_u8(byte).ok())
+    {
+        Some(io_type) => format!("{:?}", io_type),
+        None => input_type.to_string(),
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partition_stats_test() {
+        let mut stats = PartitionStats::default();
+        stats.record_add(100, None);
+        stats.record_add(300, None);
+        // replacing an entry keeps the count
+        stats.record_add(200, Some(100));
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.approx_bytes, 500);
+        assert_eq!(stats.largest_entry_bytes, 300);
+        stats.record_remove(300);
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.approx_bytes, 200);
+        assert_eq!(stats.largest_entry_bytes, 300);
+        assert_eq!((stats.adds_since_start, stats.removes_since_start), (3, 1));
+
+        stats.reset_sizes(vec![10, 20, 30].into_iter());
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.approx_bytes, 60);
+        assert_eq!(stats.largest_entry_bytes, 30);
+        assert_eq!(stats.average_entry_bytes(), 20);
+        assert_eq!((stats.adds_since_start, stats.removes_since_start), (3, 1));
+    }
+}
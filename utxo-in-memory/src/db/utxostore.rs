@@ -7,8 +7,11 @@ pub type InputType = usize;
 use crate::error::UtxosetError;
 use crate::ThreadPool;
 use serde_derive::{Deserialize, Serialize};
+use zkvm::zkos_types::Utxo;
 use zkvm::IOType;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::Arc;
 use std::time::SystemTime;
 pub type SequenceNumber = usize;
 use std::sync::mpsc;
@@ -16,9 +19,15 @@ use crate::{UTXO_COIN_TELEMETRY_COUNTER, UTXO_MEMO_TELEMETRY_COUNTER, UTXO_STATE
 
 
 use crate::pgsql::{POSTGRESQL_POOL_CONNECTION, THREADPOOL_SQL_QUERY, THREADPOOL_SQL_QUEUE};
+use crate::db::compaction::{encoded_map_bytes, CompactionConfig, PartitionCompaction};
+use crate::db::stats::{PartitionStats, StorageStats};
+use crate::db::flatsnapshot::{flat_snapshot_path, write_flat_snapshot, FlatSnapshot};
+use crate::pgsql::persistence_store;
 
 pub trait LocalDBtrait<T> {
     fn new(partition: usize) -> Self;
+    /// Adds `value` under `id`. Fails with `UtxoAlreadyExists` if the partition
+    /// already holds `id`, the stored value is kept.
     fn add(&mut self, id: KeyId, value: T, input_type: usize) -> Result<T, UtxosetError>;
     fn remove(&mut self, id: KeyId, input_type: usize) -> Result<T, UtxosetError>;
     fn search_key(&mut self, id: &KeyId, input_type: usize) -> Result<bool, UtxosetError>;
@@ -30,6 +39,8 @@ pub trait LocalDBtrait<T> {
     fn get_count_by_type(&mut self, input_type: usize) -> u64;
     fn get_utxo_from_db_by_block_height_range1(start_block: i128,limit: i64,pagination: i64,io_type: usize,
     ) -> Result<Vec<UtxokeyidOutput<T>>, UtxosetError> ;
+    /// Pages the committed utxos, the staged writes of a block are not visible.
+    fn get_utxos_page(&self, input_type: usize, offset_key: Option<&KeyId>, limit: usize) -> Result<UtxoPage<T>, UtxosetError>;
     // bulk add and bulk remove functions needed
 }
 
@@ -39,9 +50,78 @@ pub struct UtxokeyidOutput<T> {
     pub output: T,
 }
 
+// page of utxos in key order. next_offset_key is None on the last page
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UtxoPage<T> {
+    pub entries: Vec<UtxokeyidOutput<T>>,
+    pub next_offset_key: Option<KeyId>,
+}
+
+/// Key of `utxo` in the utxo set, PostgreSQL and the snapshots. The partition of a
+/// key is the IOType of its output, every lookup names both.
+pub fn utxo_key(utxo: &Utxo) -> KeyId {
+    utxo.to_bytes()
+}
+
+// re-encodes the keys of a partition persisted while utxo output indexes were a single byte
+pub fn upgrade_partition_keys<T>(partition: BTreeMap<KeyId, T>) -> BTreeMap<KeyId, T> {
+    partition
+        .into_iter()
+        .map(|(key, value)| (Utxo::upgrade_key(key), value))
+        .collect()
+}
+
+// returns at most `limit` entries with keys strictly after `offset_key`
+// a zero limit reads one entry, an empty page would end the paging of a non empty partition
+pub fn get_partition_page<T: Clone>(
+    partition: &BTreeMap<KeyId, T>,
+    offset_key: Option<&KeyId>,
+    limit: usize,
+) -> UtxoPage<T> {
+    let limit = limit.max(1);
+    let lower = match offset_key {
+        Some(key) => Bound::Excluded(key),
+        None => Bound::Unbounded,
+    };
+    let mut range = partition.range::<KeyId, _>((lower, Bound::Unbounded));
+    let mut entries: Vec<UtxokeyidOutput<T>> = Vec::new();
+    for (key, value) in range.by_ref().take(limit) {
+        entries.push(UtxokeyidOutput {
+            keyid: key.clone(),
+            output: value.clone(),
+        });
+    }
+    let next_offset_key = match range.next() {
+        Some(_) => entries.last().map(|entry| entry.keyid.clone()),
+        None => None,
+    };
+    UtxoPage {
+        entries,
+        next_offset_key,
+    }
+}
+
+// writes of a block by key, None for a removed utxo. An added utxo carries its encoding
+type StagedPartition<T> = BTreeMap<KeyId, Option<(T, Vec<u8>)>>;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct LocalStorage<T> {
-    pub data: HashMap<InputType, HashMap<KeyId, T>>,
+    // partitions are ordered by key so that paged reads are stable. They are shared
+    // with the read views, a partition held by a view is copied on write
+    pub data: HashMap<InputType, Arc<BTreeMap<KeyId, T>>>,
+    // bincode encoding of every stored value, by partition. Stored values are
+    // never modified in place, so an entry lives as long as its value
+    #[serde(skip)]
+    pub encoded: HashMap<InputType, Arc<HashMap<KeyId, Vec<u8>>>>,
+    // writes of the block being applied, see begin_block
+    #[serde(skip)]
+    staged: Option<HashMap<InputType, StagedPartition<T>>>,
+    // number of blocks committed since the start
+    #[serde(skip)]
+    pub epoch: u64,
+    // size accounting by partition, from the lengths of the cached encodings
+    #[serde(skip)]
+    pub partition_stats: HashMap<InputType, PartitionStats>,
     pub block_height: SequenceNumber,
     pub aggrigate_log_sequence: SequenceNumber,
     pub snaps: SnapShot,
@@ -60,10 +140,14 @@ where
                     partition_size = 1;
                 }
                 for i in 0..partition_size {
-                    data.insert(i, HashMap::new());
+                    data.insert(i, Arc::new(BTreeMap::new()));
                 }
                 data
             },
+            encoded: HashMap::new(),
+            staged: None,
+            epoch: 0,
+            partition_stats: HashMap::new(),
             block_height: 0,
             aggrigate_log_sequence: 0,
             snaps: SnapShot::new(partition_size),
@@ -72,65 +156,87 @@ where
     }
 
     fn add(&mut self, id: KeyId, value: T, input_type: usize) -> Result<T, UtxosetError> {
-        match self.data
-            .get_mut(&input_type){
-                Some(inner_map) => inner_map.insert(id.clone(), value.clone()),
-                None => return Err(UtxosetError::UtxoNotFound),
-            };
-
-        match input_type {
-            1 => UTXO_COIN_TELEMETRY_COUNTER.inc(),
-            2 => UTXO_MEMO_TELEMETRY_COUNTER.inc(),
-            3 => UTXO_STATE_TELEMETRY_COUNTER.inc(),
-            _ => {}
+        // a colliding key is never overwritten
+        match self.lookup(&id, input_type) {
+            Some(None) => {}
+            Some(Some(_)) => return Err(UtxosetError::UtxoAlreadyExists),
+            None => return Err(UtxosetError::UtxoNotFound),
+        }
+        let encoded = bincode::serialize(&value)?;
+        match self.staged.as_mut() {
+            Some(staged) => {
+                staged
+                    .entry(input_type)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(id, Some((value.clone(), encoded)));
+            }
+            None => self.insert_committed(id, value.clone(), encoded, input_type),
         }
-
         Ok(value)
     }
 
     fn remove(&mut self, id: KeyId, input_type: usize) -> Result<T, UtxosetError> {
-        let stoarage = self.data.get_mut(&input_type);
-        let value = match stoarage{
-            Some(inner_map) => inner_map.remove(&id),
-            None => return Err(UtxosetError::UtxoNotFound),
+        let value = match self.lookup(&id, input_type) {
+            Some(Some(value)) => value.clone(),
+            _ => return Err(UtxosetError::UtxoNotFound),
         };
-        match value {
-            Some(value) => {
-                match input_type {
-                    1 => UTXO_COIN_TELEMETRY_COUNTER.dec(),
-                    2 => UTXO_MEMO_TELEMETRY_COUNTER.dec(),
-                    3 => UTXO_STATE_TELEMETRY_COUNTER.dec(),
-                    _ => {}
-                }
-                Ok(value.clone())
-            },
-            None => Err(UtxosetError::UtxoNotFound),
+        match self.staged.as_mut() {
+            Some(staged) => {
+                staged
+                    .entry(input_type)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(id, None);
+            }
+            None => {
+                self.remove_committed(&id, input_type);
+            }
         }
+        Ok(value)
     }
 
     fn search_key(&mut self, id: &KeyId, input_type: usize) -> Result<bool, UtxosetError> {
-        let storage = self.data.get_mut(&input_type);
-        match storage{
-            Some(inner_map) => {
-                match inner_map.get(id){
-                    Some(_) => Ok(true),
-                    None => Ok(false),
-                }
-            },
+        match self.lookup(id, input_type) {
+            Some(value) => Ok(value.is_some()),
             None => Err(UtxosetError::UtxoNotFound),
         }
     }
     fn get_utxo_by_id(&mut self, id: KeyId, input_type: usize) -> Result<T, UtxosetError> {
-         Ok(self.data.get_mut(&input_type).and_then(|f|f.get(&id)).ok_or(UtxosetError::UtxoNotFound)?.clone())
+        self.lookup(&id, input_type)
+            .flatten()
+            .cloned()
+            .ok_or(UtxosetError::UtxoNotFound)
+    }
+
+    fn get_utxos_page(
+        &self,
+        input_type: usize,
+        offset_key: Option<&KeyId>,
+        limit: usize,
+    ) -> Result<UtxoPage<T>, UtxosetError> {
+        match self.data.get(&input_type) {
+            Some(partition) => Ok(get_partition_page(partition, offset_key, limit)),
+            None => Err(UtxosetError::UtxoNotFound),
+        }
     }
 
     fn get_count_by_type(&mut self, input_type: usize) -> u64 {
-        let result: u64 = match self.data.get(&input_type) {
-            Some(inner_map) => inner_map.len() as u64,
-            None => 0,
+        let partition = match self.data.get(&input_type) {
+            Some(partition) => partition,
+            None => return 0,
         };
-
-        result
+        let mut count = partition.len() as u64;
+        let staged = self
+            .staged
+            .as_ref()
+            .and_then(|staged| staged.get(&input_type));
+        for (id, change) in staged.into_iter().flatten() {
+            match (partition.contains_key(id), change.is_some()) {
+                (false, true) => count += 1,
+                (true, false) => count -= 1,
+                _ => {}
+            }
+        }
+        count
     }
 
     fn take_snapshot(&mut self) -> Result<(), UtxosetError> {
@@ -138,7 +244,7 @@ where
         let snap_path = format!("{}-snapmap", snapshot_path.clone());
         let last_block = self.block_height.clone();
         let new_snapshot_id = self.snaps.lastsnapid + 1;
-        let mut snap_partition_clone: Vec<(String, HashMap<KeyId, T>)> = Vec::new();
+        let mut snap_partition_clone: Vec<(String, Arc<BTreeMap<KeyId, T>>)> = Vec::new();
 
         let inner_snap_threadpool = ThreadPool::new(
             if self.partition_size >= 5 {
@@ -173,6 +279,20 @@ where
         self.snaps.lastsnaptimestamp = std::time::SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_micros();
+        // flat copy for a fast restart, its height and checksum are recorded in psql
+        let flat_path = flat_snapshot_path(&snapshot_path);
+        let partition_size = self.partition_size;
+        let encoded = self.encoded.clone();
+        inner_snap_threadpool.execute(move || {
+            match write_flat_snapshot(flat_path, last_block as u64, partition_size, &encoded) {
+                Ok(record) => {
+                    if let Err(e) = persistence_store().insert_flat_snapshot(&record) {
+                        eprintln!("Failed to insert_flat_snapshot: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to write flat snapshot: {}", e),
+            }
+        });
         let snap_storage = self.snaps.clone();
         //storing snapshot state with keyname "utxosnapshot"
         inner_snap_threadpool.execute(move || {
@@ -207,12 +327,14 @@ where
         for (inputtype, result_data) in snap_partition_clone.iter().enumerate() {
             match result_data {
                 Ok(data) => {
+                    let partition: BTreeMap<KeyId, T> = bincode::deserialize(&data).unwrap();
                     self.data
-                        .insert(inputtype, bincode::deserialize(&data).unwrap());
+                        .insert(inputtype, Arc::new(upgrade_partition_keys(partition)));
                 }
                 Err(_) => {}
             }
         }
+        self.encode_partitions()?;
 
         self.block_height = self.snaps.block_height;
         self.aggrigate_log_sequence = self.snaps.aggrigate_log_sequence;
@@ -237,11 +359,9 @@ where
                     Ok(utxo_data) => {
                         if utxo_data.len() > 0 {
                             println!("utxo_data.len():{}",utxo_data.len());
+                            let partition = Arc::make_mut(self.data.get_mut(&inputtype).unwrap());
                             for value in utxo_data {
-                                self.data
-                                    .get_mut(&inputtype)
-                                    .unwrap()
-                                    .insert(value.keyid, value.output);
+                                partition.insert(Utxo::upgrade_key(value.keyid), value.output);
                             }
                             pagination_counter += 1;
                         } else {
@@ -255,12 +375,14 @@ where
                 }
             }
         }
+        self.encode_partitions()?;
 
         Ok(())
     }
     fn data_meta_update(&mut self, blockheight: usize) -> bool {
         self.block_height = blockheight as usize;
         self.aggrigate_log_sequence += 1;
+        self.stats().publish_gauges();
         if self.block_height
             >= self.snaps.snap_rules.block_size_threshold * (self.snaps.currentsnapid + 1)
         {
@@ -331,6 +453,327 @@ where
     
 }
 
+impl<T> LocalStorage<T>
+where
+    T: serde::Serialize,
+{
+    /// Cached bincode encoding of a stored value, same bytes as `bincode::serialize`.
+    pub fn get_encoded_by_id(&self, id: &KeyId, input_type: usize) -> Option<&[u8]> {
+        self.encoded
+            .get(&input_type)
+            .and_then(|encoded| encoded.get(id))
+            .map(|bytes| bytes.as_slice())
+    }
+
+    // rebuilds the encoding cache after the partitions are loaded in bulk
+    fn encode_partitions(&mut self) -> Result<(), UtxosetError> {
+        let mut encoded = HashMap::new();
+        for (input_type, partition) in self.data.iter() {
+            let mut partition_encoded = HashMap::with_capacity(partition.len());
+            for (key, value) in partition.iter() {
+                partition_encoded.insert(key.clone(), bincode::serialize(value)?);
+            }
+            self.partition_stats
+                .entry(*input_type)
+                .or_insert_with(PartitionStats::default)
+                .reset_sizes(partition_encoded.values().map(|bytes| bytes.len()));
+            encoded.insert(*input_type, Arc::new(partition_encoded));
+        }
+        self.encoded = encoded;
+        Ok(())
+    }
+
+    /// Entries and encoded sizes of the partitions at the current block height.
+    /// Read from counters kept on add and remove, the partitions are not scanned.
+    pub fn stats(&self) -> StorageStats {
+        let mut partitions: Vec<(usize, &PartitionStats)> = self
+            .partition_stats
+            .iter()
+            .map(|(input_type, stats)| (*input_type, stats))
+            .collect();
+        partitions.sort_by_key(|(input_type, _)| *input_type);
+        StorageStats::new(self.block_height as u64, partitions.into_iter())
+    }
+}
+
+impl<T: Clone> LocalStorage<T> {
+    /// Stages the writes that follow until [`LocalStorage::commit_block`], so that the
+    /// changes of a block are published at once. The writer reads through the staged
+    /// writes, the read views do not see them.
+    pub fn begin_block(&mut self) {
+        if self.staged.is_none() {
+            self.staged = Some(HashMap::new());
+        }
+    }
+
+    /// Applies the staged writes to the partitions and starts the next epoch. A view
+    /// taken before keeps the partitions of its epoch.
+    pub fn commit_block(&mut self) {
+        let staged = match self.staged.take() {
+            Some(staged) => staged,
+            None => return,
+        };
+        for (input_type, changes) in staged {
+            for (id, change) in changes {
+                // a utxo removed and added again in the block is replaced
+                self.remove_committed(&id, input_type);
+                if let Some((value, encoded)) = change {
+                    self.insert_committed(id, value, encoded, input_type);
+                }
+            }
+        }
+        self.epoch += 1;
+    }
+
+    /// View of the committed utxo set, see [`ReadView`]. Only the partition handles
+    /// are copied.
+    pub fn read_view(&self) -> ReadView<T> {
+        ReadView {
+            data: self.data.clone(),
+            encoded: self.encoded.clone(),
+            epoch: self.epoch,
+            block_height: self.block_height,
+        }
+    }
+
+    /// Whether partition `input_type` holds `id`, staged writes included.
+    pub fn contains_key(&self, id: &KeyId, input_type: usize) -> bool {
+        matches!(self.lookup(id, input_type), Some(Some(_)))
+    }
+
+    // value of `id` as seen by the writer, the staged writes first. None if there is
+    // no such partition
+    fn lookup(&self, id: &KeyId, input_type: usize) -> Option<Option<&T>> {
+        let partition = self.data.get(&input_type)?;
+        let staged = self
+            .staged
+            .as_ref()
+            .and_then(|staged| staged.get(&input_type))
+            .and_then(|changes| changes.get(id));
+        Some(match staged {
+            Some(change) => change.as_ref().map(|(value, _)| value),
+            None => partition.get(id),
+        })
+    }
+
+    fn insert_committed(&mut self, id: KeyId, value: T, encoded: Vec<u8>, input_type: usize) {
+        let partition = match self.data.get_mut(&input_type) {
+            Some(partition) => partition,
+            None => return,
+        };
+        Arc::make_mut(partition).insert(id.clone(), value);
+        let encoded_len = encoded.len();
+        let replaced =
+            Arc::make_mut(self.encoded.entry(input_type).or_default()).insert(id, encoded);
+        self.partition_stats
+            .entry(input_type)
+            .or_insert_with(PartitionStats::default)
+            .record_add(encoded_len, replaced.map(|bytes| bytes.len()));
+
+        match input_type {
+            1 => UTXO_COIN_TELEMETRY_COUNTER.inc(),
+            2 => UTXO_MEMO_TELEMETRY_COUNTER.inc(),
+            3 => UTXO_STATE_TELEMETRY_COUNTER.inc(),
+            _ => {}
+        }
+    }
+
+    // a partition is only made unique, and so copied if a view holds it, when it holds `id`
+    fn remove_committed(&mut self, id: &KeyId, input_type: usize) -> Option<T> {
+        let value = match self.data.get_mut(&input_type) {
+            Some(partition) if partition.contains_key(id) => Arc::make_mut(partition).remove(id),
+            _ => None,
+        };
+        if let Some(encoded) = self.encoded.get_mut(&input_type) {
+            if encoded.contains_key(id) {
+                if let Some(bytes) = Arc::make_mut(encoded).remove(id) {
+                    self.partition_stats
+                        .entry(input_type)
+                        .or_insert_with(PartitionStats::default)
+                        .record_remove(bytes.len());
+                }
+            }
+        }
+        if value.is_some() {
+            match input_type {
+                1 => UTXO_COIN_TELEMETRY_COUNTER.dec(),
+                2 => UTXO_MEMO_TELEMETRY_COUNTER.dec(),
+                3 => UTXO_STATE_TELEMETRY_COUNTER.dec(),
+                _ => {}
+            }
+        }
+        value
+    }
+
+    /// Capacity of the encoding cache of partition `input_type`.
+    pub fn encoded_capacity(&self, input_type: usize) -> usize {
+        self.encoded
+            .get(&input_type)
+            .map(|encoded| encoded.capacity())
+            .unwrap_or(0)
+    }
+
+    /// Shrinks the encoding caches of all the partitions, see [`super::compaction`].
+    /// Nothing is compacted while a block is staged.
+    pub fn compact(&mut self) -> Vec<PartitionCompaction> {
+        self.compact_partitions(|_, _| true)
+    }
+
+    /// Shrinks the encoding caches of the partitions due for a compaction under `config`.
+    pub fn compact_if_needed(&mut self, config: &CompactionConfig) -> Vec<PartitionCompaction> {
+        self.compact_partitions(|entries, removed| config.is_due(entries, removed))
+    }
+
+    fn compact_partitions<F>(&mut self, due: F) -> Vec<PartitionCompaction>
+    where
+        F: Fn(u64, u64) -> bool,
+    {
+        let mut compacted = Vec::new();
+        if self.staged.is_some() {
+            return compacted;
+        }
+        let mut input_types: Vec<InputType> = self.encoded.keys().cloned().collect();
+        input_types.sort_unstable();
+        for input_type in input_types {
+            let stats = self
+                .partition_stats
+                .entry(input_type)
+                .or_insert_with(PartitionStats::default);
+            if !due(stats.entries, stats.removes_since_compaction) {
+                continue;
+            }
+            let removed = stats.removes_since_compaction;
+            stats.removes_since_compaction = 0;
+            let encoded = match self.encoded.get_mut(&input_type) {
+                Some(encoded) => encoded,
+                None => continue,
+            };
+            let capacity_before = encoded.capacity();
+            match Arc::get_mut(encoded) {
+                Some(unique) => unique.shrink_to_fit(),
+                // a view holds the cache, it keeps the map of its epoch
+                None => {
+                    *encoded = Arc::new(
+                        encoded
+                            .iter()
+                            .map(|(k, v)| (k.clone(), v.clone()))
+                            .collect(),
+                    )
+                }
+            }
+            let capacity_after = encoded.capacity();
+            compacted.push(PartitionCompaction {
+                input_type,
+                entries: encoded.len() as u64,
+                removed,
+                capacity_before,
+                capacity_after,
+                bytes_reclaimed: encoded_map_bytes(capacity_before)
+                    .saturating_sub(encoded_map_bytes(capacity_after)),
+            });
+        }
+        compacted
+    }
+}
+
+/// Utxo set as of the last block committed when the view was taken. The view shares
+/// the partitions of its epoch and is not changed by the blocks committed after it, a
+/// query reading a single view sees a block either fully applied or not at all.
+#[derive(Debug, Clone)]
+pub struct ReadView<T> {
+    data: HashMap<InputType, Arc<BTreeMap<KeyId, T>>>,
+    encoded: HashMap<InputType, Arc<HashMap<KeyId, Vec<u8>>>>,
+    pub epoch: u64,
+    pub block_height: SequenceNumber,
+}
+
+impl<T: Clone> ReadView<T> {
+    /// Utxos of partition `input_type` in key order.
+    pub fn partition(&self, input_type: usize) -> Option<&BTreeMap<KeyId, T>> {
+        self.data
+            .get(&input_type)
+            .map(|partition| partition.as_ref())
+    }
+
+    pub fn get(&self, id: &KeyId, input_type: usize) -> Option<&T> {
+        self.partition(input_type)
+            .and_then(|partition| partition.get(id))
+    }
+
+    /// Cached bincode encoding of a utxo of the view.
+    pub fn get_encoded(&self, id: &KeyId, input_type: usize) -> Option<&[u8]> {
+        self.encoded
+            .get(&input_type)
+            .and_then(|encoded| encoded.get(id))
+            .map(|bytes| bytes.as_slice())
+    }
+
+    pub fn count(&self, input_type: usize) -> u64 {
+        self.partition(input_type)
+            .map_or(0, |partition| partition.len() as u64)
+    }
+
+    /// See [`get_partition_page`].
+    pub fn page(
+        &self,
+        input_type: usize,
+        offset_key: Option<&KeyId>,
+        limit: usize,
+    ) -> Result<UtxoPage<T>, UtxosetError> {
+        match self.partition(input_type) {
+            Some(partition) => Ok(get_partition_page(partition, offset_key, limit)),
+            None => Err(UtxosetError::UtxoNotFound),
+        }
+    }
+}
+
+impl<T> LocalStorage<T>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Replaces the partitions with the contents of `snapshot`, scanned in order.
+    /// The encodings are copied to the cache as they are, not re-encoded.
+    pub fn load_from_flat_snapshot(&mut self, snapshot: &FlatSnapshot) -> Result<(), UtxosetError> {
+        if snapshot.partition_count() != self.partition_size {
+            return Err(UtxosetError::StaleSnapshot(format!(
+                "{} partitions, expected {}",
+                snapshot.partition_count(),
+                self.partition_size
+            )));
+        }
+        let mut data = HashMap::new();
+        let mut encoded = HashMap::new();
+        for input_type in 0..self.partition_size {
+            let mut partition = BTreeMap::new();
+            let mut partition_encoded = HashMap::new();
+            for (key, value) in snapshot.entries(input_type) {
+                partition.insert(key.to_vec(), bincode::deserialize(value)?);
+                partition_encoded.insert(key.to_vec(), value.to_vec());
+            }
+            data.insert(input_type, Arc::new(partition));
+            encoded.insert(input_type, Arc::new(partition_encoded));
+        }
+        for (input_type, partition_encoded) in encoded.iter() {
+            self.partition_stats
+                .entry(*input_type)
+                .or_insert_with(PartitionStats::default)
+                .reset_sizes(partition_encoded.values().map(|bytes| bytes.len()));
+        }
+        self.data = data;
+        self.encoded = encoded;
+        self.block_height = snapshot.block_height() as SequenceNumber;
+        Ok(())
+    }
+
+    /// Loads the flat snapshot of the snapshot path if it is current, see
+    /// [`FlatSnapshot::check_current`]. The utxo set is unchanged if it fails.
+    pub fn load_from_current_flat_snapshot(&mut self) -> Result<(), UtxosetError> {
+        let snapshot = FlatSnapshot::open(flat_snapshot_path(&self.snaps.snap_rules.path))?;
+        snapshot.check_current(persistence_store().as_ref())?;
+        self.load_from_flat_snapshot(&snapshot)
+    }
+}
+
 pub fn takesnapshotfrom_memory_to_postgresql_bulk()-> Result<(), UtxosetError>{
     let mut utxo_storage = crate::UTXO_STORAGE.lock()?;
 
@@ -338,7 +781,7 @@ pub fn takesnapshotfrom_memory_to_postgresql_bulk()-> Result<(), UtxosetError>{
     let snap_path = format!("{}-snapmap", snapshot_path.clone());
     let last_block = utxo_storage.block_height.clone();
     // let new_snapshot_id = utxo_storage.snaps.lastsnapid + 1;
-    let mut snap_partition_clone: Vec<(usize, HashMap<KeyId, zkvm::zkos_types::Output>)> =
+    let mut snap_partition_clone: Vec<(usize, Arc<BTreeMap<KeyId, zkvm::zkos_types::Output>>)> =
         Vec::new();
 
     let inner_snap_threadpool = ThreadPool::new(
@@ -375,3 +818,333 @@ pub fn takesnapshotfrom_memory_to_postgresql_bulk()-> Result<(), UtxosetError>{
     }
     Ok(())
 }
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn get_partition_page_test() {
+        let mut partition: BTreeMap<KeyId, u64> = BTreeMap::new();
+        for i in 0..100_000u64 {
+            // keys are inserted out of order
+            let key = (i.wrapping_mul(7919) % 100_000).to_be_bytes().to_vec();
+            partition.insert(key, i);
+        }
+
+        let mut seen: Vec<KeyId> = Vec::new();
+        let mut offset_key: Option<KeyId> = None;
+        loop {
+            let page = get_partition_page(&partition, offset_key.as_ref(), 999);
+            assert!(page.entries.len() <= 999);
+            seen.extend(page.entries.into_iter().map(|entry| entry.keyid));
+            match page.next_offset_key {
+                Some(key) => offset_key = Some(key),
+                None => break,
+            }
+        }
+
+        // every key exactly once and in order
+        let expected: Vec<KeyId> = partition.keys().cloned().collect();
+        assert_eq!(seen, expected);
+    }
+    #[test]
+    fn encoded_cache_test() {
+        let mut storage = LocalStorage::<Vec<String>>::new(3);
+        for i in 0..100u64 {
+            let value = vec![format!("memo-{}", i); (i % 4) as usize];
+            storage
+                .add(i.to_be_bytes().to_vec(), value, (i % 3) as usize)
+                .unwrap();
+        }
+        for (input_type, partition) in storage.data.iter() {
+            for (key, value) in partition.iter() {
+                assert_eq!(
+                    storage.get_encoded_by_id(key, *input_type).unwrap(),
+                    bincode::serialize(value).unwrap().as_slice()
+                );
+            }
+        }
+
+        let key = 3u64.to_be_bytes().to_vec();
+        storage.remove(key.clone(), 0).unwrap();
+        assert!(storage.get_encoded_by_id(&key, 0).is_none());
+
+        // a bulk load rebuilds the same cache
+        let cached = storage.encoded.clone();
+        storage.encoded.clear();
+        storage.encode_partitions().unwrap();
+        assert_eq!(storage.encoded, cached);
+    }
+    #[test]
+    fn add_collision_test() {
+        let mut storage = LocalStorage::<String>::new(3);
+        let key = utxo_key(&Utxo::random());
+        storage.add(key.clone(), String::from("coin"), 0).unwrap();
+        assert!(matches!(
+            storage.add(key.clone(), String::from("other coin"), 0),
+            Err(UtxosetError::UtxoAlreadyExists)
+        ));
+        assert_eq!(storage.get_utxo_by_id(key.clone(), 0).unwrap(), "coin");
+        assert_eq!(
+            storage.get_encoded_by_id(&key, 0).unwrap(),
+            bincode::serialize("coin").unwrap().as_slice()
+        );
+        assert_eq!(storage.get_count_by_type(0), 1);
+
+        // keys are unique within a partition
+        storage.add(key.clone(), String::from("memo"), 1).unwrap();
+        assert_eq!(storage.get_utxo_by_id(key, 1).unwrap(), "memo");
+    }
+    #[test]
+    fn storage_stats_test() {
+        let mut storage = LocalStorage::<Vec<u8>>::new(3);
+        // bincode writes a u64 length before the bytes
+        for i in 0..10u64 {
+            storage
+                .add(i.to_be_bytes().to_vec(), vec![0u8; 92], 0)
+                .unwrap();
+        }
+        storage.add(vec![1], vec![0u8; 1016], 2).unwrap();
+        let stats = storage.stats();
+        assert_eq!(stats.partitions["Coin"].entries, 10);
+        assert_eq!(stats.partitions["Coin"].approx_bytes, 1000);
+        assert_eq!(stats.partitions["State"].largest_entry_bytes, 1024);
+        assert_eq!(stats.total_bytes, 2024);
+        assert_eq!(stats.average_entry_bytes, 184);
+
+        // a colliding add changes nothing, removing an output does
+        assert!(storage.add(0u64.to_be_bytes().to_vec(), vec![0u8; 192], 0).is_err());
+        storage.remove(1u64.to_be_bytes().to_vec(), 0).unwrap();
+        let coin = storage.stats().partitions["Coin"].clone();
+        assert_eq!(coin.entries, 9);
+        assert_eq!(coin.approx_bytes, 900);
+        assert_eq!((coin.adds_since_start, coin.removes_since_start), (10, 1));
+
+        // a bulk load recomputes the sizes and keeps the counters
+        storage.partition_stats.get_mut(&0).unwrap().approx_bytes = 0;
+        storage.encode_partitions().unwrap();
+        assert_eq!(storage.stats().partitions["Coin"], coin);
+        assert_eq!(storage.stats().partitions["Memo"].entries, 0);
+    }
+    #[test]
+    fn upgrade_legacy_snapshot_keys_test() {
+        use zkvm::Hash;
+
+        // partition of a snapshot taken while utxo output indexes were a single byte
+        let fixture = include_str!("fixtures/legacy_snapshot_partition.hex");
+        let legacy: BTreeMap<KeyId, String> =
+            bincode::deserialize(&hex::decode(fixture.trim()).unwrap()).unwrap();
+        assert!(legacy
+            .keys()
+            .all(|key| key.len() == Utxo::LEGACY_ENCODED_LEN));
+
+        let partition = upgrade_partition_keys(legacy);
+        assert_eq!(partition.len(), 3);
+        for (txid, index, value) in [
+            ([0x11u8; 32], 0u16, "coin-0"),
+            ([0x11; 32], 1, "coin-1"),
+            ([0x22; 32], 255, "coin-255"),
+        ] {
+            let key = Utxo::from_hash(Hash(txid), index).to_bytes();
+            assert_eq!(partition.get(&key).map(String::as_str), Some(value));
+        }
+        // upgraded keys are left as they are
+        assert_eq!(upgrade_partition_keys(partition.clone()), partition);
+    }
+    #[test]
+    fn get_partition_page_exact_limit_test() {
+        let mut partition: BTreeMap<KeyId, u64> = BTreeMap::new();
+        for i in 0..10u64 {
+            partition.insert(i.to_be_bytes().to_vec(), i);
+        }
+        let page = get_partition_page(&partition, None, 10);
+        assert_eq!(page.entries.len(), 10);
+        assert!(page.next_offset_key.is_none());
+
+        let last = 9u64.to_be_bytes().to_vec();
+        let page = get_partition_page(&partition, Some(&last), 10);
+        assert!(page.entries.is_empty());
+        assert!(page.next_offset_key.is_none());
+    }
+
+    #[test]
+    fn get_partition_page_zero_limit_test() {
+        let mut partition: BTreeMap<KeyId, u64> = BTreeMap::new();
+        for i in 0..3u64 {
+            partition.insert(i.to_be_bytes().to_vec(), i);
+        }
+        let mut seen: Vec<u64> = Vec::new();
+        let mut offset_key: Option<KeyId> = None;
+        loop {
+            let page = get_partition_page(&partition, offset_key.as_ref(), 0);
+            assert_eq!(page.entries.len(), 1);
+            seen.extend(page.entries.iter().map(|entry| entry.output));
+            match page.next_offset_key {
+                Some(key) => offset_key = Some(key),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn staged_block_test() {
+        let mut storage = LocalStorage::<u64>::new(3);
+        let key = |i: u64| i.to_be_bytes().to_vec();
+        storage.add(key(1), 10, 0).unwrap();
+        storage.add(key(2), 20, 0).unwrap();
+
+        storage.begin_block();
+        storage.remove(key(1), 0).unwrap();
+        storage.add(key(3), 10, 0).unwrap();
+        // the writer reads through the staged writes
+        assert!(!storage.search_key(&key(1), 0).unwrap());
+        assert_eq!(storage.get_utxo_by_id(key(3), 0).unwrap(), 10);
+        assert_eq!(storage.get_count_by_type(0), 2);
+        assert!(matches!(
+            storage.add(key(3), 30, 0),
+            Err(UtxosetError::UtxoAlreadyExists)
+        ));
+        // the views do not see them
+        let before = storage.read_view();
+        assert_eq!(before.get(&key(1), 0), Some(&10));
+        assert!(before.get(&key(3), 0).is_none());
+        assert!(storage.get_encoded_by_id(&key(3), 0).is_none());
+
+        storage.commit_block();
+        let after = storage.read_view();
+        assert_eq!(after.epoch, before.epoch + 1);
+        assert!(after.get(&key(1), 0).is_none());
+        assert_eq!(after.get(&key(3), 0), Some(&10));
+        assert_eq!(
+            after.get_encoded(&key(3), 0).unwrap(),
+            bincode::serialize(&10u64).unwrap().as_slice()
+        );
+        // the view taken before the commit is unchanged
+        assert_eq!(before.get(&key(1), 0), Some(&10));
+        assert_eq!(before.count(0), 2);
+    }
+
+    #[test]
+    fn read_view_during_block_test() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Mutex;
+
+        const UTXOS: u64 = 100;
+        let storage = Arc::new(Mutex::new(LocalStorage::<u64>::new(3)));
+        let mut live: std::collections::VecDeque<u64> = (0..UTXOS).collect();
+        for i in 0..UTXOS {
+            storage
+                .lock()
+                .unwrap()
+                .add(i.to_be_bytes().to_vec(), 10, 0)
+                .unwrap();
+        }
+        let done = Arc::new(AtomicBool::new(false));
+
+        // every block moves values to new utxos, the total is the same before and after it
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let storage = storage.clone();
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    let mut last_epoch = 0;
+                    while !done.load(Ordering::SeqCst) {
+                        let view = storage.lock().unwrap().read_view();
+                        let partition = view.partition(0).unwrap();
+                        assert_eq!(partition.len() as u64, UTXOS);
+                        assert_eq!(partition.values().sum::<u64>(), UTXOS * 10);
+                        assert!(view.epoch >= last_epoch);
+                        last_epoch = view.epoch;
+                    }
+                })
+            })
+            .collect();
+
+        let mut next = UTXOS;
+        for _ in 0..200 {
+            storage.lock().unwrap().begin_block();
+            for _ in 0..10 {
+                // the lock is released between the writes, as between the txs of a block
+                let spent = live.pop_front().unwrap();
+                let value = storage
+                    .lock()
+                    .unwrap()
+                    .remove(spent.to_be_bytes().to_vec(), 0)
+                    .unwrap();
+                std::thread::yield_now();
+                storage
+                    .lock()
+                    .unwrap()
+                    .add(next.to_be_bytes().to_vec(), value, 0)
+                    .unwrap();
+                live.push_back(next);
+                next += 1;
+            }
+            storage.lock().unwrap().commit_block();
+        }
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        let mut storage = storage.lock().unwrap();
+        assert_eq!(storage.epoch, 200);
+        assert_eq!(storage.get_count_by_type(0), UTXOS);
+        assert!(storage
+            .search_key(&(next - 1).to_be_bytes().to_vec(), 0)
+            .unwrap());
+    }
+    #[test]
+    fn compaction_test() {
+        let mut storage = LocalStorage::<u64>::new(3);
+        for i in 0..10_000u64 {
+            storage.add(i.to_be_bytes().to_vec(), i, 0).unwrap();
+        }
+        for i in 100..10_000u64 {
+            storage.remove(i.to_be_bytes().to_vec(), 0).unwrap();
+        }
+        let filled = storage.encoded_capacity(0);
+        assert!(filled >= 10_000);
+
+        // fewer removals than the threshold leave the partition as is
+        let config = CompactionConfig {
+            removed_percent: 100,
+            interval_secs: 1,
+        };
+        assert!(storage.compact_if_needed(&config).is_empty());
+
+        // nothing is compacted while a block is staged
+        storage.begin_block();
+        assert!(storage.compact().is_empty());
+        storage.commit_block();
+
+        // a view taken before keeps its cache
+        let view = storage.read_view();
+        let compacted = storage.compact_if_needed(&CompactionConfig::default());
+        assert_eq!(compacted.len(), 1);
+        assert_eq!((compacted[0].entries, compacted[0].removed), (100, 9_900));
+        assert_eq!(compacted[0].capacity_before, filled);
+        assert!(storage.encoded_capacity(0) < filled / 10);
+        assert!(compacted[0].bytes_reclaimed > 0);
+        assert_eq!(
+            storage.stats().partitions["Coin"].removes_since_compaction,
+            0
+        );
+        assert_eq!(view.count(0), 100);
+        assert_eq!(
+            view.get_encoded(&5u64.to_be_bytes().to_vec(), 0),
+            storage.get_encoded_by_id(&5u64.to_be_bytes().to_vec(), 0)
+        );
+
+        // without a view the cache is shrunk in place
+        drop(view);
+        assert!(storage
+            .compact_if_needed(&CompactionConfig::default())
+            .is_empty());
+        assert_eq!(storage.compact().len(), 1);
+        assert_eq!(storage.get_count_by_type(0), 100);
+    }
+}
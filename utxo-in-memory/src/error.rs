@@ -35,8 +35,35 @@ pub enum UtxosetError {
     #[error("utxo not found")]
     UtxoNotFound,
 
+    #[error("utxo already exists")]
+    UtxoAlreadyExists,
+
     #[error("system time error")]
     SystemTimeError(#[from] std::time::SystemTimeError),
+
+    #[error("database is unavailable: {0}")]
+    DatabaseUnavailable(String),
+
+    #[error("{path} is corrupt: {reason}")]
+    CorruptFile { path: String, reason: String },
+
+    #[error("{path} has permissions {mode:o}, it must be readable by its owner only")]
+    InsecureFile { path: String, mode: u32 },
+
+    #[error("block result decode error: {0}")]
+    BlockResultDecodeError(String),
+
+    #[error("flat snapshot is stale: {0}")]
+    StaleSnapshot(String),
+
+    #[error("input spent by pending tx {txid}, a replacement needs a fee rate of {required}")]
+    MempoolConflict { txid: String, required: f64 },
+
+    #[error(transparent)]
+    ConfigError(#[from] crate::config::ConfigError),
+
+    #[error("json error")]
+    JsonError(#[from] serde_json::Error),
     // Add more error variants as needed
 }
 
@@ -1,48 +1,222 @@
+pub mod audit;
 pub mod blockoperations;
+pub mod config;
 pub mod db;
+pub mod network;
 pub mod pgsql;
+pub mod rpcaudit;
+pub mod scriptregistry;
+pub mod state;
 mod threadpool;
 pub mod error;
 //pub mod types;
 #[macro_use]
 extern crate lazy_static;
 pub use self::db::SnapShot;
-pub use self::threadpool::ThreadPool;
+pub use self::threadpool::{QueueFull, ThreadPool};
 use db::{LocalDBtrait, LocalStorage};
 pub use pgsql::init_psql;
+pub use state::{global_state, install_global_state, UtxoMetrics, UtxoState};
 use std::sync::{Arc, Mutex};
 use tungstenite::{connect, handshake::server::Response, Message, WebSocket};
 use url::Url;
 use zkvm::zkos_types::Output;
 use prometheus::{Encoder, TextEncoder, Counter, Gauge, register_counter, register_gauge};
 lazy_static! {
+    /// Utxo set of the global state, kept until callers pass a [`UtxoState`].
     pub static ref UTXO_STORAGE: Arc<Mutex<LocalStorage::<Output>>> =
-        Arc::new(Mutex::new(LocalStorage::<Output>::new(3)));
-    pub static ref  UTXO_MEMO_TELEMETRY_COUNTER: Gauge = register_gauge!("utxo_memo_count", "A counter for memo utxo").unwrap();
-    pub static ref  UTXO_STATE_TELEMETRY_COUNTER: Gauge = register_gauge!("utxo_state_count", "A counter for state utxo").unwrap();
-    pub static ref  UTXO_COIN_TELEMETRY_COUNTER: Gauge = register_gauge!("utxo_coin_count", "A counter for coin utxo").unwrap();
+        global_state().utxo_storage.clone();
+    pub static ref  UTXO_MEMO_TELEMETRY_COUNTER: Gauge = global_state().metrics.utxo_memo_count.clone();
+    pub static ref  UTXO_STATE_TELEMETRY_COUNTER: Gauge = global_state().metrics.utxo_state_count.clone();
+    pub static ref  UTXO_COIN_TELEMETRY_COUNTER: Gauge = global_state().metrics.utxo_coin_count.clone();
 }
-use blockoperations::blockprocessing::{total_coin_type_utxos, total_state_type_utxos, total_memo_type_utxos};
 
+/// Checks the block height file before [`init_utxo`], exits if it is corrupt.
+/// See [`db::checkedfile::startup_block_height`].
+pub fn check_block_height_on_startup() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    match db::checkedfile::startup_block_height(db::checkedfile::block_height_file(), &args) {
+        Ok(height) => height,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Replaces the utxo set with the `genesis` records, e.g. of
+/// `transaction::reference_tx::create_genesis_block`, to start a test from a known state.
+pub fn reset_utxo_storage(genesis: &[transaction::reference_tx::RecordUtxo]) {
+    let mut utxo_storage = LocalStorage::<Output>::new(3);
+    for record in genesis {
+        let key = db::utxo_key(&record.utx);
+        let _ = utxo_storage.add(key, record.value.clone(), record.value.out_type as usize);
+    }
+    *UTXO_STORAGE.lock().unwrap() = utxo_storage;
+}
+
+/// Loads the global state, see [`init_utxo_state`].
 pub fn init_utxo() {
+    init_utxo_state(&global_state());
+}
+
+/// Loads the utxo set and the side stores of `state` from the flat snapshot or psql.
+/// The state of a secondary network is loaded from its store, see [`network`].
+pub fn init_utxo_state(state: &UtxoState) {
+    if !state.is_primary() {
+        // a secondary network is loaded back from the rows of its store
+        match state.store().load_state() {
+            Ok(stored) => state.load_stored(stored),
+            Err(e) => {
+                eprintln!("Failed to load the state of {}: {}", state.network(), e);
+                std::process::exit(1);
+            }
+        }
+        if let Err(e) = blockoperations::balanceindexer::init_balance_indexer(state) {
+            eprintln!("Failed to start the balance indexer: {}", e);
+            std::process::exit(1);
+        }
+        println!(
+            "tracking {} from height {}",
+            state.network(),
+            state.utxo_storage.lock().unwrap().block_height
+        );
+        return;
+    }
     println!("starting utxo init");
     init_psql();
     
     {
-        let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
+        let mut utxo_storage = state.utxo_storage.lock().unwrap();
         // let _ = utxo_storage.load_from_snapshot();
-        let _ = utxo_storage.load_from_snapshot_from_psql();
+        // the flat snapshot is only used if psql has no later block or snapshot
+        match utxo_storage.load_from_current_flat_snapshot() {
+            Ok(()) => println!(
+                "finished loading from flat snapshot at height {}",
+                utxo_storage.block_height
+            ),
+            Err(e) => {
+                println!("flat snapshot not used, {}", e);
+                let _ = utxo_storage.load_from_snapshot_from_psql();
+                println!("finished loading from psql");
+            }
+        }
+        utxo_storage.stats().publish_gauges();
+    }
 
-        println!("finished loading from psql");
+    match pgsql::load_block_tx_roots_from_psql(
+        blockoperations::txroot::BLOCK_TX_ROOT_RETENTION as i64,
+    ) {
+        Ok(roots) => {
+            let mut block_tx_roots = state.block_tx_roots.lock().unwrap();
+            for root in roots {
+                block_tx_roots.insert(root);
+            }
+        }
+        Err(e) => eprintln!("Failed to load block tx roots from psql: {}", e),
     }
 
-    UTXO_MEMO_TELEMETRY_COUNTER.set(total_memo_type_utxos() as f64);
-    UTXO_STATE_TELEMETRY_COUNTER.set(total_state_type_utxos() as f64);
-    UTXO_COIN_TELEMETRY_COUNTER.set(total_coin_type_utxos() as f64);
+    match pgsql::load_block_fees_from_psql(blockoperations::fees::BLOCK_FEES_RETENTION as i64) {
+        Ok(block_fees) => {
+            let mut fee_store = state.block_fees.lock().unwrap();
+            for fees in block_fees {
+                fee_store.insert(fees);
+            }
+        }
+        Err(e) => eprintln!("Failed to load block fees from psql: {}", e),
+    }
 
-    println!("UTXO Memo Telemetry Counter Value: {}", UTXO_MEMO_TELEMETRY_COUNTER.get());
-    println!("UTXO coin Telemetry Counter Value: {}", UTXO_COIN_TELEMETRY_COUNTER.get());
-    println!("UTXO state Telemetry Counter Value: {}", UTXO_STATE_TELEMETRY_COUNTER.get());
+    match pgsql::load_block_results_from_psql(
+        blockoperations::blockresult::BLOCK_APPLY_RESULT_RETENTION as i64,
+    ) {
+        Ok(results) => {
+            let mut result_store = state.block_results.lock().unwrap();
+            for result in results {
+                result_store.insert(result);
+            }
+        }
+        Err(e) => eprintln!("Failed to load block results from psql: {}", e),
+    }
+
+    let nullifier_depth = state.nullifiers.lock().unwrap().depth;
+    match pgsql::load_block_nullifiers_from_psql(nullifier_depth as i64) {
+        Ok(blocks) => {
+            let mut nullifiers = state.nullifiers.lock().unwrap();
+            for block in blocks {
+                nullifiers.insert_block(&block);
+            }
+        }
+        Err(e) => eprintln!("Failed to load block nullifiers from psql: {}", e),
+    }
+
+    match pgsql::load_script_contracts_from_psql() {
+        Ok(contracts) => {
+            let mut registry = scriptregistry::SCRIPT_REGISTRY.lock().unwrap();
+            for contract in contracts {
+                registry.insert(contract);
+            }
+        }
+        Err(e) => eprintln!("Failed to load script contracts from psql: {}", e),
+    }
+
+    match pgsql::load_prune_logs_from_psql(
+        blockoperations::pruning::PRUNE_LOG_RETENTION as i64,
+    ) {
+        Ok(logs) => {
+            let mut prune_logs = blockoperations::pruning::PRUNE_LOGS.lock().unwrap();
+            for log in logs {
+                prune_logs.insert(log);
+            }
+        }
+        Err(e) => eprintln!("Failed to load prune logs from psql: {}", e),
+    }
+
+    match pgsql::load_burns_from_psql() {
+        Ok(burns) => {
+            let mut burn_history = state.burn_history.lock().unwrap();
+            for burn in burns {
+                let amount = burn.amount;
+                if burn_history.insert(burn) {
+                    state.metrics.burned.inc_by(amount as f64);
+                }
+            }
+        }
+        Err(e) => eprintln!("Failed to load burns from psql: {}", e),
+    }
+
+    match pgsql::load_address_txs_from_psql(
+        blockoperations::addresstx::ADDRESS_TX_RETENTION as i64,
+    ) {
+        Ok(entries) => {
+            let mut address_index = state.address_txs.lock().unwrap();
+            for entry in entries {
+                address_index.insert(entry);
+            }
+        }
+        Err(e) => eprintln!("Failed to load address txs from psql: {}", e),
+    }
+
+    match pgsql::load_failed_txs_from_psql() {
+        Ok(failed_txs) => {
+            let mut failed_store = state.failed_txs.lock().unwrap();
+            for failed_tx in failed_txs {
+                failed_store.failed.insert(failed_tx.txid.clone(), failed_tx);
+            }
+        }
+        Err(e) => eprintln!("Failed to load failed txs from psql: {}", e),
+    }
+
+    // refuses to start with an unreadable or insecure key file
+    if let Err(e) = blockoperations::balanceindexer::init_balance_indexer(state) {
+        eprintln!("Failed to start the balance indexer: {}", e);
+        std::process::exit(1);
+    }
+
+    state.metrics.set_utxo_counts(&mut state.utxo_storage.lock().unwrap());
+
+    println!("UTXO Memo Telemetry Counter Value: {}", state.metrics.utxo_memo_count.get());
+    println!("UTXO coin Telemetry Counter Value: {}", state.metrics.utxo_coin_count.get());
+    println!("UTXO state Telemetry Counter Value: {}", state.metrics.utxo_state_count.get());
 
     //load data from intial block from chain
     // if utxo_storage.block_height == 0 {
@@ -71,18 +245,11 @@ pub fn init_utxo() {
 
 //     Ok((socket, response))
 // }
-pub fn zk_oracle_subscriber() {
-    println!("started zk subsciber");
-    let url_str = "ws://0.0.0.0:7001/latestblock";
-    let url = Url::parse(url_str);
-    let url: Url = match url {
-        Ok(url) => url,
-        Err(e) => {
-            println!("Invalid URL: {}", e);
-            return;
-        }
-    };
-
+/// Applies the blocks streamed by the chain oracle at `url`, the
+/// `NYKS_BLOCK_SUBSCRIBER_URL` of [`config::UtxoStoreConfig`] for the network of `state`,
+/// to `state`.
+pub fn zk_oracle_subscriber(state: Arc<UtxoState>, url: Url) {
+    println!("started zk subsciber of {}", state.network());
     let (mut socket, _response) =
         connect(url).expect("Can't establish a web socket connection to ZKOracle");
 
@@ -94,9 +261,32 @@ pub fn zk_oracle_subscriber() {
             Message::Text(text) => {
                 let block: blockoperations::blockprocessing::Block =
                     serde_json::from_str(&text).unwrap();
-                let result = blockoperations::blockprocessing::process_block_for_utxo_insert(block);
-                if result.suceess_tx.len() > 0 {
-                    save_snapshot();
+                // every block is tracked, including blocks without zkos transactions
+                if let Err(e) = state.track_block_header(block.header()) {
+                    println!("chain continuity error: {}", e);
+                }
+                let block_height = block.block_height;
+                let result = state.process_block(block);
+                // a held block is applied by a resume, its height is written with a later block
+                if let Some(violation) = state.weight_halt() {
+                    println!("BLOCK RESULT : height={} held, {}", block_height, violation);
+                    continue;
+                }
+                // the snapshots hold the primary network only
+                if result.suceess_tx.len() > 0 && state.is_primary() {
+                    save_snapshot(&state);
+                }
+                // one line summary of the block, from its stored apply result
+                let result_store = state.block_results.lock().unwrap();
+                match result_store.get(block_height) {
+                    Some(applied) => println!("BLOCK RESULT : {}", applied),
+                    None => println!("BLOCK RESULT : height={} not applied", block_height),
+                }
+                drop(result_store);
+                if let Err(e) =
+                    db::checkedfile::write_block_height(state.block_height_file(), block_height)
+                {
+                    eprintln!("Failed to write block height: {}", e);
                 }
             }
             Message::Close(_) => {
@@ -113,14 +303,9 @@ pub fn zk_oracle_subscriber() {
     //}
 }
 
-fn save_snapshot() {
-    let mut utxo_storage = UTXO_STORAGE.lock().unwrap();
-    println!("get block height:{:#?}", utxo_storage.block_height);
-    println!("get snap:{:#?}", utxo_storage.snaps);
-    for i in 0..utxo_storage.partition_size {
-        println!("get snap:{:#?}", utxo_storage.data.get(&i).unwrap().len());
+fn save_snapshot(state: &UtxoState) {
+    let mut utxo_storage = state.utxo_storage.lock().unwrap();
+    if let Err(e) = utxo_storage.take_snapshot() {
+        eprintln!("Failed to take snapshot: {:?}", e);
     }
-    let res = utxo_storage.take_snapshot();
-    // log the result
-    println!("get snap:{:#?}", res);
 }
@@ -5,10 +5,48 @@ extern crate lazy_static;
 use utxo_in_memory::*;
 
 fn main() {
+    // refuses to start on an invalid config
+    let config = match config::UtxoStoreConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if std::env::args().any(|arg| arg == "--print-config") {
+        print!("{}", config.redacted());
+        return;
+    }
+
     let sw = Stopwatch::start_new();
-    init_utxo();
+    let block_height = check_block_height_on_startup();
+    let state = std::sync::Arc::new(UtxoState::for_network(config.network));
+    if install_global_state(state.clone()).is_err() {
+        eprintln!("utxo state is already in use");
+        std::process::exit(1);
+    }
+    init_utxo_state(&state);
+    if let Some(height) = block_height {
+        state.utxo_storage.lock().unwrap().block_height = height as usize;
+    }
     let time1 = sw.elapsed();
     println!("init_utxo: {:#?}", time1);
+
+    // exits with 1 if memory and PostgreSQL diverge, 2 if the audit could not run
+    if std::env::args().any(|arg| arg == "--audit") {
+        match audit::compare_memory_to_psql() {
+            Ok(report) => {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                if !report.consistent {
+                    std::process::exit(1);
+                }
+            }
+            Err(arg) => {
+                eprintln!("utxo audit failed: {:?}", arg);
+                std::process::exit(2);
+            }
+        }
+    }
 }
 
 // pub fn load_utxo() {
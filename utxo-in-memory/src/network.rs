@@ -0,0 +1,190 @@
+//! Chains of several networks tracked by one process.
+//!
+//! Each network has its own [`UtxoState`], so the utxo set, the address index, the
+//! block height, the last block header, the block stats and the indexed balances of a
+//! network are never shared, and the metrics of a state carry its network as the
+//! `network` label. The primary state, the network of `ZKOS_NETWORK`, is installed as
+//! the global state and written to PostgreSQL and the snapshot files. A network of
+//! `SECONDARY_NETWORKS` is applied from its own oracle subscriber to a
+//! [`UtxoState::secondary`], which writes to the store it is created with and keeps its
+//! block height in a file of its own. The api server gives it a
+//! [`crate::pgsql::NetworkStore`], the rows of the network in the `network_records`
+//! table, and it is loaded back from them at startup. The other PostgreSQL tables and the snapshots hold the primary network only.
+//!
+//! An address carries its network in its magic byte, while the partitions match the
+//! owners of the utxos by public key. A lookup of an address in the state of another
+//! network is therefore refused, see [`NetworkStates::state_for_address`].
+
+use crate::state::UtxoState;
+use address::Network;
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    #[error("network {0} is not tracked by this node")]
+    NotTracked(Network),
+
+    #[error("invalid address, {0}")]
+    InvalidAddress(String),
+
+    #[error("cross network query, the address is on {address_network} and the query on {network}")]
+    Mismatch {
+        address_network: Network,
+        network: Network,
+    },
+}
+
+/// Network of the hex encoded `address`, from its magic byte.
+pub fn address_network(address: &str) -> Result<Network, NetworkError> {
+    let bytes =
+        hex::decode(address.trim()).map_err(|e| NetworkError::InvalidAddress(e.to_string()))?;
+    let magic = bytes
+        .first()
+        .ok_or_else(|| NetworkError::InvalidAddress("empty address".to_string()))?;
+    Network::from_u8(*magic).map_err(|e| NetworkError::InvalidAddress(e.to_string()))
+}
+
+/// Fails unless the hex encoded `address` is an address of `network`.
+pub fn check_address_network(address: &str, network: Network) -> Result<(), NetworkError> {
+    let address_network = address_network(address)?;
+    if address_network != network {
+        return Err(NetworkError::Mismatch {
+            address_network,
+            network,
+        });
+    }
+    Ok(())
+}
+
+/// File of a secondary network next to the file `path` of the primary one.
+pub fn network_path(path: &str, network: Network) -> String {
+    format!("{}.{}", path, network)
+}
+
+/// States of the networks tracked by a node, by network.
+#[derive(Clone)]
+pub struct NetworkStates {
+    primary: Network,
+    states: HashMap<Network, Arc<UtxoState>>,
+}
+
+impl NetworkStates {
+    /// Tracks the network of `primary` only.
+    pub fn new(primary: Arc<UtxoState>) -> Self {
+        let network = primary.network();
+        NetworkStates {
+            primary: network,
+            states: HashMap::from([(network, primary)]),
+        }
+    }
+
+    /// Tracks the network of `state` as well. The state of the primary network is
+    /// not replaced.
+    pub fn with_state(mut self, state: Arc<UtxoState>) -> Self {
+        if state.network() != self.primary {
+            self.states.insert(state.network(), state);
+        }
+        self
+    }
+
+    /// Network of the queries without a network.
+    pub fn primary(&self) -> Network {
+        self.primary
+    }
+
+    pub fn primary_state(&self) -> Arc<UtxoState> {
+        self.states[&self.primary].clone()
+    }
+
+    /// Tracked networks, the primary one first.
+    pub fn networks(&self) -> Vec<Network> {
+        let mut networks: Vec<Network> = self.states.keys().copied().collect();
+        networks.sort_by_key(|network| (*network != self.primary, network.as_str()));
+        networks
+    }
+
+    /// State of `network`, of the primary network if None.
+    pub fn state(&self, network: Option<Network>) -> Result<Arc<UtxoState>, NetworkError> {
+        let network = network.unwrap_or(self.primary);
+        self.states
+            .get(&network)
+            .cloned()
+            .ok_or(NetworkError::NotTracked(network))
+    }
+
+    /// State of `network` to look up the hex encoded `address` in, refused if the
+    /// address is of another network.
+    pub fn state_for_address(
+        &self,
+        network: Option<Network>,
+        address: &str,
+    ) -> Result<Arc<UtxoState>, NetworkError> {
+        let state = self.state(network)?;
+        check_address_network(address, state.network())?;
+        Ok(state)
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pgsql::MemoryStore;
+    use address::Address;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+
+    #[test]
+    fn network_states_test() {
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(1u64));
+        let (pk, _) = account.get_account();
+        let mainnet = Address::standard_address(Network::Mainnet, pk).as_hex();
+        let testnet = Address::standard_address(Network::Testnet, pk).as_hex();
+        assert_eq!(address_network(&testnet), Ok(Network::Testnet));
+        assert!(matches!(
+            address_network("zz"),
+            Err(NetworkError::InvalidAddress(_))
+        ));
+
+        let primary = Arc::new(UtxoState::with_store(Arc::new(MemoryStore::new())));
+        let states = NetworkStates::new(primary.clone());
+        assert_eq!(states.networks(), vec![Network::Mainnet]);
+        assert_eq!(
+            states.state(Some(Network::Testnet)).err(),
+            Some(NetworkError::NotTracked(Network::Testnet))
+        );
+
+        let secondary = Arc::new(UtxoState::secondary(
+            Network::Testnet,
+            Arc::new(MemoryStore::new()),
+        ));
+        let states = states.with_state(secondary.clone());
+        assert_eq!(states.networks(), vec![Network::Mainnet, Network::Testnet]);
+        assert!(Arc::ptr_eq(&states.state(None).unwrap(), &primary));
+        assert!(Arc::ptr_eq(
+            &states
+                .state_for_address(Some(Network::Testnet), &testnet)
+                .unwrap(),
+            &secondary
+        ));
+        // the same key on the other network is refused
+        assert_eq!(
+            states.state_for_address(None, &testnet).err(),
+            Some(NetworkError::Mismatch {
+                address_network: Network::Testnet,
+                network: Network::Mainnet
+            })
+        );
+        assert!(states
+            .state_for_address(Some(Network::Testnet), &mainnet)
+            .is_err());
+        assert_eq!(
+            secondary.block_height_file(),
+            network_path(&primary.block_height_file(), Network::Testnet)
+        );
+    }
+}
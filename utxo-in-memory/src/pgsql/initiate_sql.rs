@@ -1,24 +1,53 @@
+use crate::config::UtxoStoreConfig;
+use crate::state::global_state;
+use crate::threadpool::{env_or, DEFAULT_QUEUE_CAPACITY};
 use crate::{error::UtxosetError, ThreadPool};
 use r2d2_postgres::postgres::NoTls;
+use r2d2::PooledConnection;
 use r2d2_postgres::PostgresConnectionManager;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use zkvm::zkos_types::Utxo;
 
 lazy_static! {
     pub static ref POSTGRESQL_POOL_CONNECTION: r2d2::Pool<PostgresConnectionManager<NoTls>> = {
-        dotenv::dotenv().expect("Failed loading dotenv");
-        let postgresql_url =
-            std::env::var("POSTGRESQL_URL").expect("missing environment variable POSTGRESQL_URL");
-        
-        let manager = PostgresConnectionManager::new(postgresql_url.parse().expect("Can not parse the POSTGRES_URL credentials to create a database connection"), NoTls);
+        let config = UtxoStoreConfig::load().unwrap_or_else(|e| panic!("{}", e));
+        let manager = PostgresConnectionManager::new(config.postgresql_url.parse().expect("Can not parse the POSTGRES_URL credentials to create a database connection"), NoTls);
         match r2d2::Pool::new(manager){
             Ok(pool) => pool,
             Err(e) => panic!("Error creating r2d2 pool: {}", e)
         }
     };
-    pub static ref THREADPOOL_SQL_QUEUE: Mutex<ThreadPool> =
-        Mutex::new(ThreadPool::new(1, String::from("THREADPOOL_SQL_QUEUE")));
-    pub static ref THREADPOOL_SQL_QUERY: Mutex<ThreadPool> =
-        Mutex::new(ThreadPool::new(4, String::from("THREADPOOL_SQL_QUEUE")));
+    /// Write queue of the global state, see [`crate::state`].
+    pub static ref THREADPOOL_SQL_QUEUE: Arc<Mutex<ThreadPool>> = global_state().sql_queue.clone();
+    pub static ref THREADPOOL_SQL_QUERY: Mutex<ThreadPool> = {
+        Mutex::new(ThreadPool::with_capacity(
+            env_or("THREADPOOL_SQL_QUERY_SIZE", 4),
+            env_or("THREADPOOL_SQL_QUERY_CAPACITY", DEFAULT_QUEUE_CAPACITY),
+            String::from("THREADPOOL_SQL_QUERY"),
+        ))
+    };
+}
+
+/// Pool of the writes to the persistence store, run in queue order.
+pub fn new_sql_queue() -> ThreadPool {
+    ThreadPool::with_capacity(
+        env_or("THREADPOOL_SQL_QUEUE_SIZE", 1),
+        env_or("THREADPOOL_SQL_QUEUE_CAPACITY", DEFAULT_QUEUE_CAPACITY),
+        String::from("THREADPOOL_SQL_QUEUE"),
+    )
+}
+
+/// Returns a connection from the PostgreSQL pool.
+/// Unlike using `POSTGRESQL_POOL_CONNECTION` directly, this does not panic if the pool
+/// can not be created, e.g. when POSTGRESQL_URL is missing or the database is down.
+pub fn get_psql_connection(
+) -> Result<PooledConnection<PostgresConnectionManager<NoTls>>, UtxosetError> {
+    match std::panic::catch_unwind(|| POSTGRESQL_POOL_CONNECTION.get()) {
+        Ok(connection) => Ok(connection?),
+        Err(_) => Err(UtxosetError::DatabaseUnavailable(
+            "PostgreSQL connection pool could not be created".to_string(),
+        )),
+    }
 }
 pub fn init_psql() {
     match create_utxo_coin_table() {
@@ -33,6 +62,70 @@ pub fn init_psql() {
         Ok(_) => println!("utxo_state_logs table inserted successfully"),
         Err(arg) => println!("Some Error 109 Found, {:#?}", arg),
     }
+    match create_block_tx_root_table() {
+        Ok(_) => println!("block_tx_roots table inserted successfully"),
+        Err(arg) => println!("Some Error 113 Found, {:#?}", arg),
+    }
+    match create_block_fees_table() {
+        Ok(_) => println!("block_fees table inserted successfully"),
+        Err(arg) => println!("Some Error 117 Found, {:#?}", arg),
+    }
+    match create_burns_table() {
+        Ok(_) => println!("burns table inserted successfully"),
+        Err(arg) => println!("Some Error 121 Found, {:#?}", arg),
+    }
+    match create_failed_txs_table() {
+        Ok(_) => println!("failed_txs table inserted successfully"),
+        Err(arg) => println!("Some Error 125 Found, {:#?}", arg),
+    }
+    match create_address_transactions_table() {
+        Ok(_) => println!("address_transactions table inserted successfully"),
+        Err(arg) => println!("Some Error 129 Found, {:#?}", arg),
+    }
+    match create_indexed_balances_table() {
+        Ok(_) => println!("indexed_balances table inserted successfully"),
+        Err(arg) => println!("Some Error 133 Found, {:#?}", arg),
+    }
+    match create_block_results_table() {
+        Ok(_) => println!("block_results table inserted successfully"),
+        Err(arg) => println!("Some Error 137 Found, {:#?}", arg),
+    }
+    match create_script_contracts_table() {
+        Ok(_) => println!("script_contracts table inserted successfully"),
+        Err(arg) => println!("Some Error 141 Found, {:#?}", arg),
+    }
+    match create_pruned_outputs_table() {
+        Ok(_) => println!("pruned_outputs table inserted successfully"),
+        Err(arg) => println!("Some Error 149 Found, {:#?}", arg),
+    }
+    match create_prune_logs_table() {
+        Ok(_) => println!("prune_logs table inserted successfully"),
+        Err(arg) => println!("Some Error 153 Found, {:#?}", arg),
+    }
+    match create_flat_snapshots_table() {
+        Ok(_) => println!("flat_snapshots table inserted successfully"),
+        Err(arg) => println!("Some Error 157 Found, {:#?}", arg),
+    }
+    match create_rpc_audit_table() {
+        Ok(_) => println!("rpc_audit table inserted successfully"),
+        Err(arg) => println!("Some Error 161 Found, {:#?}", arg),
+    }
+    match create_tx_status_table() {
+        Ok(_) => println!("tx_status table inserted successfully"),
+        Err(arg) => println!("Some Error 165 Found, {:#?}", arg),
+    }
+    match create_block_nullifiers_table() {
+        Ok(_) => println!("block_nullifiers table inserted successfully"),
+        Err(arg) => println!("Some Error 169 Found, {:#?}", arg),
+    }
+    match create_network_records_table() {
+        Ok(_) => println!("network_records table inserted successfully"),
+        Err(arg) => println!("Some Error 173 Found, {:#?}", arg),
+    }
+    match upgrade_legacy_utxo_keys() {
+        Ok(_) => println!("utxo keys upgraded successfully"),
+        Err(arg) => println!("Some Error 145 Found, {:#?}", arg),
+    }
 }
 
 fn create_utxo_coin_table() -> Result<(), UtxosetError> {
@@ -61,13 +154,19 @@ fn create_utxo_memo_table() -> Result<(), UtxosetError> {
             script_address VARCHAR(42),
             txid CHAR(64),
             vout BIGINT,
-            block_height BIGINT       
+            block_height BIGINT,
+            timebounds BIGINT NOT NULL DEFAULT 0
           );"
     );
 
     let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
 
     client.execute(&query, &[])?;
+    // tables created before the timebounds column was added
+    client.execute(
+        "ALTER TABLE public.utxo_memo_logs ADD COLUMN IF NOT EXISTS timebounds BIGINT NOT NULL DEFAULT 0;",
+        &[],
+    )?;
     Ok(())
 }
 fn create_utxo_state_table() -> Result<(), UtxosetError> {
@@ -79,7 +178,78 @@ fn create_utxo_state_table() -> Result<(), UtxosetError> {
             script_address VARCHAR(42),
             txid CHAR(64),
             vout BIGINT,
-            block_height BIGINT       
+            block_height BIGINT,
+            timebounds BIGINT NOT NULL DEFAULT 0
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    // tables created before the timebounds column was added
+    client.execute(
+        "ALTER TABLE public.utxo_state_logs ADD COLUMN IF NOT EXISTS timebounds BIGINT NOT NULL DEFAULT 0;",
+        &[],
+    )?;
+    Ok(())
+
+}
+
+fn create_block_tx_root_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.block_tx_roots (
+            block_height BIGINT PRIMARY KEY,
+            root BYTEA,
+            txids BYTEA
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+fn create_block_fees_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.block_fees (
+            block_height BIGINT PRIMARY KEY,
+            total_fees BIGINT,
+            tx_count BIGINT
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+fn create_burns_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.burns (
+            txid CHAR(64) PRIMARY KEY,
+            address VARCHAR,
+            amount BIGINT,
+            block_height BIGINT
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+fn create_failed_txs_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.failed_txs (
+            txid CHAR(64) PRIMARY KEY,
+            block_height BIGINT,
+            tx VARCHAR,
+            error VARCHAR,
+            timestamp BIGINT,
+            resolved_at BIGINT
           );"
     );
 
@@ -87,9 +257,232 @@ fn create_utxo_state_table() -> Result<(), UtxosetError> {
 
     client.execute(&query, &[])?;
     Ok(())
+}
+
+fn create_address_transactions_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.address_transactions (
+            id BIGSERIAL,
+            address VARCHAR,
+            txid CHAR(64),
+            block_height BIGINT,
+            direction VARCHAR(4),
+            PRIMARY KEY (address, txid)
+          );
+          CREATE INDEX IF NOT EXISTS address_transactions_address_height
+            ON public.address_transactions (address, block_height);"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.batch_execute(&query)?;
+    Ok(())
+}
+
+fn create_indexed_balances_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.indexed_balances (
+            address VARCHAR PRIMARY KEY,
+            balance BIGINT,
+            block_height BIGINT
+          );
+          CREATE TABLE IF NOT EXISTS public.indexed_utxos (
+            utxo BYTEA PRIMARY KEY,
+            address VARCHAR,
+            value BIGINT
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
 
+    client.batch_execute(&query)?;
+    Ok(())
 }
 
+fn create_block_results_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.block_results (
+            block_height BIGINT PRIMARY KEY,
+            result BYTEA
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+fn create_script_contracts_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.script_contracts (
+            root CHAR(64) PRIMARY KEY,
+            name VARCHAR,
+            programs JSONB,
+            metadata JSONB,
+            mainnet_address VARCHAR,
+            testnet_address VARCHAR
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+// memo outputs pruned from the utxo set, with the columns of utxo_memo_logs
+fn create_pruned_outputs_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.pruned_outputs (
+            utxo BYTEA PRIMARY KEY,
+            output BYTEA,
+            owner_address BYTEA,
+            script_address VARCHAR(42),
+            txid CHAR(64),
+            vout BIGINT,
+            block_height BIGINT,
+            timebounds BIGINT NOT NULL DEFAULT 0,
+            pruned_height BIGINT
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+fn create_prune_logs_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.prune_logs (
+            id BIGSERIAL PRIMARY KEY,
+            block_height BIGINT,
+            action VARCHAR(16),
+            utxo VARCHAR,
+            script_address VARCHAR,
+            timebounds BIGINT
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+// block height and checksum of every flat snapshot written, see `db::flatsnapshot`
+fn create_flat_snapshots_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.flat_snapshots (
+            block_height BIGINT PRIMARY KEY,
+            checksum CHAR(64),
+            entries BIGINT
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.execute(&query, &[])?;
+    Ok(())
+}
+
+// mutating rpc calls of the node, see `rpcaudit`; pruned by timestamp
+fn create_rpc_audit_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.rpc_audit (
+            id BIGSERIAL PRIMARY KEY,
+            timestamp BIGINT,
+            method VARCHAR(64),
+            client_ip VARCHAR,
+            api_key_id CHAR(64),
+            target VARCHAR,
+            owner CHAR(64),
+            outcome VARCHAR(16),
+            latency_ms BIGINT
+          );
+          CREATE INDEX IF NOT EXISTS rpc_audit_timestamp ON public.rpc_audit (timestamp);"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.batch_execute(&query)?;
+    Ok(())
+}
+
+// status transitions of the submitted txs, see `txstatus`; the status is tagged json
+fn create_tx_status_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.tx_status (
+            id BIGSERIAL PRIMARY KEY,
+            txid CHAR(64),
+            status JSONB,
+            timestamp BIGINT
+          );
+          CREATE INDEX IF NOT EXISTS tx_status_txid ON public.tx_status (txid);"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.batch_execute(&query)?;
+    Ok(())
+}
+
+// nullifiers spent by the latest blocks, see `nullifiers`; the entries are json
+fn create_block_nullifiers_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.block_nullifiers (
+            block_height BIGINT PRIMARY KEY,
+            entries JSONB
+          );"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.batch_execute(&query)?;
+    Ok(())
+}
+
+// rows of the secondary networks, see `NetworkStore`; the records are json
+fn create_network_records_table() -> Result<(), UtxosetError> {
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS public.network_records (
+            network VARCHAR(16),
+            kind VARCHAR(32),
+            key TEXT,
+            block_height BIGINT,
+            record JSONB,
+            PRIMARY KEY (network, kind, key)
+          );
+          CREATE INDEX IF NOT EXISTS network_records_height
+            ON public.network_records (network, kind, block_height);"
+    );
+
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+
+    client.batch_execute(&query)?;
+    Ok(())
+}
+
+// keys written while utxo output indexes were a single byte gain the zero high byte
+// of the u16 index, see `Utxo::upgrade_key`
+fn upgrade_legacy_utxo_keys() -> Result<(), UtxosetError> {
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    for table in [
+        "utxo_coin_logs",
+        "utxo_memo_logs",
+        "utxo_state_logs",
+        "indexed_utxos",
+    ] {
+        let query = format!(
+            "UPDATE public.{} SET utxo = utxo || '\\x00'::bytea WHERE length(utxo) = {};",
+            table,
+            Utxo::LEGACY_ENCODED_LEN
+        );
+        client.execute(&query, &[])?;
+    }
+    Ok(())
+}
 
 // // ------------------------------------------------------------------------
 // // Tests
@@ -1,10 +1,16 @@
 mod initiate_sql;
 mod sql;
 mod sql_api;
+mod store;
 mod test_tx;
 pub use self::initiate_sql::{
-    init_psql, POSTGRESQL_POOL_CONNECTION, THREADPOOL_SQL_QUERY, THREADPOOL_SQL_QUEUE,
+    get_psql_connection, init_psql, new_sql_queue, POSTGRESQL_POOL_CONNECTION,
+    THREADPOOL_SQL_QUERY, THREADPOOL_SQL_QUEUE,
 };
 pub use self::sql::*;
 pub use self::sql_api::*;
+pub use self::store::{
+    persistence_store, set_persistence_store, MemoryStore, MemoryTables, NetworkStore,
+    PersistenceStore, PostgresStore, StoredState,
+};
 pub use self::test_tx::{deserialize_tx_id, deserialize_tx_string, tx_id_string};
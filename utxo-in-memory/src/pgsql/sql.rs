@@ -1,9 +1,23 @@
 /*! Manage the Utxo ser Db insert and removal */
 use crate::{error::UtxosetError, ThreadPool};
+use crate::blockoperations::addresstx::{AddressTx, TxDirection, ADDRESS_TX_PAGE_SIZE};
+use crate::blockoperations::balanceindexer::{BalanceUpdate, IndexedBalance, IndexedUtxo};
+use crate::blockoperations::blockresult::BlockApplyResult;
+use crate::blockoperations::failedtx::FailedTx;
+use crate::blockoperations::fees::BlockFees;
+use crate::blockoperations::messages::BurnRecord;
+use crate::blockoperations::nullifiers::BlockNullifiers;
+use crate::blockoperations::pruning::{PruneAction, PruneLog};
+use crate::blockoperations::txroot::BlockTxRoot;
+use crate::blockoperations::txstatus::{TxStatus, TxStatusRecord};
+use crate::rpcaudit::{AuditOutcome, RpcAuditFilter, RpcAuditRecord, RPC_AUDIT_PAGE_SIZE};
+use crate::scriptregistry::ScriptContract;
+use crate::db::flatsnapshot::FlatSnapshotRecord;
 use crate::db::KeyId;
 use crate::pgsql::{POSTGRESQL_POOL_CONNECTION, THREADPOOL_SQL_QUEUE};
 use r2d2_postgres::postgres::types::ToSql;
 use serde::{Deserialize, Serialize};
+use zkvm::Output;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -206,7 +220,7 @@ pub fn insert_bulk_utxo_in_psql_memo_or_state(
     table_name: &str,
 ) -> Result<(), UtxosetError>{
     let mut bulk_query_insert: String = format!(
-        "INSERT INTO {}(utxo, output, owner_address, script_address, txid, vout, block_height) VALUES",
+        "INSERT INTO {}(utxo, output, owner_address, script_address, txid, vout, block_height, timebounds) VALUES",
         table_name
     );
     let mut index_count = 0;
@@ -216,8 +230,13 @@ pub fn insert_bulk_utxo_in_psql_memo_or_state(
         if index_count != 0 {
             bulk_query_insert = format!("{},", bulk_query_insert);
         }
+        // timebounds are stored in their own column for filtering
+        let timebounds = bincode::deserialize::<Output>(&raw_utxo.data)
+            .ok()
+            .and_then(|output| output.output.get_timebounds().copied())
+            .unwrap_or(0);
         bulk_query_insert = format!(
-            "{} (${}, ${}, ${},'{}', '{}', {}, {})",
+            "{} (${}, ${}, ${},'{}', '{}', {}, {}, {})",
             bulk_query_insert,
             index_count + 1,
             index_count + 2,
@@ -225,7 +244,8 @@ pub fn insert_bulk_utxo_in_psql_memo_or_state(
             raw_utxo.script_address,
             tx_id.clone(),
             raw_utxo.vout,
-            block_height
+            block_height,
+            timebounds
         );
 
         index_count += 3;
@@ -245,6 +265,694 @@ pub fn remove_bulk_utxo_in_psql(remove_utxo: Vec<KeyId>, table_name: &str)->Resu
     Ok(())
 }
 
+pub fn insert_block_tx_root_in_psql(block: &BlockTxRoot) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.block_tx_roots(block_height, root, txids) VALUES ($1, $2, $3) \
+        ON CONFLICT (block_height) DO UPDATE SET root = EXCLUDED.root, txids = EXCLUDED.txids;";
+    let height = block.block_height as i64;
+    let root = block.root.0.to_vec();
+    let txids = bincode::serialize(&block.txids)?;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&height, &root, &txids])?;
+    Ok(())
+}
+
+// loads the txid roots of the latest `limit` blocks
+pub fn load_block_tx_roots_from_psql(limit: i64) -> Result<Vec<BlockTxRoot>, UtxosetError> {
+    let query = format!(
+        "SELECT block_height, txids FROM public.block_tx_roots order by block_height desc limit {};",
+        limit
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<BlockTxRoot> = Vec::new();
+    for row in client.query(&query, &[])? {
+        let height: i64 = row.get("block_height");
+        let txids: Vec<u8> = row.get("txids");
+        result.push(BlockTxRoot::new(
+            height as u64,
+            bincode::deserialize(&txids)?,
+        ));
+    }
+    result.reverse();
+    Ok(result)
+}
+
+pub fn insert_block_fees_in_psql(block_fees: &BlockFees) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.block_fees(block_height, total_fees, tx_count) VALUES ($1, $2, $3) \
+        ON CONFLICT (block_height) DO UPDATE SET total_fees = EXCLUDED.total_fees, tx_count = EXCLUDED.tx_count;";
+    let height = block_fees.block_height as i64;
+    let total_fees = block_fees.total_fees as i64;
+    let tx_count = block_fees.tx_count as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&height, &total_fees, &tx_count])?;
+    Ok(())
+}
+
+// loads the fee totals of the latest `limit` blocks
+pub fn load_block_fees_from_psql(limit: i64) -> Result<Vec<BlockFees>, UtxosetError> {
+    let query = format!(
+        "SELECT block_height, total_fees, tx_count FROM public.block_fees order by block_height desc limit {};",
+        limit
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<BlockFees> = Vec::new();
+    for row in client.query(&query, &[])? {
+        let height: i64 = row.get("block_height");
+        let total_fees: i64 = row.get("total_fees");
+        let tx_count: i64 = row.get("tx_count");
+        result.push(BlockFees {
+            block_height: height as u64,
+            total_fees: total_fees as u64,
+            tx_count: tx_count as u64,
+        });
+    }
+    result.reverse();
+    Ok(result)
+}
+
+pub fn insert_block_result_in_psql(result: &BlockApplyResult) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.block_results(block_height, result) VALUES ($1, $2) \
+        ON CONFLICT (block_height) DO UPDATE SET result = EXCLUDED.result;";
+    let height = result.height as i64;
+    let bytes = result.to_bytes();
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&height, &bytes])?;
+    Ok(())
+}
+
+pub fn get_latest_block_height_from_psql() -> Result<Option<u64>, UtxosetError> {
+    let query = "SELECT max(block_height) AS block_height FROM public.block_results;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let row = client.query_one(query, &[])?;
+    let height: Option<i64> = row.get("block_height");
+    Ok(height.map(|height| height as u64))
+}
+
+pub fn insert_flat_snapshot_in_psql(record: &FlatSnapshotRecord) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.flat_snapshots(block_height, checksum, entries) VALUES ($1, $2, $3) \
+        ON CONFLICT (block_height) DO UPDATE SET checksum = EXCLUDED.checksum, entries = EXCLUDED.entries;";
+    let height = record.block_height as i64;
+    let entries = record.entries as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&height, &record.checksum, &entries])?;
+    Ok(())
+}
+
+pub fn get_latest_flat_snapshot_from_psql() -> Result<Option<FlatSnapshotRecord>, UtxosetError> {
+    let query = "SELECT block_height, checksum, entries FROM public.flat_snapshots \
+        order by block_height desc limit 1;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    match client.query_opt(query, &[])? {
+        Some(row) => {
+            let height: i64 = row.get("block_height");
+            let entries: i64 = row.get("entries");
+            Ok(Some(FlatSnapshotRecord {
+                block_height: height as u64,
+                checksum: row.get("checksum"),
+                entries: entries as u64,
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+fn decode_block_result(bytes: &[u8]) -> Result<BlockApplyResult, UtxosetError> {
+    BlockApplyResult::from_bytes(bytes)
+        .map_err(|e| UtxosetError::BlockResultDecodeError(e.to_string()))
+}
+
+pub fn get_block_result_from_psql(height: u64) -> Result<Option<BlockApplyResult>, UtxosetError> {
+    let query = "SELECT result FROM public.block_results WHERE block_height = $1;";
+    let height = height as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    match client.query_opt(query, &[&height])? {
+        Some(row) => {
+            let bytes: Vec<u8> = row.get("result");
+            Ok(Some(decode_block_result(&bytes)?))
+        }
+        None => Ok(None),
+    }
+}
+
+// loads the results of the latest `limit` blocks
+pub fn load_block_results_from_psql(limit: i64) -> Result<Vec<BlockApplyResult>, UtxosetError> {
+    let query = format!(
+        "SELECT result FROM public.block_results order by block_height desc limit {};",
+        limit
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<BlockApplyResult> = Vec::new();
+    for row in client.query(&query, &[])? {
+        let bytes: Vec<u8> = row.get("result");
+        result.push(decode_block_result(&bytes)?);
+    }
+    result.reverse();
+    Ok(result)
+}
+
+pub fn insert_script_contract_in_psql(contract: &ScriptContract) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.script_contracts(root, name, programs, metadata, mainnet_address, testnet_address) \
+        VALUES ($1, $2, $3, $4, $5, $6) \
+        ON CONFLICT (root) DO UPDATE SET name = EXCLUDED.name, metadata = EXCLUDED.metadata;";
+    let programs = serde_json::to_value(&contract.programs)?;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(
+        query,
+        &[
+            &contract.root,
+            &contract.name,
+            &programs,
+            &contract.metadata,
+            &contract.mainnet_address,
+            &contract.testnet_address,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn load_script_contracts_from_psql() -> Result<Vec<ScriptContract>, UtxosetError> {
+    let query = "SELECT root, name, programs, metadata, mainnet_address, testnet_address FROM public.script_contracts;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<ScriptContract> = Vec::new();
+    for row in client.query(query, &[])? {
+        let programs: serde_json::Value = row.get("programs");
+        result.push(ScriptContract {
+            name: row.get("name"),
+            programs: serde_json::from_value(programs)?,
+            root: row.get("root"),
+            mainnet_address: row.get("mainnet_address"),
+            testnet_address: row.get("testnet_address"),
+            metadata: row.get("metadata"),
+        });
+    }
+    Ok(result)
+}
+
+pub fn insert_burn_in_psql(burn: &BurnRecord) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.burns(txid, address, amount, block_height) VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (txid) DO NOTHING;";
+    let amount = burn.amount as i64;
+    let height = burn.block_height as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&burn.txid, &burn.address, &amount, &height])?;
+    Ok(())
+}
+
+// loads every recorded burn, oldest first
+pub fn load_burns_from_psql() -> Result<Vec<BurnRecord>, UtxosetError> {
+    let query =
+        "SELECT txid, address, amount, block_height FROM public.burns order by block_height asc;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<BurnRecord> = Vec::new();
+    for row in client.query(query, &[])? {
+        let amount: i64 = row.get("amount");
+        let height: i64 = row.get("block_height");
+        result.push(BurnRecord {
+            txid: row.get("txid"),
+            address: row.get("address"),
+            amount: amount as u64,
+            block_height: height as u64,
+        });
+    }
+    Ok(result)
+}
+
+// inserts a failed tx or updates the outcome of its last attempt
+pub fn insert_failed_tx_in_psql(failed_tx: &FailedTx) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.failed_txs(txid, block_height, tx, error, timestamp, resolved_at) \
+        VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (txid) DO UPDATE \
+        SET error = EXCLUDED.error, timestamp = EXCLUDED.timestamp, resolved_at = EXCLUDED.resolved_at;";
+    let height = failed_tx.block_height as i64;
+    let timestamp = failed_tx.timestamp as i64;
+    let resolved_at = failed_tx.resolved_at.map(|time| time as i64);
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(
+        query,
+        &[
+            &failed_tx.txid,
+            &height,
+            &failed_tx.tx_byte_code,
+            &failed_tx.error,
+            &timestamp,
+            &resolved_at,
+        ],
+    )?;
+    Ok(())
+}
+
+// loads every recorded failed tx
+pub fn load_failed_txs_from_psql() -> Result<Vec<FailedTx>, UtxosetError> {
+    let query = "SELECT txid, block_height, tx, error, timestamp, resolved_at FROM public.failed_txs \
+        order by block_height asc;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<FailedTx> = Vec::new();
+    for row in client.query(query, &[])? {
+        let height: i64 = row.get("block_height");
+        let timestamp: i64 = row.get("timestamp");
+        let resolved_at: Option<i64> = row.get("resolved_at");
+        result.push(FailedTx {
+            txid: row.get("txid"),
+            block_height: height as u64,
+            tx_byte_code: row.get("tx"),
+            error: row.get("error"),
+            timestamp: timestamp as u64,
+            resolved_at: resolved_at.map(|time| time as u64),
+        });
+    }
+    Ok(result)
+}
+
+// indexes the addresses of a tx, entries already indexed are kept
+pub fn insert_address_txs_in_psql(entries: &[AddressTx]) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.address_transactions(address, txid, block_height, direction) \
+        VALUES ($1, $2, $3, $4) ON CONFLICT (address, txid) DO NOTHING;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    for entry in entries {
+        let height = entry.block_height as i64;
+        client.execute(
+            query,
+            &[&entry.address, &entry.txid, &height, &entry.direction.as_str()],
+        )?;
+    }
+    Ok(())
+}
+
+fn row_to_address_tx(row: &r2d2_postgres::postgres::Row) -> AddressTx {
+    let height: i64 = row.get("block_height");
+    let direction: String = row.get("direction");
+    AddressTx {
+        address: row.get("address"),
+        txid: row.get("txid"),
+        block_height: height as u64,
+        direction: TxDirection::from_str(direction.trim()).unwrap_or(TxDirection::Both),
+    }
+}
+
+// loads the latest `limit` index entries, oldest first
+pub fn load_address_txs_from_psql(limit: i64) -> Result<Vec<AddressTx>, UtxosetError> {
+    let query = format!(
+        "SELECT address, txid, block_height, direction FROM public.address_transactions \
+        order by block_height desc, id desc limit {};",
+        limit
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<AddressTx> = Vec::new();
+    for row in client.query(&query, &[])? {
+        result.push(row_to_address_tx(&row));
+    }
+    result.reverse();
+    Ok(result)
+}
+
+// page of the txs involving `address`, newest first
+pub fn get_address_txs_from_psql(address: &str, page: usize) -> Result<Vec<AddressTx>, UtxosetError> {
+    let query = "SELECT address, txid, block_height, direction FROM public.address_transactions \
+        WHERE address = $1 order by block_height desc, id desc limit $2 offset $3;";
+    let limit = ADDRESS_TX_PAGE_SIZE as i64;
+    let offset = (page * ADDRESS_TX_PAGE_SIZE) as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<AddressTx> = Vec::new();
+    for row in client.query(query, &[&address, &limit, &offset])? {
+        result.push(row_to_address_tx(&row));
+    }
+    Ok(result)
+}
+
+pub fn insert_rpc_audit_in_psql(record: &RpcAuditRecord) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.rpc_audit(timestamp, method, client_ip, api_key_id, target, owner, outcome, latency_ms) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8);";
+    let timestamp = record.timestamp as i64;
+    let latency = record.latency_ms as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(
+        query,
+        &[
+            &timestamp,
+            &record.method,
+            &record.client_ip,
+            &record.api_key_id,
+            &record.target,
+            &record.owner,
+            &record.outcome.as_str(),
+            &latency,
+        ],
+    )?;
+    Ok(())
+}
+
+// the records of `filter` on page `page`, the newest first
+pub fn get_rpc_audit_from_psql(
+    filter: &RpcAuditFilter,
+    page: usize,
+) -> Result<Vec<RpcAuditRecord>, UtxosetError> {
+    let query =
+        "SELECT timestamp, method, client_ip, api_key_id, target, owner, outcome, latency_ms \
+        FROM public.rpc_audit \
+        WHERE ($1::VARCHAR IS NULL OR method = $1) AND ($2::VARCHAR IS NULL OR target = $2) \
+        AND ($3::VARCHAR IS NULL OR outcome = $3) AND ($4::BIGINT IS NULL OR timestamp >= $4) \
+        order by timestamp desc, id desc limit $5 offset $6;";
+    let outcome = filter.outcome.map(|outcome| outcome.as_str());
+    let since = filter.since.map(|since| since as i64);
+    let limit = RPC_AUDIT_PAGE_SIZE as i64;
+    let offset = (page * RPC_AUDIT_PAGE_SIZE) as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let rows = client.query(
+        query,
+        &[
+            &filter.method,
+            &filter.target,
+            &outcome,
+            &since,
+            &limit,
+            &offset,
+        ],
+    )?;
+    let mut result: Vec<RpcAuditRecord> = Vec::new();
+    for row in rows {
+        let timestamp: i64 = row.get("timestamp");
+        let outcome: String = row.get("outcome");
+        let latency: i64 = row.get("latency_ms");
+        result.push(RpcAuditRecord {
+            timestamp: timestamp as u64,
+            method: row.get("method"),
+            client_ip: row.get("client_ip"),
+            api_key_id: row.get("api_key_id"),
+            target: row.get("target"),
+            owner: row.get("owner"),
+            outcome: outcome.parse().unwrap_or(AuditOutcome::Error),
+            latency_ms: latency as u64,
+        });
+    }
+    Ok(result)
+}
+
+// removes the records older than `before`, returns their number
+pub fn prune_rpc_audit_in_psql(before: u64) -> Result<u64, UtxosetError> {
+    let query = "DELETE FROM public.rpc_audit WHERE timestamp < $1;";
+    let before = before as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    Ok(client.execute(query, &[&before])?)
+}
+
+// a status transition of a tx, the status as its tagged json
+pub fn insert_tx_status_in_psql(record: &TxStatusRecord) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.tx_status(txid, status, timestamp) VALUES ($1, $2, $3);";
+    let status = serde_json::to_value(&record.status)?;
+    let timestamp = record.timestamp as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&record.txid, &status, &timestamp])?;
+    Ok(())
+}
+
+// the status transitions of `txid`, oldest first
+pub fn get_tx_status_history_from_psql(txid: &str) -> Result<Vec<TxStatusRecord>, UtxosetError> {
+    let query = "SELECT txid, status, timestamp FROM public.tx_status \
+        WHERE txid = $1 order by timestamp asc, id asc;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let rows = client.query(query, &[&txid])?;
+    let mut result: Vec<TxStatusRecord> = Vec::new();
+    for row in rows {
+        let status: serde_json::Value = row.get("status");
+        let timestamp: i64 = row.get("timestamp");
+        result.push(TxStatusRecord {
+            txid: row.get("txid"),
+            status: serde_json::from_value(status).unwrap_or(TxStatus::Unknown),
+            timestamp: timestamp as u64,
+        });
+    }
+    Ok(result)
+}
+
+// writes the balances and coin values changed by an indexed or rolled back block
+// the nullifiers spent by a block, the entries as json
+pub fn insert_block_nullifiers_in_psql(block: &BlockNullifiers) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.block_nullifiers(block_height, entries) VALUES ($1, $2) \
+        ON CONFLICT (block_height) DO UPDATE SET entries = EXCLUDED.entries;";
+    let height = block.height as i64;
+    let entries = serde_json::to_value(&block.entries)?;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    client.execute(query, &[&height, &entries])?;
+    Ok(())
+}
+
+// drops the nullifiers of the blocks above `height`, on a reorg
+pub fn delete_block_nullifiers_above_in_psql(height: u64) -> Result<u64, UtxosetError> {
+    let query = "DELETE FROM public.block_nullifiers WHERE block_height > $1;";
+    let height = height as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    Ok(client.execute(query, &[&height])?)
+}
+
+// loads the nullifiers of the latest `limit` blocks, oldest first
+pub fn load_block_nullifiers_from_psql(limit: i64) -> Result<Vec<BlockNullifiers>, UtxosetError> {
+    let query = format!(
+        "SELECT block_height, entries FROM public.block_nullifiers order by block_height desc limit {};",
+        limit
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<BlockNullifiers> = Vec::new();
+    for row in client.query(&query, &[])? {
+        let height: i64 = row.get("block_height");
+        let entries: serde_json::Value = row.get("entries");
+        result.push(BlockNullifiers {
+            height: height as u64,
+            entries: serde_json::from_value(entries)?,
+        });
+    }
+    result.reverse();
+    Ok(result)
+}
+
+pub fn update_indexed_balances_in_psql(update: &BalanceUpdate) -> Result<(), UtxosetError> {
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut transaction = client.transaction()?;
+    for utxo in update.removed.iter() {
+        transaction.execute("DELETE FROM public.indexed_utxos WHERE utxo = $1;", &[utxo])?;
+    }
+    for utxo in update.added.iter() {
+        let value = utxo.value as i64;
+        transaction.execute(
+            "INSERT INTO public.indexed_utxos(utxo, address, value) VALUES ($1, $2, $3) \
+            ON CONFLICT (utxo) DO UPDATE SET address = EXCLUDED.address, value = EXCLUDED.value;",
+            &[&utxo.utxo, &utxo.address, &value],
+        )?;
+    }
+    for balance in update.balances.iter() {
+        let amount = balance.balance as i64;
+        let height = balance.block_height as i64;
+        transaction.execute(
+            "INSERT INTO public.indexed_balances(address, balance, block_height) VALUES ($1, $2, $3) \
+            ON CONFLICT (address) DO UPDATE SET balance = EXCLUDED.balance, block_height = EXCLUDED.block_height;",
+            &[&balance.address, &amount, &height],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+// loads the balances of every indexed address
+pub fn load_indexed_balances_from_psql() -> Result<Vec<IndexedBalance>, UtxosetError> {
+    let query = "SELECT address, balance, block_height FROM public.indexed_balances;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<IndexedBalance> = Vec::new();
+    for row in client.query(query, &[])? {
+        let balance: i64 = row.get("balance");
+        let height: i64 = row.get("block_height");
+        result.push(IndexedBalance {
+            address: row.get("address"),
+            balance: balance as u64,
+            block_height: height as u64,
+        });
+    }
+    Ok(result)
+}
+
+// loads the decrypted coin values of every indexed address
+pub fn load_indexed_utxos_from_psql() -> Result<Vec<IndexedUtxo>, UtxosetError> {
+    let query = "SELECT utxo, address, value FROM public.indexed_utxos;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<IndexedUtxo> = Vec::new();
+    for row in client.query(query, &[])? {
+        let value: i64 = row.get("value");
+        result.push(IndexedUtxo {
+            utxo: row.get("utxo"),
+            address: row.get("address"),
+            value: value as u64,
+        });
+    }
+    Ok(result)
+}
+
+// moves the rows of pruned memo outputs from the memo logs to the archive
+pub fn archive_pruned_outputs_in_psql(utxos: Vec<KeyId>, pruned_height: u64) -> Result<(), UtxosetError> {
+    let height = pruned_height as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut transaction = client.transaction()?;
+    transaction.execute(
+        "INSERT INTO public.pruned_outputs(utxo, output, owner_address, script_address, txid, vout, block_height, timebounds, pruned_height) \
+        SELECT utxo, output, owner_address, script_address, txid, vout, block_height, timebounds, $2 \
+        FROM public.utxo_memo_logs WHERE utxo = any($1) ON CONFLICT (utxo) DO NOTHING;",
+        &[&utxos, &height],
+    )?;
+    transaction.execute("DELETE FROM public.utxo_memo_logs WHERE utxo = any($1);", &[&utxos])?;
+    transaction.commit()?;
+    Ok(())
+}
+
+// moves the row of a pruned memo output from the archive back to the memo logs
+pub fn restore_pruned_output_in_psql(utxo: &KeyId) -> Result<(), UtxosetError> {
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut transaction = client.transaction()?;
+    let restored = transaction.execute(
+        "INSERT INTO public.utxo_memo_logs(utxo, output, owner_address, script_address, txid, vout, block_height, timebounds) \
+        SELECT utxo, output, owner_address, script_address, txid, vout, block_height, timebounds \
+        FROM public.pruned_outputs WHERE utxo = $1;",
+        &[utxo],
+    )?;
+    if restored == 0 {
+        return Err(UtxosetError::UtxoNotFound);
+    }
+    transaction.execute("DELETE FROM public.pruned_outputs WHERE utxo = $1;", &[utxo])?;
+    transaction.commit()?;
+    Ok(())
+}
+
+pub fn get_pruned_output_from_psql(utxo: &KeyId) -> Result<Option<Output>, UtxosetError> {
+    let query = "SELECT output FROM public.pruned_outputs WHERE utxo = $1;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    match client.query_opt(query, &[utxo])? {
+        Some(row) => {
+            let bytes: Vec<u8> = row.get("output");
+            Ok(Some(bincode::deserialize(&bytes)?))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn insert_prune_logs_in_psql(logs: &[PruneLog]) -> Result<(), UtxosetError> {
+    let query = "INSERT INTO public.prune_logs(block_height, action, utxo, script_address, timebounds) \
+        VALUES ($1, $2, $3, $4, $5);";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    for log in logs {
+        let height = log.block_height as i64;
+        let timebounds = log.timebounds as i64;
+        client.execute(
+            query,
+            &[&height, &log.action.as_str(), &log.utxo, &log.script_address, &timebounds],
+        )?;
+    }
+    Ok(())
+}
+
+// loads the latest `limit` prune logs, oldest first
+pub fn load_prune_logs_from_psql(limit: i64) -> Result<Vec<PruneLog>, UtxosetError> {
+    let query = format!(
+        "SELECT block_height, action, utxo, script_address, timebounds FROM public.prune_logs \
+        order by id desc limit {};",
+        limit
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<PruneLog> = Vec::new();
+    for row in client.query(&query, &[])? {
+        let height: i64 = row.get("block_height");
+        let action: String = row.get("action");
+        let timebounds: i64 = row.get("timebounds");
+        result.push(PruneLog {
+            block_height: height as u64,
+            action: PruneAction::from_str(action.trim()).unwrap_or(PruneAction::Prune),
+            utxo: row.get("utxo"),
+            script_address: row.get("script_address"),
+            timebounds: timebounds as u32,
+        });
+    }
+    result.reverse();
+    Ok(result)
+}
+
+/// A row of a secondary network in `public.network_records`, see `NetworkStore`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkRecord {
+    pub kind: &'static str,
+    pub key: String,
+    pub block_height: u64,
+    pub record: serde_json::Value,
+}
+
+// removes the rows of `removed` by kind and key and writes `records`, in one transaction
+pub fn write_network_records_in_psql(
+    network: &str,
+    removed: &[(&str, String)],
+    records: &[NetworkRecord],
+) -> Result<(), UtxosetError> {
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut transaction = client.transaction()?;
+    for (kind, key) in removed.iter() {
+        transaction.execute(
+            "DELETE FROM public.network_records WHERE network = $1 AND kind = $2 AND key = $3;",
+            &[&network, kind, key],
+        )?;
+    }
+    for record in records.iter() {
+        let height = record.block_height as i64;
+        transaction.execute(
+            "INSERT INTO public.network_records(network, kind, key, block_height, record) \
+            VALUES ($1, $2, $3, $4, $5) ON CONFLICT (network, kind, key) \
+            DO UPDATE SET block_height = EXCLUDED.block_height, record = EXCLUDED.record;",
+            &[&network, &record.kind, &record.key, &height, &record.record],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+// loads the rows of `kind` whose key starts with `prefix`, newest first, all of them
+// without a `limit`
+pub fn load_network_records_from_psql(
+    network: &str,
+    kind: &str,
+    prefix: &str,
+    offset: i64,
+    limit: Option<i64>,
+) -> Result<Vec<serde_json::Value>, UtxosetError> {
+    let query = format!(
+        "SELECT record FROM public.network_records \
+        WHERE network = $1 AND kind = $2 AND left(key, length($3)) = $3 \
+        order by block_height desc, key desc limit {} offset {};",
+        limit.map_or("ALL".to_string(), |limit| limit.to_string()),
+        offset
+    );
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let mut result: Vec<serde_json::Value> = Vec::new();
+    for row in client.query(&query, &[&network, &kind, &prefix])? {
+        result.push(row.get("record"));
+    }
+    Ok(result)
+}
+
+pub fn get_latest_network_height_from_psql(
+    network: &str,
+    kind: &str,
+) -> Result<Option<u64>, UtxosetError> {
+    let query = "SELECT max(block_height) AS block_height FROM public.network_records \
+        WHERE network = $1 AND kind = $2;";
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    let row = client.query_one(query, &[&network, &kind])?;
+    let height: Option<i64> = row.get("block_height");
+    Ok(height.map(|height| height as u64))
+}
+
+// drops the rows of `kind` of the blocks above `height`, on a reorg
+pub fn delete_network_records_above_in_psql(
+    network: &str,
+    kind: &str,
+    height: u64,
+) -> Result<u64, UtxosetError> {
+    let query = "DELETE FROM public.network_records \
+        WHERE network = $1 AND kind = $2 AND block_height > $3;";
+    let height = height as i64;
+    let mut client = POSTGRESQL_POOL_CONNECTION.get()?;
+    Ok(client.execute(query, &[&network, &kind, &height])?)
+}
+
 // ------------------------------------------------------------------------
 // Tests
 // ------------------------------------------------------------------------
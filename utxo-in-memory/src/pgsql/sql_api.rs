@@ -1,12 +1,13 @@
 use crate::db::KeyId;
 use crate::db::UtxokeyidOutput;
-use crate::pgsql::{POSTGRESQL_POOL_CONNECTION, THREADPOOL_SQL_QUERY};
+use crate::error::UtxosetError;
+use crate::pgsql::{get_psql_connection, POSTGRESQL_POOL_CONNECTION, THREADPOOL_SQL_QUERY};
 use crate::ThreadPool;
 use r2d2_postgres::postgres::types::ToSql;
 use serde::{Deserialize, Serialize};
 use zkvm::Output;
 use std::sync::mpsc;
-use zkvm::zkos_types::IOType;
+use zkvm::zkos_types::{IOType, Utxo};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UtxoOutputRaw {
     pub utxo_key:Vec<u8>,
@@ -78,7 +79,7 @@ pub fn get_utxo_from_db_by_block_height_range(
 ) -> Result<UtxoHexDecodeResult, std::io::Error> {
     let public_threadpool = THREADPOOL_SQL_QUERY.lock().unwrap();
     let (sender, receiver) = mpsc::channel();
-    public_threadpool.execute(move || {
+    let queued = public_threadpool.try_execute(move || {
         let mut query:String="".to_string();
 
         match io_type{
@@ -139,6 +140,11 @@ pub fn get_utxo_from_db_by_block_height_range(
 
     drop(public_threadpool);
 
+    // the query queue is full, let the caller retry instead of piling up jobs
+    if let Err(err) = queued {
+        return Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, err));
+    }
+
     match receiver.recv().unwrap() {
         Ok(value) => {
             return Ok(UtxoHexDecodeResult { result: value });
@@ -150,6 +156,156 @@ pub fn get_utxo_from_db_by_block_height_range(
 }
 
 
+/// Maximum number of utxos returned by one `get_utxos_detailed` call.
+pub const UTXO_DETAILED_MAX_LIMIT: i64 = 1000;
+
+/// Filters of `get_utxos_detailed`. Filters left as `None` match every utxo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UtxoDetailedQuery {
+    pub owner_address: Option<String>,
+    pub io_type: Option<IOType>,
+    /// First block height, inclusive.
+    pub start_height: Option<i64>,
+    /// Last block height, inclusive.
+    pub end_height: Option<i64>,
+    pub script_address: Option<String>,
+    /// Coins carry no timebounds and only match a minimum of zero.
+    pub min_timebound: Option<u32>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Default for UtxoDetailedQuery {
+    fn default() -> Self {
+        UtxoDetailedQuery {
+            owner_address: None,
+            io_type: None,
+            start_height: None,
+            end_height: None,
+            script_address: None,
+            min_timebound: None,
+            limit: 100,
+            offset: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoDetailed {
+    pub utxo: Utxo,
+    pub output: Output,
+    pub created_at_height: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoDetailedResult {
+    pub result: Vec<UtxoDetailed>,
+    /// Number of utxos matching the filters, ignoring limit and offset.
+    pub total_count: i64,
+}
+
+// shared by the page and the count query, all filters are bound parameters
+macro_rules! utxo_detailed_filter {
+    () => {
+        " FROM (
+            SELECT utxo, output, owner_address, NULL::VARCHAR AS script_address,
+                0::BIGINT AS timebounds, block_height, 0 AS io_type FROM public.utxo_coin_logs
+            UNION ALL
+            SELECT utxo, output, owner_address, script_address,
+                timebounds, block_height, 1 AS io_type FROM public.utxo_memo_logs
+            UNION ALL
+            SELECT utxo, output, owner_address, script_address,
+                timebounds, block_height, 2 AS io_type FROM public.utxo_state_logs
+        ) AS utxos
+        WHERE ($1::BYTEA IS NULL OR owner_address = $1)
+            AND ($2::INT IS NULL OR io_type = $2)
+            AND ($3::BIGINT IS NULL OR block_height >= $3)
+            AND ($4::BIGINT IS NULL OR block_height <= $4)
+            AND ($5::VARCHAR IS NULL OR script_address = $5)
+            AND ($6::BIGINT IS NULL OR timebounds >= $6)"
+    };
+}
+
+const UTXO_DETAILED_QUERY: &str = concat!(
+    "SELECT utxo, output, block_height",
+    utxo_detailed_filter!(),
+    " ORDER BY block_height ASC, utxo ASC LIMIT $7 OFFSET $8;"
+);
+
+const UTXO_DETAILED_COUNT_QUERY: &str =
+    concat!("SELECT COUNT(*) AS total_count", utxo_detailed_filter!(), ";");
+
+fn query_utxos_detailed(query: &UtxoDetailedQuery) -> Result<UtxoDetailedResult, UtxosetError> {
+    // owner addresses are stored bincode encoded
+    let owner_address: Option<Vec<u8>> = match query.owner_address {
+        Some(ref owner) => Some(bincode::serialize(owner)?),
+        None => None,
+    };
+    let io_type: Option<i32> = query.io_type.map(|io_type| io_type.to_usize() as i32);
+    let min_timebound: Option<i64> = query.min_timebound.map(i64::from);
+    let limit = query.limit.max(1).min(UTXO_DETAILED_MAX_LIMIT);
+    let offset = query.offset.max(0);
+    let filters: [&(dyn ToSql + Sync); 6] = [
+        &owner_address,
+        &io_type,
+        &query.start_height,
+        &query.end_height,
+        &query.script_address,
+        &min_timebound,
+    ];
+
+    let mut client = get_psql_connection()?;
+    let total_count: i64 = client
+        .query_one(UTXO_DETAILED_COUNT_QUERY, &filters)?
+        .get("total_count");
+
+    let mut params: Vec<&(dyn ToSql + Sync)> = filters.to_vec();
+    params.push(&limit);
+    params.push(&offset);
+    let mut result: Vec<UtxoDetailed> = Vec::new();
+    for row in client.query(UTXO_DETAILED_QUERY, &params)? {
+        let utxo: Vec<u8> = row.get("utxo");
+        let output: Vec<u8> = row.get("output");
+        result.push(UtxoDetailed {
+            utxo: bincode::deserialize(&utxo)?,
+            output: bincode::deserialize(&output)?,
+            created_at_height: row.get("block_height"),
+        });
+    }
+    Ok(UtxoDetailedResult {
+        result,
+        total_count,
+    })
+}
+
+/// Queries the utxos stored in PostgreSQL, filtered and paginated.
+/// Fails with `DatabaseUnavailable` instead of panicking if PostgreSQL can not be reached,
+/// and with a `WouldBlock` IO error if the query queue is full.
+pub fn get_utxos_detailed(query: UtxoDetailedQuery) -> Result<UtxoDetailedResult, UtxosetError> {
+    let public_threadpool = THREADPOOL_SQL_QUERY.lock().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let queued = public_threadpool.try_execute(move || {
+        sender.send(query_utxos_detailed(&query)).unwrap_or(());
+    });
+    drop(public_threadpool);
+
+    if let Err(err) = queued {
+        return Err(UtxosetError::IOError(std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            err,
+        )));
+    }
+
+    match receiver.recv() {
+        Ok(result) => result,
+        Err(_) => Err(UtxosetError::DatabaseUnavailable(
+            "query worker stopped".to_string(),
+        )),
+    }
+}
+
+
 
 // ------------------------------------------------------------------------
 // Tests
@@ -173,4 +329,270 @@ mod test {
     fn create_set_genesis_sets_test() {
        crate::blockoperations::set_genesis_sets();
     }
+
+    // seeds a coin, a memo with timebounds 50 and a state with timebounds 100 at `height`
+    // returns the owner, the script address and the seeded utxo keys
+    fn seed_detailed_utxos(height: u64) -> (String, String, Vec<Vec<u8>>) {
+        seed_detailed_utxos_with(height, None)
+    }
+
+    // as seed_detailed_utxos, the state carrying `state_variables`
+    fn seed_detailed_utxos_with(
+        height: u64,
+        state_variables: Option<Vec<zkvm::String>>,
+    ) -> (String, String, Vec<Vec<u8>>) {
+        use crate::pgsql::{
+            insert_bulk_utxo_in_psql_coin, insert_bulk_utxo_in_psql_memo_or_state, PGSQLDataInsert,
+        };
+        use address::{Address, Network};
+        use curve25519_dalek::scalar::Scalar;
+        use quisquislib::accounts::Account;
+        use rand::Rng;
+        use zkvm::zkos_types::{OutputCoin, OutputData, OutputMemo, OutputState};
+        use zkvm::{Commitment, Hash, TxID};
+
+        let (pk, enc) = Account::generate_random_account_with_value(Scalar::from(20u64))
+            .0
+            .get_account();
+        let owner = Address::standard_address(Network::default(), pk).as_hex();
+        let script_address =
+            Address::script_address(Network::default(), rand::thread_rng().gen::<[u8; 32]>())
+                .as_hex();
+        let tx_id: [u8; 32] = rand::thread_rng().gen();
+        let outputs = vec![
+            Output::coin(OutputData::Coin(OutputCoin {
+                encrypt: enc,
+                owner: owner.clone(),
+            })),
+            Output::memo(OutputData::Memo(OutputMemo {
+                script_address: script_address.clone(),
+                owner: owner.clone(),
+                commitment: Commitment::blinded(10u64),
+                data: None,
+                timebounds: 50,
+            })),
+            Output::state(OutputData::State(OutputState {
+                nonce: 1,
+                script_address: script_address.clone(),
+                owner: owner.clone(),
+                commitment: Commitment::blinded(10u64),
+                state_variables,
+                timebounds: 100,
+            })),
+        ];
+        let mut keys = Vec::new();
+        for (i, output) in outputs.iter().enumerate() {
+            let key = bincode::serialize(&Utxo::new(TxID(Hash(tx_id)), i as u16)).unwrap();
+            keys.push(key.clone());
+            let data = PGSQLDataInsert::new(
+                key,
+                bincode::serialize(output).unwrap(),
+                bincode::serialize(&owner).unwrap(),
+                output.output.get_script_address().unwrap_or(&"".to_string()),
+                i,
+            );
+            match i {
+                0 => insert_bulk_utxo_in_psql_coin(
+                    vec![data],
+                    hex::encode(tx_id),
+                    height,
+                    "public.utxo_coin_logs",
+                ),
+                1 => insert_bulk_utxo_in_psql_memo_or_state(
+                    vec![data],
+                    hex::encode(tx_id),
+                    height,
+                    "public.utxo_memo_logs",
+                ),
+                _ => insert_bulk_utxo_in_psql_memo_or_state(
+                    vec![data],
+                    hex::encode(tx_id),
+                    height,
+                    "public.utxo_state_logs",
+                ),
+            }
+            .unwrap();
+        }
+        (owner, script_address, keys)
+    }
+
+    fn remove_seeded_utxos(keys: Vec<Vec<u8>>) {
+        for table in ["public.utxo_coin_logs", "public.utxo_memo_logs", "public.utxo_state_logs"] {
+            crate::pgsql::remove_bulk_utxo_in_psql(keys.clone(), table).unwrap();
+        }
+    }
+
+    fn count_detailed(query: UtxoDetailedQuery) -> (usize, i64) {
+        let result = get_utxos_detailed(query).unwrap();
+        (result.result.len(), result.total_count)
+    }
+
+    // cargo test -- --nocapture --test get_utxos_detailed_filters_test --test-threads 1
+    #[test]
+    fn get_utxos_detailed_filters_test() {
+        crate::pgsql::init_psql();
+        let height = 900_000_000u64;
+        let (owner, script_address, keys) = seed_detailed_utxos(height);
+        let by_owner = UtxoDetailedQuery {
+            owner_address: Some(owner.clone()),
+            ..Default::default()
+        };
+
+        let result = get_utxos_detailed(by_owner.clone()).unwrap();
+        assert_eq!(result.total_count, 3);
+        assert!(result
+            .result
+            .iter()
+            .all(|utxo| utxo.created_at_height == height as i64));
+
+        let types = [
+            (IOType::Coin, None),
+            (IOType::Memo, Some(&50u32)),
+            (IOType::State, Some(&100u32)),
+        ];
+        for (io_type, timebounds) in types {
+            let result = get_utxos_detailed(UtxoDetailedQuery {
+                io_type: Some(io_type),
+                ..by_owner.clone()
+            })
+            .unwrap();
+            assert_eq!(result.total_count, 1);
+            assert_eq!(result.result[0].output.out_type, io_type);
+            assert_eq!(result.result[0].output.output.get_timebounds(), timebounds);
+        }
+
+        // block height range
+        let in_range = UtxoDetailedQuery {
+            start_height: Some(height as i64),
+            end_height: Some(height as i64),
+            ..by_owner.clone()
+        };
+        assert_eq!(count_detailed(in_range.clone()), (3, 3));
+        let after = UtxoDetailedQuery {
+            start_height: Some(height as i64 + 1),
+            ..by_owner.clone()
+        };
+        assert_eq!(count_detailed(after), (0, 0));
+        let before = UtxoDetailedQuery {
+            end_height: Some(height as i64 - 1),
+            ..by_owner.clone()
+        };
+        assert_eq!(count_detailed(before), (0, 0));
+
+        // script address, coins have none
+        let by_script = UtxoDetailedQuery {
+            script_address: Some(script_address.clone()),
+            ..by_owner.clone()
+        };
+        assert_eq!(count_detailed(by_script.clone()), (2, 2));
+        let coin_by_script = UtxoDetailedQuery {
+            io_type: Some(IOType::Coin),
+            ..by_script.clone()
+        };
+        assert_eq!(count_detailed(coin_by_script), (0, 0));
+
+        // minimum timebound, coins only match zero
+        let timebound = |min: u32| UtxoDetailedQuery {
+            min_timebound: Some(min),
+            ..by_owner.clone()
+        };
+        assert_eq!(count_detailed(timebound(0)), (3, 3));
+        assert_eq!(count_detailed(timebound(50)), (2, 2));
+        assert_eq!(count_detailed(timebound(51)), (1, 1));
+        assert_eq!(count_detailed(timebound(101)), (0, 0));
+
+        // every filter at once
+        let all_filters = UtxoDetailedQuery {
+            io_type: Some(IOType::State),
+            script_address: Some(script_address.clone()),
+            min_timebound: Some(100),
+            ..in_range.clone()
+        };
+        assert_eq!(count_detailed(all_filters), (1, 1));
+
+        // pagination keeps the total count
+        let page = |limit: i64, offset: i64| UtxoDetailedQuery {
+            limit,
+            offset,
+            ..by_owner.clone()
+        };
+        assert_eq!(count_detailed(page(2, 0)), (2, 3));
+        assert_eq!(count_detailed(page(2, 2)), (1, 3));
+        assert_eq!(count_detailed(page(2, 5)), (0, 3));
+        let first = get_utxos_detailed(page(2, 0)).unwrap().result;
+        let second = get_utxos_detailed(page(2, 2)).unwrap().result;
+        assert!(first.iter().all(|utxo| utxo.utxo != second[0].utxo));
+
+        remove_seeded_utxos(keys);
+    }
+
+    // cargo test -- --nocapture --test get_utxos_detailed_injection_test --test-threads 1
+    #[test]
+    fn get_utxos_detailed_injection_test() {
+        crate::pgsql::init_psql();
+        let (owner, script_address, keys) = seed_detailed_utxos(900_000_001);
+        let injections = [
+            "' OR '1'='1",
+            "'; DROP TABLE public.utxo_coin_logs; --",
+            "x') OR 1=1 --",
+        ];
+        for injection in injections {
+            let by_owner = UtxoDetailedQuery {
+                owner_address: Some(injection.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(count_detailed(by_owner), (0, 0));
+            let by_script = UtxoDetailedQuery {
+                owner_address: Some(owner.clone()),
+                script_address: Some(format!("{}{}", script_address, injection)),
+                ..Default::default()
+            };
+            assert_eq!(count_detailed(by_script), (0, 0));
+        }
+        // the tables are untouched
+        let by_owner = UtxoDetailedQuery {
+            owner_address: Some(owner),
+            ..Default::default()
+        };
+        assert_eq!(count_detailed(by_owner), (3, 3));
+
+        remove_seeded_utxos(keys);
+    }
+
+    // cargo test -- --nocapture --test oversize_stored_output_load_test --test-threads 1
+    #[test]
+    fn oversize_stored_output_load_test() {
+        use zkvm::zkos_types::OutputDataLimits;
+        use zkvm::VMError;
+
+        // a state stored before the data limits were enforced
+        crate::pgsql::init_psql();
+        let limits = OutputDataLimits::default();
+        let state_variables = vec![zkvm::String::U64(0); limits.max_elements + 1];
+        let (owner, _, keys) = seed_detailed_utxos_with(900_000_002, Some(state_variables));
+
+        let result = get_utxos_detailed(UtxoDetailedQuery {
+            owner_address: Some(owner),
+            io_type: Some(IOType::State),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(result.total_count, 1);
+        let state = &result.result[0].output;
+        assert_eq!(
+            state.validate(),
+            Err(VMError::DataTooLarge {
+                which: "data_elements",
+                size: limits.max_elements + 1,
+                limit: limits.max_elements,
+            })
+        );
+        let loaded =
+            get_utxo_from_db_by_block_height_range(900_000_002, 900_000_002, 10, 0, IOType::State)
+                .unwrap();
+        let output: Output = bincode::deserialize(&loaded.result[0].output).unwrap();
+        assert_eq!(&output, state);
+
+        remove_seeded_utxos(keys);
+    }
 }
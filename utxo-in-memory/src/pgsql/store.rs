@@ -0,0 +1,792 @@
+/*! Persistence of the block processing results, behind a trait so that it can be swapped */
+use crate::blockoperations::addresstx::{AddressTx, ADDRESS_TX_PAGE_SIZE, ADDRESS_TX_RETENTION};
+use crate::blockoperations::balanceindexer::{BalanceUpdate, IndexedBalance, IndexedUtxo};
+use crate::blockoperations::blockresult::{BlockApplyResult, BLOCK_APPLY_RESULT_RETENTION};
+use crate::blockoperations::failedtx::FailedTx;
+use crate::blockoperations::fees::{BlockFees, BLOCK_FEES_RETENTION};
+use crate::blockoperations::messages::BurnRecord;
+use crate::blockoperations::nullifiers::{BlockNullifiers, NullifierIndex};
+use crate::blockoperations::txroot::{BlockTxRoot, BLOCK_TX_ROOT_RETENTION};
+use crate::blockoperations::txstatus::TxStatusRecord;
+use crate::db::flatsnapshot::FlatSnapshotRecord;
+use crate::db::{KeyId, LocalDBtrait, LocalStorage, UtxokeyidOutput};
+use crate::error::UtxosetError;
+use crate::pgsql::sql::*;
+use crate::rpcaudit::{RpcAuditFilter, RpcAuditRecord, RPC_AUDIT_PAGE_SIZE};
+use address::Network;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use zkvm::zkos_types::{Output, Utxo};
+
+lazy_static! {
+    static ref PERSISTENCE_STORE: RwLock<Arc<dyn PersistenceStore>> =
+        RwLock::new(Arc::new(PostgresStore));
+}
+
+/// Writes and reads of the block processing made against the persistence layer.
+pub trait PersistenceStore: Send + Sync {
+    fn update_utxo_log(&self, tx: &PGSQLTransaction) -> Result<(), UtxosetError>;
+    fn insert_address_txs(&self, entries: &[AddressTx]) -> Result<(), UtxosetError>;
+    fn get_address_txs(&self, address: &str, page: usize) -> Result<Vec<AddressTx>, UtxosetError>;
+    fn insert_failed_tx(&self, failed_tx: &FailedTx) -> Result<(), UtxosetError>;
+    fn insert_burn(&self, burn: &BurnRecord) -> Result<(), UtxosetError>;
+    fn insert_block_tx_root(&self, block: &BlockTxRoot) -> Result<(), UtxosetError>;
+    fn insert_block_fees(&self, block_fees: &BlockFees) -> Result<(), UtxosetError>;
+    fn insert_block_result(&self, result: &BlockApplyResult) -> Result<(), UtxosetError>;
+    fn get_block_result(&self, height: u64) -> Result<Option<BlockApplyResult>, UtxosetError>;
+    fn update_indexed_balances(&self, update: &BalanceUpdate) -> Result<(), UtxosetError>;
+    fn insert_flat_snapshot(&self, record: &FlatSnapshotRecord) -> Result<(), UtxosetError>;
+    fn get_latest_flat_snapshot(&self) -> Result<Option<FlatSnapshotRecord>, UtxosetError>;
+    /// Height of the latest block whose result was written.
+    fn get_latest_block_height(&self) -> Result<Option<u64>, UtxosetError>;
+    fn insert_rpc_audit(&self, record: &RpcAuditRecord) -> Result<(), UtxosetError>;
+    /// Audit records of `filter` on page `page`, the newest first.
+    fn get_rpc_audit(
+        &self,
+        filter: &RpcAuditFilter,
+        page: usize,
+    ) -> Result<Vec<RpcAuditRecord>, UtxosetError>;
+    /// Removes the audit records older than the unix time `before`, returns their number.
+    fn prune_rpc_audit(&self, before: u64) -> Result<u64, UtxosetError>;
+    fn insert_tx_status(&self, record: &TxStatusRecord) -> Result<(), UtxosetError>;
+    /// Status transitions of the tx `txid`, oldest first.
+    fn get_tx_status_history(&self, txid: &str) -> Result<Vec<TxStatusRecord>, UtxosetError>;
+    fn insert_block_nullifiers(&self, block: &BlockNullifiers) -> Result<(), UtxosetError>;
+    /// Drops the nullifiers of the blocks above `height`, returns the number of blocks.
+    fn delete_block_nullifiers_above(&self, height: u64) -> Result<u64, UtxosetError>;
+    /// Rows a state is bootstrapped from at startup, see [`crate::state::UtxoState::load_stored`].
+    fn load_state(&self) -> Result<StoredState, UtxosetError>;
+    /// Balances and coin values of the indexed addresses.
+    fn load_indexed_balances(
+        &self,
+    ) -> Result<(Vec<IndexedBalance>, Vec<IndexedUtxo>), UtxosetError>;
+}
+
+/// Utxo set and the rows of the latest blocks, as read back from a store.
+#[derive(Debug, Clone, Default)]
+pub struct StoredState {
+    pub utxos: Vec<UtxokeyidOutput<Output>>,
+    // height of the latest block result, None for an empty store
+    pub block_height: Option<u64>,
+    pub block_tx_roots: Vec<BlockTxRoot>,
+    pub block_fees: Vec<BlockFees>,
+    pub block_results: Vec<BlockApplyResult>,
+    pub block_nullifiers: Vec<BlockNullifiers>,
+    pub burns: Vec<BurnRecord>,
+    pub address_txs: Vec<AddressTx>,
+    pub failed_txs: Vec<FailedTx>,
+}
+
+// the utxo of a row of the utxo logs
+fn stored_utxo(insert: &PGSQLDataInsert) -> Result<UtxokeyidOutput<Output>, UtxosetError> {
+    Ok(UtxokeyidOutput {
+        keyid: insert.key.clone(),
+        output: bincode::deserialize(&insert.data)?,
+    })
+}
+
+// the latest `limit` of `rows`, oldest first
+fn latest<T: Clone>(rows: &[T], limit: usize) -> Vec<T> {
+    rows[rows.len().saturating_sub(limit)..].to_vec()
+}
+
+/// Store in use, PostgreSQL unless replaced with `set_persistence_store`.
+pub fn persistence_store() -> Arc<dyn PersistenceStore> {
+    PERSISTENCE_STORE.read().unwrap().clone()
+}
+
+/// Replaces the store, e.g. with a `MemoryStore` in tests.
+/// Jobs already queued keep writing to the previous store.
+pub fn set_persistence_store(store: Arc<dyn PersistenceStore>) {
+    *PERSISTENCE_STORE.write().unwrap() = store;
+}
+
+/// The PostgreSQL tables of `init_psql`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostgresStore;
+
+impl PersistenceStore for PostgresStore {
+    fn update_utxo_log(&self, tx: &PGSQLTransaction) -> Result<(), UtxosetError> {
+        // failures of the single tables are logged by update_utxo_log
+        tx.clone().update_utxo_log();
+        Ok(())
+    }
+    fn insert_address_txs(&self, entries: &[AddressTx]) -> Result<(), UtxosetError> {
+        insert_address_txs_in_psql(entries)
+    }
+    fn get_address_txs(&self, address: &str, page: usize) -> Result<Vec<AddressTx>, UtxosetError> {
+        get_address_txs_from_psql(address, page)
+    }
+    fn insert_failed_tx(&self, failed_tx: &FailedTx) -> Result<(), UtxosetError> {
+        insert_failed_tx_in_psql(failed_tx)
+    }
+    fn insert_burn(&self, burn: &BurnRecord) -> Result<(), UtxosetError> {
+        insert_burn_in_psql(burn)
+    }
+    fn insert_block_tx_root(&self, block: &BlockTxRoot) -> Result<(), UtxosetError> {
+        insert_block_tx_root_in_psql(block)
+    }
+    fn insert_block_fees(&self, block_fees: &BlockFees) -> Result<(), UtxosetError> {
+        insert_block_fees_in_psql(block_fees)
+    }
+    fn insert_block_result(&self, result: &BlockApplyResult) -> Result<(), UtxosetError> {
+        insert_block_result_in_psql(result)
+    }
+    fn get_block_result(&self, height: u64) -> Result<Option<BlockApplyResult>, UtxosetError> {
+        get_block_result_from_psql(height)
+    }
+    fn update_indexed_balances(&self, update: &BalanceUpdate) -> Result<(), UtxosetError> {
+        update_indexed_balances_in_psql(update)
+    }
+    fn insert_flat_snapshot(&self, record: &FlatSnapshotRecord) -> Result<(), UtxosetError> {
+        insert_flat_snapshot_in_psql(record)
+    }
+    fn get_latest_flat_snapshot(&self) -> Result<Option<FlatSnapshotRecord>, UtxosetError> {
+        get_latest_flat_snapshot_from_psql()
+    }
+    fn get_latest_block_height(&self) -> Result<Option<u64>, UtxosetError> {
+        get_latest_block_height_from_psql()
+    }
+    fn insert_rpc_audit(&self, record: &RpcAuditRecord) -> Result<(), UtxosetError> {
+        insert_rpc_audit_in_psql(record)
+    }
+    fn get_rpc_audit(
+        &self,
+        filter: &RpcAuditFilter,
+        page: usize,
+    ) -> Result<Vec<RpcAuditRecord>, UtxosetError> {
+        get_rpc_audit_from_psql(filter, page)
+    }
+    fn prune_rpc_audit(&self, before: u64) -> Result<u64, UtxosetError> {
+        prune_rpc_audit_in_psql(before)
+    }
+    fn insert_tx_status(&self, record: &TxStatusRecord) -> Result<(), UtxosetError> {
+        insert_tx_status_in_psql(record)
+    }
+    fn get_tx_status_history(&self, txid: &str) -> Result<Vec<TxStatusRecord>, UtxosetError> {
+        get_tx_status_history_from_psql(txid)
+    }
+    fn insert_block_nullifiers(&self, block: &BlockNullifiers) -> Result<(), UtxosetError> {
+        insert_block_nullifiers_in_psql(block)
+    }
+    fn delete_block_nullifiers_above(&self, height: u64) -> Result<u64, UtxosetError> {
+        delete_block_nullifiers_above_in_psql(height)
+    }
+    fn load_state(&self) -> Result<StoredState, UtxosetError> {
+        let mut utxos = Vec::new();
+        for io_type in 0..3 {
+            let mut page = 0;
+            loop {
+                let entries = LocalStorage::<Output>::get_utxo_from_db_by_block_height_range1(
+                    0, 50000, page, io_type,
+                )?;
+                if entries.is_empty() {
+                    break;
+                }
+                for entry in entries {
+                    utxos.push(UtxokeyidOutput {
+                        keyid: Utxo::upgrade_key(entry.keyid),
+                        output: entry.output,
+                    });
+                }
+                page += 1;
+            }
+        }
+        let nullifier_depth = NullifierIndex::from_config().depth;
+        Ok(StoredState {
+            utxos,
+            block_height: get_latest_block_height_from_psql()?,
+            block_tx_roots: load_block_tx_roots_from_psql(BLOCK_TX_ROOT_RETENTION as i64)?,
+            block_fees: load_block_fees_from_psql(BLOCK_FEES_RETENTION as i64)?,
+            block_results: load_block_results_from_psql(BLOCK_APPLY_RESULT_RETENTION as i64)?,
+            block_nullifiers: load_block_nullifiers_from_psql(nullifier_depth as i64)?,
+            burns: load_burns_from_psql()?,
+            address_txs: load_address_txs_from_psql(ADDRESS_TX_RETENTION as i64)?,
+            failed_txs: load_failed_txs_from_psql()?,
+        })
+    }
+    fn load_indexed_balances(
+        &self,
+    ) -> Result<(Vec<IndexedBalance>, Vec<IndexedUtxo>), UtxosetError> {
+        Ok((
+            load_indexed_balances_from_psql()?,
+            load_indexed_utxos_from_psql()?,
+        ))
+    }
+}
+
+// kinds of the rows of a `NetworkStore`
+const UTXO_RECORD: &str = "utxo";
+const ADDRESS_TX_RECORD: &str = "address_tx";
+const FAILED_TX_RECORD: &str = "failed_tx";
+const BURN_RECORD: &str = "burn";
+const BLOCK_TX_ROOT_RECORD: &str = "block_tx_root";
+const BLOCK_FEES_RECORD: &str = "block_fees";
+const BLOCK_RESULT_RECORD: &str = "block_result";
+const BLOCK_NULLIFIERS_RECORD: &str = "block_nullifiers";
+const INDEXED_BALANCE_RECORD: &str = "indexed_balance";
+const INDEXED_UTXO_RECORD: &str = "indexed_utxo";
+const FLAT_SNAPSHOT_RECORD: &str = "flat_snapshot";
+const TX_STATUS_RECORD: &str = "tx_status";
+
+// key of the row of a block, the keys are ordered as the heights
+fn height_key(height: u64) -> String {
+    format!("{:020}", height)
+}
+
+/// The rows of a secondary network, in the PostgreSQL table `network_records` keyed by
+/// the network, see [`crate::network`]. The rpc audit is process wide and written to
+/// its own table.
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkStore {
+    network: Network,
+}
+
+impl NetworkStore {
+    pub fn new(network: Network) -> Self {
+        NetworkStore { network }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    fn write(
+        &self,
+        removed: &[(&str, String)],
+        records: &[NetworkRecord],
+    ) -> Result<(), UtxosetError> {
+        write_network_records_in_psql(self.network.as_str(), removed, records)
+    }
+
+    fn record<T: Serialize>(
+        kind: &'static str,
+        key: String,
+        block_height: u64,
+        record: &T,
+    ) -> Result<NetworkRecord, UtxosetError> {
+        Ok(NetworkRecord {
+            kind,
+            key,
+            block_height,
+            record: serde_json::to_value(record)?,
+        })
+    }
+
+    // rows of `kind` whose key starts with `prefix`, newest first
+    fn load<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+        prefix: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<T>, UtxosetError> {
+        let records = load_network_records_from_psql(
+            self.network.as_str(),
+            kind,
+            prefix,
+            offset as i64,
+            limit.map(|limit| limit as i64),
+        )?;
+        records
+            .into_iter()
+            .map(|record| Ok(serde_json::from_value(record)?))
+            .collect()
+    }
+
+    // the latest `limit` rows of `kind`, oldest first
+    fn load_latest<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<T>, UtxosetError> {
+        let mut records = self.load(kind, "", 0, limit)?;
+        records.reverse();
+        Ok(records)
+    }
+}
+
+impl PersistenceStore for NetworkStore {
+    fn update_utxo_log(&self, tx: &PGSQLTransaction) -> Result<(), UtxosetError> {
+        let removed: Vec<(&str, String)> = tx
+            .remove_utxo
+            .iter()
+            .map(|key| (UTXO_RECORD, hex::encode(key)))
+            .collect();
+        let records = tx
+            .insert_coin_utxo
+            .iter()
+            .chain(tx.insert_memo_utxo.iter())
+            .chain(tx.insert_state_utxo.iter())
+            .map(|insert| {
+                let key = hex::encode(&insert.key);
+                NetworkStore::record(UTXO_RECORD, key, tx.block_height, insert)
+            })
+            .collect::<Result<Vec<NetworkRecord>, UtxosetError>>()?;
+        self.write(&removed, &records)
+    }
+    fn insert_address_txs(&self, entries: &[AddressTx]) -> Result<(), UtxosetError> {
+        let records = entries
+            .iter()
+            .map(|entry| {
+                let key = format!("{}/{}", entry.address, entry.txid);
+                NetworkStore::record(ADDRESS_TX_RECORD, key, entry.block_height, entry)
+            })
+            .collect::<Result<Vec<NetworkRecord>, UtxosetError>>()?;
+        self.write(&[], &records)
+    }
+    fn get_address_txs(&self, address: &str, page: usize) -> Result<Vec<AddressTx>, UtxosetError> {
+        self.load(
+            ADDRESS_TX_RECORD,
+            &format!("{}/", address),
+            page * ADDRESS_TX_PAGE_SIZE,
+            Some(ADDRESS_TX_PAGE_SIZE),
+        )
+    }
+    fn insert_failed_tx(&self, failed_tx: &FailedTx) -> Result<(), UtxosetError> {
+        let key = failed_tx.txid.clone();
+        let record =
+            NetworkStore::record(FAILED_TX_RECORD, key, failed_tx.block_height, failed_tx)?;
+        self.write(&[], &[record])
+    }
+    fn insert_burn(&self, burn: &BurnRecord) -> Result<(), UtxosetError> {
+        let record = NetworkStore::record(BURN_RECORD, burn.txid.clone(), burn.block_height, burn)?;
+        self.write(&[], &[record])
+    }
+    fn insert_block_tx_root(&self, block: &BlockTxRoot) -> Result<(), UtxosetError> {
+        let key = height_key(block.block_height);
+        let record = NetworkStore::record(BLOCK_TX_ROOT_RECORD, key, block.block_height, block)?;
+        self.write(&[], &[record])
+    }
+    fn insert_block_fees(&self, block_fees: &BlockFees) -> Result<(), UtxosetError> {
+        let height = block_fees.block_height;
+        let record =
+            NetworkStore::record(BLOCK_FEES_RECORD, height_key(height), height, block_fees)?;
+        self.write(&[], &[record])
+    }
+    fn insert_block_result(&self, result: &BlockApplyResult) -> Result<(), UtxosetError> {
+        let height = result.height;
+        let record = NetworkStore::record(BLOCK_RESULT_RECORD, height_key(height), height, result)?;
+        self.write(&[], &[record])
+    }
+    fn get_block_result(&self, height: u64) -> Result<Option<BlockApplyResult>, UtxosetError> {
+        Ok(self
+            .load(BLOCK_RESULT_RECORD, &height_key(height), 0, Some(1))?
+            .pop())
+    }
+    fn update_indexed_balances(&self, update: &BalanceUpdate) -> Result<(), UtxosetError> {
+        let removed: Vec<(&str, String)> = update
+            .removed
+            .iter()
+            .map(|utxo| (INDEXED_UTXO_RECORD, hex::encode(utxo)))
+            .collect();
+        let mut records = Vec::new();
+        for utxo in update.added.iter() {
+            let key = hex::encode(&utxo.utxo);
+            records.push(NetworkStore::record(INDEXED_UTXO_RECORD, key, 0, utxo)?);
+        }
+        for balance in update.balances.iter() {
+            let key = balance.address.clone();
+            let height = balance.block_height;
+            records.push(NetworkStore::record(
+                INDEXED_BALANCE_RECORD,
+                key,
+                height,
+                balance,
+            )?);
+        }
+        self.write(&removed, &records)
+    }
+    fn insert_flat_snapshot(&self, record: &FlatSnapshotRecord) -> Result<(), UtxosetError> {
+        let height = record.block_height;
+        let record =
+            NetworkStore::record(FLAT_SNAPSHOT_RECORD, height_key(height), height, record)?;
+        self.write(&[], &[record])
+    }
+    fn get_latest_flat_snapshot(&self) -> Result<Option<FlatSnapshotRecord>, UtxosetError> {
+        Ok(self.load(FLAT_SNAPSHOT_RECORD, "", 0, Some(1))?.pop())
+    }
+    fn get_latest_block_height(&self) -> Result<Option<u64>, UtxosetError> {
+        get_latest_network_height_from_psql(self.network.as_str(), BLOCK_RESULT_RECORD)
+    }
+    fn insert_rpc_audit(&self, record: &RpcAuditRecord) -> Result<(), UtxosetError> {
+        PostgresStore.insert_rpc_audit(record)
+    }
+    fn get_rpc_audit(
+        &self,
+        filter: &RpcAuditFilter,
+        page: usize,
+    ) -> Result<Vec<RpcAuditRecord>, UtxosetError> {
+        PostgresStore.get_rpc_audit(filter, page)
+    }
+    fn prune_rpc_audit(&self, before: u64) -> Result<u64, UtxosetError> {
+        PostgresStore.prune_rpc_audit(before)
+    }
+    fn insert_tx_status(&self, record: &TxStatusRecord) -> Result<(), UtxosetError> {
+        // a tx passes through a status once
+        let key = format!(
+            "{}/{:020}/{}",
+            record.txid,
+            record.timestamp,
+            serde_json::to_string(&record.status)?
+        );
+        let record = NetworkStore::record(TX_STATUS_RECORD, key, record.timestamp, record)?;
+        self.write(&[], &[record])
+    }
+    fn get_tx_status_history(&self, txid: &str) -> Result<Vec<TxStatusRecord>, UtxosetError> {
+        let mut records: Vec<TxStatusRecord> =
+            self.load(TX_STATUS_RECORD, &format!("{}/", txid), 0, None)?;
+        records.reverse();
+        Ok(records)
+    }
+    fn insert_block_nullifiers(&self, block: &BlockNullifiers) -> Result<(), UtxosetError> {
+        let key = height_key(block.height);
+        let record = NetworkStore::record(BLOCK_NULLIFIERS_RECORD, key, block.height, block)?;
+        self.write(&[], &[record])
+    }
+    fn delete_block_nullifiers_above(&self, height: u64) -> Result<u64, UtxosetError> {
+        delete_network_records_above_in_psql(self.network.as_str(), BLOCK_NULLIFIERS_RECORD, height)
+    }
+    fn load_state(&self) -> Result<StoredState, UtxosetError> {
+        let utxos = self
+            .load::<PGSQLDataInsert>(UTXO_RECORD, "", 0, None)?
+            .iter()
+            .map(stored_utxo)
+            .collect::<Result<Vec<UtxokeyidOutput<Output>>, UtxosetError>>()?;
+        let nullifier_depth = NullifierIndex::from_config().depth as usize;
+        Ok(StoredState {
+            utxos,
+            block_height: self.get_latest_block_height()?,
+            block_tx_roots: self
+                .load_latest(BLOCK_TX_ROOT_RECORD, Some(BLOCK_TX_ROOT_RETENTION))?,
+            block_fees: self.load_latest(BLOCK_FEES_RECORD, Some(BLOCK_FEES_RETENTION))?,
+            block_results: self
+                .load_latest(BLOCK_RESULT_RECORD, Some(BLOCK_APPLY_RESULT_RETENTION))?,
+            block_nullifiers: self.load_latest(BLOCK_NULLIFIERS_RECORD, Some(nullifier_depth))?,
+            burns: self.load_latest(BURN_RECORD, None)?,
+            address_txs: self.load_latest(ADDRESS_TX_RECORD, Some(ADDRESS_TX_RETENTION))?,
+            failed_txs: self.load_latest(FAILED_TX_RECORD, None)?,
+        })
+    }
+    fn load_indexed_balances(
+        &self,
+    ) -> Result<(Vec<IndexedBalance>, Vec<IndexedUtxo>), UtxosetError> {
+        Ok((
+            self.load(INDEXED_BALANCE_RECORD, "", 0, None)?,
+            self.load(INDEXED_UTXO_RECORD, "", 0, None)?,
+        ))
+    }
+}
+
+/// Rows written to a `MemoryStore`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTables {
+    // utxo logs of all io types, by utxo key
+    pub utxos: HashMap<KeyId, PGSQLDataInsert>,
+    pub address_txs: Vec<AddressTx>,
+    pub failed_txs: Vec<FailedTx>,
+    pub burns: Vec<BurnRecord>,
+    pub block_tx_roots: Vec<BlockTxRoot>,
+    pub block_fees: Vec<BlockFees>,
+    pub block_results: Vec<BlockApplyResult>,
+    pub balance_updates: Vec<BalanceUpdate>,
+    pub flat_snapshots: Vec<FlatSnapshotRecord>,
+    pub rpc_audit: Vec<RpcAuditRecord>,
+    pub tx_statuses: Vec<TxStatusRecord>,
+    pub block_nullifiers: Vec<BlockNullifiers>,
+}
+
+/// In memory store, for running the block processing without a database.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: Mutex<MemoryTables>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Copy of the rows written so far.
+    pub fn tables(&self) -> MemoryTables {
+        self.tables.lock().unwrap().clone()
+    }
+}
+
+impl PersistenceStore for MemoryStore {
+    fn update_utxo_log(&self, tx: &PGSQLTransaction) -> Result<(), UtxosetError> {
+        let mut tables = self.tables.lock().unwrap();
+        for key in tx.remove_utxo.iter() {
+            tables.utxos.remove(key);
+        }
+        for insert in tx
+            .insert_coin_utxo
+            .iter()
+            .chain(tx.insert_memo_utxo.iter())
+            .chain(tx.insert_state_utxo.iter())
+        {
+            tables.utxos.insert(insert.key.clone(), insert.clone());
+        }
+        Ok(())
+    }
+    fn insert_address_txs(&self, entries: &[AddressTx]) -> Result<(), UtxosetError> {
+        let mut tables = self.tables.lock().unwrap();
+        for entry in entries {
+            let exists = tables
+                .address_txs
+                .iter()
+                .any(|tx| tx.address == entry.address && tx.txid == entry.txid);
+            if !exists {
+                tables.address_txs.push(entry.clone());
+            }
+        }
+        Ok(())
+    }
+    fn get_address_txs(&self, address: &str, page: usize) -> Result<Vec<AddressTx>, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        // newest first, as in psql
+        let mut txs: Vec<AddressTx> = tables
+            .address_txs
+            .iter()
+            .rev()
+            .filter(|tx| tx.address == address)
+            .cloned()
+            .collect();
+        txs.sort_by(|a, b| b.block_height.cmp(&a.block_height));
+        Ok(txs
+            .into_iter()
+            .skip(page * ADDRESS_TX_PAGE_SIZE)
+            .take(ADDRESS_TX_PAGE_SIZE)
+            .collect())
+    }
+    fn insert_failed_tx(&self, failed_tx: &FailedTx) -> Result<(), UtxosetError> {
+        self.tables
+            .lock()
+            .unwrap()
+            .failed_txs
+            .push(failed_tx.clone());
+        Ok(())
+    }
+    fn insert_burn(&self, burn: &BurnRecord) -> Result<(), UtxosetError> {
+        self.tables.lock().unwrap().burns.push(burn.clone());
+        Ok(())
+    }
+    fn insert_block_tx_root(&self, block: &BlockTxRoot) -> Result<(), UtxosetError> {
+        self.tables
+            .lock()
+            .unwrap()
+            .block_tx_roots
+            .push(block.clone());
+        Ok(())
+    }
+    fn insert_block_fees(&self, block_fees: &BlockFees) -> Result<(), UtxosetError> {
+        self.tables.lock().unwrap().block_fees.push(*block_fees);
+        Ok(())
+    }
+    fn insert_block_result(&self, result: &BlockApplyResult) -> Result<(), UtxosetError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables
+            .block_results
+            .retain(|stored| stored.height != result.height);
+        tables.block_results.push(result.clone());
+        Ok(())
+    }
+    fn get_block_result(&self, height: u64) -> Result<Option<BlockApplyResult>, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .block_results
+            .iter()
+            .find(|result| result.height == height)
+            .cloned())
+    }
+    fn update_indexed_balances(&self, update: &BalanceUpdate) -> Result<(), UtxosetError> {
+        self.tables
+            .lock()
+            .unwrap()
+            .balance_updates
+            .push(update.clone());
+        Ok(())
+    }
+    fn insert_flat_snapshot(&self, record: &FlatSnapshotRecord) -> Result<(), UtxosetError> {
+        self.tables
+            .lock()
+            .unwrap()
+            .flat_snapshots
+            .push(record.clone());
+        Ok(())
+    }
+    fn get_latest_flat_snapshot(&self) -> Result<Option<FlatSnapshotRecord>, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .flat_snapshots
+            .iter()
+            .max_by_key(|record| record.block_height)
+            .cloned())
+    }
+    fn get_latest_block_height(&self) -> Result<Option<u64>, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .block_results
+            .iter()
+            .map(|result| result.height)
+            .max())
+    }
+    fn insert_rpc_audit(&self, record: &RpcAuditRecord) -> Result<(), UtxosetError> {
+        self.tables.lock().unwrap().rpc_audit.push(record.clone());
+        Ok(())
+    }
+    fn get_rpc_audit(
+        &self,
+        filter: &RpcAuditFilter,
+        page: usize,
+    ) -> Result<Vec<RpcAuditRecord>, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        // the last written first among the records of a second, as by id in psql
+        let mut records: Vec<RpcAuditRecord> = tables
+            .rpc_audit
+            .iter()
+            .rev()
+            .filter(|record| filter.matches(record))
+            .cloned()
+            .collect();
+        records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(records
+            .into_iter()
+            .skip(page * RPC_AUDIT_PAGE_SIZE)
+            .take(RPC_AUDIT_PAGE_SIZE)
+            .collect())
+    }
+    fn prune_rpc_audit(&self, before: u64) -> Result<u64, UtxosetError> {
+        let mut tables = self.tables.lock().unwrap();
+        let count = tables.rpc_audit.len();
+        tables.rpc_audit.retain(|record| record.timestamp >= before);
+        Ok((count - tables.rpc_audit.len()) as u64)
+    }
+    fn insert_tx_status(&self, record: &TxStatusRecord) -> Result<(), UtxosetError> {
+        self.tables.lock().unwrap().tx_statuses.push(record.clone());
+        Ok(())
+    }
+    fn get_tx_status_history(&self, txid: &str) -> Result<Vec<TxStatusRecord>, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .tx_statuses
+            .iter()
+            .filter(|record| record.txid == txid)
+            .cloned()
+            .collect())
+    }
+    fn insert_block_nullifiers(&self, block: &BlockNullifiers) -> Result<(), UtxosetError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables
+            .block_nullifiers
+            .retain(|stored| stored.height != block.height);
+        tables.block_nullifiers.push(block.clone());
+        Ok(())
+    }
+    fn delete_block_nullifiers_above(&self, height: u64) -> Result<u64, UtxosetError> {
+        let mut tables = self.tables.lock().unwrap();
+        let count = tables.block_nullifiers.len();
+        tables
+            .block_nullifiers
+            .retain(|stored| stored.height <= height);
+        Ok((count - tables.block_nullifiers.len()) as u64)
+    }
+    fn load_state(&self) -> Result<StoredState, UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        let utxos = tables
+            .utxos
+            .values()
+            .map(stored_utxo)
+            .collect::<Result<Vec<UtxokeyidOutput<Output>>, UtxosetError>>()?;
+        let mut block_results = tables.block_results.clone();
+        block_results.sort_by_key(|result| result.height);
+        let mut block_nullifiers = tables.block_nullifiers.clone();
+        block_nullifiers.sort_by_key(|block| block.height);
+        let nullifier_depth = NullifierIndex::from_config().depth as usize;
+        Ok(StoredState {
+            utxos,
+            block_height: block_results.last().map(|result| result.height),
+            block_tx_roots: latest(&tables.block_tx_roots, BLOCK_TX_ROOT_RETENTION),
+            block_fees: latest(&tables.block_fees, BLOCK_FEES_RETENTION),
+            block_results: latest(&block_results, BLOCK_APPLY_RESULT_RETENTION),
+            block_nullifiers: latest(&block_nullifiers, nullifier_depth),
+            burns: tables.burns.clone(),
+            address_txs: latest(&tables.address_txs, ADDRESS_TX_RETENTION),
+            failed_txs: tables.failed_txs.clone(),
+        })
+    }
+    fn load_indexed_balances(
+        &self,
+    ) -> Result<(Vec<IndexedBalance>, Vec<IndexedUtxo>), UtxosetError> {
+        let tables = self.tables.lock().unwrap();
+        // the updates replayed in the order they were written
+        let mut balances: HashMap<String, IndexedBalance> = HashMap::new();
+        let mut utxos: HashMap<Vec<u8>, IndexedUtxo> = HashMap::new();
+        for update in tables.balance_updates.iter() {
+            for utxo in update.removed.iter() {
+                utxos.remove(utxo);
+            }
+            for utxo in update.added.iter() {
+                utxos.insert(utxo.utxo.clone(), utxo.clone());
+            }
+            for balance in update.balances.iter() {
+                balances.insert(balance.address.clone(), balance.clone());
+            }
+        }
+        Ok((
+            balances.into_values().collect(),
+            utxos.into_values().collect(),
+        ))
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockoperations::addresstx::TxDirection;
+
+    fn address_tx(address: &str, txid: &str, block_height: u64) -> AddressTx {
+        AddressTx {
+            address: address.to_string(),
+            txid: txid.to_string(),
+            block_height,
+            direction: TxDirection::Both,
+        }
+    }
+
+    #[test]
+    fn memory_store_utxo_log_test() {
+        let store = MemoryStore::new();
+        let insert = PGSQLDataInsert::new(vec![1], vec![2], vec![3], &"script".to_string(), 0);
+        let mut tx = PGSQLTransaction::default();
+        tx.insert_memo_utxo.push(insert);
+        store.update_utxo_log(&tx).unwrap();
+        assert!(store.tables().utxos.contains_key(&vec![1]));
+
+        let mut tx = PGSQLTransaction::default();
+        tx.remove_utxo.push(vec![1]);
+        store.update_utxo_log(&tx).unwrap();
+        assert!(store.tables().utxos.is_empty());
+    }
+
+    #[test]
+    fn memory_store_address_txs_test() {
+        let store = MemoryStore::new();
+        store
+            .insert_address_txs(&[address_tx("a", "t1", 1), address_tx("b", "t1", 1)])
+            .unwrap();
+        // re-inserting an entry is a no-op, as ON CONFLICT DO NOTHING
+        store
+            .insert_address_txs(&[address_tx("a", "t2", 2), address_tx("a", "t1", 1)])
+            .unwrap();
+        let txs = store.get_address_txs("a", 0).unwrap();
+        let txids: Vec<&str> = txs.iter().map(|tx| tx.txid.as_str()).collect();
+        assert_eq!(txids, vec!["t2", "t1"]);
+        assert!(store.get_address_txs("a", 1).unwrap().is_empty());
+    }
+}
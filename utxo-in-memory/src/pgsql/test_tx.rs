@@ -1,5 +1,5 @@
 pub fn deserialize_tx_string() -> transaction::Transaction {
-    bincode::deserialize(&hex::decode(return_transaction()).unwrap()).unwrap()
+    transaction::Transaction::from_bytes(&hex::decode(return_transaction()).unwrap()).unwrap()
 }
 pub fn deserialize_tx_id() -> [u8; 32] {
     let tx_id: [u8; 32] =
@@ -12,6 +12,8 @@ pub fn deserialize_tx_id() -> [u8; 32] {
 pub fn tx_id_string() -> String {
     "7DE9F3368FDBA3E23CED4AB9F425475C848CFAD5E62B692AE9DAB70B374F087F".to_string()
 }
+// The hex of a dark transfer in the current encoding. A fixed hex goes stale with every
+// change of the encoding, it is re-encoded from the reference tx instead.
 pub fn return_transaction() -> String {
-    "000000000000000001000000000000000000000000000000050500050000000000000000000000000000008a3bcf839d3c8cc68192e2bb4b883ee371ab89e33d8680d9197430589fc5c53300c8b47e8bf3a3e6f613ab300984a3ed20df8b10ba50c380bd3e829742b7afda54120a8cd9227cf5f05f6b4b726ab53de8ecf0363237646b4d11b9e199bfd778448a00000000000000306332613966383038653264616237346364383637653935396537633864373464393737626134643639373532363437343639623238346230346163366166393765313265373362356363363365333666613234316539343537353833376634313864373937653864376435646135303631663065323637376264313964373537343466366663316463000000000000000000f379587f8a901ce08654b7137c03db91a1ec985e7d93e70d3ec173acc88afd1100ee074e181d1c31d124e7cc16e8295b33014b7c468eacd594798cea3da9613d305420680eba49569b63d638f7e3ad6251c724daba03f3d14e69b92a5f55b7956a8a00000000000000306337633333386366613965633061373232356330306664643639633731363961666561393864626632306561313638336332616436636230343933646465343030326566316236396337653539313964313863626163666234666563663865646562343733326139336365373163633562386433663162343263383439306133383033653939326466000000000000000000000000000000000000000000000000000000000000000000000000000000000000fe42df6192a5d35d4c23d86f1d238d55f48d1e322ad3e03c31ddd68dcde60a79b89d073e094b0629dc7cc54515879198ae12a55e31bef14bdb11c50453020e258a00000000000000306364383566333965343935393665303664343365346266633936353762356265653764336134383431663661666336666632386636393461343138663131393236636137323465616632356630363963313432363537636332363261303065333032646661366638386532663466656134613063636330386161326231376432653432343963626366000000000000000000000000000000000000000000000000000000000000000000000000000000000000f47b7c60d8e5f439f3c3252185a12b9f9b18c72c99e97ea3fd4f3fcb55493f46b28e55ba76a18231f1a1ea8d2fb907de0e883abd107e7a43c70a02bcbc1cff458a000000000000003063656362326132393238343739363732663637333737373266656330636634636461663765653932316561333665373666633030346266376135363233626230376630306266363031366563626637356430366536663431303361643030613066393133656366613430396530353437613666306132303138666532393136323465653335383562310100000000000000000000000000000000000000000000000000000000000000000000000000000000009ebe0f25e7d65939834c08b094a17052bbe361e5330d23922e40823c8be6176ad2481685166832d276533080c291f8a34fd64d99ad038c1af4462b1ab9aa44428a000000000000003063346336386635643362656364313037646439616263653832393830653033613132613931313065663163333264623963323239333030376635346162613433323932653337373237313337343065333436613433616634336163343962643334316461376631623338306535653963323366396164376239396338626232323234656635363861360205000000000000000000000000000000f483a36ab5e1c2c7e2543a31ff85c6ea72667371516b09353dd6ed1cbaa81732f4fbdaaf2dd5cdbeaad85997b9e8d7102c22a637b41a2ddb27053e9fd405ac768a00000000000000306366343835623566376666356431636362306236336333646165363633616163333364626562613732643730366235386631383662636332303061353932353066376534393165303064306237343838336261613563373531636136376438653761326136303961363336303762353731633138643736623332636335363035363230303162636532000000000000000000c5de66e813de4813cd5cbc8a0152359252b505bdc02e268ad377e4c5cec9267c5887838569bcf38a45fc6617389deb66d235a5334ea1c5b5438818795f7e748a00000000000000306330636363323762373039363438383366343366653461316638663836363330306436393330363831363665333232393136333537313663623838626639633034333066633733666635386431643764653333346334383231636265343730386361373932616466336339666363643939643235613136323237343438396632346362356130666639000000000000000058c02c1ec4259e4d0ab45b0952d954a81dc4720289839c7e2c31c717c5987745fe84aef812c707953b0d80f5f5e8588873994d946272f2f1c91a9ec914af88668a00000000000000306338303435323736393534373734626365356264346438346435336138636465633330663333333666633330346634313233646631386632323765643939623636393266313338383031343663343636313762323930306434333163663464663066333166633333313631343736396136396439326630343666393637643637343864643135323337000000000000000042c525f74306598821c6ef005d009ae622b63a5d4e3620aa9d89b7af8ec56a3d68bdd77b57b3d191054fedeb5de59a3ffbe4b5e610d72b528440e9f04367f60e8a00000000000000306338306230653636346465613162646263656261393865626566643963353062383737363231616139373763303831346531316537636138376564663130323531356331383936323132353166303362396139313637303033303337633630326236666464623030383562653636336634613165373562393232313762616234393738323633663062000000000000000052f0ee3badd9ca9a5c5c223a9bf4705890cfc0cf2f5003c874b87d6f18d3a20b4af7d965fe5c8983e3f98b395b3f047a476136faa4cda0dd5659a5ef466e9b5e8a0000000000000030636630633462353532306238383739633463313265653731343230666639393830343364363631646262366231653637306563643966616361333763323864316663613039653937646133373030376366383764323866656563363735353262356633356130326663643332313564346362316665353030343131343433623430336437356434396505000000000000002a9f808e2dab74cd867e959e7c8d74d977ba4d69752647469b284b04ac6af97e12e73b5cc63e36fa241e94575837f418d797e8d7d5da5061f0e2677bd19d7574d6f4f6d9e0d71fd37a6901f4edc425d259c7a94e647d2c745d4312cc46f6386ff0fafaed08bc47a8a22ef1f19f5a3712cd62fb7821ba8c36a76ac0143fa2f4217c338cfa9ec0a7225c00fdd69c7169afea98dbf20ea1683c2ad6cb0493dde4002ef1b69c7e5919d18cbacfb4fecf8edeb4732a93ce71cc5b8d3f1b42c8490a38666404fcf20876ca314cd558852f90994e8f630911da10ec3d7813a475f4540f78217016336f6ac7d706aaa5c5b40878062280d657fcce946c2e9bfe6119d753d85f39e49596e06d43e4bfc9657b5bee7d3a4841f6afc6ff28f694a418f11926ca724eaf25f069c142657cc262a00e302dfa6f88e2f4fea4a0ccc08aa2b17d2e461eef14c764b0678381c502b846f61048dffe8270d8dd7fdedb1277f3320d7268f20ba90434b35ea39ea8638538d514c5d812c1e3bc1a9c0689e6ec6a8c402eecb2a2928479672f6737772fec0cf4cdaf7ee921ea36e76fc004bf7a5623bb07f00bf6016ecbf75d06e6f4103ad00a0f913ecfa409e0547a6f0a2018fe291624b452e107b43589452fadc566b3c344ba39d442a6d176b8a9095e0efb61ac663708674a118e27c495b995016ef094146ea25a9329b47aa0d2982859b3b6cada7e4c68f5d3becd107dd9abce82980e03a12a9110ef1c32db9c2293007f54aba43292e3772713740e346a43af43ac49bd341da7f1b380e5e9c23f9ad7b99c8bb2224cce123fd70d7dd25a96aae301f0c81a6cac7e6753583a1116f5db02fbae8404b0bb521cd0ee9455aaf9000a15e548cb61b842a9239e2d5d54cd131ac3930b700500000000000000e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f340488711347e6f8f00567180467e54b8a4485c80fff11f42d2453482b80abdd6d802713f758278757f9c0bc66ebc57338c90c3c6369c5eb68d579dde09e0999c6459f3bf07e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f340488711346e0b1f4bb4037f6eff32954cac1b6c860e608358089acb0551489ce6a6ee6e41e8be02700055c3a56527bc3ef3c29f077bfb13f667b1f6e89d5601c595339817e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f34048871134268f3f289be2949be5c24bf98f3ef1b1ce41b9cbaf9d34836e30366e70f2b0576eda676d68457c4d0e3c73fe7424748cc44a3cedebad120dd88fc68a36d55737e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f3404887113422944d9a56f0895b04b39ab4cdb88e4b4a8bf9330b4a2ee25765cbb1388d523ecc22460595e7612933faa075bda7a19244f2dd4fd4beab4f37054bfa2a7b2f4de2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f3404887113488b7c77a964efc53a11575705241aa3d34f3f6d70c1e8dcbb39e7b81a363e4451401278f3dfab47a93bc794834b8d76cd3986c197e39540e85d1fe79803b4f5c05000000000000002a9f808e2dab74cd867e959e7c8d74d977ba4d69752647469b284b04ac6af97e12e73b5cc63e36fa241e94575837f418d797e8d7d5da5061f0e2677bd19d757446d256402a0ae98df0a000d35a2c3bb21c3aef8067882e7c545affddfeb5d85fca95eb0e0b7d289a38620cbd318f3c773095502728a836f60c12a855cb17b9147c338cfa9ec0a7225c00fdd69c7169afea98dbf20ea1683c2ad6cb0493dde4002ef1b69c7e5919d18cbacfb4fecf8edeb4732a93ce71cc5b8d3f1b42c8490a38d40bcb2dce2f249b35a5ffcf72e7b13e4d43aff919b60b9c20994927167ebd784012cfabfa106410cbbe904a09d67c91f8dafa60ce33342e7efe2a9af3110870d85f39e49596e06d43e4bfc9657b5bee7d3a4841f6afc6ff28f694a418f11926ca724eaf25f069c142657cc262a00e302dfa6f88e2f4fea4a0ccc08aa2b17d2e7408b6a7ccdef8c6725bd6272a7f4b6add9c6a2ac6f548c484cebc82d1615f703e355c4e82492fa2e690f08ade9f3c6685f1ed46836e1040dce15afa99d0ef6fecb2a2928479672f6737772fec0cf4cdaf7ee921ea36e76fc004bf7a5623bb07f00bf6016ecbf75d06e6f4103ad00a0f913ecfa409e0547a6f0a2018fe2916241e7eabb838ae63dd0586cd031cc14db2db542ebb3305269896ef1daf9462a8639a7c23cf6376881feb03e284f9135808026ecca48e704be4cbf81ba7d8f88d774c68f5d3becd107dd9abce82980e03a12a9110ef1c32db9c2293007f54aba43292e3772713740e346a43af43ac49bd341da7f1b380e5e9c23f9ad7b99c8bb22288b81a767fedb3c2d7d076d7c13a334f428149f29d85cda0e7d8d60740334555bef20bda173f835f2bb574a666b33ce9f974da919e353f1bfbb748afce53fd09010000000500000000000000a53443ee1f28ae154ae775f9530f4b6a91f8d13cd30945dcb2a1c0cad259db0c908904c55ca65cfd607be9994448b9a5b6023ba4be27aaf8b59f12ab4fe4830940bb04afa89a2331de79e8b43be8e4dbcbce6e6ca97cc9c01786f3a5507b3e06ebfc282d9d31200174e84a1db3348e0f2e06acfa6da1fda9baca1485451ea90f875fc148dd96a4b08ba0a45b3f9ca28f960c16a1fc753431b89e381f117cf8010500000000000000209c90d91eac4b2993d10478c1ebce365f0ddf15f44a3e784458a9e59decea0b1d676849ba6717d991dd9c016a6385c5d9d4ad5d7347ab8ae7f22cdcbb589c010a94790569d384b52e307ec61830957fc4146a6b50d586227583ca0589e2590857252e7a8686cd525bf9763be371946ae63931720648eaca0c6c183ed8eda605740f7f0f8edd8bc00c764e649582788d0ac66eb534b83dd82d78283feb9c7c07050000000000000096cf3f6a25b442044bd97a7dd83973a8947928f4d8f782ec2d93d142dbd2fe051f62c464a70b1cbb06c49724b1e7b1956037b29a51d90c6f19ac5df55f6efc08f53ebbb02685d3764a6788eebe4069901b3e029eb537cde534892784a96acd0e6cd2489f9cea01282f055c6ba08f3e95d45663233b90b609b53d720ea2b76b0c5dba5b20a9b6e7240df0982c540f46ef8cbb34af1db00e4db09825adf138f0085282c8a26bcc86c12c36e84fb070a6c3c2106103450d1d871f416dd4368f88000200000000000000e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f340488711344aaf55a14af6ff3dbd296413acc9d15e2d76ca959a7296b6dfd2ea4f5865d90b486da11b4f7d22b5fa06b8282844f035b1fcb65e09973ba0db021fe078373b33e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d768c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f340488711340e2fd3cec8edd911b5569fb87d841a8d177d5863421b33719730be770d59263004b2d51c3c508cf69296d53c3ebb0852450dc45c065398a43b6323296fac870c010000000200000000000000697741d55902fccb3542c8fbc09ef333a38a81ae19e009a80d16b9d0f2f652053725c30de15a8e169b61d11ffaf1a73ceb8e2ab0c8d8344f4b20697e265ff00d020000000000000096d4a4c93780a316340a00527982ccdf015b0bf49d54b6d56d440e61aafe48012e10342b4ae1149313e731dbd07fd58b77c4838116c58c1fcc4b5648c66323010200000000000000be7ab40234f76b05f2c42b7d62f10489038fa5dc3decd8819c9389d91d1fb90414fc22e340c558950ca3de4ccfc0c08a7776d610727e59a0711ae87fbf20130b3dfc5d84d445cc34ba62e439edbbb6f446a1f07155aa094fa77cf7977d6ffa030500000000000000a002000000000000b8d6e1a47294b3db70397db1a3384777f3743aef6ead1e0d089ffe8e333f73259e88f783d2512514c8079293402e9274580d86cc011485f92cec90a16a9dcc01c2bd401ebbc49a671eb1fcf391237fcee5227b1c0aac141a9e07f34110b7cf0796382fbaa6ce67eb8d0424b2916aafcac2e89ce4004639b17a8755b4146d6a4f3d3f8187f71b080d6c44db7bc94ed0819f94f2208d846dd246f77e7894198406ce9634721448a600282f6830381c9100e1a30a58a8aff3d8893e279716688d0485abdc223e5435f66f42b0f7d408a6ee25e430f0189f8365a60fbbca42354b0bde9823472eafbbe8fcd6e13a148564b6fe5df02549fefad88a72978f5397de3fe44c0fa473d2d3e40f4fd35f37f2fc0c8ed564c4362b9abe2f90afa79403b5788e97af257b209e2c7c48d76a80c0939ef9423e467b1613bb2f84ac7bf720193a08c221c2eb4600b4a9bfa53d66b2e4e91632ea05818edb6fd10d9ceb076a5a1838bcd4268becc81e25f2be780c08a254a2e9449d7e0f14bfdadaa750e1787f3ab293321fa4ba6e9b2eb58d85981d8fdaecdee07066f188e88b0c90a0041812565613be2de898874361462358920d0d0b97334541d8f1f33541afd2e7391fb85f502f4649a85e58ab0934478d6b935e10a2d8fb982f191ef4e4a6ca69d4a30d4bd80b65c3911293343b15893a435288959a2f1b0dea7edbfccc20d161f7671f3b6a04976f1bf1a514694a9903265d3902c0a621eeae8674dc155e7c7fb998660288904ad1f8769938725770b4984bf8bb35c0a165627231ade06651e5e47f340402af1a1724cccc65432b41ef9487907b98721e65b9a3e1dd37f5e09c3bc388511b68030866933c7b32800cca6b1e03abbf0259bd86c4ec487afc3044aea76c09eef257ab8ec6ec10e20a2415cfada662008da502ecf7dda36995f8e9eea2cc04a0020000000000000069777d1b7756075de5ca0df4a02a44cd642a9dee426b035579cd503c9bff67f6d31f00a6daded1d50041057e099fba6ef6006010b142f7478ff7d17dcadb7ec696d537eb25012595d6205e8ef7ea7e18e37ea975b68fb5cc5c278e7c9ed74eb08887337af69c14e5eaa35ce1107ffbbce4863599a53cb95c03c987a311924ac65d9d6a34c678da4195a3da206908f2bb7da6d18fc05920c41e6b057bd48c0ffd7d7562d973c381c20b8087f71d403a3f42f5d1f4ca1b214c655341f1cde2070bf898727cc559469eebbdd0acc4234304afcef3c9d6a7820eeaa2113ab21b0dc66e7f9efa1c0e5c65e11a0173614a69844aa434f10397a0a90792df186afd33a28cf3ffbb2477218991c8c19ca24cc5930c0c0b1f2a95c41ef373b514b5596c92800fad0eac6beceeaf4a4659da107909f99ef219db33f8a008ccb23c0f196c8693ab93650f063c4bdd4b73c272e7725d2ec89db7bcdb945284e9c63093dd134a5849b8708da4d59b09376dcc10ceda7ed1f5239ea6d3ae1dc955096499c521806249fdda16d591ba218c3e535c716e8280030a50272abcc350e0553fd5b429989b5de2e929233c540a306e5e11da874ed1e1140d27731433b0b7681200ff4f84f0f285aec7554a3d8183a16895d5925e1d06e9bd91e3e95c3a8cf717e1356b60bcdb18f829f9dcb0731c2e03e3006ee9c3610275be3a42412e3815b91e2a6a0ac788c3b1931d542adc24a8efeb7c0cf239355b29a434fe2df0bded4b057c3726a373fb487a5e5b1b09532989f836ff53964f93b7dfee8bcd7b0e2ffb93166f06887e40d732cb0dca43a9f138481d86724140b3d52624a391180fc3f1db0321268aa0ec1f3ec5be45897b98c8fc6598b1b18035f7d7e0659a138c431d9e040ed13ae84dffdaae4b965d4d05d87d3e5e3d6e1591dedea75358823f11a3d4480ca002000000000000fcf2f258d3bfafaa9bf0282d60f96ecccbecdf6a6d96041efed0bdff1cb85835025389b7285bc1471e0e1c207a1b973673b007635a53c24a95a52eecc2cd65160e88c628eb743769854c770485b60016cf7ce74620b82ae642c036d96eb40854d43e3f4ef453a8b04151c60d5681c118240e84128bb4f9d224354fddbe7afc1a01009ffe531634fb36bdbe142085dbc4639e0585d9854ff0b200bc70a1265d07f9ab5a1924d2044641e5245f52f28eb1ec251f5ac96bf47b413d22b185da6b07b3ef3bfa2dd7f424df5e208e0a20847e4f7eb84594b77830091ae9688fa3680efa392647110a20ccb497b8b5cd6fd40f7b2e0ff569310eb54ec7322b7ab24f623eaad4a9bdee31a348bbd91992329650afc3d66a4dc846b9d736fc383e2c8d002efcff49b730163ee4e4c2f3d8609b6444e5b34837500db287e17633ef94a03e5a4ef7149309803a079a9d7f1323874cfb51f5beab68367bf99d657764e05241666e0f9810caaf6738bdaae94fd976e914169aa8be9f98f6b5c243c75466f064e81c704e0cc1e61aab05a1cc33f03a7748f1822be8baecc18594a7dd865c0a1a085bd58b22d8e01ea8627cd9f49092fcbcdb2684ee67f0d286c3b52e79a4c14a5ce05d36cdc29b08e45d3c89435ff62c7877c92110577072aba81183a2bb6e3a9a0a934832a3c7052e7e7126b6b90049cd21c7a372a61648af55c5203857ee2becd80d7ebf749496c1075a572e36469651ab3409e03e0b06bb9c3c660883b676e0a676ebef655309dd80433b4e07ceb25b9f6e2452992e02b49507eb20a3d031bc81560a2c0eb8e4aafc22a568ccadbbfc863b200c26cf63a2fa450be1a388156ed3ae2979cb2a680ff52e08db1ae16a5d6e6a3260c754723cda9bafabfa770219599c25d56da8393aef35eac71449c4bb3c0eda115a63733e5df95099348403a0020000000000000ecb247b8ca79367e3179436e9e82819fd29b48e018728430d2cb7484ab12e6cc83f83cb63948bc2b0c854982febad1af7b54fc99149254622c9aca22ab52e79526bff7237d4835c1d6c73af03ee512b8364dbf3c66beb19721977e73cb1647b0607b3b3d95ebfcde1683e2f491ad6999c9b85ef034c2f08a1cad821384e4b5f0727708421a05d948882001e3545dc56b05bc0211d84475401de66345b17a004c12c899ed14ee913e791202115b0f7e16cd23536dda52a49f7dc901d8d62ce073ab7ab9ab5bfcfc39c1989517b417814a2e6a40e0eac3c209a4e2785d6a32d02ce6ff59a4c1290fecfa847165dec06c1d62f9ecff2fa67159bef985b2b1cf9661067c92df09f7c282f0cc0178ae8132c5cdd187eab9ae7ab6737acbe8057496e0a92b03c462afddcf47105ee3b067c095a8b67f4aa763c551b3f04d3511eae1588a2df2ed0f2f60727627b9f0d02d3b7d4bcbed840077c7bdbc00d37ae4d5c23c0e70595122dfeb26b5e6ea4aca0bddd3d53da2bcb591a0452d65c8cd6bfae35fc4963ccb917db2cd3cf9d3d04c2d58fe11fffbfdb1ddc8ffa02d3fe6bdd5076cca92254025375d65e1ec12746d2a0f8348d605c9750d11545a7c757b775b03fbc9d4c65a094610720f25ab8b6f69640a709bb3645321c6b28231c347c70fd788af1876e2a32bc1b57bdccb1c561d3a9479359cd26f2a467ac32dea89977cc79846f4e232fd2f80aad730c28824dd670998204697ca154d82ff3577ae60b462d2c2c1724b3756582ada5c95b5114b2a20cc24378f1bc59f474d3289ff650304f646d8d1b4df763fab8f9092c1313033c8601d409662cb64d47995292688dce7f2c3724fc20f9d45e6cd30eb9b54313f33fc9e0b5db3eba859ad922f9e1fab00383d5bf82fb7d23451567548a9727603eab3a5eda14ad67276292a1af8eba2f0aa002000000000000762193db46f9f72cbbae6ed62408d02026ad7a8a8dcf91c77bb1a5e8d235ec0a1221594b4194fdc73245b02a80387058916eceb933f72dc1627ee1e9d2a54e33a810929ff93d3fd5aa47d8a38e100b9ee24add56a873996cc86bc9a825ec30015e07b32a12dc27b9f417f4e042180079383bb9c32828033c875ce6ffae393758e6a85aab5c922dea52a2e80a3be2d83763e50336522b182c53939fe4b5118502298d57563400fd0fdf883624842d409fc0077ac917627b87760450a89d83460009cbd03030d99b87977ba556192a504d8941031010afdbd1da1fbb1f7f483501265ffaf605161056fd3a07cdc3aace1e2cf110b6a4a70570fbd09ac86c97b9745cd4c865d0411db2379c00dfda20d45186a5123bcc323876b41d3a5539c61d01d80402f01a80eb586efd113ec2e4e5ba167752e2a2bda4e4047d805d5f4ea15de08b46566da46ad471937f11fa9f30b419671e37894a79f302c5e9d0dd416e2c5010e1008f04272e41d7965944f9d02776b61d14a9e154c40a47c67514f3c0757c9a52336d7765118216f6b888725a891d0259f687e92a75b8b19f1dc9b868348ce032b5121c6b72347cf303c312c862a0cf77fc6358280bb60cceaee2f9bb0f9ea2a19fd2e161793e42b1177e27e7f4d0998688d437c3a1fb873cb38485b65ef2f3a11ac543909a9546dea5cd894b16b040590dd36d8802d84c4d717b6ffa4b3c4319783cc055b93fef108b66a9f35ed96331785f870ec47428cf8b1f683d4da4c6aa6c261b1087a4d4f8c88ff7c94f6e1b577b377422bc77dc53ec26d0942fcc7f327741546bcee5b1e7822dab494a6c3c2208a4b51a63001a6395b7ff66571f9e964731ee85b54814457075cd306d9c4b6ca54bc739b7a30b903d5795f0008d67eb077ee9b649ce0fae365dfcf70e459d1133208e1b5c64750c60bb8fb80001000000000200000000000000d63cf200fad12fb450b39dc082714890092d5c12ac72c7bbf9282c1e72a1a10f57dac91dfe3c76d5de5c3892d6adf4afa28d507bb4c1189671c753650aadfb0eb718b2173b5fbdd61c9a886dc96c9cf6477e89a11bdec29d623863724031500703000000000000000001030000000000000003000000000000000100000000000000ff3960c7aec41fb689d995a061cc39555f24ddb47067522eef64a8efe821150428e240b6f4525c4e91fc8c29b02497ff8519074680bd4b657c0206de9ea2e40303000000000000000100000000000000f88db0005c7a058de8efc2e4115f508cf9094008379535f08732a1635a9658050363cedbf6156d46b458a8bdae46413734d8b429a4a14fb4b794e10aa9d61c0b03000000000000000100000000000000c9a842409c40538ceca49027999b79922bd5dffa2fe831ccbf44874c60d9fd04c8fcfb4d4cd49a9fbd23330474503bb70572b4679f60c3cc898638526c81fd0b".to_string()
+    hex::encode(transaction::reference_tx::create_dark_reference_transaction().to_bytes())
 }
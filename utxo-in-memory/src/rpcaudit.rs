@@ -0,0 +1,177 @@
+//! Audit log of the mutating rpc calls of a node.
+//!
+//! A record holds the method, caller, target, outcome and latency of a call, never
+//! the tx bytes or the other params it was given. Api keys and owner addresses are
+//! stored as salted hashes, see [`redact`]: a record can be matched to a known caller
+//! without revealing the callers of the others. Records are kept in the `rpc_audit`
+//! psql table until they are older than the retention of the node.
+
+use serde_derive::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Records of a page of `getAuditLog`.
+pub const RPC_AUDIT_PAGE_SIZE: usize = 100;
+
+/// Outcome of an audited call.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    /// The call was applied
+    Ok,
+    /// The call was answered with an error result, e.g. a tx failing validation
+    Rejected,
+    /// The call failed with a JSON-RPC error
+    Error,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Ok => "ok",
+            AuditOutcome::Rejected => "rejected",
+            AuditOutcome::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ok" => Ok(AuditOutcome::Ok),
+            "rejected" => Ok(AuditOutcome::Rejected),
+            "error" => Ok(AuditOutcome::Error),
+            _ => Err(format!("Unknown audit outcome {}", s)),
+        }
+    }
+}
+
+/// An audited rpc call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcAuditRecord {
+    /// Unix time of the call in seconds
+    pub timestamp: u64,
+    pub method: String,
+    /// Client ip of the request, as forwarded by the reverse proxy
+    pub client_ip: String,
+    /// Salted hash of the api key of the request
+    pub api_key_id: Option<String>,
+    /// Txid, utxo or contract root the call acts on
+    pub target: Option<String>,
+    /// Salted hash of the owner address the call acts for
+    pub owner: Option<String>,
+    pub outcome: AuditOutcome,
+    pub latency_ms: u64,
+}
+
+/// Records of `getAuditLog`, every set field has to match.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RpcAuditFilter {
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub outcome: Option<AuditOutcome>,
+    /// Records at or after this unix time
+    #[serde(default)]
+    pub since: Option<u64>,
+}
+
+impl RpcAuditFilter {
+    pub fn matches(&self, record: &RpcAuditRecord) -> bool {
+        self.method
+            .as_ref()
+            .map_or(true, |method| *method == record.method)
+            && self
+                .target
+                .as_ref()
+                .map_or(true, |target| record.target.as_ref() == Some(target))
+            && self
+                .outcome
+                .map_or(true, |outcome| outcome == record.outcome)
+            && self.since.map_or(true, |since| record.timestamp >= since)
+    }
+}
+
+/// Params of `getAuditLog`, pages start at 0.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AuditLogQuery {
+    #[serde(default)]
+    pub page: usize,
+    #[serde(default)]
+    pub filter: RpcAuditFilter,
+}
+
+/// Hex encoded hash of `value` under `salt`, the same value and salt give the same hash.
+pub fn redact(salt: &str, value: &str) -> String {
+    let mut hasher = Sha3_256::new();
+    hasher.update((salt.len() as u64).to_le_bytes());
+    hasher.update(salt.as_bytes());
+    hasher.update(value.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Unix time in seconds.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redact_test() {
+        let address = "0c0a2555a2e5e3b8f7d2e9d1c3b4a5f6";
+        assert_eq!(redact("salt", address), redact("salt", address));
+        assert_ne!(redact("salt", address), redact("other", address));
+        // the salt and the value are not simply concatenated
+        assert_ne!(redact("ab", "c"), redact("a", "bc"));
+        assert!(!redact("salt", address).contains(address));
+
+        let record = RpcAuditRecord {
+            timestamp: 100,
+            method: "txCommit".to_string(),
+            client_ip: "10.0.0.1".to_string(),
+            api_key_id: None,
+            target: Some("txid".to_string()),
+            owner: None,
+            outcome: AuditOutcome::Rejected,
+            latency_ms: 3,
+        };
+        assert!(RpcAuditFilter::default().matches(&record));
+        let filter = RpcAuditFilter {
+            method: Some("txCommit".to_string()),
+            outcome: Some(AuditOutcome::Rejected),
+            since: Some(100),
+            ..Default::default()
+        };
+        assert!(filter.matches(&record));
+        let filter = RpcAuditFilter {
+            since: Some(101),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&record));
+        assert_eq!(
+            "rejected".parse::<AuditOutcome>(),
+            Ok(AuditOutcome::Rejected)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Registry of known script contracts.
+//!
+//! A contract is the ordered list of the programs of its Merkle tree. The script
+//! address of a contract is derived from the root of the tree, so verifiers can map
+//! a script address back to the contract and check call proofs against its programs.
+//! Contracts are kept in memory and in the `script_contracts` psql table.
+
+use crate::pgsql::{insert_script_contract_in_psql, THREADPOOL_SQL_QUEUE};
+use address::{Address, Network};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use transaction::programs::PROGRAM_TREE_LABEL;
+use zkvm::{MerkleTree, Program};
+
+lazy_static! {
+    pub static ref SCRIPT_REGISTRY: Mutex<ScriptRegistry> = Mutex::new(ScriptRegistry::new());
+}
+
+/// A registered contract and its script addresses.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScriptContract {
+    pub name: String,
+    /// Hex encoded bytecode of the programs, in tree order
+    pub programs: Vec<String>,
+    /// Hex encoded Merkle root of the programs
+    pub root: String,
+    pub mainnet_address: String,
+    pub testnet_address: String,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+impl ScriptContract {
+    /// Contract of the programs `programs`, fails if a program can not be parsed.
+    pub fn new(
+        name: String,
+        programs: &[Vec<u8>],
+        metadata: serde_json::Value,
+    ) -> Result<Self, String> {
+        if name.trim().is_empty() {
+            return Err("Contract name is empty".to_string());
+        }
+        if programs.is_empty() {
+            return Err("Contract has no programs".to_string());
+        }
+        let programs = programs
+            .iter()
+            .enumerate()
+            .map(|(index, bytes)| {
+                Program::parse(bytes).map_err(|e| format!("Invalid program {}, {}", index, e))
+            })
+            .collect::<Result<Vec<Program>, String>>()?;
+        let root = MerkleTree::root(PROGRAM_TREE_LABEL, programs.iter());
+        Ok(ScriptContract {
+            name,
+            programs: programs
+                .iter()
+                .map(|program| hex::encode(program.to_bytes()))
+                .collect(),
+            root: hex::encode(root.0),
+            mainnet_address: Address::script_address(Network::Mainnet, root.0).as_hex(),
+            testnet_address: Address::script_address(Network::Testnet, root.0).as_hex(),
+            metadata,
+        })
+    }
+
+    pub fn addresses(&self) -> [&String; 2] {
+        [&self.mainnet_address, &self.testnet_address]
+    }
+}
+
+/// Parameters of `registerScriptContract`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RegisterScriptContractQuery {
+    pub name: String,
+    /// Hex encoded bytecode of the programs, in tree order
+    pub programs: Vec<String>,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+impl RegisterScriptContractQuery {
+    /// Contract of the query, the root and addresses are recomputed from the programs.
+    pub fn to_contract(&self) -> Result<ScriptContract, String> {
+        let programs = self
+            .programs
+            .iter()
+            .enumerate()
+            .map(|(index, program)| {
+                hex::decode(program.trim())
+                    .map_err(|e| format!("Invalid program hex {}, {:?}", index, e))
+            })
+            .collect::<Result<Vec<Vec<u8>>, String>>()?;
+        ScriptContract::new(self.name.clone(), &programs, self.metadata.clone())
+    }
+}
+
+/// In-memory registry of the contracts, by root.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptRegistry {
+    pub contracts: BTreeMap<String, ScriptContract>,
+    // root of the contract of each script address
+    pub roots: HashMap<String, String>,
+}
+
+impl ScriptRegistry {
+    pub fn new() -> Self {
+        ScriptRegistry::default()
+    }
+
+    /// Adds a contract, replacing the name and metadata of a contract with the same root.
+    pub fn insert(&mut self, contract: ScriptContract) {
+        for address in contract.addresses() {
+            self.roots.insert(address.clone(), contract.root.clone());
+        }
+        self.contracts.insert(contract.root.clone(), contract);
+    }
+
+    /// Contract of a script address of either network.
+    pub fn get(&self, script_address: &str) -> Option<&ScriptContract> {
+        self.roots
+            .get(script_address)
+            .and_then(|root| self.contracts.get(root))
+    }
+
+    pub fn name(&self, script_address: &str) -> Option<String> {
+        self.get(script_address)
+            .map(|contract| contract.name.clone())
+    }
+
+    pub fn list(&self) -> Vec<ScriptContract> {
+        self.contracts.values().cloned().collect()
+    }
+}
+
+/// Adds a contract to the registry and stores it in psql.
+pub fn register_script_contract(contract: ScriptContract) {
+    SCRIPT_REGISTRY.lock().unwrap().insert(contract.clone());
+
+    /***************** POstgreSQL Insert Code *********/
+    let treadpool_sql_queue = THREADPOOL_SQL_QUEUE.lock().unwrap();
+    treadpool_sql_queue.execute(move || {
+        match insert_script_contract_in_psql(&contract) {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to insert_script_contract_in_psql: {}", e),
+        };
+    });
+    drop(treadpool_sql_queue);
+    /**************** POstgreSQL Insert Code End **********/
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use transaction::programs::{create_program_tree, program_tree_script_address};
+
+    fn relayer_contract() -> ScriptContract {
+        let programs: Vec<Vec<u8>> = create_program_tree()
+            .iter()
+            .map(|program| program.to_bytes())
+            .collect();
+        ScriptContract::new(
+            "relayer v1".to_string(),
+            &programs,
+            serde_json::json!({"version": 1}),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn script_contract_address_test() {
+        let contract = relayer_contract();
+        assert_eq!(
+            contract.mainnet_address,
+            program_tree_script_address(Network::Mainnet)
+        );
+        assert_eq!(
+            contract.testnet_address,
+            program_tree_script_address(Network::Testnet)
+        );
+        assert_eq!(contract.programs.len(), create_program_tree().len());
+    }
+
+    #[test]
+    fn script_registry_test() {
+        let mut registry = ScriptRegistry::new();
+        let contract = relayer_contract();
+        registry.insert(contract.clone());
+        assert_eq!(registry.get(&contract.testnet_address), Some(&contract));
+        assert_eq!(registry.get(&contract.mainnet_address), Some(&contract));
+        assert!(registry.get(&contract.root).is_none());
+
+        // same programs under a new name replace the entry
+        let mut renamed = contract.clone();
+        renamed.name = "relayer".to_string();
+        registry.insert(renamed);
+        assert_eq!(registry.list().len(), 1);
+        assert_eq!(
+            registry.name(&contract.mainnet_address),
+            Some("relayer".to_string())
+        );
+
+        let query = RegisterScriptContractQuery {
+            name: "relayer v1".to_string(),
+            programs: contract.programs.clone(),
+            metadata: serde_json::json!({"version": 1}),
+        };
+        assert_eq!(query.to_contract().unwrap(), contract);
+
+        assert!(ScriptContract::new("empty".to_string(), &[], serde_json::Value::Null).is_err());
+        assert!(
+            ScriptContract::new("bad".to_string(), &[vec![0xff]], serde_json::Value::Null).is_err()
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! Context of the utxo set, its side stores and its metrics, passed to block processing.
+//!
+//! The lazy_static globals of the crate, e.g. `UTXO_STORAGE` or `MEMPOOL`, are the
+//! stores of the [`global_state`] and are kept while callers move to an explicit
+//! [`UtxoState`]. A binary creates its state at startup and installs it with
+//! [`install_global_state`] before any global is used, tests create a state per case.
+//!
+//! The script registry and the prune logs stay process wide.
+//!
+//! A state tracks the chain of one [`Network`]. The primary state of a process writes
+//! to PostgreSQL and the snapshot files, a state of a secondary network, see
+//! [`UtxoState::secondary`], to the store it is created with and is loaded back from
+//! it with [`UtxoState::load_stored`], see [`crate::network`].
+
+use crate::blockoperations::addresstx::{AddressTxIndex, ADDRESS_TX_RETENTION};
+use crate::blockoperations::balanceindexer::BalanceIndexer;
+use crate::blockoperations::blockheader::{track_block_header_in, BlockHeader, ChainError};
+use crate::blockoperations::blockprocessing::Block;
+use crate::blockoperations::blockresult::{
+    AppliedTxIndex, BlockApplyResultStore, BLOCK_APPLY_RESULT_RETENTION,
+};
+use crate::blockoperations::blockstats::{BlockStatsHistory, BLOCK_STATS_RETENTION};
+use crate::blockoperations::blockweight::{max_block_weight, WeightViolation};
+use crate::blockoperations::failedtx::FailedTxStore;
+use crate::blockoperations::fees::{BlockFeeStore, BLOCK_FEES_RETENTION};
+use crate::blockoperations::mempool::Mempool;
+use crate::blockoperations::messages::{AppliedMessage, BurnStore};
+use crate::blockoperations::nullifiers::NullifierIndex;
+use crate::blockoperations::subscriptions::{
+    SubscriptionRegistry, SUBSCRIPTION_OUTBOX_CAPACITY, UTXO_EVENT_RETENTION,
+};
+use crate::blockoperations::txroot::{BlockTxRootStore, BLOCK_TX_ROOT_RETENTION};
+use crate::blockoperations::txstatus::{TxStatusStore, TX_STATUS_RETENTION};
+use crate::db::compaction::{CompactionConfig, CompactionReport};
+use crate::db::checkedfile::block_height_file;
+use crate::db::{LocalDBtrait, LocalStorage};
+use crate::network::network_path;
+use crate::pgsql::{new_sql_queue, persistence_store, PersistenceStore, StoredState};
+use crate::ThreadPool;
+use address::Network;
+use prometheus::core::Collector;
+use prometheus::{Counter, Gauge, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use zkvm::zkos_types::{IOType, Output};
+
+lazy_static! {
+    static ref INSTALLED_STATE: Mutex<Option<Arc<UtxoState>>> = Mutex::new(None);
+    static ref GLOBAL_STATE: Arc<UtxoState> = {
+        let mut installed = INSTALLED_STATE.lock().unwrap();
+        GLOBAL_STATE_USED.store(true, Ordering::SeqCst);
+        match installed.take() {
+            Some(state) => state,
+            None => {
+                let state = Arc::new(UtxoState::new());
+                if let Err(e) = state.metrics.register_default() {
+                    eprintln!("Failed to register the utxo metrics: {}", e);
+                }
+                state
+            }
+        }
+    };
+}
+
+static GLOBAL_STATE_USED: AtomicBool = AtomicBool::new(false);
+
+/// State behind the globals of the crate, the installed one or a new state on first use.
+pub fn global_state() -> Arc<UtxoState> {
+    GLOBAL_STATE.clone()
+}
+
+/// Makes `state` the state behind the globals and registers its metrics in the
+/// default registry. Fails once the global state is in use, returning `state`.
+pub fn install_global_state(state: Arc<UtxoState>) -> Result<(), Arc<UtxoState>> {
+    let mut installed = INSTALLED_STATE.lock().unwrap();
+    if GLOBAL_STATE_USED.load(Ordering::SeqCst) {
+        return Err(state);
+    }
+    if let Err(e) = state.metrics.register_default() {
+        eprintln!("Failed to register the utxo metrics: {}", e);
+    }
+    *installed = Some(state);
+    Ok(())
+}
+
+/// Prometheus metrics of a state, registered in its own registry and labeled with
+/// the network of the state.
+pub struct UtxoMetrics {
+    registry: Registry,
+    pub utxo_coin_count: Gauge,
+    pub utxo_memo_count: Gauge,
+    pub utxo_state_count: Gauge,
+    pub dark_sats_minted: Gauge,
+    pub transfer_tx_count: Gauge,
+    pub script_tx_count: Gauge,
+    pub failed_tx_count: Counter,
+    pub block_failed_tx: Gauge,
+    pub fees_collected: Counter,
+    pub burned: Counter,
+    pub block_weight: Gauge,
+    pub max_block_weight: Gauge,
+    pub block_weight_violations: Counter,
+    pub compaction_runs: Counter,
+    pub compaction_bytes_reclaimed: Counter,
+}
+
+impl UtxoMetrics {
+    /// Metrics of the default network.
+    pub fn new() -> Self {
+        UtxoMetrics::for_network(Network::default())
+    }
+
+    pub fn for_network(network: Network) -> Self {
+        let registry = Registry::new();
+        let opts = |name: &str, help: &str| {
+            Opts::new(name, help).const_label("network", network.as_str())
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = Gauge::with_opts(opts(name, help)).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+        let counter = |name: &str, help: &str| {
+            let counter = Counter::with_opts(opts(name, help)).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        UtxoMetrics {
+            utxo_coin_count: gauge("utxo_coin_count", "A counter for coin utxo"),
+            utxo_memo_count: gauge("utxo_memo_count", "A counter for memo utxo"),
+            utxo_state_count: gauge("utxo_state_count", "A counter for state utxo"),
+            dark_sats_minted: gauge("dark_sats_minted", "A counter for dark Sats minted"),
+            transfer_tx_count: gauge("transfer_tx_count", "A counter for transfer tx"),
+            script_tx_count: gauge("script_tx_count", "A counter for script tx"),
+            failed_tx_count: counter(
+                "failed_tx_count",
+                "A counter for block txs that failed to apply",
+            ),
+            block_failed_tx: gauge(
+                "block_failed_tx",
+                "Txs of the last processed block that failed to apply",
+            ),
+            fees_collected: counter("total_fees_collected", "A counter for fees collected"),
+            burned: counter("total_burned", "A counter for dark Sats burned"),
+            block_weight: gauge("block_weight", "Weight of the last processed block"),
+            max_block_weight: gauge("max_block_weight", "Weight limit of a block"),
+            block_weight_violations: counter(
+                "block_weight_violations",
+                "Blocks rejected for exceeding the weight limit",
+            ),
+            compaction_runs: counter(
+                "utxo_compaction_runs",
+                "Compactions of the utxo storage",
+            ),
+            compaction_bytes_reclaimed: counter(
+                "utxo_compaction_bytes_reclaimed",
+                "Approximate bytes reclaimed by the compactions of the utxo storage",
+            ),
+            registry,
+        }
+    }
+
+    /// Registry holding only the metrics of this state.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Registers the metrics in the default registry, served by the telemetry server.
+    /// Only one state of a network can be registered.
+    pub fn register_default(&self) -> Result<(), prometheus::Error> {
+        let collectors: Vec<Box<dyn Collector>> = vec![
+            Box::new(self.utxo_coin_count.clone()),
+            Box::new(self.utxo_memo_count.clone()),
+            Box::new(self.utxo_state_count.clone()),
+            Box::new(self.dark_sats_minted.clone()),
+            Box::new(self.transfer_tx_count.clone()),
+            Box::new(self.script_tx_count.clone()),
+            Box::new(self.failed_tx_count.clone()),
+            Box::new(self.block_failed_tx.clone()),
+            Box::new(self.fees_collected.clone()),
+            Box::new(self.burned.clone()),
+            Box::new(self.block_weight.clone()),
+            Box::new(self.max_block_weight.clone()),
+            Box::new(self.block_weight_violations.clone()),
+            Box::new(self.compaction_runs.clone()),
+            Box::new(self.compaction_bytes_reclaimed.clone()),
+        ];
+        for collector in collectors {
+            prometheus::register(collector)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the utxo gauges to the counts of `utxo_storage`.
+    pub fn set_utxo_counts(&self, utxo_storage: &mut LocalStorage<Output>) {
+        self.utxo_coin_count
+            .set(utxo_storage.get_count_by_type(IOType::Coin as usize) as f64);
+        self.utxo_memo_count
+            .set(utxo_storage.get_count_by_type(IOType::Memo as usize) as f64);
+        self.utxo_state_count
+            .set(utxo_storage.get_count_by_type(IOType::State as usize) as f64);
+    }
+}
+
+impl Default for UtxoMetrics {
+    fn default() -> Self {
+        UtxoMetrics::new()
+    }
+}
+
+/// Utxo set and the stores updated with it by block processing.
+pub struct UtxoState {
+    pub utxo_storage: Arc<Mutex<LocalStorage<Output>>>,
+    pub mempool: Arc<Mutex<Mempool>>,
+    pub failed_txs: Arc<Mutex<FailedTxStore>>,
+    pub address_txs: Arc<Mutex<AddressTxIndex>>,
+    pub block_tx_roots: Arc<Mutex<BlockTxRootStore>>,
+    pub block_fees: Arc<Mutex<BlockFeeStore>>,
+    pub block_results: Arc<Mutex<BlockApplyResultStore>>,
+    /// Txids applied by the latest blocks, see [`AppliedTxIndex`]
+    pub applied_txs: Arc<Mutex<AppliedTxIndex>>,
+    pub burn_history: Arc<Mutex<BurnStore>>,
+    pub applied_messages: Arc<Mutex<HashMap<String, AppliedMessage>>>,
+    pub subscriptions: Arc<Mutex<SubscriptionRegistry>>,
+    /// Nullifiers spent by the latest blocks, see [`crate::blockoperations::nullifiers`]
+    pub nullifiers: Arc<Mutex<NullifierIndex>>,
+    /// Status transitions of the submitted txs, see [`crate::blockoperations::txstatus`]
+    pub tx_statuses: Arc<Mutex<TxStatusStore>>,
+    /// Timings of the latest blocks, see [`crate::blockoperations::blockstats`]
+    pub block_stats: Arc<Mutex<BlockStatsHistory>>,
+    /// Balances of the indexed addresses of the network, see
+    /// [`crate::blockoperations::balanceindexer`]
+    pub balance_indexer: Arc<Mutex<BalanceIndexer>>,
+    /// Queue of the writes to the persistence store
+    pub sql_queue: Arc<Mutex<ThreadPool>>,
+    pub metrics: UtxoMetrics,
+    /// Block rejected for its weight, no block is applied while set
+    pub weight_halt: Arc<Mutex<Option<WeightViolation>>>,
+    /// Blocks received while halted, the rejected block first. Locked for the whole
+    /// application of a block, so blocks are applied one at a time
+    pub halted_blocks: Arc<Mutex<Vec<Block>>>,
+    /// Header of the last block received by the oracle subscriber
+    pub last_block_header: Arc<Mutex<Option<BlockHeader>>>,
+    network: Network,
+    // false for a secondary network, see `UtxoState::secondary`
+    primary: bool,
+    max_block_weight: AtomicU64,
+    // the process wide store of `persistence_store` if None
+    store: Option<Arc<dyn PersistenceStore>>,
+}
+
+impl UtxoState {
+    /// Empty state of the default network writing to the store of `persistence_store`.
+    pub fn new() -> Self {
+        UtxoState::for_network(Network::default())
+    }
+
+    /// Empty primary state of `network` writing to the store of `persistence_store`.
+    pub fn for_network(network: Network) -> Self {
+        let metrics = UtxoMetrics::for_network(network);
+        let max_block_weight = max_block_weight();
+        metrics.max_block_weight.set(max_block_weight as f64);
+        UtxoState {
+            utxo_storage: Arc::new(Mutex::new(LocalStorage::<Output>::new(3))),
+            mempool: Arc::new(Mutex::new(Mempool::from_config())),
+            failed_txs: Arc::new(Mutex::new(FailedTxStore::new())),
+            address_txs: Arc::new(Mutex::new(AddressTxIndex::new(ADDRESS_TX_RETENTION))),
+            block_tx_roots: Arc::new(Mutex::new(BlockTxRootStore::new(BLOCK_TX_ROOT_RETENTION))),
+            block_fees: Arc::new(Mutex::new(BlockFeeStore::new(BLOCK_FEES_RETENTION))),
+            block_results: Arc::new(Mutex::new(BlockApplyResultStore::new(
+                BLOCK_APPLY_RESULT_RETENTION,
+            ))),
+            applied_txs: Arc::new(Mutex::new(AppliedTxIndex::new(
+                BLOCK_APPLY_RESULT_RETENTION,
+            ))),
+            burn_history: Arc::new(Mutex::new(BurnStore::new())),
+            applied_messages: Arc::new(Mutex::new(HashMap::new())),
+            subscriptions: Arc::new(Mutex::new(SubscriptionRegistry::new(
+                SUBSCRIPTION_OUTBOX_CAPACITY,
+                UTXO_EVENT_RETENTION,
+            ))),
+            nullifiers: Arc::new(Mutex::new(NullifierIndex::from_config())),
+            tx_statuses: Arc::new(Mutex::new(TxStatusStore::new(TX_STATUS_RETENTION))),
+            block_stats: Arc::new(Mutex::new(BlockStatsHistory::new(BLOCK_STATS_RETENTION))),
+            balance_indexer: Arc::new(Mutex::new(BalanceIndexer::from_config())),
+            sql_queue: Arc::new(Mutex::new(new_sql_queue())),
+            metrics,
+            weight_halt: Arc::new(Mutex::new(None)),
+            halted_blocks: Arc::new(Mutex::new(Vec::new())),
+            last_block_header: Arc::new(Mutex::new(None)),
+            network,
+            primary: true,
+            max_block_weight: AtomicU64::new(max_block_weight),
+            store: None,
+        }
+    }
+
+    /// Empty state writing to `store`, e.g. a `MemoryStore` in tests.
+    pub fn with_store(store: Arc<dyn PersistenceStore>) -> Self {
+        UtxoState {
+            store: Some(store),
+            ..UtxoState::new()
+        }
+    }
+
+    /// Empty state of the secondary network `network` writing to `store`. Its block
+    /// height file is the one of the primary state suffixed with the network, and it
+    /// takes no snapshots, see [`crate::network`].
+    pub fn secondary(network: Network, store: Arc<dyn PersistenceStore>) -> Self {
+        UtxoState {
+            store: Some(store),
+            primary: false,
+            ..UtxoState::for_network(network)
+        }
+    }
+
+    /// Network of the chain tracked by this state.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Whether this is the primary state of the process, see [`UtxoState::secondary`].
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+
+    /// Path of the block height file of this state.
+    pub fn block_height_file(&self) -> String {
+        if self.primary {
+            block_height_file()
+        } else {
+            network_path(&block_height_file(), self.network)
+        }
+    }
+
+    /// Validates `header` against the last block received for this state and tracks it,
+    /// see [`crate::blockoperations::blockheader`].
+    pub fn track_block_header(&self, header: BlockHeader) -> Result<(), ChainError> {
+        track_block_header_in(&self.last_block_header, header)
+    }
+
+    /// Store the block processing of this state writes to.
+    pub fn store(&self) -> Arc<dyn PersistenceStore> {
+        match &self.store {
+            Some(store) => store.clone(),
+            None => persistence_store(),
+        }
+    }
+
+    /// Weight limit of the blocks applied to this state.
+    pub fn max_block_weight(&self) -> u64 {
+        self.max_block_weight.load(Ordering::SeqCst)
+    }
+
+    pub fn set_max_block_weight(&self, limit: u64) {
+        self.max_block_weight.store(limit, Ordering::SeqCst);
+        self.metrics.max_block_weight.set(limit as f64);
+    }
+
+    /// Compacts the utxo storage and the address index, see [`crate::db::compaction`].
+    /// With a `config` only the partitions due for a compaction are compacted, and the
+    /// address index only along with a partition.
+    pub fn compact_storage(&self, config: Option<&CompactionConfig>) -> CompactionReport {
+        let mut report = CompactionReport::default();
+        {
+            let mut utxo_storage = self.utxo_storage.lock().unwrap();
+            report.epoch = utxo_storage.epoch;
+            report.partitions = match config {
+                Some(config) => utxo_storage.compact_if_needed(config),
+                None => utxo_storage.compact(),
+            };
+        }
+        if config.is_some() && report.partitions.is_empty() {
+            return report;
+        }
+        report.address_index_bytes = self.address_txs.lock().unwrap().compact();
+        report.bytes_reclaimed = report.address_index_bytes
+            + report
+                .partitions
+                .iter()
+                .map(|partition| partition.bytes_reclaimed)
+                .sum::<u64>();
+        self.metrics.compaction_runs.inc();
+        self.metrics
+            .compaction_bytes_reclaimed
+            .inc_by(report.bytes_reclaimed as f64);
+        report
+    }
+
+    /// Block that halted the block processing, if any.
+    pub fn weight_halt(&self) -> Option<WeightViolation> {
+        self.weight_halt.lock().unwrap().clone()
+    }
+
+    /// Restores the rows of `stored`, read from the store of this state at startup, see
+    /// [`PersistenceStore::load_state`].
+    pub fn load_stored(&self, stored: StoredState) {
+        {
+            let mut utxo_storage = self.utxo_storage.lock().unwrap();
+            for entry in stored.utxos {
+                let io_type = entry.output.out_type as usize;
+                if let Err(e) = utxo_storage.add(entry.keyid, entry.output, io_type) {
+                    eprintln!("Failed to load a utxo of {}: {}", self.network, e);
+                }
+            }
+            utxo_storage.block_height = stored.block_height.unwrap_or(0) as usize;
+        }
+        let mut block_tx_roots = self.block_tx_roots.lock().unwrap();
+        for root in stored.block_tx_roots {
+            block_tx_roots.insert(root);
+        }
+        let mut block_fees = self.block_fees.lock().unwrap();
+        for fees in stored.block_fees {
+            block_fees.insert(fees);
+        }
+        let mut block_results = self.block_results.lock().unwrap();
+        for result in stored.block_results {
+            block_results.insert(result);
+        }
+        let mut nullifiers = self.nullifiers.lock().unwrap();
+        for block in stored.block_nullifiers.iter() {
+            nullifiers.insert_block(block);
+        }
+        let mut burn_history = self.burn_history.lock().unwrap();
+        for burn in stored.burns {
+            let amount = burn.amount;
+            if burn_history.insert(burn) {
+                self.metrics.burned.inc_by(amount as f64);
+            }
+        }
+        let mut address_txs = self.address_txs.lock().unwrap();
+        for entry in stored.address_txs {
+            address_txs.insert(entry);
+        }
+        let mut failed_txs = self.failed_txs.lock().unwrap();
+        for failed_tx in stored.failed_txs {
+            failed_txs.failed.insert(failed_tx.txid.clone(), failed_tx);
+        }
+        self.metrics
+            .set_utxo_counts(&mut self.utxo_storage.lock().unwrap());
+    }
+}
+
+impl Default for UtxoState {
+    fn default() -> Self {
+        UtxoState::new()
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pgsql::MemoryStore;
+
+    #[test]
+    fn utxo_metrics_registry_test() {
+        let first = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        let second = UtxoState::with_store(Arc::new(MemoryStore::new()));
+        first.metrics.transfer_tx_count.inc();
+        first.metrics.fees_collected.inc_by(5.0);
+
+        // each state gathers only its own metrics
+        let value = |state: &UtxoState, name: &str| {
+            state
+                .metrics
+                .registry()
+                .gather()
+                .into_iter()
+                .find(|family| family.get_name() == name)
+                .map(|family| {
+                    let metric = &family.get_metric()[0];
+                    metric.get_gauge().get_value() + metric.get_counter().get_value()
+                })
+                .unwrap()
+        };
+        assert_eq!(value(&first, "transfer_tx_count"), 1.0);
+        assert_eq!(value(&second, "transfer_tx_count"), 0.0);
+        assert_eq!(value(&first, "total_fees_collected"), 5.0);
+        assert_eq!(second.metrics.registry().gather().len(), 15);
+
+        // the global state is in use once a global is read
+        let _ = global_state();
+        let third = Arc::new(UtxoState::new());
+        assert!(install_global_state(third).is_err());
+    }
+}
@@ -1,11 +1,53 @@
+use crate::config::config_value;
+use prometheus::{register_gauge_vec, Gauge, GaugeVec};
+use std::fmt;
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
 
+/// Number of jobs a pool queues before `execute` blocks and `try_execute` fails.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 10_000;
+
+lazy_static! {
+    static ref THREADPOOL_QUEUE_DEPTH: GaugeVec = register_gauge_vec!(
+        "utxo_threadpool_queue_depth",
+        "Jobs waiting in the queue of a thread pool",
+        &["pool"]
+    )
+    .unwrap();
+}
+
+/// Reads a pool setting from the config, falling back to `default`
+/// if the value is missing, not a number or zero.
+pub fn env_or(key: &str, default: usize) -> usize {
+    match config_value(key).and_then(|v| v.trim().parse::<usize>().ok()) {
+        Some(value) if value > 0 => value,
+        _ => default,
+    }
+}
+
+/// Returned by `try_execute` when the job queue of the pool is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull;
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "thread pool queue is full")
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
+    sender: mpsc::SyncSender<Message>,
+    receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+    // number of running workers, i.e. workers not yet told to terminate
+    size: usize,
+    next_id: usize,
+    t_name: String,
+    queue_depth: Gauge,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
@@ -24,55 +66,114 @@ impl ThreadPool {
     ///
     /// The `new` function will panic if the size is zero.
     pub fn new(size: usize, t_name: String) -> ThreadPool {
+        ThreadPool::with_capacity(size, DEFAULT_QUEUE_CAPACITY, t_name)
+    }
+
+    /// Create a new ThreadPool queueing at most `capacity` jobs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size or the capacity is zero.
+    pub fn with_capacity(size: usize, capacity: usize, t_name: String) -> ThreadPool {
         assert!(size > 0);
+        assert!(capacity > 0);
 
-        let (sender, receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::sync_channel(capacity);
 
         let receiver = Arc::new(Mutex::new(receiver));
+        let queue_depth = THREADPOOL_QUEUE_DEPTH.with_label_values(&[&t_name]);
 
-        let mut workers = Vec::with_capacity(size);
-
-        for id in 0..size {
-            workers.push(Worker::new(id, t_name.clone(), Arc::clone(&receiver)));
-        }
-
-        ThreadPool { workers, sender }
+        let mut pool = ThreadPool {
+            workers: Vec::with_capacity(size),
+            sender,
+            receiver,
+            size: 0,
+            next_id: 0,
+            t_name,
+            queue_depth,
+        };
+        pool.resize(size);
+        pool
     }
 
+    /// Queues a job, blocking while the queue is full.
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
 
-        self.sender.send(Message::NewJob(job)).unwrap();
+        match self.sender.try_send(Message::NewJob(job)) {
+            Ok(_) => self.queue_depth.inc(),
+            Err(mpsc::TrySendError::Full(message)) => {
+                eprintln!("{} queue is full, waiting for a free slot", self.t_name);
+                self.sender.send(message).unwrap();
+                self.queue_depth.inc();
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                panic!("{} workers are shut down", self.t_name)
+            }
+        }
     }
 
-    pub fn shutdown(&mut self) {
-        println!("Sending terminate message to all workers.");
+    /// Queues a job, failing with `QueueFull` instead of blocking.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), QueueFull>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
 
-        for _ in &self.workers {
-            self.sender.send(Message::Terminate).unwrap();
+        match self.sender.try_send(Message::NewJob(job)) {
+            Ok(_) => {
+                self.queue_depth.inc();
+                Ok(())
+            }
+            Err(_) => Err(QueueFull),
         }
+    }
 
-        println!("Shutting down all workers.");
+    /// Number of jobs waiting in the queue.
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.get() as usize
+    }
 
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
+    /// Number of running workers.
+    pub fn size(&self) -> usize {
+        self.size
+    }
 
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
+    /// Changes the number of workers.
+    /// Removed workers finish the jobs queued before the call, then exit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size is zero.
+    pub fn resize(&mut self, size: usize) {
+        assert!(size > 0);
+
+        while self.size < size {
+            self.workers.push(Worker::new(
+                self.next_id,
+                self.t_name.clone(),
+                Arc::clone(&self.receiver),
+                self.queue_depth.clone(),
+            ));
+            self.next_id += 1;
+            self.size += 1;
+        }
+        while self.size > size {
+            self.sender.send(Message::Terminate).unwrap();
+            self.size -= 1;
         }
     }
-}
 
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
+    /// Stops the workers once every queued job has run.
+    pub fn shutdown(&mut self) {
         println!("Sending terminate message to all workers.");
 
-        for _ in &self.workers {
+        while self.size > 0 {
             self.sender.send(Message::Terminate).unwrap();
+            self.size -= 1;
         }
 
         println!("Shutting down all workers.");
@@ -87,13 +188,24 @@ impl Drop for ThreadPool {
     }
 }
 
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
 struct Worker {
     id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
 
 impl Worker {
-    fn new(id: usize, t_name: String, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(
+        id: usize,
+        t_name: String,
+        receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+        queue_depth: Gauge,
+    ) -> Worker {
         let thread = thread::Builder::new()
             .name(format!("{}-{}", t_name, id))
             .spawn(move || loop {
@@ -102,6 +214,7 @@ impl Worker {
                 match message {
                     Message::NewJob(job) => {
                         // println!("Worker {} got a job; executing.", id);
+                        queue_depth.dec();
 
                         job();
                     }
@@ -119,3 +232,71 @@ impl Worker {
         }
     }
 }
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn queue_full_test() {
+        let pool = ThreadPool::with_capacity(1, 2, String::from("TEST_QUEUE_FULL"));
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Arc::new(Mutex::new(blocked));
+
+        // the worker holds the first job until released
+        let (started, wait_started) = mpsc::channel();
+        let held = Arc::clone(&blocked);
+        pool.execute(move || {
+            started.send(()).unwrap();
+            held.lock().unwrap().recv().unwrap();
+        });
+        wait_started.recv().unwrap();
+
+        assert!(pool.try_execute(|| {}).is_ok());
+        assert!(pool.try_execute(|| {}).is_ok());
+        assert_eq!(pool.try_execute(|| {}), Err(QueueFull));
+        assert_eq!(pool.queue_depth(), 2);
+
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn graceful_drain_on_shutdown_test() {
+        let mut pool = ThreadPool::with_capacity(2, 100, String::from("TEST_DRAIN"));
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..50 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(1));
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        pool.shutdown();
+        assert_eq!(done.load(Ordering::SeqCst), 50);
+        assert_eq!(pool.queue_depth(), 0);
+    }
+
+    #[test]
+    fn resize_test() {
+        let mut pool = ThreadPool::new(1, String::from("TEST_RESIZE"));
+        pool.resize(4);
+        assert_eq!(pool.size(), 4);
+        pool.resize(2);
+        assert_eq!(pool.size(), 2);
+
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..10 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        pool.shutdown();
+        assert_eq!(done.load(Ordering::SeqCst), 10);
+    }
+}
@@ -90,7 +90,7 @@ impl UTXO {
         output_index: usize,
     ) -> Self {
         UTXO::new(
-            bincode::serialize(&Utxo::new(txid, output_index as u8)).unwrap(),
+            bincode::serialize(&Utxo::new(txid, output_index as u16)).unwrap(),
             bincode::serialize(&output).unwrap(),
             TxInputOutputType::convert_output_type(output.out_type),
         )
@@ -1,16 +1,19 @@
 //! Constraint system-related types and operations:
 //! Commitments, Variables, Expressions and Constraints.
 
-use bulletproofs::{r1cs, r1cs::ConstraintSystem, PedersenGens};
+use bulletproofs::{r1cs, r1cs::ConstraintSystem};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::scalar::Scalar;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::iter::FromIterator;
 use std::ops::{Add, Neg};
 use subtle::{ConditionallySelectable, ConstantTimeEq};
 
 use crate::encoding::*;
 use crate::errors::VMError;
+use crate::proof_context::ProofContext;
 use crate::scalar_witness::ScalarWitness;
 
 /// Variable represents a high-level R1CS variable specified by its
@@ -81,10 +84,14 @@ pub enum Commitment {
 }
 
 /// Prover's representation of the commitment secret: witness and blinding factor
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct CommitmentWitness {
     value: ScalarWitness,
     blinding: Scalar,
+    /// Commitment point, computed on the first `to_point` call.
+    /// Not serialized and ignored by comparisons.
+    #[serde(skip)]
+    point: OnceCell<CompressedRistretto>,
 }
 
 impl Constraint {
@@ -285,6 +292,7 @@ impl ExactSizeEncodable for Commitment {
 }
 impl Commitment {
     /// Converts a Commitment to a compressed point.
+    /// The point of an open commitment is computed on the first call and reused.
     pub fn to_point(&self) -> CompressedRistretto {
         match self {
             Commitment::Closed(x) => *x,
@@ -294,26 +302,20 @@ impl Commitment {
 
     /// Creates an open commitment with a zero blinding factor.
     pub fn unblinded<T: Into<ScalarWitness>>(x: T) -> Self {
-        Commitment::Open(Box::new(CommitmentWitness {
-            blinding: Scalar::zero(),
-            value: x.into(),
-        }))
+        Commitment::Open(Box::new(CommitmentWitness::new(x.into(), Scalar::zero())))
     }
 
     /// Creates an open commitment with a random blinding factor.
     pub fn blinded<T: Into<ScalarWitness>>(x: T) -> Self {
-        Commitment::Open(Box::new(CommitmentWitness {
-            blinding: Scalar::random(&mut rand::thread_rng()),
-            value: x.into(),
-        }))
+        Commitment::Open(Box::new(CommitmentWitness::new(
+            x.into(),
+            Scalar::random(&mut rand::thread_rng()),
+        )))
     }
 
     /// Creates an open commitment with a specified blinding factor.
     pub fn blinded_with_factor<T: Into<ScalarWitness>>(x: T, blinding: Scalar) -> Self {
-        Commitment::Open(Box::new(CommitmentWitness {
-            blinding,
-            value: x.into(),
-        }))
+        Commitment::Open(Box::new(CommitmentWitness::new(x.into(), blinding)))
     }
 
     /// Returns a pair of secrets: the committed scalar or integer, and the blinding factor
@@ -336,9 +338,35 @@ impl Commitment {
 }
 
 impl CommitmentWitness {
+    fn new(value: ScalarWitness, blinding: Scalar) -> Self {
+        CommitmentWitness {
+            value,
+            blinding,
+            point: OnceCell::new(),
+        }
+    }
+
+    /// Converts the witness to a compressed point, the scalar multiplication is done once.
     fn to_point(&self) -> CompressedRistretto {
-        let gens = PedersenGens::default();
-        gens.commit(self.value.into(), self.blinding).compress()
+        *self.point.get_or_init(|| {
+            let gens = ProofContext::global().pc_gens();
+            gens.commit(self.value.into(), self.blinding).compress()
+        })
+    }
+}
+
+impl PartialEq for CommitmentWitness {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value && self.blinding == other.blinding
+    }
+}
+
+impl fmt::Debug for CommitmentWitness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommitmentWitness")
+            .field("value", &self.value)
+            .field("blinding", &self.blinding)
+            .finish()
     }
 }
 
@@ -411,6 +439,13 @@ impl Expression {
         }
     }
 
+    /// Returns the secret assignment to this expression,
+    /// based on the assignments to the underlying variables.
+    /// Returns `None` if any underlying variable does not have an assignment.
+    pub fn assignment(&self) -> Option<ScalarWitness> {
+        self.eval()
+    }
+
     /// Evaluates the expression using its optional scalar witness data.
     /// Returns None if there is no witness.
     fn eval(&self) -> Option<ScalarWitness> {
@@ -502,6 +537,7 @@ impl Into<CompressedRistretto> for Commitment {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bulletproofs::PedersenGens;
     use merlin::Transcript;
 
     #[test]
@@ -617,6 +653,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn commitment_point_cache() {
+        let blinding = Scalar::from(7u64);
+        let expected = PedersenGens::default()
+            .commit(Scalar::from(5u64), blinding)
+            .compress();
+        let commitment = Commitment::blinded_with_factor(5u64, blinding);
+        let fresh = commitment.clone();
+        assert_eq!(commitment.to_point(), expected);
+        // clones carry the computed point, and the point is not compared
+        assert_eq!(commitment.clone().to_point(), expected);
+        assert_eq!(commitment, fresh);
+        // the point is not serialized
+        let bytes = bincode::serialize(&commitment).unwrap();
+        assert_eq!(bytes, bincode::serialize(&fresh).unwrap());
+        let decoded: Commitment = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, commitment);
+        assert_eq!(decoded.to_point(), expected);
+        assert_eq!(format!("{:?}", commitment), format!("{:?}", fresh));
+    }
+
     #[test]
     fn constraints_arithmetic() {
         // eq(const, const) => cleartext(true)
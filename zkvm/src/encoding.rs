@@ -1,5 +1,6 @@
 //! Encoding utils for ZkVM
-//! All methods err using VMError::InvalidFormat for convenience.
+//! All methods err using VMError::InvalidFormat for convenience,
+//! except the hex helpers which describe the malformed input.
 
 use crate::errors::VMError;
 
@@ -89,6 +90,60 @@ pub trait WriterExt: Writer {
     }
 }
 
+/// Decodes a hex string, reporting an odd length or the position of the first
+/// invalid character.
+pub fn bytes_from_hex(hex_str: &str) -> Result<Vec<u8>, VMError> {
+    if let Some((position, c)) = hex_str.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(VMError::InvalidHex(format!(
+            "invalid character {:?} at position {}",
+            c, position
+        )));
+    }
+    if hex_str.len() % 2 != 0 {
+        return Err(VMError::InvalidHex(format!("odd length {}", hex_str.len())));
+    }
+    hex::decode(hex_str).map_err(|e| VMError::InvalidHex(e.to_string()))
+}
+
+/// Decodes the hex string of exactly 32 bytes.
+pub fn bytes32_from_hex(hex_str: &str) -> Result<[u8; 32], VMError> {
+    let bytes = bytes_from_hex(hex_str)?;
+    let len = bytes.len();
+    let mut buf = [0u8; 32];
+    if len != buf.len() {
+        return Err(VMError::InvalidHex(format!(
+            "expected 32 bytes, found {}",
+            len
+        )));
+    }
+    buf.copy_from_slice(&bytes);
+    Ok(buf)
+}
+
+/// Decodes a scalar from the hex of its 32 bytes.
+/// Fails with `NonCanonicalScalar` instead of reducing a value not below the group order.
+pub fn scalar_from_hex_canonical(hex_str: &str) -> Result<Scalar, VMError> {
+    Scalar::from_canonical_bytes(bytes32_from_hex(hex_str)?).ok_or(VMError::NonCanonicalScalar)
+}
+
+/// Hex of the 32 bytes of a scalar.
+pub fn scalar_to_hex(scalar: &Scalar) -> String {
+    hex::encode(scalar.as_bytes())
+}
+
+/// Decodes a compressed Ristretto point from hex.
+/// Fails with `InvalidPoint` if the bytes do not decompress to a point.
+pub fn point_from_hex(hex_str: &str) -> Result<CompressedRistretto, VMError> {
+    let point = CompressedRistretto(bytes32_from_hex(hex_str)?);
+    point.decompress().ok_or(VMError::InvalidPoint)?;
+    Ok(point)
+}
+
+/// Hex of a compressed Ristretto point.
+pub fn point_to_hex(point: &CompressedRistretto) -> String {
+    hex::encode(point.as_bytes())
+}
+
 impl<T> ReaderExt for T where T: Reader {}
 impl<T> WriterExt for T where T: Writer {}
 
@@ -103,3 +158,63 @@ impl From<WriteError> for VMError {
         VMError::InvalidFormat
     }
 }
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+
+    // group order l = 2^252 + 27742317777372353535851937790883648493, little endian
+    const GROUP_ORDER_HEX: &str =
+        "edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010";
+
+    #[test]
+    fn scalar_hex_test() {
+        let scalar = Scalar::from(1234567u64);
+        let hex_str = scalar_to_hex(&scalar);
+        assert_eq!(hex_str.len(), 64);
+        assert_eq!(scalar_from_hex_canonical(&hex_str), Ok(scalar));
+
+        // l and the largest 32 byte value are not reduced
+        assert_eq!(
+            scalar_from_hex_canonical(GROUP_ORDER_HEX),
+            Err(VMError::NonCanonicalScalar)
+        );
+        assert_eq!(
+            scalar_from_hex_canonical(&"ff".repeat(32)),
+            Err(VMError::NonCanonicalScalar)
+        );
+        // l - 1 is canonical
+        let l_minus_one = GROUP_ORDER_HEX.replacen("ed", "ec", 1);
+        assert_eq!(scalar_from_hex_canonical(&l_minus_one), Ok(-Scalar::one()));
+    }
+
+    #[test]
+    fn invalid_hex_test() {
+        match scalar_from_hex_canonical("abc") {
+            Err(VMError::InvalidHex(reason)) => assert!(reason.contains("odd length 3")),
+            other => panic!("unexpected {:?}", other),
+        }
+        match point_from_hex("00zz") {
+            Err(VMError::InvalidHex(reason)) => assert!(reason.contains("position 2")),
+            other => panic!("unexpected {:?}", other),
+        }
+        match scalar_from_hex_canonical("0011") {
+            Err(VMError::InvalidHex(reason)) => assert!(reason.contains("found 2")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn point_hex_test() {
+        let hex_str = point_to_hex(&RISTRETTO_BASEPOINT_COMPRESSED);
+        assert_eq!(point_from_hex(&hex_str), Ok(RISTRETTO_BASEPOINT_COMPRESSED));
+        // the identity is a valid point
+        assert!(point_from_hex(&"00".repeat(32)).is_ok());
+        // not a field element encoding of a point
+        assert_eq!(point_from_hex(&"ff".repeat(32)), Err(VMError::InvalidPoint));
+    }
+}
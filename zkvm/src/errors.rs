@@ -1,5 +1,6 @@
 //! Errors related to proving and verifying proofs.
 use bulletproofs::r1cs::R1CSError;
+use core::fmt;
 
 use thiserror::Error;
 
@@ -219,4 +220,144 @@ pub enum VMError {
     /// This error occurs when tx attempts to convert Witness into SigmaProof.
     #[error("Witness is not a sigma proof")]
     TypeNotSigmaProof,
+
+    /// This error occurs when tx attempts to convert Witness into a zero balance proof.
+    #[error("Witness is not a zero balance proof")]
+    TypeNotZeroBalanceProof,
+
+    /// This error occurs when tx attempts to convert Witness into MultisigWitness.
+    #[error("Witness is not a Multisig Witness")]
+    TypeNotMultisigWitness,
+
+    /// This error occurs when an output encryption is not a pair of valid Ristretto points.
+    #[error("Output encryption is not a valid ElGamal commitment")]
+    InvalidOutputEncryption,
+
+    /// This error occurs when an output commitment is not a valid Ristretto point.
+    #[error("Output commitment is not a valid point")]
+    InvalidOutputCommitment,
+
+    /// This error occurs when an output owner or script address can not be parsed.
+    #[error("Output address is invalid")]
+    InvalidOutputAddress,
+
+    /// This error occurs when the type of an output does not match the variant of its data.
+    #[error("Output type does not match its data")]
+    OutputTypeMismatch,
+
+    /// This error occurs when the data of a memo or the state variables of a state exceed a limit.
+    #[error("Output {which} is {size}, above the limit of {limit}")]
+    DataTooLarge {
+        which: &'static str,
+        size: usize,
+        limit: usize,
+    },
+
+    /// This error occurs when a hex string is malformed. Carries what is wrong and where.
+    #[error("Invalid hex: {0}")]
+    InvalidHex(String),
+
+    /// This error occurs when a scalar encoding is not reduced modulo the group order.
+    #[error("Scalar is not canonical")]
+    NonCanonicalScalar,
+
+    /// This error occurs when script verification fails. Carries the failure diagnostics.
+    #[error("Script verification failed: {0}")]
+    VerificationFailed(VerificationFailure),
+}
+
+/// Phase of the script verification in which a failure occurred.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerificationPhase {
+    /// Proof or program bytes could not be decoded.
+    Decode,
+    /// Stack could not be initialized with the tx inputs and outputs.
+    StackInit,
+    /// An instruction failed while running the program.
+    Execution,
+    /// The R1CS proof does not satisfy the constraint system.
+    ConstraintSystem,
+}
+
+/// Diagnostics of a failed script verification.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerificationFailure {
+    /// Phase in which verification failed.
+    pub phase: VerificationPhase,
+    /// Index of the failing instruction, counted across nested programs.
+    pub instruction_index: Option<usize>,
+    /// Opcode of the failing instruction.
+    pub opcode: Option<u8>,
+    /// Number of items on the VM stack at failure.
+    pub stack_depth: usize,
+    /// Index of the failing gadget, if reported by the constraint system.
+    pub gadget_index: Option<usize>,
+    /// Underlying error.
+    pub cause: Box<VMError>,
+}
+
+impl VerificationFailure {
+    /// Creates a failure for the given phase and cause without instruction details.
+    pub fn new(phase: VerificationPhase, stack_depth: usize, cause: VMError) -> Self {
+        VerificationFailure {
+            phase,
+            instruction_index: None,
+            opcode: None,
+            stack_depth,
+            gadget_index: None,
+            cause: Box::new(cause),
+        }
+    }
+}
+
+impl fmt::Display for VerificationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} phase", self.phase)?;
+        if let Some(index) = self.instruction_index {
+            write!(f, ", instruction {}", index)?;
+        }
+        if let Some(opcode) = self.opcode {
+            write!(f, ", opcode 0x{:02x}", opcode)?;
+        }
+        write!(f, ", stack depth {}", self.stack_depth)?;
+        if let Some(gadget) = self.gadget_index {
+            write!(f, ", gadget {}", gadget)?;
+        }
+        write!(f, ": {}", self.cause)
+    }
+}
+
+impl From<VerificationFailure> for VMError {
+    fn from(failure: VerificationFailure) -> VMError {
+        VMError::VerificationFailed(failure)
+    }
+}
+
+/// Represents an error in the construction of a program by a [`ProgramBuilder`].
+///
+/// [`ProgramBuilder`]: crate::program::ProgramBuilder
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+pub enum ProgramBuildError {
+    /// This error occurs when a stack value is used after an instruction consumed it.
+    #[error("Stack value `{name}` is used after it was consumed")]
+    ConsumedTwice { name: String },
+
+    /// This error occurs when stack values are left on the stack at the end of the program.
+    #[error("Stack values {names:?} are left unconsumed")]
+    NotConsumed { names: Vec<String> },
+
+    /// This error occurs when a stack value can not be used as the operand of an instruction.
+    #[error("Stack value `{name}` is not {expected}")]
+    KindMismatch {
+        name: String,
+        expected: &'static str,
+    },
+
+    /// This error occurs when an input is declared after the first instruction.
+    #[error("Input `{name}` is declared after the first instruction")]
+    InputAfterInstructions { name: String },
+
+    /// This error occurs when a handle does not belong to the builder.
+    #[error("Handle {index} is not a value of this builder")]
+    UnknownHandle { index: usize },
 }
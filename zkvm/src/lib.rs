@@ -21,6 +21,7 @@ mod fees;
 pub mod ops;
 pub mod predicate;
 pub mod program;
+pub mod proof_context;
 ///ZkVM Prover
 pub mod prover;
 mod scalar_witness;
@@ -34,18 +35,19 @@ pub mod zkos_types;
 
 pub use self::constraints::{Commitment, CommitmentWitness, Constraint, Expression, Variable};
 pub use self::contract::{Anchor, Contract, ContractID, PortableItem};
-pub use self::errors::VMError;
+pub use self::errors::{ProgramBuildError, VMError};
 pub use self::fees::{fee_flavor, CheckedFee, FeeRate, MAX_FEE};
 pub use self::ops::{Instruction, Opcode};
 pub use self::predicate::{Predicate, PredicateTree};
-pub use self::program::{Program, ProgramItem};
+pub use self::program::{Program, ProgramBuilder, ProgramItem};
+pub use self::proof_context::{ProofContext, DEFAULT_GENS_CAPACITY};
 pub use self::prover::Prover;
 pub use self::scalar_witness::ScalarWitness;
 pub use self::transcript::TranscriptProtocol;
 pub use self::tx::{Tx, TxEntry, TxHeader, TxID, TxLog, UnsignedTx, VerifiedTx};
-pub use self::types::{ClearValue, Item, String, Value, WideValue};
+pub use self::types::{ClearValue, Item, String, StringKind, Value, WideValue};
 pub use self::verifier::Verifier;
-pub use self::vm::{VMRun, VMScript};
+pub use self::vm::{EvalReport, VMRun, VMScript};
 pub use merkle::{Hash, Hasher, MerkleItem, MerkleTree};
 
 pub use address::Address;
@@ -1,5 +1,5 @@
 use crate::encoding::*;
-use crate::errors::VMError;
+use crate::errors::{ProgramBuildError, VMError};
 use crate::merkle::MerkleItem;
 use crate::ops::Instruction;
 use crate::predicate::PredicateTree;
@@ -194,6 +194,25 @@ impl Program {
     // def_op!(inputcoin, InputCoin, usize, "inputcoin:k");
     // def_op!(outputcoin, OutputCoin, usize, "outputcoin:k");
 
+    /// Adds instructions proving that the variable on top of the stack is in
+    /// [0, upper_bound], leaving the variable in place.
+    /// Range proves both the value and `upper_bound - value`, which only
+    /// holds for values up to the bound.
+    pub fn range_upto(&mut self, upper_bound: u64) -> &mut Program {
+        self.dup(0)
+            .expr()
+            .range()
+            .drop()
+            .dup(0)
+            .expr()
+            .neg()
+            .push(upper_bound)
+            .scalar()
+            .add()
+            .range()
+            .drop()
+    }
+
     /// Takes predicate tree and index of program in Merkle tree to verify
     /// the program's membership in that Merkle tree and call the program.
     pub fn choose_call(
@@ -281,3 +300,440 @@ impl MerkleItem for Program {
         t.append_message(b"program", &self.to_bytes());
     }
 }
+
+/// Kind of a value on the stack of a [`ProgramBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    /// A commitment string, turned into an expression with `commit` and `expr`.
+    Commitment,
+    /// A scalar string, turned into an expression with `scalar`.
+    Scalar,
+    /// An expression.
+    Expression,
+    /// A constraint.
+    Constraint,
+}
+
+impl ValueKind {
+    fn describe(self) -> &'static str {
+        match self {
+            ValueKind::Commitment => "a commitment",
+            ValueKind::Scalar => "a scalar",
+            ValueKind::Expression => "an expression",
+            ValueKind::Constraint => "a constraint",
+        }
+    }
+}
+
+/// A named value on the stack of a [`ProgramBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handle(usize);
+
+impl Handle {
+    /// Uses a copy of the value, leaving the value itself on the stack.
+    pub fn copy(self) -> Operand {
+        Operand::Copy(self)
+    }
+}
+
+/// Operand of an instruction of a [`ProgramBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// The value is consumed by the instruction, it is rolled to the top of the stack.
+    Move(Handle),
+    /// A copy of the value is consumed by the instruction, it is duplicated to the top of the stack.
+    Copy(Handle),
+}
+
+impl From<Handle> for Operand {
+    fn from(handle: Handle) -> Self {
+        Operand::Move(handle)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct StackValue {
+    name: std::string::String,
+    kind: ValueKind,
+}
+
+/// Builds a program over named stack values instead of stack offsets.
+///
+/// The values initialized on the stack are declared first, from the bottom of the
+/// stack to its top. Each operation takes its operands as handles, and the builder
+/// emits the `roll:k` or `dup:k` bringing each of them to the top of the stack,
+/// followed by the `commit`, `expr` or `scalar` turning it into an expression.
+/// An operand already in place is used as is.
+///
+/// Every value has to be consumed exactly once, by an operation or by `drop`.
+/// A value used after it was consumed, or left on the stack, fails [`build`].
+///
+/// Example:
+/// ```ascii
+/// let mut b = ProgramBuilder::new();
+/// let size = b.input_scalar("PositionSize");
+/// let im = b.input_commitment("IM");
+/// let notional = b.mul(im, size);
+/// ...
+/// let program = b.build()?;
+/// ```
+///
+/// [`build`]: ProgramBuilder::build
+#[derive(Clone, Debug, Default)]
+pub struct ProgramBuilder {
+    program: Vec<Instruction>,
+    values: Vec<StackValue>,
+    // values on the stack, from bottom to top
+    stack: Vec<usize>,
+    error: Option<ProgramBuildError>,
+}
+
+impl ProgramBuilder {
+    /// Creates a builder of an empty program, with an empty stack.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares the next commitment initialized on the stack.
+    pub fn input_commitment(&mut self, name: &str) -> Handle {
+        self.input(name, ValueKind::Commitment)
+    }
+
+    /// Declares the next scalar initialized on the stack.
+    pub fn input_scalar(&mut self, name: &str) -> Handle {
+        self.input(name, ValueKind::Scalar)
+    }
+
+    fn input(&mut self, name: &str, kind: ValueKind) -> Handle {
+        if !self.program.is_empty() {
+            self.fail(ProgramBuildError::InputAfterInstructions {
+                name: name.to_string(),
+            });
+        }
+        let handle = self.value(name.to_string(), kind);
+        self.stack.push(handle.0);
+        handle
+    }
+
+    /// Turns the value into an expression on top of the stack.
+    pub fn expr<T: Into<Operand>>(&mut self, x: T) -> Handle {
+        match self.operands(&[x.into()], ValueKind::Expression) {
+            Some(ids) => Handle(ids[0]),
+            None => self.value("?".to_string(), ValueKind::Expression),
+        }
+    }
+
+    /// Adds `-x`.
+    pub fn neg<T: Into<Operand>>(&mut self, x: T) -> Handle {
+        self.unary(x.into(), ValueKind::Expression, Instruction::Neg, |x| {
+            format!("-{}", x)
+        })
+    }
+
+    /// Adds `x + y`.
+    pub fn add<T: Into<Operand>, U: Into<Operand>>(&mut self, x: T, y: U) -> Handle {
+        self.binary(x.into(), y.into(), Instruction::Add, "+")
+    }
+
+    /// Adds `x * y`.
+    pub fn mul<T: Into<Operand>, U: Into<Operand>>(&mut self, x: T, y: U) -> Handle {
+        self.binary(x.into(), y.into(), Instruction::Mul, "*")
+    }
+
+    /// Adds the constraint `x == y`.
+    pub fn eq<T: Into<Operand>, U: Into<Operand>>(&mut self, x: T, y: U) -> Handle {
+        self.binary(x.into(), y.into(), Instruction::Eq, "==")
+    }
+
+    /// Adds the constraint `x && y`.
+    pub fn and<T: Into<Operand>, U: Into<Operand>>(&mut self, x: T, y: U) -> Handle {
+        self.binary(x.into(), y.into(), Instruction::And, "&&")
+    }
+
+    /// Adds the constraint `x || y`.
+    pub fn or<T: Into<Operand>, U: Into<Operand>>(&mut self, x: T, y: U) -> Handle {
+        self.binary(x.into(), y.into(), Instruction::Or, "||")
+    }
+
+    /// Adds the constraint `!x`.
+    pub fn not<T: Into<Operand>>(&mut self, x: T) -> Handle {
+        self.unary(x.into(), ValueKind::Constraint, Instruction::Not, |x| {
+            format!("!{}", x)
+        })
+    }
+
+    /// Proves that the expression `x` is in range, the expression is left in place.
+    pub fn range<T: Into<Operand>>(&mut self, x: T) -> Handle {
+        self.unary(x.into(), ValueKind::Expression, Instruction::Range, |x| {
+            format!("range({})", x)
+        })
+    }
+
+    /// Verifies the constraint `x`.
+    pub fn verify<T: Into<Operand>>(&mut self, x: T) {
+        if self.operands(&[x.into()], ValueKind::Constraint).is_some() {
+            self.stack.pop();
+            self.program.push(Instruction::Verify);
+        }
+    }
+
+    /// Drops the value `x`, of any kind.
+    pub fn drop<T: Into<Operand>>(&mut self, x: T) {
+        if self.fetch(x.into(), None).is_some() {
+            self.stack.pop();
+            self.program.push(Instruction::Drop);
+        }
+    }
+
+    /// Returns the program, or the first error of its construction.
+    pub fn build(self) -> Result<Program, ProgramBuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if !self.stack.is_empty() {
+            let names = self
+                .stack
+                .iter()
+                .map(|id| self.values[*id].name.clone())
+                .collect();
+            return Err(ProgramBuildError::NotConsumed { names });
+        }
+        Ok(Program::from_vec(self.program))
+    }
+
+    fn value(&mut self, name: std::string::String, kind: ValueKind) -> Handle {
+        self.values.push(StackValue { name, kind });
+        Handle(self.values.len() - 1)
+    }
+
+    fn fail(&mut self, error: ProgramBuildError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    // the result of a unary instruction is of the kind of its operand
+    fn unary(
+        &mut self,
+        x: Operand,
+        kind: ValueKind,
+        instruction: Instruction,
+        name: fn(&str) -> std::string::String,
+    ) -> Handle {
+        let ids = match self.operands(&[x], kind) {
+            Some(ids) => ids,
+            None => return self.value("?".to_string(), kind),
+        };
+        let name = name(&self.values[ids[0]].name);
+        self.stack.pop();
+        self.program.push(instruction);
+        self.push(name, kind)
+    }
+
+    // the operands of `and` and `or` are constraints, the others expressions
+    fn binary(&mut self, x: Operand, y: Operand, instruction: Instruction, symbol: &str) -> Handle {
+        let (operand, result) = match instruction {
+            Instruction::And | Instruction::Or => (ValueKind::Constraint, ValueKind::Constraint),
+            Instruction::Eq => (ValueKind::Expression, ValueKind::Constraint),
+            _ => (ValueKind::Expression, ValueKind::Expression),
+        };
+        let ids = match self.operands(&[x, y], operand) {
+            Some(ids) => ids,
+            None => return self.value("?".to_string(), result),
+        };
+        let name = format!(
+            "({} {} {})",
+            self.values[ids[0]].name, symbol, self.values[ids[1]].name
+        );
+        self.stack.truncate(self.stack.len() - 2);
+        self.program.push(instruction);
+        self.push(name, result)
+    }
+
+    fn push(&mut self, name: std::string::String, kind: ValueKind) -> Handle {
+        let handle = self.value(name, kind);
+        self.stack.push(handle.0);
+        handle
+    }
+
+    // brings the operands on top of the stack, the first one the deepest, as values
+    // of `kind`; operands already in place are left as they are
+    fn operands(&mut self, operands: &[Operand], kind: ValueKind) -> Option<Vec<usize>> {
+        if self.error.is_some() {
+            return None;
+        }
+        let count = operands.len();
+        let in_place = operands
+            .iter()
+            .enumerate()
+            .all(|(i, operand)| match operand {
+                Operand::Move(handle) => {
+                    self.depth(*handle) == Some(count - 1 - i) && self.values[handle.0].kind == kind
+                }
+                Operand::Copy(_) => false,
+            });
+        if in_place {
+            return Some(self.stack[self.stack.len() - count..].to_vec());
+        }
+        let mut ids = Vec::with_capacity(count);
+        for operand in operands {
+            ids.push(self.fetch(*operand, Some(kind))?);
+        }
+        Some(ids)
+    }
+
+    // position of the value from the top of the stack
+    fn depth(&self, handle: Handle) -> Option<usize> {
+        self.stack.iter().rev().position(|id| *id == handle.0)
+    }
+
+    // rolls the value, or a copy of it, to the top of the stack
+    fn fetch(&mut self, operand: Operand, kind: Option<ValueKind>) -> Option<usize> {
+        if self.error.is_some() {
+            return None;
+        }
+        let (handle, copy) = match operand {
+            Operand::Move(handle) => (handle, false),
+            Operand::Copy(handle) => (handle, true),
+        };
+        if handle.0 >= self.values.len() {
+            self.fail(ProgramBuildError::UnknownHandle { index: handle.0 });
+            return None;
+        }
+        let depth = match self.depth(handle) {
+            Some(depth) => depth,
+            None => {
+                let name = self.values[handle.0].name.clone();
+                self.fail(ProgramBuildError::ConsumedTwice { name });
+                return None;
+            }
+        };
+        if copy {
+            self.program.push(Instruction::Dup(depth));
+            let value = self.values[handle.0].clone();
+            self.push(value.name, value.kind);
+        } else if depth > 0 {
+            self.program.push(Instruction::Roll(depth));
+            let id = self.stack.remove(self.stack.len() - 1 - depth);
+            self.stack.push(id);
+        }
+        let id = *self.stack.last()?;
+        match kind {
+            Some(kind) => self.convert(id, kind).then_some(id),
+            None => Some(id),
+        }
+    }
+
+    fn convert(&mut self, id: usize, kind: ValueKind) -> bool {
+        match (self.values[id].kind, kind) {
+            (from, to) if from == to => return true,
+            (ValueKind::Commitment, ValueKind::Expression) => {
+                self.program.push(Instruction::Commit);
+                self.program.push(Instruction::Expr);
+            }
+            (ValueKind::Scalar, ValueKind::Expression) => {
+                self.program.push(Instruction::Scalar);
+            }
+            _ => {
+                let name = self.values[id].name.clone();
+                self.fail(ProgramBuildError::KindMismatch {
+                    name,
+                    expected: kind.describe(),
+                });
+                return false;
+            }
+        }
+        self.values[id].kind = kind;
+        true
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builder_stack_indices_test() {
+        let mut b = ProgramBuilder::new();
+        let x = b.input_commitment("X");
+        let y = b.input_scalar("Y");
+        let z = b.input_commitment("Z");
+        // z is rolled from the top, x from the bottom and y is copied
+        let zy = b.mul(z, y.copy());
+        let lhs = b.add(x, zy);
+        let c = b.eq(lhs, y);
+        b.verify(c);
+        let expected = Program::build(|p| {
+            p.commit()
+                .expr()
+                .dup(1)
+                .scalar()
+                .mul()
+                .roll(2)
+                .commit()
+                .expr()
+                .roll(1)
+                .add()
+                .roll(1)
+                .scalar()
+                .eq()
+                .verify();
+        });
+        assert_eq!(b.build().unwrap().to_bytes(), expected.to_bytes());
+    }
+
+    #[test]
+    fn builder_errors_test() {
+        let mut b = ProgramBuilder::new();
+        let x = b.input_commitment("X");
+        let y = b.input_scalar("Y");
+        let z = b.mul(x, y);
+        b.add(z, x);
+        assert_eq!(
+            b.build().unwrap_err(),
+            ProgramBuildError::ConsumedTwice {
+                name: "X".to_string()
+            }
+        );
+
+        let mut b = ProgramBuilder::new();
+        let x = b.input_commitment("X");
+        let y = b.input_scalar("Y");
+        b.mul(x, y);
+        assert_eq!(
+            b.build().unwrap_err(),
+            ProgramBuildError::NotConsumed {
+                names: vec!["(X * Y)".to_string()]
+            }
+        );
+
+        let mut b = ProgramBuilder::new();
+        let x = b.input_commitment("X");
+        let y = b.input_scalar("Y");
+        let c = b.eq(x, y);
+        b.verify(c);
+        b.input_scalar("Z");
+        assert_eq!(
+            b.build().unwrap_err(),
+            ProgramBuildError::InputAfterInstructions {
+                name: "Z".to_string()
+            }
+        );
+
+        let mut b = ProgramBuilder::new();
+        let x = b.input_commitment("X");
+        let y = b.input_scalar("Y");
+        let e = b.mul(x, y);
+        b.verify(e);
+        assert_eq!(
+            b.build().unwrap_err(),
+            ProgramBuildError::KindMismatch {
+                name: "(X * Y)".to_string(),
+                expected: "a constraint"
+            }
+        );
+    }
+}
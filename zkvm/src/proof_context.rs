@@ -0,0 +1,175 @@
+//! Generators of the R1CS proofs, shared by the provers and the verifiers.
+//!
+//! Building the Bulletproofs generators is a measurable part of proving or verifying
+//! a small transaction. A [`ProofContext`] builds them once and grows them on demand:
+//! the generators of each power of two capacity reached are kept, so a read finds
+//! them without taking a lock, and a growth extends the largest ones under a lock.
+//!
+//! The generators of a capacity are a prefix of the generators of any larger one,
+//! so a proof does not depend on the capacity it is built or verified with.
+
+use bulletproofs::{BulletproofGens, PedersenGens};
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::Mutex;
+
+/// Capacity of the generators a context starts with, and the capacity the
+/// verifiers ask for.
+pub const DEFAULT_GENS_CAPACITY: usize = 256;
+
+// generators of capacity 2^0 to 2^31
+const GENS_SLOTS: usize = 32;
+
+static GLOBAL_CONTEXT: Lazy<ProofContext> = Lazy::new(|| ProofContext::new(DEFAULT_GENS_CAPACITY));
+
+/// Pedersen generators and growable Bulletproofs generators.
+pub struct ProofContext {
+    pc_gens: PedersenGens,
+    // generators of capacity 2^i in slot i, each slot is set once
+    bp_gens: [OnceCell<BulletproofGens>; GENS_SLOTS],
+    grow: Mutex<()>,
+}
+
+impl ProofContext {
+    /// Context with generators of at least `capacity`, e.g. a context of a test.
+    pub fn new(capacity: usize) -> Self {
+        let context = ProofContext {
+            pc_gens: PedersenGens::default(),
+            bp_gens: Default::default(),
+            grow: Mutex::new(()),
+        };
+        context.bp_gens(capacity);
+        context
+    }
+
+    /// Context shared by the process, built on first use.
+    pub fn global() -> &'static ProofContext {
+        &GLOBAL_CONTEXT
+    }
+
+    pub fn pc_gens(&self) -> &PedersenGens {
+        &self.pc_gens
+    }
+
+    /// Generators of at least `capacity`, grown to the next power of two if needed.
+    pub fn bp_gens(&self, capacity: usize) -> &BulletproofGens {
+        let slot = gens_slot(capacity);
+        if let Some(gens) = self.find(slot) {
+            return gens;
+        }
+        let _grow = self.grow.lock().unwrap();
+        // grown by another thread while waiting for the lock
+        if let Some(gens) = self.find(slot) {
+            return gens;
+        }
+        let capacity = 1usize << slot;
+        let gens = match self.bp_gens[..slot].iter().rev().find_map(OnceCell::get) {
+            Some(largest) => {
+                let mut gens = largest.clone();
+                gens.increase_capacity(capacity);
+                gens
+            }
+            None => BulletproofGens::new(capacity, 1),
+        };
+        println!("ProofContext: bulletproof generators grown to {}", capacity);
+        self.bp_gens[slot].get_or_init(|| gens)
+    }
+
+    /// Capacity of the largest generators built so far.
+    pub fn capacity(&self) -> usize {
+        self.bp_gens
+            .iter()
+            .rposition(|gens| gens.get().is_some())
+            .map_or(0, |slot| 1 << slot)
+    }
+
+    // smallest generators built of at least the capacity of `slot`
+    fn find(&self, slot: usize) -> Option<&BulletproofGens> {
+        self.bp_gens[slot..].iter().find_map(OnceCell::get)
+    }
+}
+
+// slot of the generators of at least `capacity`
+fn gens_slot(capacity: usize) -> usize {
+    let slot = capacity.max(1).next_power_of_two().trailing_zeros() as usize;
+    assert!(
+        slot < GENS_SLOTS,
+        "generator capacity {} is too large",
+        capacity
+    );
+    slot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bulletproofs::r1cs::{self, ConstraintSystem, R1CSProof};
+    use curve25519_dalek::ristretto::CompressedRistretto;
+    use curve25519_dalek::scalar::Scalar;
+    use merlin::Transcript;
+    use std::sync::Arc;
+
+    // proof that the committed value squares to 9
+    fn square_proof(context: &ProofContext, capacity: usize) -> (R1CSProof, CompressedRistretto) {
+        let mut prover = r1cs::Prover::new(context.pc_gens(), Transcript::new(b"ProofContextTest"));
+        let (commitment, var) =
+            prover.commit(Scalar::from(3u64), Scalar::random(&mut rand::thread_rng()));
+        let (_, _, square) = prover.multiply(var.into(), var.into());
+        prover.constrain(square - Scalar::from(9u64));
+        let proof = prover.prove(context.bp_gens(capacity)).unwrap();
+        (proof, commitment)
+    }
+
+    fn verify_square(
+        context: &ProofContext,
+        capacity: usize,
+        proof: &R1CSProof,
+        commitment: CompressedRistretto,
+    ) -> bool {
+        let mut verifier = r1cs::Verifier::new(Transcript::new(b"ProofContextTest"));
+        let var = verifier.commit(commitment);
+        let (_, _, square) = verifier.multiply(var.into(), var.into());
+        verifier.constrain(square - Scalar::from(9u64));
+        verifier
+            .verify(proof, context.pc_gens(), context.bp_gens(capacity))
+            .is_ok()
+    }
+
+    #[test]
+    fn proof_context_growth() {
+        let context = ProofContext::new(100);
+        assert_eq!(context.capacity(), 128);
+        assert_eq!(context.bp_gens(1).gens_capacity, 128);
+
+        // grown generators extend the smaller ones, a proof verifies with both
+        let (proof, commitment) = square_proof(&context, 128);
+        assert_eq!(context.bp_gens(300).gens_capacity, 512);
+        assert_eq!(context.capacity(), 512);
+        assert!(verify_square(&context, 128, &proof, commitment));
+        assert!(verify_square(&context, 512, &proof, commitment));
+        let fresh = ProofContext::new(DEFAULT_GENS_CAPACITY);
+        assert!(verify_square(&fresh, 256, &proof, commitment));
+
+        // the smallest generators of the capacity are served
+        assert_eq!(context.bp_gens(128).gens_capacity, 128);
+        assert_eq!(context.bp_gens(129).gens_capacity, 512);
+    }
+
+    #[test]
+    fn proof_context_concurrent_growth() {
+        let context = Arc::new(ProofContext::new(1));
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let context = context.clone();
+                std::thread::spawn(move || {
+                    for capacity in (0..10).map(|i| 1usize << ((i + thread) % 10)) {
+                        assert!(context.bp_gens(capacity).gens_capacity >= capacity);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(context.capacity(), 512);
+    }
+}
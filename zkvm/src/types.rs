@@ -3,6 +3,10 @@
 use curve25519_dalek::scalar::Scalar;
 use merlin::Transcript;
 use rangeproof::{self, SignedInteger};
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+use core::str::FromStr;
+use merkle::Hash;
 use serde::{Deserialize, Serialize};
 
 use crate::constraints::{Commitment, Constraint, Expression, Variable};
@@ -17,10 +21,83 @@ use crate::zkos_types::OutputCoin;
 
 
 /// Transaction ID is a unique 32-byte identifier of a transaction effects represented by `TxLog`.
-#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(transparent)]
+/// Serialized as a hex string by human-readable formats and as raw bytes otherwise.
+#[derive(Copy, Clone, PartialEq)]
 pub struct TxID(pub Hash);
 
+impl fmt::Display for TxID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&(self.0).0))
+    }
+}
+
+impl FromStr for TxID {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s.trim()).map_err(|_| "Error::InvalidTxIDHex")?;
+        let id: [u8; 32] = bytes.try_into().map_err(|_| "Error::InvalidTxIDLength")?;
+        Ok(TxID(Hash(id)))
+    }
+}
+
+impl Serialize for TxID {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TxID {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TxIDVisitor)
+        } else {
+            Hash::deserialize(deserializer).map(TxID)
+        }
+    }
+}
+
+// accepts the hex string and the byte array written before TxID had a human-readable form
+struct TxIDVisitor;
+
+impl<'de> serde::de::Visitor<'de> for TxIDVisitor {
+    type Value = TxID;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a hex encoded 32-byte transaction id")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<TxID, E>
+    where
+        E: serde::de::Error,
+    {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<TxID, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut id = [0u8; 32];
+        for (i, byte) in id.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+        }
+        Ok(TxID(Hash(id)))
+    }
+}
+
 
 impl AsRef<[u8]> for TxID {
     fn as_ref(&self) -> &[u8] {
@@ -131,6 +208,25 @@ pub enum String {
     U32(u32),
 }
 
+/// Kind of a data item, as held by `String`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StringKind {
+    /// Opaque bytes.
+    Opaque,
+    /// A predicate.
+    Predicate,
+    /// A Pedersen commitment.
+    Commitment,
+    /// A scalar witness.
+    Scalar,
+    /// An unspent output.
+    Output,
+    /// A u64 integer.
+    U64,
+    /// A u32 integer.
+    U32,
+}
+
 /// Represents a value of an issued asset in the VM.
 /// Note: values do not necessarily have open commitments. Some can be reblinded,
 /// others can be passed-through to an output without going through `cloak` and the constraint system.
@@ -295,6 +391,46 @@ impl ExactSizeEncodable for String {
 }
 
 impl String {
+    /// Returns the kind of the data item.
+    pub fn kind(&self) -> StringKind {
+        match self {
+            String::Opaque(_) => StringKind::Opaque,
+            String::Predicate(_) => StringKind::Predicate,
+            String::Commitment(_) => StringKind::Commitment,
+            String::Scalar(_) => StringKind::Scalar,
+            String::Output(_) => StringKind::Output,
+            String::U64(_) => StringKind::U64,
+            String::U32(_) => StringKind::U32,
+        }
+    }
+
+    /// Borrows the `Commitment` of a commitment item.
+    /// Opaque data is not decoded, see `to_commitment`.
+    pub fn as_commitment(&self) -> Result<&Commitment, VMError> {
+        match self {
+            String::Commitment(c) => Ok(c),
+            _ => Err(VMError::TypeNotCommitment),
+        }
+    }
+
+    /// Borrows the `ScalarWitness` of a scalar item.
+    /// Opaque data is not decoded, see `to_scalar`.
+    pub fn as_scalar(&self) -> Result<&ScalarWitness, VMError> {
+        match self {
+            String::Scalar(scalar_witness) => Ok(scalar_witness),
+            _ => Err(VMError::TypeNotScalar),
+        }
+    }
+
+    /// Returns the integer of a u64 item.
+    /// Opaque data is not decoded, see `to_u64`.
+    pub fn as_u64(&self) -> Result<u64, VMError> {
+        match self {
+            String::U64(n) => Ok(*n),
+            _ => Err(VMError::TypeNotU64),
+        }
+    }
+
     /// Converts the String item into a vector of bytes.
     /// Opaque item is converted without extra allocations,
     /// non-opaque item is encoded to a newly allocated buffer.
@@ -476,6 +612,56 @@ impl From<Contract> for String {
     }
 }
 
+// Downcasting String to the witness data types
+
+impl TryFrom<String> for Predicate {
+    type Error = VMError;
+
+    fn try_from(x: String) -> Result<Self, Self::Error> {
+        x.to_predicate()
+    }
+}
+
+impl TryFrom<String> for Commitment {
+    type Error = VMError;
+
+    fn try_from(x: String) -> Result<Self, Self::Error> {
+        x.to_commitment()
+    }
+}
+
+impl TryFrom<String> for Contract {
+    type Error = VMError;
+
+    fn try_from(x: String) -> Result<Self, Self::Error> {
+        x.to_output()
+    }
+}
+
+impl TryFrom<String> for ScalarWitness {
+    type Error = VMError;
+
+    fn try_from(x: String) -> Result<Self, Self::Error> {
+        x.to_scalar()
+    }
+}
+
+impl TryFrom<String> for u64 {
+    type Error = VMError;
+
+    fn try_from(x: String) -> Result<Self, Self::Error> {
+        x.to_u64()
+    }
+}
+
+impl TryFrom<String> for u32 {
+    type Error = VMError;
+
+    fn try_from(x: String) -> Result<Self, Self::Error> {
+        x.to_u32()
+    }
+}
+
 // Upcasting all types to Item
 
 impl From<String> for Item {
@@ -575,3 +761,61 @@ impl From<CopyableItem> for Item {
 //         64 + 69
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_accessors() {
+        let commitment = Commitment::blinded(5u64);
+        let item = String::from(commitment.clone());
+        assert_eq!(item.kind(), StringKind::Commitment);
+        assert_eq!(item.as_commitment().unwrap(), &commitment);
+        assert_eq!(item.as_scalar(), Err(VMError::TypeNotScalar));
+        assert_eq!(item.as_u64(), Err(VMError::TypeNotU64));
+
+        let item = String::from(7u64);
+        assert_eq!(item.kind(), StringKind::Scalar);
+        assert_eq!(item.as_scalar().unwrap(), &ScalarWitness::from(7u64));
+        assert_eq!(item.as_commitment(), Err(VMError::TypeNotCommitment));
+
+        let item = String::U64(9);
+        assert_eq!(item.kind(), StringKind::U64);
+        assert_eq!(item.as_u64(), Ok(9));
+        // opaque data is only decoded by the owning conversions
+        let item = String::Opaque(9u64.to_le_bytes().to_vec());
+        assert_eq!(item.kind(), StringKind::Opaque);
+        assert_eq!(item.as_u64(), Err(VMError::TypeNotU64));
+        assert_eq!(u64::try_from(item), Ok(9));
+    }
+
+    #[test]
+    fn string_try_from() {
+        let commitment = Commitment::blinded(5u64);
+        assert_eq!(
+            Commitment::try_from(String::from(commitment.clone())),
+            Ok(commitment)
+        );
+        assert_eq!(
+            ScalarWitness::try_from(String::from(3u64)),
+            Ok(ScalarWitness::from(3u64))
+        );
+        assert_eq!(u32::try_from(String::U32(4)), Ok(4));
+
+        // a scalar where a commitment is expected
+        assert_eq!(
+            Commitment::try_from(String::from(3u64)),
+            Err(VMError::TypeNotCommitment)
+        );
+        assert_eq!(
+            ScalarWitness::try_from(String::from(Commitment::blinded(5u64))),
+            Err(VMError::TypeNotScalar)
+        );
+        assert_eq!(u64::try_from(String::U32(4)), Err(VMError::TypeNotU64));
+        assert_eq!(
+            Predicate::try_from(String::U64(1)).err(),
+            Some(VMError::TypeNotPredicate)
+        );
+    }
+}
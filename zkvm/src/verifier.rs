@@ -3,7 +3,6 @@ use std::time::Instant;
 use bulletproofs::r1cs;
 use bulletproofs::r1cs::ConstraintSystem;
 use bulletproofs::r1cs::R1CSProof;
-use bulletproofs::{BulletproofGens, PedersenGens};
 use curve25519_dalek::ristretto::CompressedRistretto;
 use merlin::Transcript;
 use mulmsgsig::{Multisignature, VerificationKey};
@@ -16,6 +15,7 @@ use crate::fees::FeeRate;
 use crate::ops::Instruction;
 use crate::predicate::Predicate;
 use crate::program::ProgramItem;
+use crate::proof_context::{ProofContext, DEFAULT_GENS_CAPACITY};
 use crate::tx::{PrecomputedTx, Tx, TxHeader, VerifiedTx};
 use crate::vm::{Delegate, VM};
 use crate::zkos_types::{Input, Output};
@@ -131,9 +131,7 @@ impl Verifier {
         header: TxHeader,
         program: Vec<u8>,
     ) -> Result<bool, VMError> {
-        let bp_gens = BulletproofGens::new(256, 1);
-        //print!("BP Gens in verify_proof {:?}", bp_gens);
-        let pc_gens = PedersenGens::default();
+        let context = ProofContext::global();
         let cs = r1cs::Verifier::new(Transcript::new(b"ZkVM.r1cs"));
 
         let mut verifier = Verifier {
@@ -152,7 +150,11 @@ impl Verifier {
         // Verify the R1CS proof
         verifier
             .cs
-            .verify(&proof, &pc_gens, &bp_gens)
+            .verify(
+                &proof,
+                context.pc_gens(),
+                context.bp_gens(DEFAULT_GENS_CAPACITY),
+            )
             .map_err(|_| VMError::InvalidR1CSProof)?;
 
         Ok(true)
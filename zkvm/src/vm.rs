@@ -10,7 +10,7 @@ use merlin::Transcript;
 use crate::constraints::{Commitment, Constraint, Expression, Variable};
 use crate::contract::{Anchor, Contract, ContractID, PortableItem};
 use crate::encoding::*;
-use crate::errors::VMError;
+use crate::errors::{VMError, VerificationFailure, VerificationPhase};
 use crate::fees::{fee_flavor, CheckedFee};
 use crate::ops::Instruction;
 use crate::predicate::{CallProof, Predicate};
@@ -48,6 +48,36 @@ where
     // input state is zero in this case and is not used in the program
     // contract_init_flag: u8,
     tx_data: Option<crate::String>,
+    // evaluation mode, constraints are checked against their assignments
+    // instead of being added to the constraint system
+    eval_only: bool,
+    // constraints in evaluation mode whose assignment is unknown
+    unresolved_constraints: usize,
+}
+
+/// Outcome of evaluating a program with `VMScript::eval_only`.
+#[derive(Debug)]
+pub struct EvalReport {
+    /// First failing instruction, None if the programs ran to completion
+    pub failure: Option<VerificationFailure>,
+    /// Items left on the stack, bottom first
+    pub stack: Vec<Item>,
+    /// Constraints and ranges which could not be checked for missing witness data
+    pub unresolved_constraints: usize,
+}
+
+impl EvalReport {
+    /// Returns Some(true) if every constraint holds, Some(false) if one does not
+    /// or the program failed, and None if it depends on values without witness data.
+    pub fn satisfiable(&self) -> Option<bool> {
+        if self.failure.is_some() {
+            Some(false)
+        } else if self.unresolved_constraints > 0 {
+            None
+        } else {
+            Some(true)
+        }
+    }
 }
 
 pub trait VMRun<CS: r1cs::RandomizableConstraintSystem> {
@@ -98,6 +128,8 @@ where
             outputs_tx: outputs,
             tx_data,
             // contract_init_flag: contract_init_flag,
+            eval_only: false,
+            unresolved_constraints: 0,
         }
     }
     /// Contract Initalization is treated differently because a contract address/state has to be deployed
@@ -333,19 +365,91 @@ where
         Ok(())
     }
     /// Runs through the entire program and nested programs until completion.
-    pub fn run(mut self) -> Result<(), VMError> {
+    pub fn run(self) -> Result<(), VMError> {
+        self.run_with_diagnostics().map_err(|failure| *failure.cause)
+    }
+
+    /// Runs the program like `run`, reporting the phase, instruction and stack depth on failure.
+    pub fn run_with_diagnostics(mut self) -> Result<(), VerificationFailure> {
+        let instruction_index = self.run_programs()?;
+        if self.stack.len() > 0 {
+            let mut failure = VerificationFailure::new(
+                VerificationPhase::Execution,
+                self.stack.len(),
+                VMError::StackNotClean,
+            );
+            failure.instruction_index = Some(instruction_index);
+            return Err(failure);
+        }
+
+        Ok(())
+    }
+
+    /// Runs the programs in evaluation mode and reports the outcome with the final stack.
+    /// `verify` and `range` check the assignments of their operands in cleartext and fail
+    /// on the first one which does not hold, instead of adding them to the constraint system,
+    /// so no proof is built and no witness is needed besides the commitment openings.
+    /// The stack is not required to be clean, the items left on it are returned instead.
+    pub fn eval_only(mut self) -> EvalReport {
+        self.eval_only = true;
+        let failure = self.run_programs().err();
+        EvalReport {
+            failure,
+            stack: self.stack,
+            unresolved_constraints: self.unresolved_constraints,
+        }
+    }
+
+    // runs the current and the queued programs, returns the number of instructions executed
+    fn run_programs(&mut self) -> Result<usize, VerificationFailure> {
         println!("stack len : {:?}", self.stack.len());
         println!("Stack : {:?}", self.stack);
+        let mut instruction_index = 0usize;
         loop {
-            if !self.step()? {
-                break;
+            let instr = match self.delegate.next_instruction(&mut self.current_run) {
+                Ok(instr) => instr,
+                Err(err) => {
+                    let mut failure =
+                        VerificationFailure::new(VerificationPhase::Decode, self.stack.len(), err);
+                    failure.instruction_index = Some(instruction_index);
+                    return Err(failure);
+                }
+            };
+            match instr {
+                Some(instr) => {
+                    let opcode = instr.encode_to_vec().first().copied();
+                    if let Err(err) = self.step(instr) {
+                        let mut failure = VerificationFailure::new(
+                            VerificationPhase::Execution,
+                            self.stack.len(),
+                            err,
+                        );
+                        failure.instruction_index = Some(instruction_index);
+                        failure.opcode = opcode;
+                        return Err(failure);
+                    }
+                    instruction_index += 1;
+                }
+                None => {
+                    // Reached the end of the current program
+                    if !self.finish_run() {
+                        break;
+                    }
+                }
             }
         }
-        if self.stack.len() > 0 {
-            return Err(VMError::StackNotClean);
-        }
+        Ok(instruction_index)
+    }
 
-        Ok(())
+    /// Returns the number of items currently on the stack.
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Queues a program to run after the current program and the programs queued before it.
+    /// Queued programs share the stack and the constraint system of the current program.
+    pub fn queue_run(&mut self, run: R::RunType) {
+        self.run_stack.insert(0, run);
     }
 
     fn finish_run(&mut self) -> bool {
@@ -359,52 +463,46 @@ where
         return false;
     }
 
-    /// Returns a flag indicating whether to continue the execution
-    fn step(&mut self) -> Result<bool, VMError> {
-        if let Some(instr) = self.delegate.next_instruction(&mut self.current_run)? {
-            println!("instr : {:?}", instr);
-            // Attempt to read the next instruction and advance the program state
-            match instr {
-                Instruction::Push(data) => self.pushdata(data),
-                Instruction::Program(prog) => self.pushprogram(prog),
-                Instruction::Drop => self.drop()?,
-                Instruction::Dup(i) => self.dup(i)?,
-                Instruction::Roll(i) => self.roll(i)?,
-                Instruction::Scalar => self.scalar()?,
-                Instruction::Commit => self.commit()?,
-                Instruction::Alloc(sw) => self.alloc(sw)?,
-                Instruction::Expr => self.expr()?,
-                Instruction::Neg => self.neg()?,
-                Instruction::Add => self.add()?,
-                Instruction::Mul => self.mul()?,
-                Instruction::Eq => self.eq()?,
-                Instruction::Range => self.range()?,
-                Instruction::And => self.and()?,
-                Instruction::Or => self.or()?,
-                Instruction::Not => self.not()?,
-                Instruction::Verify => self.verify()?,
-                Instruction::Unblind => (), //self.unblind()?,
-                Instruction::Issue => (),   //self.issue()?,
-                Instruction::Borrow => self.borrow()?,
-                Instruction::Retire => self.retire()?,
-                Instruction::Fee => self.fee()?,
-                Instruction::Input => self.input()?,
-                Instruction::Output(k) => (),   //self.output(k)?,
-                Instruction::Contract(k) => (), //self.contract(k)?,
-                Instruction::Log => self.log()?,
-                // Instruction::Call => (),    //self.call()?,
-                // Instruction::Signtx => (),  //self.signtx()?,
-                // Instruction::Signid => (),  //self.signid()?,
-                // Instruction::Signtag => (), //self.signtag()?,
-                Instruction::InputCoin(k) => self.inputcoin(k)?,
-                Instruction::OutputCoin(k) => self.outputcoin(k)?,
-                Instruction::Ext(opcode) => (), //self.ext(opcode)?,
-            }
-            return Ok(true);
-        } else {
-            // Reached the end of the current program
-            return Ok(self.finish_run());
+    /// Executes a single instruction and advances the program state
+    fn step(&mut self, instr: Instruction) -> Result<(), VMError> {
+        println!("instr : {:?}", instr);
+        match instr {
+            Instruction::Push(data) => self.pushdata(data),
+            Instruction::Program(prog) => self.pushprogram(prog),
+            Instruction::Drop => self.drop()?,
+            Instruction::Dup(i) => self.dup(i)?,
+            Instruction::Roll(i) => self.roll(i)?,
+            Instruction::Scalar => self.scalar()?,
+            Instruction::Commit => self.commit()?,
+            Instruction::Alloc(sw) => self.alloc(sw)?,
+            Instruction::Expr => self.expr()?,
+            Instruction::Neg => self.neg()?,
+            Instruction::Add => self.add()?,
+            Instruction::Mul => self.mul()?,
+            Instruction::Eq => self.eq()?,
+            Instruction::Range => self.range()?,
+            Instruction::And => self.and()?,
+            Instruction::Or => self.or()?,
+            Instruction::Not => self.not()?,
+            Instruction::Verify => self.verify()?,
+            Instruction::Unblind => (), //self.unblind()?,
+            Instruction::Issue => (),   //self.issue()?,
+            Instruction::Borrow => self.borrow()?,
+            Instruction::Retire => self.retire()?,
+            Instruction::Fee => self.fee()?,
+            Instruction::Input => self.input()?,
+            Instruction::Output(k) => (),   //self.output(k)?,
+            Instruction::Contract(k) => (), //self.contract(k)?,
+            Instruction::Log => self.log()?,
+            // Instruction::Call => (),    //self.call()?,
+            // Instruction::Signtx => (),  //self.signtx()?,
+            // Instruction::Signid => (),  //self.signid()?,
+            // Instruction::Signtag => (), //self.signtag()?,
+            Instruction::InputCoin(k) => self.inputcoin(k)?,
+            Instruction::OutputCoin(k) => self.outputcoin(k)?,
+            Instruction::Ext(opcode) => (), //self.ext(opcode)?,
         }
+        Ok(())
     }
 
     fn pushdata(&mut self, str: String) {
@@ -481,6 +579,15 @@ where
 
     fn range(&mut self) -> Result<(), VMError> {
         let expr = self.pop_item()?.to_expression()?;
+        if self.eval_only {
+            match expr.assignment() {
+                Some(x) if x.in_range() => (),
+                Some(_) => return Err(VMError::InvalidBitrange),
+                None => self.unresolved_constraints += 1,
+            }
+            self.push_item(expr);
+            return Ok(());
+        }
         //  println!("Expression: {:?}", expr);
         let res = self.add_range_proof(expr.clone())?;
         //  println!("add range: {:?}", res);
@@ -514,6 +621,14 @@ where
 
     fn verify(&mut self) -> Result<(), VMError> {
         let constraint = self.pop_item()?.to_constraint()?;
+        if self.eval_only {
+            match constraint.assignment() {
+                Some(true) => (),
+                Some(false) => return Err(VMError::CleartextConstraintFalse),
+                None => self.unresolved_constraints += 1,
+            }
+            return Ok(());
+        }
         constraint.verify(self.delegate.cs())?;
         println!("Stack : {:?}", self.stack);
         Ok(())
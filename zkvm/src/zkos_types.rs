@@ -6,11 +6,13 @@ use core::panic;
 //use crate::readerwriter::{Encodable, ExactSizeEncodable, Writer, WriteError};
 use crate::constraints::Commitment;
 use crate::encoding::*;
+use crate::proof_context::ProofContext;
+use crate::transcript::TranscriptProtocol;
 use crate::tx::TxID;
 use crate::types::String as ZkvmString;
 use bincode;
 use bincode::{deserialize, serialize};
-use bulletproofs::PedersenGens;
+use merlin::Transcript;
 use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
 use merkle::Hash;
 use quisquislib::accounts::{Account, SigmaProof};
@@ -18,24 +20,46 @@ use quisquislib::elgamal::ElGamalCommitment;
 use quisquislib::keys::PublicKey;
 use quisquislib::ristretto::RistrettoPublicKey;
 use quisquislib::ristretto::RistrettoSecretKey;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize};
 use zkschnorr::Signature;
 
+#[cfg(test)]
+mod arbitrary;
+pub mod compact;
+pub mod json;
+pub mod multisig;
+
+pub use multisig::MultisigWitness;
+
 /// Identification of unspend transaction output.
-#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+/// Human-readable formats serialize it as `<txid_hex>:<output_index>`,
+/// other formats keep the compact struct encoding.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Utxo {
     /// Hash of the transaction
     txid: TxID,
     /// Index of transaction output.
-    output_index: u8,
+    output_index: u16,
 }
 
 impl Utxo {
-    pub const fn new(txid: TxID, output_index: u8) -> Self {
+    /// Length of the bincode encoding, the txid followed by the `u16` index.
+    pub const ENCODED_LEN: usize = 34;
+    /// Length of the encoding written while the index was a `u8`.
+    pub const LEGACY_ENCODED_LEN: usize = 33;
+    /// Index bit reserved for [`Utxo::pending`] references.
+    pub const PENDING_FLAG: u16 = 0x8000;
+
+    pub const fn new(txid: TxID, output_index: u16) -> Self {
         Self { txid, output_index }
     }
 
-    pub const fn from_hash(hash: Hash, output_index: u8) -> Self {
+    pub const fn from_hash(hash: Hash, output_index: u16) -> Self {
         Self {
             txid: TxID(hash),
             output_index,
@@ -51,10 +75,24 @@ impl Utxo {
     }
 
     // Create a Utxo struct from Vec<u8>
+    // Keys persisted with a single byte index are read as well
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() == Self::LEGACY_ENCODED_LEN {
+            let (txid, index): (TxID, u8) = deserialize(bytes).ok()?;
+            return Some(Utxo::new(txid, index as u16));
+        }
         deserialize(bytes).ok()
     }
 
+    /// Re-encodes a utxo key persisted with a single byte index, other keys are kept.
+    /// The `u16` index is little endian, so the key only gains a zero byte.
+    pub fn upgrade_key(mut key: Vec<u8>) -> Vec<u8> {
+        if key.len() == Self::LEGACY_ENCODED_LEN {
+            key.push(0);
+        }
+        key
+    }
+
     pub fn from_hex(hex: &str) -> Option<Self> {
         let bytes = hex::decode(hex).ok()?;
         Self::from_bytes(&bytes)
@@ -64,13 +102,49 @@ impl Utxo {
         &self.txid
     }
 
-    pub const fn output_index(&self) -> u8 {
+    pub const fn output_index(&self) -> u16 {
         self.output_index
     }
 
     pub fn replace_tx_id(&mut self, tx_id: TxID) {
         self.txid = tx_id;
     }
+
+    /// Refers to output `output_index` of the transaction carrying the input.
+    /// The txid is only known once the transaction is complete, so the reference
+    /// is the zero txid with [`Utxo::PENDING_FLAG`] set on the index, which keeps it
+    /// apart from the default utxo of decoys and new States.
+    /// `output_index` must be below `PENDING_FLAG`, transactions are limited to fewer
+    /// outputs.
+    pub const fn pending(output_index: u16) -> Self {
+        debug_assert!(output_index & Self::PENDING_FLAG == 0);
+        Self {
+            txid: TxID(Hash([0; 32])),
+            output_index: output_index | Self::PENDING_FLAG,
+        }
+    }
+
+    /// Index of the output of the same transaction a pending utxo refers to.
+    pub fn pending_index(&self) -> Option<u16> {
+        if self.txid.0 .0 == [0; 32] && self.output_index & Self::PENDING_FLAG != 0 {
+            Some(self.output_index & !Self::PENDING_FLAG)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending_index().is_some()
+    }
+
+    /// Resolves a pending utxo to the output of the transaction `txid`.
+    /// Other utxos are returned as is.
+    pub fn resolve(&self, txid: TxID) -> Utxo {
+        match self.pending_index() {
+            Some(output_index) => Utxo::new(txid, output_index),
+            None => *self,
+        }
+    }
     pub fn tx_id_to_hex(&self) -> String {
         hex::encode(self.txid.0 .0)
     }
@@ -89,6 +163,110 @@ impl Utxo {
         }
     }
 }
+impl fmt::Display for Utxo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.output_index)
+    }
+}
+
+/// Parses `<txid_hex>:<output_index>`.
+/// Falls back to the hex of the bincode encoded Utxo returned by `to_hex`.
+impl FromStr for Utxo {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(':') {
+            Some((txid, index)) => Ok(Utxo {
+                txid: txid.parse()?,
+                output_index: index.parse().map_err(|_| "Error::InvalidUtxoOutputIndex")?,
+            }),
+            None => Utxo::from_hex(s).ok_or("Error::InvalidUtxo"),
+        }
+    }
+}
+
+impl Serialize for Utxo {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            let mut state = serializer.serialize_struct("Utxo", 2)?;
+            state.serialize_field("txid", &self.txid)?;
+            state.serialize_field("output_index", &self.output_index)?;
+            state.end()
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Utxo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(UtxoVisitor)
+        } else {
+            deserializer.deserialize_struct("Utxo", &["txid", "output_index"], UtxoVisitor)
+        }
+    }
+}
+
+// reads the string form, the compact form and the struct form written by older JSON encoders
+struct UtxoVisitor;
+
+impl<'de> Visitor<'de> for UtxoVisitor {
+    type Value = Utxo;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a utxo as <txid_hex>:<output_index>")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Utxo, E>
+    where
+        E: de::Error,
+    {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Utxo, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let txid = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let output_index = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Utxo { txid, output_index })
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Utxo, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut txid: Option<TxID> = None;
+        let mut output_index: Option<u16> = None;
+        while let Some(key) = map.next_key::<std::string::String>()? {
+            match key.as_str() {
+                "txid" => txid = Some(map.next_value()?),
+                "output_index" => output_index = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Utxo {
+            txid: txid.ok_or_else(|| de::Error::missing_field("txid"))?,
+            output_index: output_index.ok_or_else(|| de::Error::missing_field("output_index"))?,
+        })
+    }
+}
+
 ///Default returns a Utxo with id = 0 and witness index = 0
 impl Default for Utxo {
     fn default() -> Utxo {
@@ -517,6 +695,45 @@ impl Input {
             } => *witness = witness_index,
         }
     }
+
+    /// Nullifier of a coin input, the hash of the txid and index of its utxo and of its
+    /// owner. Two coin inputs spend the same coin iff their nullifiers are equal.
+    ///
+    /// Returns None for the default utxo of fresh accounts, for pending references and
+    /// for Memo and State inputs. The witness index is not bound by the proofs and takes
+    /// no part in the nullifier.
+    pub fn nullifier(&self) -> Option<Hash> {
+        match self.input {
+            InputData::Coin {
+                ref utxo,
+                ref out_coin,
+                ..
+            } => {
+                if *utxo == Utxo::default() || utxo.is_pending() {
+                    return None;
+                }
+                let mut t = Transcript::new(b"ZkOS.nullifier");
+                t.append_message(b"txid", &utxo.tx_id().0 .0);
+                t.append_u64(b"index", utxo.output_index() as u64);
+                t.append_message(b"owner", out_coin.owner.as_bytes());
+                Some(Hash(t.challenge_u8x32(b"nullifier")))
+            }
+            _ => None,
+        }
+    }
+
+    /// Input spending the output of the transaction `txid` its pending utxo refers to.
+    /// See [`Utxo::resolve`].
+    pub fn resolve_pending(&self, txid: TxID) -> Input {
+        let mut input = self.clone();
+        match input.input {
+            InputData::Coin { ref mut utxo, .. }
+            | InputData::Memo { ref mut utxo, .. }
+            | InputData::State { ref mut utxo, .. } => *utxo = utxo.resolve(txid),
+        }
+        input
+    }
+
     /// function to return the encrypted values for the input to be placed in transaction
     ///
     pub fn verifier_view(&self) -> Input {
@@ -741,7 +958,7 @@ impl OutputMemo {
         OutputMemo {
             script_address: self.script_address.clone(),
             owner: self.owner.clone(),
-            commitment: Commitment::Closed(self.commitment.clone().to_point()),
+            commitment: Commitment::Closed(self.commitment.to_point()),
             data: data_str,
             timebounds: self.timebounds,
         }
@@ -800,6 +1017,23 @@ impl Default for OutputState {
     }
 }
 impl OutputState {
+    pub fn new(
+        nonce: u32,
+        script_address: String,
+        owner: String,
+        commitment: Commitment,
+        state_variables: Option<Vec<ZkvmString>>,
+        timebounds: u32,
+    ) -> Self {
+        Self {
+            nonce,
+            script_address,
+            owner,
+            commitment,
+            state_variables,
+            timebounds,
+        }
+    }
     /// needed at the time of signing and creating the input vector for tx
     pub fn verifier_view(&self) -> Self {
         // convert the value commitmen to point
@@ -832,7 +1066,7 @@ impl OutputState {
             nonce: self.nonce,
             script_address: self.script_address.clone(),
             owner: self.owner.clone(),
-            commitment: Commitment::Closed(self.commitment.clone().to_point()),
+            commitment: Commitment::Closed(self.commitment.to_point()),
             state_variables: state_var,
             timebounds: self.timebounds,
         }
@@ -926,7 +1160,9 @@ impl OutputData {
     }
 }
 /// A complete twilight typed Output valid for a specific network.
+/// Deserialization fails if the output type does not match the data.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedOutput")]
 pub struct Output {
     /// Defines the output type.
     pub out_type: IOType,
@@ -934,28 +1170,65 @@ pub struct Output {
     pub output: OutputData,
 }
 
+// Wire format of Output, before the type is checked
+#[derive(Deserialize)]
+struct UncheckedOutput {
+    out_type: IOType,
+    output: OutputData,
+}
+
+impl TryFrom<UncheckedOutput> for Output {
+    type Error = VMError;
+
+    fn try_from(output: UncheckedOutput) -> Result<Self, Self::Error> {
+        let output = Output {
+            out_type: output.out_type,
+            output: output.output,
+        };
+        output.validate_type_consistency()?;
+        Ok(output)
+    }
+}
+
 impl Output {
-    /// Create a output of Coin which is valid on the given network.
-    pub fn coin(data: OutputData) -> Output {
-        Output {
-            out_type: IOType::default(),
+    // panics if `data` is not of `out_type`, the constructors are only called with matching data
+    fn typed(out_type: IOType, data: OutputData) -> Output {
+        let output = Output {
+            out_type,
             output: data,
+        };
+        if let Err(err) = output.validate_type_consistency() {
+            panic!("{}: {:?} output of {:?}", err, out_type, output.output);
         }
+        output
+    }
+
+    /// Create a output of Coin which is valid on the given network.
+    /// Panics if `data` is not coin data.
+    pub fn coin(data: OutputData) -> Output {
+        Output::typed(IOType::Coin, data)
     }
 
     /// Create a output of Memo which is valid on the given network.
+    /// Panics if `data` is not memo data.
     pub fn memo(data: OutputData) -> Output {
-        Output {
-            out_type: IOType::Memo,
-            output: data,
-        }
+        Output::typed(IOType::Memo, data)
     }
 
     /// Create a output of State which is valid on the given network.
+    /// Panics if `data` is not state data.
     pub fn state(data: OutputData) -> Output {
-        Output {
-            out_type: IOType::State,
-            output: data,
+        Output::typed(IOType::State, data)
+    }
+
+    /// Checks that the output type matches the variant of the output data.
+    /// Outputs are stored and counted by their type.
+    pub fn validate_type_consistency(&self) -> Result<(), VMError> {
+        match (self.out_type, &self.output) {
+            (IOType::Coin, OutputData::Coin(_))
+            | (IOType::Memo, OutputData::Memo(_))
+            | (IOType::State, OutputData::State(_)) => Ok(()),
+            _ => Err(VMError::OutputTypeMismatch),
         }
     }
 
@@ -1023,6 +1296,47 @@ impl Output {
             _ => self.clone(),
         }
     }
+
+    /// Checks the data of a memo or the state variables of a state against `limits`.
+    pub fn validate_data_size(&self, limits: &OutputDataLimits) -> Result<(), VMError> {
+        match self.output {
+            OutputData::Coin(_) => Ok(()),
+            OutputData::Memo(ref memo) => limits.check(&memo.data),
+            OutputData::State(ref state) => limits.check(&state.state_variables),
+        }
+    }
+
+    /// Checks that the output can be stored and used later.
+    /// Checks the type of the output, decompresses the ElGamal encryption of coins and the
+    /// commitments of memos and states, parses the owner and script addresses and bounds
+    /// the data of memos and states by the default [`OutputDataLimits`].
+    pub fn validate(&self) -> Result<(), VMError> {
+        self.validate_type_consistency()?;
+        match self.output {
+            OutputData::Coin(ref coin) => {
+                validate_encryption(&coin.encrypt)?;
+                if multisig::is_multisig_owner(&coin.owner) {
+                    validate_multisig_address(&coin.owner)
+                } else {
+                    validate_standard_address(&coin.owner)
+                }
+            }
+            OutputData::Memo(ref memo) => {
+                OutputDataLimits::default().check(&memo.data)?;
+                validate_commitment(&memo.commitment)?;
+                validate_data_commitments(&memo.data)?;
+                validate_standard_address(&memo.owner)?;
+                validate_script_address(&memo.script_address)
+            }
+            OutputData::State(ref state) => {
+                OutputDataLimits::default().check(&state.state_variables)?;
+                validate_commitment(&state.commitment)?;
+                validate_data_commitments(&state.state_variables)?;
+                validate_standard_address(&state.owner)?;
+                validate_script_address(&state.script_address)
+            }
+        }
+    }
     /// Create a output of Coin which is valid on the given Network
     fn output_from_account(account: Account, net: address::Network) -> Self {
         let (pk, comm) = account.get_account();
@@ -1035,6 +1349,133 @@ impl Output {
     }
 }
 
+/// Default maximum number of elements of the data of a memo or the state variables of a state.
+pub const MAX_OUTPUT_DATA_ELEMENTS: usize = 64;
+/// Default maximum encoded size of a data element, in bytes.
+pub const MAX_OUTPUT_DATA_ELEMENT_SIZE: usize = 1024;
+/// Default maximum encoded size of all data elements of an output, in bytes.
+pub const MAX_OUTPUT_DATA_SIZE: usize = 16 * 1024;
+
+/// Bounds of the data of a memo and the state variables of a state.
+/// Every node holds the outputs of the utxo set in memory, a memo or state
+/// carrying unbounded data would be held by all of them until it is spent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputDataLimits {
+    /// Maximum number of elements
+    pub max_elements: usize,
+    /// Maximum encoded size of an element, in bytes
+    pub max_element_size: usize,
+    /// Maximum encoded size of all elements, in bytes
+    pub max_output_size: usize,
+}
+
+impl Default for OutputDataLimits {
+    fn default() -> Self {
+        OutputDataLimits {
+            max_elements: MAX_OUTPUT_DATA_ELEMENTS,
+            max_element_size: MAX_OUTPUT_DATA_ELEMENT_SIZE,
+            max_output_size: MAX_OUTPUT_DATA_SIZE,
+        }
+    }
+}
+
+impl OutputDataLimits {
+    /// Checks the elements of `data` against the limits.
+    /// Fails with `DataTooLarge` naming the first limit exceeded.
+    pub fn check(&self, data: &Option<Vec<ZkvmString>>) -> Result<(), VMError> {
+        let data = match data {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        if data.len() > self.max_elements {
+            return Err(VMError::DataTooLarge {
+                which: "data_elements",
+                size: data.len(),
+                limit: self.max_elements,
+            });
+        }
+        let mut output_size = 0usize;
+        for item in data.iter() {
+            let size = item.encoded_size();
+            if size > self.max_element_size {
+                return Err(VMError::DataTooLarge {
+                    which: "data_element_size",
+                    size,
+                    limit: self.max_element_size,
+                });
+            }
+            output_size += size;
+        }
+        if output_size > self.max_output_size {
+            return Err(VMError::DataTooLarge {
+                which: "data_size",
+                size: output_size,
+                limit: self.max_output_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn validate_encryption(encrypt: &ElGamalCommitment) -> Result<(), VMError> {
+    for point in encrypt.to_bytes().chunks(32) {
+        CompressedRistretto::from_slice(point)
+            .decompress()
+            .ok_or(VMError::InvalidOutputEncryption)?;
+    }
+    Ok(())
+}
+
+fn validate_commitment(commitment: &Commitment) -> Result<(), VMError> {
+    commitment
+        .to_point()
+        .decompress()
+        .ok_or(VMError::InvalidOutputCommitment)?;
+    Ok(())
+}
+
+fn validate_data_commitments(data: &Option<Vec<ZkvmString>>) -> Result<(), VMError> {
+    if let Some(data) = data {
+        for item in data.iter() {
+            if let ZkvmString::Commitment(commitment) = item {
+                validate_commitment(commitment)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Standard address bytes: [magic byte, public key, checksum]
+fn validate_standard_address(owner: &str) -> Result<(), VMError> {
+    let bytes = hex::decode(owner).map_err(|_| VMError::InvalidOutputAddress)?;
+    if bytes.len() != 69 {
+        return Err(VMError::InvalidOutputAddress);
+    }
+    address::Standard::from_bytes(&bytes).map_err(|_| VMError::InvalidOutputAddress)?;
+    Ok(())
+}
+
+// Multisig address bytes: [magic byte, key set tree root, checksum]
+fn validate_multisig_address(owner: &str) -> Result<(), VMError> {
+    let bytes = hex::decode(owner).map_err(|_| VMError::InvalidOutputAddress)?;
+    address::Multisig::from_bytes(&bytes).map_err(|_| VMError::InvalidOutputAddress)?;
+    Ok(())
+}
+
+// Script address bytes: [magic byte, RIPEMD-160 of the script root]
+fn validate_script_address(script_address: &str) -> Result<(), VMError> {
+    let bytes = hex::decode(script_address).map_err(|_| VMError::InvalidOutputAddress)?;
+    if bytes.len() != 21 {
+        return Err(VMError::InvalidOutputAddress);
+    }
+    let network =
+        address::Network::from_u8(bytes[0]).map_err(|_| VMError::InvalidOutputAddress)?;
+    match address::AddressType::from_slice(&bytes, network) {
+        Ok(address::AddressType::Script) => Ok(()),
+        _ => Err(VMError::InvalidOutputAddress),
+    }
+}
+
 //Upcast OutputCoin to Output
 impl From<OutputCoin> for Output {
     fn from(x: OutputCoin) -> Self {
@@ -1059,12 +1500,16 @@ impl From<OutputState> for Output {
 pub enum Witness {
     // ZkSchnorr Signature
     Signature(Signature),
-    //Zero balance proof for TransferTx. Same value proof in case of memo input
+    // Same value proof in case of memo input
     Proof(SigmaProof),
     // Signature and proof over Coin<->Memo pairs for same value in ScriptTx
     ValueWitness(ValueWitness),
     // Signature and Proof over state inputs<-> ScriptTx
     State(StateWitness),
+    // m-of-n signatures and key set path over multisig coin inputs
+    Multisig(MultisigWitness),
+    // Zero balance proof of a fresh receiver account, i.e., an input with the default Utxo in TransferTx
+    ZeroBalanceProof(SigmaProof),
 }
 use crate::VMError;
 impl Witness {
@@ -1100,6 +1545,20 @@ impl Witness {
             _ => Err(VMError::TypeNotSigmaProof),
         }
     }
+    /// Downcasts Witness to the zero balance `SigmaProof` of a fresh receiver account.
+    pub fn to_zero_balance_proof(&self) -> Result<SigmaProof, VMError> {
+        match self {
+            Witness::ZeroBalanceProof(x) => Ok(x.clone()),
+            _ => Err(VMError::TypeNotZeroBalanceProof),
+        }
+    }
+    /// Downcasts Witness to `MultisigWitness` type.
+    pub fn to_multisig_witness(&self) -> Result<MultisigWitness, VMError> {
+        match self {
+            Witness::Multisig(x) => Ok(x.clone()),
+            _ => Err(VMError::TypeNotMultisigWitness),
+        }
+    }
     /// used for creating the value witness when the input to the tx is a memo
     /// returns the Same value proof and Signature
     /// @param enc_acc: Account
@@ -1217,6 +1676,13 @@ impl From<SigmaProof> for Witness {
     }
 }
 
+// Upcast MultisigWitness to Witness
+impl From<MultisigWitness> for Witness {
+    fn from(x: MultisigWitness) -> Self {
+        Witness::Multisig(x)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValueWitness {
     sign: Signature,
@@ -1320,13 +1786,16 @@ impl StateWitness {
         &self.sign
     }
 
+    /// Signs the verifier view of the input State and output State with `secret_key`.
+    /// On a contract deploy the input State must carry open commitments to zero,
+    /// whose blindings are the zero proofs.
     pub fn create_state_witness(
         input: &Input,
         output: &Output,
         secret_key: RistrettoSecretKey,
         pubkey: RistrettoPublicKey,
         contract_deploy_flag: bool,
-    ) -> Self {
+    ) -> Result<Self, &'static str> {
         //create the Signature over the Input State with the secret key
 
         //create input State for verifier view
@@ -1341,60 +1810,55 @@ impl StateWitness {
 
         //create message bytes using input_state + output_state
         let mut message: Vec<u8>;
-        message = bincode::serialize(&verifier_input).unwrap();
-        message.extend(bincode::serialize(&verifier_output).unwrap());
+        message = bincode::serialize(&verifier_input)
+            .map_err(|_| "Serialization Error::Failed to serialize the input for signing")?;
+        message.extend(
+            bincode::serialize(&verifier_output)
+                .map_err(|_| "Serialization Error::Failed to serialize the output for signing")?,
+        );
 
         //println!("message  {:?}", message);
         let sign = pubkey.sign_msg(&message, &secret_key, ("StateSign").as_bytes());
         // Contract calling
         if !contract_deploy_flag {
-            return Self {
+            return Ok(Self {
                 sign,
                 zero_proof: None,
-            };
-        } else {
-            // create the zero proof over the input state commitment and state variables
-            let state_var = input.as_out_state().unwrap();
-            let state_commitment = state_var.clone().commitment;
-            // get value, witness for state commitment
-            let (state_value, state_value_blinding) = state_commitment.witness().unwrap();
-            // check if value is zero
-            if state_value != 0.into() {
-                panic!("Error::The value of the state commitment is not zero");
-            }
-            // create the zero proof over the state commitment and state variables
-            let mut zero_proof: Vec<Scalar> = Vec::new();
-            zero_proof.push(state_value_blinding);
-            // get the state variables
-            let state_variables = state_var.state_variables.as_ref();
-            match state_variables {
-                Some(x) => {
-                    // loop over the state variables and create the zero proofs
-                    for state_variable in x.iter() {
-                        match state_variable {
-                            ZkvmString::Commitment(commitment) => {
-                                let (value, blinding) = commitment.witness().unwrap();
-                                if value != 0.into() {
-                                    panic!("Error::The value of the state variable is not zero");
-                                }
-                                zero_proof.push(blinding);
-                            }
-                            _ => {}
-                        }
-                    }
-                    return Self {
-                        sign,
-                        zero_proof: Some(zero_proof),
-                    };
-                }
-                None => {
-                    return Self {
-                        sign,
-                        zero_proof: Some(zero_proof),
-                    };
-                }
+            });
+        }
+        // create the zero proof over the input state commitment and state variables
+        let state_var = input
+            .as_out_state()
+            .ok_or("Error::State witness can only be created for State inputs")?;
+        // get value, witness for state commitment
+        let (state_value, state_value_blinding) = state_var
+            .commitment
+            .witness()
+            .ok_or("Error::The state commitment is not open")?;
+        // check if value is zero
+        if state_value != 0.into() {
+            return Err("Error::The value of the state commitment is not zero");
+        }
+        let mut zero_proof: Vec<Scalar> = vec![state_value_blinding];
+        // only committed state variables carry a zero proof
+        let commitments = state_var
+            .state_variables
+            .iter()
+            .flatten()
+            .filter_map(|state_variable| state_variable.as_commitment().ok());
+        for commitment in commitments {
+            let (value, blinding) = commitment
+                .witness()
+                .ok_or("Error::The state variable commitment is not open")?;
+            if value != 0.into() {
+                return Err("Error::The value of the state variable is not zero");
             }
+            zero_proof.push(blinding);
         }
+        Ok(Self {
+            sign,
+            zero_proof: Some(zero_proof),
+        })
     }
     /// verify_state_witness verifies the zero value proof and signature
     /// invoked if a new contract has to be deployed.
@@ -1435,21 +1899,28 @@ impl StateWitness {
             // Contract is being deployed so verify the zero proofs
             // Both for state commitments and state variables
             let in_state = input.input;
+            let commitment_witness = self
+                .zero_proof
+                .as_ref()
+                .ok_or("Error::The state witness carries no zero proofs")?;
             // the first index of zero_proof is the Zero commitment on Value state
-            let zero_witness_proof_scalar = self.zero_proof.as_ref().unwrap()[0].clone();
+            let zero_witness_proof_scalar = commitment_witness
+                .first()
+                .ok_or("Error::The state witness carries no zero proofs")?;
             // recreate the commitment using the zero scalar
-            let gens = PedersenGens::default();
-            let proof = gens.commit(0u64.into(), zero_witness_proof_scalar);
-            let state_commit = in_state.as_commitment().unwrap().to_point();
+            let gens = ProofContext::global().pc_gens();
+            let proof = gens.commit(0u64.into(), *zero_witness_proof_scalar);
+            let state_commit = in_state
+                .as_commitment()
+                .ok_or("Error::The input is not a State")?
+                .to_point();
             // compare the zero committed points
             if state_commit != proof.compress() {
                 return Err("Error::The zero proof does not match the state commitment");
             }
             //get extra state_variables if available
-            if in_state.as_state_variables().is_some() {
+            if let Some(state_variables) = in_state.as_state_variables() {
                 //verify the zero proofs if any over the state variables
-                let state_variables = in_state.as_state_variables().unwrap();
-                let commitment_witness = self.zero_proof.as_ref().unwrap();
                 if commitment_witness.len() - 1 > state_variables.len() {
                     return Err("Error::There are more zero proofs than state variables");
                 }
@@ -1457,22 +1928,18 @@ impl StateWitness {
                 let mut index: usize = 1;
 
                 for variable in state_variables {
-                    match variable {
-                        ZkvmString::Commitment(x) => {
-                            let state_comit = x.to_point();
-                            //recreate commitment using 0 as value and scalar from the proof
-                            let proof_point = gens
-                                .commit(0u64.into(), commitment_witness[index].clone())
-                                .compress();
-                            //verify the proof
-                            if state_comit != proof_point {
-                                return Err(
-                                    "Error::The zero proof does not match the state variable",
-                                );
-                            }
-                            index += 1;
+                    if let Ok(x) = variable.as_commitment() {
+                        let state_comit = x.to_point();
+                        let zero_scalar = commitment_witness
+                            .get(index)
+                            .ok_or("Error::There are fewer zero proofs than state commitments")?;
+                        //recreate commitment using 0 as value and scalar from the proof
+                        let proof_point = gens.commit(0u64.into(), *zero_scalar).compress();
+                        //verify the proof
+                        if state_comit != proof_point {
+                            return Err("Error::The zero proof does not match the state variable");
                         }
-                        _ => {}
+                        index += 1;
                     }
                 }
             }
@@ -1481,6 +1948,64 @@ impl StateWitness {
         }
     }
 }
+// owner rotation of State outputs
+impl StateWitness {
+    /// Returns true if the output state is owned by a different address than the input state.
+    pub fn is_owner_rotation(input: &Input, output: &Output) -> bool {
+        match (input.as_out_state(), output.as_out_state()) {
+            (Some(in_state), Some(out_state)) => in_state.owner != out_state.owner,
+            _ => false,
+        }
+    }
+
+    /// Creates the domain separated message signed by the previous owner to approve a rotation.
+    /// The message binds the old owner, new owner, script address and the nonce of the output state.
+    pub fn owner_rotation_message(input: &Input, output: &Output) -> Result<Vec<u8>, &'static str> {
+        let in_state = input
+            .as_out_state()
+            .ok_or("Error::Owner rotation is only allowed for State inputs")?;
+        let out_state = output
+            .as_out_state()
+            .ok_or("Error::Owner rotation is only allowed for State outputs")?;
+        let mut message: Vec<u8> = b"ZkOS.OwnerRotation".to_vec();
+        message.extend(
+            bincode::serialize(&(
+                &in_state.owner,
+                &out_state.owner,
+                &out_state.script_address,
+                out_state.nonce,
+            ))
+            .map_err(|_| "Serialization Error::Failed to serialize the owner rotation message")?,
+        );
+        Ok(message)
+    }
+
+    /// Creates the signature of the previous owner approving the change of owner
+    /// from the input state to the output state.
+    pub fn create_owner_rotation(
+        input: &Input,
+        output: &Output,
+        secret_key: RistrettoSecretKey,
+        pubkey: RistrettoPublicKey,
+    ) -> Result<Witness, &'static str> {
+        let message = StateWitness::owner_rotation_message(input, output)?;
+        let sign = pubkey.sign_msg(&message, &secret_key, ("OwnerRotation").as_bytes());
+        Ok(Witness::Signature(sign))
+    }
+
+    /// Verifies the rotation signature against the public key of the previous owner.
+    pub fn verify_owner_rotation(
+        input: &Input,
+        output: &Output,
+        sign: &Signature,
+        pubkey: RistrettoPublicKey,
+    ) -> Result<(), &'static str> {
+        let message = StateWitness::owner_rotation_message(input, output)?;
+        pubkey
+            .verify_msg(&message, sign, ("OwnerRotation").as_bytes())
+            .map_err(|_| "Owner rotation signature verification failed")
+    }
+}
 //return iterator over zero proofs  (if any)
 impl StateWitness {
     pub fn get_zero_proof(&self) -> Option<impl Iterator<Item = &Scalar>> {
@@ -0,0 +1,386 @@
+//! Property tests of the core zkos types, with their `Arbitrary` instances.
+//!
+//! Keys, points and addresses are derived from generated seeds and scalars, so every
+//! generated value holds valid curve points and addresses. The strategies are built
+//! from proptest primitives only, a failing case shrinks to small seeds, values and
+//! data lists.
+
+use super::*;
+use crate::ScalarWitness;
+use bulletproofs::PedersenGens;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use quisquislib::accounts::Prover;
+use quisquislib::keys::SecretKey;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const SIGN_LABEL: &[u8] = b"PropertyTest";
+
+// key pair of a seed
+fn key_pair(seed: [u8; 32]) -> (RistrettoSecretKey, RistrettoPublicKey) {
+    let mut rng = StdRng::from_seed(seed);
+    let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+    let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+    (sk, pk)
+}
+
+fn scalar() -> impl Strategy<Value = Scalar> {
+    any::<[u8; 32]>().prop_map(Scalar::from_bytes_mod_order)
+}
+
+fn network() -> impl Strategy<Value = address::Network> {
+    prop_oneof![
+        Just(address::Network::Mainnet),
+        Just(address::Network::Testnet)
+    ]
+}
+
+fn owner() -> impl Strategy<Value = String> {
+    (network(), any::<[u8; 32]>())
+        .prop_map(|(net, seed)| address::Address::standard_address(net, key_pair(seed).1).as_hex())
+}
+
+fn script_address() -> impl Strategy<Value = String> {
+    (network(), any::<[u8; 32]>())
+        .prop_map(|(net, root)| address::Address::script_address(net, root).as_hex())
+}
+
+fn commitment() -> impl Strategy<Value = Commitment> {
+    (any::<u64>(), scalar(), any::<bool>()).prop_map(|(value, blinding, closed)| {
+        let commitment = Commitment::blinded_with_factor(value, blinding);
+        if closed {
+            Commitment::Closed(commitment.to_point())
+        } else {
+            commitment
+        }
+    })
+}
+
+fn data_item() -> impl Strategy<Value = ZkvmString> {
+    prop_oneof![
+        commitment().prop_map(ZkvmString::from),
+        scalar().prop_map(|s| ZkvmString::Scalar(Box::new(ScalarWitness::from(s)))),
+        any::<u64>().prop_map(ZkvmString::U64),
+        any::<u32>().prop_map(ZkvmString::U32),
+        vec(any::<u8>(), 0..32).prop_map(ZkvmString::Opaque),
+    ]
+}
+
+fn data() -> impl Strategy<Value = Option<Vec<ZkvmString>>> {
+    option::of(vec(data_item(), 0..4))
+}
+
+fn signature() -> impl Strategy<Value = Signature> {
+    (any::<[u8; 32]>(), vec(any::<u8>(), 0..64)).prop_map(|(seed, message)| {
+        let (sk, pk) = key_pair(seed);
+        pk.sign_msg(&message, &sk, SIGN_LABEL)
+    })
+}
+
+// same value proof of a coin encryption and a commitment to its value
+fn value_proof() -> impl Strategy<Value = SigmaProof> {
+    (any::<[u8; 32]>(), scalar(), any::<u64>(), scalar()).prop_map(
+        |(seed, randomness, value, blinding)| {
+            let pk = key_pair(seed).1;
+            let value = Scalar::from(value);
+            let encrypt = ElGamalCommitment::generate_commitment(&pk, randomness, value);
+            let commitment = PedersenGens::default().commit(value, blinding).compress();
+            Prover::same_value_compact_prover(
+                Account::set_account(pk, encrypt),
+                blinding,
+                value,
+                commitment,
+            )
+        },
+    )
+}
+
+impl Arbitrary for Utxo {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (any::<[u8; 32]>(), any::<u16>())
+            .prop_map(|(txid, index)| Utxo::from_hash(Hash(txid), index))
+            .boxed()
+    }
+}
+
+impl Arbitrary for OutputCoin {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (network(), any::<[u8; 32]>(), scalar(), any::<u64>())
+            .prop_map(|(net, seed, randomness, value)| {
+                let pk = key_pair(seed).1;
+                let encrypt =
+                    ElGamalCommitment::generate_commitment(&pk, randomness, Scalar::from(value));
+                OutputCoin::new(
+                    encrypt,
+                    address::Address::standard_address(net, pk).as_hex(),
+                )
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for OutputMemo {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            script_address(),
+            owner(),
+            commitment(),
+            data(),
+            any::<u32>(),
+        )
+            .prop_map(|(script_address, owner, commitment, data, timebounds)| {
+                OutputMemo::new(script_address, owner, commitment, data, timebounds)
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for OutputState {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (
+            any::<u32>(),
+            script_address(),
+            owner(),
+            commitment(),
+            data(),
+            any::<u32>(),
+        )
+            .prop_map(
+                |(nonce, script_address, owner, commitment, state_variables, timebounds)| {
+                    OutputState::new(
+                        nonce,
+                        script_address,
+                        owner,
+                        commitment,
+                        state_variables,
+                        timebounds,
+                    )
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Output {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            any::<OutputCoin>().prop_map(Output::from),
+            any::<OutputMemo>().prop_map(Output::from),
+            any::<OutputState>().prop_map(Output::from),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Input {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            (any::<Utxo>(), any::<OutputCoin>(), any::<u8>())
+                .prop_map(|(utxo, coin, witness)| coin.to_input(utxo, witness)),
+            (
+                any::<Utxo>(),
+                any::<OutputMemo>(),
+                any::<u8>(),
+                option::of(commitment())
+            )
+                .prop_map(|(utxo, memo, witness, coin_value)| {
+                    Input::memo(InputData::memo(utxo, memo, witness, coin_value))
+                }),
+            (any::<Utxo>(), any::<OutputState>(), data(), any::<u8>()).prop_map(
+                |(utxo, state, script_data, witness)| {
+                    Input::state(InputData::state(utxo, state, script_data, witness))
+                }
+            ),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Witness {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            signature().prop_map(Witness::Signature),
+            value_proof().prop_map(Witness::Proof),
+            value_proof().prop_map(Witness::ZeroBalanceProof),
+            (signature(), value_proof()).prop_map(|(sign, proof)| {
+                Witness::ValueWitness(ValueWitness::set_value_witness(sign, proof))
+            }),
+            (signature(), option::of(vec(scalar(), 0..3))).prop_map(|(sign, zero_proof)| {
+                Witness::State(StateWitness::set_state_witness(sign, zero_proof))
+            }),
+        ]
+        .boxed()
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn utxo_round_trip_test(utxo in any::<Utxo>()) {
+            let bytes = bincode::serialize(&utxo).unwrap();
+            prop_assert_eq!(bytes.len(), Utxo::ENCODED_LEN);
+            prop_assert_eq!(bincode::deserialize::<Utxo>(&bytes).unwrap(), utxo);
+            prop_assert_eq!(Utxo::from_bytes(&utxo.to_bytes()), Some(utxo));
+            prop_assert_eq!(Utxo::from_hex(&utxo.to_hex()), Some(utxo));
+            prop_assert_eq!(utxo.to_string().parse::<Utxo>().unwrap(), utxo);
+        }
+
+        #[test]
+        fn output_round_trip_test(output in any::<Output>()) {
+            let bytes = bincode::serialize(&output).unwrap();
+            prop_assert_eq!(bincode::deserialize::<Output>(&bytes).unwrap(), output.clone());
+            let compact = output.to_compact_bytes().unwrap();
+            prop_assert_eq!(Output::from_compact_bytes(&compact).unwrap(), output);
+        }
+
+        #[test]
+        fn input_round_trip_test(input in any::<Input>()) {
+            // inputs and witnesses have no equality, their encodings are compared
+            let bytes = bincode::serialize(&input).unwrap();
+            let decoded: Input = bincode::deserialize(&bytes).unwrap();
+            prop_assert_eq!(bincode::serialize(&decoded).unwrap(), bytes);
+        }
+
+        #[test]
+        fn witness_round_trip_test(witness in any::<Witness>()) {
+            let bytes = bincode::serialize(&witness).unwrap();
+            let decoded: Witness = bincode::deserialize(&bytes).unwrap();
+            prop_assert_eq!(bincode::serialize(&decoded).unwrap(), bytes);
+        }
+
+        #[test]
+        fn verifier_view_idempotence_test(output in any::<Output>(), input in any::<Input>()) {
+            let view = output.to_verifier_view();
+            prop_assert_eq!(view.to_verifier_view(), view);
+            let view = bincode::serialize(&input.verifier_view()).unwrap();
+            let again = bincode::serialize(&input.verifier_view().verifier_view()).unwrap();
+            prop_assert_eq!(again, view);
+        }
+
+        #[test]
+        fn input_for_signing_test(input in any::<Input>()) {
+            let signing = input.as_input_for_signing();
+            prop_assert_eq!(signing.as_input_data().get_witness_index(), 0);
+            prop_assert_eq!(signing.as_utxo(), input.as_utxo());
+            prop_assert_eq!(signing.in_type, input.in_type);
+        }
+
+        #[test]
+        fn output_validate_test(output in any::<Output>()) {
+            prop_assert!(output.validate().is_ok());
+            prop_assert!(output.to_verifier_view().validate().is_ok());
+        }
+
+        #[test]
+        fn output_data_limits_test(memo in any::<OutputMemo>(), state in any::<OutputState>()) {
+            let limits = OutputDataLimits::default();
+            let opaque = |len: usize| ZkvmString::Opaque(vec![0u8; len]);
+            let max_size_elements = limits.max_output_size / limits.max_element_size;
+            let mut oversize = vec![opaque(limits.max_element_size); max_size_elements];
+            oversize.push(ZkvmString::U32(0));
+            // data at each limit and just over it
+            let cases = vec![
+                (vec![ZkvmString::U64(0); limits.max_elements], None),
+                (
+                    vec![ZkvmString::U64(0); limits.max_elements + 1],
+                    Some(("data_elements", limits.max_elements + 1, limits.max_elements)),
+                ),
+                (vec![opaque(limits.max_element_size)], None),
+                (
+                    vec![opaque(limits.max_element_size + 1)],
+                    Some((
+                        "data_element_size",
+                        limits.max_element_size + 1,
+                        limits.max_element_size,
+                    )),
+                ),
+                (vec![opaque(limits.max_element_size); max_size_elements], None),
+                (
+                    oversize,
+                    Some(("data_size", limits.max_output_size + 4, limits.max_output_size)),
+                ),
+            ];
+            for (data, exceeded) in cases {
+                let memo = Output::from(OutputMemo {
+                    data: Some(data.clone()),
+                    ..memo.clone()
+                });
+                let state = Output::from(OutputState {
+                    state_variables: Some(data),
+                    ..state.clone()
+                });
+                for output in [memo, state].iter() {
+                    let expected = match exceeded {
+                        Some((which, size, limit)) => {
+                            Err(VMError::DataTooLarge { which, size, limit })
+                        }
+                        None => Ok(()),
+                    };
+                    prop_assert_eq!(output.validate_data_size(&limits), expected.clone());
+                    prop_assert_eq!(output.validate(), expected);
+                }
+            }
+        }
+
+        #[test]
+        fn output_from_type_test(
+            coin in any::<OutputCoin>(),
+            memo in any::<OutputMemo>(),
+            state in any::<OutputState>(),
+        ) {
+            let outputs = [
+                (Output::from(coin), IOType::Coin),
+                (Output::from(memo), IOType::Memo),
+                (Output::from(state), IOType::State),
+            ];
+            for (output, out_type) in outputs.iter() {
+                prop_assert_eq!(output.out_type, *out_type);
+                prop_assert!(output.validate_type_consistency().is_ok());
+            }
+        }
+
+        #[test]
+        fn output_type_mismatch_test(output in any::<Output>(), shift in 1u8..3) {
+            let out_type = IOType::from_u8((output.out_type as u8 + shift) % 3).unwrap();
+            let mismatched = Output { out_type, ..output };
+            prop_assert_eq!(
+                mismatched.validate_type_consistency(),
+                Err(VMError::OutputTypeMismatch)
+            );
+            prop_assert_eq!(mismatched.validate(), Err(VMError::OutputTypeMismatch));
+            let bytes = bincode::serialize(&mismatched).unwrap();
+            prop_assert!(bincode::deserialize::<Output>(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Compact binary encoding of outputs, opted into by callers storing outputs on chain.
+//!
+//! The owner and script addresses are written as the tag and bytes of their fixed
+//! form, see [`address::compact`], instead of hex strings. The outputs keep the hex
+//! strings in memory, so their accessors and the default encoding do not change.
+//! The encoding starts with [`COMPACT_OUTPUT_VERSION`].
+
+use super::{Output, OutputCoin, OutputData, OutputMemo, OutputState};
+use crate::constraints::Commitment;
+use crate::types::String as ZkvmString;
+use crate::{IOType, VMError};
+use quisquislib::elgamal::ElGamalCommitment;
+use serde::{Deserialize, Serialize};
+
+/// Version of the compact output encoding.
+pub const COMPACT_OUTPUT_VERSION: u8 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct CompactCoin {
+    encrypt: ElGamalCommitment,
+    #[serde(with = "address::compact")]
+    owner: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactMemo {
+    #[serde(with = "address::compact")]
+    script_address: String,
+    #[serde(with = "address::compact")]
+    owner: String,
+    commitment: Commitment,
+    data: Option<Vec<ZkvmString>>,
+    timebounds: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactState {
+    nonce: u32,
+    #[serde(with = "address::compact")]
+    script_address: String,
+    #[serde(with = "address::compact")]
+    owner: String,
+    commitment: Commitment,
+    state_variables: Option<Vec<ZkvmString>>,
+    timebounds: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum CompactOutputData {
+    Coin(CompactCoin),
+    Memo(CompactMemo),
+    State(CompactState),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactOutput {
+    out_type: IOType,
+    output: CompactOutputData,
+}
+
+impl From<&Output> for CompactOutput {
+    fn from(output: &Output) -> Self {
+        let data = match output.output.clone() {
+            OutputData::Coin(coin) => CompactOutputData::Coin(CompactCoin {
+                encrypt: coin.encrypt,
+                owner: coin.owner,
+            }),
+            OutputData::Memo(memo) => CompactOutputData::Memo(CompactMemo {
+                script_address: memo.script_address,
+                owner: memo.owner,
+                commitment: memo.commitment,
+                data: memo.data,
+                timebounds: memo.timebounds,
+            }),
+            OutputData::State(state) => CompactOutputData::State(CompactState {
+                nonce: state.nonce,
+                script_address: state.script_address,
+                owner: state.owner,
+                commitment: state.commitment,
+                state_variables: state.state_variables,
+                timebounds: state.timebounds,
+            }),
+        };
+        CompactOutput {
+            out_type: output.out_type,
+            output: data,
+        }
+    }
+}
+
+impl From<CompactOutput> for Output {
+    fn from(compact: CompactOutput) -> Self {
+        let output = match compact.output {
+            CompactOutputData::Coin(coin) => {
+                OutputData::Coin(OutputCoin::new(coin.encrypt, coin.owner))
+            }
+            CompactOutputData::Memo(memo) => OutputData::Memo(OutputMemo::new(
+                memo.script_address,
+                memo.owner,
+                memo.commitment,
+                memo.data,
+                memo.timebounds,
+            )),
+            CompactOutputData::State(state) => OutputData::State(OutputState {
+                nonce: state.nonce,
+                script_address: state.script_address,
+                owner: state.owner,
+                commitment: state.commitment,
+                state_variables: state.state_variables,
+                timebounds: state.timebounds,
+            }),
+        };
+        Output {
+            out_type: compact.out_type,
+            output,
+        }
+    }
+}
+
+impl Output {
+    /// Encodes the output with fixed form addresses.
+    /// Fails if an owner or script address is not a valid address, e.g. a predicate root.
+    pub fn to_compact_bytes(&self) -> Result<Vec<u8>, VMError> {
+        let mut bytes = vec![COMPACT_OUTPUT_VERSION];
+        bincode::serialize_into(&mut bytes, &CompactOutput::from(self))
+            .map_err(|_| VMError::InvalidOutputAddress)?;
+        Ok(bytes)
+    }
+
+    /// Decodes an output encoded by [`Output::to_compact_bytes`].
+    pub fn from_compact_bytes(bytes: &[u8]) -> Result<Output, VMError> {
+        match bytes.split_first() {
+            Some((&COMPACT_OUTPUT_VERSION, body)) => {
+                let compact: CompactOutput =
+                    bincode::deserialize(body).map_err(|_| VMError::InvalidFormat)?;
+                let output: Output = compact.into();
+                output.validate_type_consistency()?;
+                Ok(output)
+            }
+            _ => Err(VMError::InvalidFormat),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::accounts::Account;
+
+    fn owner() -> String {
+        let (acc, _) = Account::generate_random_account_with_value(Scalar::from(10u64));
+        let (pk, _) = acc.get_account();
+        address::Address::standard_address(address::Network::Mainnet, pk).as_hex()
+    }
+
+    fn script_address() -> String {
+        address::Address::script_address(address::Network::Mainnet, [3u8; 32]).as_hex()
+    }
+
+    // the compact form of an address is its length, its tag and its bytes
+    fn saved(hex: &str) -> usize {
+        (8 + hex.len()) - (8 + 1 + hex.len() / 2)
+    }
+
+    #[test]
+    fn compact_output_size_test() {
+        let (acc, _) = Account::generate_random_account_with_value(Scalar::from(10u64));
+        let coin = Output::coin(OutputData::Coin(OutputCoin::new(
+            acc.get_account().1,
+            owner(),
+        )));
+        let memo = Output::memo(OutputData::Memo(OutputMemo::new(
+            script_address(),
+            owner(),
+            Commitment::blinded(10u64),
+            Some(vec![ZkvmString::U64(5)]),
+            0,
+        )));
+        let state = Output::state(OutputData::State(OutputState {
+            nonce: 1,
+            script_address: script_address(),
+            owner: owner(),
+            commitment: Commitment::blinded(10u64),
+            state_variables: None,
+            timebounds: 0,
+        }))
+        .to_verifier_view();
+        let memo = memo.to_verifier_view();
+
+        // 146 bytes of hex owner against 79 of version and fixed form
+        let encoded = bincode::serialize(&coin).unwrap();
+        assert_eq!(encoded.len() - coin.to_compact_bytes().unwrap().len(), 67);
+
+        for output in [coin, memo, state] {
+            let compact = output.to_compact_bytes().unwrap();
+            assert_eq!(Output::from_compact_bytes(&compact).unwrap(), output);
+
+            let data = output.as_output_data();
+            let mut expected = saved(data.get_owner_address().unwrap());
+            if let Some(script_address) = data.get_script_address() {
+                expected += saved(script_address);
+            }
+            // one byte of version
+            let encoded = bincode::serialize(&output).unwrap();
+            assert_eq!(encoded.len() - compact.len(), expected - 1);
+        }
+    }
+
+    #[test]
+    fn compact_output_invalid_test() {
+        // a predicate root is no address
+        let mut memo = OutputMemo::default();
+        memo.owner = hex::encode([1u8; 32]);
+        assert!(Output::memo(OutputData::Memo(memo))
+            .to_compact_bytes()
+            .is_err());
+
+        let (acc, _) = Account::generate_random_account_with_value(Scalar::from(10u64));
+        let coin = Output::coin(OutputData::Coin(OutputCoin::new(
+            acc.get_account().1,
+            owner(),
+        )));
+        let mut compact = coin.to_compact_bytes().unwrap();
+        assert!(Output::from_compact_bytes(&compact[1..]).is_err());
+        compact[0] = 2;
+        assert!(Output::from_compact_bytes(&compact).is_err());
+        assert!(Output::from_compact_bytes(&[]).is_err());
+
+        // the type of a compact output has to match its data
+        let mut mismatched = coin.to_compact_bytes().unwrap();
+        mismatched[1..5].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(
+            Output::from_compact_bytes(&mismatched),
+            Err(VMError::OutputTypeMismatch)
+        );
+    }
+}
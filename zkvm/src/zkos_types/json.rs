@@ -0,0 +1,513 @@
+//! Compact JSON representation of inputs, outputs and utxos for explorers.
+//!
+//! Curve points are hex encoded and addresses are used verbatim.
+//! The serialized shape of these types is stable and should only be extended.
+
+use super::{Input, InputData, Output, OutputCoin, OutputData, OutputMemo, OutputState, Utxo};
+use crate::constraints::Commitment;
+use crate::encoding::{
+    point_from_hex, point_to_hex, scalar_from_hex_canonical, scalar_to_hex, Encodable,
+};
+use crate::scalar_witness::ScalarWitness;
+use crate::tx::TxID;
+use crate::types::String as ZkvmString;
+use crate::IOType;
+use core::convert::{TryFrom, TryInto};
+use merkle::Hash;
+use quisquislib::elgamal::ElGamalCommitment;
+use serde::{Deserialize, Serialize};
+
+/// Utxo as `{txid_hex, index}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoJson {
+    pub txid_hex: String,
+    pub index: u16,
+}
+
+/// Data item of a memo or state output.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum DataJson {
+    Opaque(String),
+    Commitment(String),
+    Scalar(String),
+    U64(u64),
+    U32(u32),
+    /// Predicates and contracts are only rendered and can not be converted back.
+    Encoded(String),
+}
+
+/// Output with its type flattened and points hex encoded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputJson {
+    #[serde(rename = "type")]
+    pub out_type: String,
+    pub owner: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_hex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commitment_hex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u32>,
+    pub timebounds: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<DataJson>>,
+}
+
+/// Input as the spent utxo, the witness index and the flattened spent output.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputJson {
+    pub utxo: UtxoJson,
+    pub witness_index: u8,
+    #[serde(flatten)]
+    pub output: OutputJson,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coin_value_hex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script_data: Option<Vec<DataJson>>,
+}
+
+fn decode_hex<const N: usize>(hex_str: &str) -> Result<[u8; N], &'static str> {
+    let bytes = hex::decode(hex_str).map_err(|_| "Error::Invalid hex string")?;
+    bytes.try_into().map_err(|_| "Error::Invalid hex length")
+}
+
+fn commitment_to_hex(commitment: &Commitment) -> String {
+    point_to_hex(&commitment.to_point())
+}
+
+fn commitment_from_hex(hex_str: &str) -> Result<Commitment, &'static str> {
+    let point = point_from_hex(hex_str).map_err(|_| "Error::Invalid commitment")?;
+    Ok(Commitment::Closed(point))
+}
+
+fn required<T>(field: Option<T>) -> Result<T, &'static str> {
+    field.ok_or("Error::Missing field for output type")
+}
+
+fn data_to_json(data: &Option<Vec<ZkvmString>>) -> Option<Vec<DataJson>> {
+    data.as_ref()
+        .map(|items| items.iter().map(DataJson::from).collect())
+}
+
+fn data_from_json(data: Option<Vec<DataJson>>) -> Result<Option<Vec<ZkvmString>>, &'static str> {
+    match data {
+        Some(items) => Ok(Some(
+            items
+                .into_iter()
+                .map(ZkvmString::try_from)
+                .collect::<Result<Vec<ZkvmString>, &'static str>>()?,
+        )),
+        None => Ok(None),
+    }
+}
+
+impl From<&Utxo> for UtxoJson {
+    fn from(utxo: &Utxo) -> Self {
+        UtxoJson {
+            txid_hex: utxo.tx_id_to_hex(),
+            index: utxo.output_index(),
+        }
+    }
+}
+
+impl TryFrom<UtxoJson> for Utxo {
+    type Error = &'static str;
+
+    fn try_from(utxo: UtxoJson) -> Result<Self, Self::Error> {
+        Ok(Utxo::new(
+            TxID(Hash(decode_hex::<32>(&utxo.txid_hex)?)),
+            utxo.index,
+        ))
+    }
+}
+
+impl From<&ZkvmString> for DataJson {
+    fn from(item: &ZkvmString) -> Self {
+        match item {
+            ZkvmString::Opaque(bytes) => DataJson::Opaque(hex::encode(bytes)),
+            ZkvmString::Commitment(commitment) => {
+                DataJson::Commitment(commitment_to_hex(commitment))
+            }
+            ZkvmString::Scalar(scalar) => DataJson::Scalar(scalar_to_hex(&scalar.to_scalar())),
+            ZkvmString::U64(n) => DataJson::U64(*n),
+            ZkvmString::U32(n) => DataJson::U32(*n),
+            ZkvmString::Predicate(_) | ZkvmString::Output(_) => {
+                DataJson::Encoded(hex::encode(item.encode_to_vec()))
+            }
+        }
+    }
+}
+
+impl TryFrom<DataJson> for ZkvmString {
+    type Error = &'static str;
+
+    fn try_from(item: DataJson) -> Result<Self, Self::Error> {
+        match item {
+            DataJson::Opaque(hex_str) => Ok(ZkvmString::Opaque(
+                hex::decode(hex_str).map_err(|_| "Error::Invalid hex string")?,
+            )),
+            DataJson::Commitment(hex_str) => Ok(ZkvmString::Commitment(Box::new(
+                commitment_from_hex(&hex_str)?,
+            ))),
+            DataJson::Scalar(hex_str) => {
+                let scalar =
+                    scalar_from_hex_canonical(&hex_str).map_err(|_| "Error::Invalid scalar")?;
+                Ok(ZkvmString::Scalar(Box::new(ScalarWitness::Scalar(scalar))))
+            }
+            DataJson::U64(n) => Ok(ZkvmString::U64(n)),
+            DataJson::U32(n) => Ok(ZkvmString::U32(n)),
+            DataJson::Encoded(_) => Err("Error::Encoded data can not be converted back"),
+        }
+    }
+}
+
+impl From<&Output> for OutputJson {
+    fn from(output: &Output) -> Self {
+        match &output.output {
+            OutputData::Coin(coin) => OutputJson {
+                out_type: "Coin".to_string(),
+                owner: coin.owner.clone(),
+                script_address: None,
+                encryption_hex: Some(hex::encode(coin.encrypt.to_bytes())),
+                commitment_hex: None,
+                nonce: None,
+                timebounds: 0,
+                data: None,
+            },
+            OutputData::Memo(memo) => OutputJson {
+                out_type: "Memo".to_string(),
+                owner: memo.owner.clone(),
+                script_address: Some(memo.script_address.clone()),
+                encryption_hex: None,
+                commitment_hex: Some(commitment_to_hex(&memo.commitment)),
+                nonce: None,
+                timebounds: memo.timebounds,
+                data: data_to_json(&memo.data),
+            },
+            OutputData::State(state) => OutputJson {
+                out_type: "State".to_string(),
+                owner: state.owner.clone(),
+                script_address: Some(state.script_address.clone()),
+                encryption_hex: None,
+                commitment_hex: Some(commitment_to_hex(&state.commitment)),
+                nonce: Some(state.nonce),
+                timebounds: state.timebounds,
+                data: data_to_json(&state.state_variables),
+            },
+        }
+    }
+}
+
+impl TryFrom<OutputJson> for Output {
+    type Error = &'static str;
+
+    fn try_from(output: OutputJson) -> Result<Self, Self::Error> {
+        match output.out_type.as_str() {
+            "Coin" => {
+                let encrypt = ElGamalCommitment::from_bytes(&decode_hex::<64>(&required(
+                    output.encryption_hex,
+                )?)?)
+                .map_err(|_| "Error::Invalid encryption")?;
+                Ok(Output::coin(OutputData::Coin(OutputCoin::new(
+                    encrypt,
+                    output.owner,
+                ))))
+            }
+            "Memo" => Ok(Output::memo(OutputData::Memo(OutputMemo::new(
+                required(output.script_address)?,
+                output.owner,
+                commitment_from_hex(&required(output.commitment_hex)?)?,
+                data_from_json(output.data)?,
+                output.timebounds,
+            )))),
+            "State" => Ok(Output::state(OutputData::State(OutputState {
+                nonce: required(output.nonce)?,
+                script_address: required(output.script_address)?,
+                owner: output.owner,
+                commitment: commitment_from_hex(&required(output.commitment_hex)?)?,
+                state_variables: data_from_json(output.data)?,
+                timebounds: output.timebounds,
+            }))),
+            _ => Err("Error::Invalid output type"),
+        }
+    }
+}
+
+impl From<&Input> for InputJson {
+    fn from(input: &Input) -> Self {
+        let (utxo, witness_index, output, coin_value_hex, script_data) = match &input.input {
+            InputData::Coin {
+                utxo,
+                out_coin,
+                witness,
+            } => (utxo, witness, out_coin.to_output(), None, None),
+            InputData::Memo {
+                utxo,
+                out_memo,
+                witness,
+                coin_value,
+            } => (
+                utxo,
+                witness,
+                out_memo.to_output(),
+                coin_value.as_ref().map(commitment_to_hex),
+                None,
+            ),
+            InputData::State {
+                utxo,
+                out_state,
+                witness,
+                script_data,
+            } => (
+                utxo,
+                witness,
+                Output::state(OutputData::State(out_state.clone())),
+                None,
+                data_to_json(script_data),
+            ),
+        };
+        InputJson {
+            utxo: UtxoJson::from(utxo),
+            witness_index: *witness_index,
+            output: OutputJson::from(&output),
+            coin_value_hex,
+            script_data,
+        }
+    }
+}
+
+impl TryFrom<InputJson> for Input {
+    type Error = &'static str;
+
+    fn try_from(input: InputJson) -> Result<Self, Self::Error> {
+        let utxo = Utxo::try_from(input.utxo)?;
+        let output = Output::try_from(input.output)?;
+        match output.out_type {
+            IOType::Coin => Ok(Input::coin(InputData::coin(
+                utxo,
+                required(output.as_out_coin().cloned())?,
+                input.witness_index,
+            ))),
+            IOType::Memo => {
+                let coin_value = match input.coin_value_hex {
+                    Some(hex_str) => Some(commitment_from_hex(&hex_str)?),
+                    None => None,
+                };
+                Ok(Input::memo(InputData::memo(
+                    utxo,
+                    required(output.as_out_memo().cloned())?,
+                    input.witness_index,
+                    coin_value,
+                )))
+            }
+            IOType::State => Ok(Input::state(InputData::state(
+                utxo,
+                required(output.as_out_state().cloned())?,
+                data_from_json(input.script_data)?,
+                input.witness_index,
+            ))),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+    use serde_json::json;
+
+    fn test_utxo() -> Utxo {
+        Utxo::new(TxID(Hash([7u8; 32])), 2)
+    }
+
+    fn test_commitment() -> Commitment {
+        Commitment::Closed(RISTRETTO_BASEPOINT_COMPRESSED)
+    }
+
+    fn test_commitment_hex() -> String {
+        hex::encode(RISTRETTO_BASEPOINT_COMPRESSED.as_bytes())
+    }
+
+    #[test]
+    fn utxo_json_shape_test() {
+        let utxo_json = UtxoJson::from(&test_utxo());
+        assert_eq!(
+            serde_json::to_value(&utxo_json).unwrap(),
+            json!({ "txid_hex": hex::encode([7u8; 32]), "index": 2 })
+        );
+        assert_eq!(Utxo::try_from(utxo_json).unwrap(), test_utxo());
+    }
+
+    #[test]
+    fn memo_output_json_shape_test() {
+        let memo = OutputMemo::new(
+            "script".to_string(),
+            "owner".to_string(),
+            test_commitment(),
+            Some(vec![
+                ZkvmString::U64(5),
+                ZkvmString::from(test_commitment()),
+            ]),
+            3,
+        );
+        let output = memo.to_output();
+        let output_json = OutputJson::from(&output);
+        assert_eq!(
+            serde_json::to_value(&output_json).unwrap(),
+            json!({
+                "type": "Memo",
+                "owner": "owner",
+                "script_address": "script",
+                "commitment_hex": test_commitment_hex(),
+                "timebounds": 3,
+                "data": [
+                    { "type": "U64", "value": 5 },
+                    { "type": "Commitment", "value": test_commitment_hex() }
+                ]
+            })
+        );
+        assert_eq!(Output::try_from(output_json).unwrap(), output);
+    }
+
+    #[test]
+    fn state_input_json_shape_test() {
+        let state = OutputState {
+            nonce: 4,
+            script_address: "script".to_string(),
+            owner: "owner".to_string(),
+            commitment: test_commitment(),
+            state_variables: None,
+            timebounds: 0,
+        };
+        let input = Input::state(InputData::state(
+            test_utxo(),
+            state,
+            Some(vec![ZkvmString::U32(9)]),
+            1,
+        ));
+        let input_json = InputJson::from(&input);
+        assert_eq!(
+            serde_json::to_value(&input_json).unwrap(),
+            json!({
+                "utxo": { "txid_hex": hex::encode([7u8; 32]), "index": 2 },
+                "witness_index": 1,
+                "type": "State",
+                "owner": "owner",
+                "script_address": "script",
+                "commitment_hex": test_commitment_hex(),
+                "nonce": 4,
+                "timebounds": 0,
+                "script_data": [{ "type": "U32", "value": 9 }]
+            })
+        );
+        assert_eq!(Input::try_from(input_json).unwrap(), input);
+    }
+
+    #[test]
+    fn invalid_output_json_test() {
+        let mut output_json = OutputJson::from(&OutputMemo::default().to_output());
+        output_json.commitment_hex = None;
+        assert!(Output::try_from(output_json.clone()).is_err());
+        output_json.out_type = "Unknown".to_string();
+        assert!(Output::try_from(output_json).is_err());
+
+        // the commitment has to be a valid point
+        let mut output_json = OutputJson::from(&OutputMemo::default().to_output());
+        output_json.commitment_hex = Some(hex::encode([1u8; 32]));
+        assert!(Output::try_from(output_json).is_err());
+    }
+
+    #[test]
+    fn utxo_display_round_trip_test() {
+        let utxo = test_utxo();
+        let utxo_str = utxo.to_string();
+        assert_eq!(utxo_str, format!("{}:2", hex::encode([7u8; 32])));
+        assert_eq!(utxo_str.parse::<Utxo>().unwrap(), utxo);
+        let txid: TxID = utxo.tx_id().to_string().parse().unwrap();
+        assert_eq!(txid, *utxo.tx_id());
+
+        // the hex of the bincode encoding is still accepted
+        assert_eq!(utxo.to_hex().parse::<Utxo>().unwrap(), utxo);
+
+        assert!("zz:1".parse::<Utxo>().is_err());
+        assert!(format!("{}:256", hex::encode([7u8; 32]))
+            .parse::<Utxo>()
+            .is_err());
+        assert!(hex::encode([7u8; 31]).parse::<TxID>().is_err());
+    }
+
+    #[test]
+    fn utxo_short_input_test() {
+        let bytes = test_utxo().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(Utxo::from_bytes(&bytes[..len]).is_none());
+            assert!(hex::encode(&bytes[..len]).parse::<Utxo>().is_err());
+        }
+        assert!(Utxo::from_hex("0").is_none());
+        assert!(":".parse::<Utxo>().is_err());
+        assert!("".parse::<Utxo>().is_err());
+    }
+
+    #[test]
+    fn utxo_serde_forms_test() {
+        let utxo = test_utxo();
+        // JSON uses the string form
+        assert_eq!(
+            serde_json::to_value(&utxo).unwrap(),
+            json!(utxo.to_string())
+        );
+        let decoded: Utxo = serde_json::from_value(json!(utxo.to_string())).unwrap();
+        assert_eq!(decoded, utxo);
+
+        // bincode keeps the compact form, the txid bytes followed by the u16 index
+        let bytes = bincode::serialize(&utxo).unwrap();
+        let mut expected = [7u8; 32].to_vec();
+        expected.extend_from_slice(&2u16.to_le_bytes());
+        assert_eq!(bytes, expected);
+        assert_eq!(bytes.len(), Utxo::ENCODED_LEN);
+        assert_eq!(bincode::deserialize::<Utxo>(&bytes).unwrap(), utxo);
+
+        // keys written with a single byte index
+        let mut legacy_key = [7u8; 32].to_vec();
+        legacy_key.push(2);
+        assert_eq!(Utxo::from_bytes(&legacy_key), Some(utxo));
+        assert_eq!(Utxo::upgrade_key(legacy_key), bytes);
+        assert_eq!(Utxo::upgrade_key(bytes.clone()), bytes);
+        let wide = Utxo::new(*utxo.tx_id(), 300);
+        assert_eq!(Utxo::from_bytes(&wide.to_bytes()), Some(wide));
+
+        // JSON written before the string form
+        let legacy = json!({ "txid": [7u8; 32].to_vec(), "output_index": 2 });
+        assert_eq!(serde_json::from_value::<Utxo>(legacy).unwrap(), utxo);
+        let txid: TxID = serde_json::from_value(json!([7u8; 32].to_vec())).unwrap();
+        assert_eq!(txid, *utxo.tx_id());
+    }
+
+    #[test]
+    fn utxo_pending_test() {
+        let pending = Utxo::pending(3);
+        assert_eq!(pending.pending_index(), Some(3));
+        assert!(pending.is_pending());
+        // the default utxo of decoys and new States is no reference
+        assert_eq!(Utxo::default().pending_index(), None);
+        assert_ne!(Utxo::pending(0), Utxo::default());
+        assert!(!test_utxo().is_pending());
+
+        // the reference survives both serde forms
+        let decoded: Utxo =
+            serde_json::from_value(serde_json::to_value(&pending).unwrap()).unwrap();
+        assert_eq!(decoded, pending);
+        let bytes = bincode::serialize(&pending).unwrap();
+        assert_eq!(Utxo::from_bytes(&bytes), Some(pending));
+
+        let txid = *test_utxo().tx_id();
+        assert_eq!(pending.resolve(txid), Utxo::new(txid, 3));
+        assert_eq!(test_utxo().resolve(TxID(Hash([1u8; 32]))), test_utxo());
+    }
+}
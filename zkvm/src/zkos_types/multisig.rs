@@ -0,0 +1,451 @@
+//! Coins owned by m of n keys.
+//!
+//! A multisig address commits to the merkle root of every set of `m` keys out
+//! of the `n` owner keys. The value proofs of a coin are made against a single
+//! ElGamal account, so each key set also carries the account key the coin is
+//! encrypted to. Spending reveals one key set with its merkle path and a
+//! signature by each key of the set over the input and the verifier view of the
+//! inputs and outputs of the tx. The keys outside the revealed set stay private.
+
+use super::{Input, Output};
+use address::{Address, AddressType, Network};
+use curve25519_dalek::ristretto::CompressedRistretto;
+use merkle::{Hash, Hasher, MerkleItem, MerkleTree, Path};
+use merlin::Transcript;
+use quisquislib::accounts::{Account, SigmaProof, Verifier};
+use quisquislib::keys::PublicKey;
+use quisquislib::ristretto::{RistrettoPublicKey, RistrettoSecretKey};
+use serde::{Deserialize, Serialize};
+use zkschnorr::Signature;
+
+/// Maximum number of owner keys of a multisig address.
+/// The tree holds one leaf per key set, i.e. `n choose m` leaves.
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+const MULTISIG_TREE_LABEL: &[u8] = b"ZkOS.Multisig";
+const MULTISIG_SIGN_LABEL: &[u8] = b"MultisigSign";
+
+/// Leaf of the multisig tree: `m` owner keys and the account key of the coin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MultisigKeySet {
+    pub account_key: RistrettoPublicKey,
+    pub keys: Vec<RistrettoPublicKey>,
+}
+
+impl MerkleItem for MultisigKeySet {
+    fn commit(&self, t: &mut Transcript) {
+        t.append_message(b"account_key", &self.account_key.as_bytes());
+        t.append_u64(b"m", self.keys.len() as u64);
+        for key in self.keys.iter() {
+            t.append_message(b"key", &key.as_bytes());
+        }
+    }
+}
+
+/// Owner keys and threshold of a multisig address.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MultisigPolicy {
+    pub account_key: RistrettoPublicKey,
+    pub keys: Vec<RistrettoPublicKey>,
+    pub threshold: usize,
+}
+
+impl MultisigPolicy {
+    /// Creates an `m`-of-`keys.len()` policy for coins encrypted to `account_key`.
+    pub fn new(
+        account_key: RistrettoPublicKey,
+        keys: Vec<RistrettoPublicKey>,
+        m: usize,
+    ) -> Result<MultisigPolicy, &'static str> {
+        if keys.is_empty() || keys.len() > MAX_MULTISIG_KEYS {
+            return Err("MultisigError::InvalidKeyCount");
+        }
+        if m == 0 || m > keys.len() {
+            return Err("MultisigError::InvalidThreshold");
+        }
+        for (i, key) in keys.iter().enumerate() {
+            if keys[i + 1..].contains(key) {
+                return Err("MultisigError::DuplicateKey");
+            }
+        }
+        Ok(MultisigPolicy {
+            account_key,
+            keys,
+            threshold: m,
+        })
+    }
+
+    /// Every set of `threshold` keys, in lexicographic order of the key indices.
+    pub fn key_sets(&self) -> Vec<MultisigKeySet> {
+        let mut key_sets = Vec::new();
+        let mut indices: Vec<usize> = (0..self.threshold).collect();
+        loop {
+            key_sets.push(MultisigKeySet {
+                account_key: self.account_key.clone(),
+                keys: indices.iter().map(|i| self.keys[*i].clone()).collect(),
+            });
+            // advance to the next combination
+            let n = self.keys.len();
+            let mut i = self.threshold;
+            while i > 0 && indices[i - 1] == n - self.threshold + i - 1 {
+                i -= 1;
+            }
+            if i == 0 {
+                return key_sets;
+            }
+            indices[i - 1] += 1;
+            for j in i..self.threshold {
+                indices[j] = indices[j - 1] + 1;
+            }
+        }
+    }
+
+    /// Merkle root of the key sets.
+    pub fn root(&self) -> Hash {
+        MerkleTree::root(MULTISIG_TREE_LABEL, self.key_sets().iter())
+    }
+
+    /// Multisig address of the policy on the given network.
+    pub fn to_address(&self, network: Network) -> Address {
+        Address::multisig_address(network, self.root().0)
+    }
+
+    /// Key set of the signers at `signer_indices` and its merkle path.
+    /// `signer_indices` are indices into `keys` and must hold exactly `threshold` of them.
+    pub fn key_set_path(
+        &self,
+        signer_indices: &[usize],
+    ) -> Result<(MultisigKeySet, Path), &'static str> {
+        let mut signers = signer_indices.to_vec();
+        signers.sort_unstable();
+        signers.dedup();
+        if signers.len() != self.threshold || signers.iter().any(|i| *i >= self.keys.len()) {
+            return Err("MultisigError::InvalidSignerSet");
+        }
+        let key_set = MultisigKeySet {
+            account_key: self.account_key.clone(),
+            keys: signers.iter().map(|i| self.keys[*i].clone()).collect(),
+        };
+        let key_sets = self.key_sets();
+        let index = key_sets
+            .iter()
+            .position(|leaf| *leaf == key_set)
+            .ok_or("MultisigError::InvalidSignerSet")?;
+        let path = Path::new(&key_sets, index, &Hasher::new(MULTISIG_TREE_LABEL))
+            .ok_or("MultisigError::InvalidSignerSet")?;
+        Ok((key_set, path))
+    }
+}
+
+/// Message signed by each key of the revealed key set: the index of the multisig input
+/// and the verifier view of every input and output of the tx, so that the signatures
+/// cannot be lifted into a tx with other inputs or outputs.
+/// Witness indices are left out, the witness is appended once the owners signed.
+pub fn multisig_signing_message(
+    input_index: usize,
+    inputs: &[Input],
+    outputs: &[Output],
+) -> Vec<u8> {
+    let inputs: Vec<Input> = inputs
+        .iter()
+        .map(|input| input.verifier_view().as_input_for_signing())
+        .collect();
+    let outputs: Vec<Output> = outputs.iter().map(Output::to_verifier_view).collect();
+    bincode::serialize(&(input_index as u64, inputs, outputs)).unwrap()
+}
+
+/// Signature of one owner key over the multisig coin input at `input_index` of a tx.
+pub fn sign_multisig_input(
+    input_index: usize,
+    inputs: &[Input],
+    outputs: &[Output],
+    secret_key: &RistrettoSecretKey,
+    public_key: &RistrettoPublicKey,
+) -> Signature {
+    public_key.sign_msg(
+        &multisig_signing_message(input_index, inputs, outputs),
+        secret_key,
+        MULTISIG_SIGN_LABEL,
+    )
+}
+
+/// Returns true if `owner` is a hex encoded multisig address.
+pub fn is_multisig_owner(owner: &str) -> bool {
+    AddressType::from_hex(owner) == Ok(AddressType::Multisig)
+}
+
+/// Witness spending a multisig coin input.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultisigWitness {
+    key_set: MultisigKeySet,
+    path: Path,
+    // one signature per key of the key set, in the same order
+    signatures: Vec<Signature>,
+    // same value proof of the coin, required when the coin is spent into a memo
+    value_proof: Option<SigmaProof>,
+}
+
+impl MultisigWitness {
+    pub fn new(
+        key_set: MultisigKeySet,
+        path: Path,
+        signatures: Vec<Signature>,
+        value_proof: Option<SigmaProof>,
+    ) -> Self {
+        MultisigWitness {
+            key_set,
+            path,
+            signatures,
+            value_proof,
+        }
+    }
+
+    pub fn get_key_set(&self) -> &MultisigKeySet {
+        &self.key_set
+    }
+
+    pub fn get_value_proof(&self) -> Option<&SigmaProof> {
+        self.value_proof.as_ref()
+    }
+
+    /// Verifies that the key set belongs to the owner address of the coin input at
+    /// `input_index` and that every key of the set signed the inputs and outputs of the tx.
+    /// Returns the account of the coin, made of the account key of the key set and the
+    /// encryption of the input.
+    pub fn verify(
+        &self,
+        input_index: usize,
+        inputs: &[Input],
+        outputs: &[Output],
+    ) -> Result<Account, &'static str> {
+        let input = inputs
+            .get(input_index)
+            .ok_or("MultisigError::InvalidInputType")?;
+        let owner = input
+            .as_owner_address()
+            .ok_or("MultisigError::InvalidOwnerAddress")?;
+        let root = Address::from_hex(owner, AddressType::Multisig)
+            .and_then(|address| address.get_multisig_address())
+            .map_err(|_| "MultisigError::InvalidOwnerAddress")?
+            .get_root_hash();
+        let hasher: Hasher<MultisigKeySet> = Hasher::new(MULTISIG_TREE_LABEL);
+        if !self.path.verify_root(&Hash(root), &self.key_set, &hasher) {
+            return Err("MultisigError::KeySetNotInTree");
+        }
+        if self.key_set.keys.is_empty() || self.signatures.len() != self.key_set.keys.len() {
+            return Err("MultisigError::MissingSignature");
+        }
+        let message = multisig_signing_message(input_index, inputs, outputs);
+        for (key, signature) in self.key_set.keys.iter().zip(self.signatures.iter()) {
+            key.verify_msg(&message, signature, MULTISIG_SIGN_LABEL)
+                .map_err(|_| "MultisigError::InvalidSignature")?;
+        }
+        let encryption = input
+            .as_encryption()
+            .ok_or("MultisigError::InvalidInputType")?;
+        Ok(Account::set_account(
+            self.key_set.account_key.clone(),
+            encryption,
+        ))
+    }
+
+    /// Verifies the witness and the same value proof of the coin against the
+    /// Pedersen commitment of the memo it is spent into.
+    pub fn verify_same_value(
+        &self,
+        input_index: usize,
+        inputs: &[Input],
+        outputs: &[Output],
+        commitment: CompressedRistretto,
+    ) -> Result<Account, &'static str> {
+        let account = self.verify(input_index, inputs, outputs)?;
+        let value_proof = self
+            .value_proof
+            .clone()
+            .ok_or("MultisigError::MissingValueProof")?;
+        Verifier::verify_same_value_compact_verifier(account, commitment, value_proof)?;
+        Ok(account)
+    }
+}
+
+// ------------------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------------------
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::zkos_types::{InputData, OutputCoin, OutputData, Utxo};
+    use curve25519_dalek::scalar::Scalar;
+    use quisquislib::keys::SecretKey;
+
+    fn keypair() -> (RistrettoSecretKey, RistrettoPublicKey) {
+        let mut rng = rand::thread_rng();
+        let sk: RistrettoSecretKey = SecretKey::random(&mut rng);
+        let pk = RistrettoPublicKey::from_secret_key(&sk, &mut rng);
+        (sk, pk)
+    }
+
+    // 2-of-3 policy and a tx spending a coin owned by it
+    fn two_of_three() -> (
+        Vec<RistrettoSecretKey>,
+        MultisigPolicy,
+        Vec<Input>,
+        Vec<Output>,
+    ) {
+        let (account, _) = Account::generate_random_account_with_value(Scalar::from(50u64));
+        let (account_key, encrypt) = account.get_account();
+        let (sks, pks): (Vec<_>, Vec<_>) = (0..3).map(|_| keypair()).unzip();
+        let policy = MultisigPolicy::new(account_key, pks, 2).unwrap();
+        let owner = policy.to_address(Network::default()).as_hex();
+        let input = Input::coin(InputData::coin(
+            Utxo::random(),
+            OutputCoin::new(encrypt, owner.clone()),
+            0,
+        ));
+        let output = Output::coin(OutputData::coin(OutputCoin::new(encrypt, owner)));
+        (sks, policy, vec![input], vec![output])
+    }
+
+    // witness of the multisig input of the tx, signed by the keys at `signers`
+    fn witness(
+        sks: &[RistrettoSecretKey],
+        policy: &MultisigPolicy,
+        inputs: &[Input],
+        outputs: &[Output],
+        signers: &[usize],
+    ) -> MultisigWitness {
+        let (key_set, path) = policy.key_set_path(signers).unwrap();
+        let signatures = signers
+            .iter()
+            .map(|i| sign_multisig_input(0, inputs, outputs, &sks[*i], &policy.keys[*i]))
+            .collect();
+        MultisigWitness::new(key_set, path, signatures, None)
+    }
+
+    #[test]
+    fn multisig_policy_test() {
+        let (_, pks): (Vec<_>, Vec<_>) = (0..4).map(|_| keypair()).unzip();
+        let (account_key, _) = keypair();
+        let policy = MultisigPolicy::new(account_key.clone(), pks.clone(), 2).unwrap();
+        assert_eq!(policy.key_sets().len(), 6);
+        let full = MultisigPolicy::new(account_key.clone(), pks.clone(), 4).unwrap();
+        assert_eq!(full.key_sets().len(), 1);
+
+        assert!(MultisigPolicy::new(account_key.clone(), pks.clone(), 0).is_err());
+        assert!(MultisigPolicy::new(account_key.clone(), pks.clone(), 5).is_err());
+        let duplicate = vec![pks[0].clone(), pks[1].clone(), pks[0].clone()];
+        assert_eq!(
+            MultisigPolicy::new(account_key, duplicate, 2),
+            Err("MultisigError::DuplicateKey")
+        );
+        let owner = policy.to_address(Network::default()).as_hex();
+        assert!(is_multisig_owner(&owner));
+    }
+
+    #[test]
+    fn multisig_two_of_three_test() {
+        let (sks, policy, inputs, outputs) = two_of_three();
+        for signers in [[0usize, 1], [0, 2], [2, 1]].iter() {
+            let account = witness(&sks, &policy, &inputs, &outputs, signers)
+                .verify(0, &inputs, &outputs)
+                .unwrap();
+            let (account_key, _) = account.get_account();
+            assert_eq!(account_key, policy.account_key);
+        }
+    }
+
+    #[test]
+    fn multisig_one_of_three_test() {
+        let (sks, policy, inputs, outputs) = two_of_three();
+        assert!(policy.key_set_path(&[1]).is_err());
+
+        // a single signature on a path of a 2 key set
+        let mut single = witness(&sks, &policy, &inputs, &outputs, &[0, 1]);
+        single.signatures.truncate(1);
+        assert_eq!(
+            single.verify(0, &inputs, &outputs).unwrap_err(),
+            "MultisigError::MissingSignature"
+        );
+        // a 1 key set does not belong to the tree
+        let one_key = MultisigKeySet {
+            account_key: policy.account_key.clone(),
+            keys: vec![policy.keys[0].clone()],
+        };
+        let signatures = vec![sign_multisig_input(
+            0,
+            &inputs,
+            &outputs,
+            &sks[0],
+            &policy.keys[0],
+        )];
+        let forged = MultisigWitness::new(one_key, single.path.clone(), signatures, None);
+        assert_eq!(
+            forged.verify(0, &inputs, &outputs).unwrap_err(),
+            "MultisigError::KeySetNotInTree"
+        );
+        // both keys of the set must sign
+        let mut wrong_signer = witness(&sks, &policy, &inputs, &outputs, &[0, 1]);
+        wrong_signer.signatures[1] =
+            sign_multisig_input(0, &inputs, &outputs, &sks[2], &policy.keys[2]);
+        assert_eq!(
+            wrong_signer.verify(0, &inputs, &outputs).unwrap_err(),
+            "MultisigError::InvalidSignature"
+        );
+    }
+
+    #[test]
+    fn multisig_lifted_signatures_test() {
+        let (sks, policy, inputs, outputs) = two_of_three();
+        let signed = witness(&sks, &policy, &inputs, &outputs, &[0, 1]);
+        assert!(signed.verify(0, &inputs, &outputs).is_ok());
+
+        // the signatures moved to a tx paying the coin to another account
+        let (other, _) = Account::generate_random_account_with_value(Scalar::from(50u64));
+        let other_outputs = vec![Output::from_quisquis_account(other, Network::default())];
+        assert_eq!(
+            signed.verify(0, &inputs, &other_outputs).unwrap_err(),
+            "MultisigError::InvalidSignature"
+        );
+        // or to a tx with another input next to the coin
+        let mut other_inputs = inputs.clone();
+        other_inputs.push(Input::input_from_quisquis_account(
+            &other,
+            Utxo::random(),
+            0,
+            Network::default(),
+        ));
+        assert_eq!(
+            signed.verify(0, &other_inputs, &outputs).unwrap_err(),
+            "MultisigError::InvalidSignature"
+        );
+    }
+
+    #[test]
+    fn multisig_wrong_tree_test() {
+        let (sks, policy, inputs, outputs) = two_of_three();
+        // same signers under another policy with a different third key
+        let (_, other_key) = keypair();
+        let other = MultisigPolicy::new(
+            policy.account_key.clone(),
+            vec![policy.keys[0].clone(), policy.keys[1].clone(), other_key],
+            2,
+        )
+        .unwrap();
+        let (_, other_path) = other.key_set_path(&[0, 2]).unwrap();
+        let mut wrong_path = witness(&sks, &policy, &inputs, &outputs, &[0, 1]);
+        wrong_path.path = other_path;
+        assert_eq!(
+            wrong_path.verify(0, &inputs, &outputs).unwrap_err(),
+            "MultisigError::KeySetNotInTree"
+        );
+
+        // a key set with another account key
+        let (other_account, _) = keypair();
+        let mut wrong_account = witness(&sks, &policy, &inputs, &outputs, &[0, 1]);
+        wrong_account.key_set.account_key = other_account;
+        assert_eq!(
+            wrong_account.verify(0, &inputs, &outputs).unwrap_err(),
+            "MultisigError::KeySetNotInTree"
+        );
+    }
+}
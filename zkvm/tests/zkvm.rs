@@ -898,13 +898,9 @@ fn test_state_witness_deploy_contract() {
     //convert outputState to Output
     let output: Output = Output::state(OutputData::State(out_state.clone()));
 
-    let witness = Witness::State(StateWitness::create_state_witness(
-        &input,
-        &output,
-        sk_in,
-        pk_in.clone(),
-        true,
-    ));
+    let witness = Witness::State(
+        StateWitness::create_state_witness(&input, &output, sk_in, pk_in.clone(), true).unwrap(),
+    );
 
     // verify the witness
     let state_wit = witness.to_state_witness().unwrap();
@@ -974,13 +970,9 @@ fn test_state_witness_deploy_contract_only_state_commitment() {
     //convert outputState to Output
     let output: Output = Output::state(OutputData::State(out_state.clone()));
 
-    let witness = Witness::State(StateWitness::create_state_witness(
-        &input,
-        &output,
-        sk_in,
-        pk_in.clone(),
-        true,
-    ));
+    let witness = Witness::State(
+        StateWitness::create_state_witness(&input, &output, sk_in, pk_in.clone(), true).unwrap(),
+    );
 
     // verify the witness
     let state_wit = witness.to_state_witness().unwrap();
@@ -1032,13 +1024,9 @@ fn test_state_witness_contract_call() {
     //convert outputState to Output
     let output: Output = Output::state(OutputData::State(out_state.clone()));
 
-    let witness = Witness::State(StateWitness::create_state_witness(
-        &input,
-        &output,
-        sk_in,
-        pk_in.clone(),
-        false,
-    ));
+    let witness = Witness::State(
+        StateWitness::create_state_witness(&input, &output, sk_in, pk_in.clone(), false).unwrap(),
+    );
 
     // verify the witness
     let state_wit = witness.to_state_witness().unwrap();
@@ -1050,6 +1038,123 @@ fn test_state_witness_contract_call() {
     let res = state_wit.verify_state_witness(verifier_input, verifier_output, pk_in.clone(), false);
     println!("res {:?}", res);
 }
+// a deployed State with a committed state variable, and the input and output of its deploy
+fn deploy_state(
+    pk_in: &RistrettoPublicKey,
+    commitment: Commitment,
+    state_variables: Vec<String>,
+) -> (Input, Output) {
+    let add: Address = Address::standard_address(Network::default(), pk_in.clone());
+    let mut out_state = OutputState {
+        nonce: 1u32,
+        script_address: add.as_hex(),
+        owner: add.as_hex(),
+        commitment,
+        state_variables: Some(state_variables),
+        timebounds: 0,
+    };
+    let input = Input::state(InputData::state(
+        Utxo::default(),
+        out_state.clone(),
+        None,
+        1,
+    ));
+    out_state.nonce = 2;
+    (input, Output::state(OutputData::State(out_state)))
+}
+
+#[test]
+fn test_state_witness_rejects_wrong_kind_state() {
+    let mut rng = rand::thread_rng();
+    let sk_in: RistrettoSecretKey = RistrettoSecretKey::random(&mut rng);
+    let pk_in: RistrettoPublicKey = RistrettoPublicKey::from_secret_key(&sk_in, &mut rng);
+
+    // closed commitments carry no blinding to prove the zero value with
+    let (input, output) = deploy_state(
+        &pk_in,
+        Commitment::Closed(Commitment::blinded(0u64).to_point()),
+        vec![],
+    );
+    let err =
+        StateWitness::create_state_witness(&input, &output, sk_in.clone(), pk_in.clone(), true)
+            .unwrap_err();
+    assert_eq!(err, "Error::The state commitment is not open");
+
+    let closed_variable = Commitment::Closed(Commitment::blinded(0u64).to_point());
+    let (input, output) = deploy_state(
+        &pk_in,
+        Commitment::blinded(0u64),
+        vec![String::from(closed_variable)],
+    );
+    let err =
+        StateWitness::create_state_witness(&input, &output, sk_in.clone(), pk_in.clone(), true)
+            .unwrap_err();
+    assert_eq!(err, "Error::The state variable commitment is not open");
+
+    // only State inputs have a zero proof
+    let coin = Input::coin(InputData::coin(
+        Utxo::default(),
+        OutputCoin {
+            encrypt: ElGamalCommitment::generate_commitment(
+                &pk_in,
+                Scalar::random(&mut rng),
+                0u64.into(),
+            ),
+            owner: Address::standard_address(Network::default(), pk_in.clone()).as_hex(),
+        },
+        0,
+    ));
+    let err = StateWitness::create_state_witness(&coin, &output, sk_in, pk_in, true).unwrap_err();
+    assert_eq!(
+        err,
+        "Error::State witness can only be created for State inputs"
+    );
+}
+
+#[test]
+fn test_state_witness_rejects_missing_zero_proofs() {
+    let mut rng = rand::thread_rng();
+    let sk_in: RistrettoSecretKey = RistrettoSecretKey::random(&mut rng);
+    let pk_in: RistrettoPublicKey = RistrettoPublicKey::from_secret_key(&sk_in, &mut rng);
+
+    // a scalar state variable takes no zero proof, a committed one does
+    let (input, output) = deploy_state(
+        &pk_in,
+        Commitment::blinded(0u64),
+        vec![String::from(5u64), String::from(Commitment::blinded(0u64))],
+    );
+    let witness =
+        StateWitness::create_state_witness(&input, &output, sk_in, pk_in.clone(), true).unwrap();
+    let verify = |witness: &StateWitness| {
+        witness.verify_state_witness(
+            input.verifier_view(),
+            output.to_verifier_view(),
+            pk_in.clone(),
+            true,
+        )
+    };
+    assert_eq!(verify(&witness), Ok(true));
+
+    let sign = witness.get_sign().clone();
+    let no_proofs = StateWitness::set_state_witness(sign.clone(), None);
+    assert_eq!(
+        verify(&no_proofs),
+        Err("Error::The state witness carries no zero proofs")
+    );
+    let empty_proofs = StateWitness::set_state_witness(sign.clone(), Some(vec![]));
+    assert_eq!(
+        verify(&empty_proofs),
+        Err("Error::The state witness carries no zero proofs")
+    );
+    // the value is proven, the committed state variable is not
+    let value_proof = *witness.get_zero_proof().unwrap().next().unwrap();
+    let short_proofs = StateWitness::set_state_witness(sign, Some(vec![value_proof]));
+    assert_eq!(
+        verify(&short_proofs),
+        Err("Error::There are fewer zero proofs than state commitments")
+    );
+}
+
 #[test]
 fn value_witness_test() {
     let mut rng = rand::thread_rng();